@@ -0,0 +1,103 @@
+use std::time::Duration;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, SamplingMode};
+use criterion::measurement::WallTime;
+use rand::{thread_rng, Rng};
+use zklib::graph::Graph;
+use zklib::permutation::Permutation;
+
+// Times the constant-time permutation kernels, which work on bit-packed adjacency matrices, on sparse
+// graphs with 1k to 10k vertices. Next to them are the bit-at-a-time kernels they replaced, kept here as
+// a baseline, and permuting and comparing edge by edge. The kernels take O(n^3 / 64) word operations
+// whatever the number of edges, so 10k vertices get their own group, sampled with a fixed number of
+// iterations per sample.
+
+fn sparse_graph(n: u32) -> Graph {
+    Graph::new(n, (0..4 * n).map(|_| (thread_rng().gen_range(0..n), thread_rng().gen_range(0..n))).collect())
+}
+
+// ************ Scalar baseline ************
+//
+// The kernels as they were before they moved to whole words: the transpose moves one bit at a time and
+// the comparison folds over the words without being split out into a loop the compiler vectorizes.
+
+fn scalar_matrix(graph: &Graph, n: usize) -> Vec<u64> {
+    let words = n.div_ceil(64);
+    let mut bits = vec![0; n * words];
+    for (a, b) in graph.edges() {
+        bits[a as usize * words + b as usize / 64] |= 1 << (b % 64);
+    }
+    bits
+}
+
+fn scalar_permute_rows(bits: &[u64], n: usize, map: &[u32]) -> Vec<u64> {
+    let words = n.div_ceil(64);
+    let mut permuted = vec![0; n * words];
+    for (u, row) in permuted.chunks_mut(words.max(1)).enumerate() {
+        for (i, &target) in map.iter().enumerate() {
+            let x = (target ^ u as u32) as u64;
+            let mask = ((x | x.wrapping_neg()) >> 63).wrapping_sub(1);
+            row.iter_mut().zip(&bits[i * words..(i + 1) * words]).for_each(|(x, y)| *x |= y & mask);
+        }
+    }
+    permuted
+}
+
+fn scalar_transpose(bits: &[u64], n: usize) -> Vec<u64> {
+    let words = n.div_ceil(64);
+    let mut transposed = vec![0; n * words];
+    for i in 0..n {
+        for j in 0..n {
+            transposed[j * words + i / 64] |= ((bits[i * words + j / 64] >> (j % 64)) & 1) << (i % 64);
+        }
+    }
+    transposed
+}
+
+fn scalar_permutes_to(graph: &Graph, isomorphism: &Permutation, other: &Graph) -> bool {
+    let (n, map) = (isomorphism.n() as usize, isomorphism.as_slice());
+    let rows = scalar_permute_rows(&scalar_matrix(graph, n), n, map);
+    let permuted = scalar_transpose(&scalar_permute_rows(&scalar_transpose(&rows, n), n, map), n);
+    let difference = permuted.iter().zip(scalar_matrix(other, n).iter()).fold(0, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
+// ************ Benchmarks ************
+
+fn bench_sizes(group: &mut BenchmarkGroup<WallTime>, sizes: &[u32]) {
+    for &n in sizes {
+        let graph = sparse_graph(n);
+        let isomorphism = Permutation::random(n, &mut thread_rng());
+        let permuted = graph.permute(&isomorphism);
+        group.bench_with_input(BenchmarkId::new("permutes_to_constant_time", n), &n, |b, _| {
+            b.iter(|| assert!(graph.permutes_to_constant_time(&isomorphism, &permuted)))
+        });
+        group.bench_with_input(BenchmarkId::new("permutes_to_scalar", n), &n, |b, _| {
+            b.iter(|| assert!(scalar_permutes_to(&graph, &isomorphism, &permuted)))
+        });
+        group.bench_with_input(BenchmarkId::new("permute_constant_time", n), &n, |b, _| {
+            b.iter(|| graph.permute_constant_time(&isomorphism))
+        });
+        group.bench_with_input(BenchmarkId::new("permute", n), &n, |b, _| {
+            b.iter(|| assert!(graph.permute(&isomorphism) == permuted))
+        });
+    }
+}
+
+fn bench_adjacency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("adjacency");
+    group.sample_size(10);
+    bench_sizes(&mut group, &[1000, 2000, 4000]);
+    group.finish();
+}
+
+// Calls take a third of a second or more at this size, too long for samples that each run more
+// iterations than the last
+fn bench_adjacency_large(c: &mut Criterion) {
+    let mut group = c.benchmark_group("adjacency_large");
+    group.sample_size(10).sampling_mode(SamplingMode::Flat).warm_up_time(Duration::from_secs(1)).measurement_time(Duration::from_secs(30));
+    bench_sizes(&mut group, &[10_000]);
+    group.finish();
+}
+
+criterion_group!(benches, bench_adjacency, bench_adjacency_large);
+criterion_main!(benches);
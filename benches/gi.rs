@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use zklib::graph::{GIProver, GIVerifier, Graph, GraphPair, ParallelGIProver, ParallelGIVerifier};
+use zklib::pipeline::run_transcribed_proof;
+use zklib::transcript::ChallengeTranscript;
+
+// Compares k sequential runs of the GI proof against a single run of the constant-round protocol with k
+// parallel repetitions, both in running time and in the number of bytes exchanged.
+
+fn instance() -> GraphPair {
+    GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    }
+}
+
+// Runs k sequential GI proofs, returning the total number of rounds and bytes exchanged
+fn run_sequential(instance: &GraphPair, k: usize) -> (u32, u64) {
+    let mut transcript = ChallengeTranscript::new(b"gi");
+    let mut rounds = 0;
+    for _ in 0..k {
+        let mut prover = GIProver::new(instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+        rounds += run_transcribed_proof(&mut prover, &mut verifier, &mut transcript).1;
+    }
+    (rounds, transcript.message_bytes())
+}
+
+fn run_parallel(instance: &GraphPair, k: usize) -> (u32, u64) {
+    let mut transcript = ChallengeTranscript::new(b"gi");
+    let mut prover = ParallelGIProver { r: 0, k, random_perms: Vec::new(), openings: Vec::new(), instance };
+    let mut verifier = ParallelGIVerifier { r: 0, k, challenge: Vec::new(), commitments: Vec::new(), instance };
+    let rounds = run_transcribed_proof(&mut prover, &mut verifier, &mut transcript).1;
+    (rounds, transcript.message_bytes())
+}
+
+fn bench_gi(c: &mut Criterion) {
+    let instance = instance();
+    let mut group = c.benchmark_group("gi");
+    for &k in [8, 16, 32].iter() {
+        let (sequential_rounds, sequential_bytes) = run_sequential(&instance, k);
+        let (parallel_rounds, parallel_bytes) = run_parallel(&instance, k);
+        eprintln!("k = {}: sequential {} rounds, {} bytes; parallel {} rounds, {} bytes",
+            k, sequential_rounds, sequential_bytes, parallel_rounds, parallel_bytes);
+        group.bench_with_input(BenchmarkId::new("sequential", k), &k, |b, &k| b.iter(|| run_sequential(&instance, k)));
+        group.bench_with_input(BenchmarkId::new("parallel", k), &k, |b, &k| b.iter(|| run_parallel(&instance, k)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gi);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+use rand::thread_rng;
+use zklib::coloring::{random_3_colorable_graph, ColoringProver, ColoringVerifier};
+use zklib::committed::GraphCommitmentScheme;
+use zklib::experiments::{repeat, run_metered, Report};
+use zklib::graph::{GraphPair, ParallelGIProver, ParallelGIVerifier};
+use zklib::hamiltonian::{random_hamiltonian_graph, HamiltonianProver, HamiltonianVerifier};
+use zklib::permutation::Permutation;
+
+// Proves statements about graphs of the same size with GI, 3-coloring and Hamiltonicity, repeating each
+// protocol until a cheating prover is accepted with probability at most 2^-SECURITY_BITS, and compares
+// what that costs. GI runs its repetitions in parallel, in 2 rounds, while the other two repeat their
+// 2-round interactions in sequence. 3-coloring catches a cheater only when the challenged edge is
+// monochromatic, so it needs far more repetitions than the others on a graph with many edges.
+// Hamiltonicity is run twice, committing to its graphs entry by entry and with a Merkle root.
+
+const VERTICES: u32 = 12;
+const EDGE_PROBABILITY: f64 = 0.3;
+const SECURITY_BITS: u32 = 20;
+const TRIALS: usize = 5;
+
+fn main() {
+    println!("\n===========================================");
+    println!("-------------------------------------------");
+    println!("\nPROTOCOL COMPARISON\n");
+    println!("-------------------------------------------");
+    println!("===========================================");
+
+    // GI and Hamiltonicity prove statements about the same graph. 3-coloring needs a graph with a planted
+    // coloring, where only the pairs of differently colored vertices, about 2/3 of them, can be joined, so
+    // its edge probability is scaled by 3/2 to give about as many edges.
+    let (graph, cycle) = random_hamiltonian_graph(VERTICES, EDGE_PROBABILITY);
+    let gi_instance = GraphPair { g1: graph.permute(&Permutation::random(VERTICES, &mut thread_rng())), g0: graph.clone() };
+    let (colorable, coloring) = random_3_colorable_graph(VERTICES, EDGE_PROBABILITY * 1.5);
+    let edges = colorable.edges().len() as f64;
+    println!("\nGraphs on {} vertices: {} edges for GI and Hamiltonicity, {} for 3-coloring.", VERTICES, graph.edges().len(), edges);
+
+    let coloring_repetitions = (SECURITY_BITS as f64 / -(1.0 - 1.0 / edges).log2()).ceil() as u32;
+    let mut report = Report::default();
+    println!("\nRunning {} trials of each protocol", TRIALS);
+    println!("===========================================");
+    report.measure("gi", 1, TRIALS, || {
+        let k = SECURITY_BITS as usize;
+        let prover = ParallelGIProver { r: 0, k, random_perms: Vec::new(), openings: Vec::new(), instance: &gi_instance };
+        let verifier = ParallelGIVerifier { r: 0, k, challenge: Vec::new(), commitments: Vec::new(), instance: &gi_instance };
+        run_metered(prover, verifier)
+    });
+    report.measure("3-coloring", coloring_repetitions, TRIALS, || repeat(coloring_repetitions, || {
+        let prover = ColoringProver::new(&colorable, &coloring).expect("The planted coloring uses 3 colors");
+        let verifier = ColoringVerifier { r: 0, edge: None, commitments: Vec::new(), graph: &colorable };
+        run_metered(prover, verifier)
+    }));
+    for (name, scheme) in [("hamiltonicity", GraphCommitmentScheme::PerEntry), ("ham/merkle", GraphCommitmentScheme::Merkle)].iter().cloned() {
+        report.measure(name, SECURITY_BITS, TRIALS, || repeat(SECURITY_BITS, || {
+            let prover = HamiltonianProver { r: 0, scheme, permutation: Permutation::identity(0), committed: None, graph: &graph, cycle: &cycle };
+            let verifier = HamiltonianVerifier { r: 0, b: false, commitment: None, graph: &graph };
+            run_metered(prover, verifier)
+        }));
+    }
+
+    // Rounds are the round trips each proof took, as counted by the metered verifier
+    let repetitions = [SECURITY_BITS, coloring_repetitions, SECURITY_BITS, SECURITY_BITS];
+
+    println!("\nCost of a proof with soundness error 2^-{}, averaged over {} trials", SECURITY_BITS, TRIALS);
+    println!("===========================================");
+    println!("{:<14} {:>12} {:>8} {:>10} {:>12} {:>12}", "protocol", "repetitions", "rounds", "bytes", "prover ms", "verifier ms");
+    for (i, m) in report.measurements.iter().enumerate() {
+        println!("{:<14} {:>12} {:>8.0} {:>10.0} {:>12.3} {:>12.3}", m.name, repetitions[i], m.messages,
+            m.message_bytes, m.prover_time.wall * 1000.0, m.verifier_time.wall * 1000.0);
+    }
+    let rejected: Vec<&str> = report.measurements.iter().filter(|m| m.trials.acceptance_rate() < 1.0).map(|m| m.name.as_str()).collect();
+    if !rejected.is_empty() {
+        println!("\nThe verifier rejected an honest prover in: {}.", rejected.join(", "));
+    }
+}
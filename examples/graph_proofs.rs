@@ -35,12 +35,7 @@ fn main() {
 
     println!("\nGI interactive proof with honest prover");
     println!("===========================================");
-    let mut gi_prover = GIProverMalicious{
-        r: 0,
-        isomorphism: Vec::new(),
-        instance: &gi_instance,
-        p: 0.5,
-    };
+    let mut gi_prover = GIProverMalicious::new(&gi_instance);
     let mut gi_verifier = GIVerifier{
         r: 0,
         b: false,
@@ -51,12 +46,7 @@ fn main() {
 
     println!("\nGI interactive proof with malicious prover");
     println!("===========================================");
-    let mut gi_malicious_prover = GIProverMalicious{
-        r: 0,
-        isomorphism: Vec::new(),
-        instance: &gni_instance,
-        p: 0.5,
-    };
+    let mut gi_malicious_prover = GIProverMalicious::new(&gni_instance);
     let mut gi_malicious_verifier = GIVerifier{
         r: 0,
         b: false,
@@ -0,0 +1,79 @@
+use zklib::{sudoku::{SudokuPuzzle, SudokuProver, SudokuVerifier, SudokuChallenge}, run_interactive_proof};
+
+fn main() {
+    println!("\n===========================================");
+    println!("-------------------------------------------");
+    println!("\nPUZZLE PROTOCOLS\n");
+    println!("-------------------------------------------");
+    println!("===========================================");
+    let puzzle = SudokuPuzzle {
+        grid: [
+            [5, 3, 0, 0, 7, 0, 0, 0, 0],
+            [6, 0, 0, 1, 9, 5, 0, 0, 0],
+            [0, 9, 8, 0, 0, 0, 0, 6, 0],
+            [8, 0, 0, 0, 6, 0, 0, 0, 3],
+            [4, 0, 0, 8, 0, 3, 0, 0, 1],
+            [7, 0, 0, 0, 2, 0, 0, 0, 6],
+            [0, 6, 0, 0, 0, 0, 2, 8, 0],
+            [0, 0, 0, 4, 1, 9, 0, 0, 5],
+            [0, 0, 0, 0, 8, 0, 0, 7, 9],
+        ],
+    };
+    let solution = [
+        [5, 3, 4, 6, 7, 8, 9, 1, 2],
+        [6, 7, 2, 1, 9, 5, 3, 4, 8],
+        [1, 9, 8, 3, 4, 2, 5, 6, 7],
+        [8, 5, 9, 7, 6, 1, 4, 2, 3],
+        [4, 2, 6, 8, 5, 3, 7, 9, 1],
+        [7, 1, 3, 9, 2, 4, 8, 5, 6],
+        [9, 6, 1, 5, 3, 7, 2, 8, 4],
+        [2, 8, 7, 4, 1, 9, 6, 3, 5],
+        [3, 4, 5, 2, 8, 6, 1, 7, 9],
+    ];
+    // A completed Sudoku that ignores the puzzle's clues, obtained by swapping digits 1 and 2
+    let mut wrong_solution = solution;
+    wrong_solution.iter_mut().for_each(|row| row.iter_mut().for_each(|x| *x = match *x { 1 => 2, 2 => 1, x => x }));
+
+    println!("\nSudoku interactive proof with honest prover");
+    println!("===========================================");
+    let mut sudoku_prover = SudokuProver {
+        r: 0,
+        cell_openings: Vec::new(),
+        relabeling_openings: Vec::new(),
+        puzzle: &puzzle,
+        solution: &solution,
+    };
+    let mut sudoku_verifier = SudokuVerifier {
+        r: 0,
+        challenge: SudokuChallenge::Clues,
+        cell_commitments: Vec::new(),
+        relabeling_commitments: Vec::new(),
+        puzzle: &puzzle,
+    };
+    run_interactive_proof(&mut sudoku_prover, &mut sudoku_verifier);
+
+    println!("\nSudoku interactive proof with malicious prover");
+    println!("===========================================");
+    // The malicious prover is only caught when the verifier asks to see the clues, so run until it is
+    loop {
+        let mut sudoku_malicious_prover = SudokuProver {
+            r: 0,
+            cell_openings: Vec::new(),
+            relabeling_openings: Vec::new(),
+            puzzle: &puzzle,
+            solution: &wrong_solution,
+        };
+        let mut sudoku_malicious_verifier = SudokuVerifier {
+            r: 0,
+            challenge: SudokuChallenge::Clues,
+            cell_commitments: Vec::new(),
+            relabeling_commitments: Vec::new(),
+            puzzle: &puzzle,
+        };
+        if !run_interactive_proof(&mut sudoku_malicious_prover, &mut sudoku_malicious_verifier) {
+            break;
+        }
+        println!();
+    }
+    println!();
+}
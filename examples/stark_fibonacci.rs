@@ -0,0 +1,183 @@
+use zklib::field::{evaluate_on_coset, intt, Fp};
+use zklib::fri::Fri;
+use zklib::iop::{iop_to_interactive, iop_to_nizk, query_indices, verify_iop_nizk, Iop, IopProver, Oracle, Query};
+use zklib::run_interactive_proof;
+use zklib::transcript::ProofContext;
+
+// A STARK for the claim "the Fibonacci sequence starting 1, 1 has `result` as its element number steps - 1",
+// modulo the field's prime. The execution trace T_0, ..., T_(n-1) is interpolated into a polynomial T of
+// degree below n on the subgroup <g> of order n, so that T(g^i) = T_i, and the AIR has one transition
+// constraint T(g^2 x) = T(g x) + T(x) on the first n - 2 rows, plus boundary constraints T(1) = 1,
+// T(g) = 1, and T(g^(n-1)) = result.
+//
+// The prover sends the trace's evaluations on a coset of size blowup * n. After a challenge alpha, it
+// sends the composition polynomial
+//
+//   H = C(x) / Z(x) + alpha (T(x) - 1) / (x - 1) + alpha^2 (T(x) - 1) / (x - g) + alpha^3 (T(x) - result) / (x - g^(n-1))
+//
+// where C(x) = T(g^2 x) - T(g x) - T(x) and Z vanishes on the rows the transition applies to. Every
+// quotient is a polynomial of low degree exactly when its constraint holds, so FRI then shows that H has
+// degree below n. The verifier recomputes H at each queried point from the trace at x, g x, and g^2 x, and
+// checks it against FRI's first layer. This is a proof of integrity only: the trace isn't masked, so the
+// queried values of T reveal parts of the sequence.
+
+struct FibonacciAir {
+    steps: usize,
+    result: Fp,
+    fri: Fri,
+}
+
+impl FibonacciAir {
+    fn new(steps: usize, result: Fp, blowup: usize, queries: usize) -> FibonacciAir {
+        FibonacciAir { steps, result, fri: Fri::new(steps, blowup, queries) }
+    }
+
+    fn blowup(&self) -> usize {
+        self.fri.domain_size / self.steps
+    }
+
+    // Point of the evaluation domain at position i
+    fn point(&self, i: usize) -> Fp {
+        Fri::OFFSET * Fp::root_of_unity(self.fri.domain_size.trailing_zeros()).pow(i as u64)
+    }
+
+    // Value of the composition polynomial at x from the trace at x, g x, and g^2 x
+    fn composition(&self, x: Fp, t: [Fp; 3], alpha: Fp) -> Fp {
+        let n = self.steps as u64;
+        let g = Fp::root_of_unity(self.steps.trailing_zeros());
+        let quotient = |numerator: Fp, denominator: Fp| numerator * denominator.inverse().expect("The coset avoids the trace domain");
+        let last = g.pow(n - 1);
+        let transition = quotient((t[2] - t[1] - t[0]) * (x - g.pow(n - 2)) * (x - last), x.pow(n) - Fp::ONE);
+        let boundaries = [quotient(t[0] - Fp::ONE, x - Fp::ONE), quotient(t[0] - Fp::ONE, x - g), quotient(t[0] - self.result, x - last)];
+        boundaries.iter().rev().fold(Fp::ZERO, |acc, &b| (acc + b) * alpha) + transition
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        [self.steps as u64, self.result.value(), self.blowup() as u64, self.fri.queries as u64].iter().flat_map(|x| x.to_be_bytes()).collect()
+    }
+}
+
+impl Iop for FibonacciAir {
+    fn rounds(&self) -> usize {
+        2 + self.fri.folds()
+    }
+
+    // For each index, the trace at x, g x, and g^2 x, followed by FRI's queries with the composition as its first layer
+    fn queries(&self, _challenges: &[[u8; 32]], _lengths: &[usize], randomness: &[u8; 32]) -> Vec<Query> {
+        let size = self.fri.domain_size;
+        let indices = query_indices(randomness, self.fri.queries, size);
+        let mut queries: Vec<Query> = indices.iter().flat_map(|&i| (0..3).map(move |k| Query { oracle: 0, index: (i + k * self.blowup()) % size })).collect();
+        queries.extend(self.fri.layer_queries(1, &indices));
+        queries
+    }
+
+    fn decide(&self, challenges: &[[u8; 32]], lengths: &[usize], queries: &[Query], answers: &[Vec<u8>]) -> bool {
+        let trace_queries = 3 * self.fri.queries;
+        if lengths[0] != self.fri.domain_size || lengths[1..] != *self.fri.layer_lengths() || answers.len() < trace_queries {
+            return false;
+        }
+        let alpha = Fp::from_challenge(&challenges[1]);
+        let betas: Vec<Fp> = challenges[2..].iter().map(Fp::from_challenge).collect();
+        let indices: Vec<usize> = queries[..trace_queries].iter().step_by(3).map(|x| x.index).collect();
+        let compositions = match self.fri.check(&betas, &indices, &answers[trace_queries..]) {
+            Ok(values) => values,
+            Err(e) => {
+                println!("Rejecting proof: {}.", e);
+                return false;
+            }
+        };
+        let trace: Option<Vec<Fp>> = answers[..trace_queries].iter().map(|x| Fp::from_bytes(x)).collect();
+        let trace = match trace {
+            Some(trace) => trace,
+            None => return false,
+        };
+        indices.iter().zip(trace.chunks(3)).zip(compositions).all(|((&i, t), h)| self.composition(self.point(i), [t[0], t[1], t[2]], alpha) == h)
+    }
+}
+
+struct FibonacciProver<'a> {
+    air: &'a FibonacciAir,
+    // Evaluations of the trace polynomial on the domain, and of the last FRI layer sent
+    trace: Vec<Fp>,
+    layer: Vec<Fp>,
+}
+
+impl<'a> FibonacciProver<'a> {
+    fn new(air: &'a FibonacciAir) -> FibonacciProver<'a> {
+        let mut trace = vec![Fp::ONE, Fp::ONE];
+        while trace.len() < air.steps {
+            trace.push(trace[trace.len() - 1] + trace[trace.len() - 2]);
+        }
+        intt(&mut trace, Fp::root_of_unity(air.steps.trailing_zeros()));
+        FibonacciProver { air, trace: evaluate_on_coset(&trace, Fri::OFFSET, air.fri.domain_size), layer: Vec::new() }
+    }
+}
+
+impl IopProver for FibonacciProver<'_> {
+    fn oracle(&mut self, round: usize, challenge: &[u8; 32]) -> Oracle {
+        let (size, blowup) = (self.air.fri.domain_size, self.air.blowup());
+        let layer = match round {
+            0 => return self.trace.iter().map(|x| x.to_bytes().to_vec()).collect(),
+            1 => {
+                let alpha = Fp::from_challenge(challenge);
+                (0..size).map(|i| {
+                    let t = [self.trace[i], self.trace[(i + blowup) % size], self.trace[(i + 2 * blowup) % size]];
+                    self.air.composition(self.air.point(i), t, alpha)
+                }).collect()
+            },
+            _ => self.air.fri.fold(&self.layer, round - 2, Fp::from_challenge(challenge)),
+        };
+        self.layer = layer;
+        self.layer.iter().map(|x| x.to_bytes().to_vec()).collect()
+    }
+}
+
+fn fibonacci(steps: usize) -> Fp {
+    let (mut a, mut b) = (Fp::ONE, Fp::ONE);
+    for _ in 2..steps {
+        let next = a + b;
+        a = b;
+        b = next;
+    }
+    b
+}
+
+fn main() {
+    println!("\n===========================================");
+    println!("-------------------------------------------");
+    println!("\nSTARK FOR A FIBONACCI TRACE\n");
+    println!("-------------------------------------------");
+    println!("===========================================");
+    let steps = 1 << 10;
+    let result = fibonacci(steps);
+
+    println!("\nNon-interactive proof of the true result");
+    println!("===========================================");
+    let air = FibonacciAir::new(steps, result, 8, 40);
+    let context = ProofContext::new("stark-fibonacci", b"example", &air.encode());
+    let proof = iop_to_nizk(&air, &mut FibonacciProver::new(&air), &context);
+    let bytes = bincode::serialize(&proof).unwrap().len();
+    println!("Element {} of the sequence is {:?}. Proof of {} oracles takes {} bytes.", steps - 1, result, proof.commitments.len(), bytes);
+    match verify_iop_nizk(&air, &proof, &context) {
+        Ok(()) => println!("Proof accepted."),
+        Err(e) => println!("Proof rejected: {}.", e),
+    }
+
+    println!("\nNon-interactive proof of a false result");
+    println!("===========================================");
+    // The prover computes the true trace, so the boundary constraint on the last row fails
+    let false_air = FibonacciAir::new(steps, result + Fp::ONE, 8, 40);
+    let context = ProofContext::new("stark-fibonacci", b"example", &false_air.encode());
+    let proof = iop_to_nizk(&false_air, &mut FibonacciProver::new(&false_air), &context);
+    match verify_iop_nizk(&false_air, &proof, &context) {
+        Ok(()) => println!("Proof accepted."),
+        Err(e) => println!("Proof rejected: {}.", e),
+    }
+
+    println!("\nInteractive proof of the true result");
+    println!("===========================================");
+    let air = FibonacciAir::new(64, fibonacci(64), 4, 20);
+    let mut fibonacci_prover = FibonacciProver::new(&air);
+    let (mut prover, mut verifier) = iop_to_interactive(&air, &mut fibonacci_prover);
+    run_interactive_proof(&mut prover, &mut verifier);
+}
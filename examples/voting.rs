@@ -0,0 +1,59 @@
+use rand::{thread_rng, Rng};
+use zklib::keys::ElGamalKeyPair;
+use zklib::params::{Parameters, SchnorrParameters};
+use zklib::voting::{shuffle, Ballot, Decryption, ElGamalCiphertext, Opening, ShuffleProof, SHUFFLE_ROUNDS};
+
+// A toy verifiable election, put together from the ballots, shuffle proof and decryption proofs of
+// src/voting.rs. Voters cast ballots under the trustee's ElGamal key, a mixer shuffles the accepted
+// ballots with a proof, and the trustee decrypts every shuffled ballot with a proof, from which anyone
+// can recount the tally. The parameters are far too small to be secure.
+
+const BITS: u64 = 128;
+const VOTERS: usize = 9;
+
+fn main() {
+    println!("\n===========================================");
+    println!("-------------------------------------------");
+    println!("\nVERIFIABLE VOTING\n");
+    println!("-------------------------------------------");
+    println!("===========================================");
+
+    let params = SchnorrParameters::generate(BITS);
+    let trustee = ElGamalKeyPair::generate(&params);
+    let key = trustee.public_key();
+
+    println!("\nCasting ballots");
+    println!("===========================================");
+    let votes: Vec<bool> = (0..VOTERS).map(|_| thread_rng().gen()).collect();
+    let mut board: Vec<Ballot> = votes.iter().map(|&vote| Ballot::cast(&key, vote)).collect();
+    // A voter tries to count twice by encrypting 2, reusing the proof of an honest ballot
+    let stuffed = Ballot { ciphertext: ElGamalCiphertext::encrypt(&key, 2, &params.random_exponent()), proof: board[0].proof.clone() };
+    board.push(stuffed);
+    let accepted: Vec<ElGamalCiphertext> = board.iter().enumerate().filter_map(|(i, ballot)| {
+        let valid = ballot.verify(&key);
+        println!("Ballot {}: {}", i, if valid { "accepted" } else { "rejected, not an encryption of 0 or 1" });
+        if valid { Some(ballot.ciphertext.clone()) } else { None }
+    }).collect();
+
+    println!("\nShuffling {} ballots", accepted.len());
+    println!("===========================================");
+    let opening = Opening::random(&params, accepted.len());
+    let mixed = shuffle(&key, &accepted, &opening);
+    let proof = ShuffleProof::prove(&key, &accepted, &mixed, &opening);
+    println!("Shuffle proof with {} rounds: {}", SHUFFLE_ROUNDS, if proof.verify(&key, &accepted, &mixed) { "accepted" } else { "rejected" });
+    // A mixer that swaps in a ballot of its own can't open the shuffles that lead to the output
+    let mut tampered = mixed.clone();
+    tampered[0] = ElGamalCiphertext::encrypt(&key, 1, &params.random_exponent());
+    let tampered_proof = ShuffleProof::prove(&key, &accepted, &tampered, &opening);
+    println!("Shuffle proof with a replaced ballot: {}", if tampered_proof.verify(&key, &accepted, &tampered) { "accepted" } else { "rejected" });
+
+    println!("\nTallying");
+    println!("===========================================");
+    let decryptions: Vec<Decryption> = mixed.iter().map(|ciphertext| Decryption::decrypt(&trustee, ciphertext)).collect();
+    let verified = mixed.iter().zip(&decryptions).all(|(ciphertext, decryption)| decryption.verify(&key, ciphertext));
+    let tally: u32 = decryptions.iter().map(|x| x.vote).sum();
+    println!("Decryption proofs: {}", if verified { "accepted" } else { "rejected" });
+    println!("Shuffled votes: {:?}", decryptions.iter().map(|x| x.vote).collect::<Vec<_>>());
+    println!("Tally: {} of {} votes for 1, expected {}", tally, decryptions.len(), votes.iter().filter(|&&vote| vote).count());
+    println!();
+}
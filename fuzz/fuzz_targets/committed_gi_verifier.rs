@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zklib::fuzzing::fuzz_protocol;
+
+fuzz_target!(|data: &[u8]| {
+    if let Err(finding) = fuzz_protocol("committed-gi", data) {
+        panic!("{}", finding);
+    }
+});
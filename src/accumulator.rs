@@ -0,0 +1,408 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::graph::check_count;
+use crate::hash::{Hasher, Sha256Hasher};
+use crate::modarith::{is_probable_prime, mod_exp, mod_inverse};
+use crate::params::{serde_biguint, serde_biguints, Parameters, RsaParameters};
+use crate::randomness::checked_thread_rng;
+use crate::secret::Secret;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ RSA accumulator implementation ************
+
+// An RSA accumulator compresses a set into a single element A = g^(x_1 x_2 ... x_k) mod N, where each
+// element of the set is hashed to a prime x_i. The witness that x is a member is the x-th root of A,
+// w = g^(product of the other primes), which anyone can check with w^x = A. Forging a witness for a prime
+// that isn't accumulated means taking a root of g, which is hard for anyone who doesn't know the
+// factorization of N by the strong RSA assumption, so the modulus has to be generated by a party that
+// discards it. Adding an element raises A to its prime, which changes the witness of every other member.
+//
+// Elements hash to primes x = 2^MEMBER_BITS + y with y below 2^HASH_BITS, so that every member lies in a
+// narrow range far above 1 and far below the product of any two members. The membership proof relies on
+// this to tell single members apart from 1 and from products of members, both of which have roots of A.
+
+// Members are 2^MEMBER_BITS plus a hash of HASH_BITS bits
+const MEMBER_BITS: usize = 512;
+const HASH_BITS: usize = 128;
+// Size of the membership proof's challenges, and extra bits in its masks that hide the secrets statistically
+const CHALLENGE_BITS: u64 = 128;
+const SLACK_BITS: u64 = 80;
+
+// Hashes an element to the prime representing it in an accumulator
+pub fn hash_to_prime(element: &[u8]) -> BigUint {
+    let base = BigUint::one() << MEMBER_BITS;
+    (0u64..).map(|counter| {
+        let digest = Sha256Hasher::hash(&[b"accumulator", &counter.to_be_bytes(), element]);
+        &base + (BigUint::from_bytes_be(&digest[..HASH_BITS / 8]) | BigUint::one())
+    }).find(is_probable_prime).unwrap()
+}
+
+// RSA modulus N with two random quadratic residues g and h, whose discrete logs to each other are unknown
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccumulatorParameters {
+    #[serde(with = "serde_biguint")]
+    pub n: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub g: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub h: BigUint,
+}
+
+impl Parameters for AccumulatorParameters {
+    fn generate(bits: u64) -> AccumulatorParameters {
+        let n = RsaParameters::generate(bits).n;
+        let square = || loop {
+            let x = checked_thread_rng().gen_biguint_range(&BigUint::from(2u32), &n);
+            if x.gcd(&n).is_one() {
+                break mod_exp(&x, &BigUint::from(2u32), &n);
+            }
+        };
+        let (g, h) = (square(), square());
+        AccumulatorParameters { n, g, h }
+    }
+
+    fn validate(&self) -> bool {
+        // Without the factorization we can't check that g and h are squares, only that they are units
+        self.n.is_odd() && !is_probable_prime(&self.n)
+            && self.check_element(&self.g).is_ok() && self.check_element(&self.h).is_ok()
+    }
+}
+
+impl AccumulatorParameters {
+    // Checks that x is a unit mod N other than 1, describing the problem otherwise
+    pub fn check_element(&self, x: &BigUint) -> Result<(), String> {
+        if x.is_zero() || x.is_one() || *x >= self.n {
+            Err(format!("{} is not a residue mod N other than 0 or 1", x))
+        } else if !x.gcd(&self.n).is_one() {
+            Err(format!("{} is not coprime to N", x))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Checks that the witness is a root of the accumulated value for the element's prime
+    pub fn verify(&self, value: &BigUint, element: &[u8], witness: &BigUint) -> bool {
+        *witness < self.n && mod_exp(witness, &hash_to_prime(element), &self.n) == *value
+    }
+
+    fn pow(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        mod_exp(base, exponent, &self.n)
+    }
+
+    // Inverse of a unit mod N, which elements are checked to be before they are inverted
+    fn inv(&self, x: &BigUint) -> BigUint {
+        mod_inverse(x, &self.n).unwrap()
+    }
+}
+
+pub struct Accumulator {
+    pub params: AccumulatorParameters,
+    // Accumulated value A
+    pub value: BigUint,
+    primes: Vec<BigUint>,
+}
+
+impl Accumulator {
+    // Accumulator of the empty set, whose value is g
+    pub fn new(params: &AccumulatorParameters) -> Accumulator {
+        Accumulator { params: params.clone(), value: params.g.clone(), primes: Vec::new() }
+    }
+
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.primes.contains(&hash_to_prime(element))
+    }
+
+    // Adds the element, returning whether it wasn't already a member
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let prime = hash_to_prime(element);
+        if self.primes.contains(&prime) {
+            return false;
+        }
+        self.value = self.params.pow(&self.value, &prime);
+        self.primes.push(prime);
+        true
+    }
+
+    // Witness that the element is a member of the current set, if it is one
+    pub fn witness(&self, element: &[u8]) -> Option<BigUint> {
+        let prime = hash_to_prime(element);
+        if !self.primes.contains(&prime) {
+            return None;
+        }
+        let others = self.primes.iter().filter(|&x| *x != prime).fold(BigUint::one(), |acc, x| acc * x);
+        Some(self.params.pow(&self.params.g, &others))
+    }
+}
+
+// ************ Committed membership proof implementation ************
+
+// The prover shows that a committed prime is accumulated in A without revealing which one, following
+// Camenisch and Lysyanskaya. The member x is committed to as C = g^x h^r mod N, an integer commitment
+// that hides x statistically when r has SLACK_BITS more bits than N. The prover hides its witness w as
+// C_w = w h^r2, commits to r2 as C_r = g^r2 h^r3, and proves knowledge of x' = x - 2^MEMBER_BITS, r, r2,
+// r3, delta = x r2 and epsilon = x r3 satisfying
+//
+// - C / g^(2^MEMBER_BITS) = g^x' h^r, so x' is the value committed to in C, less the fixed offset
+// - C_r = g^r2 h^r3
+// - C_r^-(2^MEMBER_BITS) = C_r^x' g^-delta h^-epsilon, which makes delta = x r2 and epsilon = x r3
+// - A C_w^-(2^MEMBER_BITS) = C_w^x' h^-delta, so that (C_w / h^r2)^x = A
+//
+// with one sigma protocol over the integers: every secret gets a mask with CHALLENGE_BITS + SLACK_BITS
+// more bits than the secret itself, and the response is mask + challenge * secret, with no reduction since
+// the order of the group is unknown. The verifier also checks that the response for x' is small enough
+// that an extracted x' is below 2^(MEMBER_BITS - 1) in absolute value, so x lies strictly between
+// 2^(MEMBER_BITS - 1) and 2^(MEMBER_BITS + 1). Under the strong RSA assumption, an x with a known root of
+// A divides the product of the members, and the only such numbers in that range are the members
+// themselves.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MemberCommitment {
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct MemberOpening {
+    pub prime: BigUint,
+    pub randomness: Secret<BigUint>,
+    pub witness: Secret<BigUint>,
+}
+
+impl AccumulatorParameters {
+    // Commits to the element's prime, keeping the witness for it with the opening
+    pub fn commit_member(&self, element: &[u8], witness: &BigUint) -> (MemberCommitment, MemberOpening) {
+        let prime = hash_to_prime(element);
+        let randomness = checked_thread_rng().gen_biguint(self.randomness_bits());
+        let c = self.pow(&self.g, &prime) * self.pow(&self.h, &randomness) % &self.n;
+        (MemberCommitment { c }, MemberOpening { prime, randomness: Secret::new(randomness), witness: Secret::new(witness.clone()) })
+    }
+
+    // Bits of the randomness r, r2 and r3 of the integer commitments, SLACK_BITS more than N
+    fn randomness_bits(&self) -> u64 {
+        self.n.bits() + SLACK_BITS
+    }
+
+    // Most bits of each secret (x', r, r2, r3, delta, epsilon), where delta and epsilon are products of a
+    // member of MEMBER_BITS + 1 bits with randomness
+    fn secret_bits(&self) -> Vec<u64> {
+        let randomness = self.randomness_bits();
+        let product = MEMBER_BITS as u64 + 1 + randomness;
+        vec![HASH_BITS as u64, randomness, randomness, randomness, product, product]
+    }
+
+    // Bits of the mask of each secret, CHALLENGE_BITS + SLACK_BITS more than the secret, so that the mask
+    // hides the challenge times the secret statistically
+    fn mask_bits(&self) -> Vec<u64> {
+        self.secret_bits().into_iter().map(|bits| bits + CHALLENGE_BITS + SLACK_BITS).collect()
+    }
+
+    // Right-hand sides of the four relations for the exponents (x', r, r2, r3, delta, epsilon)
+    fn relations(&self, witness: &BigUint, randomness: &BigUint, e: &[BigUint]) -> Vec<BigUint> {
+        let (n, g, h) = (&self.n, &self.g, &self.h);
+        vec![
+            self.pow(g, &e[0]) * self.pow(h, &e[1]) % n,
+            self.pow(g, &e[2]) * self.pow(h, &e[3]) % n,
+            self.pow(randomness, &e[0]) * self.inv(&(self.pow(g, &e[4]) * self.pow(h, &e[5]) % n)) % n,
+            self.pow(witness, &e[0]) * self.inv(&self.pow(h, &e[4])) % n,
+        ]
+    }
+
+    // Left-hand sides of the four relations, which don't depend on the secrets
+    fn targets(&self, value: &BigUint, commitment: &MemberCommitment, witness: &BigUint, randomness: &BigUint) -> Vec<BigUint> {
+        let (n, offset) = (&self.n, BigUint::one() << MEMBER_BITS);
+        vec![
+            &commitment.c * self.inv(&self.pow(&self.g, &offset)) % n,
+            randomness.clone(),
+            self.inv(&self.pow(randomness, &offset)),
+            value * self.inv(&self.pow(witness, &offset)) % n,
+        ]
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MembershipProverMessage {
+    // Blinded witness C_w, commitment C_r to its blinding, and the announcement for each relation
+    Announcement {
+        #[serde(with = "serde_biguint")]
+        witness: BigUint,
+        #[serde(with = "serde_biguint")]
+        randomness: BigUint,
+        #[serde(with = "serde_biguints")]
+        announcements: Vec<BigUint>,
+    },
+    // Responses for x', r, r2, r3, delta and epsilon
+    Response(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MembershipVerifierMessage {
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+// Proves that the committed prime is accumulated in the value
+pub struct MembershipProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Secrets (x', r, r2, r3, delta, epsilon) followed by their masks
+    pub state: Secret<Vec<BigUint>>,
+    pub params: &'a AccumulatorParameters,
+    pub opening: &'a MemberOpening,
+}
+
+impl Prover for MembershipProver<'_> {
+    type ProverMessage = MembershipProverMessage;
+    type VerifierMessage = MembershipVerifierMessage;
+
+    fn handle(&mut self, msg: &MembershipVerifierMessage) -> (MembershipProverMessage, bool) {
+        self.r += 1;
+        let (params, opening) = (self.params, self.opening);
+        match self.r {
+            // During the first round, the prover blinds its witness and announces the relations
+            1 => {
+                let (r2, r3) = (checked_thread_rng().gen_biguint(params.randomness_bits()), checked_thread_rng().gen_biguint(params.randomness_bits()));
+                let witness = &*opening.witness * params.pow(&params.h, &r2) % &params.n;
+                let randomness = params.pow(&params.g, &r2) * params.pow(&params.h, &r3) % &params.n;
+                let x = &opening.prime - (BigUint::one() << MEMBER_BITS);
+                let (delta, epsilon) = (&opening.prime * &r2, &opening.prime * &r3);
+                let masks: Vec<BigUint> = params.mask_bits().into_iter().map(|bits| checked_thread_rng().gen_biguint(bits)).collect();
+                let announcements = params.relations(&witness, &randomness, &masks);
+                self.state = Secret::new(vec![x, (*opening.randomness).clone(), r2, r3, delta, epsilon].into_iter().chain(masks).collect());
+                (MembershipProverMessage::Announcement { witness, randomness, announcements }, false)
+            },
+            // During the second round, the prover responds to the challenge without reducing the responses
+            2 => {
+                let (secrets, masks) = self.state.split_at(6);
+                let response = secrets.iter().zip(masks).map(|(x, mask)| mask + &msg.c * x).collect();
+                (MembershipProverMessage::Response(response), false)
+            },
+            // After responding, the prover sends a message to terminate the interaction
+            _ => (MembershipProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct MembershipVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    pub witness: BigUint,
+    pub randomness: BigUint,
+    pub announcements: Vec<BigUint>,
+    pub challenge: BigUint,
+    pub params: &'a AccumulatorParameters,
+    // Accumulated value the member must be in
+    pub value: &'a BigUint,
+    pub commitment: &'a MemberCommitment,
+}
+
+impl MembershipVerifier<'_> {
+    pub fn new<'a>(params: &'a AccumulatorParameters, value: &'a BigUint, commitment: &'a MemberCommitment) -> MembershipVerifier<'a> {
+        MembershipVerifier { r: 0, witness: BigUint::zero(), randomness: BigUint::zero(), announcements: Vec::new(), challenge: BigUint::zero(), params, value, commitment }
+    }
+}
+
+impl Verifier for MembershipVerifier<'_> {
+    type ProverMessage = MembershipProverMessage;
+    type VerifierMessage = MembershipVerifierMessage;
+
+    fn init(&mut self) -> MembershipVerifierMessage {
+        MembershipVerifierMessage { c: BigUint::zero() }
+    }
+
+    fn validate(&self, msg: &MembershipProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, MembershipProverMessage::Announcement { witness, randomness, announcements }) => {
+                check_count(announcements.len(), 4, "announcements")?;
+                [self.value, &self.commitment.c, witness, randomness].iter().cloned().chain(announcements).try_for_each(|x| self.params.check_element(x))
+            },
+            (1, _) => Err("expected an announcement".to_string()),
+            (_, MembershipProverMessage::Response(response)) => check_count(response.len(), 6, "responses"),
+            _ => Err("expected a response".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &MembershipProverMessage) -> (MembershipVerifierMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match (self.r, msg) {
+            (1, MembershipProverMessage::Announcement { witness, randomness, announcements }) => {
+                self.witness = witness.clone();
+                self.randomness = randomness.clone();
+                self.announcements = announcements.clone();
+                self.challenge = checked_thread_rng().gen_biguint(CHALLENGE_BITS);
+                (MembershipVerifierMessage { c: self.challenge.clone() }, false)
+            },
+            // Each relation must hold for the responses, and the response for x' must keep x in range
+            (_, MembershipProverMessage::Response(response)) => {
+                let in_range = response[0].bits() <= params.mask_bits()[0] + 1;
+                let relations = params.relations(&self.witness, &self.randomness, response);
+                let targets = params.targets(self.value, self.commitment, &self.witness, &self.randomness);
+                let accept = in_range && relations.iter().zip(self.announcements.iter().zip(&targets)).all(|(lhs, (t, y))| {
+                    *lhs == t * params.pow(y, &self.challenge) % &params.n
+                });
+                (MembershipVerifierMessage { c: self.challenge.clone() }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_accumulator() {
+    let params = AccumulatorParameters::generate(256);
+    assert!(params.validate());
+    let mut accumulator = Accumulator::new(&params);
+    assert!(accumulator.add(b"alice") && accumulator.add(b"bob"));
+    assert!(!accumulator.add(b"alice"));
+    let witness = accumulator.witness(b"alice").unwrap();
+    assert!(params.verify(&accumulator.value, b"alice", &witness));
+    assert!(!params.verify(&accumulator.value, b"bob", &witness));
+    assert_eq!(accumulator.witness(b"carol"), None);
+    // Adding a member changes the accumulated value, so old witnesses have to be recomputed
+    accumulator.add(b"carol");
+    assert!(!params.verify(&accumulator.value, b"alice", &witness));
+    assert!(params.verify(&accumulator.value, b"alice", &accumulator.witness(b"alice").unwrap()));
+}
+
+#[test]
+fn test_membership_proof() {
+    let params = AccumulatorParameters::generate(256);
+    let mut accumulator = Accumulator::new(&params);
+    for element in ["alice", "bob", "carol"].iter() {
+        accumulator.add(element.as_bytes());
+    }
+    let play = |element: &[u8], witness: &BigUint| {
+        let (commitment, opening) = params.commit_member(element, witness);
+        let mut prover = MembershipProver { r: 0, state: Secret::new(Vec::new()), params: &params, opening: &opening };
+        let mut verifier = MembershipVerifier::new(&params, &accumulator.value, &commitment);
+        run_interactive_proof(&mut prover, &mut verifier)
+    };
+    assert!(play(b"bob", &accumulator.witness(b"bob").unwrap()));
+    // A non-member can't use a member's witness
+    assert!(!play(b"dave", &accumulator.witness(b"bob").unwrap()));
+}
+
+#[test]
+fn test_membership_mask_bits() {
+    let params = AccumulatorParameters::generate(256);
+    let mut accumulator = Accumulator::new(&params);
+    accumulator.add(b"alice");
+    let (_, opening) = params.commit_member(b"alice", &accumulator.witness(b"alice").unwrap());
+    assert!(opening.randomness.bits() <= params.randomness_bits());
+    let mut prover = MembershipProver { r: 0, state: Secret::new(Vec::new()), params: &params, opening: &opening };
+    prover.handle(&MembershipVerifierMessage { c: BigUint::zero() });
+    // Every secret fits its bound, and every mask has CHALLENGE_BITS + SLACK_BITS more bits than the bound
+    let (secrets, masks) = prover.state.split_at(6);
+    for ((secret, mask), (secret_bits, mask_bits)) in secrets.iter().zip(masks).zip(params.secret_bits().into_iter().zip(params.mask_bits())) {
+        assert!(secret.bits() <= secret_bits);
+        assert_eq!(mask_bits, secret_bits + CHALLENGE_BITS + SLACK_BITS);
+        assert!(mask.bits() <= mask_bits && mask.bits() > mask_bits - 40);
+    }
+    assert_eq!(params.secret_bits()[1], 256 + SLACK_BITS);
+    assert_eq!(params.secret_bits()[4], MEMBER_BITS as u64 + 1 + 256 + SLACK_BITS);
+}
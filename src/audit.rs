@@ -0,0 +1,163 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::transcript::ChallengeTranscript;
+#[cfg(test)]
+use rand::{thread_rng, Rng};
+
+// ************ Audit log implementation ************
+
+// Deployments that must account for what they accepted keep an audit log of every verdict: which
+// protocol, a digest of the statement, a digest of the proof or transcript, and when. The log is a file of
+// JSON lines, one entry per line, and each entry carries the hash of the one before it. Editing, removing,
+// or reordering an entry therefore breaks every later link, which `zklib audit verify` detects. Removing
+// entries from the end leaves a valid chain, so operators should record the head hash it prints
+// somewhere the server can't write to.
+
+// Previous hash of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // Position in the log, counting from 0
+    pub sequence: u64,
+    // Unix time of the verdict
+    pub timestamp: u64,
+    pub protocol: String,
+    // Hex SHA-256 digest of the statement that was proven
+    pub statement: String,
+    pub accept: bool,
+    // Hex SHA-256 digest of the proof, or of the messages of an interactive proof
+    pub transcript: String,
+    // Hash of the previous entry
+    pub previous: String,
+    // Hash of every other field of this entry
+    pub hash: String,
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl AuditEntry {
+    pub fn compute_hash(&self) -> String {
+        let mut transcript = ChallengeTranscript::new(b"zklib audit entry");
+        transcript.append_message(b"sequence", &self.sequence.to_be_bytes());
+        transcript.append_message(b"timestamp", &self.timestamp.to_be_bytes());
+        transcript.append_message(b"protocol", self.protocol.as_bytes());
+        transcript.append_message(b"statement", self.statement.as_bytes());
+        transcript.append_message(b"accept", &[self.accept as u8]);
+        transcript.append_message(b"transcript", self.transcript.as_bytes());
+        transcript.append_message(b"previous", self.previous.as_bytes());
+        to_hex(&transcript.digest())
+    }
+}
+
+// Number of entries in a verified log, how many were accepts, and the hash of the last entry
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditSummary {
+    pub entries: u64,
+    pub accepted: u64,
+    pub head: String,
+}
+
+// Checks that every entry's hash matches its contents and links to the entry before it, describing the
+// first entry that doesn't
+pub fn verify_entries<R: BufRead>(reader: R) -> Result<AuditSummary, String> {
+    let mut summary = AuditSummary { entries: 0, accepted: 0, head: GENESIS_HASH.to_string() };
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        let entry: AuditEntry = serde_json::from_str(&line).map_err(|e| format!("Line {}: malformed entry: {}", i + 1, e))?;
+        if entry.sequence != summary.entries {
+            return Err(format!("Line {}: entry has sequence number {}, expected {}", i + 1, entry.sequence, summary.entries));
+        }
+        if entry.previous != summary.head {
+            return Err(format!("Line {}: entry doesn't link to the entry before it", i + 1));
+        }
+        if entry.hash != entry.compute_hash() {
+            return Err(format!("Line {}: entry doesn't match its hash", i + 1));
+        }
+        summary.entries += 1;
+        summary.accepted += entry.accept as u64;
+        summary.head = entry.hash;
+    }
+    Ok(summary)
+}
+
+pub fn verify_log(path: &Path) -> Result<AuditSummary, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    verify_entries(BufReader::new(file))
+}
+
+// Appends entries to a log file, extending the chain of the entries already in it
+pub struct AuditLog {
+    file: File,
+    pub head: String,
+    pub entries: u64,
+}
+
+impl AuditLog {
+    // Opens the log for appending, creating it if needed. An existing log is verified first, so new
+    // entries never extend a broken chain.
+    pub fn open(path: &Path) -> Result<AuditLog, String> {
+        let summary = if path.exists() {
+            verify_log(path)?
+        } else {
+            AuditSummary { entries: 0, accepted: 0, head: GENESIS_HASH.to_string() }
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        Ok(AuditLog { file, head: summary.head, entries: summary.entries })
+    }
+
+    // Writes the verdict to disk before returning, so that no verdict is reported without being logged
+    pub fn append(&mut self, protocol: &str, statement: &[u8; 32], accept: bool, transcript: &[u8; 32]) -> Result<AuditEntry, String> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        let mut entry = AuditEntry {
+            sequence: self.entries,
+            timestamp,
+            protocol: protocol.to_string(),
+            statement: to_hex(statement),
+            accept,
+            transcript: to_hex(transcript),
+            previous: self.head.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        writeln!(self.file, "{}", serde_json::to_string(&entry).unwrap())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("failed to write audit log: {}", e))?;
+        self.head = entry.hash.clone();
+        self.entries += 1;
+        Ok(entry)
+    }
+}
+
+#[test]
+fn test_audit_log() {
+    let path = std::env::temp_dir().join(format!("zklib-audit-{}.jsonl", thread_rng().gen::<u64>()));
+    let mut log = AuditLog::open(&path).unwrap();
+    log.append("gi", &[1u8; 32], true, &[2u8; 32]).unwrap();
+    log.append("gi", &[1u8; 32], false, &[3u8; 32]).unwrap();
+    drop(log);
+    // Reopening the log continues its chain
+    let mut log = AuditLog::open(&path).unwrap();
+    let last = log.append("schnorr-signature", &[4u8; 32], true, &[5u8; 32]).unwrap();
+    let summary = verify_log(&path).unwrap();
+    assert_eq!(summary, AuditSummary { entries: 3, accepted: 2, head: last.hash });
+
+    // Flipping a verdict, dropping an entry, or swapping two entries is detected
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    let flipped = contents.replacen("\"accept\":false", "\"accept\":true", 1);
+    assert!(verify_entries(flipped.as_bytes()).unwrap_err().starts_with("Line 2"));
+    let dropped = [lines[0], lines[2]].join("\n");
+    assert!(verify_entries(dropped.as_bytes()).is_err());
+    let swapped = [lines[1], lines[0], lines[2]].join("\n");
+    assert!(verify_entries(swapped.as_bytes()).is_err());
+    // A log with a broken chain can't be extended
+    std::fs::write(&path, flipped).unwrap();
+    assert!(AuditLog::open(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,308 @@
+use serde::{Serialize, Deserialize};
+use crate::{Diagnosis, Prover, Verifier};
+use crate::beacon::PublicCoinVerifier;
+use crate::challenge::Challenge;
+use crate::graph::{check_count, Graph, GraphPair};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::statement::{Statement, Transparent, Witness};
+use crate::transcript::{ChallengeTranscript, TranscriptMessage};
+#[cfg(test)]
+use crate::{diagnose_interactive_proof, run_interactive_proof, ProofFailure};
+#[cfg(test)]
+use crate::repetition::{run_repeated, RepetitionPolicy};
+#[cfg(test)]
+use crate::statement::prove;
+#[cfg(test)]
+use crate::transport::transport_test_instance;
+
+// ************ Batched graph isomorphism implementation ************
+
+// Proving many isomorphisms at once, e.g. that every pair of records flagged as duplicates in a dataset
+// is the same graph up to relabeling, would take a separate GI interaction and its round trips per pair.
+// The batched protocol proves the whole batch in one: for each of k repetitions, the prover sends a random
+// relabeling of every pair's g0, the verifier sends one bit per repetition, and the prover maps each
+// graph of repetition i onto g(b_i) of its pair. The prover knows an isomorphism sigma from g0 to g1 for
+// every pair, so it answers 0 with its relabeling rho^-1, and 1 with rho^-1 followed by sigma.
+//
+// Every pair shares the challenge bits, so the batch costs the round trips of a single pair. If any pair
+// isn't isomorphic, each graph sent for it is a relabeling of at most one of its graphs, and the prover
+// survives repetition i only if it guessed b_i, so the soundness error is 2^-k however many pairs there
+// are. With k = 1 a simulator guesses a single bit for the whole batch, so the batch is zero knowledge
+// like one GI repetition, while k > 1 runs repetitions in parallel, which isn't known to be.
+
+// Pairs that are each claimed to be isomorphic
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphPairBatch {
+    pub pairs: Vec<GraphPair>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BatchGIProverMessage {
+    // For each repetition, a random relabeling of every pair's g0
+    Graphs(Vec<Vec<Graph>>),
+    // For each repetition, an isomorphism from every graph to the challenged graph of its pair
+    Isomorphisms(Vec<Vec<Permutation>>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchGIVerifierMessage {
+    // Graph of every pair to map onto, for each repetition
+    pub challenge: Vec<bool>,
+}
+
+pub struct BatchGIProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of repetitions
+    pub k: usize,
+    // Permutations taking every pair's g0 to the graph sent for it, for each repetition
+    pub relabelings: Vec<Vec<Permutation>>,
+    pub instance: &'a GraphPairBatch,
+    // Isomorphism taking every pair's g0 to its g1
+    pub witness: &'a [Permutation],
+}
+
+impl Prover for BatchGIProver<'_> {
+    type ProverMessage = BatchGIProverMessage;
+    type VerifierMessage = BatchGIVerifierMessage;
+
+    fn handle(&mut self, msg: &BatchGIVerifierMessage) -> (BatchGIProverMessage, bool) {
+        self.r += 1;
+        let pairs = &self.instance.pairs;
+        match self.r {
+            // During the first round, the prover relabels every g0 once per repetition
+            1 => {
+                self.relabelings = (0..self.k).map(|_| pairs.iter().map(|pair| Permutation::random(pair.g0.n, &mut checked_thread_rng())).collect()).collect();
+                let graphs = self.relabelings.iter().map(|row| pairs.iter().zip(row).map(|(pair, rho)| pair.g0.permute_secret(rho)).collect()).collect();
+                (BatchGIProverMessage::Graphs(graphs), false)
+            },
+            // During the second round, the prover undoes each relabeling, going on to g1 where challenged
+            2 => {
+                let isomorphisms = self.relabelings.iter().zip(&msg.challenge).map(|(row, &b)| {
+                    row.iter().zip(self.witness).map(|(rho, sigma)| if b { rho.invert().compose(sigma) } else { rho.invert() }).collect()
+                }).collect();
+                (BatchGIProverMessage::Isomorphisms(isomorphisms), false)
+            },
+            // After sending the isomorphisms, the prover sends a message to terminate the interaction
+            _ => (BatchGIProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &BatchGIVerifierMessage) -> Result<(), String> {
+        if self.r + 1 == 2 { msg.challenge.check(&self.k) } else { Ok(()) }
+    }
+}
+
+pub struct BatchGIVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of repetitions
+    pub k: usize,
+    // Randomly chosen bits
+    pub challenge: Vec<bool>,
+    // Graphs received from the prover
+    pub graphs: Vec<Vec<Graph>>,
+    pub instance: &'a GraphPairBatch,
+}
+
+impl BatchGIVerifier<'_> {
+    // Checks that there is one row per repetition, with one item per pair acting on that pair's vertices
+    fn check_rows<T>(&self, rows: &[Vec<T>], n: impl Fn(&T) -> u32, name: &str) -> Result<(), String> {
+        check_count(rows.len(), self.k, "repetitions")?;
+        rows.iter().try_for_each(|row| {
+            check_count(row.len(), self.instance.pairs.len(), name)?;
+            row.iter().zip(&self.instance.pairs).try_for_each(|(x, pair)| check_count(n(x) as usize, pair.g0.n as usize, &format!("vertices in {}", name)))
+        })
+    }
+
+    // Graph of the pair challenged in the repetition
+    fn target(&self, i: usize, j: usize) -> &Graph {
+        let pair = &self.instance.pairs[j];
+        if self.challenge[i] { &pair.g1 } else { &pair.g0 }
+    }
+}
+
+impl Verifier for BatchGIVerifier<'_> {
+    type ProverMessage = BatchGIProverMessage;
+    type VerifierMessage = BatchGIVerifierMessage;
+
+    fn init(&mut self) -> BatchGIVerifierMessage {
+        narrate!("Initializing batched GI instance with {} pairs and {} repetitions.", self.instance.pairs.len(), self.k);
+        BatchGIVerifierMessage { challenge: Vec::new() }
+    }
+
+    fn validate(&self, msg: &BatchGIProverMessage) -> Result<(), String> {
+        // An empty batch would be accepted without anything being proven
+        if self.instance.pairs.is_empty() {
+            return Err("batch has no pairs".to_string());
+        }
+        if let Some(j) = self.instance.pairs.iter().position(|pair| pair.g0.n != pair.g1.n) {
+            return Err(format!("graphs of pair {} differ in size", j));
+        }
+        match (self.r + 1, msg) {
+            (1, BatchGIProverMessage::Graphs(graphs)) => self.check_rows(graphs, |graph: &Graph| graph.n, "graphs"),
+            (1, _) => Err("expected graphs".to_string()),
+            (_, BatchGIProverMessage::Isomorphisms(isomorphisms)) => self.check_rows(isomorphisms, Permutation::n, "isomorphisms"),
+            _ => Err("expected isomorphisms".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &BatchGIProverMessage) -> Option<Diagnosis> {
+        let isomorphisms = match msg {
+            BatchGIProverMessage::Isomorphisms(isomorphisms) => isomorphisms,
+            _ => return None,
+        };
+        // Reports the first pair that failed, in the first repetition it failed
+        (0..self.k).flat_map(|i| (0..self.instance.pairs.len()).map(move |j| (i, j))).find_map(|(i, j)| {
+            let permuted = self.graphs[i][j].permute(&isomorphisms[i][j]);
+            let target = self.target(i, j);
+            if &permuted == target {
+                return None;
+            }
+            let check = format!("permuted graph of pair {} != g{} in repetition {}", j, self.challenge[i] as u8, i);
+            Some(Diagnosis::new(&check, Some(permuted.diff(target).to_string())))
+        })
+    }
+
+    fn handle(&mut self, msg: &BatchGIProverMessage) -> (BatchGIVerifierMessage, bool) {
+        self.handle_with(msg, None)
+    }
+}
+
+impl PublicCoinVerifier for BatchGIVerifier<'_> {
+    type Challenge = Vec<bool>;
+
+    fn challenge_space(&self) -> Option<usize> {
+        if self.r == 0 { Some(self.k) } else { None }
+    }
+
+    fn handle_with(&mut self, msg: &BatchGIProverMessage, challenge: Option<Vec<bool>>) -> (BatchGIVerifierMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            (1, BatchGIProverMessage::Graphs(graphs)) => {
+                self.graphs = graphs.clone();
+                self.challenge = challenge.unwrap_or_else(|| Vec::<bool>::sample(&self.k, &mut checked_thread_rng()));
+                narrate!("Verifier challenged every pair with {:?}.", self.challenge);
+                (BatchGIVerifierMessage { challenge: self.challenge.clone() }, false)
+            },
+            // Every graph must map onto the challenged graph of its pair
+            (_, BatchGIProverMessage::Isomorphisms(isomorphisms)) => {
+                let accept = (0..self.k).all(|i| (0..self.instance.pairs.len()).all(|j| &self.graphs[i][j].permute(&isomorphisms[i][j]) == self.target(i, j)));
+                (BatchGIVerifierMessage { challenge: self.challenge.clone() }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+impl TranscriptMessage for BatchGIProverMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        match self {
+            BatchGIProverMessage::Graphs(graphs) => graphs.iter().flatten().for_each(|x| transcript.append_message(b"graph", &x.encode())),
+            BatchGIProverMessage::Isomorphisms(isomorphisms) => isomorphisms.iter().flatten().for_each(|x| transcript.append_message(b"isomorphism", &x.encode())),
+            BatchGIProverMessage::Done => transcript.append_message(b"done", &[]),
+        }
+    }
+}
+
+impl TranscriptMessage for BatchGIVerifierMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_challenge(b"challenge", &self.challenge);
+    }
+}
+
+// Isomorphism taking every pair's g0 to its g1
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphPairBatchWitness(pub Vec<Permutation>);
+
+impl Witness for GraphPairBatchWitness {}
+
+// The statement that every pair is isomorphic, with an isomorphism from g0 to g1 for each pair as the witness
+impl Statement for GraphPairBatch {
+    type Witness = GraphPairBatchWitness;
+
+    fn protocol(&self) -> &'static str {
+        "batched graph isomorphism"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = (self.pairs.len() as u64).to_be_bytes().to_vec();
+        for pair in &self.pairs {
+            let encoded = pair.encode();
+            bytes.extend((encoded.len() as u64).to_be_bytes());
+            bytes.extend(encoded);
+        }
+        bytes
+    }
+
+    fn memory_footprint(&self) -> usize {
+        self.pairs.iter().map(GraphPair::memory_footprint).sum()
+    }
+
+    fn validate(&self, witness: &GraphPairBatchWitness) -> Result<(), String> {
+        if self.pairs.is_empty() {
+            return Err("Batch has no pairs".to_string());
+        }
+        check_count(witness.0.len(), self.pairs.len(), "isomorphisms")?;
+        self.pairs.iter().zip(&witness.0).enumerate().try_for_each(|(j, (pair, sigma))| pair.validate(sigma).map_err(|e| format!("Pair {}: {}", j, e)))
+    }
+
+    fn run_proof(&self, witness: &GraphPairBatchWitness) -> bool {
+        let mut prover = BatchGIProver { r: 0, k: 1, relabelings: Vec::new(), instance: self, witness: &witness.0 };
+        let mut verifier = BatchGIVerifier { r: 0, k: 1, challenge: Vec::new(), graphs: Vec::new(), instance: self };
+        crate::run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+impl Transparent for GraphPairBatch {}
+
+#[test]
+fn test_batch_gi_proof() {
+    // Copies of the transport fixture, whose isomorphism swaps vertices 0 and 2
+    let batch = GraphPairBatch { pairs: vec![transport_test_instance(); 3] };
+    let witness = GraphPairBatchWitness(vec![Permutation::new(vec![2, 1, 0, 3]).unwrap(); 3]);
+    assert_eq!(prove(&batch, &witness), Ok(true));
+    let play = |batch: &GraphPairBatch, k: usize| {
+        let mut prover = BatchGIProver { r: 0, k, relabelings: Vec::new(), instance: batch, witness: &witness.0 };
+        let mut verifier = BatchGIVerifier { r: 0, k, challenge: Vec::new(), graphs: Vec::new(), instance: batch };
+        run_interactive_proof(&mut prover, &mut verifier)
+    };
+    assert!((0..10).all(|_| play(&batch, 8)));
+
+    // One pair that isn't isomorphic is caught whenever its bit is 1, whichever pair it is
+    let mut bad = batch.clone();
+    bad.pairs[1].g1 = Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]);
+    assert!(bad.validate(&witness).unwrap_err().starts_with("Pair 1"));
+    let accepted = (0..200).filter(|_| play(&bad, 1)).count();
+    assert!(accepted > 60 && accepted < 140);
+    // With 8 shared bits the batch is only accepted when all of them are 0
+    assert!((0..100).filter(|_| play(&bad, 8)).count() < 5);
+    let mut prover = BatchGIProver { r: 0, k: 8, relabelings: Vec::new(), instance: &bad, witness: &witness.0 };
+    let mut verifier = BatchGIVerifier { r: 0, k: 8, challenge: Vec::new(), graphs: Vec::new(), instance: &bad };
+    match diagnose_interactive_proof(&mut prover, &mut verifier) {
+        Err(ProofFailure::Rejected(rejection)) => assert!(rejection.diagnosis.unwrap().check.starts_with("permuted graph of pair 1 != g1")),
+        result => assert!(result.is_ok() && verifier.challenge == [false; 8]),
+    }
+
+    // An empty batch is refused rather than accepted without proving anything
+    let empty = GraphPairBatch { pairs: Vec::new() };
+    let mut prover = BatchGIProver { r: 0, k: 1, relabelings: Vec::new(), instance: &empty, witness: &[] };
+    let mut verifier = BatchGIVerifier { r: 0, k: 1, challenge: Vec::new(), graphs: Vec::new(), instance: &empty };
+    assert!(matches!(diagnose_interactive_proof(&mut prover, &mut verifier), Err(ProofFailure::Invalid(_))));
+}
+
+#[test]
+fn test_batch_gi_repetition() {
+    // Four shared bits per interaction reach a soundness error of 2^-20 for the whole batch in 5 interactions
+    let batch = GraphPairBatch { pairs: vec![transport_test_instance(); 3] };
+    let witness = vec![Permutation::new(vec![2, 1, 0, 3]).unwrap(); 3];
+    let run = run_repeated(&RepetitionPolicy::bits(20, 100), || {
+        let prover = BatchGIProver { r: 0, k: 4, relabelings: Vec::new(), instance: &batch, witness: &witness };
+        (prover, BatchGIVerifier { r: 0, k: 4, challenge: Vec::new(), graphs: Vec::new(), instance: &batch })
+    }).unwrap();
+    assert!(run.accept);
+    assert_eq!(run.repetitions, 5);
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+use rand::{Rng, RngCore};
+use serde::{Serialize, Deserialize};
+use crate::{Diagnosis, Prover, ValidationError, Verifier};
+use crate::challenge::Challenge;
+use crate::randomness::checked_thread_rng;
+use crate::transcript::{ChallengeTranscript, TranscriptMessage};
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMalicious, GIVerifier, Graph, GraphPair, GuessStrategy, ParallelGIProver, ParallelGIVerifier};
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Randomness beacon implementation ************
+
+// A public-coin verifier's challenges are just random values it sends in the clear, so they can come from
+// anywhere both parties trust to be unpredictable. A randomness beacon publishes such values in numbered
+// rounds. Drawing every challenge from a beacon makes the verifier's randomness auditable: anyone who
+// sees the beacon values can recompute the challenges and check that the verifier didn't choose them.
+
+// A value published by a beacon, along with the round it was published in
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BeaconValue {
+    pub round: u64,
+    pub randomness: Vec<u8>,
+}
+
+pub trait RandomnessBeacon {
+    // Returns a value the prover could not have predicted before it sent its last message
+    fn next_value(&mut self) -> Result<BeaconValue, String>;
+}
+
+// Fresh local randomness, for experiments that don't need an external beacon
+#[derive(Clone, Debug, Default)]
+pub struct LocalBeacon {
+    pub round: u64,
+}
+
+impl RandomnessBeacon for LocalBeacon {
+    fn next_value(&mut self) -> Result<BeaconValue, String> {
+        self.round += 1;
+        Ok(BeaconValue { round: self.round, randomness: checked_thread_rng().gen::<[u8; 32]>().to_vec() })
+    }
+}
+
+// Replays a fixed list of values, e.g. test vectors or values recorded from another beacon
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedBeacon {
+    pub values: Vec<BeaconValue>,
+    pub next: usize,
+}
+
+impl FixedBeacon {
+    pub fn new(values: Vec<BeaconValue>) -> FixedBeacon {
+        FixedBeacon { values, next: 0 }
+    }
+}
+
+impl RandomnessBeacon for FixedBeacon {
+    fn next_value(&mut self) -> Result<BeaconValue, String> {
+        let value = self.values.get(self.next).cloned().ok_or_else(|| format!("Fixed beacon ran out after {} values", self.values.len()))?;
+        self.next += 1;
+        Ok(value)
+    }
+}
+
+// Absorbing a beacon value into a Fiat-Shamir transcript makes every later challenge depend on it, so a
+// prover can't have computed the proof before the value was published
+impl TranscriptMessage for BeaconValue {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_message(b"beacon round", &self.round.to_be_bytes());
+        transcript.append_message(b"beacon randomness", &self.randomness);
+    }
+}
+
+// Expands a beacon value into a challenge, deterministically so that anyone can recompute it
+pub fn challenge_from_beacon<C: Challenge>(value: &BeaconValue, space: &C::Space) -> C {
+    let mut transcript = ChallengeTranscript::new(b"beacon challenge");
+    value.append_to(&mut transcript);
+    transcript.challenge(b"challenge", space)
+}
+
+// ************ Public-coin runner implementation ************
+
+// A verifier whose only randomness is the challenges it sends, which can take its challenges from
+// outside instead of sampling them
+pub trait PublicCoinVerifier: Verifier {
+    type Challenge: Challenge;
+
+    // Space of the challenge the verifier sends in reply to the prover's next message, or None if its
+    // reply carries no challenge
+    fn challenge_space(&self) -> Option<<Self::Challenge as Challenge>::Space>;
+
+    // Handles the prover's message like handle, but replying with the given challenge rather than
+    // sampling one if it is set
+    fn handle_with(&mut self, msg: &Self::ProverMessage, challenge: Option<Self::Challenge>) -> (Self::VerifierMessage, bool);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PublicCoinError {
+    Invalid(ValidationError),
+    Beacon(String),
+}
+
+impl fmt::Display for PublicCoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicCoinError::Invalid(e) => write!(f, "{}", e),
+            PublicCoinError::Beacon(reason) => write!(f, "Randomness beacon failed: {}", reason),
+        }
+    }
+}
+
+// Whether the verifier accepted, along with the beacon values its challenges were derived from, in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicCoinRun {
+    pub accept: bool,
+    pub beacon_values: Vec<BeaconValue>,
+}
+
+// Runs the interaction like try_run_interactive_proof, but draws every challenge the verifier sends from
+// the beacon. Each value is requested only after the prover's message it answers, so the prover can't
+// predict its challenges as long as the beacon can't be predicted.
+pub fn run_public_coin<P, V>(prover: &mut P, verifier: &mut V, beacon: &mut dyn RandomnessBeacon) -> Result<PublicCoinRun, PublicCoinError>
+where P: Prover, V: PublicCoinVerifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage> {
+    let mut verifier_msg = verifier.init();
+    let mut accept = false;
+    let mut round = 1;
+    let mut beacon_values = Vec::new();
+
+    loop {
+        prover.validate(&verifier_msg).map_err(|reason| PublicCoinError::Invalid(ValidationError::VerifierMessage(round, reason)))?;
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        if done {
+            break;
+        }
+        verifier.validate(&prover_msg).map_err(|reason| PublicCoinError::Invalid(ValidationError::ProverMessage(round, reason)))?;
+        let challenge = match verifier.challenge_space() {
+            Some(space) => {
+                let value = beacon.next_value().map_err(PublicCoinError::Beacon)?;
+                let challenge = challenge_from_beacon(&value, &space);
+                beacon_values.push(value);
+                Some(challenge)
+            },
+            None => None,
+        };
+        let x = verifier.handle_with(&prover_msg, challenge);
+        verifier_msg = x.0;
+        accept = x.1;
+        round += 1;
+    }
+
+    Ok(PublicCoinRun { accept, beacon_values })
+}
+
+// ************ Biased verifier implementation ************
+
+// Soundness arguments assume the verifier's challenges are uniform, and a verifier with a bad source of
+// randomness, e.g. a broken RNG that favours 1 bits, gives a prover that knows the bias better odds than
+// guessing. A BiasedVerifier wraps a public-coin verifier and samples each of its challenges from a
+// BiasedRng, whose bits are independently 1 with a configurable probability. Every challenge type is built
+// from random bytes, so the same wrapper biases a GI verifier's bit, each bit of a parallel challenge, or
+// a Schnorr verifier's scalar.
+
+// Random bits that are each 1 with probability q, which is checked to be a probability when the RNG is
+// built, so sampling never panics on a q outside [0, 1] or NaN
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiasedRng {
+    q: f64,
+}
+
+impl BiasedRng {
+    pub fn new(q: f64) -> Result<BiasedRng, String> {
+        match (0.0..=1.0).contains(&q) {
+            true => Ok(BiasedRng { q }),
+            false => Err(format!("Bias {} is not a probability", q)),
+        }
+    }
+
+    pub fn q(&self) -> f64 {
+        self.q
+    }
+}
+
+impl RngCore for BiasedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut rng = checked_thread_rng();
+        dest.iter_mut().for_each(|byte| *byte = (0..8).fold(0, |x, i| x | ((rng.gen_bool(self.q) as u8) << i)));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// A public-coin verifier whose challenges come from a BiasedRng
+#[derive(Clone)]
+pub struct BiasedVerifier<V> {
+    pub inner: V,
+    pub rng: BiasedRng,
+}
+
+impl<V> BiasedVerifier<V> {
+    // Wraps the verifier so that each bit of its challenges is 1 with probability q, failing if q isn't a probability
+    pub fn new(inner: V, q: f64) -> Result<BiasedVerifier<V>, String> {
+        Ok(BiasedVerifier { inner, rng: BiasedRng::new(q)? })
+    }
+}
+
+impl<V: PublicCoinVerifier> Verifier for BiasedVerifier<V> {
+    type ProverMessage = V::ProverMessage;
+    type VerifierMessage = V::VerifierMessage;
+
+    fn init(&mut self) -> V::VerifierMessage {
+        self.inner.init()
+    }
+
+    fn validate(&self, msg: &V::ProverMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+
+    fn diagnose(&self, msg: &V::ProverMessage) -> Option<Diagnosis> {
+        self.inner.diagnose(msg)
+    }
+
+    fn handle(&mut self, msg: &V::ProverMessage) -> (V::VerifierMessage, bool) {
+        let challenge = self.inner.challenge_space().map(|space| V::Challenge::sample(&space, &mut self.rng));
+        self.inner.handle_with(msg, challenge)
+    }
+}
+
+#[cfg(test)]
+fn test_instance(isomorphic: bool) -> GraphPair {
+    let g1 = if isomorphic {
+        Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)])
+    } else {
+        Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)])
+    };
+    GraphPair { g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]), g1 }
+}
+
+#[test]
+fn test_public_coin_gi() {
+    let instance = test_instance(true);
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let run = run_public_coin(&mut prover, &mut verifier, &mut LocalBeacon::default()).unwrap();
+    assert!(run.accept);
+    // The verifier's challenge can be recomputed from the beacon value
+    assert_eq!(run.beacon_values.len(), 1);
+    assert_eq!(verifier.b, challenge_from_beacon::<bool>(&run.beacon_values[0], &()));
+}
+
+#[test]
+fn test_fixed_beacon() {
+    // Test vectors fix the challenges, so a cheating prover's fate depends only on its own guess
+    let values: Vec<BeaconValue> = (0..2).map(|round| BeaconValue { round, randomness: vec![round as u8; 32] }).collect();
+    let bits: Vec<Vec<bool>> = values.iter().map(|value| challenge_from_beacon(value, &8)).collect();
+    assert_ne!(bits[0], bits[1]);
+
+    let instance = test_instance(true);
+    let mut beacon = FixedBeacon::new(values);
+    let mut prover = ParallelGIProver { r: 0, k: 8, random_perms: Vec::new(), openings: Vec::new(), instance: &instance };
+    let mut verifier = ParallelGIVerifier { r: 0, k: 8, challenge: Vec::new(), commitments: Vec::new(), instance: &instance };
+    assert!(run_public_coin(&mut prover, &mut verifier, &mut beacon).unwrap().accept);
+    assert_eq!(verifier.challenge, bits[0]);
+
+    // Running out of values is an error rather than a rejection
+    let mut beacon = FixedBeacon::new(Vec::new());
+    let mut prover = GIProverMalicious::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert!(matches!(run_public_coin(&mut prover, &mut verifier, &mut beacon), Err(PublicCoinError::Beacon(_))));
+}
+
+#[test]
+fn test_biased_verifier() {
+    assert_eq!(BiasedRng::new(0.0).unwrap().next_u64(), 0);
+    assert_eq!(BiasedRng::new(1.0).unwrap().next_u32(), u32::MAX);
+    // Anything but a probability is refused up front, rather than panicking on the first challenge
+    for q in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+        assert_eq!(BiasedRng::new(q), Err(format!("Bias {} is not a probability", q)));
+    }
+    assert!(BiasedVerifier::new(GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &test_instance(true) }, 2.0).is_err());
+
+    // A verifier that always challenges with 1 is fooled by a prover that always guesses 1
+    let instance = test_instance(false);
+    let mut prover = GIProverMalicious::with_strategy(&instance, 1.0, GuessStrategy::Random).unwrap();
+    let mut verifier = BiasedVerifier::new(GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }, 1.0).unwrap();
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+    assert!(verifier.inner.b);
+    let instance = test_instance(true);
+    let mut prover = ParallelGIProver { r: 0, k: 8, random_perms: Vec::new(), openings: Vec::new(), instance: &instance };
+    let mut verifier = BiasedVerifier::new(ParallelGIVerifier { r: 0, k: 8, challenge: Vec::new(), commitments: Vec::new(), instance: &instance }, 0.0).unwrap();
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+    assert_eq!(verifier.inner.challenge, vec![false; 8]);
+}
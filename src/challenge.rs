@@ -0,0 +1,136 @@
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use rand::RngCore;
+
+// ************ Challenge implementation ************
+
+// Every challenge a verifier sends is drawn uniformly from some challenge space: a single bit for GI, k
+// bits for k parallel repetitions, or an element of Z_q for sigma protocols like Schnorr. A challenge type
+// maps uniformly random bytes onto its space, so the same type can be sampled by an interactive verifier,
+// derived from a transcript by a Fiat-Shamir prover, or read from any other source of random bytes. Its
+// encoding is what both parties absorb into their transcripts.
+pub trait Challenge: Clone + Sized {
+    // Describes the challenge space, e.g. the number of bits or the modulus
+    type Space;
+
+    // Number of uniformly random bytes from_bytes needs
+    fn byte_len(space: &Self::Space) -> usize;
+
+    // Maps byte_len uniformly random bytes onto a challenge that is uniform, or negligibly far from it
+    fn from_bytes(space: &Self::Space, bytes: &[u8]) -> Self;
+
+    fn encode(&self) -> Vec<u8>;
+
+    // Checks that a received challenge lies in the space
+    fn check(&self, space: &Self::Space) -> Result<(), String>;
+
+    // Chance of guessing a uniform challenge in advance, which is the soundness error of one run of a
+    // protocol in which a prover that can answer two different challenges must know a witness
+    fn guessing_probability(space: &Self::Space) -> f64;
+
+    fn sample<R: RngCore + ?Sized>(space: &Self::Space, rng: &mut R) -> Self {
+        let mut bytes = vec![0u8; Self::byte_len(space)];
+        rng.fill_bytes(&mut bytes);
+        Self::from_bytes(space, &bytes)
+    }
+}
+
+// A single bit, e.g. which graph the GI prover must map its permuted graph onto
+impl Challenge for bool {
+    type Space = ();
+
+    fn byte_len(_space: &()) -> usize {
+        1
+    }
+
+    fn from_bytes(_space: &(), bytes: &[u8]) -> bool {
+        bytes[0] & 1 == 1
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn check(&self, _space: &()) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn guessing_probability(_space: &()) -> f64 {
+        0.5
+    }
+}
+
+// One bit per parallel repetition, with the number of repetitions as the space
+impl Challenge for Vec<bool> {
+    type Space = usize;
+
+    fn byte_len(k: &usize) -> usize {
+        k.div_ceil(8)
+    }
+
+    fn from_bytes(k: &usize, bytes: &[u8]) -> Vec<bool> {
+        (0..*k).map(|i| (bytes[i / 8] >> (i % 8)) & 1 == 1).collect()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.iter().map(|&b| b as u8).collect()
+    }
+
+    fn check(&self, k: &usize) -> Result<(), String> {
+        if self.len() != *k {
+            return Err(format!("Challenge has {} bits, expected {}", self.len(), k));
+        }
+        Ok(())
+    }
+
+    fn guessing_probability(k: &usize) -> f64 {
+        0.5f64.powi(*k as i32)
+    }
+}
+
+// An element of Z_q, with the modulus q as the space
+impl Challenge for BigUint {
+    type Space = BigUint;
+
+    // Reducing 128 bits more than the size of q makes the bias from reducing mod q negligible
+    fn byte_len(q: &BigUint) -> usize {
+        (q.bits() as usize + 128).div_ceil(8)
+    }
+
+    fn from_bytes(q: &BigUint, bytes: &[u8]) -> BigUint {
+        BigUint::from_bytes_be(bytes) % q
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_bytes_be()
+    }
+
+    fn check(&self, q: &BigUint) -> Result<(), String> {
+        if self >= q {
+            return Err("Challenge is not reduced mod q".to_string());
+        }
+        Ok(())
+    }
+
+    fn guessing_probability(q: &BigUint) -> f64 {
+        q.to_f64().map_or(0.0, |q| 1.0 / q)
+    }
+}
+
+#[test]
+fn test_bit_vector_challenge() {
+    let challenge = Vec::<bool>::from_bytes(&10, &[0b0000_0101, 0b0000_0010]);
+    assert_eq!(challenge, vec![true, false, true, false, false, false, false, false, false, true]);
+    assert!(challenge.check(&10).is_ok());
+    assert!(challenge.check(&9).is_err());
+    assert_eq!(Vec::<bool>::guessing_probability(&10), 1.0 / 1024.0);
+}
+
+#[test]
+fn test_scalar_challenge() {
+    let q = BigUint::from(101u32);
+    let challenges: Vec<BigUint> = (0..100).map(|_| BigUint::sample(&q, &mut rand::thread_rng())).collect();
+    assert!(challenges.iter().all(|c| c.check(&q).is_ok()));
+    assert!(challenges.iter().any(|c| c != &challenges[0]));
+    assert!(q.check(&q).is_err());
+}
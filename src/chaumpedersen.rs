@@ -0,0 +1,165 @@
+use num_bigint::BigUint;
+use serde::{Serialize, Deserialize};
+use crate::modarith::mod_exp;
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::transcript::ChallengeTranscript;
+#[cfg(test)]
+use crate::params::Parameters;
+
+// ************ Chaum-Pedersen proof implementation ************
+
+// A Chaum-Pedersen proof shows that two elements h1 = g1^x and h2 = g2^x of the subgroup of order q share
+// their discrete log x, without revealing it. The prover announces (g1^k, g2^k) for a random k, and
+// answers the challenge c with s = k + c x, which the verifier checks as g1^s = a1 h1^c and g2^s = a2 h2^c.
+// Proofs here are non-interactive: the challenge is derived from a ChallengeTranscript under a label
+// naming the use, which absorbs the group, the statement, the announcements and a message the proof is
+// bound to. Only (c, s) is sent, and the verifier recovers the announcements as g1^s h1^-c and g2^s h2^-c.
+// Nullifiers prove that a tag matches a public key this way, and decryptions of ElGamal ciphertexts that
+// they used the decryption key.
+//
+// An OrProof shows that one of two statements holds without revealing which. The prover simulates a proof
+// of the statement it can't prove, picking its challenge and response first and solving for the
+// announcements, proves the other for real, and splits the transcript's challenge c between the two so
+// that c0 + c1 = c. The verifier only checks that the challenges add up, so the prover can choose one of
+// them but not both, and must know the discrete log of one of the statements.
+
+// Statement log_g1 h1 = log_g2 h2, as the elements (g1, h1, g2, h2)
+pub type EqualityStatement = [BigUint; 4];
+
+// Challenge and response (c, s) of a Chaum-Pedersen proof
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EqualityProof {
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub s: BigUint,
+}
+
+// Proofs of two statements, one of them simulated, whose challenges add up to the transcript's
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrProof {
+    pub proofs: [EqualityProof; 2],
+}
+
+// Derives the challenge of the statements from the announcements, binding it to the label and message
+fn challenge(params: &SchnorrParameters, label: &[u8], statements: &[&EqualityStatement], announcements: &[&BigUint], message: &[u8]) -> BigUint {
+    let mut transcript = ChallengeTranscript::new(label);
+    [&params.p, &params.q, &params.g].iter().for_each(|x| transcript.append_biguint(b"group", x));
+    statements.iter().flat_map(|statement| statement.iter()).for_each(|x| transcript.append_biguint(b"statement", x));
+    announcements.iter().for_each(|x| transcript.append_biguint(b"announcement", x));
+    transcript.append_message(b"message", message);
+    transcript.challenge_scalar(b"challenge", &params.q)
+}
+
+// Checks that every element of the statement is in the subgroup, so that a verifier never works with
+// elements of small order
+fn check_statement(params: &SchnorrParameters, statement: &EqualityStatement) -> Result<(), String> {
+    statement.iter().try_for_each(|x| params.check_element(x))
+}
+
+impl EqualityProof {
+    // Proves the statement with its discrete log x, binding the proof to the label and message
+    pub fn prove(params: &SchnorrParameters, statement: &EqualityStatement, x: &BigUint, label: &[u8], message: &[u8]) -> EqualityProof {
+        let k = params.random_exponent();
+        let announcements = [mod_exp(&statement[0], &k, &params.p), mod_exp(&statement[2], &k, &params.p)];
+        let c = challenge(params, label, &[statement], &[&announcements[0], &announcements[1]], message);
+        let s = (k + &c * x) % &params.q;
+        EqualityProof { c, s }
+    }
+
+    // Checks the proof of the statement under the label and message
+    pub fn verify(&self, params: &SchnorrParameters, statement: &EqualityStatement, label: &[u8], message: &[u8]) -> bool {
+        match self.announcements(params, statement) {
+            Ok([a1, a2]) => challenge(params, label, &[statement], &[&a1, &a2], message) == self.c,
+            Err(_) => false,
+        }
+    }
+
+    // Announcements g1^s h1^-c and g2^s h2^-c that the proof must have started from, after checking that
+    // the statement is in the subgroup and the proof is reduced mod q
+    pub fn announcements(&self, params: &SchnorrParameters, statement: &EqualityStatement) -> Result<[BigUint; 2], String> {
+        check_statement(params, statement)?;
+        params.check_exponent(&self.c)?;
+        params.check_exponent(&self.s)?;
+        let [g1, h1, g2, h2] = statement;
+        let p = &params.p;
+        // h^(q - c) is h^-c in the subgroup
+        let minus_c = &params.q - &self.c;
+        Ok([
+            mod_exp(g1, &self.s, p) * mod_exp(h1, &minus_c, p) % p,
+            mod_exp(g2, &self.s, p) * mod_exp(h2, &minus_c, p) % p,
+        ])
+    }
+}
+
+impl OrProof {
+    // Proves that one of the statements holds, knowing the discrete log x of the one at index known
+    pub fn prove(params: &SchnorrParameters, statements: &[EqualityStatement; 2], known: usize, x: &BigUint, label: &[u8], message: &[u8]) -> OrProof {
+        let q = &params.q;
+        let other = 1 - known;
+        let simulated = EqualityProof { c: params.random_exponent(), s: params.random_exponent() };
+        let k = params.random_exponent();
+        let mut announcements: [[BigUint; 2]; 2] = Default::default();
+        announcements[other] = simulated.announcements(params, &statements[other]).expect("Statements must be in the subgroup");
+        announcements[known] = [mod_exp(&statements[known][0], &k, &params.p), mod_exp(&statements[known][2], &k, &params.p)];
+        let c = challenge(params, label, &[&statements[0], &statements[1]], &announcements.iter().flatten().collect::<Vec<_>>(), message);
+        let c_known = (c + q - &simulated.c) % q;
+        let real = EqualityProof { s: (k + &c_known * x) % q, c: c_known };
+        let proofs = if known == 0 { [real, simulated] } else { [simulated, real] };
+        OrProof { proofs }
+    }
+
+    pub fn verify(&self, params: &SchnorrParameters, statements: &[EqualityStatement; 2], label: &[u8], message: &[u8]) -> bool {
+        let announcements = match (self.proofs[0].announcements(params, &statements[0]), self.proofs[1].announcements(params, &statements[1])) {
+            (Ok(a0), Ok(a1)) => [a0, a1],
+            _ => return false,
+        };
+        let c = challenge(params, label, &[&statements[0], &statements[1]], &announcements.iter().flatten().collect::<Vec<_>>(), message);
+        (&self.proofs[0].c + &self.proofs[1].c) % &params.q == c
+    }
+}
+
+#[cfg(test)]
+fn statement_with_log(params: &SchnorrParameters, x: &BigUint) -> EqualityStatement {
+    let g2 = mod_exp(&params.g, &params.random_exponent(), &params.p);
+    [params.g.clone(), mod_exp(&params.g, x, &params.p), g2.clone(), mod_exp(&g2, x, &params.p)]
+}
+
+#[test]
+fn test_equality_proof() {
+    let params = SchnorrParameters::generate(64);
+    let x = params.random_exponent();
+    let statement = statement_with_log(&params, &x);
+    let proof = EqualityProof::prove(&params, &statement, &x, b"test", b"message");
+    assert!(proof.verify(&params, &statement, b"test", b"message"));
+    // The proof is bound to its label, message and statement
+    assert!(!proof.verify(&params, &statement, b"other", b"message"));
+    assert!(!proof.verify(&params, &statement, b"test", b"other message"));
+    let other = statement_with_log(&params, &x);
+    assert!(!proof.verify(&params, &other, b"test", b"message"));
+    // Logs that differ can't be proven equal
+    let mut unequal = statement.clone();
+    unequal[3] = statement_with_log(&params, &params.random_exponent())[1].clone();
+    assert!(!EqualityProof::prove(&params, &unequal, &x, b"test", b"message").verify(&params, &unequal, b"test", b"message"));
+    // Elements outside the subgroup and unreduced responses are refused
+    let mut outside = statement.clone();
+    outside[1] = &params.p - 1u32;
+    assert!(!proof.verify(&params, &outside, b"test", b"message"));
+    assert!(!EqualityProof { c: proof.c.clone(), s: &proof.s + &params.q }.verify(&params, &statement, b"test", b"message"));
+}
+
+#[test]
+fn test_or_proof() {
+    let params = SchnorrParameters::generate(64);
+    let x = params.random_exponent();
+    for known in 0..2 {
+        let mut statements = [statement_with_log(&params, &params.random_exponent()), statement_with_log(&params, &params.random_exponent())];
+        statements[known] = statement_with_log(&params, &x);
+        let proof = OrProof::prove(&params, &statements, known, &x, b"test", b"message");
+        assert!(proof.verify(&params, &statements, b"test", b"message"));
+        assert!(!proof.verify(&params, &statements, b"test", b"other message"));
+        // Without the discrete log of either statement, the challenges don't add up
+        let forged = OrProof::prove(&params, &statements, 1 - known, &x, b"test", b"message");
+        assert!(!forged.verify(&params, &statements, b"test", b"message"));
+    }
+}
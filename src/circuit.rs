@@ -0,0 +1,141 @@
+// ************ Boolean circuit description format ************
+
+// A boolean circuit over wires numbered from 0. The first num_inputs wires carry the inputs, and each
+// gate adds one wire carrying its output, so gate k drives wire num_inputs + k. Gates may only read
+// wires numbered below their own, which keeps the circuit acyclic and lets it be evaluated in order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gate {
+    And(usize, usize),
+    Xor(usize, usize),
+    Not(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Circuit {
+    pub num_inputs: usize,
+    pub gates: Vec<Gate>,
+    // Wire carrying the circuit's single output bit
+    pub output: usize,
+}
+
+impl Circuit {
+    pub fn num_wires(&self) -> usize {
+        self.num_inputs + self.gates.len()
+    }
+
+    // Checks that every gate only reads earlier wires and that the output wire exists
+    pub fn is_valid(&self) -> bool {
+        self.output < self.num_wires() && self.gates.iter().enumerate().all(|(k, gate)| {
+            let wire = self.num_inputs + k;
+            match *gate {
+                Gate::And(a, b) | Gate::Xor(a, b) => a < wire && b < wire,
+                Gate::Not(a) => a < wire,
+            }
+        })
+    }
+
+    // Computes the value of every wire on the given inputs
+    pub fn evaluate_wires(&self, inputs: &[bool]) -> Vec<bool> {
+        if inputs.len() != self.num_inputs {
+            panic!("Circuit expects {} inputs. Found {} inputs.", self.num_inputs, inputs.len());
+        }
+        let mut wires = inputs.to_vec();
+        for gate in self.gates.iter() {
+            let value = match *gate {
+                Gate::And(a, b) => wires[a] && wires[b],
+                Gate::Xor(a, b) => wires[a] ^ wires[b],
+                Gate::Not(a) => !wires[a],
+            };
+            wires.push(value);
+        }
+        wires
+    }
+
+    pub fn evaluate(&self, inputs: &[bool]) -> bool {
+        self.evaluate_wires(inputs)[self.output]
+    }
+
+    // Parses a circuit from its text description, with one directive per line:
+    //
+    //   inputs <n>        declares the number of input wires (must come first)
+    //   and <a> <b>       adds a gate computing wire a AND wire b
+    //   xor <a> <b>       adds a gate computing wire a XOR wire b
+    //   not <a>           adds a gate computing NOT wire a
+    //   output <w>        marks the output wire (must come last)
+    //
+    // Blank lines and lines starting with '#' are ignored.
+    pub fn parse(description: &str) -> Result<Circuit, String> {
+        let mut num_inputs = None;
+        let mut gates = Vec::new();
+        let mut output = None;
+        for (line_number, line) in description.lines().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {}", line_number + 1, message);
+            let args = tokens[1..].iter().map(|x| x.parse::<usize>()).collect::<Result<Vec<usize>, _>>()
+                .map_err(|_| error("expected wire numbers"))?;
+            if output.is_some() {
+                return Err(error("output must be the last directive"));
+            }
+            match (tokens[0], num_inputs, args.as_slice()) {
+                ("inputs", None, &[n]) => num_inputs = Some(n),
+                ("inputs", Some(_), _) => return Err(error("inputs declared twice")),
+                (_, None, _) => return Err(error("inputs must be declared first")),
+                ("and", _, &[a, b]) => gates.push(Gate::And(a, b)),
+                ("xor", _, &[a, b]) => gates.push(Gate::Xor(a, b)),
+                ("not", _, &[a]) => gates.push(Gate::Not(a)),
+                ("output", _, &[w]) => output = Some(w),
+                _ => return Err(error(&format!("invalid directive '{}'", line.trim()))),
+            }
+        }
+        let circuit = Circuit {
+            num_inputs: num_inputs.ok_or("Missing inputs declaration")?,
+            gates,
+            output: output.ok_or("Missing output declaration")?,
+        };
+        if !circuit.is_valid() {
+            return Err("Gates must only read earlier wires and the output wire must exist".to_string());
+        }
+        Ok(circuit)
+    }
+}
+
+#[cfg(test)]
+// Outputs 1 exactly when the three input bits are 1, 0, 1
+pub(crate) const EXAMPLE_CIRCUIT: &str = "
+# x0 AND NOT x1 AND x2
+inputs 3
+not 1
+and 0 3
+and 4 2
+output 5
+";
+
+#[test]
+fn test_parse_circuit() {
+    let circuit = Circuit::parse(EXAMPLE_CIRCUIT).unwrap();
+    assert_eq!(circuit.num_inputs, 3);
+    assert_eq!(circuit.gates, vec![Gate::Not(1), Gate::And(0, 3), Gate::And(4, 2)]);
+    assert_eq!(circuit.output, 5);
+}
+
+#[test]
+fn test_evaluate_circuit() {
+    let circuit = Circuit::parse(EXAMPLE_CIRCUIT).unwrap();
+    for x in 0..8 {
+        let inputs: Vec<bool> = (0..3).map(|i| (x >> i) & 1 == 1).collect();
+        assert_eq!(circuit.evaluate(&inputs), inputs == vec![true, false, true]);
+    }
+}
+
+#[test]
+fn test_parse_invalid_circuits() {
+    // Gates reading later wires would create cycles
+    assert!(Circuit::parse("inputs 2\nand 0 3\nand 0 1\noutput 3").is_err());
+    assert!(Circuit::parse("and 0 1\ninputs 2\noutput 2").is_err());
+    assert!(Circuit::parse("inputs 2\nor 0 1\noutput 2").is_err());
+    assert!(Circuit::parse("inputs 2\nand 0 1").is_err());
+    assert!(Circuit::parse("inputs 2\noutput 2").is_err());
+}
@@ -0,0 +1,193 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{Diagnosis, Prover, Verifier};
+use crate::commitment::{commit, Commitment, Opening};
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Coin flipping by telephone implementation ************
+
+// Blum's protocol lets two parties who don't trust each other agree on random coins. The first party
+// commits to random bits a, the second party replies with random bits b, and the first party opens
+// its commitment. Both parties output a XOR b, which is uniformly random as long as either party is
+// honest: the commitment hides a from the second party, and binds the first party to a before it sees b.
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CoinFlipProverMessage {
+    // Commitment to the first party's random bits
+    Commitment(Commitment),
+    // Opening of the commitment
+    Opening(Opening),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CoinFlipVerifierMessage {
+    // Second party's random bits
+    pub bits: Vec<bool>,
+}
+
+pub struct CoinFlipProver {
+    // Keep track of round number
+    pub r: u32,
+    // Number of coins to flip
+    pub num_coins: usize,
+    // Opening of the commitment to the first party's bits
+    pub opening: Option<Opening>,
+    // Resulting coins once the interaction is complete
+    pub coins: Vec<bool>,
+}
+
+impl Prover for CoinFlipProver {
+    type ProverMessage = CoinFlipProverMessage;
+    type VerifierMessage = CoinFlipVerifierMessage;
+
+    fn handle(&mut self, msg: &CoinFlipVerifierMessage) -> (CoinFlipProverMessage, bool) {
+        self.r += 1;
+        match self.r {
+            // During the first round, the first party commits to random bits
+            1 => {
+                let bits: Vec<u8> = (0..self.num_coins).map(|_| checked_thread_rng().gen_range(0..2)).collect();
+                let (commitment, opening) = commit(&bits);
+                self.opening = Some(opening);
+                (CoinFlipProverMessage::Commitment(commitment), false)
+            },
+            // During the second round, the first party opens its commitment and combines both parties' bits
+            2 => {
+                let opening = self.opening.clone().unwrap();
+                self.coins = xor_bits(&opening.value, &msg.bits);
+                (CoinFlipProverMessage::Opening(opening), false)
+            },
+            // After opening the commitment, the first party sends a message to terminate the interaction
+            _ => (CoinFlipProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &CoinFlipVerifierMessage) -> Result<(), String> {
+        if self.r + 1 == 2 && msg.bits.len() != self.num_coins {
+            return Err(format!("Found {} bits, expected {}", msg.bits.len(), self.num_coins));
+        }
+        Ok(())
+    }
+}
+
+pub struct CoinFlipVerifier {
+    // Keep track of round number
+    pub r: u32,
+    // Number of coins to flip
+    pub num_coins: usize,
+    // Commitment received from the first party
+    pub commitment: Option<Commitment>,
+    // Second party's random bits
+    pub bits: Vec<bool>,
+    // Resulting coins once the interaction is complete
+    pub coins: Vec<bool>,
+}
+
+impl Verifier for CoinFlipVerifier {
+    type ProverMessage = CoinFlipProverMessage;
+    type VerifierMessage = CoinFlipVerifierMessage;
+
+    fn init(&mut self) -> CoinFlipVerifierMessage {
+        narrate!("Initializing coin flip for {} coins.", self.num_coins);
+        CoinFlipVerifierMessage { bits: Vec::new() }
+    }
+
+    // The opening's bits are checked on handling, since a malformed opening is just an invalid one
+    fn validate(&self, msg: &CoinFlipProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, CoinFlipProverMessage::Commitment(_)) | (2, CoinFlipProverMessage::Opening(_)) => Ok(()),
+            (1, _) => Err("expected a commitment".to_string()),
+            _ => Err("expected an opening".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &CoinFlipProverMessage) -> Option<Diagnosis> {
+        let opening = match msg {
+            CoinFlipProverMessage::Opening(opening) => opening,
+            _ => return None,
+        };
+        if !self.commitment.as_ref().is_some_and(|x| x.verify(opening)) {
+            Some(Diagnosis::new("opening does not match commitment", None))
+        } else if opening.value.len() != self.num_coins {
+            Some(Diagnosis::new("wrong number of bits", Some(format!("Found {} bits, expected {}", opening.value.len(), self.num_coins))))
+        } else {
+            Some(Diagnosis::new("opened value is not a bit string", None))
+        }
+    }
+
+    fn handle(&mut self, msg: &CoinFlipProverMessage) -> (CoinFlipVerifierMessage, bool) {
+        self.r += 1;
+        match self.r {
+            1 => {
+                if let CoinFlipProverMessage::Commitment(commitment) = msg {
+                    self.commitment = Some(commitment.clone());
+                    // Only once the first party is committed does the second party choose its bits
+                    self.bits = (0..self.num_coins).map(|_| checked_thread_rng().gen_bool(0.5)).collect();
+                    (CoinFlipVerifierMessage { bits: self.bits.clone() }, false)
+                } else {
+                    panic!("Prover did not send a valid commitment on round 1!")
+                }
+            },
+            _ => {
+                if let CoinFlipProverMessage::Opening(opening) = msg {
+                    // The second party accepts the outcome if the opening is valid and contains one bit per coin
+                    let valid = self.commitment.as_ref().unwrap().verify(opening)
+                        && opening.value.len() == self.num_coins
+                        && opening.value.iter().all(|&x| x < 2);
+                    if valid {
+                        self.coins = xor_bits(&opening.value, &self.bits);
+                        narrate!("Parties agreed on coins {:?}.", self.coins.iter().map(|&x| if x {1} else {0}).collect::<Vec<u8>>());
+                    }
+                    (CoinFlipVerifierMessage { bits: self.bits.clone() }, valid)
+                } else {
+                    panic!("Prover did not send a valid opening on round 2!")
+                }
+            }
+        }
+    }
+}
+
+fn xor_bits(a: &[u8], b: &[bool]) -> Vec<bool> {
+    a.iter().zip(b.iter()).map(|(&x, &y)| (x == 1) ^ y).collect()
+}
+
+#[test]
+fn test_coin_flip() {
+    let mut prover = CoinFlipProver { r: 0, num_coins: 128, opening: None, coins: Vec::new() };
+    let mut verifier = CoinFlipVerifier { r: 0, num_coins: 128, commitment: None, bits: Vec::new(), coins: Vec::new() };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+    // Both parties should agree on the outcome
+    assert_eq!(prover.coins.len(), 128);
+    assert_eq!(prover.coins, verifier.coins);
+    // With 128 fair coins, a lopsided outcome is vanishingly unlikely
+    let heads = verifier.coins.iter().filter(|&&x| x).count();
+    assert!(heads > 20 && heads < 108);
+}
+
+#[test]
+fn test_coin_flip_equivocation() {
+    // A first party trying to change its bits after seeing the second party's bits is caught
+    struct EquivocatingProver { inner: CoinFlipProver }
+    impl Prover for EquivocatingProver {
+        type ProverMessage = CoinFlipProverMessage;
+        type VerifierMessage = CoinFlipVerifierMessage;
+
+        fn handle(&mut self, msg: &CoinFlipVerifierMessage) -> (CoinFlipProverMessage, bool) {
+            match self.inner.handle(msg) {
+                // Flip the first bit so that the first coin comes out the other way
+                (CoinFlipProverMessage::Opening(mut opening), done) => {
+                    opening.value[0] ^= 1;
+                    (CoinFlipProverMessage::Opening(opening), done)
+                },
+                x => x,
+            }
+        }
+    }
+    let mut prover = EquivocatingProver { inner: CoinFlipProver { r: 0, num_coins: 8, opening: None, coins: Vec::new() } };
+    let mut verifier = CoinFlipVerifier { r: 0, num_coins: 8, commitment: None, bits: Vec::new(), coins: Vec::new() };
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+    assert!(verifier.coins.is_empty());
+}
@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use rand::{Rng, seq::IteratorRandom};
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::commitment::{commit, Commitment, Opening};
+use crate::graph::{check_count, Graph};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Graph coloring witnesses and heuristics ************
+
+// Assignment of a color to each vertex of a graph, where colors are labeled 0 to k-1.
+// Edge directions are ignored, so a coloring is proper if no edge joins two vertices of the same color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coloring {
+    pub colors: Vec<u32>,
+}
+
+impl Coloring {
+    // Checks that every vertex is colored and that no edge is monochromatic
+    pub fn verify(&self, graph: &Graph) -> bool {
+        self.colors.len() == graph.n as usize && graph.edges().all(|(a, b)| {
+            self.colors[a as usize] != self.colors[b as usize]
+        })
+    }
+
+    // Number of distinct colors used
+    pub fn num_colors(&self) -> usize {
+        self.colors.iter().collect::<HashSet<_>>().len()
+    }
+
+    // Colors vertices in order, giving each the smallest color not used by an already colored neighbor
+    pub fn greedy(graph: &Graph) -> Coloring {
+        let neighbors = undirected_neighbors(graph);
+        let mut colors: Vec<Option<u32>> = vec![None; graph.n as usize];
+        for v in 0..graph.n as usize {
+            colors[v] = Some(smallest_free_color(&neighbors[v], &colors));
+        }
+        Coloring { colors: colors.into_iter().map(Option::unwrap).collect() }
+    }
+
+    // DSATUR heuristic: repeatedly colors the uncolored vertex whose neighbors already use the most
+    // distinct colors (its saturation), breaking ties by degree. Optimal on bipartite graphs.
+    pub fn dsatur(graph: &Graph) -> Coloring {
+        let neighbors = undirected_neighbors(graph);
+        let mut colors: Vec<Option<u32>> = vec![None; graph.n as usize];
+        for _ in 0..graph.n {
+            let v = (0..graph.n as usize).filter(|&v| colors[v].is_none()).max_by_key(|&v| {
+                let saturation = neighbors[v].iter().filter_map(|&u| colors[u as usize]).collect::<HashSet<u32>>().len();
+                (saturation, neighbors[v].len())
+            }).unwrap();
+            colors[v] = Some(smallest_free_color(&neighbors[v], &colors));
+        }
+        Coloring { colors: colors.into_iter().map(Option::unwrap).collect() }
+    }
+}
+
+// Generates a random graph on n vertices with a planted k-coloring. Each vertex is assigned a random
+// color, and each pair of differently colored vertices is joined by an edge with probability p.
+pub fn random_colorable_graph(n: u32, k: u32, p: f64) -> (Graph, Coloring) {
+    let mut rng = checked_thread_rng();
+    let colors: Vec<u32> = (0..n).map(|_| rng.gen_range(0..k)).collect();
+    let mut edges = Vec::new();
+    for a in 0..n {
+        for b in a + 1..n {
+            if colors[a as usize] != colors[b as usize] && rng.gen_bool(p) {
+                edges.push((a, b));
+            }
+        }
+    }
+    (Graph::new(n, edges), Coloring { colors })
+}
+
+// Generates a random 3-colorable graph along with its planted coloring, for use as an honest witness
+pub fn random_3_colorable_graph(n: u32, p: f64) -> (Graph, Coloring) {
+    random_colorable_graph(n, 3, p)
+}
+
+fn undirected_neighbors(graph: &Graph) -> Vec<HashSet<u32>> {
+    let mut neighbors = vec![HashSet::new(); graph.n as usize];
+    for (a, b) in graph.edges() {
+        neighbors[a as usize].insert(b);
+        neighbors[b as usize].insert(a);
+    }
+    neighbors
+}
+
+fn smallest_free_color(neighbors: &HashSet<u32>, colors: &[Option<u32>]) -> u32 {
+    let used: HashSet<u32> = neighbors.iter().filter_map(|&u| colors[u as usize]).collect();
+    (0..).find(|c| !used.contains(c)).unwrap()
+}
+
+// ************ Zero-knowledge 3-coloring proof implementation ************
+
+// The protocol of Goldreich, Micali and Wigderson. The prover renames the three colors of its coloring at
+// random and commits to the color of every vertex, the verifier picks an edge of the graph, and the prover
+// opens the commitments to its endpoints, which must hold two different colors. The verifier only sees two
+// distinct random colors, so the proof is zero knowledge, but a graph with no proper 3-coloring has at
+// least one monochromatic edge under any commitments, so a cheating prover is caught with probability at
+// least 1/m per repetition on a graph with m edges, rather than 1/2 as for GI.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ColoringProverMessage {
+    // Commitments to the renamed color of each vertex
+    Commitments(Vec<Commitment>),
+    // Openings of the commitments to the challenged edge's endpoints
+    Openings(Opening, Opening),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ColoringVerifierMessage {
+    // Edge whose endpoints the prover must open, or None before the commitments arrive or if the graph
+    // has no edges
+    pub edge: Option<(u32, u32)>,
+}
+
+pub struct ColoringProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Openings of the commitments sent to the verifier
+    pub openings: Vec<Opening>,
+    pub graph: &'a Graph,
+    pub coloring: &'a Coloring,
+}
+
+impl<'a> ColoringProver<'a> {
+    // Starts a proof with the coloring as the witness. The coloring needn't be proper, so that a cheating
+    // prover can be run, but every vertex needs one of the three colors the protocol commits to.
+    pub fn new(graph: &'a Graph, coloring: &'a Coloring) -> Result<ColoringProver<'a>, String> {
+        check_count(coloring.colors.len(), graph.n as usize, "colors")?;
+        if let Some((v, c)) = coloring.colors.iter().enumerate().find(|(_, &c)| c >= 3) {
+            return Err(format!("Vertex {} has color {}, expected 0, 1 or 2", v, c));
+        }
+        Ok(ColoringProver { r: 0, openings: Vec::new(), graph, coloring })
+    }
+}
+
+impl Prover for ColoringProver<'_> {
+    type ProverMessage = ColoringProverMessage;
+    type VerifierMessage = ColoringVerifierMessage;
+
+    fn handle(&mut self, msg: &ColoringVerifierMessage) -> (ColoringProverMessage, bool) {
+        self.r += 1;
+        match (self.r, msg.edge) {
+            // During the first round, the prover commits to its coloring with the colors renamed at random
+            (1, _) => {
+                let renaming = Permutation::random(3, &mut checked_thread_rng());
+                let (commitments, openings) = self.coloring.colors.iter().map(|&c| commit(&[renaming.apply(c) as u8])).unzip();
+                self.openings = openings;
+                (ColoringProverMessage::Commitments(commitments), false)
+            },
+            // During the second round, the prover opens the colors of the challenged edge's endpoints
+            (2, Some((a, b))) => (ColoringProverMessage::Openings(self.openings[a as usize].clone(), self.openings[b as usize].clone()), false),
+            // After opening the colors, or if there was no edge to challenge, the prover terminates the interaction
+            _ => (ColoringProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &ColoringVerifierMessage) -> Result<(), String> {
+        match msg.edge {
+            Some((a, b)) if self.r + 1 == 2 && !self.graph.has_edge(a, b) => Err(format!("({}, {}) is not an edge", a, b)),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct ColoringVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen edge
+    pub edge: Option<(u32, u32)>,
+    // Commitments received from prover
+    pub commitments: Vec<Commitment>,
+    pub graph: &'a Graph,
+}
+
+impl Verifier for ColoringVerifier<'_> {
+    type ProverMessage = ColoringProverMessage;
+    type VerifierMessage = ColoringVerifierMessage;
+
+    fn init(&mut self) -> ColoringVerifierMessage {
+        ColoringVerifierMessage { edge: None }
+    }
+
+    fn validate(&self, msg: &ColoringProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, ColoringProverMessage::Commitments(commitments)) => check_count(commitments.len(), self.graph.n as usize, "commitments"),
+            (1, _) => Err("expected commitments".to_string()),
+            (_, ColoringProverMessage::Openings(..)) => Ok(()),
+            _ => Err("expected openings".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &ColoringProverMessage) -> (ColoringVerifierMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            (1, ColoringProverMessage::Commitments(commitments)) => {
+                self.commitments = commitments.clone();
+                self.edge = self.graph.edges().choose(&mut checked_thread_rng());
+                // A graph without edges is colored by any assignment
+                (ColoringVerifierMessage { edge: self.edge }, self.edge.is_none())
+            },
+            (_, ColoringProverMessage::Openings(a, b)) => {
+                let accept = self.edge.is_some_and(|(x, y)| {
+                    self.commitments[x as usize].verify(a) && self.commitments[y as usize].verify(b)
+                        && a.value.len() == 1 && b.value.len() == 1 && a.value[0] < 3 && b.value[0] < 3 && a.value != b.value
+                });
+                (ColoringVerifierMessage { edge: self.edge }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_verify_coloring() {
+    let triangle = Graph::new(3, vec![(0, 1), (1, 2), (2, 0)]);
+    assert!(Coloring { colors: vec![0, 1, 2] }.verify(&triangle));
+    assert!(!Coloring { colors: vec![0, 1, 1] }.verify(&triangle));
+    // Every vertex must be colored
+    assert!(!Coloring { colors: vec![0, 1] }.verify(&triangle));
+}
+
+#[test]
+fn test_self_loop_is_not_colorable() {
+    let graph = Graph::new(2, vec![(0, 1), (1, 1)]);
+    assert!(!Coloring::greedy(&graph).verify(&graph));
+}
+
+#[test]
+fn test_planted_coloring() {
+    let (graph, coloring) = random_3_colorable_graph(50, 0.3);
+    assert!(coloring.verify(&graph));
+    assert!(coloring.num_colors() <= 3);
+}
+
+#[test]
+fn test_heuristics_produce_proper_colorings() {
+    let (graph, _) = random_3_colorable_graph(50, 0.3);
+    assert!(Coloring::greedy(&graph).verify(&graph));
+    assert!(Coloring::dsatur(&graph).verify(&graph));
+}
+
+#[test]
+fn test_dsatur_bipartite() {
+    // Greedy coloring in vertex order needs 3 colors on this path, while DSATUR finds the optimal 2
+    let path = Graph::new(4, vec![(0, 2), (2, 3), (3, 1)]);
+    assert_eq!(Coloring::greedy(&path).num_colors(), 3);
+    assert_eq!(Coloring::dsatur(&path).num_colors(), 2);
+}
+
+#[test]
+fn test_coloring_proof() {
+    let (graph, coloring) = random_3_colorable_graph(20, 0.3);
+    let mut prover = ColoringProver::new(&graph, &coloring).unwrap();
+    let mut verifier = ColoringVerifier { r: 0, edge: None, commitments: Vec::new(), graph: &graph };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+
+    // A triangle can't be colored with two colors, so the prover is caught on one of its three edges
+    let triangle = Graph::new(3, vec![(0, 1), (1, 2), (2, 0)]);
+    let improper = Coloring { colors: vec![0, 1, 1] };
+    let rejected = (0..100).filter(|_| {
+        let mut prover = ColoringProver::new(&triangle, &improper).unwrap();
+        let mut verifier = ColoringVerifier { r: 0, edge: None, commitments: Vec::new(), graph: &triangle };
+        !run_interactive_proof(&mut prover, &mut verifier)
+    }).count();
+    assert!(rejected > 10 && rejected < 60);
+
+    // A coloring the commitments can't hold is refused up front
+    assert!(ColoringProver::new(&triangle, &Coloring { colors: vec![0, 1, 3] }).err().unwrap().contains("Vertex 2 has color 3"));
+    assert!(ColoringProver::new(&triangle, &Coloring { colors: vec![0, 1] }).is_err());
+}
@@ -0,0 +1,466 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::modarith::{mod_exp, mod_inverse};
+use crate::params::serde_biguints;
+use crate::pedersen::{PedersenCommitment, PedersenOpening, PedersenParameters};
+use crate::transcript::{ChallengeTranscript, ProofContext, encode_biguints};
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Commit-and-prove framework ************
+
+// In commit-and-prove, a prover commits to a value once and then proves several statements about the
+// committed value, e.g. that it lies in a range and that it equals a value committed elsewhere. Every
+// proof refers to the same commitment, so the verifier knows the statements are all about one value even
+// though it never learns the value itself.
+//
+// A session holds the commitment along with a transcript shared by all of the proofs about it. The
+// transcript starts from a proof context naming the protocol, the session, and the commitment, and
+// absorbs the messages of every proof in turn, so proofs can't be replayed into another session or
+// reordered within one. Proofs can either be run interactively or made non-interactive by deriving
+// challenges from the transcript.
+
+// A sigma protocol proving a statement about the value inside a Pedersen commitment. The prover sends an
+// announcement, receives a random challenge in Z_q, and sends a response.
+pub trait CommittedProof {
+    // Secret input the prover needs besides the opening of the commitment
+    type Witness;
+
+    // Name of the proof, absorbed into the transcript before its messages
+    fn label(&self) -> &'static [u8];
+
+    // Returns the announcement along with the secret state needed to respond to the challenge
+    fn announce(&self, params: &PedersenParameters, opening: &PedersenOpening, witness: &Self::Witness) -> (Vec<BigUint>, Vec<BigUint>);
+
+    fn respond(&self, params: &PedersenParameters, opening: &PedersenOpening, witness: &Self::Witness, state: &[BigUint], challenge: &BigUint) -> Vec<BigUint>;
+
+    // Checks the proof against the commitment, rejecting malformed messages
+    fn check(&self, params: &PedersenParameters, commitment: &PedersenCommitment, announcement: &[BigUint], challenge: &BigUint, response: &[BigUint]) -> bool;
+}
+
+// Domain separation label for commit-and-prove sessions
+pub const COMMIT_AND_PROVE_DOMAIN: &str = "zklib commit-and-prove";
+
+// Announcement and response of a proof whose challenge was derived from the transcript
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NonInteractiveProof {
+    // Context of the session the proof was produced in
+    pub context: ProofContext,
+    #[serde(with = "serde_biguints")]
+    pub announcement: Vec<BigUint>,
+    #[serde(with = "serde_biguints")]
+    pub response: Vec<BigUint>,
+}
+
+pub struct CommitAndProve {
+    pub params: PedersenParameters,
+    pub commitment: PedersenCommitment,
+    pub context: ProofContext,
+    // Transcript shared by every proof about the commitment
+    pub transcript: ChallengeTranscript,
+}
+
+impl CommitAndProve {
+    // Starts a session about the commitment, whose statement is the parameters and the commitment itself
+    pub fn new(params: &PedersenParameters, commitment: &PedersenCommitment, session_id: &[u8]) -> CommitAndProve {
+        let statement = encode_biguints(&[&params.group.p, &params.group.q, &params.group.g, &params.h, &commitment.c]);
+        let context = ProofContext::new(COMMIT_AND_PROVE_DOMAIN, session_id, &statement);
+        let transcript = ChallengeTranscript::with_context(&context);
+        CommitAndProve { params: params.clone(), commitment: commitment.clone(), context, transcript }
+    }
+
+    // Proves a statement about the committed value non-interactively (Fiat-Shamir)
+    pub fn prove<P: CommittedProof>(&mut self, proof: &P, opening: &PedersenOpening, witness: &P::Witness) -> NonInteractiveProof {
+        let (announcement, state) = proof.announce(&self.params, opening, witness);
+        self.absorb_announcement(proof, &announcement);
+        let challenge = self.transcript.challenge_scalar(b"challenge", &self.params.group.q);
+        let response = proof.respond(&self.params, opening, witness, &state, &challenge);
+        self.absorb(b"response", &response);
+        NonInteractiveProof { context: self.context.clone(), announcement, response }
+    }
+
+    // Verifies a non-interactive proof, which must come in the same position of the session as it was proven
+    pub fn verify<P: CommittedProof>(&mut self, proof: &P, nizk: &NonInteractiveProof) -> bool {
+        if let Err(e) = nizk.context.check(&self.context) {
+            narrate!("Rejecting proof: {}.", e);
+            return false;
+        }
+        self.absorb_announcement(proof, &nizk.announcement);
+        let challenge = self.transcript.challenge_scalar(b"challenge", &self.params.group.q);
+        self.absorb(b"response", &nizk.response);
+        proof.check(&self.params, &self.commitment, &nizk.announcement, &challenge, &nizk.response)
+    }
+
+    fn absorb_announcement<P: CommittedProof>(&mut self, proof: &P, announcement: &[BigUint]) {
+        self.transcript.append_message(b"proof", proof.label());
+        self.absorb(b"announcement", announcement);
+    }
+
+    fn absorb(&mut self, label: &[u8], values: &[BigUint]) {
+        self.transcript.append_message(label, &(values.len() as u64).to_be_bytes());
+        values.iter().for_each(|x| self.transcript.append_biguint(label, x));
+    }
+}
+
+// ************ Interactive commit-and-prove implementation ************
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CommittedProverMessage {
+    // Announcement of the sigma protocol
+    Announcement(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Response to the verifier's challenge
+    Response(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone)]
+pub struct CommittedVerifierMessage {
+    // Random challenge in Z_q
+    pub c: BigUint,
+}
+
+pub struct CommittedProver<'a, P: CommittedProof> {
+    // Keep track of round number
+    pub r: u32,
+    // Secret state from the announcement
+    pub state: Vec<BigUint>,
+    pub session: &'a mut CommitAndProve,
+    pub proof: &'a P,
+    pub opening: &'a PedersenOpening,
+    pub witness: &'a P::Witness,
+}
+
+impl<P: CommittedProof> Prover for CommittedProver<'_, P> {
+    type ProverMessage = CommittedProverMessage;
+    type VerifierMessage = CommittedVerifierMessage;
+
+    fn handle(&mut self, msg: &CommittedVerifierMessage) -> (CommittedProverMessage, bool) {
+        self.r += 1;
+        match self.r {
+            // During the first round, the prover sends the announcement
+            1 => {
+                let (announcement, state) = self.proof.announce(&self.session.params, self.opening, self.witness);
+                self.state = state;
+                self.session.absorb_announcement(self.proof, &announcement);
+                (CommittedProverMessage::Announcement(announcement), false)
+            },
+            // During the second round, the prover responds to the challenge
+            2 => {
+                self.session.transcript.append_challenge(b"challenge", &msg.c);
+                let response = self.proof.respond(&self.session.params, self.opening, self.witness, &self.state, &msg.c);
+                self.session.absorb(b"response", &response);
+                (CommittedProverMessage::Response(response), false)
+            },
+            // After sending its response, the prover sends a message to terminate the interaction
+            _ => (CommittedProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &CommittedVerifierMessage) -> Result<(), String> {
+        match self.r + 1 {
+            2 => self.session.params.group.check_exponent(&msg.c),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct CommittedVerifier<'a, P: CommittedProof> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub announcement: Vec<BigUint>,
+    pub challenge: BigUint,
+    pub session: &'a mut CommitAndProve,
+    pub proof: &'a P,
+}
+
+impl<P: CommittedProof> Verifier for CommittedVerifier<'_, P> {
+    type ProverMessage = CommittedProverMessage;
+    type VerifierMessage = CommittedVerifierMessage;
+
+    fn init(&mut self) -> CommittedVerifierMessage {
+        narrate!("Initializing {} proof about commitment {}.", String::from_utf8_lossy(self.proof.label()), self.session.commitment.c);
+        CommittedVerifierMessage { c: BigUint::zero() }
+    }
+
+    // The shape of the announcement and response depends on the proof, whose check rejects malformed ones
+    fn validate(&self, msg: &CommittedProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, CommittedProverMessage::Announcement(_)) => Ok(()),
+            (1, _) => Err("expected an announcement".to_string()),
+            (_, CommittedProverMessage::Response(_)) => Ok(()),
+            _ => Err("expected a response".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &CommittedProverMessage) -> (CommittedVerifierMessage, bool) {
+        self.r += 1;
+        match self.r {
+            1 => {
+                if let CommittedProverMessage::Announcement(announcement) = msg {
+                    self.session.absorb_announcement(self.proof, announcement);
+                    self.announcement = announcement.clone();
+                    self.challenge = self.session.transcript.verifier_challenge_scalar(b"challenge", &self.session.params.group.q);
+                    (CommittedVerifierMessage { c: self.challenge.clone() }, false)
+                } else {
+                    panic!("Prover did not send a valid announcement on round 1!")
+                }
+            },
+            _ => {
+                if let CommittedProverMessage::Response(response) = msg {
+                    self.session.absorb(b"response", response);
+                    let accept = self.proof.check(&self.session.params, &self.session.commitment, &self.announcement, &self.challenge, response);
+                    (CommittedVerifierMessage { c: self.challenge.clone() }, accept)
+                } else {
+                    panic!("Prover did not send a valid response on round 2!")
+                }
+            }
+        }
+    }
+}
+
+// ************ Proofs about committed values ************
+
+// Proves knowledge of an opening (x, r) of C = g^x h^r
+pub struct OpeningProof;
+
+impl CommittedProof for OpeningProof {
+    type Witness = ();
+
+    fn label(&self) -> &'static [u8] {
+        b"opening"
+    }
+
+    // Announces a = g^k1 h^k2 for random nonces k1 and k2
+    fn announce(&self, params: &PedersenParameters, _opening: &PedersenOpening, _witness: &()) -> (Vec<BigUint>, Vec<BigUint>) {
+        let (k1, k2) = (params.group.random_exponent(), params.group.random_exponent());
+        (vec![params.commit_with_randomness(&k1, &k2).c], vec![k1, k2])
+    }
+
+    // Responds with z1 = k1 + c x and z2 = k2 + c r
+    fn respond(&self, params: &PedersenParameters, opening: &PedersenOpening, _witness: &(), state: &[BigUint], challenge: &BigUint) -> Vec<BigUint> {
+        let q = &params.group.q;
+        vec![(&state[0] + challenge * &opening.value) % q, (&state[1] + challenge * &*opening.randomness) % q]
+    }
+
+    // Accepts if g^z1 h^z2 = a C^c
+    fn check(&self, params: &PedersenParameters, commitment: &PedersenCommitment, announcement: &[BigUint], challenge: &BigUint, response: &[BigUint]) -> bool {
+        let p = &params.group.p;
+        announcement.len() == 1 && response.len() == 2
+            && params.commit_with_randomness(&response[0], &response[1]).c == &announcement[0] * mod_exp(&commitment.c, challenge, p) % p
+    }
+}
+
+// Proves that the committed value equals the value inside another commitment C'. Since
+// C / C' = g^(x - x') h^(r - r'), this amounts to proving knowledge of log_h(C / C') = r - r'.
+pub struct EqualityProof {
+    pub other: PedersenCommitment,
+}
+
+impl CommittedProof for EqualityProof {
+    // Opening of the other commitment
+    type Witness = PedersenOpening;
+
+    fn label(&self) -> &'static [u8] {
+        b"equality"
+    }
+
+    // Announces a = h^k for a random nonce k
+    fn announce(&self, params: &PedersenParameters, _opening: &PedersenOpening, _witness: &PedersenOpening) -> (Vec<BigUint>, Vec<BigUint>) {
+        let k = params.group.random_exponent();
+        (vec![mod_exp(&params.h, &k, &params.group.p)], vec![k])
+    }
+
+    // Responds with z = k + c (r - r')
+    fn respond(&self, params: &PedersenParameters, opening: &PedersenOpening, witness: &PedersenOpening, state: &[BigUint], challenge: &BigUint) -> Vec<BigUint> {
+        let q = &params.group.q;
+        let difference = (&*opening.randomness + q - &*witness.randomness % q) % q;
+        vec![(&state[0] + challenge * difference) % q]
+    }
+
+    // Accepts if h^z = a (C / C')^c
+    fn check(&self, params: &PedersenParameters, commitment: &PedersenCommitment, announcement: &[BigUint], challenge: &BigUint, response: &[BigUint]) -> bool {
+        let p = &params.group.p;
+        let quotient = match mod_inverse(&self.other.c, p) {
+            Some(inverse) => &commitment.c * inverse % p,
+            None => return false,
+        };
+        announcement.len() == 1 && response.len() == 1
+            && mod_exp(&params.h, &response[0], p) == &announcement[0] * mod_exp(&quotient, challenge, p) % p
+    }
+}
+
+// Proves that the committed value lies in [0, 2^bits). The prover commits to each bit b_i of the value
+// as C_i = g^b_i h^s_i, with randomness chosen so that the product of the C_i^(2^i) is C, and proves
+// that each C_i commits to 0 or 1. Each of those is an OR proof that either C_i = h^s_i or
+// C_i / g = h^s_i: the prover answers the branch it knows honestly and simulates the other, and the
+// verifier only checks that the two branch challenges add up to its challenge.
+pub struct RangeProof {
+    pub bits: u32,
+}
+
+impl CommittedProof for RangeProof {
+    type Witness = ();
+
+    fn label(&self) -> &'static [u8] {
+        b"range"
+    }
+
+    // Announces (C_i, a_i0, a_i1) for each bit, where a_ib = h^k_i for the true bit b
+    fn announce(&self, params: &PedersenParameters, opening: &PedersenOpening, _witness: &()) -> (Vec<BigUint>, Vec<BigUint>) {
+        let (p, q) = (&params.group.p, &params.group.q);
+        let mut announcement = Vec::new();
+        let mut state = Vec::new();
+        // Randomness committed so far, weighted by powers of 2
+        let mut weighted = BigUint::zero();
+        for i in 0..self.bits {
+            let b = (&opening.value >> i) & BigUint::one();
+            let power = BigUint::one() << i;
+            let s = if i + 1 == self.bits {
+                // The last bit's randomness makes the weighted sum of the randomness equal r
+                let remainder = (&*opening.randomness + q - &weighted % q) % q;
+                remainder * mod_inverse(&(&power % q), q).unwrap() % q
+            } else {
+                params.group.random_exponent()
+            };
+            weighted += &power * &s;
+            let c_i = params.commit_with_randomness(&b, &s).c;
+            let branches = bit_branches(params, &c_i);
+            // Simulate the branch for the other bit with a random challenge and response
+            let (k, c_sim, z_sim) = (params.group.random_exponent(), params.group.random_exponent(), params.group.random_exponent());
+            let real = mod_exp(&params.h, &k, p);
+            let simulated = mod_exp(&params.h, &z_sim, p) * mod_exp(&branches[1 - bit_index(&b)], &(q - &c_sim), p) % p;
+            let (a0, a1) = if b.is_zero() { (real, simulated) } else { (simulated, real) };
+            announcement.extend(vec![c_i, a0, a1]);
+            state.extend(vec![b, s, k, c_sim, z_sim]);
+        }
+        (announcement, state)
+    }
+
+    // Responds with (c_i0, z_i0, z_i1) for each bit, where the real branch's challenge is c - c_sim
+    fn respond(&self, params: &PedersenParameters, _opening: &PedersenOpening, _witness: &(), state: &[BigUint], challenge: &BigUint) -> Vec<BigUint> {
+        let q = &params.group.q;
+        state.chunks(5).flat_map(|x| {
+            let (b, s, k, c_sim, z_sim) = (&x[0], &x[1], &x[2], &x[3], &x[4]);
+            let c_real = (challenge + q - c_sim) % q;
+            let z_real = (k + &c_real * s) % q;
+            if b.is_zero() {
+                vec![c_real, z_real, z_sim.clone()]
+            } else {
+                vec![c_sim.clone(), z_sim.clone(), z_real]
+            }
+        }).collect()
+    }
+
+    // Accepts if the bit commitments combine into C and both branches of every OR proof verify
+    fn check(&self, params: &PedersenParameters, commitment: &PedersenCommitment, announcement: &[BigUint], challenge: &BigUint, response: &[BigUint]) -> bool {
+        let (p, q) = (&params.group.p, &params.group.q);
+        let n = self.bits as usize;
+        // The range must be smaller than the group order so that the bits can't wrap around mod q
+        if n == 0 || self.bits as u64 >= q.bits() || announcement.len() != 3 * n || response.len() != 3 * n {
+            return false;
+        }
+        let combined = announcement.chunks(3).enumerate().fold(BigUint::one(), |acc, (i, x)| {
+            acc * mod_exp(&x[0], &(BigUint::one() << i), p) % p
+        });
+        combined == commitment.c && announcement.chunks(3).zip(response.chunks(3)).all(|(a, z)| {
+            let branches = bit_branches(params, &a[0]);
+            let challenges = [z[0].clone(), (challenge + q - &z[0] % q) % q];
+            (0..2).all(|j| mod_exp(&params.h, &z[1 + j], p) == &a[1 + j] * mod_exp(&branches[j], &challenges[j], p) % p)
+        })
+    }
+}
+
+// Elements C_i and C_i / g, exactly one of which is a power of h for an honest bit commitment
+fn bit_branches(params: &PedersenParameters, c: &BigUint) -> [BigUint; 2] {
+    let group = &params.group;
+    [c.clone(), c * mod_exp(&group.g, &(&group.q - 1u32), &group.p) % &group.p]
+}
+
+fn bit_index(b: &BigUint) -> usize {
+    if b.is_zero() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+// Runs a proof interactively between the two sessions
+fn run_committed_proof<P: CommittedProof>(prover_session: &mut CommitAndProve, verifier_session: &mut CommitAndProve, proof: &P, opening: &PedersenOpening, witness: &P::Witness) -> bool {
+    let mut prover = CommittedProver { r: 0, state: Vec::new(), session: prover_session, proof, opening, witness };
+    let mut verifier = CommittedVerifier { r: 0, announcement: Vec::new(), challenge: BigUint::zero(), session: verifier_session, proof };
+    run_interactive_proof(&mut prover, &mut verifier)
+}
+
+#[test]
+fn test_commit_and_prove_interactive() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(1000u32));
+    let (other, other_opening) = params.commit(&BigUint::from(1000u32));
+    let mut prover_session = CommitAndProve::new(&params, &commitment, b"test session");
+    let mut verifier_session = CommitAndProve::new(&params, &commitment, b"test session");
+    assert!(run_committed_proof(&mut prover_session, &mut verifier_session, &OpeningProof, &opening, &()));
+    assert!(run_committed_proof(&mut prover_session, &mut verifier_session, &RangeProof { bits: 16 }, &opening, &()));
+    assert!(run_committed_proof(&mut prover_session, &mut verifier_session, &EqualityProof { other }, &opening, &other_opening));
+    // Both parties saw the same messages, so their transcripts agree
+    assert_eq!(prover_session.transcript, verifier_session.transcript);
+}
+
+#[test]
+fn test_range_proof_out_of_range() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(70000u32));
+    let mut prover_session = CommitAndProve::new(&params, &commitment, b"test session");
+    let mut verifier_session = CommitAndProve::new(&params, &commitment, b"test session");
+    assert!(run_committed_proof(&mut prover_session, &mut verifier_session, &RangeProof { bits: 17 }, &opening, &()));
+    assert!(!run_committed_proof(&mut prover_session, &mut verifier_session, &RangeProof { bits: 16 }, &opening, &()));
+}
+
+#[test]
+fn test_equality_proof_different_values() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(1000u32));
+    let (other, other_opening) = params.commit(&BigUint::from(1001u32));
+    let mut prover_session = CommitAndProve::new(&params, &commitment, b"test session");
+    let mut verifier_session = CommitAndProve::new(&params, &commitment, b"test session");
+    assert!(!run_committed_proof(&mut prover_session, &mut verifier_session, &EqualityProof { other }, &opening, &other_opening));
+}
+
+#[test]
+fn test_commit_and_prove_noninteractive() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(1000u32));
+    let (other, other_opening) = params.commit(&BigUint::from(1000u32));
+    let range = RangeProof { bits: 16 };
+    let equality = EqualityProof { other };
+    let mut prover_session = CommitAndProve::new(&params, &commitment, b"test session");
+    let range_proof = prover_session.prove(&range, &opening, &());
+    let equality_proof = prover_session.prove(&equality, &opening, &other_opening);
+
+    let mut verifier_session = CommitAndProve::new(&params, &commitment, b"test session");
+    assert!(verifier_session.verify(&range, &range_proof));
+    assert!(verifier_session.verify(&equality, &equality_proof));
+    assert_eq!(prover_session.transcript, verifier_session.transcript);
+
+    // The proofs are bound to the session's context and to their order within the session
+    let mut other_session = CommitAndProve::new(&params, &commitment, b"other session");
+    assert!(!other_session.verify(&range, &range_proof));
+    let mut reordered_session = CommitAndProve::new(&params, &commitment, b"test session");
+    assert!(!reordered_session.verify(&equality, &equality_proof));
+}
+
+#[test]
+fn test_commit_and_prove_replay() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(1000u32));
+    let range = RangeProof { bits: 16 };
+    let proof = CommitAndProve::new(&params, &commitment, b"session 1").prove(&range, &opening, &());
+    // A proof relabeled with another session's context fails since its challenge was derived from the original context
+    let mut other_session = CommitAndProve::new(&params, &commitment, b"session 2");
+    let relabeled = NonInteractiveProof { context: other_session.context.clone(), ..proof.clone() };
+    assert!(!other_session.verify(&range, &relabeled));
+    // A proof about one commitment doesn't verify against another
+    let (other_commitment, _) = params.commit(&BigUint::from(1000u32));
+    assert!(!CommitAndProve::new(&params, &other_commitment, b"session 1").verify(&range, &proof));
+    assert!(CommitAndProve::new(&params, &commitment, b"session 1").verify(&range, &proof));
+}
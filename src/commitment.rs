@@ -0,0 +1,87 @@
+use rand::Rng;
+use crate::hash::{Hasher, Sha256Hasher};
+use serde::{Serialize, Deserialize};
+use crate::randomness::checked_thread_rng;
+use crate::secret::{Secret, ct_eq};
+
+// ************ Hash-based commitment scheme ************
+
+// A commitment to a value is the SHA-256 digest of fresh randomness followed by the value.
+// It is hiding since the randomness masks the value, and binding since opening to a different
+// value would require finding a SHA-256 collision. Commitments can also use another Hasher, such as
+// Poseidon, as long as the committer and the verifier agree on it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Commitment {
+    pub digest: [u8; 32],
+}
+
+// Everything the committer needs to reveal in order to open a commitment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Opening {
+    pub value: Vec<u8>,
+    pub randomness: Secret<[u8; 32]>,
+}
+
+// Commit to the given value, returning the commitment to publish and the opening to keep secret
+pub fn commit(value: &[u8]) -> (Commitment, Opening) {
+    commit_with_hasher::<Sha256Hasher>(value)
+}
+
+pub fn commit_with_hasher<H: Hasher>(value: &[u8]) -> (Commitment, Opening) {
+    let opening = Opening {
+        value: value.to_vec(),
+        randomness: Secret::new(checked_thread_rng().gen()),
+    };
+    (Commitment { digest: hash_opening::<H>(&opening) }, opening)
+}
+
+impl Commitment {
+    // Checks that the opening is consistent with this commitment
+    pub fn verify(&self, opening: &Opening) -> bool {
+        self.verify_with_hasher::<Sha256Hasher>(opening)
+    }
+
+    pub fn verify_with_hasher<H: Hasher>(&self, opening: &Opening) -> bool {
+        ct_eq(&self.digest, &hash_opening::<H>(opening))
+    }
+}
+
+impl Opening {
+    // The commitment this opens, which lets a list of openings be checked against a digest of their commitments
+    pub fn commitment(&self) -> Commitment {
+        Commitment { digest: hash_opening::<Sha256Hasher>(self) }
+    }
+}
+
+fn hash_opening<H: Hasher>(opening: &Opening) -> [u8; 32] {
+    H::hash(&[&*opening.randomness, &opening.value])
+}
+
+#[test]
+fn test_commit_and_open() {
+    let (commitment, opening) = commit(b"zklib");
+    assert!(commitment.verify(&opening));
+}
+
+#[test]
+fn test_open_to_different_value() {
+    let (commitment, mut opening) = commit(&[1]);
+    opening.value = vec![2];
+    assert!(!commitment.verify(&opening));
+}
+
+#[test]
+fn test_commitments_are_randomized() {
+    // Committing to the same value twice should produce different commitments
+    let (c0, _) = commit(&[7]);
+    let (c1, _) = commit(&[7]);
+    assert_ne!(c0, c1);
+}
+
+#[test]
+fn test_poseidon_commitment() {
+    use crate::hash::PoseidonHasher;
+    let (commitment, opening) = commit_with_hasher::<PoseidonHasher>(b"zklib");
+    assert!(commitment.verify_with_hasher::<PoseidonHasher>(&opening));
+    assert!(!commitment.verify(&opening));
+}
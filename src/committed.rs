@@ -0,0 +1,332 @@
+use num_bigint::BigUint;
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use crate::commitment::{commit, Commitment, Opening};
+use crate::graph::{check_count, Graph};
+use crate::merkle::{MerklePath, MerkleTree};
+use crate::modarith::mod_exp;
+use crate::pedersen::{PedersenCommitment, PedersenOpening, PedersenParameters};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::secret::Secret;
+#[cfg(test)]
+use crate::params::Parameters;
+
+// ************ Committed graph implementation ************
+
+// A commitment to a graph is a hash commitment to every entry of its adjacency matrix, so that entries can
+// be opened one at a time, e.g. the edges of a Hamiltonian cycle, while the others stay hidden. A directed
+// graph commits to all n^2 entries row by row, and an undirected one only to the entries a < b above the
+// diagonal, in the order of pairs.
+//
+// The entry commitments are either all sent, 32 bytes per entry, or only the root of a Merkle tree over
+// them, in which case each opened entry carries its Merkle path. Opening every entry needs no paths, since
+// the verifier can rebuild the tree, so the Merkle root is much smaller when only a few entries are
+// opened, and costs nothing extra when all of them are.
+//
+// The Hamiltonicity proof commits to graphs this way. The 3-coloring proof doesn't, since its graph is
+// public and what it hides is the color of each vertex, which it commits to one vertex at a time.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GraphCommitmentScheme {
+    // Sends every entry's commitment
+    PerEntry,
+    // Sends the root of a Merkle tree over the entries' commitments
+    Merkle,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EntryCommitments {
+    PerEntry(Vec<Commitment>),
+    Merkle([u8; 32]),
+}
+
+// What the committer publishes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphCommitment {
+    pub n: u32,
+    pub directed: bool,
+    pub entries: EntryCommitments,
+}
+
+// Opening of the entry for the edge (a, b), with its Merkle path if the entries are committed by a root
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntryOpening {
+    pub a: u32,
+    pub b: u32,
+    pub opening: Opening,
+    pub path: Option<MerklePath>,
+}
+
+// A graph committed to along with everything needed to open it
+pub struct CommittedGraph {
+    pub commitment: GraphCommitment,
+    openings: Vec<Opening>,
+    tree: Option<MerkleTree>,
+}
+
+// Number of committed entries for a graph on n vertices
+pub fn entry_count(n: u32, directed: bool) -> usize {
+    let n = n as usize;
+    if directed { n * n } else { n * n.saturating_sub(1) / 2 }
+}
+
+// Pairs of vertices a < b, in the order of their entries in an undirected graph's commitment
+pub fn pairs(n: u32) -> impl Iterator<Item = (u32, u32)> {
+    (0..n).tuple_combinations()
+}
+
+// Position of the entry for the edge (a, b) in a commitment, where edges of an undirected graph are pairs
+// {a, b}, or None if there is no such entry
+pub fn checked_entry_index(n: u32, directed: bool, a: u32, b: u32) -> Option<usize> {
+    if a >= n || b >= n || (!directed && a == b) {
+        return None;
+    }
+    let n = n as usize;
+    if directed {
+        return (a as usize).checked_mul(n)?.checked_add(b as usize);
+    }
+    let (a, b) = (a.min(b) as usize, a.max(b) as usize);
+    // a(n - 1) - a(a - 1)/2 entries come before row a, and b - a - 1 before the entry in it
+    let before = a.checked_mul(n - 1)?.checked_sub(a * a.saturating_sub(1) / 2)?;
+    before.checked_add(b - a - 1)
+}
+
+// Like checked_entry_index, for an edge known to have an entry
+pub fn entry_index(n: u32, directed: bool, a: u32, b: u32) -> usize {
+    checked_entry_index(n, directed, a, b).expect("Edge has no entry")
+}
+
+// Every edge (a, b) with an entry, in the order of the entries
+fn entries(n: u32, directed: bool) -> Box<dyn Iterator<Item = (u32, u32)>> {
+    if directed { Box::new((0..n).cartesian_product(0..n)) } else { Box::new(pairs(n)) }
+}
+
+// Whether the graph has the edge, ignoring its direction if the graph is undirected
+pub fn has_entry(graph: &Graph, directed: bool, a: u32, b: u32) -> bool {
+    graph.has_edge(a, b) || (!directed && graph.has_edge(b, a))
+}
+
+fn merkle_tree(commitments: &[Commitment]) -> MerkleTree {
+    MerkleTree::new(&commitments.iter().map(|x| x.digest.to_vec()).collect::<Vec<_>>())
+}
+
+impl CommittedGraph {
+    pub fn commit(graph: &Graph, directed: bool, scheme: GraphCommitmentScheme) -> CommittedGraph {
+        let n = graph.n;
+        let (commitments, openings): (Vec<_>, Vec<_>) = entries(n, directed).map(|(a, b)| commit(&[has_entry(graph, directed, a, b) as u8])).unzip();
+        let (entries, tree) = match scheme {
+            GraphCommitmentScheme::PerEntry => (EntryCommitments::PerEntry(commitments), None),
+            GraphCommitmentScheme::Merkle => {
+                let tree = merkle_tree(&commitments);
+                (EntryCommitments::Merkle(tree.root()), Some(tree))
+            },
+        };
+        CommittedGraph { commitment: GraphCommitment { n, directed, entries }, openings, tree }
+    }
+
+    // Opens the entry for the edge (a, b)
+    pub fn open(&self, a: u32, b: u32) -> EntryOpening {
+        let index = entry_index(self.commitment.n, self.commitment.directed, a, b);
+        EntryOpening { a, b, opening: self.openings[index].clone(), path: self.tree.as_ref().map(|x| x.open(index)) }
+    }
+
+    // Opens every entry, in order
+    pub fn open_all(&self) -> Vec<Opening> {
+        self.openings.clone()
+    }
+}
+
+impl GraphCommitment {
+    // Checks that the opening matches this commitment, returning whether the entry is an edge
+    pub fn verify_entry(&self, opening: &EntryOpening) -> Option<bool> {
+        let (n, directed) = (self.n, self.directed);
+        let index = checked_entry_index(n, directed, opening.a, opening.b)?;
+        let consistent = match (&self.entries, &opening.path) {
+            (EntryCommitments::PerEntry(commitments), None) => commitments.get(index).is_some_and(|x| x.verify(&opening.opening)),
+            (EntryCommitments::Merkle(root), Some(path)) => path.verify(root, entry_count(n, directed), index, &opening.opening.commitment().digest),
+            _ => false,
+        };
+        if consistent { entry_bit(&opening.opening) } else { None }
+    }
+
+    // Checks that the openings of every entry match this commitment, returning the committed graph
+    pub fn verify_all(&self, openings: &[Opening]) -> Option<Graph> {
+        let (n, directed) = (self.n, self.directed);
+        if openings.len() != entry_count(n, directed) {
+            return None;
+        }
+        let commitments: Vec<Commitment> = openings.iter().map(Opening::commitment).collect();
+        let consistent = match &self.entries {
+            EntryCommitments::PerEntry(expected) => *expected == commitments,
+            EntryCommitments::Merkle(root) => merkle_tree(&commitments).root() == *root,
+        };
+        if !consistent {
+            return None;
+        }
+        let mut edges = Vec::new();
+        for ((a, b), opening) in entries(n, directed).zip(openings) {
+            if entry_bit(opening)? {
+                edges.push((a, b));
+            }
+        }
+        Some(Graph::new(n, edges))
+    }
+
+    // Checks that the number of per-entry commitments is right, since a Merkle root commits to its length
+    pub fn check(&self) -> Result<(), String> {
+        match &self.entries {
+            EntryCommitments::PerEntry(commitments) if commitments.len() != entry_count(self.n, self.directed) => {
+                Err(format!("Found {} entry commitments, expected {}", commitments.len(), entry_count(self.n, self.directed)))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+fn entry_bit(opening: &Opening) -> Option<bool> {
+    match opening.value[..] {
+        [0] => Some(false),
+        [1] => Some(true),
+        _ => None,
+    }
+}
+
+// ************ Homomorphically committed graph implementation ************
+
+// Hash commitments can only be checked by opening them, so a statement about a graph that stays hidden
+// for good, e.g. that it is connected, commits to it with Pedersen commitments C_ab = g^e_ab h^r instead,
+// laid out like an undirected CommittedGraph. Proofs about such a graph relabel it with a random
+// permutation pi and commit afresh to the relabeled matrix, D_pi(a)pi(b) = g^e_ab h^s. Revealing pi along
+// with every difference s - r shows that D_pi(a)pi(b) = C_ab h^(s - r) commits to the same bit as C_ab
+// without revealing it, since the differences are uniformly random, so D can then be partly opened
+// instead of C without linking what is opened to the original labels. Nothing here shows that an entry
+// commits to a bit, which opening it does, so a proof that only opens sums of entries has to prove that
+// every entry is a bit as well.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PedersenGraph {
+    pub params: PedersenParameters,
+    pub n: u32,
+    pub commitments: Vec<PedersenCommitment>,
+}
+
+// The committed graph, and openings of its commitments in the same order
+pub struct PedersenGraphWitness {
+    pub graph: Graph,
+    pub openings: Vec<PedersenOpening>,
+}
+
+// Fresh commitments to a random relabeling of a committed graph, with the values and randomness behind them
+pub struct Relabeling {
+    pub permutation: Permutation,
+    pub commitments: Vec<PedersenCommitment>,
+    // Values committed to in each relabeled entry, which are the witness's bits for an honest prover
+    pub values: Secret<Vec<BigUint>>,
+    pub randomness: Secret<Vec<BigUint>>,
+}
+
+impl PedersenGraph {
+    // Commits to the graph with edge directions ignored, returning the commitments along with the witness
+    pub fn commit(params: &PedersenParameters, graph: &Graph) -> (PedersenGraph, PedersenGraphWitness) {
+        let (commitments, openings) = pairs(graph.n).map(|(a, b)| params.commit(&BigUint::from(has_entry(graph, false, a, b) as u32))).unzip();
+        (PedersenGraph { params: params.clone(), n: graph.n, commitments }, PedersenGraphWitness { graph: graph.clone(), openings })
+    }
+
+    pub fn relabel(&self, witness: &PedersenGraphWitness) -> Relabeling {
+        let n = self.n;
+        let permutation = Permutation::random(n, &mut checked_thread_rng());
+        let inverse = permutation.invert();
+        let (commitments, (values, randomness)): (Vec<_>, (Vec<_>, Vec<_>)) = pairs(n).map(|(a, b)| {
+            let s = self.params.group.random_exponent();
+            let value = witness.openings[entry_index(n, false, inverse.apply(a), inverse.apply(b))].value.clone();
+            (self.params.commit_with_randomness(&value, &s), (value, s))
+        }).unzip();
+        Relabeling { permutation, commitments, values: Secret::new(values), randomness: Secret::new(randomness) }
+    }
+
+    // Differences s - r between the randomness of each relabeled entry and of the original, in the order
+    // of the original entries, where the permutation takes this graph to the relabeled one. That is the
+    // relabeling's own permutation for the graph it relabeled, but the relabeled graph is also a relabeling
+    // of any graph isomorphic to that one.
+    pub fn differences(&self, witness: &PedersenGraphWitness, permutation: &Permutation, relabeling: &Relabeling) -> Vec<BigUint> {
+        let (n, q) = (self.n, &self.params.group.q);
+        pairs(n).zip(&witness.openings).map(|((a, b), opening)| {
+            let s = &relabeling.randomness[entry_index(n, false, permutation.apply(a), permutation.apply(b))];
+            (s + q - &*opening.randomness % q) % q
+        }).collect()
+    }
+
+    // Checks that there is a commitment in the group for every entry
+    pub fn check_commitments(&self, commitments: &[PedersenCommitment]) -> Result<(), String> {
+        check_count(commitments.len(), entry_count(self.n, false), "commitments")?;
+        commitments.iter().try_for_each(|x| self.params.group.check_element(&x.c))
+    }
+
+    // Checks that the relabeled commitments commit to this graph relabeled by the permutation
+    pub fn check_relabeling(&self, relabeled: &[PedersenCommitment], permutation: &Permutation, differences: &[BigUint]) -> bool {
+        let (n, p) = (self.n, &self.params.group.p);
+        permutation.n() == n && relabeled.len() == self.commitments.len() && differences.len() == self.commitments.len()
+            && pairs(n).zip(self.commitments.iter().zip(differences)).all(|((a, b), (c, d))| {
+                relabeled[entry_index(n, false, permutation.apply(a), permutation.apply(b))].c == &c.c * mod_exp(&self.params.h, d, p) % p
+            })
+    }
+}
+
+#[test]
+fn test_entry_index() {
+    let indices: Vec<usize> = pairs(5).map(|(a, b)| entry_index(5, false, b, a)).collect();
+    assert_eq!(indices, (0..10).collect::<Vec<_>>());
+    assert_eq!(entries(3, true).map(|(a, b)| entry_index(3, true, a, b)).collect::<Vec<_>>(), (0..9).collect::<Vec<_>>());
+    assert_eq!(entry_count(5, false), 10);
+    // Edges without an entry have no index, even where a * n + b would overflow a u32
+    assert_eq!(checked_entry_index(5, false, 2, 2), None);
+    assert_eq!(checked_entry_index(5, true, 5, 0), None);
+    assert_eq!(checked_entry_index(u32::MAX, true, u32::MAX - 1, 1), Some((u32::MAX as usize - 1) * u32::MAX as usize + 1));
+}
+
+#[test]
+fn test_committed_graph() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (2, 3), (3, 0)]);
+    for scheme in [GraphCommitmentScheme::PerEntry, GraphCommitmentScheme::Merkle].iter().cloned() {
+        let committed = CommittedGraph::commit(&graph, true, scheme);
+        let commitment = &committed.commitment;
+        assert_eq!(commitment.check(), Ok(()));
+        assert_eq!(commitment.verify_entry(&committed.open(1, 2)), Some(true));
+        assert_eq!(commitment.verify_entry(&committed.open(2, 1)), Some(false));
+        // An opening can't be passed off as another entry's, or as a different value
+        let moved = EntryOpening { a: 2, b: 1, ..committed.open(1, 2) };
+        assert_eq!(commitment.verify_entry(&moved), None);
+        let mut flipped = committed.open(2, 1);
+        flipped.opening.value = vec![1];
+        assert_eq!(commitment.verify_entry(&flipped), None);
+        assert_eq!(commitment.verify_all(&committed.open_all()), Some(graph.clone()));
+        let mut openings = committed.open_all();
+        openings.swap(1, 2);
+        assert_eq!(commitment.verify_all(&openings), None);
+    }
+
+    // Undirected commitments have one entry per pair, whichever way it is asked for
+    let committed = CommittedGraph::commit(&graph, false, GraphCommitmentScheme::Merkle);
+    assert_eq!(committed.commitment.verify_entry(&committed.open(0, 3)), Some(true));
+    assert_eq!(committed.commitment.verify_entry(&committed.open(3, 0)), Some(true));
+    assert_eq!(committed.commitment.verify_entry(&committed.open(1, 3)), Some(false));
+    assert_eq!(committed.commitment.verify_all(&committed.open_all()), Some(Graph::new(4, vec![(0, 1), (1, 2), (2, 3), (0, 3)])));
+}
+
+#[test]
+fn test_relabeling() {
+    let params = PedersenParameters::generate(64);
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (3, 1)]);
+    let (committed, witness) = PedersenGraph::commit(&params, &graph);
+    let relabeling = committed.relabel(&witness);
+    let differences = committed.differences(&witness, &relabeling.permutation, &relabeling);
+    assert!(committed.check_relabeling(&relabeling.commitments, &relabeling.permutation, &differences));
+    // A permutation taking the center of the star elsewhere, or commitments to another graph, don't match
+    let mut swapped = relabeling.permutation.as_slice().to_vec();
+    swapped.swap(0, 1);
+    let other = Permutation::new(swapped).unwrap();
+    assert!(!committed.check_relabeling(&relabeling.commitments, &other, &differences));
+    let (different, _) = PedersenGraph::commit(&params, &Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]));
+    assert!(!different.check_relabeling(&relabeling.commitments, &relabeling.permutation, &differences));
+}
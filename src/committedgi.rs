@@ -0,0 +1,233 @@
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::committed::{entry_count, PedersenGraph, PedersenGraphWitness, Relabeling};
+use crate::graph::check_count;
+use crate::params::serde_biguints;
+use crate::pedersen::PedersenCommitment;
+use crate::permutation::Permutation;
+use crate::permutationcommitment::{PermutationCommitment, PermutationOpening};
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use rand::thread_rng;
+#[cfg(test)]
+use crate::{run_interactive_proof, graph::Graph, params::Parameters, pedersen::PedersenParameters};
+
+// ************ Committed graph isomorphism implementation ************
+
+// The GI protocol with both graphs hidden: the verifier holds two PedersenGraphs and a
+// PermutationCommitment to an isomorphism sigma, all under the same parameters, and the prover shows that
+// the committed sigma takes the committed g0 to the committed g1 without opening any of them. Committing
+// to sigma lets later proofs refer to the same isomorphism, e.g. to show that it fixes some vertex.
+//
+// Each repetition follows the public GI protocol, with the relabeled graph committed to rather than sent.
+// The prover commits afresh to g0 relabeled by a random rho, and to the permutations rho and tau, where
+// tau is sigma^-1 followed by rho and so takes g1 to the same relabeling. Since rho is sigma followed by
+// tau, their matrices satisfy R = S T. On challenge b = 0 the prover opens R, shows that the relabeled
+// commitments relabel g0 by rho, and shows that T rearranges the entries of S as S^-1 R does, with
+// T_ij committing to the same bit as S_rho^-1(j),i. On b = 1 it opens T instead, shows that they relabel
+// g1 by tau, and that R rearranges S as S T does, with R_ij committing to the same bit as S_i,tau^-1(j).
+// The rearrangements are shown by randomness differences, as for the relabeled graph.
+//
+// Either opened permutation is uniformly random on its own and every difference is uniformly random, so
+// the verifier learns nothing about the graphs or sigma. A prover that could answer both challenges would
+// have opened R to rho and T to tau with S = R T^-1, so S commits to the permutation matrix of rho
+// followed by tau^-1, which takes g0 to the relabeling and back to g1. A prover whose committed sigma
+// isn't an isomorphism of the committed graphs is therefore caught with probability 1/2 per repetition.
+
+// Two graphs committed under the same parameters, and a commitment to an isomorphism between them
+pub struct CommittedGraphPair {
+    pub g0: PedersenGraph,
+    pub g1: PedersenGraph,
+    pub isomorphism: PermutationCommitment,
+}
+
+// Openings of both graphs, and of the isomorphism taking g0 to g1
+pub struct CommittedGraphPairWitness {
+    pub w0: PedersenGraphWitness,
+    pub w1: PedersenGraphWitness,
+    pub isomorphism: PermutationOpening,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CommittedGIProverMessage {
+    // Commitments to a random relabeling rho of g0, to rho, and to the isomorphism's inverse followed by rho
+    Commitments(Vec<PedersenCommitment>, PermutationCommitment, PermutationCommitment),
+    // Permutation taking gb to the relabeled graph with the randomness opening its commitment, the
+    // randomness differences for each entry of gb, and those rearranging the isomorphism into the other
+    // permutation's commitment
+    Relabeling(Permutation, #[serde(with = "serde_biguints")] Vec<BigUint>, #[serde(with = "serde_biguints")] Vec<BigUint>, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommittedGIVerifierMessage {
+    // Graph the prover must relate the relabeled commitments to
+    pub b: bool,
+}
+
+pub struct CommittedGIProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Relabeled graph committed to
+    pub relabeling: Option<Relabeling>,
+    // Openings of the commitments to rho and to tau
+    pub permutations: Option<(PermutationOpening, PermutationOpening)>,
+    pub instance: &'a CommittedGraphPair,
+    pub witness: &'a CommittedGraphPairWitness,
+}
+
+impl CommittedGIProver<'_> {
+    pub fn new<'a>(instance: &'a CommittedGraphPair, witness: &'a CommittedGraphPairWitness) -> CommittedGIProver<'a> {
+        CommittedGIProver { r: 0, relabeling: None, permutations: None, instance, witness }
+    }
+}
+
+impl Prover for CommittedGIProver<'_> {
+    type ProverMessage = CommittedGIProverMessage;
+    type VerifierMessage = CommittedGIVerifierMessage;
+
+    fn handle(&mut self, msg: &CommittedGIVerifierMessage) -> (CommittedGIProverMessage, bool) {
+        self.r += 1;
+        let (instance, witness) = (self.instance, self.witness);
+        let params = &instance.g0.params;
+        let q = &params.group.q;
+        match (self.r, &self.relabeling, &self.permutations) {
+            // During the first round, the prover commits to a random relabeling of g0, and to the permutations
+            // taking g0 and g1 to it
+            (1, _, _) => {
+                let relabeling = instance.g0.relabel(&witness.w0);
+                let tau = witness.isomorphism.permutation.invert().compose(&relabeling.permutation);
+                let (rho_commitment, rho) = PermutationCommitment::commit(params, &relabeling.permutation);
+                let (tau_commitment, tau) = PermutationCommitment::commit(params, &tau);
+                let commitments = relabeling.commitments.clone();
+                self.relabeling = Some(relabeling);
+                self.permutations = Some((rho, tau));
+                (CommittedGIProverMessage::Commitments(commitments, rho_commitment, tau_commitment), false)
+            },
+            // During the second round, the prover shows that the commitments relabel g1, by way of g0, and that
+            // rho is the isomorphism followed by tau
+            (2, Some(relabeling), Some((rho, tau))) if msg.b => {
+                let differences = instance.g1.differences(&witness.w1, &tau.permutation, relabeling);
+                let inverse = tau.permutation.invert();
+                let rearranged = witness.isomorphism.differences(q, rho, |i, j| (i, inverse.apply(j)));
+                (CommittedGIProverMessage::Relabeling(tau.permutation.clone(), tau.randomness(), differences, rearranged), false)
+            },
+            // Or that they relabel g0, and that tau is the isomorphism's inverse followed by rho
+            (2, Some(relabeling), Some((rho, tau))) => {
+                let differences = instance.g0.differences(&witness.w0, &rho.permutation, relabeling);
+                let inverse = rho.permutation.invert();
+                let rearranged = witness.isomorphism.differences(q, tau, |i, j| (inverse.apply(j), i));
+                (CommittedGIProverMessage::Relabeling(rho.permutation.clone(), rho.randomness(), differences, rearranged), false)
+            },
+            // After relabeling, the prover sends a message to terminate the interaction
+            _ => (CommittedGIProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct CommittedGIVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen bit
+    pub b: bool,
+    // Commitments received from prover
+    pub commitments: Vec<PedersenCommitment>,
+    // Commitments to rho and to tau received from prover
+    pub permutations: Option<(PermutationCommitment, PermutationCommitment)>,
+    pub instance: &'a CommittedGraphPair,
+}
+
+impl CommittedGIVerifier<'_> {
+    pub fn new(instance: &CommittedGraphPair) -> CommittedGIVerifier<'_> {
+        CommittedGIVerifier { r: 0, b: false, commitments: Vec::new(), permutations: None, instance }
+    }
+}
+
+impl Verifier for CommittedGIVerifier<'_> {
+    type ProverMessage = CommittedGIProverMessage;
+    type VerifierMessage = CommittedGIVerifierMessage;
+
+    fn init(&mut self) -> CommittedGIVerifierMessage {
+        CommittedGIVerifierMessage { b: false }
+    }
+
+    fn validate(&self, msg: &CommittedGIProverMessage) -> Result<(), String> {
+        let (g0, g1) = (&self.instance.g0, &self.instance.g1);
+        if g0.n != g1.n || g0.params != g1.params {
+            return Err("committed graphs differ in size or parameters".to_string());
+        }
+        let (params, n) = (&g0.params, g0.n);
+        match (self.r + 1, msg) {
+            (1, CommittedGIProverMessage::Commitments(commitments, rho, tau)) => {
+                self.instance.isomorphism.check_commitments(params, n)?;
+                g0.check_commitments(commitments)?;
+                rho.check_commitments(params, n)?;
+                tau.check_commitments(params, n)
+            },
+            (1, _) => Err("expected commitments".to_string()),
+            (_, CommittedGIProverMessage::Relabeling(permutation, randomness, differences, rearranged)) => {
+                check_count(permutation.n() as usize, n as usize, "vertices in permutation")?;
+                check_count(randomness.len(), n as usize * n as usize, "randomness of permutation commitment")?;
+                check_count(differences.len(), entry_count(n, false), "randomness differences")?;
+                check_count(rearranged.len(), n as usize * n as usize, "randomness differences of isomorphism")
+            },
+            _ => Err("expected a relabeling".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &CommittedGIProverMessage) -> (CommittedGIVerifierMessage, bool) {
+        self.r += 1;
+        match (self.r, msg, &self.permutations) {
+            (1, CommittedGIProverMessage::Commitments(commitments, rho, tau), _) => {
+                self.commitments = commitments.clone();
+                self.permutations = Some((rho.clone(), tau.clone()));
+                self.b = checked_thread_rng().gen();
+                (CommittedGIVerifierMessage { b: self.b }, false)
+            },
+            // The relabeled commitments must commit to the challenged graph, under the opened permutation, and the
+            // other permutation's commitment must rearrange the isomorphism's accordingly
+            (_, CommittedGIProverMessage::Relabeling(permutation, randomness, differences, rearranged), Some((rho, tau))) => {
+                let instance = self.instance;
+                let params = &instance.g0.params;
+                let inverse = permutation.invert();
+                let accept = if self.b {
+                    tau.check_opening(params, permutation, randomness)
+                        && instance.g1.check_relabeling(&self.commitments, permutation, differences)
+                        && instance.isomorphism.check_rearranged(params, rho, |i, j| (i, inverse.apply(j)), rearranged)
+                } else {
+                    rho.check_opening(params, permutation, randomness)
+                        && instance.g0.check_relabeling(&self.commitments, permutation, differences)
+                        && instance.isomorphism.check_rearranged(params, tau, |i, j| (inverse.apply(j), i), rearranged)
+                };
+                (CommittedGIVerifierMessage { b: self.b }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_committed_gi_proof() {
+    let params = PedersenParameters::generate(64);
+    let g0 = Graph::new(5, vec![(0, 1), (1, 2), (2, 0), (3, 2), (4, 3)]);
+    let isomorphism = Permutation::random(5, &mut thread_rng());
+    let play = |g1: &Graph, sigma: &Permutation| {
+        let ((c0, w0), (c1, w1)) = (PedersenGraph::commit(&params, &g0), PedersenGraph::commit(&params, g1));
+        let (commitment, opening) = PermutationCommitment::commit(&params, sigma);
+        let instance = CommittedGraphPair { g0: c0, g1: c1, isomorphism: commitment };
+        let witness = CommittedGraphPairWitness { w0, w1, isomorphism: opening };
+        run_interactive_proof(&mut CommittedGIProver::new(&instance, &witness), &mut CommittedGIVerifier::new(&instance))
+    };
+    assert!((0..10).all(|_| play(&g0.permute(&isomorphism), &isomorphism)));
+    let rejected = |g1: &Graph, sigma: &Permutation| (0..60).filter(|_| !play(g1, sigma)).count();
+    // A graph with the same degree sequence, but a 4-cycle instead of a triangle
+    let other = Graph::new(5, vec![(0, 1), (1, 2), (2, 3), (3, 0), (4, 0)]);
+    let caught = rejected(&other.permute(&isomorphism), &isomorphism);
+    assert!(caught > 15 && caught < 45);
+    // Isomorphic graphs, but with a commitment to a permutation that isn't an isomorphism between them
+    let caught = rejected(&g0.permute(&isomorphism), &Permutation::new(vec![4, 3, 2, 1, 0]).unwrap().compose(&isomorphism));
+    assert!(caught > 15 && caught < 45);
+}
@@ -0,0 +1,546 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::LocalKey;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Serialize, Deserialize};
+
+// ************ Library configuration ************
+
+// Process-wide settings for library users that embed zklib, e.g. in a server. Each setting has a setter
+// that can be called at any time and applies to every protocol run that starts afterwards.
+
+static CONSTANT_TIME: AtomicBool = AtomicBool::new(false);
+
+// Whether provers apply their secret permutations with Graph::permute_constant_time, whose memory
+// accesses don't depend on the permutation, at a cost of O(n^3 / 64) word operations rather than O(m)
+pub fn set_constant_time(enabled: bool) {
+    CONSTANT_TIME.store(enabled, Ordering::Relaxed);
+}
+
+pub fn constant_time() -> bool {
+    CONSTANT_TIME_OVERRIDE.with(Cell::get).unwrap_or_else(|| CONSTANT_TIME.load(Ordering::Relaxed))
+}
+
+thread_local! {
+    static CONSTANT_TIME_OVERRIDE: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+// Runs f with constant-time permutations on or off on the calling thread only, e.g. in a test, leaving
+// the process-wide setting alone
+pub fn with_constant_time<T>(enabled: bool, f: impl FnOnce() -> T) -> T {
+    overriding(&CONSTANT_TIME_OVERRIDE, enabled, f)
+}
+
+// Runs f with the calling thread's override of a setting replaced by value, restoring the previous
+// override even if f panics
+fn overriding<V: Copy + 'static, T>(setting: &'static LocalKey<Cell<Option<V>>>, value: V, f: impl FnOnce() -> T) -> T {
+    struct Restore<V: Copy + 'static>(&'static LocalKey<Cell<Option<V>>>, Option<V>);
+    impl<V: Copy + 'static> Drop for Restore<V> {
+        fn drop(&mut self) {
+            self.0.with(|setting| setting.set(self.1));
+        }
+    }
+    let _restore = Restore(setting, setting.with(|setting| setting.replace(Some(value))));
+    f()
+}
+
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// Largest statement, by Statement::memory_footprint, that statement::prove runs a protocol on, so that a
+// process handling statements it was sent can bound the memory they take. None removes the limit.
+pub fn set_memory_limit(bytes: Option<usize>) {
+    MEMORY_LIMIT.store(bytes.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+pub fn memory_limit() -> Option<usize> {
+    Some(MEMORY_LIMIT_OVERRIDE.with(Cell::get).unwrap_or_else(|| MEMORY_LIMIT.load(Ordering::Relaxed))).filter(|&bytes| bytes != usize::MAX)
+}
+
+thread_local! {
+    static MEMORY_LIMIT_OVERRIDE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Runs f with the memory limit set on the calling thread only, like with_constant_time
+pub fn with_memory_limit<T>(bytes: Option<usize>, f: impl FnOnce() -> T) -> T {
+    overriding(&MEMORY_LIMIT_OVERRIDE, bytes.unwrap_or(usize::MAX), f)
+}
+
+static REQUIRE_CRYPTO_RNG: AtomicBool = AtomicBool::new(false);
+
+// Whether randomness::HealthCheckedRng refuses to wrap RNGs that aren't known to be cryptographic, e.g.
+// a seeded one left in from an experiment, and the library's own randomness is health checked, which
+// deployments turn on as a production mode
+pub fn set_require_crypto_rng(required: bool) {
+    REQUIRE_CRYPTO_RNG.store(required, Ordering::Relaxed);
+}
+
+pub fn require_crypto_rng() -> bool {
+    REQUIRE_CRYPTO_RNG.load(Ordering::Relaxed)
+}
+
+static NARRATION: AtomicBool = AtomicBool::new(true);
+
+// Whether provers, verifiers and experiments describe each step on stdout as they run, which the CLI turns
+// off when stdout has to stay machine-readable
+pub fn set_narration(enabled: bool) {
+    NARRATION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn narration() -> bool {
+    NARRATION.load(Ordering::Relaxed) && !NARRATION_SUPPRESSED.with(Cell::get)
+}
+
+thread_local! {
+    static NARRATION_SUPPRESSED: Cell<bool> = const { Cell::new(false) };
+}
+
+// Runs f with narration off on the calling thread only, e.g. while timing a protocol, leaving the setting
+// of every other thread alone and restoring this thread's even if f panics
+pub fn without_narration<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NARRATION_SUPPRESSED.with(|suppressed| suppressed.set(self.0));
+        }
+    }
+    let _restore = Restore(NARRATION_SUPPRESSED.with(|suppressed| suppressed.replace(true)));
+    f()
+}
+
+static PARALLELISM: AtomicUsize = AtomicUsize::new(0);
+
+// Most threads the library's parallel work, such as experiment trials and knowledge extraction, runs on,
+// so that a server embedding zklib keeps cores for itself. 0, the default, means one thread per core.
+// Isomorphism searches and verifiers run on the calling thread, so they always take a single core.
+pub fn set_parallelism(threads: usize) {
+    PARALLELISM.store(threads, Ordering::Relaxed);
+}
+
+pub fn parallelism() -> usize {
+    PARALLELISM_OVERRIDE.with(Cell::get).unwrap_or_else(|| PARALLELISM.load(Ordering::Relaxed))
+}
+
+thread_local! {
+    static PARALLELISM_OVERRIDE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Runs f with the thread limit set on the calling thread only, like with_constant_time. Work f hands to a
+// pool is bounded when it starts, but the pool's own threads see the process-wide limit.
+pub fn with_parallelism<T>(threads: usize, f: impl FnOnce() -> T) -> T {
+    overriding(&PARALLELISM_OVERRIDE, threads, f)
+}
+
+// Threads a single call may use, on top of the process-wide limit from set_parallelism
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParallelismHint {
+    // As many as set_parallelism allows
+    #[default]
+    Default,
+    // A single thread
+    Sequential,
+    // At most the given number, and no more than set_parallelism allows
+    Threads(usize),
+}
+
+impl ParallelismHint {
+    // Number of threads the call may use, or None for rayon's global pool of one thread per core
+    pub fn threads(self) -> Option<usize> {
+        let limit = parallelism();
+        match self {
+            ParallelismHint::Default => Some(limit).filter(|&threads| threads != 0),
+            ParallelismHint::Sequential => Some(1),
+            ParallelismHint::Threads(threads) if limit == 0 => Some(threads.max(1)),
+            ParallelismHint::Threads(threads) => Some(threads.clamp(1, limit)),
+        }
+    }
+}
+
+// Runs f with rayon's parallel iterators bounded to the number of threads the hint allows. A pool is
+// built the first time each size is asked for and kept for later calls.
+pub(crate) fn install<R: Send>(hint: ParallelismHint, f: impl FnOnce() -> R + Send) -> R {
+    static POOLS: OnceLock<Mutex<HashMap<usize, Arc<ThreadPool>>>> = OnceLock::new();
+    match hint.threads() {
+        None => f(),
+        Some(threads) => {
+            let pool = POOLS.get_or_init(Default::default).lock().unwrap().entry(threads).or_insert_with(|| {
+                Arc::new(ThreadPoolBuilder::new().num_threads(threads).build().expect("Failed to build thread pool"))
+            }).clone();
+            pool.install(f)
+        }
+    }
+}
+
+// ************ Logging implementation ************
+
+// How much the CLI and the verification service report as they run, from least to most
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    // Only failures of the service itself
+    Error,
+    // Also requests the service refused
+    Warn,
+    // Also what the service loaded on startup
+    #[default]
+    Info,
+    // Also every request
+    Debug,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogLevel, String> {
+        match s {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!("unknown log level {}, expected one of error, warn, info, debug", s)),
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level() -> LogLevel {
+    [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug][LOG_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+// Writes the message to stderr if the log level lets messages of its level through
+pub fn log(level: LogLevel, message: &str) {
+    if level <= log_level() {
+        eprintln!("[{}] {}", level, message);
+    }
+}
+
+// ************ Configuration file implementation ************
+
+// A deployment describes itself in a TOML file, zklib.toml by default, rather than in a long list of flags.
+// Every setting is optional, and is taken from the first of these that has it: the command line flag, an
+// environment variable, the file, and the built-in default. Environment variables are named after the
+// setting's key, e.g. ZKLIB_THREADS for threads and ZKLIB_SERVER_LIMITS_MAX_BODY_BYTES for max_body_bytes
+// in [server.limits], with lists separated by commas.
+//
+//     log_level = "warn"
+//     threads = 4
+//
+//     [server]
+//     listen = "0.0.0.0:8443"
+//     transport = "https"
+//     tls_cert = "/etc/zklib/cert.pem"
+//     tls_key = "/etc/zklib/key.pem"
+//     protocols = ["schnorr-signature", "gi"]
+//
+//     [server.limits]
+//     max_body_bytes = 65536
+//     requests_per_second = 2.5
+
+// Name of the file looked for in the working directory when no other file is given
+pub const CONFIG_FILE: &str = "zklib.toml";
+
+// Environment variable naming the file to load instead
+pub const CONFIG_VAR: &str = "ZKLIB_CONFIG";
+
+// Settings for every command
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub log_level: Option<LogLevel>,
+    // See set_parallelism, set_constant_time and set_memory_limit
+    pub threads: Option<usize>,
+    pub constant_time: Option<bool>,
+    pub memory_limit: Option<usize>,
+    pub server: ServerConfig,
+}
+
+// How the verification service listens for requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpTransport {
+    #[default]
+    Http,
+    // HTTP over TLS, with the certificate chain and key in tls_cert and tls_key
+    Https,
+}
+
+impl FromStr for HttpTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<HttpTransport, String> {
+        match s {
+            "http" => Ok(HttpTransport::Http),
+            "https" => Ok(HttpTransport::Https),
+            _ => Err(format!("unknown transport {}, expected http or https", s)),
+        }
+    }
+}
+
+// PEM files the service serves HTTPS with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsPaths {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// Settings for zklib serve, each of which has a flag of the same name unless it has no default
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: Option<String>,
+    pub transport: Option<HttpTransport>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // Protocols the service verifies and runs sessions for, or all of them if missing
+    pub protocols: Option<Vec<String>>,
+    pub state_dir: Option<PathBuf>,
+    pub session_ttl: Option<u64>,
+    pub audit_log: Option<PathBuf>,
+    pub registry: Option<PathBuf>,
+    pub identities: Option<PathBuf>,
+    pub health_port: Option<u16>,
+    // Seconds sessions under way may take to finish once the server is asked to stop
+    pub drain_timeout: Option<u64>,
+    pub limits: LimitsConfig,
+}
+
+// Overrides of the service's default limits, named as in server::Limits
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_body_bytes: Option<usize>,
+    pub max_in_flight_per_ip: Option<usize>,
+    pub requests_per_second: Option<f64>,
+    pub burst: Option<u32>,
+    pub max_modulus_bits: Option<u64>,
+    pub max_statements: Option<usize>,
+    pub max_range_bits: Option<u32>,
+    pub max_vertices: Option<u32>,
+    pub max_instance_bytes: Option<usize>,
+    pub max_repetitions: Option<u32>,
+    pub max_sessions: Option<usize>,
+    pub max_registered: Option<usize>,
+    pub work_bits: Option<u32>,
+}
+
+// Parses the variable if it is set, naming it in the error if it doesn't parse
+fn parse_var<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>, String> where T::Err: fmt::Display {
+    var(name).map(|value| value.parse().map_err(|e| format!("invalid {}={}: {}", name, value, e))).transpose()
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<ConfigFile, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        ConfigFile::from_toml(&contents).map_err(|e| format!("invalid configuration in {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<ConfigFile, String> {
+        let config: ConfigFile = toml::from_str(contents).map_err(|e| e.message().to_string())?;
+        config.server.tls()?;
+        Ok(config)
+    }
+
+    // Loads the given file, or the one named by ZKLIB_CONFIG, or zklib.toml if the working directory has
+    // one, and applies the environment's overrides. With no file at all, only the overrides are set.
+    pub fn discover(path: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> Result<ConfigFile, String> {
+        let path = path.map(Path::to_path_buf).or_else(|| var(CONFIG_VAR).map(PathBuf::from));
+        let config = match path {
+            Some(path) => ConfigFile::load(&path)?,
+            None if Path::new(CONFIG_FILE).exists() => ConfigFile::load(Path::new(CONFIG_FILE))?,
+            None => ConfigFile::default(),
+        };
+        config.with_env(var)
+    }
+
+    // Replaces every setting whose environment variable is set, given a way to read variables such as
+    // |name| std::env::var(name).ok()
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Result<ConfigFile, String> {
+        let var = &var;
+        self.log_level = parse_var(var, "ZKLIB_LOG_LEVEL")?.or(self.log_level);
+        self.threads = parse_var(var, "ZKLIB_THREADS")?.or(self.threads);
+        self.constant_time = parse_var(var, "ZKLIB_CONSTANT_TIME")?.or(self.constant_time);
+        self.memory_limit = parse_var(var, "ZKLIB_MEMORY_LIMIT")?.or(self.memory_limit);
+        let server = &mut self.server;
+        server.listen = var("ZKLIB_SERVER_LISTEN").or(server.listen.take());
+        server.transport = parse_var(var, "ZKLIB_SERVER_TRANSPORT")?.or(server.transport);
+        server.tls_cert = parse_var(var, "ZKLIB_SERVER_TLS_CERT")?.or(server.tls_cert.take());
+        server.tls_key = parse_var(var, "ZKLIB_SERVER_TLS_KEY")?.or(server.tls_key.take());
+        if let Some(protocols) = var("ZKLIB_SERVER_PROTOCOLS") {
+            server.protocols = Some(protocols.split(',').map(|protocol| protocol.trim().to_string()).filter(|protocol| !protocol.is_empty()).collect());
+        }
+        server.state_dir = parse_var(var, "ZKLIB_SERVER_STATE_DIR")?.or(server.state_dir.take());
+        server.session_ttl = parse_var(var, "ZKLIB_SERVER_SESSION_TTL")?.or(server.session_ttl);
+        server.audit_log = parse_var(var, "ZKLIB_SERVER_AUDIT_LOG")?.or(server.audit_log.take());
+        server.registry = parse_var(var, "ZKLIB_SERVER_REGISTRY")?.or(server.registry.take());
+        server.identities = parse_var(var, "ZKLIB_SERVER_IDENTITIES")?.or(server.identities.take());
+        server.health_port = parse_var(var, "ZKLIB_SERVER_HEALTH_PORT")?.or(server.health_port);
+        server.drain_timeout = parse_var(var, "ZKLIB_SERVER_DRAIN_TIMEOUT")?.or(server.drain_timeout);
+        let limits = &mut server.limits;
+        limits.max_body_bytes = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_BODY_BYTES")?.or(limits.max_body_bytes);
+        limits.max_in_flight_per_ip = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_IN_FLIGHT_PER_IP")?.or(limits.max_in_flight_per_ip);
+        limits.requests_per_second = parse_var(var, "ZKLIB_SERVER_LIMITS_REQUESTS_PER_SECOND")?.or(limits.requests_per_second);
+        limits.burst = parse_var(var, "ZKLIB_SERVER_LIMITS_BURST")?.or(limits.burst);
+        limits.max_modulus_bits = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_MODULUS_BITS")?.or(limits.max_modulus_bits);
+        limits.max_statements = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_STATEMENTS")?.or(limits.max_statements);
+        limits.max_range_bits = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_RANGE_BITS")?.or(limits.max_range_bits);
+        limits.max_vertices = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_VERTICES")?.or(limits.max_vertices);
+        limits.max_instance_bytes = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_INSTANCE_BYTES")?.or(limits.max_instance_bytes);
+        limits.max_repetitions = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_REPETITIONS")?.or(limits.max_repetitions);
+        limits.max_sessions = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_SESSIONS")?.or(limits.max_sessions);
+        limits.max_registered = parse_var(var, "ZKLIB_SERVER_LIMITS_MAX_REGISTERED")?.or(limits.max_registered);
+        limits.work_bits = parse_var(var, "ZKLIB_SERVER_LIMITS_WORK_BITS")?.or(limits.work_bits);
+        self.server.tls()?;
+        Ok(self)
+    }
+
+    // Sets the process-wide settings the file has, leaving the others as they are
+    pub fn apply(&self) {
+        if let Some(level) = self.log_level {
+            set_log_level(level);
+        }
+        if let Some(threads) = self.threads {
+            set_parallelism(threads);
+        }
+        if let Some(enabled) = self.constant_time {
+            set_constant_time(enabled);
+        }
+        if self.memory_limit.is_some() {
+            set_memory_limit(self.memory_limit);
+        }
+    }
+}
+
+impl ServerConfig {
+    // Files to serve HTTPS with, or None to serve plain HTTP. The certificate and key only make sense
+    // together and over HTTPS, so anything else is a mistake in the configuration.
+    pub fn tls(&self) -> Result<Option<TlsPaths>, String> {
+        match (self.transport.unwrap_or_default(), &self.tls_cert, &self.tls_key) {
+            (HttpTransport::Https, Some(cert), Some(key)) => Ok(Some(TlsPaths { cert: cert.clone(), key: key.clone() })),
+            (HttpTransport::Https, _, _) => Err("transport https needs both tls_cert and tls_key".to_string()),
+            (HttpTransport::Http, None, None) => Ok(None),
+            (HttpTransport::Http, _, _) => Err("tls_cert and tls_key are only used with transport https".to_string()),
+        }
+    }
+}
+
+#[test]
+fn test_parallelism_hint() {
+    let hints = [ParallelismHint::Default, ParallelismHint::Sequential, ParallelismHint::Threads(8), ParallelismHint::Threads(0)];
+    with_parallelism(2, || {
+        assert_eq!(hints.map(ParallelismHint::threads), [Some(2), Some(1), Some(2), Some(1)]);
+        assert_eq!(install(ParallelismHint::Default, rayon::current_num_threads), 2);
+    });
+    with_parallelism(0, || {
+        assert_eq!(hints.map(ParallelismHint::threads), [None, Some(1), Some(8), Some(1)]);
+        assert_eq!(install(ParallelismHint::Sequential, rayon::current_num_threads), 1);
+    });
+    // Other threads keep the process-wide limit
+    let limit = parallelism();
+    with_parallelism(limit + 1, || assert_eq!(std::thread::spawn(parallelism).join().unwrap(), limit));
+}
+
+#[test]
+fn test_with_constant_time() {
+    let enabled = constant_time();
+    assert!(with_constant_time(!enabled, || constant_time() != enabled && with_constant_time(enabled, constant_time) == enabled));
+    assert_eq!(constant_time(), enabled);
+    // Other threads keep the process-wide setting, and a panic restores the calling thread's
+    with_constant_time(!enabled, || assert_eq!(std::thread::spawn(constant_time).join().unwrap(), enabled));
+    assert!(std::panic::catch_unwind(|| with_constant_time(!enabled, || panic!("interrupted"))).is_err());
+    assert_eq!(constant_time(), enabled);
+}
+
+#[test]
+fn test_with_memory_limit() {
+    let limit = memory_limit();
+    assert_eq!(with_memory_limit(Some(100), || (memory_limit(), with_memory_limit(None, memory_limit))), (Some(100), None));
+    assert_eq!(memory_limit(), limit);
+    with_memory_limit(Some(100), || assert_eq!(std::thread::spawn(memory_limit).join().unwrap(), limit));
+}
+
+#[test]
+fn test_without_narration() {
+    let enabled = narration();
+    assert!(without_narration(|| !narration() && without_narration(|| !narration())));
+    assert_eq!(narration(), enabled);
+    // Other threads keep narrating, and a panic restores the calling thread's setting
+    without_narration(|| assert_eq!(std::thread::spawn(narration).join().unwrap(), enabled));
+    assert!(std::panic::catch_unwind(|| without_narration(|| panic!("interrupted"))).is_err());
+    assert_eq!(narration(), enabled);
+}
+
+#[test]
+fn test_config_file() {
+    let contents = r#"
+        log_level = "warn"
+        threads = 4
+
+        [server]
+        listen = "0.0.0.0:8443"
+        transport = "https"
+        tls_cert = "cert.pem"
+        tls_key = "key.pem"
+        protocols = ["schnorr-signature", "gi"]
+
+        [server.limits]
+        max_body_bytes = 65536
+        requests_per_second = 2.5
+    "#;
+    let config = ConfigFile::from_toml(contents).unwrap();
+    assert_eq!(config.log_level, Some(LogLevel::Warn));
+    assert_eq!((config.threads, config.constant_time), (Some(4), None));
+    assert_eq!(config.server.tls(), Ok(Some(TlsPaths { cert: PathBuf::from("cert.pem"), key: PathBuf::from("key.pem") })));
+    assert_eq!(config.server.protocols, Some(vec!["schnorr-signature".to_string(), "gi".to_string()]));
+    assert_eq!((config.server.limits.max_body_bytes, config.server.limits.requests_per_second), (Some(65536), Some(2.5)));
+
+    // Environment variables take precedence over the file
+    let vars: HashMap<&str, &str> = [
+        ("ZKLIB_LOG_LEVEL", "debug"),
+        ("ZKLIB_SERVER_LISTEN", "127.0.0.1:9000"),
+        ("ZKLIB_SERVER_PROTOCOLS", "designated, gi"),
+        ("ZKLIB_SERVER_LIMITS_MAX_BODY_BYTES", "1024"),
+    ].iter().copied().collect();
+    let config = config.with_env(|name| vars.get(name).map(|value| value.to_string())).unwrap();
+    assert_eq!(config.log_level, Some(LogLevel::Debug));
+    assert_eq!(config.threads, Some(4));
+    assert_eq!(config.server.listen.as_deref(), Some("127.0.0.1:9000"));
+    assert_eq!(config.server.protocols, Some(vec!["designated".to_string(), "gi".to_string()]));
+    assert_eq!((config.server.limits.max_body_bytes, config.server.limits.requests_per_second), (Some(1024), Some(2.5)));
+    let error = config.with_env(|name| (name == "ZKLIB_THREADS").then(|| "many".to_string())).unwrap_err();
+    assert!(error.contains("ZKLIB_THREADS=many"));
+}
+
+#[test]
+fn test_invalid_config_file() {
+    assert_eq!(ConfigFile::from_toml(""), Ok(ConfigFile::default()));
+    // Misspelled keys are caught rather than ignored
+    assert!(ConfigFile::from_toml("[server]\nlisten_on = \"0.0.0.0:80\"").is_err());
+    assert!(ConfigFile::from_toml("log_level = \"loud\"").is_err());
+    assert!(ConfigFile::from_toml("[server]\ntransport = \"https\"\ntls_cert = \"cert.pem\"").is_err());
+    assert!(ConfigFile::from_toml("[server]\ntls_key = \"key.pem\"").is_err());
+    let https = |name: &str| (name == "ZKLIB_SERVER_TRANSPORT").then(|| "https".to_string());
+    assert!(ConfigFile::default().with_env(https).is_err());
+    assert_eq!("debug".parse::<LogLevel>().map(|level| level.to_string()), Ok("debug".to_string()));
+}
@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::committed::{entry_count, entry_index, has_entry, PedersenGraph, PedersenGraphWitness, Relabeling};
+use crate::graph::{check_count, Graph};
+use crate::params::serde_biguints;
+use crate::pedersen::PedersenCommitment;
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters, pedersen::PedersenParameters};
+
+// ************ Committed graph connectivity implementation ************
+
+// The graph protocols above prove statements about public graphs. Here the verifier only holds a
+// PedersenGraph, commitments to the entries of an undirected graph's adjacency matrix, and the prover shows
+// that the committed graph is connected without opening them. Each repetition follows Blum's Hamiltonicity
+// protocol: the prover commits afresh to a random relabeling of the graph, and on challenge 0 shows that
+// it relabeled the committed graph, while on challenge 1 it opens the entries of the relabeled matrix on a
+// spanning tree to 1, and the verifier checks that the n - 1 opened pairs connect every vertex.
+//
+// A prover whose committed graph is disconnected can answer only one of the challenges, so it is caught
+// with probability 1/2 per repetition. The protocol is not zero knowledge: the tree is relabeled at
+// random, but its shape is revealed, and the shape of a breadth-first tree says a lot about the graph.
+// The root's children are all of its neighbors, so the tree reveals the root's degree, and its depth is
+// the root's eccentricity. The prover picks a fresh random root each repetition, so over many repetitions
+// the verifier samples the degrees and eccentricities of random vertices, and with them e.g. the degree
+// distribution and the diameter. All the verifier can't learn is which committed vertex is which.
+
+// Edges of a breadth-first spanning tree of the component containing the root, ignoring edge directions
+fn spanning_tree(graph: &Graph, root: u32) -> Vec<(u32, u32)> {
+    let mut visited = vec![false; graph.n as usize];
+    let mut queue = VecDeque::from(vec![root]);
+    let mut tree = Vec::new();
+    visited[root as usize] = true;
+    while let Some(a) = queue.pop_front() {
+        for b in 0..graph.n {
+            if !visited[b as usize] && has_entry(graph, false, a, b) {
+                visited[b as usize] = true;
+                tree.push((a, b));
+                queue.push_back(b);
+            }
+        }
+    }
+    tree
+}
+
+// Checks that n - 1 edges connect all n vertices, which makes them a spanning tree
+fn spans(n: u32, edges: &[(u32, u32)]) -> bool {
+    let mut parent: Vec<usize> = (0..n as usize).collect();
+    fn find(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+    edges.len() + 1 == n as usize && edges.iter().all(|&(a, b)| {
+        if a >= n || b >= n {
+            return false;
+        }
+        let (x, y) = (find(&mut parent, a as usize), find(&mut parent, b as usize));
+        parent[x] = y;
+        x != y
+    })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ConnectivityProverMessage {
+    // Commitments D to the relabeled matrix
+    Commitments(Vec<PedersenCommitment>),
+    // Permutation relabeling the graph, and the randomness differences s - r for each pair of the original
+    // matrix
+    Relabeling(Permutation, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Edges of a spanning tree of the relabeled graph, and the randomness opening each of their entries in D to 1
+    Tree(Vec<(u32, u32)>, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConnectivityVerifierMessage {
+    // Whether the prover must open a spanning tree rather than relate D to the instance
+    pub b: bool,
+}
+
+pub struct ConnectivityProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Relabeled graph committed to
+    pub relabeling: Option<Relabeling>,
+    pub instance: &'a PedersenGraph,
+    pub witness: &'a PedersenGraphWitness,
+}
+
+impl Prover for ConnectivityProver<'_> {
+    type ProverMessage = ConnectivityProverMessage;
+    type VerifierMessage = ConnectivityVerifierMessage;
+
+    fn handle(&mut self, msg: &ConnectivityVerifierMessage) -> (ConnectivityProverMessage, bool) {
+        self.r += 1;
+        let n = self.instance.n;
+        match (self.r, &self.relabeling) {
+            // During the first round, the prover commits to a random relabeling of the graph
+            (1, _) => {
+                let relabeling = self.instance.relabel(self.witness);
+                let commitments = relabeling.commitments.clone();
+                self.relabeling = Some(relabeling);
+                (ConnectivityProverMessage::Commitments(commitments), false)
+            },
+            // During the second round, the prover opens a spanning tree of the relabeled graph
+            (2, Some(relabeling)) if msg.b => {
+                let permutation = &relabeling.permutation;
+                let root = checked_thread_rng().gen_range(0..n.max(1));
+                let tree: Vec<(u32, u32)> = if n == 0 { Vec::new() } else { spanning_tree(&self.witness.graph, root) }.into_iter()
+                    .map(|(a, b)| (permutation.apply(a), permutation.apply(b))).collect();
+                let randomness = tree.iter().map(|&(a, b)| relabeling.randomness[entry_index(n, false, a, b)].clone()).collect();
+                (ConnectivityProverMessage::Tree(tree, randomness), false)
+            },
+            // Or reveals the relabeling along with how the randomness of each entry changed
+            (2, Some(relabeling)) => {
+                let differences = self.instance.differences(self.witness, &relabeling.permutation, relabeling);
+                (ConnectivityProverMessage::Relabeling(relabeling.permutation.clone(), differences), false)
+            },
+            // After opening, the prover sends a message to terminate the interaction
+            _ => (ConnectivityProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct ConnectivityVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen bit
+    pub b: bool,
+    // Commitments received from prover
+    pub commitments: Vec<PedersenCommitment>,
+    pub instance: &'a PedersenGraph,
+}
+
+impl Verifier for ConnectivityVerifier<'_> {
+    type ProverMessage = ConnectivityProverMessage;
+    type VerifierMessage = ConnectivityVerifierMessage;
+
+    fn init(&mut self) -> ConnectivityVerifierMessage {
+        ConnectivityVerifierMessage { b: false }
+    }
+
+    fn validate(&self, msg: &ConnectivityProverMessage) -> Result<(), String> {
+        let n = self.instance.n;
+        let entries = entry_count(n, false);
+        match (self.r + 1, msg, self.b) {
+            (1, ConnectivityProverMessage::Commitments(commitments), _) => self.instance.check_commitments(commitments),
+            (1, _, _) => Err("expected commitments".to_string()),
+            (_, ConnectivityProverMessage::Relabeling(permutation, differences), false) => {
+                check_count(permutation.n() as usize, n as usize, "vertices in permutation")?;
+                check_count(differences.len(), entries, "randomness differences")
+            },
+            (_, ConnectivityProverMessage::Tree(tree, randomness), true) => {
+                check_count(randomness.len(), tree.len(), "openings of tree edges")
+            },
+            (_, _, false) => Err("expected a relabeling".to_string()),
+            (_, _, true) => Err("expected a spanning tree".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &ConnectivityProverMessage) -> (ConnectivityVerifierMessage, bool) {
+        self.r += 1;
+        let (params, n) = (&self.instance.params, self.instance.n);
+        match (self.r, msg) {
+            (1, ConnectivityProverMessage::Commitments(commitments)) => {
+                self.commitments = commitments.clone();
+                self.b = checked_thread_rng().gen();
+                (ConnectivityVerifierMessage { b: self.b }, false)
+            },
+            // The relabeled commitments must commit to the same graph
+            (_, ConnectivityProverMessage::Relabeling(permutation, differences)) => {
+                let accept = self.instance.check_relabeling(&self.commitments, permutation, differences);
+                (ConnectivityVerifierMessage { b: self.b }, accept)
+            },
+            // The opened entries must all be edges, and must connect every vertex
+            (_, ConnectivityProverMessage::Tree(tree, randomness)) => {
+                let one = BigUint::from(1u32);
+                let accept = spans(n, tree) && tree.iter().zip(randomness).all(|(&(a, b), s)| {
+                    a != b && self.commitments[entry_index(n, false, a, b)] == params.commit_with_randomness(&one, s)
+                });
+                (ConnectivityVerifierMessage { b: self.b }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_spans() {
+    assert!(spans(4, &[(0, 1), (2, 1), (3, 0)]));
+    assert!(!spans(4, &[(0, 1), (1, 0), (2, 3)]));
+}
+
+#[test]
+fn test_connectivity_proof() {
+    let params = PedersenParameters::generate(64);
+    // A path with its edges in both directions is connected, but drops apart without the edge 2 -> 3
+    let path = Graph::new(6, vec![(0, 1), (2, 1), (2, 3), (4, 3), (5, 4)]);
+    let split = Graph::new(6, vec![(0, 1), (2, 1), (4, 3), (5, 4)]);
+    let play = |graph: &Graph| {
+        let (instance, witness) = PedersenGraph::commit(&params, graph);
+        let mut prover = ConnectivityProver { r: 0, relabeling: None, instance: &instance, witness: &witness };
+        let mut verifier = ConnectivityVerifier { r: 0, b: false, commitments: Vec::new(), instance: &instance };
+        run_interactive_proof(&mut prover, &mut verifier)
+    };
+    assert!((0..10).all(|_| play(&path)));
+    let rejected = (0..60).filter(|_| !play(&split)).count();
+    assert!(rejected > 15 && rejected < 45);
+}
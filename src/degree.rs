@@ -0,0 +1,254 @@
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::commitandprove::{CommittedProof, RangeProof};
+use crate::committed::{entry_count, entry_index, has_entry, PedersenGraph, PedersenGraphWitness, Relabeling};
+use crate::graph::{check_count, Graph};
+use crate::params::{serde_biguint, serde_biguints};
+use crate::pedersen::{PedersenCommitment, PedersenOpening};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::secret::Secret;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters, pedersen::PedersenParameters};
+
+// ************ Committed graph degree sequence implementation ************
+
+// The prover shows that a PedersenGraph has a claimed degree sequence, i.e. that its vertices' degrees
+// are the claimed numbers in some order, without revealing which vertex has which degree or anything
+// about the edges. Multiplying the commitments in a row of the adjacency matrix gives a commitment to the
+// row's degree, g^deg h^(sum of randomness), so a degree can be opened without opening any entry.
+//
+// Each repetition commits afresh to a random relabeling of the graph. On challenge 0 the prover shows
+// that it relabeled the committed graph, and on challenge 1 it opens the degree of every relabeled vertex,
+// which the verifier checks against the claimed sequence. A prover whose graph has another degree sequence
+// can answer only one of the challenges, so it is caught with probability 1/2 per repetition, while the
+// verifier only sees the claimed degrees in a random order.
+//
+// A row only sums to the degree if every entry commits to a bit: a prover committing to 1/2 mod q in each
+// entry of a triangle would open degrees of 1. So alongside the relabeled commitments, the prover
+// announces an OR proof for each of them that it commits to 0 or 1, as in RangeProof with one bit, and
+// answers the verifier's challenge c in every OR proof with either opening. Relabeled entries commit to
+// the same values as the original ones, so the OR proofs cover the committed graph as well.
+
+// Degrees of the vertices of the graph with edge directions ignored, from smallest to largest
+pub fn degree_sequence(graph: &Graph) -> Vec<u32> {
+    let mut degrees: Vec<u32> = (0..graph.n).map(|a| (0..graph.n).filter(|&b| a != b && has_entry(graph, false, a, b)).count() as u32).collect();
+    degrees.sort_unstable();
+    degrees
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DegreeProverMessage {
+    // Commitments to the relabeled matrix, and announcements of the OR proof for each of them
+    Commitments(Vec<PedersenCommitment>, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Permutation relabeling the graph, the randomness differences for each entry of the original matrix,
+    // and the OR proof responses
+    Relabeling(Permutation, #[serde(with = "serde_biguints")] Vec<BigUint>, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Degree of each relabeled vertex, the total randomness of the entries in its row, and the OR proof responses
+    Degrees(Vec<u32>, #[serde(with = "serde_biguints")] Vec<BigUint>, #[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DegreeVerifierMessage {
+    // Whether the prover must open the degrees rather than relate the relabeled matrix to the instance
+    pub b: bool,
+    // Challenge shared by the OR proofs
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct DegreeProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Relabeled graph committed to
+    pub relabeling: Option<Relabeling>,
+    // Secret state of the OR proof for each relabeled entry
+    pub state: Secret<Vec<Vec<BigUint>>>,
+    pub instance: &'a PedersenGraph,
+    pub witness: &'a PedersenGraphWitness,
+}
+
+impl DegreeProver<'_> {
+    pub fn new<'a>(instance: &'a PedersenGraph, witness: &'a PedersenGraphWitness) -> DegreeProver<'a> {
+        DegreeProver { r: 0, relabeling: None, state: Secret::new(Vec::new()), instance, witness }
+    }
+
+    // Openings of the relabeled entries
+    fn openings(relabeling: &Relabeling) -> impl Iterator<Item = PedersenOpening> + '_ {
+        relabeling.values.iter().zip(relabeling.randomness.iter()).map(|(value, s)| PedersenOpening { value: value.clone(), randomness: Secret::new(s.clone()) })
+    }
+}
+
+impl Prover for DegreeProver<'_> {
+    type ProverMessage = DegreeProverMessage;
+    type VerifierMessage = DegreeVerifierMessage;
+
+    fn validate(&self, msg: &DegreeVerifierMessage) -> Result<(), String> {
+        self.instance.params.group.check_exponent(&msg.c)
+    }
+
+    fn handle(&mut self, msg: &DegreeVerifierMessage) -> (DegreeProverMessage, bool) {
+        self.r += 1;
+        let (params, n, q) = (&self.instance.params, self.instance.n, &self.instance.params.group.q);
+        let proof = RangeProof { bits: 1 };
+        match (self.r, &self.relabeling) {
+            // During the first round, the prover commits to a random relabeling of the graph and announces the OR proofs
+            (1, _) => {
+                let relabeling = self.instance.relabel(self.witness);
+                let (bits, state): (Vec<_>, Vec<_>) = Self::openings(&relabeling).map(|x| proof.announce(params, &x, &())).unzip();
+                let commitments = relabeling.commitments.clone();
+                self.relabeling = Some(relabeling);
+                self.state = Secret::new(state);
+                (DegreeProverMessage::Commitments(commitments, bits.concat()), false)
+            },
+            // During the second round, the prover opens the degree of every relabeled vertex, the sum of the
+            // values in its row, and responds to the challenge in every OR proof
+            (2, Some(relabeling)) if msg.b => {
+                let response = Self::openings(relabeling).zip(self.state.iter()).flat_map(|(x, state)| proof.respond(params, &x, &(), state, &msg.c)).collect();
+                let (degrees, randomness) = (0..n).map(|a| {
+                    let others = (0..n).filter(|&b| a != b);
+                    let degree = others.clone().fold(BigUint::zero(), |acc, b| (acc + &relabeling.values[entry_index(n, false, a, b)]) % q);
+                    let total = others.fold(BigUint::zero(), |acc, b| (acc + &relabeling.randomness[entry_index(n, false, a, b)]) % q);
+                    (degree.to_u32().unwrap_or(u32::MAX), total)
+                }).unzip();
+                (DegreeProverMessage::Degrees(degrees, randomness, response), false)
+            },
+            // Or reveals the relabeling along with how the randomness of each entry changed
+            (2, Some(relabeling)) => {
+                let response = Self::openings(relabeling).zip(self.state.iter()).flat_map(|(x, state)| proof.respond(params, &x, &(), state, &msg.c)).collect();
+                let differences = self.instance.differences(self.witness, &relabeling.permutation, relabeling);
+                (DegreeProverMessage::Relabeling(relabeling.permutation.clone(), differences, response), false)
+            },
+            // After opening, the prover sends a message to terminate the interaction
+            _ => (DegreeProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct DegreeVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen bit
+    pub b: bool,
+    // Challenge shared by the OR proofs
+    pub c: BigUint,
+    // Commitments and OR proof announcements received from prover
+    pub commitments: Vec<PedersenCommitment>,
+    pub bits: Vec<BigUint>,
+    pub instance: &'a PedersenGraph,
+    // Claimed degrees, in any order
+    pub degrees: &'a [u32],
+}
+
+impl DegreeVerifier<'_> {
+    pub fn new<'a>(instance: &'a PedersenGraph, degrees: &'a [u32]) -> DegreeVerifier<'a> {
+        DegreeVerifier { r: 0, b: false, c: BigUint::zero(), commitments: Vec::new(), bits: Vec::new(), instance, degrees }
+    }
+
+    // Checks the OR proof that each relabeled entry commits to a bit
+    fn entries_are_bits(&self, response: &[BigUint]) -> bool {
+        let proof = RangeProof { bits: 1 };
+        self.commitments.iter().zip(self.bits.chunks(3).zip(response.chunks(3))).all(|(x, (a, z))| proof.check(&self.instance.params, x, a, &self.c, z))
+    }
+}
+
+impl Verifier for DegreeVerifier<'_> {
+    type ProverMessage = DegreeProverMessage;
+    type VerifierMessage = DegreeVerifierMessage;
+
+    fn init(&mut self) -> DegreeVerifierMessage {
+        DegreeVerifierMessage { b: false, c: BigUint::zero() }
+    }
+
+    fn validate(&self, msg: &DegreeProverMessage) -> Result<(), String> {
+        let n = self.instance.n as usize;
+        let entries = entry_count(n as u32, false);
+        match (self.r + 1, msg, self.b) {
+            (1, DegreeProverMessage::Commitments(commitments, bits), _) => {
+                self.instance.check_commitments(commitments)?;
+                check_count(bits.len(), 3 * entries, "OR proof announcements")
+            },
+            (1, _, _) => Err("expected commitments".to_string()),
+            (_, DegreeProverMessage::Relabeling(permutation, differences, response), false) => {
+                check_count(permutation.n() as usize, n, "vertices in permutation")?;
+                check_count(differences.len(), entries, "randomness differences")?;
+                check_count(response.len(), 3 * entries, "OR proof responses")
+            },
+            (_, DegreeProverMessage::Degrees(degrees, randomness, response), true) => {
+                check_count(degrees.len(), n, "degrees")?;
+                check_count(randomness.len(), n, "randomness totals")?;
+                check_count(response.len(), 3 * entries, "OR proof responses")
+            },
+            (_, _, false) => Err("expected a relabeling".to_string()),
+            (_, _, true) => Err("expected degrees".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &DegreeProverMessage) -> (DegreeVerifierMessage, bool) {
+        self.r += 1;
+        let (params, n) = (&self.instance.params, self.instance.n);
+        match (self.r, msg) {
+            (1, DegreeProverMessage::Commitments(commitments, bits)) => {
+                self.commitments = commitments.clone();
+                self.bits = bits.clone();
+                self.b = checked_thread_rng().gen();
+                self.c = checked_thread_rng().gen_range(BigUint::zero()..params.group.q.clone());
+                (DegreeVerifierMessage { b: self.b, c: self.c.clone() }, false)
+            },
+            // The relabeled commitments must commit to bits of the same graph
+            (_, DegreeProverMessage::Relabeling(permutation, differences, response)) => {
+                let accept = self.entries_are_bits(response) && self.instance.check_relabeling(&self.commitments, permutation, differences);
+                (DegreeVerifierMessage { b: self.b, c: self.c.clone() }, accept)
+            },
+            // Each relabeled entry must commit to a bit, each row of the relabeled matrix must commit to its
+            // opened degree, and the degrees must be the claimed ones
+            (_, DegreeProverMessage::Degrees(degrees, randomness, response)) => {
+                let p = &params.group.p;
+                let rows_match = (0..n).all(|a| {
+                    let row = (0..n).filter(|&b| a != b).fold(BigUint::one(), |acc, b| acc * &self.commitments[entry_index(n, false, a, b)].c % p);
+                    row == params.commit_with_randomness(&BigUint::from(degrees[a as usize]), &randomness[a as usize]).c
+                });
+                let (mut opened, mut claimed) = (degrees.clone(), self.degrees.to_vec());
+                opened.sort_unstable();
+                claimed.sort_unstable();
+                (DegreeVerifierMessage { b: self.b, c: self.c.clone() }, self.entries_are_bits(response) && rows_match && opened == claimed)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_degree_sequence_proof() {
+    let params = PedersenParameters::generate(64);
+    let graph = Graph::new(5, vec![(0, 1), (1, 2), (2, 0), (3, 2), (4, 3)]);
+    assert_eq!(degree_sequence(&graph), vec![1, 2, 2, 2, 3]);
+    let (instance, witness) = PedersenGraph::commit(&params, &graph);
+    let play = |degrees: &[u32]| {
+        run_interactive_proof(&mut DegreeProver::new(&instance, &witness), &mut DegreeVerifier::new(&instance, degrees))
+    };
+    // The claimed degrees can be in any order
+    assert!((0..10).all(|_| play(&[3, 2, 1, 2, 2])));
+    let rejected = (0..60).filter(|_| !play(&[2, 2, 2, 2, 2])).count();
+    assert!(rejected > 15 && rejected < 45);
+}
+
+#[test]
+fn test_degree_non_bit_entries() {
+    // Committing to 1/2 mod q in every entry of a triangle makes each row sum to 1, a degree sequence no
+    // graph on 3 vertices has
+    let params = PedersenParameters::generate(64);
+    let q = &params.group.q;
+    let half = (q + BigUint::one()) / 2u32;
+    let triangle = Graph::new(3, vec![(0, 1), (1, 2), (2, 0)]);
+    let (commitments, openings) = (0..entry_count(3, false)).map(|_| params.commit(&half)).unzip();
+    let instance = PedersenGraph { params: params.clone(), n: 3, commitments };
+    let witness = PedersenGraphWitness { graph: triangle, openings };
+    // The rows open to the claimed degrees, but the OR proofs give the prover away on every challenge
+    assert!((0..20).all(|_| !run_interactive_proof(&mut DegreeProver::new(&instance, &witness), &mut DegreeVerifier::new(&instance, &[1, 1, 1]))));
+}
@@ -0,0 +1,228 @@
+use num_bigint::BigUint;
+use crate::{Prover, Verifier};
+use crate::modarith::mod_exp;
+use serde::{Serialize, Deserialize};
+use crate::params::{SchnorrParameters, serde_biguint, serde_biguint_pair};
+use crate::schnorr::SchnorrKeyPair;
+use crate::transcript::{ChallengeTranscript, ProofContext, encode_biguints};
+#[cfg(test)]
+use crate::params::Parameters;
+#[cfg(test)]
+use crate::secret::Secret;
+
+// ************ Designated-verifier proof implementation ************
+
+// A designated-verifier proof of knowledge of x with y = g^x only convinces one verifier, who holds the
+// secret key w of Y = g^w. Instead of proving knowledge of x, the prover proves knowledge of x OR w
+// non-interactively. The prover answers the first branch honestly and simulates the second, choosing its
+// challenge and response first, and the Fiat-Shamir challenge only fixes the sum of the two branch
+// challenges.
+//
+// The designated verifier knows it didn't produce the proof itself, so it is convinced the prover knows
+// x. Anyone else is not, since the verifier could have produced the same proof by answering the second
+// branch with w and simulating the first. This makes the proof deniable: the verifier can't use it to
+// convince a third party of anything.
+
+// Domain separation label for designated-verifier proofs
+pub const DESIGNATED_VERIFIER_DOMAIN: &str = "zklib designated verifier";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DesignatedProof {
+    pub context: ProofContext,
+    // Announcements for the prover's key and the verifier's key
+    #[serde(with = "serde_biguint_pair")]
+    pub announcements: (BigUint, BigUint),
+    // Challenge for the prover's key, the other being the Fiat-Shamir challenge minus this one
+    #[serde(with = "serde_biguint")]
+    pub challenge: BigUint,
+    #[serde(with = "serde_biguint_pair")]
+    pub responses: (BigUint, BigUint),
+}
+
+// Proves knowledge of the prover's secret key in a way that only convinces the holder of verifier_key
+pub fn prove_designated(prover: &SchnorrKeyPair, verifier_key: &BigUint, session_id: &[u8]) -> DesignatedProof {
+    prove_or(&prover.params, &prover.y, verifier_key, session_id, 0, &prover.x)
+}
+
+// Produces a proof that verifies for the prover's public key using only the verifier's secret key
+pub fn simulate_designated(verifier: &SchnorrKeyPair, prover_key: &BigUint, session_id: &[u8]) -> DesignatedProof {
+    prove_or(&verifier.params, prover_key, &verifier.y, session_id, 1, &verifier.x)
+}
+
+pub fn verify_designated(params: &SchnorrParameters, prover_key: &BigUint, verifier_key: &BigUint, session_id: &[u8], proof: &DesignatedProof) -> bool {
+    let (p, q) = (&params.p, &params.q);
+    let context = designated_context(params, prover_key, verifier_key, session_id);
+    if let Err(e) = proof.context.check(&context) {
+        narrate!("Rejecting proof: {}.", e);
+        return false;
+    }
+    let (a0, a1) = &proof.announcements;
+    let c = or_challenge(params, &context, a0, a1);
+    let challenges = [proof.challenge.clone() % q, (&c + q - &proof.challenge % q) % q];
+    let keys = [prover_key, verifier_key];
+    let (announcements, responses) = ([a0, a1], [&proof.responses.0, &proof.responses.1]);
+    // Both branches must satisfy g^z_i = a_i * y_i^c_i
+    (0..2).all(|i| mod_exp(&params.g, responses[i], p) == announcements[i] * mod_exp(keys[i], &challenges[i], p) % p)
+}
+
+// Proves knowledge of the discrete log of one of the two keys, given the secret key for branch known
+fn prove_or(params: &SchnorrParameters, prover_key: &BigUint, verifier_key: &BigUint, session_id: &[u8], known: usize, secret: &BigUint) -> DesignatedProof {
+    let (p, q) = (&params.p, &params.q);
+    let context = designated_context(params, prover_key, verifier_key, session_id);
+    let keys = [prover_key, verifier_key];
+    // Simulate the other branch by picking its challenge and response first
+    let (c_sim, z_sim) = (params.random_exponent(), params.random_exponent());
+    let simulated = mod_exp(&params.g, &z_sim, p) * mod_exp(keys[1 - known], &(q - &c_sim), p) % p;
+    let k = params.random_exponent();
+    let real = mod_exp(&params.g, &k, p);
+    let (a0, a1) = if known == 0 { (real, simulated) } else { (simulated, real) };
+    let c = or_challenge(params, &context, &a0, &a1);
+    let c_real = (c + q - &c_sim) % q;
+    let z_real = (k + &c_real * secret) % q;
+    let (challenge, responses) = if known == 0 { (c_real, (z_real, z_sim)) } else { (c_sim, (z_sim, z_real)) };
+    DesignatedProof { context, announcements: (a0, a1), challenge, responses }
+}
+
+// The statement is the pair of keys, so a proof for one verifier is rejected by every other
+fn designated_context(params: &SchnorrParameters, prover_key: &BigUint, verifier_key: &BigUint, session_id: &[u8]) -> ProofContext {
+    let statement = encode_biguints(&[&params.p, &params.q, &params.g, prover_key, verifier_key]);
+    ProofContext::new(DESIGNATED_VERIFIER_DOMAIN, session_id, &statement)
+}
+
+fn or_challenge(params: &SchnorrParameters, context: &ProofContext, a0: &BigUint, a1: &BigUint) -> BigUint {
+    let mut transcript = ChallengeTranscript::with_context(context);
+    transcript.append_biguint(b"announcement", a0);
+    transcript.append_biguint(b"announcement", a1);
+    transcript.challenge_scalar(b"challenge", &params.q)
+}
+
+// ************ Designated-verifier exchange implementation ************
+
+// Sending a designated-verifier proof is a one-message interaction. DesignatedProver and DesignatedVerifier
+// play it through the Prover and Verifier traits, so the proof can be run and fuzzed like the interactive
+// protocols. The verifier's first message only starts the interaction.
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum DesignatedProverMessage {
+    // Proof for the designated verifier's key
+    Proof(DesignatedProof),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone)]
+pub struct DesignatedVerifierMessage;
+
+pub struct DesignatedProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    pub key: &'a SchnorrKeyPair,
+    pub verifier_key: &'a BigUint,
+    pub session_id: &'a [u8],
+}
+
+impl Prover for DesignatedProver<'_> {
+    type ProverMessage = DesignatedProverMessage;
+    type VerifierMessage = DesignatedVerifierMessage;
+
+    fn handle(&mut self, _msg: &DesignatedVerifierMessage) -> (DesignatedProverMessage, bool) {
+        self.r += 1;
+        match self.r {
+            1 => (DesignatedProverMessage::Proof(prove_designated(self.key, self.verifier_key, self.session_id)), false),
+            // After sending the proof, the prover sends a message to terminate the interaction
+            _ => (DesignatedProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct DesignatedVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    pub params: &'a SchnorrParameters,
+    pub prover_key: &'a BigUint,
+    pub verifier_key: &'a BigUint,
+    pub session_id: &'a [u8],
+}
+
+impl Verifier for DesignatedVerifier<'_> {
+    type ProverMessage = DesignatedProverMessage;
+    type VerifierMessage = DesignatedVerifierMessage;
+
+    fn init(&mut self) -> DesignatedVerifierMessage {
+        DesignatedVerifierMessage
+    }
+
+    fn validate(&self, msg: &DesignatedProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, DesignatedProverMessage::Proof(proof)) => {
+                self.params.check_element(&proof.announcements.0)?;
+                self.params.check_element(&proof.announcements.1)?;
+                self.params.check_exponent(&proof.challenge)?;
+                self.params.check_exponent(&proof.responses.0)?;
+                self.params.check_exponent(&proof.responses.1)
+            },
+            (1, _) => Err("expected a proof".to_string()),
+            _ => Err("expected no more messages".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &DesignatedProverMessage) -> (DesignatedVerifierMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            (1, DesignatedProverMessage::Proof(proof)) => {
+                let accept = verify_designated(self.params, self.prover_key, self.verifier_key, self.session_id, proof);
+                (DesignatedVerifierMessage, accept)
+            },
+            _ => panic!("Prover did not send a valid proof on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_designated_verifier_proof() {
+    let params = SchnorrParameters::generate(64);
+    let prover = SchnorrKeyPair::generate(&params);
+    let verifier = SchnorrKeyPair::generate(&params);
+    let proof = prove_designated(&prover, &verifier.y, b"session");
+    assert!(verify_designated(&params, &prover.y, &verifier.y, b"session", &proof));
+    // The proof is bound to the designated verifier and the session
+    let other = SchnorrKeyPair::generate(&params);
+    assert!(!verify_designated(&params, &prover.y, &other.y, b"session", &proof));
+    assert!(!verify_designated(&params, &prover.y, &verifier.y, b"other session", &proof));
+}
+
+#[test]
+fn test_designated_verifier_proof_wrong_key() {
+    // A prover without the secret key for its claimed public key can't answer either branch
+    let params = SchnorrParameters::generate(64);
+    let prover = SchnorrKeyPair::generate(&params);
+    let verifier = SchnorrKeyPair::generate(&params);
+    let impostor = SchnorrKeyPair { params: params.clone(), x: Secret::new(params.random_exponent()), y: prover.y.clone() };
+    let proof = prove_designated(&impostor, &verifier.y, b"session");
+    assert!(!verify_designated(&params, &prover.y, &verifier.y, b"session", &proof));
+}
+
+#[test]
+fn test_designated_verifier_deniability() {
+    // The verifier can produce a valid proof on its own, so the proof convinces nobody else
+    let params = SchnorrParameters::generate(64);
+    let prover = SchnorrKeyPair::generate(&params);
+    let verifier = SchnorrKeyPair::generate(&params);
+    let forged = simulate_designated(&verifier, &prover.y, b"session");
+    assert!(verify_designated(&params, &prover.y, &verifier.y, b"session", &forged));
+}
+
+#[test]
+fn test_designated_verifier_exchange() {
+    let params = SchnorrParameters::generate(64);
+    let prover = SchnorrKeyPair::generate(&params);
+    let verifier = SchnorrKeyPair::generate(&params);
+    let run = |key: &SchnorrKeyPair, session_id: &[u8]| crate::run_interactive_proof(
+        &mut DesignatedProver { r: 0, key, verifier_key: &verifier.y, session_id },
+        &mut DesignatedVerifier { r: 0, params: &params, prover_key: &prover.y, verifier_key: &verifier.y, session_id: b"session" },
+    );
+    assert!(run(&prover, b"session"));
+    assert!(!run(&prover, b"other session"));
+    let impostor = SchnorrKeyPair { params: params.clone(), x: Secret::new(params.random_exponent()), y: prover.y.clone() };
+    assert!(!run(&impostor, b"session"));
+}
@@ -0,0 +1,229 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use blst::BLST_ERROR;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::beacon::{BeaconValue, RandomnessBeacon};
+
+// ************ drand beacon implementation ************
+
+// drand is a network of nodes that jointly publish a BLS threshold signature every period, and the
+// randomness of each round is the SHA-256 hash of its signature. Since the signature verifies under the
+// network's public key, a beacon value fetched from any relay can be checked without trusting the
+// relay, and since no threshold of nodes can be predicted, neither can future rounds. What is signed
+// depends on the network's scheme:
+//
+// - pedersen-bls-chained: H(previous signature || round), signed on G2 under a key on G1
+// - pedersen-bls-unchained: H(round), signed on G2 under a key on G1
+// - bls-unchained-g1-rfc9380 (quicknet): H(round), signed on G1 under a key on G2
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrandScheme {
+    Chained,
+    Unchained,
+    UnchainedG1,
+}
+
+impl DrandScheme {
+    pub fn from_id(id: &str) -> Option<DrandScheme> {
+        match id {
+            "pedersen-bls-chained" => Some(DrandScheme::Chained),
+            "pedersen-bls-unchained" => Some(DrandScheme::Unchained),
+            "bls-unchained-g1-rfc9380" => Some(DrandScheme::UnchainedG1),
+            _ => None,
+        }
+    }
+
+    fn dst(&self) -> &'static [u8] {
+        match self {
+            DrandScheme::Chained | DrandScheme::Unchained => b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_NUL_",
+            DrandScheme::UnchainedG1 => b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_",
+        }
+    }
+}
+
+// A round as served by a drand relay, with byte strings in hex
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DrandRound {
+    pub round: u64,
+    pub randomness: String,
+    pub signature: String,
+    #[serde(default)]
+    pub previous_signature: Option<String>,
+}
+
+// A drand network, identified by the relay URL of its chain (ending in the chain hash), its public key,
+// and its schedule. The public key must come from a trusted source rather than the relay being checked.
+#[derive(Clone, Debug)]
+pub struct DrandBeacon {
+    pub url: String,
+    pub public_key: Vec<u8>,
+    pub scheme: DrandScheme,
+    pub genesis_time: u64,
+    // Seconds between rounds
+    pub period: u64,
+    // Last round handed out, so that no round is used for two challenges
+    pub last_round: u64,
+}
+
+const QUICKNET_URL: &str = "https://api.drand.sh/52db9ba70e0cc0f6eaf7803dd07447a1f5477735fd3f661792ba94600c84e971";
+const QUICKNET_PUBLIC_KEY: &str = "83cf0f2896adee7eb8b5f01fcad3912212c437e0073e911fb90022d3e760183c8c4b450b6a0a6c3ac6a5776a2d1064510d1fec758c921cc22b0e17e63aaf4bcb5ed66304de9cf809bd274ca73bab4af5a6e9c76a4bc09e76eae8991ef5ece45a";
+
+// Times to retry a round that the relay hasn't published yet, a second apart
+const FETCH_RETRIES: u32 = 5;
+
+impl DrandBeacon {
+    // The League of Entropy's quicknet network, which publishes a round every 3 seconds
+    pub fn quicknet() -> DrandBeacon {
+        DrandBeacon {
+            url: QUICKNET_URL.to_string(),
+            public_key: hex::decode(QUICKNET_PUBLIC_KEY).unwrap(),
+            scheme: DrandScheme::UnchainedG1,
+            genesis_time: 1692803367,
+            period: 3,
+            last_round: 0,
+        }
+    }
+
+    // Round being published at the given Unix time, where round 1 is published at genesis
+    pub fn round_at(&self, time: u64) -> u64 {
+        if time < self.genesis_time { 0 } else { (time - self.genesis_time) / self.period + 1 }
+    }
+
+    // Checks the round's signature under the network's public key, and that its randomness is the hash of
+    // the signature
+    pub fn verify(&self, round: &DrandRound) -> Result<BeaconValue, String> {
+        let decode = |field: &str, x: &str| hex::decode(x).map_err(|e| format!("Round {} has a malformed {}: {}", round.round, field, e));
+        let signature = decode("signature", &round.signature)?;
+        let randomness = decode("randomness", &round.randomness)?;
+        let mut message = Vec::new();
+        if self.scheme == DrandScheme::Chained {
+            let previous = round.previous_signature.as_ref().ok_or_else(|| format!("Round {} of a chained network has no previous signature", round.round))?;
+            message.extend(decode("previous signature", previous)?);
+        }
+        message.extend(round.round.to_be_bytes());
+        let message = Sha256::digest(&message);
+
+        let result = match self.scheme {
+            DrandScheme::Chained | DrandScheme::Unchained => {
+                use blst::min_pk::{PublicKey, Signature};
+                let key = PublicKey::from_bytes(&self.public_key).map_err(|e| format!("Malformed public key: {:?}", e))?;
+                let signature = Signature::from_bytes(&signature).map_err(|e| format!("Round {} has a malformed signature: {:?}", round.round, e))?;
+                signature.verify(true, &message, self.scheme.dst(), &[], &key, true)
+            },
+            DrandScheme::UnchainedG1 => {
+                use blst::min_sig::{PublicKey, Signature};
+                let key = PublicKey::from_bytes(&self.public_key).map_err(|e| format!("Malformed public key: {:?}", e))?;
+                let signature = Signature::from_bytes(&signature).map_err(|e| format!("Round {} has a malformed signature: {:?}", round.round, e))?;
+                signature.verify(true, &message, self.scheme.dst(), &[], &key, true)
+            },
+        };
+        if result != BLST_ERROR::BLST_SUCCESS {
+            return Err(format!("Round {} has an invalid signature", round.round));
+        }
+        if Sha256::digest(&signature).as_slice() != randomness.as_slice() {
+            return Err(format!("Round {} has randomness that isn't the hash of its signature", round.round));
+        }
+        Ok(BeaconValue { round: round.round, randomness })
+    }
+
+    // Fetches and verifies the given round, retrying while the relay hasn't published it yet
+    pub fn fetch(&self, round: u64) -> Result<BeaconValue, String> {
+        let url = format!("{}/public/{}", self.url, round);
+        let mut attempts = 0;
+        let body = loop {
+            match ureq::get(&url).call() {
+                Ok(mut response) => break response.body_mut().read_to_string().map_err(|e| e.to_string())?,
+                Err(ureq::Error::StatusCode(_)) if attempts < FETCH_RETRIES => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_secs(1));
+                },
+                Err(e) => return Err(format!("Failed to fetch {}: {}", url, e)),
+            }
+        };
+        let fetched: DrandRound = serde_json::from_str(&body).map_err(|e| format!("Malformed round from {}: {}", url, e))?;
+        if fetched.round != round {
+            return Err(format!("Asked for round {} but got round {}", round, fetched.round));
+        }
+        self.verify(&fetched)
+    }
+}
+
+impl RandomnessBeacon for DrandBeacon {
+    // Waits for the first round published after the call, so the value can't have been known when the
+    // prover sent the message it answers
+    fn next_value(&mut self) -> Result<BeaconValue, String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+        let round = (self.round_at(now.as_secs()) + 1).max(self.last_round + 1);
+        let publish_time = Duration::from_secs(self.genesis_time + (round - 1) * self.period);
+        if let Some(wait) = publish_time.checked_sub(now) {
+            thread::sleep(wait);
+        }
+        let value = self.fetch(round)?;
+        self.last_round = round;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+fn serve_rounds(rounds: Vec<String>) -> String {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // Serves the given rounds as a relay would, at /public/<i> for the ith round starting from 1
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            let round = request.split_whitespace().nth(1).and_then(|path| path.strip_prefix("/public/")).and_then(|x| x.parse::<usize>().ok());
+            let response = match round.and_then(|i| rounds.get(i.wrapping_sub(1))) {
+                Some(body) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn test_quicknet_public_key() {
+    assert!(blst::min_sig::PublicKey::from_bytes(&DrandBeacon::quicknet().public_key).is_ok());
+}
+
+#[test]
+fn test_drand_beacon() {
+    use blst::min_sig::SecretKey;
+
+    // A network of one node signing rounds like quicknet
+    let key = SecretKey::key_gen(&[7u8; 32], &[]).unwrap();
+    let dst = DrandScheme::UnchainedG1.dst();
+    let rounds: Vec<(u64, Vec<u8>)> = (1..=3u64).map(|round| (round, key.sign(&Sha256::digest(round.to_be_bytes()), dst, &[]).to_bytes().to_vec())).collect();
+    let json = |round: u64, signature: &[u8]| format!(r#"{{"round":{},"randomness":"{}","signature":"{}"}}"#, round, hex::encode(Sha256::digest(signature)), hex::encode(signature));
+    let mut served: Vec<String> = rounds.iter().map(|(round, signature)| json(*round, signature)).collect();
+    // Round 3 is served with round 2's signature
+    served[2] = json(3, &rounds[1].1);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let mut beacon = DrandBeacon {
+        url: serve_rounds(served),
+        public_key: key.sk_to_pk().to_bytes().to_vec(),
+        scheme: DrandScheme::UnchainedG1,
+        // Round 1 is being published now, so the next value is round 2
+        genesis_time: now,
+        period: 1,
+        last_round: 0,
+    };
+    let value = beacon.next_value().unwrap();
+    assert_eq!(value.round, 2);
+    assert_eq!(value.randomness, Sha256::digest(&rounds[1].1).to_vec());
+    assert!(beacon.fetch(1).is_ok());
+    assert!(beacon.fetch(3).unwrap_err().contains("invalid signature"));
+
+    // Values from another network don't verify
+    beacon.public_key = SecretKey::key_gen(&[8u8; 32], &[]).unwrap().sk_to_pk().to_bytes().to_vec();
+    assert!(beacon.fetch(1).is_err());
+}
@@ -0,0 +1,155 @@
+use num_bigint::BigUint;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::Prover;
+use crate::audit::to_hex;
+use crate::keys::{ElGamalKeyPair, ElGamalPublicKey};
+use crate::params::serde_biguint;
+#[cfg(test)]
+use crate::graph::{GIProver, GIVerifier, Graph, GraphPair};
+#[cfg(test)]
+use crate::params::{Parameters, SchnorrParameters};
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Witness escrow implementation ************
+
+// A zero-knowledge transcript convinces the verifier without recording how the prover produced its
+// messages, so a later dispute, e.g. over whether a prover really knew the witness or guessed the
+// challenges, can't be settled from the transcript alone. The prover can instead annotate its messages
+// with what it knows about them, e.g. the permutation it built the GI prover's graph with, and encrypt
+// the annotations under an auditor's ElGamal key. The verifier stores the ciphertext with the transcript,
+// and only the auditor can read it. Hashed ElGamal hides the annotations from anyone without the
+// auditor's key, so the proof stays zero knowledge toward the verifier, who learns only their length.
+//
+// The ciphertext is bound to a context, a digest of the statement and of the session it belongs to, so
+// it can't be moved to another transcript, and the plaintext carries a digest of itself, so a ciphertext
+// altered in storage fails to open rather than opening to different annotations.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    // Round of the interaction, counting the prover's messages from 1
+    pub round: u32,
+    pub label: String,
+    pub value: Vec<u8>,
+}
+
+// A prover that can describe the message it just sent, for an auditor's eyes only
+pub trait AnnotatingProver: Prover {
+    // Labelled encodings of what the prover knows about its last message, e.g. the randomness behind it
+    fn annotate(&self) -> Vec<(String, Vec<u8>)>;
+}
+
+// Runs the inner prover, collecting its annotations on every message it sends
+pub struct EscrowingProver<'a, P: AnnotatingProver> {
+    pub inner: &'a mut P,
+    // Messages sent so far
+    pub round: u32,
+    pub annotations: Vec<Annotation>,
+}
+
+impl<'a, P: AnnotatingProver> EscrowingProver<'a, P> {
+    pub fn new(inner: &'a mut P) -> EscrowingProver<'a, P> {
+        EscrowingProver { inner, round: 0, annotations: Vec::new() }
+    }
+
+    // Encrypts the annotations collected so far
+    pub fn seal(&self, auditor: &ElGamalPublicKey, context: &[u8; 32]) -> Escrow {
+        Escrow::seal(auditor, context, &self.annotations)
+    }
+}
+
+impl<P: AnnotatingProver> Prover for EscrowingProver<'_, P> {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        let (reply, done) = self.inner.handle(msg);
+        // The message sent along with the done flag never reaches the verifier
+        if !done {
+            self.round += 1;
+            let round = self.round;
+            self.annotations.extend(self.inner.annotate().into_iter().map(|(label, value)| Annotation { round, label, value }));
+        }
+        (reply, done)
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+}
+
+// Context binding an escrow to the session of the statement with the given digest
+pub fn escrow_context(statement: &[u8; 32], session: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zklib escrow");
+    hasher.update(statement);
+    hasher.update(session.as_bytes());
+    hasher.finalize().into()
+}
+
+// Hashed ElGamal encryption of a transcript's annotations
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Escrow {
+    // Hex digest of the context, see escrow_context
+    pub context: String,
+    #[serde(with = "serde_biguint")]
+    pub ephemeral: BigUint,
+    pub masked: Vec<u8>,
+}
+
+fn plaintext_digest(body: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zklib escrow plaintext");
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+impl Escrow {
+    pub fn seal(auditor: &ElGamalPublicKey, context: &[u8; 32], annotations: &[Annotation]) -> Escrow {
+        let body = bincode::serialize(&(context, annotations)).unwrap();
+        let plaintext = [plaintext_digest(&body).as_slice(), &body].concat();
+        let (ephemeral, masked) = auditor.encrypt(&plaintext);
+        Escrow { context: to_hex(context), ephemeral, masked }
+    }
+
+    // Decrypts the annotations, failing if the ciphertext was altered or isn't for this key
+    pub fn open(&self, auditor: &ElGamalKeyPair) -> Result<Vec<Annotation>, String> {
+        let plaintext = auditor.decrypt(&(self.ephemeral.clone(), self.masked.clone()));
+        if plaintext.len() < 32 || plaintext[..32] != plaintext_digest(&plaintext[32..]) {
+            return Err("Escrow does not decrypt under this key, or was altered".to_string());
+        }
+        let (context, annotations): ([u8; 32], Vec<Annotation>) = bincode::deserialize(&plaintext[32..]).map_err(|e| format!("Malformed escrow: {}", e))?;
+        if to_hex(&context) != self.context {
+            return Err("Escrow was sealed for a different context".to_string());
+        }
+        Ok(annotations)
+    }
+}
+
+#[test]
+fn test_escrow() {
+    let params = SchnorrParameters::generate(64);
+    let auditor = ElGamalKeyPair::generate(&params);
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let mut prover = GIProver::new(&instance);
+    let mut escrowing = EscrowingProver::new(&mut prover);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert!(run_interactive_proof(&mut escrowing, &mut verifier));
+    assert_eq!(escrowing.annotations.len(), 1);
+    assert_eq!(escrowing.annotations[0].round, 1);
+
+    let context = escrow_context(&instance.digest(), "session");
+    let escrow = escrowing.seal(&auditor.public_key(), &context);
+    assert!(!escrow.masked.windows(escrowing.annotations[0].value.len()).any(|x| x == escrowing.annotations[0].value));
+    assert_eq!(escrow.open(&auditor), Ok(escrowing.annotations.clone()));
+    assert!(escrow.open(&ElGamalKeyPair::generate(&params)).is_err());
+    let mut altered = escrow.clone();
+    altered.masked[40] ^= 1;
+    assert!(altered.open(&auditor).is_err());
+    let moved = Escrow { context: to_hex(&escrow_context(&instance.digest(), "another session")), ..escrow };
+    assert!(moved.open(&auditor).is_err());
+}
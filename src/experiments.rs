@@ -0,0 +1,1215 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::ops::{Add, AddAssign};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use cpu_time::ThreadTime;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::{try_run_interactive_proof, Diagnosis, Prover, Verifier};
+use crate::config::{self, ParallelismHint};
+use crate::field::{evaluate_on_coset, Fp, TWO_ADICITY};
+use crate::fri::{Fri, FriProver};
+use crate::iop::iop_to_interactive;
+use crate::beacon::BiasedRng;
+use crate::graph::{CanonicalGNIProver, GIProver, GIProverMalicious, GIVerifier, GIVerifierBiased, GNIProver, GNIProverMalicious, GNIProverMessage, GNIVerifier, GNIVerifierMessage, Graph, GraphPair, GuessStrategy, SearchBudget, MAX_VERTICES};
+use crate::isomorphism::{decide, Decision, DecisionMode};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+#[cfg(feature = "plot")]
+use std::path::Path;
+#[cfg(feature = "plot")]
+use itertools::Itertools;
+
+// ************ Statistical experiment implementation ************
+
+// Soundness and completeness experiments share a shape: set up fresh parties, run one interaction, and
+// count how often the verifier accepts. run_trials runs the trials in parallel batches, reporting
+// progress after each batch, and can stop early once the confidence interval for the acceptance rate
+// is good enough to answer the question being asked, e.g. whether a cheating prover succeeds less than
+// half the time.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EarlyStop {
+    // Runs every trial
+    Never,
+    // Stops once the confidence interval is narrower than the given width
+    Width(f64),
+    // Stops once the confidence interval lies entirely above or entirely below the given rate
+    Separates(f64),
+}
+
+// Called with the results so far after every batch of trials
+pub type Progress<'a> = Box<dyn Fn(&Trials) + Sync + 'a>;
+
+pub struct TrialOptions<'a> {
+    pub early_stop: EarlyStop,
+    // Standard deviations covered by each side of the confidence interval, e.g. 2.576 for 99% confidence
+    pub z: f64,
+    // Number of trials run in parallel between progress reports and stopping checks
+    pub batch: usize,
+    pub progress: Option<Progress<'a>>,
+    // Threads the trials may run on, within the limit from config::set_parallelism
+    pub parallelism: ParallelismHint,
+}
+
+impl Default for TrialOptions<'_> {
+    fn default() -> Self {
+        TrialOptions { early_stop: EarlyStop::Never, z: 2.576, batch: 100, progress: None, parallelism: ParallelismHint::Default }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Trials {
+    // Number of trials asked for, which is more than were run if the experiment stopped early
+    pub requested: usize,
+    pub trials: usize,
+    pub accepted: usize,
+    // Wall-clock time spent running trials
+    pub seconds: f64,
+    // Width of the confidence interval, in standard deviations on each side
+    pub z: f64,
+}
+
+impl Trials {
+    pub fn acceptance_rate(&self) -> f64 {
+        self.accepted as f64 / self.trials as f64
+    }
+
+    // Wilson score interval for the acceptance rate, which unlike the normal approximation stays within
+    // [0, 1] and is sensible when nearly every trial is accepted or rejected
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        if self.trials == 0 {
+            return (0.0, 1.0);
+        }
+        let (n, p, z2) = (self.trials as f64, self.acceptance_rate(), self.z * self.z);
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half_width = self.z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        ((center - half_width).max(0.0), (center + half_width).min(1.0))
+    }
+
+    pub fn stopped_early(&self) -> bool {
+        self.trials < self.requested
+    }
+
+    fn should_stop(&self, early_stop: EarlyStop) -> bool {
+        let (lower, upper) = self.confidence_interval();
+        match early_stop {
+            EarlyStop::Never => false,
+            EarlyStop::Width(width) => upper - lower < width,
+            EarlyStop::Separates(rate) => upper < rate || lower > rate,
+        }
+    }
+}
+
+// Runs the trial n times in parallel with the default options. Each trial sets up fresh parties, plays
+// one interaction, and returns whether the verifier accepted.
+pub fn run_trials<F: Fn() -> bool + Sync>(n: usize, trial: F) -> Trials {
+    run_trials_with(n, trial, &TrialOptions::default())
+}
+
+pub fn run_trials_with<F: Fn() -> bool + Sync>(n: usize, trial: F, options: &TrialOptions) -> Trials {
+    let start = Instant::now();
+    let mut trials = Trials { requested: n, trials: 0, accepted: 0, seconds: 0.0, z: options.z };
+    while trials.trials < n {
+        let batch = options.batch.max(1).min(n - trials.trials);
+        trials.accepted += config::install(options.parallelism, || (0..batch).into_par_iter().filter(|_| trial()).count());
+        trials.trials += batch;
+        trials.seconds = start.elapsed().as_secs_f64();
+        if let Some(progress) = &options.progress {
+            progress(&trials);
+        }
+        if trials.should_stop(options.early_stop) {
+            break;
+        }
+    }
+    trials
+}
+
+// ************ Experiment export implementation ************
+
+// Named results flattened into rows for CSV and JSON export, so they can be plotted or compared across runs
+
+#[derive(Serialize)]
+struct Row<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    trials: &'a Trials,
+    acceptance_rate: f64,
+    lower: f64,
+    upper: f64,
+}
+
+impl Row<'_> {
+    fn csv(&self) -> String {
+        let t = self.trials;
+        format!("{},{},{},{},{},{},{},{},{}", self.name, t.requested, t.trials, t.accepted, t.seconds, t.z, self.acceptance_rate, self.lower, self.upper)
+    }
+}
+
+fn rows<'a>(results: &[(&'a str, &'a Trials)]) -> Vec<Row<'a>> {
+    results.iter().map(|&(name, trials)| {
+        let (lower, upper) = trials.confidence_interval();
+        Row { name, trials, acceptance_rate: trials.acceptance_rate(), lower, upper }
+    }).collect()
+}
+
+pub fn to_csv(results: &[(&str, &Trials)]) -> String {
+    let mut csv = "name,requested,trials,accepted,seconds,z,acceptance_rate,lower,upper\n".to_string();
+    rows(results).iter().for_each(|row| csv.push_str(&format!("{}\n", row.csv())));
+    csv
+}
+
+pub fn to_json(results: &[(&str, &Trials)]) -> String {
+    serde_json::to_string_pretty(&rows(results)).unwrap()
+}
+
+// ************ Report implementation ************
+
+// A report collects measurements of several configurations of an experiment, e.g. an honest and a
+// cheating GI prover against 1 to 8 sequential repetitions. Along with the acceptance rate and timing,
+// each measurement records the mean number of bytes exchanged per trial, with messages encoded as JSON,
+// and the mean time spent in the prover's and the verifier's code. A verifier that does much less work
+// than the prover shows up as a small verifier share, whatever the total running time.
+
+// Wall-clock and CPU seconds spent in one party's code. CPU time is that of the calling thread, which
+// runs both parties, so it leaves out time spent waiting or on other threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct PartyTime {
+    pub wall: f64,
+    pub cpu: f64,
+}
+
+impl PartyTime {
+    // Runs f, adding the time it took to the entry for the given round, counting from 0
+    fn record<R>(rounds: &mut Vec<PartyTime>, round: usize, f: impl FnOnce() -> R) -> R {
+        let (start, cpu_start) = (Instant::now(), ThreadTime::now());
+        let result = f();
+        if rounds.len() <= round {
+            rounds.resize(round + 1, PartyTime::default());
+        }
+        rounds[round] += PartyTime { wall: start.elapsed().as_secs_f64(), cpu: cpu_start.elapsed().as_secs_f64() };
+        result
+    }
+
+    fn total(rounds: &[PartyTime]) -> PartyTime {
+        rounds.iter().fold(PartyTime::default(), |acc, &x| acc + x)
+    }
+}
+
+impl Add for PartyTime {
+    type Output = PartyTime;
+
+    fn add(self, other: PartyTime) -> PartyTime {
+        PartyTime { wall: self.wall + other.wall, cpu: self.cpu + other.cpu }
+    }
+}
+
+impl AddAssign for PartyTime {
+    fn add_assign(&mut self, other: PartyTime) {
+        *self = *self + other;
+    }
+}
+
+// A prover that counts the bytes of every message it sends and receives, and times each of its rounds
+pub struct MeteredProver<P> {
+    pub inner: P,
+    pub bytes: usize,
+    // Time spent in each call to handle
+    pub rounds: Vec<PartyTime>,
+    round: usize,
+}
+
+impl<P> MeteredProver<P> {
+    pub fn new(inner: P) -> MeteredProver<P> {
+        MeteredProver { inner, bytes: 0, rounds: Vec::new(), round: 0 }
+    }
+}
+
+// A verifier that times each of its rounds. Its first message counts towards round 0, and the prover's
+// message of each round, from validation to the reply, towards that round.
+pub struct MeteredVerifier<V> {
+    pub inner: V,
+    pub rounds: Vec<PartyTime>,
+    round: usize,
+    // Time spent validating the message handle is about to be called with
+    validation: Cell<PartyTime>,
+}
+
+impl<V> MeteredVerifier<V> {
+    pub fn new(inner: V) -> MeteredVerifier<V> {
+        MeteredVerifier { inner, rounds: Vec::new(), round: 0, validation: Cell::new(PartyTime::default()) }
+    }
+}
+
+impl<V: Verifier> Verifier for MeteredVerifier<V> {
+    type ProverMessage = V::ProverMessage;
+    type VerifierMessage = V::VerifierMessage;
+
+    fn init(&mut self) -> V::VerifierMessage {
+        let inner = &mut self.inner;
+        PartyTime::record(&mut self.rounds, 0, || inner.init())
+    }
+
+    // The runner validates each prover message before handing it over, but validate takes &self, so its
+    // time is held until handle adds it to the round. A message that fails validation is never handled,
+    // and the interaction ends without its validation being counted.
+    fn validate(&self, msg: &V::ProverMessage) -> Result<(), String> {
+        let mut validation = Vec::new();
+        let result = PartyTime::record(&mut validation, 0, || self.inner.validate(msg));
+        self.validation.set(self.validation.get() + validation[0]);
+        result
+    }
+
+    fn diagnose(&self, msg: &V::ProverMessage) -> Option<Diagnosis> {
+        self.inner.diagnose(msg)
+    }
+
+    fn handle(&mut self, msg: &V::ProverMessage) -> (V::VerifierMessage, bool) {
+        self.round += 1;
+        let inner = &mut self.inner;
+        let reply = PartyTime::record(&mut self.rounds, self.round, || inner.handle(msg));
+        self.rounds[self.round] += self.validation.take();
+        reply
+    }
+}
+
+fn encoded_len<T: Serialize>(msg: &T) -> usize {
+    serde_json::to_vec(msg).map_or(0, |bytes| bytes.len())
+}
+
+impl<P: Prover> Prover for MeteredProver<P> where P::ProverMessage: Serialize, P::VerifierMessage: Serialize {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        self.round += 1;
+        let inner = &mut self.inner;
+        let (reply, done) = PartyTime::record(&mut self.rounds, self.round, || inner.handle(msg));
+        self.bytes += encoded_len(msg);
+        // The message sent along with the done flag never reaches the verifier
+        if !done {
+            self.bytes += encoded_len(&reply);
+        }
+        (reply, done)
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+}
+
+// Cost of one or more interactions played in sequence
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metered {
+    pub accept: bool,
+    pub bytes: usize,
+    // Number of prover messages the verifier handled, which is the number of round trips
+    pub messages: usize,
+    // Time spent in each party's code on each round, with the rounds of later interactions after those
+    // of earlier ones
+    pub prover: Vec<PartyTime>,
+    pub verifier: Vec<PartyTime>,
+}
+
+// Plays one interaction, returning whether the verifier accepted, the number of bytes exchanged, and the
+// time each party spent on each round
+pub fn run_metered<P: Prover, V: Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>>(prover: P, verifier: V) -> Metered
+where P::ProverMessage: Serialize, P::VerifierMessage: Serialize {
+    let (mut prover, mut verifier) = (MeteredProver::new(prover), MeteredVerifier::new(verifier));
+    let accept = try_run_interactive_proof(&mut prover, &mut verifier).unwrap_or(false);
+    // The verifier's first round is its opening message, and each one after handles a prover message
+    let messages = verifier.rounds.len().saturating_sub(1);
+    Metered { accept, bytes: prover.bytes, messages, prover: prover.rounds, verifier: verifier.rounds }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Measurement {
+    pub name: String,
+    // Number of sequential repetitions of the protocol in each trial
+    pub rounds: u32,
+    #[serde(flatten)]
+    pub trials: Trials,
+    // Mean number of prover messages the verifier handled per trial, and of bytes exchanged
+    pub messages: f64,
+    pub message_bytes: f64,
+    // Mean time per trial spent in the prover's and the verifier's code
+    pub prover_time: PartyTime,
+    pub verifier_time: PartyTime,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub measurements: Vec<Measurement>,
+}
+
+#[derive(Serialize)]
+struct MeasurementRow<'a> {
+    #[serde(flatten)]
+    row: Row<'a>,
+    rounds: u32,
+    messages: f64,
+    message_bytes: f64,
+    prover_time: PartyTime,
+    verifier_time: PartyTime,
+}
+
+impl Report {
+    // Runs the trial n times and records the results under the given name. Each trial plays one or more
+    // interactions and returns what they cost, e.g. with run_metered.
+    pub fn measure<F: Fn() -> Metered + Sync>(&mut self, name: &str, rounds: u32, n: usize, trial: F) -> &Measurement {
+        let bytes = AtomicUsize::new(0);
+        let messages = AtomicUsize::new(0);
+        let times = Mutex::new((PartyTime::default(), PartyTime::default()));
+        let trials = run_trials(n, || {
+            let metered = trial();
+            bytes.fetch_add(metered.bytes, Ordering::Relaxed);
+            messages.fetch_add(metered.messages, Ordering::Relaxed);
+            let mut times = times.lock().unwrap();
+            times.0 += PartyTime::total(&metered.prover);
+            times.1 += PartyTime::total(&metered.verifier);
+            metered.accept
+        });
+        let count = trials.trials.max(1) as f64;
+        let mean = |x: PartyTime| PartyTime { wall: x.wall / count, cpu: x.cpu / count };
+        let (prover, verifier) = times.into_inner().unwrap();
+        let (messages, message_bytes) = (messages.into_inner() as f64 / count, bytes.into_inner() as f64 / count);
+        self.measurements.push(Measurement { name: name.to_string(), rounds, trials, messages, message_bytes, prover_time: mean(prover), verifier_time: mean(verifier) });
+        self.measurements.last().unwrap()
+    }
+
+    fn rows(&self) -> Vec<MeasurementRow<'_>> {
+        self.measurements.iter().map(|m| {
+            let row = rows(&[(&m.name, &m.trials)]).remove(0);
+            MeasurementRow { row, rounds: m.rounds, messages: m.messages, message_bytes: m.message_bytes, prover_time: m.prover_time, verifier_time: m.verifier_time }
+        }).collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "name,requested,trials,accepted,seconds,z,acceptance_rate,lower,upper,rounds,messages,message_bytes,prover_wall,prover_cpu,verifier_wall,verifier_cpu\n".to_string();
+        self.rows().iter().for_each(|m| {
+            csv.push_str(&format!("{},{},{},{},{},{},{},{}\n", m.row.csv(), m.rounds, m.messages, m.message_bytes, m.prover_time.wall, m.prover_time.cpu, m.verifier_time.wall, m.verifier_time.cpu));
+        });
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.rows()).unwrap()
+    }
+}
+
+// ************ Soundness curve implementation ************
+
+// Measures how the acceptance rate of honest and cheating provers changes with the number of sequential
+// repetitions of a protocol. An honest prover should always be accepted, while a cheating prover should
+// be accepted with probability 2^-k after k repetitions.
+
+pub const BENCHED_PROTOCOLS: &[&str] = &["gi", "gni"];
+
+// Plays the interaction returned by play the given number of times in sequence, stopping at the first
+// rejection as a verifier would
+pub fn repeat<F: FnMut() -> Metered>(rounds: u32, mut play: F) -> Metered {
+    let mut total = Metered { accept: true, ..Metered::default() };
+    for _ in 0..rounds {
+        let metered = play();
+        total.bytes += metered.bytes;
+        total.messages += metered.messages;
+        total.prover.extend(metered.prover);
+        total.verifier.extend(metered.verifier);
+        if !metered.accept {
+            total.accept = false;
+            break;
+        }
+    }
+    total
+}
+
+fn bench_instance(isomorphic: bool) -> GraphPair {
+    let g1 = if isomorphic {
+        Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)])
+    } else {
+        Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)])
+    };
+    GraphPair { g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]), g1 }
+}
+
+// Measures "<protocol>/honest" and "<protocol>/cheating" for 1 to max_rounds repetitions, n trials each
+pub fn soundness_vs_rounds(protocol: &str, max_rounds: u32, n: usize) -> Result<Report, String> {
+    soundness_vs_rounds_on(protocol, &bench_instance(true), &bench_instance(false), max_rounds, n)
+}
+
+// Checks that the instance is a pair of graphs on the same vertices that are isomorphic, within the budget
+// the honest GI prover searches with, so that the honest prover has an isomorphism to prove
+pub fn check_isomorphic(instance: &GraphPair) -> Result<(), String> {
+    check_vertex_counts(instance)?;
+    match decide(&instance.g0, &instance.g1, DecisionMode::Budgeted(SearchBudget::default())) {
+        Decision::Isomorphic(_) => Ok(()),
+        Decision::NonIsomorphic => Err("the isomorphic instance's graphs aren't isomorphic".to_string()),
+        Decision::Unknown => Err("the isomorphic instance couldn't be decided within the search budget of the honest prover".to_string()),
+    }
+}
+
+// Checks that the instance is a pair of graphs on the same vertices that isn't known to be isomorphic.
+// Instances the search can't decide are let through, since hard non-isomorphic pairs are the point of
+// loading instances.
+pub fn check_nonisomorphic(instance: &GraphPair) -> Result<(), String> {
+    check_vertex_counts(instance)?;
+    match decide(&instance.g0, &instance.g1, DecisionMode::Budgeted(SearchBudget::default())) {
+        Decision::Isomorphic(_) => Err("the non-isomorphic instance's graphs are isomorphic".to_string()),
+        _ => Ok(()),
+    }
+}
+
+// Checks that the instance's graphs are on the same vertices, which any GI or GNI party needs to compare them
+pub fn check_vertex_counts(instance: &GraphPair) -> Result<(), String> {
+    if instance.g0.n != instance.g1.n {
+        return Err(format!("the instance's graphs have {} and {} vertices", instance.g0.n, instance.g1.n));
+    }
+    Ok(())
+}
+
+// Like soundness_vs_rounds, on the given instances instead of the built-in 4-vertex ones, e.g. ones loaded
+// with io::load_instance. Honest provers are run on the instance the protocol is about, and cheating
+// provers on the other one, so the instances are checked with check_isomorphic and check_nonisomorphic
+// before any trial is run.
+pub fn soundness_vs_rounds_on(protocol: &str, isomorphic: &GraphPair, nonisomorphic: &GraphPair, max_rounds: u32, n: usize) -> Result<Report, String> {
+    check_isomorphic(isomorphic)?;
+    check_nonisomorphic(nonisomorphic)?;
+    let mut report = Report::default();
+    for k in 1..=max_rounds {
+        let honest = format!("{}/honest", protocol);
+        let cheating = format!("{}/cheating", protocol);
+        match protocol {
+            "gi" => {
+                let verifier = |instance| GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+                report.measure(&honest, k, n, || repeat(k, || {
+                    run_metered(GIProver::new(isomorphic), verifier(isomorphic))
+                }));
+                report.measure(&cheating, k, n, || repeat(k, || {
+                    run_metered(GIProverMalicious::new(nonisomorphic), verifier(nonisomorphic))
+                }));
+            },
+            "gni" => {
+                report.measure(&honest, k, n, || repeat(k, || {
+                    run_metered(GNIProver { sent_guess: false, instance: nonisomorphic }, GNIVerifier { b: false, instance: nonisomorphic })
+                }));
+                report.measure(&cheating, k, n, || repeat(k, || {
+                    run_metered(GNIProverMalicious { sent_guess: false, p: 0.5 }, GNIVerifier { b: false, instance: isomorphic })
+                }));
+            },
+            _ => return Err(format!("unknown protocol {}, expected one of {}", protocol, BENCHED_PROTOCOLS.join(", "))),
+        }
+    }
+    Ok(report)
+}
+
+// Name of a malicious GI prover's configuration in reports, e.g. "random-p0.9"
+fn adversary_name(strategy: GuessStrategy, p: f64) -> String {
+    match strategy {
+        GuessStrategy::Random => format!("random-p{}", p),
+        GuessStrategy::AlwaysZero => "always-zero".to_string(),
+        GuessStrategy::ReusePrevious => format!("reuse-previous-p{}", p),
+    }
+}
+
+// Measures "gi/<adversary>" for malicious GI provers with each strategy and probability of guessing 1, for
+// 1 to max_rounds repetitions on an instance that isn't in GI. Each trial keeps one prover for all of its
+// repetitions, so a prover reusing its permutation really sends the same graph every time. Against an
+// honest verifier, whose challenge is independent of the graph, every adversary is accepted 2^-k of the
+// time.
+pub fn gi_adversaries_vs_rounds(nonisomorphic: &GraphPair, adversaries: &[(GuessStrategy, f64)], max_rounds: u32, n: usize) -> Result<Report, String> {
+    let provers = adversaries.iter().map(|&(strategy, p)| GIProverMalicious::with_strategy(nonisomorphic, p, strategy)).collect::<Result<Vec<_>, _>>()?;
+    let mut report = Report::default();
+    for k in 1..=max_rounds {
+        for prover in &provers {
+            report.measure(&format!("gi/{}", adversary_name(prover.strategy, prover.p)), k, n, || {
+                let mut prover = prover.clone();
+                repeat(k, || {
+                    prover.r = 0;
+                    run_metered(&mut prover, GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: nonisomorphic })
+                })
+            });
+        }
+    }
+    Ok(report)
+}
+
+// Measures "gi/bias-q<q>" for 1 to max_rounds repetitions against a GI verifier that challenges with g1
+// with probability q, see GIVerifierBiased. The cheating prover knows the bias and always guesses the
+// likelier challenge, so it is accepted max(q, 1 - q)^k of the time rather than 2^-k, and a verifier with
+// q = 0 or 1 gives no soundness at all.
+pub fn soundness_vs_bias(biases: &[f64], max_rounds: u32, n: usize) -> Result<Report, String> {
+    bias_report(&bench_instance(false), biases, max_rounds, n)
+}
+
+// Like soundness_vs_bias, on the given instance that isn't in GI, checked with check_nonisomorphic
+pub fn soundness_vs_bias_on(nonisomorphic: &GraphPair, biases: &[f64], max_rounds: u32, n: usize) -> Result<Report, String> {
+    check_nonisomorphic(nonisomorphic)?;
+    bias_report(nonisomorphic, biases, max_rounds, n)
+}
+
+// Fails before any trials if a bias isn't a probability
+fn bias_report(nonisomorphic: &GraphPair, biases: &[f64], max_rounds: u32, n: usize) -> Result<Report, String> {
+    let rngs = biases.iter().map(|&q| BiasedRng::new(q)).collect::<Result<Vec<_>, _>>()?;
+    let mut report = Report::default();
+    for k in 1..=max_rounds {
+        for &rng in &rngs {
+            let p = if rng.q() >= 0.5 { 1.0 } else { 0.0 };
+            report.measure(&format!("gi/bias-q{}", rng.q()), k, n, || repeat(k, || {
+                let prover = GIProverMalicious { p, ..GIProverMalicious::new(nonisomorphic) };
+                run_metered(prover, GIVerifierBiased { inner: GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: nonisomorphic }, rng })
+            }));
+        }
+    }
+    Ok(report)
+}
+
+// ************ Growth analysis implementation ************
+
+// Measures how the cost of a protocol grows with the size of its instance, so that protocols can be
+// compared by their curves rather than by one instance. For each size, honest parties are run on a fresh
+// instance of that size, one trial at a time so that timings don't compete for cores, and the mean bytes
+// exchanged, prover messages, and time in each party's code are reported. Exponents fitted to each curve
+// summarize it, e.g. a proof whose size grows linearly has a size exponent near 1.
+//
+// Sizes are the number of vertices for GI and GNI, and the degree bound for FRI, rounded up to a power of
+// two, with blowup 4 and 20 queries. GI and GNI run on random graphs with each directed edge present with
+// probability 1/2: the honest GI prover finds its isomorphisms by search, and sparse graphs leave it too
+// little to prune with beyond a few hundred vertices. The GNI graphs are redrawn until they are decided
+// non-isomorphic, since the honest GNI prover can't answer otherwise.
+
+pub const ANALYZED_PROTOCOLS: &[&str] = &["gi", "gni", "fri"];
+
+const FRI_BLOWUP: usize = 4;
+
+// Largest FRI degree bound whose evaluation domain fits in a subgroup of the field
+const MAX_FRI_DEGREE: usize = 1 << (TWO_ADICITY - FRI_BLOWUP.trailing_zeros());
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GrowthPoint {
+    pub size: usize,
+    pub trials: usize,
+    // Number of trials the verifier accepted, which should be all of them for honest parties
+    pub accepted: usize,
+    // Mean number of messages the prover sent per trial, and of bytes exchanged
+    pub rounds: f64,
+    pub proof_bytes: f64,
+    // Mean time per trial spent in the prover's and the verifier's code
+    pub prover_time: PartyTime,
+    pub verifier_time: PartyTime,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Growth {
+    pub protocol: String,
+    pub points: Vec<GrowthPoint>,
+}
+
+impl Growth {
+    // Slope of log(metric) against log(size) by least squares, or None without two distinct sizes with a
+    // positive metric
+    pub fn exponent<F: Fn(&GrowthPoint) -> f64>(&self, metric: F) -> Option<f64> {
+        let logs: Vec<(f64, f64)> = self.points.iter().filter(|p| metric(p) > 0.0).map(|p| ((p.size as f64).ln(), metric(p).ln())).collect();
+        let n = logs.len() as f64;
+        let (mean_x, mean_y) = (logs.iter().map(|x| x.0).sum::<f64>() / n, logs.iter().map(|x| x.1).sum::<f64>() / n);
+        let spread: f64 = logs.iter().map(|x| (x.0 - mean_x).powi(2)).sum();
+        if logs.len() < 2 || spread == 0.0 {
+            return None;
+        }
+        Some(logs.iter().map(|x| (x.0 - mean_x) * (x.1 - mean_y)).sum::<f64>() / spread)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "protocol,size,trials,accepted,rounds,proof_bytes,prover_wall,prover_cpu,verifier_wall,verifier_cpu\n".to_string();
+        for p in &self.points {
+            csv.push_str(&format!("{},{},{},{},{},{},{},{},{},{}\n", self.protocol, p.size, p.trials, p.accepted, p.rounds, p.proof_bytes,
+                p.prover_time.wall, p.prover_time.cpu, p.verifier_time.wall, p.verifier_time.cpu));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// Random graph on n vertices with each directed edge present with probability 1/2
+fn random_graph(n: u32) -> Graph {
+    let mut rng = checked_thread_rng();
+    Graph::new(n, (0..n).flat_map(|a| (0..n).map(move |b| (a, b))).filter(|_| rng.gen_bool(0.5)).collect())
+}
+
+// Pair of random graphs on n vertices that aren't isomorphic
+fn random_nonisomorphic_pair(n: u32) -> GraphPair {
+    let g0 = random_graph(n);
+    loop {
+        let g1 = random_graph(n);
+        if decide(&g0, &g1, DecisionMode::Exact).is_isomorphic() == Some(false) {
+            return GraphPair { g0, g1 };
+        }
+    }
+}
+
+// Runs honest parties of the protocol trials times on an instance of each size
+pub fn analyze(protocol: &str, sizes: &[usize], trials: usize) -> Result<Growth, String> {
+    let max_size = match protocol {
+        "gi" | "gni" => MAX_VERTICES as usize,
+        "fri" => MAX_FRI_DEGREE,
+        _ => return Err(format!("unknown protocol {}, expected one of {}", protocol, ANALYZED_PROTOCOLS.join(", "))),
+    };
+    if let Some(size) = sizes.iter().find(|&&size| size < 2 || size > max_size) {
+        return Err(format!("size {} is out of range for {}, expected 2 to {}", size, protocol, max_size));
+    }
+    let mut growth = Growth { protocol: protocol.to_string(), points: Vec::new() };
+    for &size in sizes {
+        let results: Vec<Metered> = match protocol {
+            "gi" => {
+                let g0 = random_graph(size as u32);
+                let instance = GraphPair { g1: g0.permute(&Permutation::random(g0.n, &mut checked_thread_rng())), g0 };
+                (0..trials).map(|_| run_metered(GIProver::new(&instance), GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance })).collect()
+            },
+            "gni" => {
+                let instance = random_nonisomorphic_pair(size as u32);
+                (0..trials).map(|_| run_metered(GNIProver { sent_guess: false, instance: &instance }, GNIVerifier { b: false, instance: &instance })).collect()
+            },
+            _ => {
+                let fri = Fri::new(size.next_power_of_two(), FRI_BLOWUP, 20);
+                let coefficients: Vec<Fp> = (0..fri.degree_bound).map(|_| Fp::new(checked_thread_rng().gen())).collect();
+                (0..trials).map(|_| {
+                    let mut prover = FriProver { fri: fri.clone(), layer: evaluate_on_coset(&coefficients, Fri::OFFSET, fri.domain_size) };
+                    let (prover, verifier) = iop_to_interactive(&fri, &mut prover);
+                    run_metered(prover, verifier)
+                }).collect()
+            },
+        };
+        let count = trials.max(1) as f64;
+        let mean = |times: Vec<PartyTime>| { let total = PartyTime::total(&times); PartyTime { wall: total.wall / count, cpu: total.cpu / count } };
+        growth.points.push(GrowthPoint {
+            size,
+            trials,
+            accepted: results.iter().filter(|x| x.accept).count(),
+            rounds: results.iter().map(|x| x.messages).sum::<usize>() as f64 / count,
+            proof_bytes: results.iter().map(|x| x.bytes).sum::<usize>() as f64 / count,
+            prover_time: mean(results.iter().flat_map(|x| x.prover.clone()).collect()),
+            verifier_time: mean(results.iter().flat_map(|x| x.verifier.clone()).collect()),
+        });
+    }
+    Ok(growth)
+}
+
+// ************ Isomorphism benchmark implementation ************
+
+// Times isomorphism decisions on benchmark graphs, such as the bliss and nauty collections loaded with
+// io::load_dataset, to compare isomorphism engines on standard hard instances. Each graph is decided
+// against a random relabeling of itself, so the right answer is always isomorphic, and graphs run one at
+// a time so that their timings don't compete for cores.
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IsomorphismRun {
+    pub name: String,
+    pub vertices: u32,
+    pub edges: usize,
+    // "isomorphic", "non-isomorphic", or "unknown", where "non-isomorphic" means the engine is wrong
+    pub decision: &'static str,
+    pub seconds: f64,
+}
+
+pub fn bench_isomorphism(dataset: &[(String, Graph)], mode: DecisionMode) -> Vec<IsomorphismRun> {
+    dataset.iter().map(|(name, graph)| {
+        let relabeled = graph.permute(&Permutation::random(graph.n, &mut checked_thread_rng()));
+        let start = Instant::now();
+        let decision = decide(graph, &relabeled, mode);
+        let seconds = start.elapsed().as_secs_f64();
+        let decision = match decision {
+            Decision::Isomorphic(isomorphism) => {
+                assert!(graph.permute(&isomorphism) == relabeled, "Decided {} with an isomorphism that doesn't relabel it", name);
+                "isomorphic"
+            }
+            Decision::NonIsomorphic => "non-isomorphic",
+            Decision::Unknown => "unknown",
+        };
+        IsomorphismRun { name: name.clone(), vertices: graph.n, edges: graph.edge_count(), decision, seconds }
+    }).collect()
+}
+
+// ************ Timing leakage implementation ************
+
+// A party whose response time depends on a secret leaks it to anyone who can time its messages, e.g. on
+// the network between a remote prover and the server. The honest GNI prover is an example: it answers
+// the verifier's graph H by deciding whether H is isomorphic to G1. When H is a copy of G1 it has to find
+// an isomorphism, while a copy of G0 is usually told apart from G1 by color refinement alone, so a quick
+// answer gives away the verifier's bit b. The verifier's first message can leak b too, since permuting
+// G0 takes longer than permuting G1 when G0 has more edges.
+//
+// measure_gni_timing plays the protocol one trial at a time, so that timings don't compete for cores, and
+// splits each party's response times by the verifier's bit. Welch's t-test then tells whether the two
+// classes differ, in the style of dudect: |t| above LEAKAGE_THRESHOLD is far beyond what chance produces
+// over any reasonable number of trials. The slowest few percent of samples, mostly from the thread being
+// preempted, are dropped first. PaddedProver and PaddedVerifier mitigate the leak by holding back every
+// response until a fixed time after the message it answers arrived. Padding only hides work that fits in
+// it, so responses that overrun the pad are counted.
+
+pub const LEAKAGE_THRESHOLD: f64 = 4.5;
+
+// Fraction of the samples, the slowest ones, left out of the test
+const CROPPED: f64 = 0.05;
+
+// Waits until pad has passed since start, returning whether it already had. It spins rather than sleeping,
+// since both how late a thread wakes up and how fast it runs afterwards depend on how long it slept, which
+// is enough to tell the classes apart over a few hundred trials.
+fn pad_until(start: Instant, pad: Duration) -> bool {
+    if start.elapsed() >= pad {
+        return true;
+    }
+    while start.elapsed() < pad {
+        std::hint::spin_loop();
+    }
+    false
+}
+
+// A prover that takes at least pad to answer each message
+pub struct PaddedProver<P> {
+    pub inner: P,
+    pub pad: Duration,
+    // Number of answers that took longer than pad without padding
+    pub overruns: usize,
+}
+
+impl<P> PaddedProver<P> {
+    pub fn new(inner: P, pad: Duration) -> PaddedProver<P> {
+        PaddedProver { inner, pad, overruns: 0 }
+    }
+}
+
+impl<P: Prover> Prover for PaddedProver<P> {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        let start = Instant::now();
+        let reply = self.inner.handle(msg);
+        self.overruns += pad_until(start, self.pad) as usize;
+        reply
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+}
+
+// A verifier that takes at least pad to produce each of its messages, including the first
+pub struct PaddedVerifier<V> {
+    pub inner: V,
+    pub pad: Duration,
+    pub overruns: usize,
+}
+
+impl<V> PaddedVerifier<V> {
+    pub fn new(inner: V, pad: Duration) -> PaddedVerifier<V> {
+        PaddedVerifier { inner, pad, overruns: 0 }
+    }
+}
+
+impl<V: Verifier> Verifier for PaddedVerifier<V> {
+    type ProverMessage = V::ProverMessage;
+    type VerifierMessage = V::VerifierMessage;
+
+    fn init(&mut self) -> V::VerifierMessage {
+        let start = Instant::now();
+        let msg = self.inner.init();
+        self.overruns += pad_until(start, self.pad) as usize;
+        msg
+    }
+
+    fn validate(&self, msg: &V::ProverMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+
+    fn diagnose(&self, msg: &V::ProverMessage) -> Option<Diagnosis> {
+        self.inner.diagnose(msg)
+    }
+
+    fn handle(&mut self, msg: &V::ProverMessage) -> (V::VerifierMessage, bool) {
+        let start = Instant::now();
+        let reply = self.inner.handle(msg);
+        self.overruns += pad_until(start, self.pad) as usize;
+        reply
+    }
+}
+
+// Response times, in seconds, of one party when the secret bit had one value
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TimingClass {
+    pub samples: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl TimingClass {
+    fn new(samples: &[f64]) -> TimingClass {
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n.max(1.0);
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+        TimingClass { samples: samples.len(), mean, std_dev: variance.sqrt() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Leakage {
+    // Party whose response times were measured
+    pub party: String,
+    // Response times when the secret bit was 0 and when it was 1
+    pub classes: [TimingClass; 2],
+    // Welch's t statistic for the difference in mean response time, positive if 1 is slower
+    pub t: f64,
+    // Number of responses that took longer than the pad, if any
+    pub overruns: usize,
+}
+
+impl Leakage {
+    // Splits the samples by secret bit, drops the slowest and tests whether the classes differ
+    pub fn test(party: &str, samples: &[(bool, f64)], overruns: usize) -> Leakage {
+        let mut times: Vec<f64> = samples.iter().map(|x| x.1).collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let cutoff = times.get(((times.len() as f64) * (1.0 - CROPPED)) as usize).copied().unwrap_or(f64::INFINITY);
+        let class = |bit: bool| TimingClass::new(&samples.iter().filter(|x| x.0 == bit && x.1 < cutoff).map(|x| x.1).collect::<Vec<f64>>());
+        let classes = [class(false), class(true)];
+        let se = (classes[0].std_dev.powi(2) / classes[0].samples.max(1) as f64 + classes[1].std_dev.powi(2) / classes[1].samples.max(1) as f64).sqrt();
+        let difference = classes[1].mean - classes[0].mean;
+        let t = if se > 0.0 { difference / se } else if difference == 0.0 { 0.0 } else { difference.signum() * f64::INFINITY };
+        Leakage { party: party.to_string(), classes, t, overruns }
+    }
+
+    pub fn leaks(&self) -> bool {
+        self.t.abs() > LEAKAGE_THRESHOLD
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub trials: usize,
+    // Time every response was padded to, in seconds
+    pub pad: Option<f64>,
+    // Whether the prover answered by comparing canonical forms
+    pub canonical: bool,
+    pub verifier: Leakage,
+    pub prover: Leakage,
+}
+
+impl TimingReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// Sparse and dense random graphs on n vertices, which refinement tells apart at once and which take
+// different times to permute, so that both parties leak the verifier's bit
+pub fn gni_timing_instance(n: u32) -> GraphPair {
+    let mut rng = checked_thread_rng();
+    let sparse = Graph::new(n, (0..n).flat_map(|a| (0..n).map(move |b| (a, b))).filter(|_| rng.gen_bool(0.1)).collect());
+    GraphPair { g0: sparse, g1: random_graph(n) }
+}
+
+// Times the GNI verifier's first message and the honest prover's answer to it over n trials, split by the
+// verifier's bit, with every response padded to pad if given. The prover is graph::GNIProver, or
+// graph::CanonicalGNIProver if canonical is set.
+pub fn measure_gni_timing(instance: &GraphPair, n: usize, pad: Option<Duration>, canonical: bool) -> Result<TimingReport, String> {
+    // The honest prover would be rejected on an instance it can't tell apart
+    if instance.g0.n != instance.g1.n || decide(&instance.g0, &instance.g1, DecisionMode::default()).is_isomorphic() != Some(false) {
+        return Err("GNI timing needs two graphs of the same size that aren't isomorphic".to_string());
+    }
+    // Narration would be timed along with the parties' work
+    let mut report = config::without_narration(|| if canonical {
+        // The canonical forms are computed once, outside the timed responses
+        let prover = CanonicalGNIProver::new(instance, SearchBudget::default())?;
+        time_gni(instance, n, pad, || prover.clone())
+    } else {
+        time_gni(instance, n, pad, || GNIProver { sent_guess: false, instance })
+    })?;
+    report.canonical = canonical;
+    Ok(report)
+}
+
+fn time_gni<P, F>(instance: &GraphPair, n: usize, pad: Option<Duration>, new_prover: F) -> Result<TimingReport, String>
+where P: Prover<ProverMessage = GNIProverMessage, VerifierMessage = GNIVerifierMessage>, F: Fn() -> P {
+    let pad_or_zero = pad.unwrap_or(Duration::ZERO);
+    let (mut verifier_samples, mut prover_samples) = (Vec::with_capacity(n), Vec::with_capacity(n));
+    let (mut verifier_overruns, mut prover_overruns) = (0, 0);
+    for _ in 0..n {
+        let mut prover = MeteredProver::new(PaddedProver::new(new_prover(), pad_or_zero));
+        let mut verifier = MeteredVerifier::new(PaddedVerifier::new(GNIVerifier { b: false, instance }, pad_or_zero));
+        // An honest prover is only rejected if its isomorphism search runs out of budget
+        if !try_run_interactive_proof(&mut prover, &mut verifier).unwrap_or(false) {
+            return Err("Honest GNI prover was rejected, so the instance is too hard to decide".to_string());
+        }
+        let b = verifier.inner.inner.b;
+        // The verifier's first message is its round 0, and the prover's guess its round 1
+        verifier_samples.push((b, verifier.rounds[0].wall));
+        prover_samples.push((b, prover.rounds[1].wall));
+        verifier_overruns += verifier.inner.overruns;
+        prover_overruns += prover.inner.overruns;
+    }
+    // Without padding, every response counts as an overrun
+    let counted = |overruns: usize| if pad.is_some() { overruns } else { 0 };
+    Ok(TimingReport {
+        trials: n,
+        pad: pad.map(|pad| pad.as_secs_f64()),
+        canonical: false,
+        verifier: Leakage::test("verifier", &verifier_samples, counted(verifier_overruns)),
+        prover: Leakage::test("prover", &prover_samples, counted(prover_overruns)),
+    })
+}
+
+// ************ Plotting implementation ************
+
+// Renders the acceptance rate of every configuration in a report against the number of repetitions as an
+// SVG chart, with one line per configuration and error bars for the confidence intervals
+#[cfg(feature = "plot")]
+impl Report {
+    pub fn plot(&self, title: &str, path: &Path) -> Result<(), String> {
+        use plotters::prelude::*;
+
+        let max_rounds = self.measurements.iter().map(|m| m.rounds).max().unwrap_or(1);
+        let root = SVGBackend::new(path, (800, 600)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(1u32..max_rounds.max(2), 0.0..1.0)
+            .map_err(|e| e.to_string())?;
+        chart.configure_mesh().x_desc("repetitions").y_desc("acceptance rate").draw().map_err(|e| e.to_string())?;
+
+        let names: Vec<&str> = self.measurements.iter().map(|m| m.name.as_str()).unique().collect();
+        for (i, &name) in names.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            let points: Vec<&Measurement> = self.measurements.iter().filter(|m| m.name == name).collect();
+            chart.draw_series(LineSeries::new(points.iter().map(|m| (m.rounds, m.trials.acceptance_rate())), color.stroke_width(2)))
+                .map_err(|e| e.to_string())?
+                .label(name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            chart.draw_series(points.iter().map(|m| {
+                let (lower, upper) = m.trials.confidence_interval();
+                ErrorBar::new_vertical(m.rounds, lower, m.trials.acceptance_rate(), upper, color.filled(), 8)
+            })).map_err(|e| e.to_string())?;
+        }
+        chart.configure_series_labels().background_style(WHITE).border_style(BLACK).draw().map_err(|e| e.to_string())?;
+        root.present().map_err(|e| e.to_string())
+    }
+}
+
+#[test]
+fn test_run_trials() {
+    // Every fourth trial is accepted
+    let count = AtomicUsize::new(0);
+    let trials = run_trials(1000, || count.fetch_add(1, Ordering::Relaxed).is_multiple_of(4));
+    assert_eq!((trials.requested, trials.trials, trials.accepted), (1000, 1000, 250));
+    let (lower, upper) = trials.confidence_interval();
+    assert!(lower < 0.25 && 0.25 < upper);
+}
+
+#[test]
+fn test_metered_validation() {
+    // A verifier whose validation is slow, which must show up in the rounds it validates
+    struct SlowValidation<'a>(GIVerifier<'a>);
+    impl Verifier for SlowValidation<'_> {
+        type ProverMessage = crate::graph::GIProverMessage;
+        type VerifierMessage = crate::graph::GIVerifierMessage;
+        fn init(&mut self) -> Self::VerifierMessage { self.0.init() }
+        fn validate(&self, msg: &Self::ProverMessage) -> Result<(), String> {
+            std::thread::sleep(Duration::from_millis(20));
+            self.0.validate(msg)
+        }
+        fn handle(&mut self, msg: &Self::ProverMessage) -> (Self::VerifierMessage, bool) { self.0.handle(msg) }
+    }
+    let instance = GraphPair { g0: Graph::new(3, vec![(0, 1)]), g1: Graph::new(3, vec![(1, 2)]) };
+    let verifier = SlowValidation(GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance });
+    let metered = run_metered(GIProver::new(&instance), verifier);
+    assert!(metered.accept);
+    assert!(metered.verifier[0].wall < 0.02 && metered.verifier[1..].iter().all(|round| round.wall >= 0.02));
+}
+
+#[test]
+fn test_early_stop() {
+    // A trial that always fails is separated from 1/2 long before a million trials
+    let reports = AtomicUsize::new(0);
+    let options = TrialOptions {
+        early_stop: EarlyStop::Separates(0.5),
+        batch: 10,
+        progress: Some(Box::new(|_| { reports.fetch_add(1, Ordering::Relaxed); })),
+        ..TrialOptions::default()
+    };
+    let trials = run_trials_with(1_000_000, || false, &options);
+    assert!(trials.stopped_early());
+    assert_eq!(trials.trials, 10 * reports.load(Ordering::Relaxed));
+    assert!(trials.confidence_interval().1 < 0.5);
+}
+
+#[test]
+fn test_export() {
+    let trials = Trials { requested: 4, trials: 4, accepted: 1, seconds: 0.5, z: 2.0 };
+    let csv = to_csv(&[("cheating", &trials)]);
+    assert!(csv.lines().nth(1).unwrap().starts_with("cheating,4,4,1,0.5,2,0.25,"));
+    let json: serde_json::Value = serde_json::from_str(&to_json(&[("cheating", &trials)])).unwrap();
+    assert_eq!(json[0]["name"], "cheating");
+    assert_eq!(json[0]["accepted"], 1);
+    assert_eq!(json[0]["acceptance_rate"], 0.25);
+}
+
+#[test]
+fn test_report() {
+    let mut report = Report::default();
+    let time = PartyTime { wall: 0.5, cpu: 0.25 };
+    report.measure("always", 2, 10, || Metered { accept: true, bytes: 3, messages: 4, prover: vec![time, time], verifier: vec![time] });
+    report.measure("never", 2, 10, Metered::default);
+    assert_eq!((report.measurements[0].messages, report.measurements[0].message_bytes), (4.0, 3.0));
+    assert_eq!(report.measurements[0].prover_time, PartyTime { wall: 1.0, cpu: 0.5 });
+    assert_eq!(report.measurements[0].verifier_time, time);
+    let csv = report.to_csv();
+    assert!(csv.lines().nth(1).unwrap().starts_with("always,10,10,10,"));
+    assert!(csv.lines().nth(1).unwrap().ends_with(",2,4,3,1,0.5,0.5,0.25"));
+    assert!(csv.lines().nth(2).unwrap().ends_with(",2,0,0,0,0,0,0"));
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json[1]["name"], "never");
+    assert_eq!(json[1]["rounds"], 2);
+    assert_eq!(json[0]["messages"], 4.0);
+    assert_eq!(json[1]["acceptance_rate"], 0.0);
+    assert_eq!(json[0]["verifier_time"]["cpu"], 0.25);
+}
+
+#[test]
+fn test_soundness_vs_rounds() {
+    let report = soundness_vs_rounds("gi", 3, 200).unwrap();
+    assert_eq!(report.measurements.len(), 6);
+    for m in &report.measurements {
+        if m.name == "gi/honest" {
+            assert_eq!(m.trials.accepted, m.trials.trials);
+        } else {
+            // A cheating prover survives k repetitions with probability 2^-k
+            assert!((m.trials.acceptance_rate() - 0.5f64.powi(m.rounds as i32)).abs() < 0.15);
+        }
+        assert!(m.message_bytes > 0.0);
+        assert!(m.prover_time.wall > 0.0 && m.verifier_time.wall > 0.0);
+    }
+    assert!(soundness_vs_rounds("sudoku", 1, 1).is_err());
+
+    // Loaded instances that don't fit their role are refused before any trial is run
+    let (isomorphic, nonisomorphic) = (bench_instance(true), bench_instance(false));
+    let mismatched = GraphPair { g0: isomorphic.g0.clone(), g1: Graph::new(5, vec![(0, 1)]) };
+    for protocol in BENCHED_PROTOCOLS {
+        assert!(soundness_vs_rounds_on(protocol, &isomorphic, &nonisomorphic, 1, 1).is_ok());
+        assert!(soundness_vs_rounds_on(protocol, &nonisomorphic, &nonisomorphic, 1, 1).is_err());
+        assert!(soundness_vs_rounds_on(protocol, &isomorphic, &isomorphic, 1, 1).is_err());
+        assert!(soundness_vs_rounds_on(protocol, &mismatched, &nonisomorphic, 1, 1).is_err());
+        assert!(soundness_vs_rounds_on(protocol, &isomorphic, &mismatched, 1, 1).is_err());
+    }
+    assert!(soundness_vs_bias_on(&isomorphic, &[0.5], 1, 1).is_err());
+    assert!(soundness_vs_bias_on(&mismatched, &[0.5], 1, 1).is_err());
+}
+
+#[test]
+fn test_gi_adversaries_vs_rounds() {
+    let adversaries = [(GuessStrategy::Random, 1.0), (GuessStrategy::AlwaysZero, 0.5), (GuessStrategy::ReusePrevious, 0.5)];
+    let report = gi_adversaries_vs_rounds(&bench_instance(false), &adversaries, 3, 200).unwrap();
+    assert_eq!(report.measurements.iter().map(|m| m.name.as_str()).take(3).collect::<Vec<_>>(), ["gi/random-p1", "gi/always-zero", "gi/reuse-previous-p0.5"]);
+    for m in &report.measurements {
+        assert!((m.trials.acceptance_rate() - 0.5f64.powi(m.rounds as i32)).abs() < 0.15);
+    }
+}
+
+#[test]
+fn test_soundness_vs_bias() {
+    let report = soundness_vs_bias(&[0.5, 0.75, 1.0], 3, 200).unwrap();
+    assert_eq!(report.measurements.len(), 9);
+    for m in &report.measurements {
+        let q: f64 = m.name.trim_start_matches("gi/bias-q").parse().unwrap();
+        assert!((m.trials.acceptance_rate() - q.powi(m.rounds as i32)).abs() < 0.15);
+    }
+    assert_eq!(soundness_vs_bias(&[0.5, f64::NAN], 3, 200).err(), Some("Bias NaN is not a probability".to_string()));
+}
+
+#[cfg(feature = "plot")]
+#[test]
+fn test_plot() {
+    let report = soundness_vs_rounds("gi", 2, 20).unwrap();
+    let path = std::env::temp_dir().join("zklib_test_plot.svg");
+    report.plot("GI soundness", &path).unwrap();
+    let svg = std::fs::read_to_string(&path).unwrap();
+    assert!(svg.contains("GI soundness") && svg.contains("gi/cheating"));
+}
+
+#[test]
+fn test_bench_isomorphism() {
+    let dataset = vec![
+        ("cycle".to_string(), Graph::new(8, (0..8).map(|v| (v, (v + 1) % 8)).collect())),
+        ("small".to_string(), bench_instance(true).g0),
+    ];
+    let runs = bench_isomorphism(&dataset, DecisionMode::default());
+    assert!(runs.iter().all(|run| run.decision == "isomorphic"));
+    assert_eq!((runs[0].vertices, runs[1].edges), (8, 5));
+    let runs = bench_isomorphism(&dataset, DecisionMode::WLOnly);
+    assert!(runs.iter().all(|run| run.decision == "unknown"));
+}
+
+#[test]
+fn test_analyze() {
+    for protocol in ANALYZED_PROTOCOLS {
+        let growth = analyze(protocol, &[8, 64], 2).unwrap();
+        assert_eq!(growth.points.len(), 2);
+        for p in &growth.points {
+            assert_eq!(p.accepted, p.trials);
+            assert!(p.rounds >= 1.0 && p.proof_bytes > 0.0);
+        }
+        // Every instance here grows with its size, and so does its encoding
+        assert!(growth.points[1].proof_bytes > growth.points[0].proof_bytes);
+    }
+    assert!(analyze("sudoku", &[8], 1).is_err());
+    assert!(analyze("gi", &[1], 1).is_err());
+    // FRI sizes are limited by the field rather than by graph sizes, and are checked before anything runs
+    assert!(analyze("fri", &[8, MAX_FRI_DEGREE + 1], 1).unwrap_err().contains("out of range for fri"));
+    assert!(analyze("gi", &[8, MAX_VERTICES as usize + 1], 1).is_err());
+    let pair = random_nonisomorphic_pair(3);
+    assert_eq!(decide(&pair.g0, &pair.g1, DecisionMode::Exact).is_isomorphic(), Some(false));
+    let growth = Growth { protocol: "test".to_string(), points: [10, 100, 1000].iter().map(|&size| GrowthPoint {
+        size, trials: 1, accepted: 1, rounds: 2.0, proof_bytes: (size * size) as f64, prover_time: PartyTime::default(), verifier_time: PartyTime::default(),
+    }).collect() };
+    assert!((growth.exponent(|p| p.proof_bytes).unwrap() - 2.0).abs() < 1e-9);
+    assert!(growth.exponent(|p| p.rounds).unwrap().abs() < 1e-9);
+    assert_eq!(growth.exponent(|p| p.prover_time.cpu), None);
+    assert!(growth.to_csv().lines().nth(3).unwrap().starts_with("test,1000,1,1,2,1000000,"));
+}
+
+#[test]
+fn test_gni_timing() {
+    let instance = gni_timing_instance(24);
+    let report = measure_gni_timing(&instance, 100, None, false).unwrap();
+    assert_eq!((report.trials, report.pad, report.canonical), (100, None, false));
+    let kept = report.prover.classes[0].samples + report.prover.classes[1].samples;
+    assert!(kept > 85 && kept <= 100);
+    // Padded responses never finish early
+    let report = measure_gni_timing(&instance, 20, Some(Duration::from_millis(5)), false).unwrap();
+    assert_eq!(report.pad, Some(0.005));
+    assert!(report.prover.classes.iter().chain(&report.verifier.classes).all(|class| class.mean >= 0.005));
+    assert!(measure_gni_timing(&bench_instance(true), 1, None, false).is_err());
+    assert!(measure_gni_timing(&instance, 20, None, true).unwrap().canonical);
+}
+
+// Asserts on wall-clock times, which a loaded machine skews, so it only runs with cargo test -- --ignored
+#[test]
+#[ignore]
+fn test_gni_timing_leakage() {
+    // Relative difference between the mean response times of the two classes, which |t| overstates once
+    // responses are padded to within a few microseconds of each other
+    let gap = |leakage: &Leakage| (leakage.classes[1].mean - leakage.classes[0].mean).abs() / leakage.classes[0].mean.max(leakage.classes[1].mean);
+    let instance = gni_timing_instance(24);
+    let report = measure_gni_timing(&instance, 300, None, false).unwrap();
+    assert!(report.prover.leaks() && report.prover.t > 0.0 && gap(&report.prover) > 0.5, "{:?}", report.prover);
+    assert!(report.verifier.leaks(), "{:?}", report.verifier);
+    let report = measure_gni_timing(&instance, 300, Some(Duration::from_millis(5)), false).unwrap();
+    assert!(gap(&report.prover) < 0.01 && gap(&report.verifier) < 0.01, "{:?}", report);
+    // Balancing the canonical prover's work closes most of the gap without padding
+    let report = measure_gni_timing(&instance, 200, None, true).unwrap();
+    assert!(report.canonical && gap(&report.prover) < 0.1, "{:?}", report.prover);
+}
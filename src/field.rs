@@ -0,0 +1,239 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use serde::{Serialize, Deserialize};
+
+// ************ Prime field implementation ************
+
+// Arithmetic modulo the prime p = 2^64 - 2^32 + 1, whose multiplicative group has a subgroup of every
+// order 2^k up to 2^32. Polynomials can then be evaluated on and interpolated from those subgroups with
+// the number-theoretic transform in O(n log n), which is what FRI and STARK provers spend their time on.
+// Elements fit in a u64, unlike the BigUint arithmetic of the group-based protocols, so that oracles of
+// millions of evaluations stay cheap.
+
+pub const MODULUS: u64 = 0xffff_ffff_0000_0001;
+
+// Largest k for which the field has a subgroup of order 2^k
+pub const TWO_ADICITY: u32 = 32;
+
+// Generator of the multiplicative group, whose cosets are disjoint from every subgroup of order 2^k
+pub const GENERATOR: Fp = Fp(7);
+
+// Field element, always reduced below the modulus
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fp(u64);
+
+impl Fp {
+    pub const ZERO: Fp = Fp(0);
+    pub const ONE: Fp = Fp(1);
+
+    pub fn new(x: u64) -> Fp {
+        Fp(x % MODULUS)
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+
+    pub fn pow(self, mut exponent: u64) -> Fp {
+        let (mut base, mut result) = (self, Fp::ONE);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    // Inverse by Fermat's little theorem, or None for zero
+    pub fn inverse(self) -> Option<Fp> {
+        if self == Fp::ZERO { None } else { Some(self.pow(MODULUS - 2)) }
+    }
+
+    // Generator of the subgroup of order 2^log_size
+    pub fn root_of_unity(log_size: u32) -> Fp {
+        if log_size > TWO_ADICITY {
+            panic!("The field has no subgroup of order 2^{}", log_size);
+        }
+        GENERATOR.pow((MODULUS - 1) >> log_size)
+    }
+
+    // Little-endian encoding, as oracle symbols
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    // Decodes an encoding from to_bytes, rejecting encodings that aren't canonical
+    pub fn from_bytes(bytes: &[u8]) -> Option<Fp> {
+        let x = u64::from_le_bytes(bytes.try_into().ok()?);
+        if x < MODULUS { Some(Fp(x)) } else { None }
+    }
+
+    // Maps a uniformly random challenge to a field element, with statistical distance below 2^-64 from uniform
+    pub fn from_challenge(challenge: &[u8; 32]) -> Fp {
+        Fp((u128::from_le_bytes(challenge[..16].try_into().unwrap()) % MODULUS as u128) as u64)
+    }
+}
+
+impl fmt::Debug for Fp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for Fp {
+    fn from(x: u64) -> Fp {
+        Fp::new(x)
+    }
+}
+
+impl Add for Fp {
+    type Output = Fp;
+
+    fn add(self, other: Fp) -> Fp {
+        Fp(((self.0 as u128 + other.0 as u128) % MODULUS as u128) as u64)
+    }
+}
+
+impl Sub for Fp {
+    type Output = Fp;
+
+    fn sub(self, other: Fp) -> Fp {
+        self + -other
+    }
+}
+
+impl Neg for Fp {
+    type Output = Fp;
+
+    fn neg(self) -> Fp {
+        if self.0 == 0 { self } else { Fp(MODULUS - self.0) }
+    }
+}
+
+impl Mul for Fp {
+    type Output = Fp;
+
+    fn mul(self, other: Fp) -> Fp {
+        Fp((self.0 as u128 * other.0 as u128 % MODULUS as u128) as u64)
+    }
+}
+
+impl AddAssign for Fp {
+    fn add_assign(&mut self, other: Fp) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Fp {
+    fn sub_assign(&mut self, other: Fp) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Fp {
+    fn mul_assign(&mut self, other: Fp) {
+        *self = *self * other;
+    }
+}
+
+// ************ Polynomial implementation ************
+
+// Polynomials are vectors of coefficients, lowest degree first
+
+pub fn evaluate(coefficients: &[Fp], x: Fp) -> Fp {
+    coefficients.iter().rev().fold(Fp::ZERO, |acc, &a| acc * x + a)
+}
+
+// Replaces the coefficients of a polynomial with its evaluations at root^0, root^1, ..., for a root of
+// unity whose order is the number of coefficients, a power of two
+pub fn ntt(values: &mut [Fp], root: Fp) {
+    let n = values.len();
+    if !n.is_power_of_two() {
+        panic!("Transform size must be a power of two. Found size {}.", n);
+    }
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits().checked_shr(usize::BITS - bits).unwrap_or(0);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= n {
+        let step = root.pow((n / size) as u64);
+        for chunk in values.chunks_mut(size) {
+            let mut w = Fp::ONE;
+            let (low, high) = chunk.split_at_mut(size / 2);
+            for (a, b) in low.iter_mut().zip(high) {
+                let t = *b * w;
+                *b = *a - t;
+                *a += t;
+                w *= step;
+            }
+        }
+        size *= 2;
+    }
+}
+
+// Inverse of ntt, recovering the coefficients from the evaluations on the subgroup generated by root
+pub fn intt(values: &mut [Fp], root: Fp) {
+    ntt(values, root.inverse().expect("Roots of unity are nonzero"));
+    let scale = Fp::new(values.len() as u64).inverse().expect("Transform size is below the modulus");
+    values.iter_mut().for_each(|x| *x *= scale);
+}
+
+// Evaluates a polynomial of degree below size on the coset offset * <w> of the subgroup of order size
+pub fn evaluate_on_coset(coefficients: &[Fp], offset: Fp, size: usize) -> Vec<Fp> {
+    if coefficients.len() > size {
+        panic!("Polynomial with {} coefficients doesn't fit a domain of size {}", coefficients.len(), size);
+    }
+    let mut power = Fp::ONE;
+    let mut values: Vec<Fp> = coefficients.iter().map(|&a| {
+        let x = a * power;
+        power *= offset;
+        x
+    }).collect();
+    values.resize(size, Fp::ZERO);
+    ntt(&mut values, Fp::root_of_unity(size.trailing_zeros()));
+    values
+}
+
+#[test]
+fn test_field_arithmetic() {
+    let x = Fp::new(MODULUS - 1);
+    assert_eq!(x + Fp::ONE, Fp::ZERO);
+    assert_eq!(x * x, Fp::ONE);
+    assert_eq!(Fp::ZERO - Fp::ONE, x);
+    let y = Fp::new(123456789);
+    assert_eq!(y * y.inverse().unwrap(), Fp::ONE);
+    assert_eq!(Fp::ZERO.inverse(), None);
+    assert_eq!(Fp::from_bytes(&y.to_bytes()), Some(y));
+    assert_eq!(Fp::from_bytes(&MODULUS.to_le_bytes()), None);
+    // Roots of unity have exactly the order they are asked for
+    for log_size in [1, 5, TWO_ADICITY] {
+        let root = Fp::root_of_unity(log_size);
+        assert_eq!(root.pow(1 << log_size), Fp::ONE);
+        assert_ne!(root.pow(1 << (log_size - 1)), Fp::ONE);
+    }
+}
+
+#[test]
+fn test_ntt() {
+    let coefficients: Vec<Fp> = (1..=8).map(Fp::new).collect();
+    let root = Fp::root_of_unity(3);
+    let mut values = coefficients.clone();
+    ntt(&mut values, root);
+    for (i, &value) in values.iter().enumerate() {
+        assert_eq!(value, evaluate(&coefficients, root.pow(i as u64)));
+    }
+    intt(&mut values, root);
+    assert_eq!(values, coefficients);
+    let coset = evaluate_on_coset(&coefficients[..5], GENERATOR, 16);
+    let root = Fp::root_of_unity(4);
+    for (i, &value) in coset.iter().enumerate() {
+        assert_eq!(value, evaluate(&coefficients[..5], GENERATOR * root.pow(i as u64)));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::field::{Fp, GENERATOR};
+use crate::iop::{Iop, IopProver, Oracle, Query, query_indices};
+#[cfg(test)]
+use crate::field::evaluate_on_coset;
+#[cfg(test)]
+use crate::iop::{iop_to_nizk, verify_iop_nizk};
+#[cfg(test)]
+use crate::transcript::ProofContext;
+
+// ************ FRI implementation ************
+
+// FRI is an IOP showing that an oracle of evaluations of f on a coset of size N is close to a polynomial
+// of degree below d, with N = d * blowup. Each round, the verifier sends a random beta and the prover
+// folds the last oracle in half: writing f(x) = f_e(x^2) + x f_o(x^2),
+//
+//   f'(x^2) = (f(x) + f(-x)) / 2 + beta (f(x) - f(-x)) / (2x) = f_e(x^2) + beta f_o(x^2)
+//
+// which halves both the degree and the domain, since x and -x square to the same point. After log2(d)
+// folds, the oracle of an honest prover is constant. The verifier follows random positions through every
+// layer, reading x and -x in each and checking that they fold into the value read in the next layer, and
+// reads the whole last layer to check that it is constant. An oracle far from every polynomial of degree
+// below d fails some query with constant probability, so the queries set the soundness error.
+//
+// Layer k is evaluated on the coset offset^(2^k) <w_k> of size N / 2^k, with the point offset^(2^k) w_k^j
+// at position j, so -x is at position j + N / 2^(k + 1). Symbols are field elements in Fp::to_bytes form.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fri {
+    // Size of the first layer's domain
+    pub domain_size: usize,
+    // Degree bound d, a power of two of at least 2 below domain_size
+    pub degree_bound: usize,
+    // Number of positions followed through the layers
+    pub queries: usize,
+}
+
+impl Fri {
+    // Offset of the first layer's coset
+    pub const OFFSET: Fp = GENERATOR;
+
+    pub fn new(degree_bound: usize, blowup: usize, queries: usize) -> Fri {
+        if !degree_bound.is_power_of_two() || !blowup.is_power_of_two() || degree_bound < 2 || blowup < 2 {
+            panic!("Degree bound and blowup must be powers of two of at least 2. Found {} and {}.", degree_bound, blowup);
+        }
+        Fri { domain_size: degree_bound * blowup, degree_bound, queries }
+    }
+
+    // Number of folds, after which an honest oracle is constant
+    pub fn folds(&self) -> usize {
+        self.degree_bound.trailing_zeros() as usize
+    }
+
+    // Lengths of the layers, from the first to the constant last layer
+    pub fn layer_lengths(&self) -> Vec<usize> {
+        (0..=self.folds()).map(|k| self.domain_size >> k).collect()
+    }
+
+    // Folds layer k with beta into layer k + 1
+    pub fn fold(&self, layer: &[Fp], k: usize, beta: Fp) -> Vec<Fp> {
+        let half = layer.len() / 2;
+        let root = Fp::root_of_unity(layer.len().trailing_zeros());
+        let mut x = Fri::OFFSET.pow(1 << k);
+        (0..half).map(|j| {
+            let folded = fold_pair(x, layer[j], layer[j + half], beta);
+            x *= root;
+            folded
+        }).collect()
+    }
+
+    // Queries following each first-layer index through the layers, with the first layer at the given
+    // oracle, followed by the whole last layer
+    pub fn layer_queries(&self, first_oracle: usize, indices: &[usize]) -> Vec<Query> {
+        let lengths = self.layer_lengths();
+        let mut queries: Vec<Query> = indices.iter().flat_map(|&index| {
+            lengths[..self.folds()].iter().enumerate().flat_map(move |(k, &length)| {
+                let j = index % (length / 2);
+                vec![Query { oracle: first_oracle + k, index: j }, Query { oracle: first_oracle + k, index: j + length / 2 }]
+            })
+        }).collect();
+        let last = lengths[self.folds()];
+        queries.extend((0..last).map(|index| Query { oracle: first_oracle + self.folds(), index }));
+        queries
+    }
+
+    // Checks the answers to layer_queries, returning the first layer's value at each index
+    pub fn check(&self, betas: &[Fp], indices: &[usize], answers: &[Vec<u8>]) -> Result<Vec<Fp>, String> {
+        let folds = self.folds();
+        let lengths = self.layer_lengths();
+        if betas.len() != folds || answers.len() != 2 * folds * indices.len() + lengths[folds] {
+            return Err("Wrong number of answers to the FRI queries".to_string());
+        }
+        let values: Vec<Fp> = answers.iter().map(|x| Fp::from_bytes(x)).collect::<Option<_>>().ok_or("FRI answer is not a field element")?;
+        let (paths, last) = values.split_at(2 * folds * indices.len());
+        if last.iter().any(|&x| x != last[0]) {
+            return Err("Last FRI layer is not constant".to_string());
+        }
+        indices.iter().zip(paths.chunks(2 * folds)).map(|(&index, path)| {
+            let mut expected = None;
+            for (k, pair) in path.chunks(2).enumerate() {
+                let length = lengths[k];
+                let j = index % (length / 2);
+                // The value folded from the layer before is at whichever of j and j + length / 2 the index reaches
+                let read = if index % length < length / 2 { pair[0] } else { pair[1] };
+                if expected.is_some_and(|x| x != read) {
+                    return Err(format!("FRI layer {} is inconsistent with the layer before at index {}", k, index));
+                }
+                let x = Fri::OFFSET.pow(1 << k) * Fp::root_of_unity(length.trailing_zeros()).pow(j as u64);
+                expected = Some(fold_pair(x, pair[0], pair[1], betas[k]));
+            }
+            if expected != Some(last[0]) {
+                return Err(format!("Last FRI layer is inconsistent with the layer before at index {}", index));
+            }
+            Ok(if index % self.domain_size < self.domain_size / 2 { path[0] } else { path[1] })
+        }).collect()
+    }
+}
+
+// Folds f(x) and f(-x) into f_e(x^2) + beta f_o(x^2)
+fn fold_pair(x: Fp, fx: Fp, f_neg_x: Fp, beta: Fp) -> Fp {
+    let half = Fp::new(2).inverse().unwrap();
+    let even = (fx + f_neg_x) * half;
+    let odd = (fx - f_neg_x) * half * x.inverse().expect("FRI domains don't contain 0");
+    even + beta * odd
+}
+
+// FRI on its own, proving that the prover's first oracle has degree below the bound
+impl Iop for Fri {
+    fn rounds(&self) -> usize {
+        1 + self.folds()
+    }
+
+    fn queries(&self, _challenges: &[[u8; 32]], _lengths: &[usize], randomness: &[u8; 32]) -> Vec<Query> {
+        self.layer_queries(0, &query_indices(randomness, self.queries, self.domain_size))
+    }
+
+    fn decide(&self, challenges: &[[u8; 32]], lengths: &[usize], queries: &[Query], answers: &[Vec<u8>]) -> bool {
+        let betas: Vec<Fp> = challenges[1..].iter().map(Fp::from_challenge).collect();
+        // Each index is followed by 2 queries per fold, starting from the first layer
+        let indices: Vec<usize> = queries.iter().step_by(2 * self.folds()).take(self.queries).map(|x| x.index).collect();
+        lengths == self.layer_lengths().as_slice() && self.check(&betas, &indices, answers).is_ok()
+    }
+}
+
+// Prover holding the evaluations of the first layer
+pub struct FriProver {
+    pub fri: Fri,
+    pub layer: Vec<Fp>,
+}
+
+impl IopProver for FriProver {
+    fn oracle(&mut self, round: usize, challenge: &[u8; 32]) -> Oracle {
+        if round > 0 {
+            self.layer = self.fri.fold(&self.layer, round - 1, Fp::from_challenge(challenge));
+        }
+        self.layer.iter().map(|x| x.to_bytes().to_vec()).collect()
+    }
+}
+
+#[cfg(test)]
+fn fri_proof(fri: &Fri, coefficients: &[Fp]) -> Result<(), String> {
+    let context = ProofContext::new("fri", b"session", b"statement");
+    let layer = evaluate_on_coset(coefficients, Fri::OFFSET, fri.domain_size);
+    let proof = iop_to_nizk(fri, &mut FriProver { fri: fri.clone(), layer }, &context);
+    verify_iop_nizk(fri, &proof, &context)
+}
+
+#[test]
+fn test_fri() {
+    let fri = Fri::new(16, 4, 20);
+    let low: Vec<Fp> = (0..16).map(|x| Fp::new(x * x + 3)).collect();
+    assert_eq!(fri_proof(&fri, &low), Ok(()));
+    assert_eq!(fri_proof(&fri, &low[..3]), Ok(()));
+    // A polynomial of degree 16 doesn't fold to a constant
+    let high: Vec<Fp> = (0..17).map(|x| Fp::new(x + 1)).collect();
+    assert!(fri_proof(&fri, &high).is_err());
+}
+
+#[test]
+fn test_fri_interactive() {
+    let fri = Fri::new(8, 4, 10);
+    let layer = evaluate_on_coset(&[Fp::new(5), Fp::new(7)], Fri::OFFSET, fri.domain_size);
+    let mut honest = FriProver { fri: fri.clone(), layer };
+    let (mut prover, mut verifier) = crate::iop::iop_to_interactive(&fri, &mut honest);
+    assert!(crate::run_interactive_proof(&mut prover, &mut verifier));
+    // Random evaluations are far from every low degree polynomial
+    let layer = (0..fri.domain_size as u64).map(|x| Fp::new(x.wrapping_mul(0x9e3779b97f4a7c15))).collect();
+    let mut cheating = FriProver { fri: fri.clone(), layer };
+    let (mut prover, mut verifier) = crate::iop::iop_to_interactive(&fri, &mut cheating);
+    assert!(!crate::run_interactive_proof(&mut prover, &mut verifier));
+}
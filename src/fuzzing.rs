@@ -0,0 +1,868 @@
+use std::{fmt, panic::{self, AssertUnwindSafe}, sync::OnceLock};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Number, Value};
+use num_bigint::BigUint;
+use crate::{Prover, Verifier};
+use crate::accumulator::{Accumulator, AccumulatorParameters, MembershipProver, MembershipVerifier};
+use crate::batchgi::{BatchGIProver, BatchGIVerifier, GraphPairBatch};
+use crate::coinflip::{CoinFlipProver, CoinFlipVerifier};
+use crate::coloring::{Coloring, ColoringProver, ColoringVerifier};
+use crate::commitandprove::{CommitAndProve, CommittedProver, CommittedVerifier, RangeProof};
+use crate::committed::{GraphCommitmentScheme, PedersenGraph};
+use crate::committedgi::{CommittedGIProver, CommittedGIVerifier, CommittedGraphPair, CommittedGraphPairWitness};
+use crate::connectivity::{ConnectivityProver, ConnectivityVerifier};
+use crate::degree::{degree_sequence, DegreeProver, DegreeVerifier};
+use crate::designated::{DesignatedProver, DesignatedVerifier};
+use crate::garbled::{example_circuit, JKOProver, JKOVerifier};
+use crate::graph::{GIProver, GIVerifier, GNIProver, GNIVerifier, Graph, GraphPair, HardenedGNIProver, HardenedGNIVerifier, ParallelGIProver, ParallelGIVerifier, RefinedGIVerifier, RefinementCache};
+use crate::hamiltonian::{HamiltonianCycle, HamiltonianProver, HamiltonianVerifier};
+use crate::lookup::{LookupInstance, LookupProver, LookupVerifier};
+use crate::okamoto::{OkamotoInstance, OkamotoProver, OkamotoVerifier};
+use crate::ot::{OTReceiver, OTSender};
+use crate::params::{Parameters, SchnorrParameters};
+use crate::pedersen::PedersenParameters;
+use crate::permutation::Permutation;
+use crate::permutationcommitment::{PermutationCommitment, PermutationMatrixProver, PermutationMatrixVerifier};
+use crate::schnorr::{SchnorrKeyPair, SchnorrProver, SchnorrVerifier};
+use crate::secret::Secret;
+use crate::sudoku::{example_puzzle, SudokuChallenge, SudokuProver, SudokuVerifier};
+use crate::transcript::ChallengeTranscript;
+use crate::vss::{deal, ShareProver, ShareVerifier};
+
+// ************ Arbitrary message implementation ************
+
+// A fuzzer hands the harness an arbitrary byte string. Rather than sending those bytes to a verifier
+// directly, where nearly all of them would fail to parse, ArbitraryMessage reads them as a stream of
+// choices describing how to corrupt the message an honest prover would have sent. This keeps the fuzzer
+// close to messages that get past deserialization and exercise validate and handle.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    // Sends the honest message unchanged
+    Honest,
+    // Cuts the message off after some number of bytes
+    Truncate,
+    // Overwrites some bytes of the message with arbitrary ones
+    Corrupt,
+    // Replaces part of the message with a value of another type, e.g. a string where a number is expected
+    ConfuseType,
+    // Replaces part of the message with a huge number or a long repetition of itself
+    Oversize,
+    // Sends the message from an earlier round in place of this round's
+    Replay,
+    // Sends the fuzzer's remaining bytes as the message
+    Raw,
+}
+
+const MUTATIONS: [Mutation; 7] = [
+    Mutation::Honest,
+    Mutation::Truncate,
+    Mutation::Corrupt,
+    Mutation::ConfuseType,
+    Mutation::Oversize,
+    Mutation::Replay,
+    Mutation::Raw,
+];
+
+// Number of times an array is repeated by an oversize mutation
+const OVERSIZE_REPETITIONS: usize = 1024;
+
+pub struct ArbitraryMessage<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ArbitraryMessage<'a> {
+    pub fn new(data: &'a [u8]) -> ArbitraryMessage<'a> {
+        ArbitraryMessage { data }
+    }
+
+    // Next byte of input, or zero once the input is used up
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&x, rest)) => {
+                self.data = rest;
+                x
+            },
+            None => 0,
+        }
+    }
+
+    // Arbitrary index into a collection of the given nonzero length
+    pub fn index(&mut self, len: usize) -> usize {
+        let x = u32::from_be_bytes([self.byte(), self.byte(), self.byte(), self.byte()]);
+        x as usize % len
+    }
+
+    pub fn mutation(&mut self) -> Mutation {
+        MUTATIONS[self.index(MUTATIONS.len())]
+    }
+
+    // Applies the mutation to the serialized honest message, given the serialized messages sent on earlier
+    // rounds
+    pub fn mutate(&mut self, mutation: Mutation, honest: &[u8], earlier: &[Vec<u8>]) -> Vec<u8> {
+        match mutation {
+            Mutation::Honest => honest.to_vec(),
+            Mutation::Truncate => honest[..self.index(honest.len() + 1)].to_vec(),
+            Mutation::Corrupt => {
+                let mut message = honest.to_vec();
+                let count = 1 + self.byte() as usize % 4;
+                if !message.is_empty() {
+                    (0..count).for_each(|_| {
+                        let i = self.index(message.len());
+                        message[i] = self.byte();
+                    });
+                }
+                message
+            },
+            Mutation::ConfuseType | Mutation::Oversize => {
+                let mut value: Value = match serde_json::from_slice(honest) {
+                    Ok(value) => value,
+                    Err(_) => return honest.to_vec(),
+                };
+                let i = self.index(count_nodes(&value));
+                let node = nth_node(&mut value, i).expect("Node index is in range");
+                *node = if mutation == Mutation::ConfuseType { self.confuse(node) } else { oversize(node) };
+                serde_json::to_vec(&value).unwrap()
+            },
+            Mutation::Replay if !earlier.is_empty() => earlier[self.index(earlier.len())].clone(),
+            Mutation::Replay => honest.to_vec(),
+            Mutation::Raw => std::mem::take(&mut self.data).to_vec(),
+        }
+    }
+
+    // Picks a value of a different JSON type than the given one
+    fn confuse(&mut self, value: &Value) -> Value {
+        let candidates = other_types(value);
+        candidates[self.index(candidates.len())].clone()
+    }
+}
+
+// A value of each JSON type other than the given value's
+fn other_types(value: &Value) -> Vec<Value> {
+    let candidates = [
+        Value::Null,
+        Value::Bool(true),
+        Value::Number(Number::from(-1)),
+        Value::String("zz".to_string()),
+        Value::Array(Vec::new()),
+        Value::Object(serde_json::Map::new()),
+    ];
+    candidates.iter().filter(|x| std::mem::discriminant(*x) != std::mem::discriminant(value)).cloned().collect()
+}
+
+// Number of values in the JSON tree, including the root
+fn count_nodes(value: &Value) -> usize {
+    1 + match value {
+        Value::Array(items) => items.iter().map(count_nodes).sum(),
+        Value::Object(fields) => fields.values().map(count_nodes).sum(),
+        _ => 0,
+    }
+}
+
+// Value at the given position of a pre-order traversal of the JSON tree
+fn nth_node(value: &mut Value, i: usize) -> Option<&mut Value> {
+    if i == 0 {
+        return Some(value);
+    }
+    let mut i = i - 1;
+    let children: Vec<&mut Value> = match value {
+        Value::Array(items) => items.iter_mut().collect(),
+        Value::Object(fields) => fields.values_mut().collect(),
+        _ => return None,
+    };
+    for child in children {
+        let size = count_nodes(child);
+        if i < size {
+            return nth_node(child, i);
+        }
+        i -= size;
+    }
+    None
+}
+
+fn oversize(value: &Value) -> Value {
+    match value {
+        // Big integers are hexadecimal strings, so a long run of digits is a huge number
+        Value::String(s) => Value::String(s.repeat(OVERSIZE_REPETITIONS)),
+        Value::Array(items) if !items.is_empty() => Value::Array(items.iter().cycle().take(OVERSIZE_REPETITIONS).cloned().collect()),
+        _ => Value::Number(Number::from(u64::MAX)),
+    }
+}
+
+// ************ Verifier fuzzing implementation ************
+
+// fuzz_verifier plays an honest interaction up to a round chosen by the fuzzer, then replaces the
+// honest prover's message on that round with a mutated one and passes it through deserialization,
+// validate, and handle. Errors at any stage are the expected outcome for a hostile message, but a panic
+// is a bug, reported as a Finding classified by the stage that panicked.
+//
+// A verifier only reaches its verdict on the last message, and rejects every message before it, so a
+// mutated message it handles on an earlier round hasn't been rejected yet. The honest prover then
+// answers the verifier's replies until the interaction ends, each of its messages passing through the
+// same stages, and the verifier's verdict on the last of them is the outcome.
+
+// Stage of message processing that panicked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BugClass {
+    Deserialize,
+    Validate,
+    Handle,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub class: BugClass,
+    // Round the message that caused the panic was sent on, either the mutated message or an honest one
+    // sent after it
+    pub round: u32,
+    pub mutation: Mutation,
+    // Serialized message that caused the panic
+    pub message: Vec<u8>,
+    // Panic message
+    pub panic: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} panicked on round {} after {:?} mutation: {}\nMessage: {}",
+            self.class, self.round, self.mutation, self.panic, String::from_utf8_lossy(&self.message))
+    }
+}
+
+// What happened to a mutated message that didn't cause a panic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    // The message failed to deserialize
+    Unparsed,
+    // The verifier's validate rejected the message
+    Rejected(String),
+    // The verifier handled the message and every one after it, accepting at the end of the interaction or not
+    Handled(bool),
+}
+
+// Rounds the fuzzer can choose to attack. Interactions shorter than this are attacked on their last round.
+const MAX_FUZZED_ROUND: u32 = 4;
+
+pub fn fuzz_verifier<T, U, P, V>(prover: &mut P, verifier: &mut V, data: &[u8]) -> Result<Outcome, Finding>
+where
+    T: Serialize + DeserializeOwned,
+    P: Prover<ProverMessage = T, VerifierMessage = U>,
+    V: Verifier<ProverMessage = T, VerifierMessage = U>,
+{
+    let mut input = ArbitraryMessage::new(data);
+    let target = 1 + input.byte() as u32 % MAX_FUZZED_ROUND;
+    let mutation = input.mutation();
+    play_and_attack(prover, verifier, target, mutation, |honest, earlier| input.mutate(mutation, honest, earlier))
+}
+
+// Plays an honest interaction up to the target round, or the last round if there are fewer, and attacks
+// the verifier there with the message corrupt makes from the honest prover's message and those of earlier
+// rounds, then plays the rest of the interaction honestly
+fn play_and_attack<T, U, P, V, F>(prover: &mut P, verifier: &mut V, target: u32, mutation: Mutation, corrupt: F) -> Result<Outcome, Finding>
+where
+    T: Serialize + DeserializeOwned,
+    P: Prover<ProverMessage = T, VerifierMessage = U>,
+    V: Verifier<ProverMessage = T, VerifierMessage = U>,
+    F: FnOnce(&[u8], &[Vec<u8>]) -> Vec<u8>,
+{
+    let mut earlier = Vec::new();
+    let mut verifier_msg = verifier.init();
+    let mut round = 1;
+    let (mut outcome, mut reply) = loop {
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        let honest = serde_json::to_vec(&prover_msg).unwrap();
+        if done {
+            return attack(verifier, round, mutation, corrupt(&honest, &earlier)).map(|(outcome, _)| outcome);
+        }
+        if round == target {
+            break attack(verifier, round, mutation, corrupt(&honest, &earlier))?;
+        }
+        verifier_msg = verifier.handle(&prover_msg).0;
+        earlier.push(honest);
+        round += 1;
+    };
+    // Until the prover is done, the verifier's verdict is only provisional
+    while let Some(verifier_msg) = reply {
+        // An honest prover refuses to go on after a reply it can't answer, which the verifier can't accept
+        if prover.validate(&verifier_msg).is_err() {
+            return Ok(Outcome::Handled(false));
+        }
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        if done {
+            break;
+        }
+        round += 1;
+        let next = attack(verifier, round, mutation, serde_json::to_vec(&prover_msg).unwrap())?;
+        outcome = next.0;
+        reply = next.1;
+    }
+    Ok(outcome)
+}
+
+// Passes the message through deserialization, validate and handle, returning the verifier's reply if it
+// handled the message
+fn attack<T, U, V>(verifier: &mut V, round: u32, mutation: Mutation, message: Vec<u8>) -> Result<(Outcome, Option<U>), Finding>
+where
+    T: DeserializeOwned,
+    V: Verifier<ProverMessage = T, VerifierMessage = U>,
+{
+    let finding = |class, payload: Box<dyn std::any::Any + Send>| Finding {
+        class,
+        round,
+        mutation,
+        message: message.clone(),
+        panic: panic_message(payload),
+    };
+    let msg = match panic::catch_unwind(|| serde_json::from_slice::<T>(&message)) {
+        Ok(Ok(msg)) => msg,
+        Ok(Err(_)) => return Ok((Outcome::Unparsed, None)),
+        Err(payload) => return Err(finding(BugClass::Deserialize, payload)),
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| verifier.validate(&msg))) {
+        Ok(Ok(())) => {},
+        Ok(Err(reason)) => return Ok((Outcome::Rejected(reason), None)),
+        Err(payload) => return Err(finding(BugClass::Validate, payload)),
+    }
+    match panic::catch_unwind(AssertUnwindSafe(|| verifier.handle(&msg))) {
+        Ok((reply, accept)) => Ok((Outcome::Handled(accept), Some(reply))),
+        Err(payload) => Err(finding(BugClass::Handle, payload)),
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("non-string panic".to_string(), |s| s.to_string()),
+    }
+}
+
+// ************ Fuzz target implementation ************
+
+// Each verifier fuzzed by the targets under fuzz/, set up against a small fixed instance. The targets
+// pass the fuzzer's input to fuzz_protocol and panic on any finding, which the fuzzer then records and
+// minimizes.
+
+pub const FUZZED_PROTOCOLS: [&str; 23] = [
+    "gi", "parallel-gi", "batch-gi", "committed-gi", "refined-gi", "gni", "hardened-gni", "schnorr",
+    "okamoto", "share", "coinflip", "sudoku", "coloring", "hamiltonian", "connectivity", "degree", "lookup",
+    "membership", "permutation-matrix", "ot", "garbled", "commit-and-prove", "designated",
+];
+
+// Generating parameters dominates the cost of a run, so every run in a process shares one group
+fn fuzz_parameters() -> &'static SchnorrParameters {
+    static PARAMS: OnceLock<SchnorrParameters> = OnceLock::new();
+    PARAMS.get_or_init(|| SchnorrParameters::generate(64))
+}
+
+fn fuzz_pedersen_parameters() -> &'static PedersenParameters {
+    static PARAMS: OnceLock<PedersenParameters> = OnceLock::new();
+    PARAMS.get_or_init(|| {
+        let group = fuzz_parameters();
+        PedersenParameters { group: group.clone(), h: group.random_generator() }
+    })
+}
+
+fn fuzz_accumulator_parameters() -> &'static AccumulatorParameters {
+    static PARAMS: OnceLock<AccumulatorParameters> = OnceLock::new();
+    PARAMS.get_or_init(|| AccumulatorParameters::generate(256))
+}
+
+// Something done to a fuzzed protocol's prover and verifier, which with_protocol sets up
+pub trait Attack {
+    type Output;
+
+    fn run<T, U, P, V>(self, prover: &mut P, verifier: &mut V) -> Self::Output
+    where
+        T: Serialize + DeserializeOwned,
+        P: Prover<ProverMessage = T, VerifierMessage = U>,
+        V: Verifier<ProverMessage = T, VerifierMessage = U>;
+}
+
+// Attacks with a message mutated as the fuzzer's input chooses, see fuzz_verifier
+pub struct Fuzz<'a>(pub &'a [u8]);
+
+impl Attack for Fuzz<'_> {
+    type Output = Result<Outcome, Finding>;
+
+    fn run<T, U, P, V>(self, prover: &mut P, verifier: &mut V) -> Self::Output
+    where
+        T: Serialize + DeserializeOwned,
+        P: Prover<ProverMessage = T, VerifierMessage = U>,
+        V: Verifier<ProverMessage = T, VerifierMessage = U>,
+    {
+        fuzz_verifier(prover, verifier, self.0)
+    }
+}
+
+pub fn fuzz_protocol(protocol: &str, data: &[u8]) -> Result<Outcome, Finding> {
+    with_protocol(protocol, Fuzz(data))
+}
+
+pub fn with_protocol<A: Attack>(protocol: &str, attack: A) -> A::Output {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let non_isomorphic = GraphPair {
+        g0: instance.g0.clone(),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    // Isomorphism taking the instance's g0 to its g1
+    let isomorphism = Permutation::new(vec![2, 1, 0, 3]).expect("Fuzzed isomorphism is a permutation");
+    let zero = || BigUint::from(0u32);
+    match protocol {
+        "gi" => attack.run(
+            &mut GIProver::new(&instance),
+            &mut GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance },
+        ),
+        "parallel-gi" => attack.run(
+            &mut ParallelGIProver { r: 0, k: 4, random_perms: Vec::new(), openings: Vec::new(), instance: &instance },
+            &mut ParallelGIVerifier { r: 0, k: 4, challenge: Vec::new(), commitments: Vec::new(), instance: &instance },
+        ),
+        "batch-gi" => {
+            let batch = GraphPairBatch { pairs: vec![instance.clone(); 2] };
+            let witness = vec![isomorphism; 2];
+            attack.run(
+                &mut BatchGIProver { r: 0, k: 2, relabelings: Vec::new(), instance: &batch, witness: &witness },
+                &mut BatchGIVerifier { r: 0, k: 2, challenge: Vec::new(), graphs: Vec::new(), instance: &batch },
+            )
+        },
+        "committed-gi" => {
+            let params = fuzz_pedersen_parameters();
+            let ((g0, w0), (g1, w1)) = (PedersenGraph::commit(params, &instance.g0), PedersenGraph::commit(params, &instance.g1));
+            let (commitment, opening) = PermutationCommitment::commit(params, &isomorphism);
+            let committed = CommittedGraphPair { g0, g1, isomorphism: commitment };
+            let witness = CommittedGraphPairWitness { w0, w1, isomorphism: opening };
+            attack.run(&mut CommittedGIProver::new(&committed, &witness), &mut CommittedGIVerifier::new(&committed))
+        },
+        "refined-gi" => {
+            let refinement = RefinementCache::new(&instance);
+            attack.run(
+                &mut GIProver::new(&instance),
+                &mut RefinedGIVerifier { inner: GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }, refinement: &refinement },
+            )
+        },
+        "gni" => attack.run(
+            &mut GNIProver { sent_guess: false, instance: &non_isomorphic },
+            &mut GNIVerifier { b: false, instance: &non_isomorphic },
+        ),
+        "hardened-gni" => attack.run(
+            &mut HardenedGNIProver { r: 0, k: 4, challenge: Vec::new(), query: None, instance: &non_isomorphic },
+            &mut HardenedGNIVerifier { r: 0, k: 4, b: false, isomorphism: Permutation::identity(0), pairs: Vec::new(), instance: &non_isomorphic },
+        ),
+        "schnorr" => {
+            let key = SchnorrKeyPair::generate(fuzz_parameters());
+            attack.run(
+                &mut SchnorrProver { r: 0, nonce: Secret::default(), key: &key },
+                &mut SchnorrVerifier { r: 0, commitment: zero(), challenge: zero(), transcript: ChallengeTranscript::new(b"schnorr identification"), params: &key.params, y: &key.y },
+            )
+        },
+        "okamoto" => {
+            let (instance, witness) = OkamotoInstance::generate(fuzz_parameters());
+            attack.run(
+                &mut OkamotoProver { r: 0, nonces: Secret::default(), instance: &instance, witness: &witness },
+                &mut OkamotoVerifier { r: 0, commitment: zero(), challenge: zero(), response: (zero(), zero()), transcript: ChallengeTranscript::new(b"okamoto"), instance: &instance },
+            )
+        },
+        "share" => {
+            let (shares, commitments) = deal(fuzz_parameters(), &BigUint::from(42u32), 2, 3);
+            attack.run(
+                &mut ShareProver { r: 0, nonce: Secret::default(), commitments: &commitments, share: &shares[1] },
+                &mut ShareVerifier { r: 0, commitment: zero(), challenge: zero(), transcript: ChallengeTranscript::new(b"feldman share"), commitments: &commitments, index: 2 },
+            )
+        },
+        "coinflip" => attack.run(
+            &mut CoinFlipProver { r: 0, num_coins: 8, opening: None, coins: Vec::new() },
+            &mut CoinFlipVerifier { r: 0, num_coins: 8, commitment: None, bits: Vec::new(), coins: Vec::new() },
+        ),
+        "sudoku" => {
+            let (puzzle, solution) = example_puzzle();
+            attack.run(
+                &mut SudokuProver { r: 0, cell_openings: Vec::new(), relabeling_openings: Vec::new(), puzzle: &puzzle, solution: &solution },
+                &mut SudokuVerifier { r: 0, challenge: SudokuChallenge::Clues, cell_commitments: Vec::new(), relabeling_commitments: Vec::new(), puzzle: &puzzle },
+            )
+        },
+        "coloring" => {
+            let coloring = Coloring { colors: vec![0, 1, 0, 2] };
+            attack.run(
+                &mut ColoringProver::new(&instance.g0, &coloring).expect("Fuzzed coloring uses three colors"),
+                &mut ColoringVerifier { r: 0, edge: None, commitments: Vec::new(), graph: &instance.g0 },
+            )
+        },
+        "hamiltonian" => {
+            let (graph, cycle) = (Graph::new(4, vec![(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)]), HamiltonianCycle { vertices: vec![0, 1, 2, 3] });
+            attack.run(
+                &mut HamiltonianProver { r: 0, scheme: GraphCommitmentScheme::PerEntry, permutation: Permutation::identity(0), committed: None, graph: &graph, cycle: &cycle },
+                &mut HamiltonianVerifier { r: 0, b: false, commitment: None, graph: &graph },
+            )
+        },
+        "connectivity" => {
+            let (committed, witness) = PedersenGraph::commit(fuzz_pedersen_parameters(), &Graph::new(4, vec![(0, 1), (2, 1), (2, 3)]));
+            attack.run(
+                &mut ConnectivityProver { r: 0, relabeling: None, instance: &committed, witness: &witness },
+                &mut ConnectivityVerifier { r: 0, b: false, commitments: Vec::new(), instance: &committed },
+            )
+        },
+        "degree" => {
+            let (committed, witness) = PedersenGraph::commit(fuzz_pedersen_parameters(), &instance.g0);
+            let degrees = degree_sequence(&instance.g0);
+            attack.run(&mut DegreeProver::new(&committed, &witness), &mut DegreeVerifier::new(&committed, &degrees))
+        },
+        "lookup" => {
+            let numbers = |xs: &[u32]| xs.iter().map(|&x| BigUint::from(x)).collect::<Vec<_>>();
+            let (lookup, witness) = LookupInstance::commit(fuzz_pedersen_parameters(), &numbers(&[1, 2, 3, 5]), &numbers(&[3, 5, 3]));
+            attack.run(
+                &mut LookupProver { r: 0, state: Secret::default(), instance: &lookup, witness: &witness },
+                &mut LookupVerifier::new(&lookup),
+            )
+        },
+        "membership" => {
+            let params = fuzz_accumulator_parameters();
+            let mut accumulator = Accumulator::new(params);
+            accumulator.add(b"alice");
+            accumulator.add(b"bob");
+            let (commitment, opening) = params.commit_member(b"bob", &accumulator.witness(b"bob").expect("bob was added"));
+            attack.run(
+                &mut MembershipProver { r: 0, state: Secret::default(), params, opening: &opening },
+                &mut MembershipVerifier::new(params, &accumulator.value, &commitment),
+            )
+        },
+        "permutation-matrix" => {
+            let params = fuzz_pedersen_parameters();
+            let (commitment, opening) = PermutationCommitment::commit(params, &isomorphism);
+            attack.run(
+                &mut PermutationMatrixProver { r: 0, state: Secret::default(), params, opening: &opening },
+                &mut PermutationMatrixVerifier::new(params, &commitment),
+            )
+        },
+        "ot" => {
+            let params = fuzz_parameters();
+            attack.run(
+                &mut OTSender { r: 0, c: zero(), params, messages: (b"left", b"right") },
+                &mut OTReceiver { r: 0, choice: true, k: Secret::default(), received: None, params },
+            )
+        },
+        "garbled" => {
+            let (params, circuit) = (fuzz_parameters(), example_circuit());
+            attack.run(
+                &mut JKOProver { r: 0, c: zero(), ot_keys: Vec::new(), garbled: None, opening: None, params, circuit: &circuit, witness: &[true, false, true, true] },
+                &mut JKOVerifier { r: 0, c: zero(), labels: Vec::new(), ot_randomness: Vec::new(), commitment: None, params, circuit: &circuit },
+            )
+        },
+        "commit-and-prove" => {
+            let params = fuzz_pedersen_parameters();
+            let (commitment, opening) = params.commit(&BigUint::from(42u32));
+            let (mut prover_session, mut verifier_session) = (CommitAndProve::new(params, &commitment, b"fuzz"), CommitAndProve::new(params, &commitment, b"fuzz"));
+            let proof = RangeProof { bits: 8 };
+            attack.run(
+                &mut CommittedProver { r: 0, state: Vec::new(), session: &mut prover_session, proof: &proof, opening: &opening, witness: &() },
+                &mut CommittedVerifier { r: 0, announcement: Vec::new(), challenge: zero(), session: &mut verifier_session, proof: &proof },
+            )
+        },
+        "designated" => {
+            let params = fuzz_parameters();
+            let (prover, verifier) = (SchnorrKeyPair::generate(params), SchnorrKeyPair::generate(params));
+            attack.run(
+                &mut DesignatedProver { r: 0, key: &prover, verifier_key: &verifier.y, session_id: b"fuzz" },
+                &mut DesignatedVerifier { r: 0, params, prover_key: &prover.y, verifier_key: &verifier.y, session_id: b"fuzz" },
+            )
+        },
+        _ => panic!("Unknown protocol {}!", protocol),
+    }
+}
+
+// ************ Malformed input implementation ************
+
+// Fuzzing only finds what random choices reach, so each fuzzed protocol also gets tests, generated by
+// reject_malformed_tests, that send its verifier every message of a few kinds of malformed input on every
+// round. Each must be rejected, by deserialization, validate, or a verdict of reject, without a panic:
+//
+// - Replay: the honest message of every other round, earlier or later, as if the rounds were out of order
+// - ConfuseType: every part of the message swapped for a value of each other JSON type, and an enum
+//   message tagged with the variant of another round's message, so the wrong variant is sent
+// - Oversize: every part of the message made huge
+
+// Records the honest prover's messages that reach the verifier
+pub struct Record;
+
+impl Attack for Record {
+    type Output = Vec<Vec<u8>>;
+
+    fn run<T, U, P, V>(self, prover: &mut P, verifier: &mut V) -> Self::Output
+    where
+        T: Serialize + DeserializeOwned,
+        P: Prover<ProverMessage = T, VerifierMessage = U>,
+        V: Verifier<ProverMessage = T, VerifierMessage = U>,
+    {
+        let mut messages = Vec::new();
+        let mut verifier_msg = verifier.init();
+        loop {
+            let (prover_msg, done) = prover.handle(&verifier_msg);
+            if done {
+                return messages;
+            }
+            messages.push(serde_json::to_vec(&prover_msg).unwrap());
+            verifier_msg = verifier.handle(&prover_msg).0;
+        }
+    }
+}
+
+// Attacks with the given message on the given round, after playing the earlier rounds honestly
+pub struct Deliver {
+    pub round: u32,
+    pub mutation: Mutation,
+    pub message: Vec<u8>,
+}
+
+impl Attack for Deliver {
+    type Output = Result<Outcome, Finding>;
+
+    fn run<T, U, P, V>(self, prover: &mut P, verifier: &mut V) -> Self::Output
+    where
+        T: Serialize + DeserializeOwned,
+        P: Prover<ProverMessage = T, VerifierMessage = U>,
+        V: Verifier<ProverMessage = T, VerifierMessage = U>,
+    {
+        let message = self.message;
+        play_and_attack(prover, verifier, self.round, self.mutation, |_, _| message)
+    }
+}
+
+// Every malformed message of the kind the mutation makes, for each round of the honest transcript, with
+// the round to send it on
+pub fn malformed_messages(transcript: &[Vec<u8>], mutation: Mutation) -> Vec<(u32, Vec<u8>)> {
+    let parsed: Vec<Value> = transcript.iter().map(|x| serde_json::from_slice(x).unwrap()).collect();
+    let mut messages = Vec::new();
+    for (i, honest) in parsed.iter().enumerate() {
+        let round = i as u32 + 1;
+        let mut replace = |node: usize, value: Value| {
+            let mut message = honest.clone();
+            *nth_node(&mut message, node).expect("Node index is in range") = value;
+            messages.push((round, serde_json::to_vec(&message).unwrap()));
+        };
+        match mutation {
+            Mutation::Replay => (0..transcript.len()).filter(|&j| j != i).for_each(|j| messages.push((round, transcript[j].clone()))),
+            Mutation::ConfuseType => {
+                for node in 0..count_nodes(honest) {
+                    let original = nth_node(&mut honest.clone(), node).unwrap().clone();
+                    other_types(&original).into_iter().for_each(|value| replace(node, value));
+                }
+                // Serde tags an enum message as an object with the variant as its only key
+                for other in &parsed {
+                    if let (Some((tag, content)), Some((other_tag, _))) = (single_field(honest), single_field(other)) {
+                        if tag != other_tag {
+                            messages.push((round, serde_json::to_vec(&serde_json::json!({ other_tag: content })).unwrap()));
+                        }
+                    }
+                }
+            },
+            Mutation::Oversize => {
+                for node in 0..count_nodes(honest) {
+                    let original = nth_node(&mut honest.clone(), node).unwrap().clone();
+                    replace(node, oversize(&original));
+                }
+            },
+            _ => panic!("{:?} is not a malformation", mutation),
+        }
+    }
+    messages
+}
+
+fn single_field(value: &Value) -> Option<(&String, &Value)> {
+    match value {
+        Value::Object(fields) if fields.len() == 1 => fields.iter().next(),
+        _ => None,
+    }
+}
+
+// Sends the protocol's verifier every malformed message of the kind the mutation makes, failing on the
+// first that panics or that the verifier accepts
+pub fn check_rejections(protocol: &str, mutation: Mutation) -> Result<usize, String> {
+    let messages = malformed_messages(&with_protocol(protocol, Record), mutation);
+    for (round, message) in &messages {
+        match with_protocol(protocol, Deliver { round: *round, mutation, message: message.clone() }) {
+            Err(finding) => return Err(finding.to_string()),
+            Ok(Outcome::Handled(true)) => return Err(format!("Verifier accepted on round {} after {:?} mutation: {}", round, mutation, String::from_utf8_lossy(message))),
+            Ok(_) => (),
+        }
+    }
+    Ok(messages.len())
+}
+
+// Generates a module of tests named after the protocol, checking that its verifier rejects every kind of
+// malformed message. Hyphens in protocol names are written as underscores.
+#[cfg(test)]
+macro_rules! reject_malformed_tests {
+    ($protocol:ident) => {
+        mod $protocol {
+            use super::{check_rejections, Mutation};
+
+            fn check(mutation: Mutation) {
+                let protocol = stringify!($protocol).replace('_', "-");
+                if let Err(e) = check_rejections(&protocol, mutation) {
+                    panic!("{} verifier mishandled a malformed message. {}", protocol, e);
+                }
+            }
+
+            #[test]
+            fn test_rejects_out_of_order_rounds() {
+                check(Mutation::Replay);
+            }
+
+            #[test]
+            fn test_rejects_wrong_variants() {
+                check(Mutation::ConfuseType);
+            }
+
+            #[test]
+            fn test_rejects_oversized_payloads() {
+                check(Mutation::Oversize);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+reject_malformed_tests!(gi);
+#[cfg(test)]
+reject_malformed_tests!(parallel_gi);
+#[cfg(test)]
+reject_malformed_tests!(batch_gi);
+#[cfg(test)]
+reject_malformed_tests!(committed_gi);
+#[cfg(test)]
+reject_malformed_tests!(refined_gi);
+#[cfg(test)]
+reject_malformed_tests!(gni);
+#[cfg(test)]
+reject_malformed_tests!(hardened_gni);
+#[cfg(test)]
+reject_malformed_tests!(schnorr);
+#[cfg(test)]
+reject_malformed_tests!(okamoto);
+#[cfg(test)]
+reject_malformed_tests!(share);
+#[cfg(test)]
+reject_malformed_tests!(coinflip);
+#[cfg(test)]
+reject_malformed_tests!(sudoku);
+#[cfg(test)]
+reject_malformed_tests!(coloring);
+#[cfg(test)]
+reject_malformed_tests!(hamiltonian);
+#[cfg(test)]
+reject_malformed_tests!(connectivity);
+#[cfg(test)]
+reject_malformed_tests!(degree);
+#[cfg(test)]
+reject_malformed_tests!(lookup);
+#[cfg(test)]
+reject_malformed_tests!(membership);
+#[cfg(test)]
+reject_malformed_tests!(permutation_matrix);
+#[cfg(test)]
+reject_malformed_tests!(ot);
+#[cfg(test)]
+reject_malformed_tests!(garbled);
+#[cfg(test)]
+reject_malformed_tests!(commit_and_prove);
+#[cfg(test)]
+reject_malformed_tests!(designated);
+
+#[test]
+fn test_malformed_messages() {
+    let transcript = [br#"{"Graph":{"n":2,"edges":[[0,1]]}}"#.to_vec(), br#"{"Permutation":[1,0]}"#.to_vec()];
+    assert_eq!(malformed_messages(&transcript, Mutation::Replay), [(1, transcript[1].clone()), (2, transcript[0].clone())]);
+    let wrong_variant = malformed_messages(&transcript, Mutation::ConfuseType);
+    assert!(wrong_variant.contains(&(1, br#"{"Permutation":{"edges":[[0,1]],"n":2}}"#.to_vec())));
+    assert!(wrong_variant.contains(&(2, br#"{"Permutation":[1,"zz"]}"#.to_vec())));
+    // Each of the 7 values in the first message is oversized in turn
+    assert_eq!(malformed_messages(&transcript, Mutation::Oversize).iter().filter(|(round, _)| *round == 1).count(), 7);
+}
+
+#[test]
+fn test_mutations() {
+    let honest = br#"{"Graph":{"n":4,"edges":[[0,1],[1,2]]}}"#;
+    let mut input = ArbitraryMessage::new(&[0, 0, 0, 5]);
+    assert_eq!(input.mutate(Mutation::Truncate, honest, &[]), &honest[..5]);
+    // Once the input runs out, every choice is zero, so type confusion replaces the root
+    let mut input = ArbitraryMessage::new(&[]);
+    assert_eq!(input.mutate(Mutation::ConfuseType, honest, &[]), b"null");
+    let mut input = ArbitraryMessage::new(&[]);
+    assert_eq!(input.mutate(Mutation::Replay, honest, &[b"1".to_vec()]), b"1");
+    // The oversized message is still valid JSON
+    let mut input = ArbitraryMessage::new(&[0, 0, 0, 2]);
+    let oversized: Value = serde_json::from_slice(&input.mutate(Mutation::Oversize, honest, &[])).unwrap();
+    assert_eq!(oversized["Graph"]["edges"].as_array().unwrap().len(), OVERSIZE_REPETITIONS);
+}
+
+#[test]
+fn test_fuzz_protocols() {
+    use rand::{Rng, SeedableRng};
+    // Every mutation of every round is tried a few times with random choices, and none may panic. The
+    // choices are seeded so that a failure can be reproduced.
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    FUZZED_PROTOCOLS.iter().for_each(|protocol| {
+        for round in 0..MAX_FUZZED_ROUND as u8 {
+            for mutation in 0..MUTATIONS.len() as u8 {
+                for _ in 0..4 {
+                    let mut data = vec![round, 0, 0, 0, mutation];
+                    data.extend((0..32).map(|_| rng.gen::<u8>()));
+                    if let Err(finding) = fuzz_protocol(protocol, &data) {
+                        panic!("Fuzzing {} found a bug. {}", protocol, finding);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn test_final_verdict() {
+    // Rejects every message until the last, which it accepts whatever it is
+    struct LateVerifier { r: u32 }
+    impl Verifier for LateVerifier {
+        type ProverMessage = crate::graph::GIProverMessage;
+        type VerifierMessage = crate::graph::GIVerifierMessage;
+        fn init(&mut self) -> Self::VerifierMessage { crate::graph::GIVerifierMessage { b: false } }
+        fn handle(&mut self, _msg: &Self::ProverMessage) -> (Self::VerifierMessage, bool) {
+            self.r += 1;
+            (crate::graph::GIVerifierMessage { b: false }, self.r == 2)
+        }
+    }
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    // Not accepting the mutated first message isn't a rejection, since the interaction goes on to be accepted
+    let garbage = br#"{"Graph":{"n":0,"edges":[]}}"#.to_vec();
+    let outcome = play_and_attack(&mut GIProver::new(&instance), &mut LateVerifier { r: 0 }, 1, Mutation::Raw, |_, _| garbage);
+    assert_eq!(outcome, Ok(Outcome::Handled(true)));
+    // The honest GI verifier takes a well-formed graph that isn't isomorphic to the instance on the first
+    // round, and rejects at the end, when the prover's permutation doesn't map to it
+    let other = br#"{"Graph":{"n":4,"edges":[[0,2],[2,3],[1,3],[2,1],[3,0]]}}"#.to_vec();
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let outcome = play_and_attack(&mut GIProver::new(&instance), &mut verifier, 1, Mutation::Raw, |_, _| other);
+    assert_eq!(outcome, Ok(Outcome::Handled(false)));
+}
+
+#[test]
+fn test_fuzz_finds_panics() {
+    // The GI verifier's handle panics on messages of the wrong variant, which validate normally catches
+    struct UnvalidatedVerifier<'a> { inner: GIVerifier<'a> }
+    impl Verifier for UnvalidatedVerifier<'_> {
+        type ProverMessage = crate::graph::GIProverMessage;
+        type VerifierMessage = crate::graph::GIVerifierMessage;
+        fn init(&mut self) -> Self::VerifierMessage { self.inner.init() }
+        fn handle(&mut self, msg: &Self::ProverMessage) -> (Self::VerifierMessage, bool) { self.inner.handle(msg) }
+    }
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = UnvalidatedVerifier { inner: GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance } };
+    // Replaying the round 1 graph on round 2
+    let finding = fuzz_verifier(&mut prover, &mut verifier, &[1, 0, 0, 0, 5]).unwrap_err();
+    assert_eq!((finding.class, finding.round, finding.mutation), (BugClass::Handle, 2, Mutation::Replay));
+}
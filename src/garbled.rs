@@ -0,0 +1,417 @@
+use std::convert::TryFrom;
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::{Prover, Verifier};
+use crate::circuit::{Circuit, Gate};
+use crate::commitment::{commit, Commitment, Opening};
+use crate::graph::check_count;
+use crate::ot::{Ciphertext, decrypt, encrypt_with_randomness, other_public_key, receiver_keys};
+use crate::params::{SchnorrParameters, serde_biguints};
+use crate::randomness::{CheckedThreadRng, checked_thread_rng};
+#[cfg(test)]
+use num_traits::Zero;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Garbled circuits ************
+
+// Each wire carries one of two random labels, standing for 0 and 1. The last bit of a label is its
+// color, and the two labels of a wire always have different colors, so an evaluator holding one label
+// per input wire can find the table row to decrypt without learning which value it holds
+// (point-and-permute).
+pub type Label = [u8; 16];
+
+// Encrypted truth tables for each gate, indexed by the colors of the gate's input labels. NOT gates
+// need no table since their output labels are their input labels swapped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GarbledCircuit {
+    pub tables: Vec<Vec<Label>>,
+}
+
+fn color(label: &Label) -> usize {
+    (label[15] & 1) as usize
+}
+
+// Samples a pair of labels (for 0 and 1) for every wire of the circuit
+pub fn random_labels(circuit: &Circuit) -> Vec<[Label; 2]> {
+    let mut rng = checked_thread_rng();
+    let mut labels: Vec<[Label; 2]> = Vec::with_capacity(circuit.num_wires());
+    let fresh_pair = |rng: &mut CheckedThreadRng| {
+        let zero: Label = rng.gen();
+        let mut one: Label = rng.gen();
+        one[15] = (one[15] & !1) | (1 - color(&zero) as u8);
+        [zero, one]
+    };
+    for _ in 0..circuit.num_inputs {
+        labels.push(fresh_pair(&mut rng));
+    }
+    for gate in circuit.gates.iter() {
+        let pair = match *gate {
+            Gate::Not(a) => [labels[a][1], labels[a][0]],
+            _ => fresh_pair(&mut rng),
+        };
+        labels.push(pair);
+    }
+    labels
+}
+
+// Garbles the circuit with the given wire labels. Garbling is deterministic given the labels, so anyone
+// who learns all of them can check a garbled circuit. Returns None if the labels are inconsistent with
+// the circuit, e.g. if a NOT gate's output labels aren't its input labels swapped.
+pub fn garble(circuit: &Circuit, labels: &[[Label; 2]]) -> Option<GarbledCircuit> {
+    if labels.len() != circuit.num_wires() || labels.iter().any(|x| color(&x[0]) == color(&x[1])) {
+        return None;
+    }
+    let mut tables = Vec::with_capacity(circuit.gates.len());
+    for (k, gate) in circuit.gates.iter().enumerate() {
+        let out = &labels[circuit.num_inputs + k];
+        let table = match *gate {
+            Gate::Not(a) => {
+                if *out != [labels[a][1], labels[a][0]] {
+                    return None;
+                }
+                Vec::new()
+            },
+            Gate::And(a, b) | Gate::Xor(a, b) => {
+                let mut table = vec![[0u8; 16]; 4];
+                for &va in [false, true].iter() {
+                    for &vb in [false, true].iter() {
+                        let (la, lb) = (&labels[a][va as usize], &labels[b][vb as usize]);
+                        let value = if let Gate::And(..) = gate { va && vb } else { va ^ vb };
+                        table[2 * color(la) + color(lb)] = xor_labels(&hash_labels(la, lb, k), &out[value as usize]);
+                    }
+                }
+                table
+            },
+        };
+        tables.push(table);
+    }
+    Some(GarbledCircuit { tables })
+}
+
+// Evaluates a garbled circuit given one label for each input wire, returning the label of the output wire
+pub fn evaluate_garbled(circuit: &Circuit, garbled: &GarbledCircuit, input_labels: &[Label]) -> Option<Label> {
+    if input_labels.len() != circuit.num_inputs || garbled.tables.len() != circuit.gates.len() {
+        return None;
+    }
+    let mut wires = input_labels.to_vec();
+    for (k, gate) in circuit.gates.iter().enumerate() {
+        let label = match *gate {
+            Gate::Not(a) => wires[a],
+            Gate::And(a, b) | Gate::Xor(a, b) => {
+                let (la, lb) = (&wires[a], &wires[b]);
+                let row = garbled.tables[k].get(2 * color(la) + color(lb))?;
+                xor_labels(&hash_labels(la, lb, k), row)
+            },
+        };
+        wires.push(label);
+    }
+    Some(wires[circuit.output])
+}
+
+fn hash_labels(a: &Label, b: &Label, gate: usize) -> Label {
+    let mut hasher = Sha256::new();
+    hasher.update(a);
+    hasher.update(b);
+    hasher.update((gate as u64).to_be_bytes());
+    let mut label = [0u8; 16];
+    label.copy_from_slice(&hasher.finalize()[..16]);
+    label
+}
+
+fn xor_labels(a: &Label, b: &Label) -> Label {
+    let mut result = [0u8; 16];
+    result.iter_mut().enumerate().for_each(|(i, x)| *x = a[i] ^ b[i]);
+    result
+}
+
+// ************ Zero knowledge from garbled circuits implementation ************
+
+// In the protocol of Jawurek, Kerschbaum, and Orlandi, the prover knows a witness w with C(w) = 1 for a
+// public boolean circuit C. The verifier garbles C and transfers the input labels for w to the prover by
+// oblivious transfer, so it never learns w. The prover evaluates the garbled circuit and commits to the
+// output label. The verifier then reveals all of its randomness, and once the prover has checked that
+// the garbling and transfers were honest, it opens its commitment. The verifier accepts if the output
+// label is the label for 1, which the prover could only have obtained by evaluating C to 1.
+
+#[derive(Clone)]
+pub enum JKOVerifierMessage {
+    // Random group element C for the oblivious transfers
+    Setup(BigUint),
+    // Garbled circuit along with encryptions of both labels of each input wire
+    GarbledCircuit(GarbledCircuit, Vec<(Ciphertext, Ciphertext)>),
+    // All wire labels and the randomness used to encrypt the input labels
+    Reveal(Vec<[Label; 2]>, Vec<(BigUint, BigUint)>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum JKOProverMessage {
+    // OT public keys PK_0 for each input wire
+    PublicKeys(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Commitment to the output label
+    Commitment(Commitment),
+    // Opening of the commitment to the output label
+    Opening(Opening),
+    // The prover caught the verifier garbling dishonestly and refuses to continue
+    Abort,
+    // Interaction complete
+    Done
+}
+
+pub struct JKOProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random group element for the oblivious transfers
+    pub c: BigUint,
+    // OT secret key k and public key PK_0 for each input wire
+    pub ot_keys: Vec<(BigUint, BigUint)>,
+    // Garbled circuit and OT ciphertexts received from the verifier
+    pub garbled: Option<(GarbledCircuit, Vec<(Ciphertext, Ciphertext)>)>,
+    // Opening of the commitment to the output label
+    pub opening: Option<Opening>,
+    pub params: &'a SchnorrParameters,
+    pub circuit: &'a Circuit,
+    pub witness: &'a [bool],
+}
+
+impl JKOProver<'_> {
+    // Checks that the revealed labels and randomness reproduce exactly the garbled circuit and ciphertexts
+    // the verifier sent. Since both ciphertexts are checked, the outcome doesn't depend on the witness.
+    fn check_reveal(&self, labels: &[[Label; 2]], randomness: &[(BigUint, BigUint)]) -> bool {
+        let (garbled, ciphertexts) = self.garbled.as_ref().unwrap();
+        if garble(self.circuit, labels).as_ref() != Some(garbled)
+            || ciphertexts.len() != self.circuit.num_inputs || randomness.len() != self.circuit.num_inputs {
+            return false;
+        }
+        (0..self.circuit.num_inputs).all(|i| {
+            let pk0 = &self.ot_keys[i].1;
+            let pk1 = other_public_key(self.params, &self.c, pk0);
+            ciphertexts[i].0 == encrypt_with_randomness(self.params, pk0, &labels[i][0], &randomness[i].0)
+                && ciphertexts[i].1 == encrypt_with_randomness(self.params, &pk1, &labels[i][1], &randomness[i].1)
+        })
+    }
+}
+
+impl Prover for JKOProver<'_> {
+    type ProverMessage = JKOProverMessage;
+    type VerifierMessage = JKOVerifierMessage;
+
+    fn handle(&mut self, msg: &JKOVerifierMessage) -> (JKOProverMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            // During the first round, the prover requests the input labels for its witness bits
+            (1, JKOVerifierMessage::Setup(c)) => {
+                self.c = c.clone();
+                self.ot_keys = self.witness.iter().map(|&b| receiver_keys(self.params, c, b)).collect();
+                (JKOProverMessage::PublicKeys(self.ot_keys.iter().map(|x| x.1.clone()).collect()), false)
+            },
+            // During the second round, the prover evaluates the garbled circuit and commits to the output label
+            (2, JKOVerifierMessage::GarbledCircuit(garbled, ciphertexts)) => {
+                let input_labels: Option<Vec<Label>> = self.witness.iter().zip(self.ot_keys.iter()).zip(ciphertexts.iter())
+                    .map(|((&b, (k, _)), (c0, c1))| Label::try_from(decrypt(self.params, k, if b { c1 } else { c0 }).as_slice()).ok())
+                    .collect();
+                let output = input_labels.and_then(|x| evaluate_garbled(self.circuit, garbled, &x));
+                // If evaluation fails, commit to garbage so as not to reveal anything before the verifier opens its garbling
+                let (commitment, opening) = commit(&output.unwrap_or_else(|| checked_thread_rng().gen()));
+                self.opening = Some(opening);
+                self.garbled = Some((garbled.clone(), ciphertexts.clone()));
+                (JKOProverMessage::Commitment(commitment), false)
+            },
+            // During the third round, the prover checks the verifier's garbling before opening its commitment
+            (3, JKOVerifierMessage::Reveal(labels, randomness)) => {
+                if self.check_reveal(labels, randomness) {
+                    (JKOProverMessage::Opening(self.opening.clone().unwrap()), false)
+                } else {
+                    narrate!("Prover detected a dishonest garbling.");
+                    (JKOProverMessage::Abort, false)
+                }
+            },
+            (1..=3, _) => panic!("Verifier did not send a valid message on round {}!", self.r),
+            // After opening its commitment, the prover sends a message to terminate the interaction
+            _ => (JKOProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &JKOVerifierMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, JKOVerifierMessage::Setup(c)) => self.params.check_element(c),
+            (1, _) => Err("expected a setup message".to_string()),
+            (2, JKOVerifierMessage::GarbledCircuit(_, ciphertexts)) => {
+                check_count(ciphertexts.len(), self.circuit.num_inputs, "ciphertext pairs")?;
+                ciphertexts.iter().try_for_each(|((e0, _), (e1, _))| {
+                    self.params.check_element(e0)?;
+                    self.params.check_element(e1)
+                })
+            },
+            (2, _) => Err("expected a garbled circuit".to_string()),
+            (3, JKOVerifierMessage::Reveal(..)) => Ok(()),
+            (3, _) => Err("expected the garbling to be revealed".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct JKOVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random group element for the oblivious transfers
+    pub c: BigUint,
+    // Labels for every wire of the garbled circuit
+    pub labels: Vec<[Label; 2]>,
+    // Randomness used to encrypt both labels of each input wire
+    pub ot_randomness: Vec<(BigUint, BigUint)>,
+    // Prover's commitment to the output label
+    pub commitment: Option<Commitment>,
+    pub params: &'a SchnorrParameters,
+    pub circuit: &'a Circuit,
+}
+
+impl Verifier for JKOVerifier<'_> {
+    type ProverMessage = JKOProverMessage;
+    type VerifierMessage = JKOVerifierMessage;
+
+    fn init(&mut self) -> JKOVerifierMessage {
+        narrate!("Initializing garbled circuit proof for a circuit with {} inputs and {} gates.", self.circuit.num_inputs, self.circuit.gates.len());
+        self.c = self.params.random_generator();
+        JKOVerifierMessage::Setup(self.c.clone())
+    }
+
+    fn validate(&self, msg: &JKOProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            // Each PK_0 has to be invertible for the verifier to derive PK_1
+            (1, JKOProverMessage::PublicKeys(keys)) => {
+                check_count(keys.len(), self.circuit.num_inputs, "public keys")?;
+                keys.iter().try_for_each(|pk0| self.params.check_element(pk0))
+            },
+            (1, _) => Err("expected public keys".to_string()),
+            (2, JKOProverMessage::Commitment(_)) => Ok(()),
+            (2, _) => Err("expected a commitment".to_string()),
+            (3, JKOProverMessage::Opening(_)) | (3, JKOProverMessage::Abort) => Ok(()),
+            (3, _) => Err("expected an opening".to_string()),
+            _ => Err("expected no more messages".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &JKOProverMessage) -> (JKOVerifierMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match (self.r, msg) {
+            (1, JKOProverMessage::PublicKeys(keys)) => {
+                if keys.len() != self.circuit.num_inputs {
+                    panic!("Prover did not send one public key per input wire on round 1!")
+                }
+                // The verifier garbles the circuit and encrypts both labels of each input wire
+                self.labels = random_labels(self.circuit);
+                let garbled = garble(self.circuit, &self.labels).unwrap();
+                self.ot_randomness = keys.iter().map(|_| (params.random_exponent(), params.random_exponent())).collect();
+                let ciphertexts = keys.iter().enumerate().map(|(i, pk0)| {
+                    let pk1 = other_public_key(params, &self.c, pk0);
+                    (encrypt_with_randomness(params, pk0, &self.labels[i][0], &self.ot_randomness[i].0),
+                     encrypt_with_randomness(params, &pk1, &self.labels[i][1], &self.ot_randomness[i].1))
+                }).collect();
+                (JKOVerifierMessage::GarbledCircuit(garbled, ciphertexts), false)
+            },
+            (2, JKOProverMessage::Commitment(commitment)) => {
+                // Once the prover is committed to its output, revealing the garbling gives it nothing new
+                self.commitment = Some(commitment.clone());
+                (JKOVerifierMessage::Reveal(self.labels.clone(), self.ot_randomness.clone()), false)
+            },
+            (3, JKOProverMessage::Opening(opening)) => {
+                let accept = self.commitment.as_ref().unwrap().verify(opening) && opening.value == self.labels[self.circuit.output][1];
+                narrate!("Verifier received output label {}.", if accept { "for 1" } else { "not for 1" });
+                (JKOVerifierMessage::Setup(self.c.clone()), accept)
+            },
+            (3, JKOProverMessage::Abort) => (JKOVerifierMessage::Setup(self.c.clone()), false),
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+// Outputs 1 exactly when the four input bits are 1, 0, 1, 1, for tests and the fuzzer
+pub(crate) fn example_circuit() -> Circuit {
+    Circuit::parse("
+        inputs 4
+        not 1
+        and 0 4
+        and 5 2
+        and 6 3
+        output 7
+    ").unwrap()
+}
+
+#[cfg(test)]
+fn run_jko(params: &SchnorrParameters, circuit: &Circuit, witness: &[bool], tamper: bool) -> bool {
+    // A cheating verifier that flips a bit of one garbled table, which the prover should notice
+    struct TamperingVerifier<'a> { inner: JKOVerifier<'a> }
+    impl Verifier for TamperingVerifier<'_> {
+        type ProverMessage = JKOProverMessage;
+        type VerifierMessage = JKOVerifierMessage;
+
+        fn init(&mut self) -> JKOVerifierMessage {
+            self.inner.init()
+        }
+
+        fn handle(&mut self, msg: &JKOProverMessage) -> (JKOVerifierMessage, bool) {
+            match self.inner.handle(msg) {
+                (JKOVerifierMessage::GarbledCircuit(mut garbled, ciphertexts), accept) => {
+                    garbled.tables[1][0][0] ^= 1;
+                    (JKOVerifierMessage::GarbledCircuit(garbled, ciphertexts), accept)
+                },
+                x => x,
+            }
+        }
+    }
+    let mut prover = JKOProver { r: 0, c: BigUint::zero(), ot_keys: Vec::new(), garbled: None, opening: None, params, circuit, witness };
+    let mut verifier = JKOVerifier { r: 0, c: BigUint::zero(), labels: Vec::new(), ot_randomness: Vec::new(), commitment: None, params, circuit };
+    if tamper {
+        run_interactive_proof(&mut prover, &mut TamperingVerifier { inner: verifier })
+    } else {
+        run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+#[test]
+fn test_garbled_evaluation() {
+    // Evaluating the garbled circuit should give the label for the plaintext output on every input
+    let circuit = Circuit::parse("inputs 3\nxor 0 1\nnot 3\nand 4 2\noutput 5").unwrap();
+    let labels = random_labels(&circuit);
+    let garbled = garble(&circuit, &labels).unwrap();
+    for x in 0..8 {
+        let inputs: Vec<bool> = (0..3).map(|i| (x >> i) & 1 == 1).collect();
+        let input_labels: Vec<Label> = inputs.iter().enumerate().map(|(i, &b)| labels[i][b as usize]).collect();
+        let output = evaluate_garbled(&circuit, &garbled, &input_labels).unwrap();
+        assert_eq!(output, labels[circuit.output][circuit.evaluate(&inputs) as usize]);
+    }
+}
+
+#[test]
+fn test_garble_rejects_inconsistent_labels() {
+    let circuit = Circuit::parse("inputs 1\nnot 0\noutput 1").unwrap();
+    let mut labels = random_labels(&circuit);
+    labels[1].swap(0, 1);
+    assert_eq!(garble(&circuit, &labels), None);
+}
+
+#[test]
+fn test_jko_interactive_proof() {
+    let params = SchnorrParameters::generate(64);
+    let circuit = example_circuit();
+    assert!(run_jko(&params, &circuit, &[true, false, true, true], false));
+}
+
+#[test]
+fn test_jko_wrong_witness() {
+    let params = SchnorrParameters::generate(64);
+    let circuit = example_circuit();
+    // A prover without a satisfying input only learns the label for 0, and cannot guess the label for 1
+    assert!(!run_jko(&params, &circuit, &[true, true, true, true], false));
+}
+
+#[test]
+fn test_jko_dishonest_verifier() {
+    let params = SchnorrParameters::generate(64);
+    let circuit = example_circuit();
+    assert!(!run_jko(&params, &circuit, &[true, false, true, true], true));
+}
@@ -1,33 +1,78 @@
-use std::{collections::HashSet, cmp, fmt};
-use rand::{thread_rng, Rng, seq::SliceRandom};
+use std::{collections::{hash_map::DefaultHasher, hash_set, BTreeSet, HashSet}, cmp, mem, slice, convert::TryFrom, fmt, hash::{Hash, Hasher}, time::{Duration, Instant}};
+use sha2::{Digest, Sha256};
+use rand::Rng;
 use itertools::Itertools;
-use rayon::prelude::*;
-use crate::{Prover, Verifier, run_interactive_proof};
+use serde::{Serialize, Deserialize};
+use crate::{Diagnosis, Prover, ResumableProver, Verifier};
+use crate::beacon::{BiasedVerifier, PublicCoinVerifier};
+use crate::challenge::Challenge;
+use crate::commitment::{commit, Commitment, Opening};
+use crate::config;
+use crate::escrow::AnnotatingProver;
+use crate::isomorphism::{self, DecisionMode};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::statement::{Statement, Transparent, Witness};
+use crate::transcript::{ChallengeTranscript, ProofContext, TranscriptMessage};
+#[cfg(test)]
+use rand::thread_rng;
+#[cfg(test)]
+use crate::experiments::run_trials;
+#[cfg(test)]
+use crate::run_interactive_proof;
 
 // ************ Zero-knowledge graph isomorphism proof implementation ************
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum GIProverMessage {
     // Random permutation of g0 or g1
     Graph(Graph),
     // Isomorphism between graph permutation and gb
-    Isomorphism(Vec<u32>),
+    Isomorphism(Permutation),
     // Interaction complete
     Done
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GIVerifierMessage {
     // Random graph for prover to prove isomorphism with random permutation
-    b: bool,
+    pub b: bool,
 }
 
+#[derive(Clone)]
 pub struct GIProver<'a> {
     // Keep track of round number
     pub r: u32,
     // Random permutation sent to verifier
     pub random_perm: Graph,
+    // Isomorphism taking g0 to the random permutation
+    pub permutation: Permutation,
+    // Isomorphism taking g0 to g1, if known; otherwise the prover searches for its answers
+    pub witness: Option<Permutation>,
     pub instance: &'a GraphPair,
 }
 
+impl<'a> GIProver<'a> {
+    pub fn new(instance: &'a GraphPair) -> GIProver<'a> {
+        GIProver { r: 0, random_perm: Graph::new(0, Vec::new()), permutation: Permutation::identity(0), witness: None, instance }
+    }
+
+    // Prover answering with the witness instead of searching, which works on instances of any size
+    pub fn with_witness(instance: &'a GraphPair, witness: Permutation) -> GIProver<'a> {
+        GIProver { witness: Some(witness), ..GIProver::new(instance) }
+    }
+
+    // Isomorphism from the random permutation to g1 if b, else to g0
+    fn answer(&self, b: bool) -> Option<Permutation> {
+        match (&self.witness, b) {
+            // H = permutation(g0), so its inverse takes H back to g0, and the witness then takes g0 to g1
+            (Some(_), false) => Some(self.permutation.invert()),
+            (Some(witness), true) => Some(self.permutation.invert().compose(witness)),
+            (None, _) => self.random_perm.find_isomorphism_to(if b {&self.instance.g1} else {&self.instance.g0}),
+        }
+    }
+}
+
 impl Prover for GIProver<'_> {
     type ProverMessage = GIProverMessage;
     type VerifierMessage = GIVerifierMessage;
@@ -36,50 +81,144 @@ impl Prover for GIProver<'_> {
         self.r += 1;
         match self.r {
             // During the first round, the prover sends random permutation of G0 to the verifier
-            1 => { self.random_perm = self.instance.g0.random_permutation(); (GIProverMessage::Graph(self.random_perm.clone()), false) },
+            1 => {
+                self.permutation = Permutation::random(self.instance.g0.n, &mut checked_thread_rng());
+                self.random_perm = self.instance.g0.permute_secret(&self.permutation);
+                (GIProverMessage::Graph(self.random_perm.clone()), false)
+            },
             // During the second round, the prover sends an isomorphism from the random permutation to a graph of verifier's choosing
-            2 => (GIProverMessage::Isomorphism(self.random_perm.find_isomorphism_to(if msg.b {&self.instance.g1} else {&self.instance.g0}).unwrap()), false),
+            2 => match self.answer(msg.b) {
+                Some(isomorphism) => (GIProverMessage::Isomorphism(isomorphism), false),
+                // A prover that can't find the isomorphism ends the interaction unanswered, which is never accepted
+                None => {
+                    narrate!("Prover could not find an isomorphism within its budget, aborting.");
+                    (GIProverMessage::Done, true)
+                },
+            },
             // After sending an isomorphism, the prover sends a message to terminate the interaction
             _ => (GIProverMessage::Done, true),
         }
     }
 }
 
+// State of a GIProver between messages, with the digest of its instance in place of the instance
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GIProverState {
+    pub instance: [u8; 32],
+    pub r: u32,
+    pub random_perm: Graph,
+    pub permutation: Permutation,
+}
+
+impl ResumableProver for GIProver<'_> {
+    type ProverState = GIProverState;
+
+    fn snapshot(&self) -> GIProverState {
+        GIProverState { instance: self.instance.digest(), r: self.r, random_perm: self.random_perm.clone(), permutation: self.permutation.clone() }
+    }
+
+    fn restore(&mut self, state: GIProverState) -> Result<(), String> {
+        if state.instance != self.instance.digest() {
+            return Err("Snapshot was taken on another instance".to_string());
+        }
+        // Once the graph is sent, the prover answers for it with the permutation, so the two must agree
+        if state.r > 0 && (state.permutation.n() != self.instance.g0.n || self.instance.g0.permute(&state.permutation) != state.random_perm) {
+            return Err("Snapshot's graph is not its permutation of g0".to_string());
+        }
+        self.r = state.r;
+        self.random_perm = state.random_perm;
+        self.permutation = state.permutation;
+        Ok(())
+    }
+}
+
+// After sending its graph H, the prover notes the isomorphism mapping H onto g0, which shows an auditor
+// that H was a copy of g0 rather than of whichever graph a cheating prover guessed would be challenged.
+// That is the inverse of the permutation the prover built H with, so no search is needed.
+impl AnnotatingProver for GIProver<'_> {
+    fn annotate(&self) -> Vec<(String, Vec<u8>)> {
+        match self.r {
+            1 => vec![("isomorphism to g0".to_string(), bincode::serialize(&self.permutation.invert()).unwrap())],
+            _ => Vec::new(),
+        }
+    }
+}
+
+// How a malicious GI prover picks the graph it sends
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GuessStrategy {
+    // Guesses 1 with probability p, with a fresh permutation every time
+    Random,
+    // Always guesses 0, sending a permutation of g0
+    AlwaysZero,
+    // Guesses like Random the first time, then sends the same graph on every later repetition it is used
+    // for, as a prover that saves on randomness might
+    ReusePrevious,
+}
+
 // A malicious prover can do no better than randomly guessing bit b and sending a permutation of the corresponding graph
+#[derive(Clone)]
 pub struct GIProverMalicious<'a> {
     // Keep track of round number
     pub r: u32,
     // Random isomorphism of chosen graph, result of applying this isomorphism sent to verifier
-    pub isomorphism: Vec<u32>,
+    pub isomorphism: Permutation,
     pub instance: &'a GraphPair,
     // Probability of guessing 1
     pub p: f64,
+    pub strategy: GuessStrategy,
+    // Last bit guessed
+    pub guess: bool,
+}
+
+impl GIProverMalicious<'_> {
+    // Guesses each challenge uniformly at random, like a prover that knows nothing about the verifier
+    pub fn new(instance: &GraphPair) -> GIProverMalicious<'_> {
+        GIProverMalicious { r: 0, isomorphism: Permutation::identity(0), instance, p: 0.5, strategy: GuessStrategy::Random, guess: false }
+    }
+
+    // Guesses 1 with probability p under the strategy, failing if p isn't a probability
+    pub fn with_strategy(instance: &GraphPair, p: f64, strategy: GuessStrategy) -> Result<GIProverMalicious<'_>, String> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(format!("Guess probability {} is not a probability", p));
+        }
+        Ok(GIProverMalicious { p, strategy, ..GIProverMalicious::new(instance) })
+    }
 }
 
 impl Prover for GIProverMalicious<'_> {
     type ProverMessage = GIProverMessage;
     type VerifierMessage = GIVerifierMessage;
 
-    fn handle(&mut self, msg: &GIVerifierMessage) -> (GIProverMessage, bool) {
+    fn handle(&mut self, _msg: &GIVerifierMessage) -> (GIProverMessage, bool) {
         self.r += 1;
         match self.r {
-            // In the first round, the prover guesses a random bit and sends a random permutation of the corresponding graph
+            // In the first round, the prover guesses a bit and sends a random permutation of the corresponding graph
             1 => {
-                let b = rand::thread_rng().gen_bool(0.5);
-                println!("Prover guessed bit {}.", if b {1} else {0});
-                let graph = if b {self.instance.g1.clone()} else {self.instance.g0.clone()};
-                self.isomorphism = (0..graph.n).collect::<Vec<u32>>();
-                self.isomorphism.shuffle(&mut thread_rng());
+                // A prover reused after a repetition starts again from round 0 with its last permutation
+                let reuse = self.strategy == GuessStrategy::ReusePrevious && self.isomorphism.n() == self.instance.g0.n;
+                if !reuse {
+                    self.guess = match self.strategy {
+                        GuessStrategy::AlwaysZero => false,
+                        _ => checked_thread_rng().gen_bool(self.p),
+                    };
+                }
+                narrate!("Prover guessed bit {}.", if self.guess {1} else {0});
+                let graph = if self.guess {&self.instance.g1} else {&self.instance.g0};
+                if !reuse {
+                    self.isomorphism = Permutation::random(graph.n, &mut checked_thread_rng());
+                }
                 (GIProverMessage::Graph(graph.permute(&self.isomorphism)), false)
             },
             // The prover can only find an isomorphism to the graph it chose, so it sends it regardless of what the verifier chooses
-            2 => (GIProverMessage::Isomorphism(invert_isomorphism(&self.isomorphism)), false),
+            2 => (GIProverMessage::Isomorphism(self.isomorphism.invert()), false),
             // After sending an isomorphism, the prover sends a message to terminate the interaction
             _ => (GIProverMessage::Done, true),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct GIVerifier<'a> {
     // Keep track of round number
     pub r: u32,
@@ -95,19 +234,55 @@ impl Verifier for GIVerifier<'_> {
     type VerifierMessage = GIVerifierMessage;
 
     fn init(&mut self) -> GIVerifierMessage {
-        println!("Initializing GI instance with graphs {:?} and {:?}.", &self.instance.g0, &self.instance.g1);
+        narrate!("Initializing GI instance with graphs {:?} and {:?}.", &self.instance.g0, &self.instance.g1);
         GIVerifierMessage{ b: false }
     }
 
+    fn validate(&self, msg: &GIProverMessage) -> Result<(), String> {
+        let n = self.instance.g0.n;
+        match (self.r + 1, msg) {
+            (1, GIProverMessage::Graph(graph)) => check_vertex_count(graph, n),
+            (1, _) => Err("expected a graph".to_string()),
+            (_, GIProverMessage::Isomorphism(isomorphism)) => check_count(isomorphism.n() as usize, n as usize, "vertices in isomorphism"),
+            _ => Err("expected an isomorphism".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &GIProverMessage) -> Option<Diagnosis> {
+        if let GIProverMessage::Isomorphism(isomorphism) = msg {
+            let (target, check) = if self.b { (&self.instance.g1, "permuted graph != g1") } else { (&self.instance.g0, "permuted graph != g0") };
+            // Distinguishes a wrong isomorphism from a graph that was never a copy of the challenged graph
+            let cause = match self.random_perm.approximate_edit_distance(target) {
+                0 => "the prover's graph is a copy of the challenged graph, so the isomorphism is wrong".to_string(),
+                d => format!("the prover's graph is at most {} edge edits from a copy of the challenged graph", d),
+            };
+            Some(Diagnosis::new(check, Some(format!("{}; {}", self.random_perm.permute(isomorphism).diff(target), cause))))
+        } else {
+            None
+        }
+    }
+
     fn handle(&mut self, msg: &GIProverMessage) -> (GIVerifierMessage, bool) {
+        self.handle_with(msg, None)
+    }
+}
+
+impl PublicCoinVerifier for GIVerifier<'_> {
+    type Challenge = bool;
+
+    fn challenge_space(&self) -> Option<()> {
+        if self.r == 0 { Some(()) } else { None }
+    }
+
+    fn handle_with(&mut self, msg: &GIProverMessage, challenge: Option<bool>) -> (GIVerifierMessage, bool) {
         self.r += 1;
         match self.r {
             1 => {
                 if let GIProverMessage::Graph(random_perm) = msg {
-                    println!("Verifier received permutation {:?}.", random_perm);
+                    narrate!("Verifier received permutation {:?}.", random_perm);
                     self.random_perm = random_perm.clone();
-                    self.b = rand::thread_rng().gen_bool(0.5);
-                    println!("Verifier chose graph {}.", if self.b { 1 } else { 0 });
+                    self.b = challenge.unwrap_or_else(|| bool::sample(&(), &mut checked_thread_rng()));
+                    narrate!("Verifier chose graph {}.", if self.b { 1 } else { 0 });
                     (GIVerifierMessage { b: self.b }, false)
                 } else {
                     panic!("Prover did not send a valid graph on round 1!")
@@ -115,8 +290,8 @@ impl Verifier for GIVerifier<'_> {
             },
             _ => {
                 if let GIProverMessage::Isomorphism(isomorphism) = msg {
-                    println!("Verifier received isomorphism {:?}.", isomorphism);
-                    (GIVerifierMessage { b: self.b }, &self.random_perm.permute(&isomorphism) == if self.b {&self.instance.g1} else {&self.instance.g0})
+                    narrate!("Verifier received isomorphism {:?}.", isomorphism);
+                    (GIVerifierMessage { b: self.b }, &self.random_perm.permute(isomorphism) == if self.b {&self.instance.g1} else {&self.instance.g0})
                 } else {
                     panic!("Prover did not send a valid isomorphism on round 2!")
                 }
@@ -126,17 +301,32 @@ impl Verifier for GIVerifier<'_> {
     }
 }
 
+// A GI verifier that challenges with g1 with probability q rather than 1/2, see BiasedVerifier
+pub type GIVerifierBiased<'a> = BiasedVerifier<GIVerifier<'a>>;
+
+impl TranscriptMessage for GIProverMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        match self {
+            GIProverMessage::Graph(graph) => transcript.append_message(b"graph", &graph.encode()),
+            GIProverMessage::Isomorphism(isomorphism) => transcript.append_message(b"isomorphism", &isomorphism.encode()),
+            GIProverMessage::Done => transcript.append_message(b"done", &[]),
+        }
+    }
+}
+
+impl TranscriptMessage for GIVerifierMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_challenge(b"challenge", &self.b);
+    }
+}
+
 #[test]
 fn test_gi_interactive_proof() {
     let instance = GraphPair {
         g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
         g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
     };
-    let mut prover = GIProver{
-        r: 0,
-        random_perm: Graph::new(0, Vec::new()),
-        instance: &instance,
-    };
+    let mut prover = GIProver::new(&instance);
     let mut verifier = GIVerifier{
         r: 0,
         b: false,
@@ -151,7 +341,7 @@ fn test_gi_interactive_proof() {
 fn test_gi_malicious_interactive_proof() {
     // Malicious prover should have probability of 1/2^N of successfully convincing
     // verifier after N rounds of the interactive proof.
-    let N = 1000;
+    let n = 1000;
     let instance = GraphPair {
         g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
         g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
@@ -159,12 +349,14 @@ fn test_gi_malicious_interactive_proof() {
 
     // There should be a negligible chance of the prover successfully convincing the verifier
     // in all 1000 rounds.
-    let successes = (0..N).collect::<Vec<i32>>().par_iter().filter(|_| {
+    let successes = run_trials(n, || {
         let mut prover = GIProverMalicious{
             r: 0,
-            isomorphism: Vec::new(),
+            isomorphism: Permutation::identity(0),
             instance: &instance,
             p: 0.5,
+            strategy: GuessStrategy::Random,
+            guess: false,
         };
         let mut verifier = GIVerifier{
             r: 0,
@@ -173,23 +365,324 @@ fn test_gi_malicious_interactive_proof() {
             instance: &instance,
         };
         run_interactive_proof(&mut prover, &mut verifier)
-    }).count();
+    }).accepted;
+
+    narrate!("Malicious GI prover succeeded {} out of {} times.", successes, n);
+
+    assert!(successes != n);
+}
+
+#[test]
+fn test_gi_malicious_strategies() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    let sent = |prover: &mut GIProverMalicious| {
+        prover.r = 0;
+        match prover.handle(&GIVerifierMessage { b: false }).0 {
+            GIProverMessage::Graph(graph) => graph,
+            _ => panic!("Expected a graph"),
+        }
+    };
+    // The prover guesses 1 as often as p says, unless it always guesses 0
+    let mut prover = GIProverMalicious::with_strategy(&instance, 1.0, GuessStrategy::Random).unwrap();
+    assert!(sent(&mut prover).find_isomorphism_to(&instance.g1).is_some());
+    prover.p = 0.0;
+    assert!(sent(&mut prover).find_isomorphism_to(&instance.g0).is_some());
+    prover.p = 1.0;
+    prover.strategy = GuessStrategy::AlwaysZero;
+    assert!(sent(&mut prover).find_isomorphism_to(&instance.g0).is_some());
+    // A prover reusing its permutation sends the same graph each repetition, and a fresh one otherwise
+    let mut prover = GIProverMalicious::with_strategy(&instance, 0.5, GuessStrategy::ReusePrevious).unwrap();
+    let first = sent(&mut prover);
+    assert!((0..4).all(|_| sent(&mut prover) == first));
+    prover.strategy = GuessStrategy::Random;
+    assert!((0..8).any(|_| sent(&mut prover) != first));
+    // Probabilities outside [0, 1] are refused when the prover is built, rather than panicking when it guesses
+    assert!(GIProverMalicious::with_strategy(&instance, 1.5, GuessStrategy::Random).is_err());
+    assert!(GIProverMalicious::with_strategy(&instance, f64::NAN, GuessStrategy::Random).is_err());
+}
+
+// ************ Constant-round zero-knowledge graph isomorphism proof implementation ************
+
+// Running the GI proof k times in sequence brings the soundness error down to 2^-k, but takes 2k rounds.
+// Here the k repetitions run in parallel in three messages: the prover commits to k random permutations
+// H_1, ..., H_k of G0, the verifier sends a k-bit challenge b, and the prover opens every commitment and
+// sends an isomorphism from each H_i to G_(b_i). The commitments aren't needed for soundness, but keep
+// the graphs hidden until the challenge is fixed and make the first message k digests long regardless
+// of the size of the graphs.
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ParallelGIProverMessage {
+    // Commitments to random permutations of g0
+    Commitments(Vec<Commitment>),
+    // Openings of the commitments and isomorphisms from each permutation to the challenged graph
+    Openings(Vec<Opening>, Vec<Permutation>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ParallelGIVerifierMessage {
+    // Random graph to prove isomorphism with for each repetition
+    pub challenge: Vec<bool>,
+}
+
+pub struct ParallelGIProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of parallel repetitions
+    pub k: usize,
+    // Random permutations committed to, along with their openings
+    pub random_perms: Vec<Graph>,
+    pub openings: Vec<Opening>,
+    pub instance: &'a GraphPair,
+}
+
+impl Prover for ParallelGIProver<'_> {
+    type ProverMessage = ParallelGIProverMessage;
+    type VerifierMessage = ParallelGIVerifierMessage;
+
+    fn handle(&mut self, msg: &ParallelGIVerifierMessage) -> (ParallelGIProverMessage, bool) {
+        self.r += 1;
+        match self.r {
+            // During the first round, the prover commits to k random permutations of G0
+            1 => {
+                self.random_perms = (0..self.k).map(|_| self.instance.g0.random_permutation()).collect();
+                let (commitments, openings) = self.random_perms.iter().map(|x| commit(&x.encode())).unzip();
+                self.openings = openings;
+                (ParallelGIProverMessage::Commitments(commitments), false)
+            },
+            // During the second round, the prover opens every commitment along with an isomorphism to the challenged graph
+            2 => {
+                let isomorphisms = self.random_perms.iter().zip(msg.challenge.iter())
+                    .map(|(perm, &b)| perm.find_isomorphism_to(if b {&self.instance.g1} else {&self.instance.g0}))
+                    .collect();
+                match isomorphisms {
+                    Some(isomorphisms) => (ParallelGIProverMessage::Openings(self.openings.clone(), isomorphisms), false),
+                    None => {
+                        narrate!("Prover could not find an isomorphism within its budget, aborting.");
+                        (ParallelGIProverMessage::Done, true)
+                    },
+                }
+            },
+            // After sending the openings, the prover sends a message to terminate the interaction
+            _ => (ParallelGIProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &ParallelGIVerifierMessage) -> Result<(), String> {
+        if self.r + 1 == 2 { msg.challenge.check(&self.k) } else { Ok(()) }
+    }
+}
+
+pub struct ParallelGIVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of parallel repetitions
+    pub k: usize,
+    // Randomly chosen bits
+    pub challenge: Vec<bool>,
+    // Commitments received from prover
+    pub commitments: Vec<Commitment>,
+    pub instance: &'a GraphPair,
+}
+
+impl Verifier for ParallelGIVerifier<'_> {
+    type ProverMessage = ParallelGIProverMessage;
+    type VerifierMessage = ParallelGIVerifierMessage;
+
+    fn init(&mut self) -> ParallelGIVerifierMessage {
+        narrate!("Initializing GI instance with {} parallel repetitions.", self.k);
+        ParallelGIVerifierMessage { challenge: Vec::new() }
+    }
+
+    fn validate(&self, msg: &ParallelGIProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, ParallelGIProverMessage::Commitments(commitments)) => check_count(commitments.len(), self.k, "commitments"),
+            (1, _) => Err("expected commitments".to_string()),
+            (_, ParallelGIProverMessage::Openings(openings, isomorphisms)) => {
+                check_count(openings.len(), self.k, "openings")?;
+                check_count(isomorphisms.len(), self.k, "isomorphisms")?;
+                isomorphisms.iter().try_for_each(|x| check_count(x.n() as usize, self.instance.g0.n as usize, "vertices in isomorphism"))
+            },
+            _ => Err("expected openings".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &ParallelGIProverMessage) -> Option<Diagnosis> {
+        let (openings, isomorphisms) = match msg {
+            ParallelGIProverMessage::Openings(openings, isomorphisms) => (openings, isomorphisms),
+            _ => return None,
+        };
+        // Nothing to diagnose before the verifier has committed to its challenge
+        if self.commitments.len() != self.k || self.challenge.len() != self.k {
+            return None;
+        }
+        // The message may not have been validated, so its lengths and sizes are checked before permuting
+        let counts = check_count(openings.len(), self.k, "openings").and_then(|_| check_count(isomorphisms.len(), self.k, "isomorphisms"));
+        if let Err(reason) = counts {
+            return Some(Diagnosis::new("wrong number of openings", Some(reason)));
+        }
+        // Reports the first repetition that failed
+        (0..self.k).find_map(|i| {
+            if !self.commitments[i].verify(&openings[i]) {
+                return Some(Diagnosis::new(&format!("opening {} does not match commitment {}", i, i), None));
+            }
+            let target = if self.challenge[i] {&self.instance.g1} else {&self.instance.g0};
+            if let Err(reason) = check_count(isomorphisms[i].n() as usize, target.n as usize, "vertices in isomorphism") {
+                return Some(Diagnosis::new(&format!("isomorphism {} has the wrong size", i), Some(reason)));
+            }
+            let details = Graph::decode(&openings[i].value).filter(|committed| committed.n == target.n)
+                .map(|committed| committed.permute(&isomorphisms[i]).diff(target).to_string());
+            if openings[i].value != target.permute(&isomorphisms[i].invert()).encode() {
+                Some(Diagnosis::new(&format!("permuted graph {} != g{}", i, self.challenge[i] as u8), details))
+            } else {
+                None
+            }
+        })
+    }
 
-    println!("Malicious GI prover succeeded {} out of {} times.", successes, N);
+    fn handle(&mut self, msg: &ParallelGIProverMessage) -> (ParallelGIVerifierMessage, bool) {
+        self.handle_with(msg, None)
+    }
+}
+
+impl PublicCoinVerifier for ParallelGIVerifier<'_> {
+    type Challenge = Vec<bool>;
+
+    fn challenge_space(&self) -> Option<usize> {
+        if self.r == 0 { Some(self.k) } else { None }
+    }
+
+    fn handle_with(&mut self, msg: &ParallelGIProverMessage, challenge: Option<Vec<bool>>) -> (ParallelGIVerifierMessage, bool) {
+        self.r += 1;
+        match self.r {
+            1 => {
+                if let ParallelGIProverMessage::Commitments(commitments) = msg {
+                    self.commitments = commitments.clone();
+                    self.challenge = challenge.unwrap_or_else(|| Vec::<bool>::sample(&self.k, &mut checked_thread_rng()));
+                    (ParallelGIVerifierMessage { challenge: self.challenge.clone() }, false)
+                } else {
+                    panic!("Prover did not send valid commitments on round 1!")
+                }
+            },
+            _ => {
+                if let ParallelGIProverMessage::Openings(openings, isomorphisms) = msg {
+                    let n = self.instance.g0.n;
+                    // Each isomorphism must take the committed graph to the challenged graph, i.e. the committed
+                    // graph must be the challenged graph under the inverse isomorphism
+                    let accept = self.commitments.len() == self.k && openings.len() == self.k && isomorphisms.len() == self.k
+                        && (0..self.k).all(|i| {
+                            let target = if self.challenge[i] {&self.instance.g1} else {&self.instance.g0};
+                            isomorphisms[i].n() == n && self.commitments[i].verify(&openings[i])
+                                && openings[i].value == target.permute(&isomorphisms[i].invert()).encode()
+                        });
+                    (ParallelGIVerifierMessage { challenge: self.challenge.clone() }, accept)
+                } else {
+                    panic!("Prover did not send valid openings on round 2!")
+                }
+            }
+        }
+    }
+}
+
+impl TranscriptMessage for ParallelGIProverMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        match self {
+            ParallelGIProverMessage::Commitments(commitments) => {
+                commitments.iter().for_each(|x| transcript.append_message(b"commitment", &x.digest));
+            },
+            ParallelGIProverMessage::Openings(openings, isomorphisms) => {
+                openings.iter().for_each(|x| {
+                    transcript.append_message(b"opening", &x.value);
+                    transcript.append_message(b"randomness", &*x.randomness);
+                });
+                isomorphisms.iter().for_each(|x| transcript.append_message(b"isomorphism", &x.encode()));
+            },
+            ParallelGIProverMessage::Done => transcript.append_message(b"done", &[]),
+        }
+    }
+}
+
+impl TranscriptMessage for ParallelGIVerifierMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_challenge(b"challenge", &self.challenge);
+    }
+}
+
+#[test]
+fn test_parallel_gi_interactive_proof() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let mut prover = ParallelGIProver { r: 0, k: 40, random_perms: Vec::new(), openings: Vec::new(), instance: &instance };
+    let mut verifier = ParallelGIVerifier { r: 0, k: 40, challenge: Vec::new(), commitments: Vec::new(), instance: &instance };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+}
+
+#[test]
+fn test_parallel_gi_malicious_interactive_proof() {
+    // A prover for nonisomorphic graphs must guess all k bits in advance, succeeding with probability 2^-k
+    let n = 1000;
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    struct GuessingProver<'a> { inner: ParallelGIProver<'a>, isomorphisms: Vec<Permutation> }
+    impl Prover for GuessingProver<'_> {
+        type ProverMessage = ParallelGIProverMessage;
+        type VerifierMessage = ParallelGIVerifierMessage;
+
+        fn handle(&mut self, msg: &ParallelGIVerifierMessage) -> (ParallelGIProverMessage, bool) {
+            let inner = &mut self.inner;
+            inner.r += 1;
+            match inner.r {
+                // Commit to permutations of randomly guessed graphs, remembering the isomorphisms back to them
+                1 => {
+                    let mut commitments = Vec::new();
+                    for _ in 0..inner.k {
+                        let guess = if thread_rng().gen_bool(0.5) {&inner.instance.g1} else {&inner.instance.g0};
+                        let isomorphism = Permutation::random(guess.n, &mut thread_rng());
+                        let (commitment, opening) = commit(&guess.permute(&isomorphism).encode());
+                        commitments.push(commitment);
+                        inner.openings.push(opening);
+                        self.isomorphisms.push(isomorphism.invert());
+                    }
+                    (ParallelGIProverMessage::Commitments(commitments), false)
+                },
+                2 => (ParallelGIProverMessage::Openings(inner.openings.clone(), self.isomorphisms.clone()), false),
+                _ => inner.handle(msg),
+            }
+        }
+    }
+    let successes = run_trials(n, || {
+        let inner = ParallelGIProver { r: 0, k: 4, random_perms: Vec::new(), openings: Vec::new(), instance: &instance };
+        let mut prover = GuessingProver { inner, isomorphisms: Vec::new() };
+        let mut verifier = ParallelGIVerifier { r: 0, k: 4, challenge: Vec::new(), commitments: Vec::new(), instance: &instance };
+        run_interactive_proof(&mut prover, &mut verifier)
+    }).accepted;
+
+    narrate!("Malicious parallel GI prover succeeded {} out of {} times.", successes, n);
 
-    assert!(successes != N as usize);
+    // With 4 repetitions, the prover should succeed about 1/16 of the time
+    assert!(successes > 20 && successes < 120);
 }
 
 // ************ Zero-knowledge graph non-isomorphism proof implementation ************
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GNIProverMessage {
     // Prover guess
-    b: bool,
+    pub b: bool,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GNIVerifierMessage {
     // Random permutation of either g0 or g1
-    gb: Graph,
+    pub gb: Graph,
 }
 
 pub struct GNIProver<'a> {
@@ -207,12 +700,24 @@ impl Prover for GNIProver<'_> {
             // If the prover already sent a guess, they send a message to terminate the interaction
             (GNIProverMessage { b: false }, true)
         } else {
-            println!("Prover received permutation: {:?}.", &msg.gb);
+            narrate!("Prover received permutation: {:?}.", &msg.gb);
             // The prover sends b = 1 if Gb is in the same equivalence class as G1
             self.sent_guess = true;
-            (GNIProverMessage { b: are_isomorphic(&msg.gb, &self.instance.g1) }, false)
+            match are_isomorphic(&msg.gb, &self.instance.g1) {
+                Some(b) => (GNIProverMessage { b }, false),
+                // A prover that can't tell ends the interaction without guessing, which is never accepted
+                None => {
+                    narrate!("Prover could not decide isomorphism within its budget, aborting.");
+                    (GNIProverMessage { b: false }, true)
+                },
+            }
         }
     }
+
+    fn validate(&self, msg: &GNIVerifierMessage) -> Result<(), String> {
+        // Once the prover has guessed, the verifier's message is a placeholder
+        if self.sent_guess { Ok(()) } else { check_vertex_count(&msg.gb, self.instance.g0.n) }
+    }
 }
 
 // A malicious prover can do no better than randomly guessing bit b
@@ -232,10 +737,10 @@ impl Prover for GNIProverMalicious {
             // If the prover already sent a guess, they send a message to terminate the interaction
             (GNIProverMessage { b: false }, true)
         } else {
-            println!("Prover received permutation: {:?}.", &msg.gb);
+            narrate!("Prover received permutation: {:?}.", &msg.gb);
             // The malicious prover sends a random bit b that is 1 with probability p
             self.sent_guess = true;
-            (GNIProverMessage { b: rand::thread_rng().gen_bool(self.p) }, false)
+            (GNIProverMessage { b: checked_thread_rng().gen_bool(self.p) }, false)
         }
     }
 }
@@ -251,18 +756,22 @@ impl Verifier for GNIVerifier<'_> {
     type VerifierMessage = GNIVerifierMessage;
 
     fn init(&mut self) -> GNIVerifierMessage {
-        println!("Initializing GNI instance with the following graphs:\nG0: {:?}\nG1: {:?}.", &self.instance.g0, &self.instance.g1);
+        narrate!("Initializing GNI instance with the following graphs:\nG0: {:?}\nG1: {:?}.", &self.instance.g0, &self.instance.g1);
         // The verifier randomly chooses a random graph to randomly permute and send to the prover
-        self.b = rand::thread_rng().gen_bool(0.5);
-        println!("Verifier chose graph {}.", if self.b {1} else {0});
+        self.b = checked_thread_rng().gen_bool(0.5);
+        narrate!("Verifier chose graph {}.", if self.b {1} else {0});
         GNIVerifierMessage{gb: if self.b {self.instance.g1.random_permutation()} else {self.instance.g0.random_permutation()}}
     }
 
     fn handle(&mut self, msg: &GNIProverMessage) -> (GNIVerifierMessage, bool) {
-        println!("Verifier received bit {}.", if msg.b {1} else {0});
+        narrate!("Verifier received bit {}.", if msg.b {1} else {0});
         // The verifier accepts the proof if the prover correctly guesses bit b
         (GNIVerifierMessage{gb: Graph::new(0, vec![])}, msg.b == self.b)
     }
+
+    fn diagnose(&self, msg: &GNIProverMessage) -> Option<Diagnosis> {
+        Some(Diagnosis::new("guess != b", Some(format!("prover guessed {}, verifier chose {}", msg.b as u8, self.b as u8))))
+    }
 }
 
 #[test]
@@ -284,7 +793,7 @@ fn test_gni_interactive_proof() {
 fn test_gni_malicious_interactive_proof() {
     // Malicious prover should have probability of 1/2^N of successfully convincing
     // verifier after N rounds of the interactive proof.
-    let N = 1000;
+    let n = 1000;
     let instance = GraphPair {
         g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
         g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
@@ -292,145 +801,1603 @@ fn test_gni_malicious_interactive_proof() {
 
     // There should be a negligible chance of the prover successfully convincing the verifier
     // in all 1000 rounds.
-    let successes = (0..N).collect::<Vec<i32>>().par_iter().filter(|_| {
+    let successes = run_trials(n, || {
         let mut prover = GNIProverMalicious{
             sent_guess: false,
             p: 0.5,
         };
         let mut verifier = GNIVerifier{b:false, instance: &instance};
         run_interactive_proof(&mut prover, &mut verifier)
-    }).count();
+    }).accepted;
 
-    println!("Malicious GI prover succeeded {} out of {} times.", successes, N);
+    narrate!("Malicious GI prover succeeded {} out of {} times.", successes, n);
 
-    assert!(successes != N as usize);
+    assert!(successes != n);
 }
 
-// ************ Graph and additional function implementations ************
+// ************ Zero-knowledge graph non-isomorphism proof implementation (work-balanced prover) ************
+
+// The honest GNI prover answers by searching for an isomorphism from the verifier's graph H to G1, which
+// ends at once when refinement rules H out and takes longer when there is an isomorphism to find, so how
+// long it takes to answer tells anyone timing the messages which graph the verifier chose. The timing
+// experiment in experiments::measure_gni_timing shows the difference. CanonicalGNIProver computes the
+// canonical forms of G0 and G1 once, up front, and answers by canonicalizing H and comparing its adjacency
+// matrix with both forms word by word, so the comparison itself doesn't branch on the answer.
+//
+// The prover is not constant time. Only the final comparison is, while canonicalizing H is a backtracking
+// search whose running time and memory accesses depend on H, so the prover balances that work instead.
+//
+// Canonicalizing H explores the same search tree as canonicalizing Gb did, which on its own would leak b
+// just the same whenever the two trees differ. After answering, the prover canonicalizes the graph H didn't
+// come from as well, so every answer costs the work of canonicalizing both graphs, whichever the verifier
+// chose. Each graph is canonicalized through a fresh random relabeling, since a graph just built runs
+// slower than one the prover has searched before. This balances the work rather than the time, and what
+// is left, such as which graph is still in cache, shows up over thousands of trials on larger graphs, so
+// padding responses with experiments::PaddedProver is still the way to hide timing entirely.
 
 #[derive(Clone)]
-pub struct Graph {
-    // Number of vertices
-    n: u32,
-    // List of directed edges
-    edges: HashSet<(u32, u32)>,
-    // Adjacency list representation
-    adj: Vec<HashSet<u32>>,
+pub struct CanonicalGNIProver<'a> {
+    // Keep track of whether the interaction is done
+    pub sent_guess: bool,
+    pub instance: &'a GraphPair,
+    // Adjacency matrices of the canonical forms of g0 and g1
+    forms: [Vec<u64>; 2],
+    // Search nodes needed to canonicalize either graph, which is as many as a relabeling of either needs
+    budget: SearchBudget,
 }
 
-impl Graph {
-    pub fn new(n: u32, edges: Vec<(u32, u32)>) -> Graph {
-        let mut graph = Graph {
-            n,
-            edges: edges.clone().into_iter().collect(),
-            adj: vec![HashSet::new(); edges.len()],
-        };
-        // The constructor builds the adjacency list from the provided list of directed edges
-        for edge in edges.iter() {
-            if cmp::max(edge.0, edge.1) >= n {
-                panic!("Vertex labels must be in the range 0 to N-1. Found vertex {:?}.", cmp::max(edge.0, edge.1));
-            }
-            graph.adj[edge.0 as usize].insert(edge.1);
+impl<'a> CanonicalGNIProver<'a> {
+    // Canonicalizes both graphs, failing if either search runs out of budget or the graphs are isomorphic
+    pub fn new(instance: &'a GraphPair, budget: SearchBudget) -> Result<CanonicalGNIProver<'a>, String> {
+        if instance.g0.n != instance.g1.n {
+            return Err("Graphs have different numbers of vertices".to_string());
         }
-        graph
-    }
-
-    // Apply given isomorphism to self and return resulting graph
-    fn permute(&self, isomorphism: &Vec<u32>) -> Graph {
-        let mut edges: Vec<(u32, u32)> = Vec::new();
-        for edge in self.edges.iter() {
-            let (a, b) = (edge.0 as usize, edge.1 as usize);
-            edges.push((isomorphism[a], isomorphism[b]));
+        let canonicalize = |g: &Graph| isomorphism::canonical_labeling(g, budget).ok_or_else(|| format!("Canonical labeling exhausted its budget of {:?}", budget));
+        let (c0, c1) = (canonicalize(&instance.g0)?, canonicalize(&instance.g1)?);
+        let forms = [BitMatrix::from_graph(&instance.g0.permute(&c0.labeling)).bits, BitMatrix::from_graph(&instance.g1.permute(&c1.labeling)).bits];
+        if difference(&forms[0], &forms[1]) == 0 {
+            return Err("Graphs are isomorphic".to_string());
         }
-        Graph::new(self.n, edges)
-    }
-
-    // Apply random isomorphism to self and return resulting graph
-    fn random_permutation(&self) -> Graph {
-        let mut isomorphism: Vec<u32> = (0..self.n).collect();
-        isomorphism.shuffle(&mut thread_rng());
-        self.permute(&isomorphism)
+        Ok(CanonicalGNIProver { sent_guess: false, instance, forms, budget: SearchBudget::nodes(c0.nodes.max(c1.nodes)) })
     }
 
-    // Finds isomorphism that takes self to other
-    fn find_isomorphism_to(&self, other: &Graph) -> Option<Vec<u32>> {
-        if self.n != other.n {
-            return None;
-        }
-        (0..self.n).permutations(self.n as usize).find(|x| self.permute(x) == *other)
+    // Adjacency matrix of the graph's canonical form, or None if its search outgrows both graphs' searches.
+    // The random relabeling gives the graph a fresh layout in memory, like the verifier's graph has.
+    fn form(&self, graph: &Graph) -> Option<Vec<u64>> {
+        let graph = graph.permute(&Permutation::random(graph.n, &mut checked_thread_rng()));
+        isomorphism::canonical_labeling(&graph, self.budget).map(|canonical| BitMatrix::from_graph(&graph.permute(&canonical.labeling)).bits)
     }
 }
 
-impl fmt::Debug for Graph {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut output = String::new();
-        output.push_str("Graph {{\n");
-        self.adj.iter().enumerate().for_each(|(i, x)| {
-            output.push_str(&format!("  {}: {:?}\n", i, x));
+impl Prover for CanonicalGNIProver<'_> {
+    type ProverMessage = GNIProverMessage;
+    type VerifierMessage = GNIVerifierMessage;
+
+    fn handle(&mut self, msg: &GNIVerifierMessage) -> (GNIProverMessage, bool) {
+        if self.sent_guess {
+            // If the prover already sent a guess, they send a message to terminate the interaction
+            return (GNIProverMessage { b: false }, true);
+        }
+        narrate!("Prover received permutation: {:?}.", &msg.gb);
+        self.sent_guess = true;
+        // A graph whose search outgrows both of the instance's isn't a relabeling of either, and any answer will do
+        let b = self.form(&msg.gb).is_some_and(|form| {
+            let differences = [difference(&form, &self.forms[0]), difference(&form, &self.forms[1])];
+            std::hint::black_box(differences)[1] == 0
         });
-        output.push_str("}}");
-        write!(f, "{}", output)
+        // Canonicalizing the other graph too makes the work the same whichever graph H came from
+        let other = if b { &self.instance.g0 } else { &self.instance.g1 };
+        std::hint::black_box(self.form(other));
+        (GNIProverMessage { b }, false)
     }
-}
 
-impl PartialEq for Graph
-{
-    fn eq(&self, other: &Self) -> bool {
-        self.edges == other.edges
+    fn validate(&self, msg: &GNIVerifierMessage) -> Result<(), String> {
+        if self.sent_guess { Ok(()) } else { check_vertex_count(&msg.gb, self.instance.g0.n) }
     }
 }
 
-fn are_isomorphic(a: &Graph, b: &Graph) -> bool {
-    // First checks if the graphs have an equal number of vertices and edges, then searches through all possible permutations
-    if a.n != b.n || a.edges.len() != b.edges.len(){
-        return false;
-    }
-    (0..a.n).permutations(a.n as usize).any(|x| a.permute(&x) == *b)
+#[test]
+fn test_canonical_gni_proof() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    let prover = CanonicalGNIProver::new(&instance, SearchBudget::default()).unwrap();
+    assert!((0..20).all(|_| run_interactive_proof(&mut prover.clone(), &mut GNIVerifier { b: false, instance: &instance })));
+    let isomorphic = GraphPair { g0: instance.g0.clone(), g1: instance.g0.random_permutation() };
+    assert!(CanonicalGNIProver::new(&isomorphic, SearchBudget::default()).is_err());
+    // Refinement can't tell apart the CFI graphs, which the canonical forms still do
+    let hard = GraphPair::sample_hard_instance(6);
+    let prover = CanonicalGNIProver::new(&hard, SearchBudget::default()).unwrap();
+    assert!(run_interactive_proof(&mut prover.clone(), &mut GNIVerifier { b: false, instance: &hard }));
 }
 
-fn invert_isomorphism(isomorphism: &Vec<u32>) -> Vec<u32> {
-    let mut inverted = vec![0; isomorphism.len()];
-    isomorphism.iter().enumerate().for_each(|(i, x)| {
-        inverted[*x as usize] = i as u32;
-    });
-    inverted
+// ************ Zero-knowledge graph non-isomorphism proof implementation (hardened) ************
+
+// The GNI proof above is only zero knowledge against an honest verifier. A cheating verifier can send a
+// graph H it didn't derive from G0 or G1 and learn from the prover's answer whether H is isomorphic to
+// G1, which it may not be able to compute on its own. The fix is for the verifier to first prove that it
+// knows an isomorphism from H to G0 or G1. Along with H, the verifier sends k pairs of random permutations
+// of G0 and G1, each in random order. For each pair, the prover asks either to see the permutations,
+// showing the pair is a permuted copy of (G0, G1), or to see an isomorphism from H to one member of the
+// pair. A verifier that can answer both for some pair knows an isomorphism from H to G0 or G1, so a
+// verifier that doesn't is caught with probability 1 - 2^-k, and the prover aborts without answering.
+
+// Verifier's answer to the prover's challenge for one pair
+#[derive(Clone, Serialize, Deserialize)]
+pub enum PairAnswer {
+    // Whether the pair was swapped, and the permutations taking G0 and G1 to their copies
+    Reveal(bool, Permutation, Permutation),
+    // Position within the pair of a copy of Gb, and an isomorphism from H to it
+    Link(usize, Permutation),
 }
 
-pub struct GraphPair {
-    pub g0: Graph,
-    pub g1: Graph,
+#[derive(Clone, Serialize, Deserialize)]
+pub enum HardenedGNIVerifierMessage {
+    // Random permutation H of Gb, along with pairs of permutations of G0 and G1
+    Query(Graph, Vec<(Graph, Graph)>),
+    // Answers to the prover's challenge for each pair
+    Answers(Vec<PairAnswer>),
+    // Verdict sent once the prover has guessed
+    Done,
 }
 
-#[test]
-fn test_create_graph_single_edge() {
-    let graph = Graph::new(2, vec![(0, 1)]);
-    assert_eq!(graph.n, 2);
-    assert!(graph.edges.contains(&(0, 1)));
-    assert_eq!(graph.adj[0].len(), 1);
-    assert!(graph.adj[0].contains(&1));
+#[derive(Clone, Serialize, Deserialize)]
+pub enum HardenedGNIProverMessage {
+    // Random bit for each pair, choosing whether to reveal the pair or link it to H
+    Challenge(Vec<bool>),
+    // Prover guess
+    Guess(bool),
+    // The verifier failed to prove it knows how H was formed, so the prover refuses to guess
+    Abort,
+    // Interaction complete
+    Done
 }
 
-#[test]
-fn test_create_graph_multi_edge() {
-    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
-    assert_eq!(graph.n, 4);
-    assert_eq!(graph.edges.len(), 5);
-    assert_eq!(graph.adj[0].len(), 2);
-    assert_eq!(graph.adj[1].len(), 2);
-    assert_eq!(graph.adj[2].len(), 0);
-    assert_eq!(graph.adj[3].len(), 1);
+pub struct HardenedGNIProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of pairs the verifier must answer for
+    pub k: usize,
+    // Challenge sent to the verifier
+    pub challenge: Vec<bool>,
+    // Query received from the verifier
+    pub query: Option<(Graph, Vec<(Graph, Graph)>)>,
+    pub instance: &'a GraphPair,
 }
 
-#[test]
-#[should_panic]
-fn test_create_invalid_graph() {
-    // Vertex labels must be in the range 0 to N-1, inclusive
-    Graph::new(4, vec![(0, 1), (1, 5), (1, 3), (0, 3), (3, 0)]);
+impl HardenedGNIProver<'_> {
+    // Checks the verifier's answers against the query and the challenge
+    fn check_answers(&self, answers: &[PairAnswer]) -> bool {
+        let (h, pairs) = self.query.as_ref().unwrap();
+        let (g0, g1) = (&self.instance.g0, &self.instance.g1);
+        pairs.len() == self.k && answers.len() == self.k && answers.iter().zip(pairs.iter()).zip(self.challenge.iter()).all(|((answer, pair), &c)| {
+            match (answer, c) {
+                (PairAnswer::Reveal(swapped, p0, p1), false) => {
+                    let copies = if *swapped { (&pair.1, &pair.0) } else { (&pair.0, &pair.1) };
+                    p0.n() == g0.n && p1.n() == g1.n && g0.permute(p0) == *copies.0 && g1.permute(p1) == *copies.1
+                },
+                (PairAnswer::Link(position, isomorphism), true) => {
+                    let target = match position { 0 => &pair.0, 1 => &pair.1, _ => return false };
+                    isomorphism.n() == h.n && h.permute(isomorphism) == *target
+                },
+                _ => false,
+            }
+        })
+    }
 }
 
-#[test]
-fn test_permute() {
-    let perm = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]).permute(&vec![1, 2, 3, 0]);
+impl Prover for HardenedGNIProver<'_> {
+    type ProverMessage = HardenedGNIProverMessage;
+    type VerifierMessage = HardenedGNIVerifierMessage;
+
+    fn handle(&mut self, msg: &HardenedGNIVerifierMessage) -> (HardenedGNIProverMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            // During the first round, the prover challenges the verifier to answer for each pair
+            (1, HardenedGNIVerifierMessage::Query(h, pairs)) => {
+                self.query = Some((h.clone(), pairs.clone()));
+                self.challenge = Vec::<bool>::sample(&self.k, &mut checked_thread_rng());
+                (HardenedGNIProverMessage::Challenge(self.challenge.clone()), false)
+            },
+            // During the second round, the prover only guesses b if the verifier answered every challenge
+            (2, HardenedGNIVerifierMessage::Answers(answers)) => {
+                if !self.check_answers(answers) {
+                    narrate!("Verifier failed to prove knowledge of its query, aborting.");
+                    return (HardenedGNIProverMessage::Abort, false);
+                }
+                match are_isomorphic(&self.query.as_ref().unwrap().0, &self.instance.g1) {
+                    Some(b) => (HardenedGNIProverMessage::Guess(b), false),
+                    None => {
+                        narrate!("Prover could not decide isomorphism within its budget, aborting.");
+                        (HardenedGNIProverMessage::Abort, false)
+                    },
+                }
+            },
+            (1, _) | (2, _) => panic!("Verifier did not send a valid message on round {}!", self.r),
+            // After guessing, the prover sends a message to terminate the interaction
+            _ => (HardenedGNIProverMessage::Done, true),
+        }
+    }
+
+    // Only checks the shape of the verifier's messages, since wrong answers are handled by aborting
+    fn validate(&self, msg: &HardenedGNIVerifierMessage) -> Result<(), String> {
+        let n = self.instance.g0.n;
+        match (self.r + 1, msg) {
+            (1, HardenedGNIVerifierMessage::Query(h, pairs)) => {
+                check_vertex_count(h, n)?;
+                check_count(pairs.len(), self.k, "pairs")?;
+                pairs.iter().try_for_each(|(a, b)| check_vertex_count(a, n).and(check_vertex_count(b, n)))
+            },
+            (1, _) => Err("expected a query".to_string()),
+            (2, HardenedGNIVerifierMessage::Answers(answers)) => check_count(answers.len(), self.k, "answers"),
+            (2, _) => Err("expected answers".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct HardenedGNIVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Number of pairs sent with the query
+    pub k: usize,
+    // Randomly chosen bit
+    pub b: bool,
+    // Permutation taking Gb to H
+    pub isomorphism: Permutation,
+    // Whether each pair was swapped, and the permutations taking G0 and G1 to their copies
+    pub pairs: Vec<(bool, Permutation, Permutation)>,
+    pub instance: &'a GraphPair,
+}
+
+impl Verifier for HardenedGNIVerifier<'_> {
+    type ProverMessage = HardenedGNIProverMessage;
+    type VerifierMessage = HardenedGNIVerifierMessage;
+
+    fn init(&mut self) -> HardenedGNIVerifierMessage {
+        let (g0, g1) = (&self.instance.g0, &self.instance.g1);
+        self.b = checked_thread_rng().gen_bool(0.5);
+        self.isomorphism = Permutation::random(g0.n, &mut checked_thread_rng());
+        let h = if self.b { g1 } else { g0 }.permute(&self.isomorphism);
+        self.pairs = (0..self.k).map(|_| (checked_thread_rng().gen_bool(0.5), Permutation::random(g0.n, &mut checked_thread_rng()), Permutation::random(g1.n, &mut checked_thread_rng()))).collect();
+        let pairs = self.pairs.iter().map(|(swapped, p0, p1)| {
+            let copies = (g0.permute(p0), g1.permute(p1));
+            if *swapped { (copies.1, copies.0) } else { copies }
+        }).collect();
+        HardenedGNIVerifierMessage::Query(h, pairs)
+    }
+
+    fn handle(&mut self, msg: &HardenedGNIProverMessage) -> (HardenedGNIVerifierMessage, bool) {
+        self.r += 1;
+        match (self.r, msg) {
+            (1, HardenedGNIProverMessage::Challenge(challenge)) => {
+                // To link H to a pair, compose the inverse of the permutation taking Gb to H with the one taking Gb to its copy
+                let inverse = self.isomorphism.invert();
+                let answers = self.pairs.iter().zip(challenge.iter()).map(|((swapped, p0, p1), &c)| {
+                    if c {
+                        let to_copy = if self.b { p1 } else { p0 };
+                        PairAnswer::Link((self.b ^ swapped) as usize, inverse.compose(to_copy))
+                    } else {
+                        PairAnswer::Reveal(*swapped, p0.clone(), p1.clone())
+                    }
+                }).collect();
+                (HardenedGNIVerifierMessage::Answers(answers), false)
+            },
+            // The verifier accepts the proof if the prover correctly guesses bit b
+            (2, HardenedGNIProverMessage::Guess(b)) => (HardenedGNIVerifierMessage::Done, *b == self.b),
+            (2, HardenedGNIProverMessage::Abort) => (HardenedGNIVerifierMessage::Done, false),
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+
+    fn validate(&self, msg: &HardenedGNIProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, HardenedGNIProverMessage::Challenge(challenge)) => challenge.check(&self.k),
+            (1, _) => Err("expected a challenge".to_string()),
+            (2, HardenedGNIProverMessage::Guess(_)) | (2, HardenedGNIProverMessage::Abort) => Ok(()),
+            _ => Err("expected a guess".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &HardenedGNIProverMessage) -> Option<Diagnosis> {
+        match msg {
+            HardenedGNIProverMessage::Guess(b) => Some(Diagnosis::new("guess != b", Some(format!("prover guessed {}, verifier chose {}", *b as u8, self.b as u8)))),
+            HardenedGNIProverMessage::Abort => Some(Diagnosis::new("prover aborted", Some("the prover did not accept the verifier's answers".to_string()))),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_hardened_gni_interactive_proof() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    let mut prover = HardenedGNIProver { r: 0, k: 20, challenge: Vec::new(), query: None, instance: &instance };
+    let mut verifier = HardenedGNIVerifier { r: 0, k: 20, b: false, isomorphism: Permutation::identity(0), pairs: Vec::new(), instance: &instance };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+}
+
+#[test]
+fn test_hardened_gni_cheating_verifier() {
+    // A verifier querying a graph isomorphic to neither G0 nor G1 can't answer the challenge, so the prover
+    // aborts instead of revealing whether the graph is isomorphic to G1
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    struct CheatingVerifier<'a> { inner: HardenedGNIVerifier<'a> }
+    impl Verifier for CheatingVerifier<'_> {
+        type ProverMessage = HardenedGNIProverMessage;
+        type VerifierMessage = HardenedGNIVerifierMessage;
+
+        fn init(&mut self) -> HardenedGNIVerifierMessage {
+            match self.inner.init() {
+                HardenedGNIVerifierMessage::Query(_, pairs) => HardenedGNIVerifierMessage::Query(Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]), pairs),
+                x => x,
+            }
+        }
+
+        fn handle(&mut self, msg: &HardenedGNIProverMessage) -> (HardenedGNIVerifierMessage, bool) {
+            if let HardenedGNIProverMessage::Guess(_) = msg {
+                panic!("Prover revealed information about the cheating verifier's query!");
+            }
+            self.inner.handle(msg)
+        }
+    }
+    let mut prover = HardenedGNIProver { r: 0, k: 20, challenge: Vec::new(), query: None, instance: &instance };
+    let mut verifier = CheatingVerifier { inner: HardenedGNIVerifier { r: 0, k: 20, b: false, isomorphism: Permutation::identity(0), pairs: Vec::new(), instance: &instance } };
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+}
+
+// ************ Graph and additional function implementations ************
+
+// Graphs are serialized as their vertex count and edge list, and checked like Graph::try_new when
+// deserialized so that a malformed message can't produce a graph with out-of-range edges
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "GraphData", into = "GraphData")]
+pub struct Graph {
+    // Number of vertices
+    pub(crate) n: u32,
+    // Directed edges, in the representation chosen when the graph was built
+    store: EdgeStore,
+}
+
+// How a graph stores its edges, chosen at construction. Every method gives the same results with either,
+// so the choice only trades memory against the cost of edge lookups.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GraphStorage {
+    // A hash set of edges plus a hash set of successors per vertex, with constant-time edge lookups at a
+    // cost of roughly 100 bytes per edge
+    #[default]
+    HashSets,
+    // Compressed sparse rows: the successors of every vertex in sorted order in one array, with the offset
+    // of each vertex's row in another. Takes 4 bytes per edge and per vertex, and looks up edges by binary
+    // search, which suits large sparse graphs.
+    Csr,
+}
+
+#[derive(Clone)]
+enum EdgeStore {
+    HashSets { edges: HashSet<(u32, u32)>, adj: Vec<HashSet<u32>> },
+    // Successors of v are targets[offsets[v]..offsets[v + 1]], sorted and without duplicates
+    Csr { offsets: Vec<u32>, targets: Vec<u32> },
+}
+
+// Sorted successors of v in a CSR graph, which are none for a vertex outside the graph
+fn row<'a>(offsets: &[u32], targets: &'a [u32], v: u32) -> &'a [u32] {
+    match (offsets.get(v as usize), offsets.get(v as usize + 1)) {
+        (Some(&start), Some(&end)) => &targets[start as usize..end as usize],
+        _ => &[],
+    }
+}
+
+impl Graph {
+    pub fn new(n: u32, edges: Vec<(u32, u32)>) -> Graph {
+        Graph::try_new(n, edges).unwrap_or_else(|e| panic!("{}.", e))
+    }
+
+    // Builds the graph like new, describing the problem instead of panicking if an edge is out of range or
+    // the graph is larger than MAX_VERTICES or MAX_EDGES
+    pub fn try_new(n: u32, edges: Vec<(u32, u32)>) -> Result<Graph, String> {
+        Graph::try_with_storage(n, edges, GraphStorage::default())
+    }
+
+    pub fn with_storage(n: u32, edges: Vec<(u32, u32)>, storage: GraphStorage) -> Graph {
+        Graph::try_with_storage(n, edges, storage).unwrap_or_else(|e| panic!("{}.", e))
+    }
+
+    pub fn try_with_storage(n: u32, mut edges: Vec<(u32, u32)>, storage: GraphStorage) -> Result<Graph, String> {
+        if n > MAX_VERTICES {
+            return Err(format!("Graph has {} vertices, more than the limit of {}", n, MAX_VERTICES));
+        }
+        if edges.len() > MAX_EDGES {
+            return Err(format!("Graph has {} edges, more than the limit of {}", edges.len(), MAX_EDGES));
+        }
+        if let Some(v) = edges.iter().map(|&(a, b)| cmp::max(a, b)).find(|&v| v >= n) {
+            return Err(format!("Vertex labels must be in the range 0 to N-1. Found vertex {:?}", v));
+        }
+        let store = match storage {
+            GraphStorage::HashSets => {
+                // The constructor builds the adjacency list from the provided list of directed edges
+                let mut adj = vec![HashSet::new(); n as usize];
+                for edge in edges.iter() {
+                    adj[edge.0 as usize].insert(edge.1);
+                }
+                EdgeStore::HashSets { edges: edges.into_iter().collect(), adj }
+            }
+            GraphStorage::Csr => {
+                edges.sort_unstable();
+                edges.dedup();
+                let mut offsets = vec![0u32; n as usize + 1];
+                edges.iter().for_each(|&(a, _)| offsets[a as usize + 1] += 1);
+                for v in 0..n as usize {
+                    offsets[v + 1] += offsets[v];
+                }
+                EdgeStore::Csr { offsets, targets: edges.iter().map(|&(_, b)| b).collect() }
+            }
+        };
+        Ok(Graph { n, store })
+    }
+
+    pub fn storage(&self) -> GraphStorage {
+        match self.store {
+            EdgeStore::HashSets { .. } => GraphStorage::HashSets,
+            EdgeStore::Csr { .. } => GraphStorage::Csr,
+        }
+    }
+
+    // Copy of the graph with its edges stored the given way
+    pub fn to_storage(&self, storage: GraphStorage) -> Graph {
+        Graph::with_storage(self.n, self.edges().collect(), storage)
+    }
+
+    pub fn edge_count(&self) -> usize {
+        match &self.store {
+            EdgeStore::HashSets { edges, .. } => edges.len(),
+            EdgeStore::Csr { targets, .. } => targets.len(),
+        }
+    }
+
+    pub fn has_edge(&self, a: u32, b: u32) -> bool {
+        match &self.store {
+            EdgeStore::HashSets { edges, .. } => edges.contains(&(a, b)),
+            EdgeStore::Csr { offsets, targets } => row(offsets, targets, a).binary_search(&b).is_ok(),
+        }
+    }
+
+    // Every directed edge once, in no particular order
+    pub fn edges(&self) -> Edges<'_> {
+        match &self.store {
+            EdgeStore::HashSets { edges, .. } => Edges(EdgesInner::HashSets(edges.iter())),
+            EdgeStore::Csr { offsets, targets } => Edges(EdgesInner::Csr { offsets, targets, source: 0, index: 0 }),
+        }
+    }
+
+    // Vertices with an edge from v, in no particular order. A vertex outside the graph has none.
+    pub fn successors(&self, v: u32) -> Successors<'_> {
+        match &self.store {
+            EdgeStore::HashSets { adj, .. } => match adj.get(v as usize) {
+                Some(successors) => Successors(SuccessorsInner::HashSets(successors.iter())),
+                None => Successors(SuccessorsInner::Csr([].iter())),
+            },
+            EdgeStore::Csr { offsets, targets } => Successors(SuccessorsInner::Csr(row(offsets, targets, v).iter())),
+        }
+    }
+
+    pub fn out_degree(&self, v: u32) -> usize {
+        match &self.store {
+            EdgeStore::HashSets { adj, .. } => adj.get(v as usize).map_or(0, HashSet::len),
+            EdgeStore::Csr { offsets, targets } => row(offsets, targets, v).len(),
+        }
+    }
+
+    // Estimate of the bytes the graph takes up, counting the struct and the buffers it owns, for bounding
+    // how much memory instances from untrusted sources can hold on to
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Graph>() + match &self.store {
+            EdgeStore::HashSets { edges, adj } => {
+                hash_set_footprint(edges) + adj.capacity() * mem::size_of::<HashSet<u32>>() + adj.iter().map(hash_set_footprint).sum::<usize>()
+            }
+            EdgeStore::Csr { offsets, targets } => (offsets.capacity() + targets.capacity()) * mem::size_of::<u32>(),
+        }
+    }
+
+    // Edges in sorted order, which CSR graphs store them in already
+    fn sorted_edges(&self) -> Vec<(u32, u32)> {
+        match &self.store {
+            EdgeStore::HashSets { edges, .. } => edges.iter().copied().sorted().collect(),
+            EdgeStore::Csr { .. } => self.edges().collect(),
+        }
+    }
+
+    // Canonical encoding of the graph: the vertex count followed by the edge list in sorted order, each
+    // number as 4 big-endian bytes. Equal graphs have equal encodings regardless of the order their edges
+    // were added in, and the encoding is stable across runs and platforms.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.n.to_be_bytes().to_vec();
+        self.sorted_edges().into_iter().for_each(|(a, b)| {
+            bytes.extend(a.to_be_bytes());
+            bytes.extend(b.to_be_bytes());
+        });
+        bytes
+    }
+
+    // Inverse of encode, returning None if the bytes aren't the encoding of a graph
+    pub fn decode(bytes: &[u8]) -> Option<Graph> {
+        if bytes.len() < 4 || bytes.len() % 8 != 4 {
+            return None;
+        }
+        if bytes.len() / 8 > MAX_DESERIALIZED_EDGES {
+            return None;
+        }
+        let words: Vec<u32> = bytes.chunks(4).map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]])).collect();
+        if words[0] > MAX_DESERIALIZED_VERTICES {
+            return None;
+        }
+        Graph::try_new(words[0], words[1..].chunks(2).map(|x| (x[0], x[1])).collect()).ok()
+    }
+
+    // SHA-256 digest of the canonical encoding, identifying the graph in transcripts and instance lists
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"zklib graph");
+        hasher.update(self.encode());
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        digest
+    }
+
+    // Apply given isomorphism to self and return resulting graph
+    pub fn permute(&self, isomorphism: &Permutation) -> Graph {
+        self.permute_by(isomorphism.as_slice())
+    }
+
+    // Relabels vertex v as map[v], for maps already known to be permutations of 0..n
+    fn permute_by(&self, map: &[u32]) -> Graph {
+        match &self.store {
+            EdgeStore::HashSets { edges, .. } => {
+                Graph::new(self.n, edges.iter().map(|&(a, b)| (map[a as usize], map[b as usize])).collect())
+            }
+            // Row v of the result is row map^-1(v) relabeled, so the rows can be laid out from the degrees
+            // and filled in place without sorting the whole edge list again
+            EdgeStore::Csr { offsets, targets } => {
+                let n = self.n as usize;
+                let mut permuted_offsets = vec![0u32; n + 1];
+                (0..n).for_each(|v| permuted_offsets[map[v] as usize + 1] = offsets[v + 1] - offsets[v]);
+                for v in 0..n {
+                    permuted_offsets[v + 1] += permuted_offsets[v];
+                }
+                let mut permuted_targets = vec![0u32; targets.len()];
+                for v in 0..n {
+                    let start = permuted_offsets[map[v] as usize] as usize;
+                    let row = &targets[offsets[v] as usize..offsets[v + 1] as usize];
+                    let permuted_row = &mut permuted_targets[start..start + row.len()];
+                    permuted_row.iter_mut().zip(row).for_each(|(x, &b)| *x = map[b as usize]);
+                    permuted_row.sort_unstable();
+                }
+                Graph { n: self.n, store: EdgeStore::Csr { offsets: permuted_offsets, targets: permuted_targets } }
+            }
+        }
+    }
+
+    // Apply random isomorphism to self and return resulting graph
+    fn random_permutation(&self) -> Graph {
+        self.permute_secret(&Permutation::random(self.n, &mut checked_thread_rng()))
+    }
+
+    // Finds isomorphism that takes self to other within the default budget, returning None if there is
+    // none or the budget runs out, rather than letting a prover hang on a hard instance
+    fn find_isomorphism_to(&self, other: &Graph) -> Option<Permutation> {
+        match self.find_isomorphism_to_with_budget(other, SearchBudget::default()) {
+            SearchResult::Found(isomorphism) => Some(isomorphism),
+            SearchResult::NotFound | SearchResult::Exhausted => None,
+        }
+    }
+
+    // Searches for an isomorphism that takes self to other, giving up once the budget is spent
+    pub fn find_isomorphism_to_with_budget(&self, other: &Graph, budget: SearchBudget) -> SearchResult {
+        if self.n != other.n || self.edge_count() != other.edge_count() {
+            return SearchResult::NotFound;
+        }
+        let mut search = IsomorphismSearch {
+            from: self,
+            to: other,
+            from_degrees: self.degrees(),
+            to_degrees: other.degrees(),
+            map: Vec::new(),
+            used: vec![false; other.n as usize],
+            nodes: 0,
+            budget,
+            start: Instant::now(),
+        };
+        match search.extend() {
+            Ok(true) => SearchResult::Found(Permutation::new(search.map).unwrap()),
+            Ok(false) => SearchResult::NotFound,
+            Err(()) => SearchResult::Exhausted,
+        }
+    }
+
+    // Out-degree and in-degree of every vertex
+    fn degrees(&self) -> Vec<(usize, usize)> {
+        let mut degrees: Vec<(usize, usize)> = (0..self.n).map(|v| (self.out_degree(v), 0)).collect();
+        self.edges().for_each(|(_, b)| degrees[b as usize].1 += 1);
+        degrees
+    }
+}
+
+// Limits on the work an isomorphism search may do. Each node is a tentative assignment of one vertex.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchBudget {
+    pub max_nodes: Option<u64>,
+    pub max_time: Option<Duration>,
+}
+
+impl SearchBudget {
+    pub fn unlimited() -> SearchBudget {
+        SearchBudget { max_nodes: None, max_time: None }
+    }
+
+    pub fn nodes(max_nodes: u64) -> SearchBudget {
+        SearchBudget { max_nodes: Some(max_nodes), max_time: None }
+    }
+
+    pub fn time(max_time: Duration) -> SearchBudget {
+        SearchBudget { max_nodes: None, max_time: Some(max_time) }
+    }
+}
+
+impl Default for SearchBudget {
+    // Enough for the small instances the protocols are run on, while bounding the work on adversarial ones
+    fn default() -> SearchBudget {
+        SearchBudget::nodes(10_000_000)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchResult {
+    Found(Permutation),
+    // The whole search space was explored without finding an isomorphism
+    NotFound,
+    // The budget ran out before the search finished
+    Exhausted,
+}
+
+// Backtracking search assigning the vertices of one graph to the other in order, only trying vertices of
+// the same degrees and only keeping assignments that preserve every edge and non-edge between the
+// vertices assigned so far
+struct IsomorphismSearch<'a> {
+    from: &'a Graph,
+    to: &'a Graph,
+    from_degrees: Vec<(usize, usize)>,
+    to_degrees: Vec<(usize, usize)>,
+    // Images of vertices 0..map.len()
+    map: Vec<u32>,
+    used: Vec<bool>,
+    nodes: u64,
+    budget: SearchBudget,
+    start: Instant,
+}
+
+impl IsomorphismSearch<'_> {
+    // Extends the partial assignment to a full isomorphism, returning Err if the budget runs out
+    fn extend(&mut self) -> Result<bool, ()> {
+        let v = self.map.len();
+        if v == self.from.n as usize {
+            return Ok(true);
+        }
+        for w in 0..self.to.n as usize {
+            if self.used[w] || self.from_degrees[v] != self.to_degrees[w] {
+                continue;
+            }
+            self.nodes += 1;
+            if self.budget.max_nodes.is_some_and(|x| self.nodes > x)
+                || self.budget.max_time.is_some_and(|x| self.start.elapsed() > x) {
+                return Err(());
+            }
+            if self.consistent(vertex_label(v), vertex_label(w)) {
+                self.map.push(vertex_label(w));
+                self.used[w] = true;
+                if self.extend()? {
+                    return Ok(true);
+                }
+                self.map.pop();
+                self.used[w] = false;
+            }
+        }
+        Ok(false)
+    }
+
+    // Checks that mapping v to w preserves the edges between v and every vertex assigned so far, and v itself
+    fn consistent(&self, v: u32, w: u32) -> bool {
+        let (from, to) = (self.from, self.to);
+        from.has_edge(v, v) == to.has_edge(w, w) && self.map.iter().enumerate().all(|(u, &x)| {
+            let u = vertex_label(u);
+            from.has_edge(u, v) == to.has_edge(x, w) && from.has_edge(v, u) == to.has_edge(w, x)
+        })
+    }
+}
+
+impl fmt::Debug for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut output = String::new();
+        output.push_str("Graph {{\n");
+        (0..self.n).for_each(|i| {
+            output.push_str(&format!("  {}: {:?}\n", i, self.successors(i).collect::<BTreeSet<u32>>()));
+        });
+        output.push_str("}}");
+        write!(f, "{}", output)
+    }
+}
+
+// Heap bytes of a hash set. The standard library's tables have a power of two number of buckets, at most
+// 7/8 of them full, with an element and a control byte per bucket and a group of 16 extra control bytes.
+fn hash_set_footprint<T>(set: &HashSet<T>) -> usize {
+    if set.capacity() == 0 {
+        return 0;
+    }
+    (set.capacity() * 8 / 7).next_power_of_two() * (mem::size_of::<T>() + 1) + 16
+}
+
+// Iterator over the edges of a graph, from Graph::edges
+pub struct Edges<'a>(EdgesInner<'a>);
+
+enum EdgesInner<'a> {
+    HashSets(hash_set::Iter<'a, (u32, u32)>),
+    Csr { offsets: &'a [u32], targets: &'a [u32], source: usize, index: usize },
+}
+
+impl Iterator for Edges<'_> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        match &mut self.0 {
+            EdgesInner::HashSets(iter) => iter.next().copied(),
+            EdgesInner::Csr { offsets, targets, source, index } => {
+                let target = *targets.get(*index)?;
+                while offsets[*source + 1] as usize <= *index {
+                    *source += 1;
+                }
+                *index += 1;
+                Some((vertex_label(*source), target))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match &self.0 {
+            EdgesInner::HashSets(iter) => iter.len(),
+            EdgesInner::Csr { targets, index, .. } => targets.len() - index,
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Edges<'_> {}
+
+// Iterator over the successors of a vertex, from Graph::successors
+pub struct Successors<'a>(SuccessorsInner<'a>);
+
+enum SuccessorsInner<'a> {
+    HashSets(hash_set::Iter<'a, u32>),
+    Csr(slice::Iter<'a, u32>),
+}
+
+impl Iterator for Successors<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match &mut self.0 {
+            SuccessorsInner::HashSets(iter) => iter.next().copied(),
+            SuccessorsInner::Csr(iter) => iter.next().copied(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match &self.0 {
+            SuccessorsInner::HashSets(iter) => iter.len(),
+            SuccessorsInner::Csr(iter) => iter.len(),
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Successors<'_> {}
+
+// Largest graph that can be built. Vertex labels are u32 to keep graphs and messages compact, while
+// indices into per-vertex tables are usize, converted with vertex_label so that a label never wraps.
+pub const MAX_VERTICES: u32 = 1 << 24;
+pub const MAX_EDGES: usize = 1 << 28;
+
+// Largest graph accepted from a serialized message. The adjacency list is allocated up front, so without
+// a limit a message claiming billions of vertices would exhaust memory before it could be validated.
+pub const MAX_DESERIALIZED_VERTICES: u32 = 1 << 16;
+pub const MAX_DESERIALIZED_EDGES: usize = 1 << 20;
+
+// Converts an index into a per-vertex table back into a vertex label. Graphs and permutations have at
+// most MAX_VERTICES vertices, so an index that doesn't fit is a bug rather than bad input.
+pub(crate) fn vertex_label(index: usize) -> u32 {
+    u32::try_from(index).expect("Vertex index does not fit in a vertex label")
+}
+
+#[derive(Serialize, Deserialize)]
+struct GraphData {
+    n: u32,
+    edges: Vec<(u32, u32)>,
+}
+
+impl TryFrom<GraphData> for Graph {
+    type Error = String;
+
+    fn try_from(data: GraphData) -> Result<Graph, String> {
+        if data.n > MAX_DESERIALIZED_VERTICES {
+            return Err(format!("Graph has {} vertices, more than the limit of {}", data.n, MAX_DESERIALIZED_VERTICES));
+        }
+        if data.edges.len() > MAX_DESERIALIZED_EDGES {
+            return Err(format!("Graph has {} edges, more than the limit of {}", data.edges.len(), MAX_DESERIALIZED_EDGES));
+        }
+        Graph::try_new(data.n, data.edges)
+    }
+}
+
+impl From<Graph> for GraphData {
+    fn from(graph: Graph) -> GraphData {
+        GraphData { n: graph.n, edges: graph.sorted_edges() }
+    }
+}
+
+impl PartialEq for Graph
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.store, &other.store) {
+            (EdgeStore::Csr { offsets, targets }, EdgeStore::Csr { offsets: other_offsets, targets: other_targets }) => {
+                self.n == other.n && offsets == other_offsets && targets == other_targets
+            }
+            (EdgeStore::HashSets { edges, .. }, EdgeStore::HashSets { edges: other_edges, .. }) => {
+                self.n == other.n && edges == other_edges
+            }
+            _ => self.n == other.n && self.edge_count() == other.edge_count() && self.edges().all(|(a, b)| other.has_edge(a, b)),
+        }
+    }
+}
+
+impl Eq for Graph {}
+
+// Hashes the canonical encoding, so that graphs can be deduplicated in hash sets and maps
+impl Hash for Graph {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.encode().hash(state);
+    }
+}
+
+// Settles pairs that color refinement tells apart without searching, which is most of what the GNI
+// prover is asked about, and otherwise searches like find_isomorphism_to. Returns None if the search
+// runs out of budget.
+fn are_isomorphic(a: &Graph, b: &Graph) -> Option<bool> {
+    isomorphism::decide(a, b, DecisionMode::default()).is_isomorphic()
+}
+
+fn check_vertex_count(graph: &Graph, n: u32) -> Result<(), String> {
+    check_count(graph.n as usize, n as usize, "vertices in graph")
+}
+
+pub(crate) fn check_count(found: usize, expected: usize, what: &str) -> Result<(), String> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(format!("Found {} {}, expected {}", found, what, expected))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphPair {
+    pub g0: Graph,
+    pub g1: Graph,
+}
+
+impl GraphPair {
+    // Digest identifying the instance, which depends on the order of the two graphs
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"zklib graph pair");
+        hasher.update(self.g0.digest());
+        hasher.update(self.g1.digest());
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        digest
+    }
+
+    // Proof context binding a proof about this instance to the protocol and session, which for the GI
+    // protocol agrees with statement_context
+    pub fn context(&self, domain: &str, session_id: &[u8]) -> ProofContext {
+        ProofContext::new(domain, session_id, &Statement::encode(self))
+    }
+
+    pub fn memory_footprint(&self) -> usize {
+        self.g0.memory_footprint() + self.g1.memory_footprint()
+    }
+}
+
+impl Witness for Permutation {}
+
+// The statement that g0 and g1 are isomorphic, with the isomorphism from g0 to g1 as the witness, which
+// the prover answers with instead of searching.
+impl Statement for GraphPair {
+    type Witness = Permutation;
+
+    fn protocol(&self) -> &'static str {
+        "graph isomorphism"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (g0, g1) = (self.g0.encode(), self.g1.encode());
+        let mut bytes = (g0.len() as u64).to_be_bytes().to_vec();
+        bytes.extend(g0);
+        bytes.extend(g1);
+        bytes
+    }
+
+    fn memory_footprint(&self) -> usize {
+        GraphPair::memory_footprint(self)
+    }
+
+    fn validate(&self, witness: &Permutation) -> Result<(), String> {
+        check_count(witness.n() as usize, self.g0.n as usize, "vertices in isomorphism")?;
+        if !self.g0.permutes_to(witness, &self.g1) {
+            return Err("Isomorphism does not take g0 to g1".to_string());
+        }
+        Ok(())
+    }
+
+    fn run_proof(&self, witness: &Permutation) -> bool {
+        let mut prover = GIProver::with_witness(self, witness.clone());
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: self };
+        crate::run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+impl Transparent for GraphPair {}
+
+// ************ Constant-time permutation implementation ************
+
+// Permuting a graph edge by edge reads and writes memory at addresses that depend on the permutation, so
+// a prover whose permutation must stay secret, such as the one hiding which graph its first message came
+// from, can leak it through cache timing. The constant-time variants work on the adjacency matrix instead,
+// one bit per ordered pair of vertices packed into rows of 64-bit words, and touch every word the same way
+// whatever the permutation. Only the permutation is protected: building the matrix from the edges and the
+// edges from the matrix depend on the graphs, which the verifier sees anyway. config::set_constant_time
+// switches the provers' random permutations and witness checks over to these variants, though not the
+// isomorphism search of the honest GI prover, which is inherently data dependent.
+//
+// The kernels below work on whole words. Row operations are plain loops over pairs of words, which the
+// compiler turns into vector instructions, and transposes move 64 by 64 blocks of bits with shifts and
+// masks rather than one bit at a time.
+
+struct BitMatrix {
+    n: usize,
+    // Words per row
+    words: usize,
+    bits: Vec<u64>,
+}
+
+// All ones if a equals b and zero otherwise, computed without branching
+fn equality_mask(a: u32, b: u32) -> u64 {
+    let x = (a ^ b) as u64;
+    ((x | x.wrapping_neg()) >> 63).wrapping_sub(1)
+}
+
+impl BitMatrix {
+    fn zero(n: usize) -> BitMatrix {
+        let words = n.div_ceil(64);
+        BitMatrix { n, words, bits: vec![0; n * words] }
+    }
+
+    fn from_graph(graph: &Graph) -> BitMatrix {
+        let mut matrix = BitMatrix::zero(graph.n as usize);
+        for (a, b) in graph.edges() {
+            matrix.bits[a as usize * matrix.words + b as usize / 64] |= 1 << (b % 64);
+        }
+        matrix
+    }
+
+    // Reads the set bits of every word by counting trailing zeros, which depends only on the graph
+    fn to_graph(&self) -> Graph {
+        let mut edges = Vec::new();
+        for (a, row) in self.bits.chunks(self.words.max(1)).take(self.n).enumerate() {
+            for (w, &word) in row.iter().enumerate() {
+                let mut word = word;
+                while word != 0 {
+                    edges.push((vertex_label(a), vertex_label(w * 64 + word.trailing_zeros() as usize)));
+                    word &= word - 1;
+                }
+            }
+        }
+        Graph::new(vertex_label(self.n), edges)
+    }
+
+    // Moves row i to row map[i], reading every row for every row written
+    fn permute_rows(&self, map: &[u32]) -> BitMatrix {
+        let mut permuted = BitMatrix::zero(self.n);
+        for (u, row) in permuted.bits.chunks_mut(self.words.max(1)).enumerate() {
+            for (i, &target) in map.iter().enumerate() {
+                or_masked(row, &self.bits[i * self.words..(i + 1) * self.words], equality_mask(target, vertex_label(u)));
+            }
+        }
+        permuted
+    }
+
+    // Transposes one 64 by 64 block at a time, padding the last block row with zero rows
+    fn transpose(&self) -> BitMatrix {
+        let mut transposed = BitMatrix::zero(self.n);
+        let mut block = [0u64; 64];
+        for i in 0..self.words {
+            for j in 0..self.words {
+                for (k, x) in block.iter_mut().enumerate() {
+                    let row = i * 64 + k;
+                    *x = if row < self.n { self.bits[row * self.words + j] } else { 0 };
+                }
+                transpose_block(&mut block);
+                for (k, &x) in block.iter().enumerate().take(self.n - j * 64) {
+                    transposed.bits[(j * 64 + k) * self.words + i] = x;
+                }
+            }
+        }
+        transposed
+    }
+}
+
+// Sets target to target | (source & mask), word by word
+fn or_masked(target: &mut [u64], source: &[u64], mask: u64) {
+    target.iter_mut().zip(source).for_each(|(x, y)| *x |= y & mask);
+}
+
+// OR of the XORs of corresponding words, which is zero exactly when the slices are equal
+fn difference(a: &[u64], b: &[u64]) -> u64 {
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y))
+}
+
+// Transposes a 64 by 64 block of bits in place, where bit j of word i is entry (i, j), by swapping the
+// off-diagonal quarters of every 2w by 2w sub-block for w = 32, 16, ..., 1
+fn transpose_block(block: &mut [u64; 64]) {
+    let mut width = 32;
+    let mut mask: u64 = 0x0000_0000_ffff_ffff;
+    while width != 0 {
+        for start in (0..64).step_by(2 * width) {
+            for i in start..start + width {
+                let swapped = ((block[i] >> width) ^ block[i + width]) & mask;
+                block[i] ^= swapped << width;
+                block[i + width] ^= swapped;
+            }
+        }
+        width >>= 1;
+        mask ^= mask << width;
+    }
+}
+
+impl Graph {
+    // Computes P M P^T for the adjacency matrix M and permutation matrix P, by permuting the rows of M and
+    // then the rows of the transpose
+    fn permuted_matrix(&self, isomorphism: &Permutation) -> BitMatrix {
+        let map = isomorphism.as_slice();
+        BitMatrix::from_graph(self).permute_rows(map).transpose().permute_rows(map).transpose()
+    }
+
+    // Like permute, but with memory accesses that don't depend on the permutation. Takes O(n^3 / 64) word
+    // operations rather than O(m).
+    pub fn permute_constant_time(&self, isomorphism: &Permutation) -> Graph {
+        assert_eq!(isomorphism.n(), self.n, "Cannot permute a graph by a permutation of a different size");
+        self.permuted_matrix(isomorphism).to_graph()
+    }
+
+    // Whether the permutation takes self to other, comparing every word of the adjacency matrices
+    pub fn permutes_to_constant_time(&self, isomorphism: &Permutation, other: &Graph) -> bool {
+        if isomorphism.n() != self.n || self.n != other.n {
+            return false;
+        }
+        let difference = difference(&self.permuted_matrix(isomorphism).bits, &BitMatrix::from_graph(other).bits);
+        std::hint::black_box(difference) == 0
+    }
+
+    // Applies a permutation that must stay secret, in constant time if config::constant_time is set
+    pub fn permute_secret(&self, isomorphism: &Permutation) -> Graph {
+        if config::constant_time() {
+            self.permute_constant_time(isomorphism)
+        } else {
+            self.permute(isomorphism)
+        }
+    }
+
+    // Whether a secret permutation takes self to other, in constant time if config::constant_time is set
+    pub fn permutes_to(&self, isomorphism: &Permutation, other: &Graph) -> bool {
+        if config::constant_time() {
+            self.permutes_to_constant_time(isomorphism, other)
+        } else {
+            isomorphism.n() == self.n && self.permute(isomorphism) == *other
+        }
+    }
+}
+
+// ************ Graph difference implementation ************
+
+// Tools for comparing graphs that should be equal or isomorphic but aren't: the edges on which two
+// labeled graphs differ, and an estimate of how many edge edits separate two graphs up to relabeling.
+// Diagnostics use them to tell a wrong isomorphism apart from a wrong graph, and they help construct
+// almost isomorphic instances for negative tests.
+
+// Difference between a graph and the graph it was expected to equal, shown when a verifier rejects
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphDiff {
+    // Edges in the graph but not the expected graph
+    pub extra: Vec<(u32, u32)>,
+    // Edges in the expected graph but not the graph
+    pub missing: Vec<(u32, u32)>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.extra.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let edges = |x: &[(u32, u32)]| x.iter().map(|(a, b)| format!("{}->{}", a, b)).join(", ");
+        write!(f, "extra edges [{}], missing edges [{}]", edges(&self.extra), edges(&self.missing))
+    }
+}
+
+// Number of passes over all pairs of vertices the edit distance search makes without finding a better
+// relabeling before it gives up
+const EDIT_DISTANCE_STALLED_PASSES: usize = 20;
+
+impl Graph {
+    // Edges that would have to be removed from and added to self to obtain expected
+    pub fn diff(&self, expected: &Graph) -> GraphDiff {
+        GraphDiff {
+            extra: self.edges().filter(|&(a, b)| !expected.has_edge(a, b)).sorted().collect(),
+            missing: expected.edges().filter(|&(a, b)| !self.has_edge(a, b)).sorted().collect(),
+        }
+    }
+
+    // Graph on the larger of the two vertex sets whose edges are in exactly one of self and other
+    pub fn symmetric_difference(&self, other: &Graph) -> Graph {
+        let edges = self.edges().filter(|&(a, b)| !other.has_edge(a, b))
+            .chain(other.edges().filter(|&(a, b)| !self.has_edge(a, b)));
+        Graph::with_storage(cmp::max(self.n, other.n), edges.collect(), self.storage())
+    }
+
+    // Upper bound on the number of edges that must be added or removed to make self isomorphic to other,
+    // padding the smaller graph with isolated vertices. An isomorphism found by a short search gives
+    // distance zero. Otherwise a local search starts by matching vertices in order of degree, then swaps
+    // the images of pairs of vertices whenever that shrinks the symmetric difference, and at random when
+    // it leaves it unchanged so that the search can cross plateaus.
+    pub fn approximate_edit_distance(&self, other: &Graph) -> usize {
+        let n = cmp::max(self.n, other.n);
+        let (a, b) = (self.padded(n), other.padded(n));
+        if let SearchResult::Found(_) = a.find_isomorphism_to_with_budget(&b, SearchBudget::nodes(10_000)) {
+            return 0;
+        }
+        // Every relabeling leaves at least the difference in edge counts
+        let lower_bound = a.edge_count().abs_diff(b.edge_count());
+        let by_degree = |g: &Graph| {
+            let degrees = g.degrees();
+            (0..n).sorted_by_key(|&v| (degrees[v as usize].0 + degrees[v as usize].1, v)).collect::<Vec<u32>>()
+        };
+        let mut map = vec![0; n as usize];
+        by_degree(&a).into_iter().zip(by_degree(&b)).for_each(|(x, y)| map[x as usize] = y);
+
+        // Edges of a incident to each vertex, so a swap only rechecks the edges it moves
+        let mut incident: Vec<Vec<(u32, u32)>> = vec![Vec::new(); n as usize];
+        a.edges().for_each(|(x, y)| {
+            incident[x as usize].push((x, y));
+            if x != y {
+                incident[y as usize].push((x, y));
+            }
+        });
+        let kept = |map: &[u32], edges: &mut dyn Iterator<Item = (u32, u32)>| {
+            edges.filter(|&(x, y)| b.has_edge(map[x as usize], map[y as usize])).count()
+        };
+        let mut common = kept(&map, &mut a.edges());
+        let mut best = common;
+        let distance = |common: usize| a.edge_count() + b.edge_count() - 2 * common;
+        let mut stalled = 0;
+        while stalled < EDIT_DISTANCE_STALLED_PASSES && distance(best) > lower_bound {
+            stalled += 1;
+            for (i, j) in (0..n as usize).tuple_combinations() {
+                let moved: Vec<(u32, u32)> = incident[i].iter().chain(incident[j].iter().filter(|&&(x, y)| x as usize != i && y as usize != i)).copied().collect();
+                let before = kept(&map, &mut moved.iter().copied());
+                map.swap(i, j);
+                let after = kept(&map, &mut moved.iter().copied());
+                if after > before || (after == before && checked_thread_rng().gen_bool(0.5)) {
+                    common = common + after - before;
+                    if common > best {
+                        best = common;
+                        stalled = 0;
+                    }
+                } else {
+                    map.swap(i, j);
+                }
+            }
+        }
+        distance(best)
+    }
+
+    // Copy of the graph with isolated vertices added to make n vertices
+    fn padded(&self, n: u32) -> Graph {
+        Graph::with_storage(n, self.edges().collect(), self.storage())
+    }
+}
+
+// ************ Color refinement implementation ************
+
+// Color refinement, also called the 1-dimensional Weisfeiler-Leman algorithm, starts with every vertex
+// the same color and repeatedly recolors each vertex by its color together with the multisets of colors
+// of its out-neighbors and in-neighbors, until the number of colors stops growing. Isomorphic graphs
+// always end with the same number of vertices of each color, so different counts prove two graphs
+// non-isomorphic. Equal counts prove nothing: regular graphs of the same size, for instance, are never
+// told apart.
+
+impl Graph {
+    // Stable coloring of the vertices, numbered so that colors can be compared only within this graph
+    pub fn color_refinement(&self) -> Vec<u32> {
+        refine_colors(&[self]).remove(0)
+    }
+
+    // Whether color refinement fails to tell the graphs apart. False proves they aren't isomorphic.
+    pub fn refinement_equivalent(&self, other: &Graph) -> bool {
+        if self.n != other.n || self.edge_count() != other.edge_count() {
+            return false;
+        }
+        let colors = refine_colors(&[self, other]);
+        colors[0].iter().sorted().eq(colors[1].iter().sorted())
+    }
+}
+
+// Color of a vertex along with the sorted colors of its out-neighbors and in-neighbors
+type Signature = (u32, Vec<u32>, Vec<u32>);
+
+// Refines the disjoint union of the graphs, so that colors are comparable across them
+fn refine_colors(graphs: &[&Graph]) -> Vec<Vec<u32>> {
+    refine_colors_from(graphs, graphs.iter().map(|g| vec![0; g.n as usize]).collect())
+}
+
+// Refines a starting coloring of the graphs. Colors are renumbered from 0 in the order of the starting
+// colors, so a vertex colored below another at the start stays below it.
+pub(crate) fn refine_colors_from(graphs: &[&Graph], mut colors: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+    let mut count = colors.iter().flatten().unique().count();
+    loop {
+        let signatures: Vec<Vec<Signature>> = graphs.iter().zip(colors.iter()).map(|(g, colors)| {
+            let mut ins: Vec<Vec<u32>> = vec![Vec::new(); g.n as usize];
+            g.edges().for_each(|(a, b)| ins[b as usize].push(colors[a as usize]));
+            (0..g.n as usize).map(|v| {
+                let outs = g.successors(vertex_label(v)).map(|w| colors[w as usize]).sorted().collect();
+                (colors[v], outs, ins[v].iter().copied().sorted().collect())
+            }).collect()
+        }).collect();
+        // Renumbers the signatures in sorted order, which keeps the coloring independent of vertex labels
+        let palette: Vec<&Signature> = signatures.iter().flatten().sorted().dedup().collect();
+        colors = signatures.iter().map(|x| x.iter().map(|s| vertex_label(palette.binary_search(&s).unwrap())).collect()).collect();
+        if palette.len() == count {
+            return colors;
+        }
+        count = palette.len();
+    }
+}
+
+// ************ Refinement cache implementation ************
+
+// Telling whether a graph is refinement-equivalent to another means refining the two together, since
+// colors are numbered by sorting the signatures that occur. Naming each color by a hash of its signature
+// instead makes colors mean the same thing in every graph, so a verifier running many repetitions of a
+// GI proof on one instance can refine g0 and g1 once and refine only the prover's graph each round,
+// stopping at the first round whose colors differ. A hash collision can only make two graphs look
+// equivalent, never tell isomorphic graphs apart, so the check never rejects an honest prover.
+
+// Sorted colors of every round of refinement of g0 and g1, up to the first round that splits no color class
+#[derive(Clone, Debug)]
+pub struct RefinementCache {
+    g0: Vec<Vec<u64>>,
+    g1: Vec<Vec<u64>>,
+}
+
+impl RefinementCache {
+    pub fn new(instance: &GraphPair) -> RefinementCache {
+        RefinementCache { g0: HashedRefinement::new(&instance.g0).collect(), g1: HashedRefinement::new(&instance.g1).collect() }
+    }
+
+    // Whether color refinement fails to tell the graph apart from g1 if b is set, or g0 otherwise, like
+    // Graph::refinement_equivalent. False proves the graph isn't a relabeling of it.
+    pub fn equivalent(&self, graph: &Graph, b: bool) -> bool {
+        let expected = if b { &self.g1 } else { &self.g0 };
+        let mut rounds = HashedRefinement::new(graph);
+        expected.iter().all(|colors| rounds.next().as_ref() == Some(colors))
+    }
+}
+
+// Rounds of color refinement of a graph with hashed colors, yielding the sorted colors of each round
+struct HashedRefinement<'a> {
+    graph: &'a Graph,
+    colors: Option<Vec<u64>>,
+    count: usize,
+    stable: bool,
+}
+
+impl HashedRefinement<'_> {
+    fn new(graph: &Graph) -> HashedRefinement<'_> {
+        HashedRefinement { graph, colors: None, count: 0, stable: false }
+    }
+}
+
+impl Iterator for HashedRefinement<'_> {
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Vec<u64>> {
+        if self.stable {
+            return None;
+        }
+        let g = self.graph;
+        let colors = match &self.colors {
+            None => vec![0; g.n as usize],
+            Some(colors) => {
+                let mut ins: Vec<Vec<u64>> = vec![Vec::new(); g.n as usize];
+                g.edges().for_each(|(a, b)| ins[b as usize].push(colors[a as usize]));
+                ins.into_iter().enumerate().map(|(v, ins)| {
+                    let outs: Vec<u64> = g.successors(vertex_label(v)).map(|w| colors[w as usize]).sorted().collect();
+                    // DefaultHasher::new always uses the same keys, so the names agree across graphs
+                    let mut hasher = DefaultHasher::new();
+                    (colors[v], outs, ins.into_iter().sorted().collect::<Vec<u64>>()).hash(&mut hasher);
+                    hasher.finish()
+                }).collect()
+            }
+        };
+        let count = colors.iter().unique().count();
+        self.stable = self.colors.is_some() && count == self.count;
+        self.count = count;
+        let sorted = colors.iter().copied().sorted().collect();
+        self.colors = Some(colors);
+        Some(sorted)
+    }
+}
+
+// GI verifier that also refuses a first message that color refinement tells apart from g0, checking it
+// against a cache shared by every repetition instead of refining g0 again
+pub struct RefinedGIVerifier<'a> {
+    pub inner: GIVerifier<'a>,
+    pub refinement: &'a RefinementCache,
+}
+
+impl Verifier for RefinedGIVerifier<'_> {
+    type ProverMessage = GIProverMessage;
+    type VerifierMessage = GIVerifierMessage;
+
+    fn init(&mut self) -> GIVerifierMessage {
+        self.inner.init()
+    }
+
+    fn validate(&self, msg: &GIProverMessage) -> Result<(), String> {
+        self.inner.validate(msg)?;
+        match (self.inner.r + 1, msg) {
+            (1, GIProverMessage::Graph(graph)) if !self.refinement.equivalent(graph, false) => {
+                Err("graph is not a relabeling of g0, color refinement tells them apart".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn diagnose(&self, msg: &GIProverMessage) -> Option<Diagnosis> {
+        self.inner.diagnose(msg)
+    }
+
+    fn handle(&mut self, msg: &GIProverMessage) -> (GIVerifierMessage, bool) {
+        self.inner.handle(msg)
+    }
+}
+
+impl PublicCoinVerifier for RefinedGIVerifier<'_> {
+    type Challenge = bool;
+
+    fn challenge_space(&self) -> Option<()> {
+        self.inner.challenge_space()
+    }
+
+    fn handle_with(&mut self, msg: &GIProverMessage, challenge: Option<bool>) -> (GIVerifierMessage, bool) {
+        self.inner.handle_with(msg, challenge)
+    }
+}
+
+// ************ Hard instance implementation ************
+
+// Small random graphs make GI look easy: degrees and color refinement quickly pin down every vertex, so
+// the isomorphism search barely backtracks. The Cai-Furer-Immerman (CFI) construction instead replaces
+// every vertex of a base graph with a gadget, and every edge with a pair of connections between gadgets
+// that are either straight or twisted. Graphs built from the same base graph are isomorphic exactly when
+// they have the same parity of twisted edges, yet look the same locally, so color refinement can't tell
+// a pair of opposite parity apart. The base graph here is the prism on n vertices (two n/2-cycles joined
+// by a perfect matching), which is 3-regular, and each of its vertices becomes 10 vertices.
+
+impl GraphPair {
+    // Non-isomorphic pair of CFI graphs on 10n vertices, with no twisted edges and with one. Both graphs
+    // are randomly relabeled. Panics unless n is even, at least 6, and 10n is at most MAX_VERTICES.
+    pub fn sample_hard_instance(n: u32) -> GraphPair {
+        let base = prism(n);
+        let twisted = checked_thread_rng().gen_range(0..base.len());
+        GraphPair { g0: cfi_graph(n, &base, None).random_permutation(), g1: cfi_graph(n, &base, Some(twisted)).random_permutation() }
+    }
+
+    // Isomorphic pair of CFI graphs on 10n vertices, along with the isomorphism taking g0 to g1. Finding
+    // the isomorphism without it is as hard as telling the graphs of sample_hard_instance apart.
+    pub fn sample_hard_isomorphic_instance(n: u32) -> (GraphPair, Permutation) {
+        let base = prism(n);
+        let twisted = checked_thread_rng().gen_range(0..base.len());
+        let g0 = cfi_graph(n, &base, Some(twisted)).random_permutation();
+        let isomorphism = Permutation::random(g0.n, &mut checked_thread_rng());
+        (GraphPair { g1: g0.permute(&isomorphism), g0 }, isomorphism)
+    }
+}
+
+// Edges of the prism on n vertices: vertex i < n/2 lies on one cycle, and vertex i + n/2 on the other
+fn prism(n: u32) -> Vec<(u32, u32)> {
+    assert!(n >= 6 && n.is_multiple_of(2), "The prism must have an even number of vertices, at least 6");
+    let k = n / 2;
+    (0..k).flat_map(|i| vec![(i, (i + 1) % k), (k + i, k + (i + 1) % k), (i, k + i)]).collect()
+}
+
+// CFI graph over a 3-regular base graph, optionally twisting one base edge. Each base vertex v becomes two
+// end vertices for each of its edges e, one for each bit, and one middle vertex for each even-size subset
+// S of its edges, joined to the end vertex of e for bit 1 if e is in S and for bit 0 otherwise. Each base
+// edge joins the end vertices of its two endpoints with equal bits, or with opposite bits if twisted.
+fn cfi_graph(n: u32, base: &[(u32, u32)], twisted: Option<usize>) -> Graph {
+    let incident: Vec<Vec<usize>> = (0..n).map(|v| (0..base.len()).filter(|&e| base[e].0 == v || base[e].1 == v).collect()).collect();
+    // Vertex v uses labels 10v to 10v + 9: the middle vertices first, then the end vertices of its i-th edge
+    let end = |v: u32, e: usize, bit: u32| vertex_label(10 * v as usize + 4 + 2 * incident[v as usize].iter().position(|&x| x == e).unwrap() + bit as usize);
+    let mut edges = Vec::new();
+    for v in 0..n {
+        let subsets = (0..8u32).filter(|s| s.count_ones() % 2 == 0);
+        for (m, s) in subsets.enumerate() {
+            for (i, &e) in incident[v as usize].iter().enumerate() {
+                edges.push((vertex_label(10 * v as usize + m), end(v, e, (s >> i) & 1)));
+            }
+        }
+    }
+    for (e, &(u, v)) in base.iter().enumerate() {
+        let flip = (twisted == Some(e)) as u32;
+        edges.extend([(end(u, e, 0), end(v, e, flip)), (end(u, e, 1), end(v, e, 1 - flip))]);
+    }
+    // The construction is undirected, so each edge goes both ways
+    let reversed: Vec<(u32, u32)> = edges.iter().map(|&(a, b)| (b, a)).collect();
+    edges.extend(reversed);
+    Graph::new(vertex_label(10 * n as usize), edges)
+}
+
+#[test]
+fn test_create_graph_single_edge() {
+    let graph = Graph::new(2, vec![(0, 1)]);
+    assert_eq!(graph.n, 2);
+    assert!(graph.has_edge(0, 1));
+    assert_eq!(graph.out_degree(0), 1);
+    assert!(graph.successors(0).eq([1]));
+}
+
+#[test]
+fn test_create_graph_multi_edge() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    assert_eq!(graph.n, 4);
+    assert_eq!(graph.edge_count(), 5);
+    assert_eq!(graph.out_degree(0), 2);
+    assert_eq!(graph.out_degree(1), 2);
+    assert_eq!(graph.out_degree(2), 0);
+    assert_eq!(graph.out_degree(3), 1);
+}
+
+#[test]
+fn test_create_graph_fewer_edges_than_vertices() {
+    // The adjacency list should have an entry for every vertex, even when there are few edges
+    let graph = Graph::new(4, vec![(3, 0)]);
+    assert_eq!(graph.out_degree(3), 1);
+    assert!(graph.has_edge(3, 0));
+    assert!((0..3).all(|v| graph.out_degree(v) == 0));
+}
+
+#[test]
+#[should_panic]
+fn test_create_invalid_graph() {
+    // Vertex labels must be in the range 0 to N-1, inclusive
+    Graph::new(4, vec![(0, 1), (1, 5), (1, 3), (0, 3), (3, 0)]);
+}
+
+#[test]
+fn test_graph_diff() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]);
+    let expected = Graph::new(4, vec![(0, 1), (2, 1), (2, 3), (3, 0)]);
+    let diff = graph.diff(&expected);
+    assert_eq!(diff, GraphDiff { extra: vec![(1, 2)], missing: vec![(2, 1), (3, 0)] });
+    assert_eq!(diff.to_string(), "extra edges [1->2], missing edges [2->1, 3->0]");
+    assert!(graph.diff(&graph).is_empty());
+    assert_eq!(Graph::decode(&expected.encode()), Some(expected));
+    assert_eq!(Graph::decode(&[0, 0]), None);
+}
+
+#[test]
+fn test_symmetric_difference() {
+    let a = Graph::new(3, vec![(0, 1), (1, 2)]);
+    let b = Graph::new(4, vec![(1, 2), (2, 3)]);
+    assert_eq!(a.symmetric_difference(&b), Graph::new(4, vec![(0, 1), (2, 3)]));
+    assert_eq!(a.symmetric_difference(&a), Graph::new(3, Vec::new()));
+}
+
+#[test]
+fn test_approximate_edit_distance() {
+    let g = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let copy = g.permute(&Permutation::new(vec![2, 1, 0, 3]).unwrap());
+    assert_eq!(g.approximate_edit_distance(&copy), 0);
+    // Adding an edge to a relabeled copy costs one edit, found without knowing the relabeling
+    let mut edges: Vec<(u32, u32)> = copy.edges().collect();
+    edges.push((2, 0));
+    assert_eq!(g.approximate_edit_distance(&Graph::new(4, edges)), 1);
+    // The smaller graph is padded with isolated vertices
+    assert_eq!(Graph::new(2, vec![(0, 1)]).approximate_edit_distance(&Graph::new(5, vec![(3, 4), (4, 3)])), 1);
+}
+
+#[test]
+fn test_gi_diagnosis() {
+    // A verifier that challenged with g1 explains that the prover's isomorphism doesn't take its graph to g1
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let verifier = GIVerifier { r: 2, b: true, random_perm: instance.g0.clone(), instance: &instance };
+    let diagnosis = verifier.diagnose(&GIProverMessage::Isomorphism(Permutation::identity(4))).unwrap();
+    assert_eq!(diagnosis.check, "permuted graph != g1");
+    let details = diagnosis.details.unwrap();
+    assert!(details.starts_with(&instance.g0.diff(&instance.g1).to_string()));
+    assert!(details.ends_with("so the isomorphism is wrong"));
+    // A graph that isn't a copy of g1 is blamed instead
+    let verifier = GIVerifier { random_perm: Graph::new(4, vec![(0, 1)]), ..verifier };
+    let details = verifier.diagnose(&GIProverMessage::Isomorphism(Permutation::identity(4))).unwrap().details.unwrap();
+    assert!(details.ends_with("at most 4 edge edits from a copy of the challenged graph"));
+}
+
+#[test]
+fn test_parallel_gi_diagnosis() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let (commitment, opening) = commit(&instance.g0.encode());
+    let verifier = ParallelGIVerifier { r: 2, k: 1, challenge: vec![true], commitments: vec![commitment], instance: &instance };
+    let diagnosis = verifier.diagnose(&ParallelGIProverMessage::Openings(vec![opening.clone()], vec![Permutation::identity(4)])).unwrap();
+    assert_eq!(diagnosis.check, "permuted graph 0 != g1");
+    assert_eq!(diagnosis.details, Some(instance.g0.diff(&instance.g1).to_string()));
+    // Malformed messages are diagnosed rather than indexed or permuted out of range
+    let diagnosis = verifier.diagnose(&ParallelGIProverMessage::Openings(Vec::new(), Vec::new())).unwrap();
+    assert_eq!(diagnosis.details, Some("Found 0 openings, expected 1".to_string()));
+    let diagnosis = verifier.diagnose(&ParallelGIProverMessage::Openings(vec![opening.clone()], vec![Permutation::identity(7)])).unwrap();
+    assert_eq!(diagnosis.check, "isomorphism 0 has the wrong size");
+    let (commitment, small) = commit(&Graph::new(2, vec![(0, 1)]).encode());
+    let verifier = ParallelGIVerifier { commitments: vec![commitment], ..verifier };
+    let diagnosis = verifier.diagnose(&ParallelGIProverMessage::Openings(vec![small], vec![Permutation::identity(4)])).unwrap();
+    assert_eq!((diagnosis.check.as_str(), diagnosis.details), ("permuted graph 0 != g1", None));
+    // A verifier that hasn't challenged yet has nothing to diagnose
+    let verifier = ParallelGIVerifier { r: 0, k: 1, challenge: Vec::new(), commitments: Vec::new(), instance: &instance };
+    assert!(verifier.diagnose(&ParallelGIProverMessage::Openings(vec![opening], vec![Permutation::identity(4)])).is_none());
+}
+
+#[test]
+fn test_graph_serde() {
+    let graph = Graph::new(4, vec![(3, 0), (0, 1), (1, 2)]);
+    let json = serde_json::to_string(&graph).unwrap();
+    assert_eq!(json, r#"{"n":4,"edges":[[0,1],[1,2],[3,0]]}"#);
+    assert_eq!(serde_json::from_str::<Graph>(&json).unwrap(), graph);
+    // Deserialization rejects out-of-range edges and oversized graphs instead of panicking or allocating
+    assert!(serde_json::from_str::<Graph>(r#"{"n":4,"edges":[[0,4]]}"#).is_err());
+    assert!(serde_json::from_str::<Graph>(r#"{"n":4294967295,"edges":[]}"#).is_err());
+}
+
+#[test]
+fn test_permute() {
+    let perm = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]).permute(&Permutation::new(vec![1, 2, 3, 0]).unwrap());
     let expected_perm = Graph::new(4, vec![(1, 2), (2, 3), (2, 0), (1, 0), (0, 1)]);
 
     assert_eq!(perm, expected_perm);
@@ -440,12 +2407,299 @@ fn test_permute() {
 fn test_are_isomorphic() {
     let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
     // All permutations of the graph should be isomorphic to it
-    assert!((0..graph.n).permutations(graph.n as usize).any(|x| are_isomorphic(&graph, &graph.permute(&x))));
+    assert!((0..graph.n).permutations(graph.n as usize).all(|x| are_isomorphic(&graph, &graph.permute_by(&x)) == Some(true)));
+}
+
+#[test]
+fn test_gi_prover_aborts_without_isomorphism() {
+    // Challenged on a graph its own isn't a copy of, the prover ends the interaction instead of panicking
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    let mut prover = GIProver::new(&instance);
+    prover.handle(&GIVerifierMessage { b: false });
+    assert!(matches!(prover.handle(&GIVerifierMessage { b: true }), (GIProverMessage::Done, true)));
+    let mut prover = ParallelGIProver { r: 0, k: 2, random_perms: Vec::new(), openings: Vec::new(), instance: &instance };
+    prover.handle(&ParallelGIVerifierMessage { challenge: Vec::new() });
+    assert!(matches!(prover.handle(&ParallelGIVerifierMessage { challenge: vec![false, true] }), (ParallelGIProverMessage::Done, true)));
+    // Half the time the verifier challenges with g1, and the proof ends rejected rather than panicking
+    let runs = (0..20).filter(|_| {
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        run_interactive_proof(&mut GIProver::new(&instance), &mut verifier)
+    }).count();
+    assert!(runs < 20);
 }
 
 #[test]
 fn test_random_permute() {
     let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
     // Any random permutation of the graph should be isomorphic to it
-    assert!(are_isomorphic(&graph, &graph.random_permutation()))
+    assert_eq!(are_isomorphic(&graph, &graph.random_permutation()), Some(true))
+}
+
+#[test]
+fn test_gi_invalid_isomorphism_rejected() {
+    // A prover sending an isomorphism of the wrong size is rejected by validation before the verifier handles it
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    struct CollapsingProver<'a> { inner: GIProver<'a> }
+    impl Prover for CollapsingProver<'_> {
+        type ProverMessage = GIProverMessage;
+        type VerifierMessage = GIVerifierMessage;
+
+        fn handle(&mut self, msg: &GIVerifierMessage) -> (GIProverMessage, bool) {
+            match self.inner.handle(msg) {
+                (GIProverMessage::Isomorphism(_), done) => (GIProverMessage::Isomorphism(Permutation::identity(3)), done),
+                x => x,
+            }
+        }
+    }
+    let mut prover = CollapsingProver { inner: GIProver::new(&instance) };
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(crate::try_run_interactive_proof(&mut prover, &mut verifier),
+        Err(crate::ValidationError::ProverMessage(2, "Found 3 vertices in isomorphism, expected 4".to_string())));
+}
+
+#[test]
+fn test_gi_wrong_vertex_count_rejected() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(verifier.validate(&GIProverMessage::Graph(Graph::new(5, vec![(0, 1)]))), Err("Found 5 vertices in graph, expected 4".to_string()));
+    assert!(verifier.validate(&GIProverMessage::Isomorphism(Permutation::identity(4))).is_err());
+}
+
+#[test]
+fn test_size_limits() {
+    assert!(Graph::try_new(MAX_VERTICES + 1, Vec::new()).unwrap_err().contains("more than the limit"));
+    assert!(serde_json::from_str::<Graph>(&format!(r#"{{"n":{},"edges":[]}}"#, MAX_DESERIALIZED_VERTICES + 1)).is_err());
+    // A message with too many edges is refused before any of them are inserted
+    let mut bytes = 4u32.to_be_bytes().to_vec();
+    bytes.extend((0..MAX_DESERIALIZED_EDGES + 1).flat_map(|_| [0u8; 8]));
+    assert!(Graph::decode(&bytes).is_none());
+    bytes.truncate(bytes.len() - 8);
+    assert_eq!(Graph::decode(&bytes), Some(Graph::new(4, vec![(0, 0)])));
+}
+
+#[test]
+fn test_permute_compose() {
+    // Permuting by p and then by q is the same as permuting by p composed with q
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let p = Permutation::random(4, &mut thread_rng());
+    let q = Permutation::random(4, &mut thread_rng());
+    assert_eq!(graph.permute(&p).permute(&q), graph.permute(&p.compose(&q)));
+    assert_eq!(graph.permute(&p).permute(&p.invert()), graph);
+}
+
+#[test]
+fn test_find_isomorphism() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let other = graph.random_permutation();
+    match graph.find_isomorphism_to_with_budget(&other, SearchBudget::unlimited()) {
+        SearchResult::Found(isomorphism) => assert_eq!(graph.permute(&isomorphism), other),
+        x => panic!("Expected an isomorphism, found {:?}", x),
+    }
+    let nonisomorphic = Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]);
+    assert_eq!(graph.find_isomorphism_to_with_budget(&nonisomorphic, SearchBudget::unlimited()), SearchResult::NotFound);
+}
+
+#[test]
+fn test_find_isomorphism_budget() {
+    // Every vertex of a cycle has the same degrees, so the search can't prune its first few assignments
+    let edges: Vec<(u32, u32)> = (0..8).map(|i| (i, (i + 1) % 8)).collect();
+    let cycle = Graph::new(8, edges.clone());
+    let other_cycle = Graph::new(8, edges.iter().map(|&(a, b)| (b, a)).collect());
+    assert_eq!(cycle.find_isomorphism_to_with_budget(&other_cycle, SearchBudget::nodes(3)), SearchResult::Exhausted);
+    assert_eq!(cycle.find_isomorphism_to_with_budget(&other_cycle, SearchBudget::time(Duration::from_secs(0))), SearchResult::Exhausted);
+    assert!(matches!(cycle.find_isomorphism_to_with_budget(&other_cycle, SearchBudget::nodes(1000)), SearchResult::Found(_)));
+}
+
+#[test]
+fn test_find_isomorphism_large_graph() {
+    // Degree pruning lets the search handle graphs far too large to enumerate every permutation of
+    let n = 40;
+    let edges: Vec<(u32, u32)> = (0..n).flat_map(|i| vec![(i, (i + 1) % n), (i, (i * 7 + 3) % n)]).collect();
+    let graph = Graph::new(n, edges);
+    assert!(matches!(graph.find_isomorphism_to_with_budget(&graph.random_permutation(), SearchBudget::default()), SearchResult::Found(_)));
+}
+
+#[test]
+fn test_graph_encoding_is_canonical() {
+    // The encoding doesn't depend on the order edges were added in
+    let a = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let b = Graph::new(4, vec![(3, 0), (0, 3), (1, 3), (1, 2), (0, 1)]);
+    assert_eq!(a.encode(), b.encode());
+    assert_eq!(a.digest(), b.digest());
+    assert_eq!(Graph::new(2, vec![(1, 0)]).encode(), vec![0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0]);
+    // Graphs with the same edges but different numbers of vertices are different graphs
+    assert_ne!(Graph::new(5, vec![(0, 1)]).digest(), Graph::new(4, vec![(0, 1)]).digest());
+    assert_ne!(Graph::new(5, vec![(0, 1)]), Graph::new(4, vec![(0, 1)]));
+}
+
+#[test]
+fn test_graph_deduplication() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let graphs: HashSet<Graph> = (0..100).map(|_| graph.random_permutation()).collect();
+    // There are at most 4! = 24 relabelings of the graph
+    assert!(graphs.len() <= 24);
+    assert!(graphs.iter().all(|x| are_isomorphic(x, &graph) == Some(true)));
+    let pair = GraphPair { g0: graph.clone(), g1: graph.random_permutation() };
+    let swapped = GraphPair { g0: pair.g1.clone(), g1: pair.g0.clone() };
+    assert_eq!(pair.context("test", b"session").check(&pair.context("test", b"session")), Ok(()));
+    assert!(pair.g0 == pair.g1 || pair.digest() != swapped.digest());
+}
+
+#[test]
+fn test_color_refinement() {
+    // A path is told apart from a star with the same number of edges, but not from a relabeled copy
+    let path = Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]);
+    let star = Graph::new(4, vec![(0, 1), (0, 2), (0, 3)]);
+    assert!(!path.refinement_equivalent(&star));
+    assert!(path.refinement_equivalent(&path.random_permutation()));
+    // Every vertex of a directed path is told apart from every other
+    assert_eq!(path.color_refinement().iter().unique().count(), 4);
+}
+
+#[test]
+fn test_sample_hard_instance() {
+    let instance = GraphPair::sample_hard_instance(6);
+    assert_eq!((instance.g0.n, instance.g1.n), (60, 60));
+    assert_eq!(instance.g0.edge_count(), instance.g1.edge_count());
+    // Color refinement gives every vertex of each gadget type the same color in both graphs
+    assert!(instance.g0.refinement_equivalent(&instance.g1));
+    // The pair isn't isomorphic, but the search can't rule it out within a modest budget either
+    assert!(matches!(instance.g0.find_isomorphism_to_with_budget(&instance.g1, SearchBudget::nodes(100_000)), SearchResult::Exhausted));
+    let (instance, isomorphism) = GraphPair::sample_hard_isomorphic_instance(6);
+    assert_eq!(instance.g0.permute(&isomorphism), instance.g1);
+}
+
+#[test]
+fn test_permute_constant_time() {
+    // Sizes around word boundaries, including the empty graph
+    for n in [0u32, 1, 5, 63, 64, 65, 130].iter().copied() {
+        let edges = if n == 0 { Vec::new() } else { (0..n * 2).map(|_| (thread_rng().gen_range(0..n), thread_rng().gen_range(0..n))).collect() };
+        let graph = Graph::new(n, edges);
+        let p = Permutation::random(n, &mut thread_rng());
+        let permuted = graph.permute(&p);
+        assert_eq!(graph.permute_constant_time(&p), permuted);
+        assert!(graph.permutes_to_constant_time(&p, &permuted));
+        assert!(!graph.permutes_to_constant_time(&Permutation::identity(n + 1), &permuted));
+        if n > 1 && graph != permuted {
+            assert!(!graph.permutes_to_constant_time(&Permutation::identity(n), &permuted));
+        }
+    }
+
+    // Provers produce the same messages either way
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    config::with_constant_time(true, || {
+        assert!(instance.g0.permutes_to(&Permutation::new(vec![2, 1, 0, 3]).unwrap(), &instance.g1));
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        assert!(run_interactive_proof(&mut prover, &mut verifier));
+    });
+}
+
+#[test]
+fn test_csr_storage() {
+    for n in [0u32, 1, 5, 64, 200].iter().copied() {
+        let edges: Vec<(u32, u32)> = if n == 0 { Vec::new() } else { (0..n * 3).map(|_| (thread_rng().gen_range(0..n), thread_rng().gen_range(0..n))).collect() };
+        let graph = Graph::new(n, edges.clone());
+        let csr = Graph::with_storage(n, edges, GraphStorage::Csr);
+        assert_eq!(csr.storage(), GraphStorage::Csr);
+        // Duplicate edges are only stored once, like in the hash sets
+        assert_eq!(csr.edge_count(), graph.edge_count());
+        assert_eq!(csr, graph);
+        assert_eq!(graph, csr);
+        assert_eq!(csr.encode(), graph.encode());
+        assert_eq!(csr.to_storage(GraphStorage::HashSets).storage(), GraphStorage::HashSets);
+        assert!((0..n).all(|v| csr.out_degree(v) == graph.out_degree(v)
+            && csr.successors(v).sorted().eq(graph.successors(v).sorted())));
+        assert!(graph.edges().all(|(a, b)| csr.has_edge(a, b)));
+        assert!(!csr.has_edge(n, 0));
+        // Vertices outside the graph have no successors in either storage
+        assert!([&graph, &csr].iter().all(|g| g.out_degree(n) == 0 && g.successors(n).next().is_none() && !g.has_edge(n, 0)));
+        assert_eq!(csr.color_refinement(), graph.color_refinement());
+
+        let p = Permutation::random(n, &mut thread_rng());
+        let permuted = csr.permute(&p);
+        assert_eq!(permuted.storage(), GraphStorage::Csr);
+        assert_eq!(permuted, graph.permute(&p));
+        assert_eq!(permuted, csr.permute_constant_time(&p));
+        assert!(csr.permutes_to(&p, &permuted));
+        if n <= 5 {
+            assert_eq!(csr.find_isomorphism_to(&permuted).map(|q| csr.permute(&q)), Some(permuted));
+        }
+    }
+    assert_ne!(Graph::with_storage(2, vec![(0, 1)], GraphStorage::Csr), Graph::new(2, vec![(1, 0)]));
+    assert_eq!(format!("{:?}", Graph::with_storage(2, vec![(0, 1)], GraphStorage::Csr)), format!("{:?}", Graph::new(2, vec![(0, 1)])));
+}
+
+#[test]
+fn test_memory_footprint() {
+    let n = 10_000;
+    let edges: Vec<(u32, u32)> = (0..2 * n).map(|_| (thread_rng().gen_range(0..n), thread_rng().gen_range(0..n))).collect();
+    let graph = Graph::new(n, edges.clone());
+    let csr = Graph::with_storage(n, edges, GraphStorage::Csr);
+    // Each edge and vertex costs at least 4 bytes in CSR form. Hash sets hold every edge as a pair and
+    // again as a target, and a set per vertex, however std lays out the tables.
+    let (m, n) = (graph.edge_count(), n as usize);
+    assert_eq!(csr.edge_count(), m);
+    assert!(csr.memory_footprint() >= 4 * (n + m));
+    assert!(graph.memory_footprint() >= mem::size_of::<(u32, u32)>() * m + mem::size_of::<u32>() * m + mem::size_of::<HashSet<u32>>() * n);
+    assert!(csr.memory_footprint() < graph.memory_footprint());
+    assert!(Graph::new(0, Vec::new()).memory_footprint() < graph.memory_footprint());
+}
+
+#[test]
+fn test_refinement_cache() {
+    // Agrees with refining the graphs together
+    for _ in 0..200 {
+        let random_graph = || Graph::new(5, (0..4).map(|_| (thread_rng().gen_range(0..5), thread_rng().gen_range(0..5))).collect());
+        let instance = GraphPair { g0: random_graph(), g1: random_graph() };
+        let cache = RefinementCache::new(&instance);
+        let graph = random_graph();
+        assert_eq!(cache.equivalent(&graph, false), instance.g0.refinement_equivalent(&graph));
+        assert_eq!(cache.equivalent(&graph, true), instance.g1.refinement_equivalent(&graph));
+        assert!(cache.equivalent(&instance.g1.random_permutation(), true));
+    }
+    assert!(!RefinementCache::new(&GraphPair { g0: Graph::new(0, Vec::new()), g1: Graph::new(0, Vec::new()) }).equivalent(&Graph::new(1, Vec::new()), false));
+
+    // Honest repetitions pass the check, while graphs that aren't relabelings of g0 are refused
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let cache = RefinementCache::new(&instance);
+    for _ in 0..3 {
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = RefinedGIVerifier { inner: GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }, refinement: &cache };
+        assert_eq!(crate::try_run_interactive_proof(&mut prover, &mut verifier), Ok(true));
+    }
+    let verifier = RefinedGIVerifier { inner: GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }, refinement: &cache };
+    assert!(verifier.validate(&GIProverMessage::Graph(Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 1)]))).is_err());
+
+    // CFI graphs can't be told apart by color refinement, so a twisted copy passes the check
+    let hard = GraphPair::sample_hard_instance(6);
+    assert!(RefinementCache::new(&hard).equivalent(&hard.g1, false));
+}
+
+#[test]
+fn test_bit_matrix_kernels() {
+    for n in [1u32, 63, 64, 65, 130].iter().copied() {
+        let graph = Graph::new(n, (0..n * 4).map(|_| (thread_rng().gen_range(0..n), thread_rng().gen_range(0..n))).collect());
+        let matrix = BitMatrix::from_graph(&graph);
+        assert_eq!(matrix.to_graph(), graph);
+        let reversed = Graph::new(n, graph.edges().map(|(a, b)| (b, a)).collect());
+        assert_eq!(matrix.transpose().to_graph(), reversed);
+        assert_eq!(difference(&matrix.transpose().transpose().bits, &matrix.bits), 0);
+        assert_ne!(difference(&matrix.bits, &BitMatrix::zero(n as usize).bits), 0);
+    }
 }
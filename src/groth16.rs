@@ -0,0 +1,232 @@
+use std::str::FromStr;
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{prepare_verifying_key, Groth16, PreparedVerifyingKey, Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+use crate::statement::{Compile, Statement, Witness};
+
+// ************ Groth16 verification implementation ************
+
+// Proofs produced outside zklib, by arkworks or any prover with the same encoding, can be checked next to
+// zklib's own protocols, e.g. by the verification service. Only verification is supported: a Groth16
+// instance is a verifying key over BN254 and the circuit's public inputs, and its proof is the witness,
+// since producing one needs the circuit and a proving key that zklib never sees.
+//
+// Keys and proofs are hex strings of arkworks' compressed canonical encoding, which checks that every
+// point is on the curve and in the right subgroup when it is decoded. Public inputs are decimal strings,
+// as snarkjs and circom write them, and must be less than the order of the scalar field.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Groth16Instance {
+    pub verifying_key: String,
+    pub public_inputs: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Groth16Proof {
+    pub proof: String,
+}
+
+impl Groth16Instance {
+    pub fn new(verifying_key: &VerifyingKey<Bn254>, public_inputs: &[Fr]) -> Groth16Instance {
+        Groth16Instance { verifying_key: to_hex(verifying_key), public_inputs: public_inputs.iter().map(Fr::to_string).collect() }
+    }
+
+    pub fn parse_verifying_key(&self) -> Result<VerifyingKey<Bn254>, String> {
+        from_hex(&self.verifying_key).map_err(|e| format!("Invalid verifying key: {}", e))
+    }
+
+    pub fn parse_public_inputs(&self) -> Result<Vec<Fr>, String> {
+        self.public_inputs.iter().enumerate().map(|(i, input)| parse_scalar(input).map_err(|e| format!("Invalid public input {}: {}", i, e))).collect()
+    }
+}
+
+impl Groth16Proof {
+    pub fn new(proof: &Proof<Bn254>) -> Groth16Proof {
+        Groth16Proof { proof: to_hex(proof) }
+    }
+
+    pub fn parse(&self) -> Result<Proof<Bn254>, String> {
+        from_hex(&self.proof).map_err(|e| format!("Invalid proof: {}", e))
+    }
+}
+
+// Checks the proof against the instance, describing why it was rejected otherwise
+pub fn verify_groth16(instance: &Groth16Instance, proof: &Groth16Proof) -> Result<(), String> {
+    let key = prepare_verifying_key(&instance.parse_verifying_key()?);
+    verify_prepared(&key, instance, proof)
+}
+
+fn verify_prepared(key: &PreparedVerifyingKey<Bn254>, instance: &Groth16Instance, proof: &Groth16Proof) -> Result<(), String> {
+    let inputs = instance.parse_public_inputs()?;
+    check_input_count(&key.vk, &inputs)?;
+    match Groth16::<Bn254>::verify_proof(key, &proof.parse()?, &inputs) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Proof does not verify".to_string()),
+        Err(e) => Err(format!("Proof does not verify: {}", e)),
+    }
+}
+
+// The verifying key has one point per public input, plus one for the constant term
+fn check_input_count(key: &VerifyingKey<Bn254>, inputs: &[Fr]) -> Result<(), String> {
+    if inputs.len() + 1 != key.gamma_abc_g1.len() {
+        return Err(format!("Got {} public inputs for a circuit with {}", inputs.len(), key.gamma_abc_g1.len().saturating_sub(1)));
+    }
+    Ok(())
+}
+
+fn parse_scalar(input: &str) -> Result<Fr, String> {
+    let scalar = Fr::from_str(input).map_err(|_| "not a decimal number".to_string())?;
+    // Only the canonical encoding is accepted, so that each input has a single encoding even if parsing
+    // tolerates leading zeros or values past the field order
+    if scalar.to_string() != input {
+        return Err("not a canonical decimal field element".to_string());
+    }
+    Ok(scalar)
+}
+
+fn to_hex<T: CanonicalSerialize>(value: &T) -> String {
+    let mut bytes = Vec::new();
+    value.serialize_compressed(&mut bytes).expect("Serializing to a Vec can't fail");
+    hex::encode(bytes)
+}
+
+fn from_hex<T: CanonicalDeserialize>(text: &str) -> Result<T, String> {
+    let bytes = hex::decode(text).map_err(|e| e.to_string())?;
+    let mut reader = bytes.as_slice();
+    let value = T::deserialize_compressed(&mut reader).map_err(|e| e.to_string())?;
+    if !reader.is_empty() {
+        return Err(format!("{} trailing bytes", reader.len()));
+    }
+    Ok(value)
+}
+
+impl Witness for Groth16Proof {}
+
+// The statement that the circuit is satisfiable with the public inputs, with a proof as the witness.
+// Groth16 is non-interactive, so running the proof means verifying it.
+impl Statement for Groth16Instance {
+    type Witness = Groth16Proof;
+
+    fn protocol(&self) -> &'static str {
+        "groth16-bn254"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.verifying_key.as_bytes().to_vec();
+        for input in &self.public_inputs {
+            bytes.push(b',');
+            bytes.extend_from_slice(input.as_bytes());
+        }
+        bytes
+    }
+
+    // Only decodes the key, inputs and proof, leaving the pairings to run_proof so that proving a statement
+    // computes them once
+    fn validate(&self, witness: &Groth16Proof) -> Result<(), String> {
+        check_input_count(&self.parse_verifying_key()?, &self.parse_public_inputs()?)?;
+        witness.parse().map(|_| ())
+    }
+
+    fn run_proof(&self, witness: &Groth16Proof) -> bool {
+        verify_groth16(self, witness).is_ok()
+    }
+}
+
+// Compiling a Groth16 instance prepares its verifying key, computing the pairing of alpha and beta that
+// every verification would otherwise repeat. The verifier key is the prepared key, so it is decoded once
+// when the keys are loaded rather than on every proof.
+impl Compile for Groth16Instance {
+    type ProverKey = ();
+    type VerifierKey = PreparedKey;
+
+    fn compile(&self) -> Result<((), PreparedKey), String> {
+        Ok(((), PreparedKey(prepare_verifying_key(&self.parse_verifying_key()?))))
+    }
+
+    fn run_proof_with_keys(&self, _prover_key: &(), verifier_key: &PreparedKey, witness: &Groth16Proof) -> bool {
+        verify_prepared(&verifier_key.0, self, witness).is_ok()
+    }
+}
+
+// A prepared verifying key, serialized as its uncompressed canonical encoding
+pub struct PreparedKey(pub PreparedVerifyingKey<Bn254>);
+
+impl Serialize for PreparedKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = Vec::new();
+        self.0.serialize_uncompressed(&mut bytes).expect("Serializing to a Vec can't fail");
+        bytes.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PreparedKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PreparedKey, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        PreparedVerifyingKey::deserialize_uncompressed(bytes.as_slice()).map(PreparedKey).map_err(|e| D::Error::custom(format!("Invalid prepared verifying key: {}", e)))
+    }
+}
+
+// Proves knowledge of a square root of the public input
+#[cfg(test)]
+struct SquareRoot {
+    root: Option<Fr>,
+}
+
+#[cfg(test)]
+impl ark_relations::r1cs::ConstraintSynthesizer<Fr> for SquareRoot {
+    fn generate_constraints(self, cs: ark_relations::r1cs::ConstraintSystemRef<Fr>) -> ark_relations::r1cs::Result<()> {
+        use ark_relations::{lc, r1cs::SynthesisError};
+        let root = cs.new_witness_variable(|| self.root.ok_or(SynthesisError::AssignmentMissing))?;
+        let square = cs.new_input_variable(|| self.root.map(|x| x * x).ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce_constraint(lc!() + root, lc!() + root, lc!() + square)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn square_root_proof() -> (Groth16Instance, Groth16Proof) {
+    use ark_snark::SNARK;
+    let mut rng = rand::thread_rng();
+    let (proving_key, verifying_key) = Groth16::<Bn254>::circuit_specific_setup(SquareRoot { root: None }, &mut rng).unwrap();
+    let root = Fr::from(12345u64);
+    let proof = Groth16::<Bn254>::prove(&proving_key, SquareRoot { root: Some(root) }, &mut rng).unwrap();
+    (Groth16Instance::new(&verifying_key, &[root * root]), Groth16Proof::new(&proof))
+}
+
+#[test]
+fn test_verify_groth16() {
+    let (instance, proof) = square_root_proof();
+    assert_eq!(verify_groth16(&instance, &proof), Ok(()));
+    assert_eq!(crate::statement::prove(&instance, &proof), Ok(true));
+    let wrong = Groth16Instance { public_inputs: vec!["4".to_string()], ..instance.clone() };
+    assert_eq!(verify_groth16(&wrong, &proof), Err("Proof does not verify".to_string()));
+    // Validating only decodes the proof, and proving then rejects it
+    assert_eq!(wrong.validate(&proof), Ok(()));
+    assert_eq!(crate::statement::prove(&wrong, &proof), Ok(false));
+    let missing = Groth16Instance { public_inputs: Vec::new(), ..instance.clone() };
+    assert!(verify_groth16(&missing, &proof).unwrap_err().contains("0 public inputs"));
+    // Inputs must be canonical, so that each field element has a single encoding
+    for input in ["04", "-4", "4x", ""] {
+        let malformed = Groth16Instance { public_inputs: vec![input.to_string()], ..instance.clone() };
+        assert!(verify_groth16(&malformed, &proof).unwrap_err().contains("public input 0"));
+    }
+    let truncated = Groth16Proof { proof: proof.proof[..proof.proof.len() - 2].to_string() };
+    assert!(verify_groth16(&instance, &truncated).unwrap_err().contains("Invalid proof"));
+}
+
+#[test]
+fn test_compile_groth16() {
+    let (instance, proof) = square_root_proof();
+    let keys = crate::statement::compile(&instance).unwrap();
+    assert_eq!(crate::statement::prove_with_keys(&instance, &keys, &proof), Ok(true));
+    let wrong = Groth16Instance { public_inputs: vec!["4".to_string()], ..instance.clone() };
+    assert!(!wrong.run_proof_with_keys(&keys.prover, &keys.verifier, &proof));
+    // The prepared key survives a round trip through a key file
+    let decoded: crate::statement::Keys<Groth16Instance> = bincode::deserialize(&bincode::serialize(&keys).unwrap()).unwrap();
+    assert_eq!(crate::statement::prove_with_keys(&instance, &decoded, &proof), Ok(true));
+    // A key that doesn't decode fails to compile rather than compiling to an empty key
+    let malformed = Groth16Instance { verifying_key: "00".to_string(), ..instance };
+    assert!(crate::statement::compile(&malformed).err().unwrap().contains("Invalid verifying key"));
+}
@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use rand::{Rng, seq::SliceRandom};
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::commitment::Opening;
+use crate::committed::{CommittedGraph, EntryOpening, GraphCommitment, GraphCommitmentScheme};
+use crate::graph::{check_count, Graph};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Hamiltonian cycle search and instance generation ************
+
+// A directed cycle visiting every vertex exactly once, listed in the order the vertices are visited.
+// The cycle closes with an edge from the last vertex back to the first.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HamiltonianCycle {
+    pub vertices: Vec<u32>,
+}
+
+impl HamiltonianCycle {
+    // Checks that the cycle visits every vertex of the graph exactly once using only edges of the graph
+    pub fn verify(&self, graph: &Graph) -> bool {
+        let n = graph.n as usize;
+        n > 0 && self.vertices.len() == n
+            && self.vertices.iter().all(|&v| v < graph.n)
+            && self.vertices.iter().collect::<HashSet<_>>().len() == n
+            && (0..n).all(|i| graph.has_edge(self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+}
+
+// Searches for a Hamiltonian cycle by backtracking over paths starting at vertex 0
+pub fn find_hamiltonian_cycle(graph: &Graph) -> Option<HamiltonianCycle> {
+    let n = graph.n as usize;
+    if n == 0 {
+        return None;
+    }
+    // Every vertex of a Hamiltonian cycle needs an incoming and an outgoing edge
+    let mut in_degree = vec![0; n];
+    graph.edges().for_each(|(_, b)| in_degree[b as usize] += 1);
+    if (0..graph.n).any(|v| graph.out_degree(v) == 0 || in_degree[v as usize] == 0) {
+        return None;
+    }
+    // Visit neighbors in a fixed order so that the search is deterministic
+    let successors: Vec<Vec<u32>> = (0..graph.n).map(|v| graph.successors(v).sorted().collect()).collect();
+    let mut path = vec![0];
+    let mut visited = vec![false; n];
+    visited[0] = true;
+    if extend_path(graph, &successors, &mut path, &mut visited) {
+        Some(HamiltonianCycle { vertices: path })
+    } else {
+        None
+    }
+}
+
+fn extend_path(graph: &Graph, successors: &[Vec<u32>], path: &mut Vec<u32>, visited: &mut [bool]) -> bool {
+    let last = *path.last().unwrap();
+    if path.len() == graph.n as usize {
+        return graph.has_edge(last, path[0]);
+    }
+    for &next in successors[last as usize].iter() {
+        if visited[next as usize] {
+            continue;
+        }
+        visited[next as usize] = true;
+        path.push(next);
+        if extend_path(graph, successors, path, visited) {
+            return true;
+        }
+        path.pop();
+        visited[next as usize] = false;
+    }
+    false
+}
+
+// Generates a random graph on n vertices containing a planted Hamiltonian cycle through a random
+// ordering of the vertices, with every other directed edge added independently with probability p.
+pub fn random_hamiltonian_graph(n: u32, p: f64) -> (Graph, HamiltonianCycle) {
+    let mut rng = checked_thread_rng();
+    let mut vertices: Vec<u32> = (0..n).collect();
+    vertices.shuffle(&mut rng);
+    let mut edges: HashSet<(u32, u32)> = (0..n as usize).map(|i| (vertices[i], vertices[(i + 1) % n as usize])).collect();
+    for a in 0..n {
+        for b in 0..n {
+            if a != b && rng.gen_bool(p) {
+                edges.insert((a, b));
+            }
+        }
+    }
+    (Graph::new(n, edges.into_iter().collect()), HamiltonianCycle { vertices })
+}
+
+// ************ Zero-knowledge Hamiltonicity proof implementation ************
+
+// Blum's protocol. The prover relabels the graph with a random permutation and commits to the relabeled
+// graph, see CommittedGraph. The verifier flips a coin: on 0 the prover opens the whole adjacency matrix
+// and reveals the permutation, showing that it committed to a copy of the graph, and on 1 it opens only
+// the n entries on the relabeled cycle, showing that the committed graph has a Hamiltonian cycle. Neither
+// answer alone says anything about the cycle in the original graph, and a prover without one can prepare
+// for only one of the coins, so it is caught with probability 1/2 per repetition, like in GI. Every
+// repetition commits to n^2 entries, which is where Hamiltonicity pays for being NP-complete, although
+// committing with a Merkle root sends only the n opened entries' paths on challenge 1.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum HamiltonianProverMessage {
+    // Commitment to the relabeled graph
+    Commitment(GraphCommitment),
+    // Permutation relabeling the graph, and openings of every entry
+    Permutation(Permutation, Vec<Opening>),
+    // Relabeled cycle, and openings of the entries for its edges in order
+    Cycle(HamiltonianCycle, Vec<EntryOpening>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HamiltonianVerifierMessage {
+    // Whether the prover must open the cycle rather than the whole matrix
+    pub b: bool,
+}
+
+pub struct HamiltonianProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // How the relabeled graph is committed to
+    pub scheme: GraphCommitmentScheme,
+    // Permutation relabeling the graph, and the relabeled graph committed to
+    pub permutation: Permutation,
+    pub committed: Option<CommittedGraph>,
+    pub graph: &'a Graph,
+    pub cycle: &'a HamiltonianCycle,
+}
+
+impl Prover for HamiltonianProver<'_> {
+    type ProverMessage = HamiltonianProverMessage;
+    type VerifierMessage = HamiltonianVerifierMessage;
+
+    fn handle(&mut self, msg: &HamiltonianVerifierMessage) -> (HamiltonianProverMessage, bool) {
+        self.r += 1;
+        match (self.r, &self.committed) {
+            // During the first round, the prover commits to a random relabeling of the graph
+            (1, _) => {
+                self.permutation = Permutation::random(self.graph.n, &mut checked_thread_rng());
+                let committed = CommittedGraph::commit(&self.graph.permute(&self.permutation), true, self.scheme);
+                let commitment = committed.commitment.clone();
+                self.committed = Some(committed);
+                (HamiltonianProverMessage::Commitment(commitment), false)
+            },
+            // During the second round, the prover opens either the relabeled cycle or the whole matrix
+            (2, Some(committed)) if msg.b => {
+                let vertices: Vec<u32> = self.cycle.vertices.iter().map(|&v| self.permutation.apply(v)).collect();
+                let openings = (0..vertices.len()).map(|i| committed.open(vertices[i], vertices[(i + 1) % vertices.len()])).collect();
+                (HamiltonianProverMessage::Cycle(HamiltonianCycle { vertices }, openings), false)
+            },
+            (2, Some(committed)) => (HamiltonianProverMessage::Permutation(self.permutation.clone(), committed.open_all()), false),
+            // After opening, the prover sends a message to terminate the interaction
+            _ => (HamiltonianProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct HamiltonianVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen bit
+    pub b: bool,
+    // Commitment received from prover
+    pub commitment: Option<GraphCommitment>,
+    pub graph: &'a Graph,
+}
+
+impl Verifier for HamiltonianVerifier<'_> {
+    type ProverMessage = HamiltonianProverMessage;
+    type VerifierMessage = HamiltonianVerifierMessage;
+
+    fn init(&mut self) -> HamiltonianVerifierMessage {
+        HamiltonianVerifierMessage { b: false }
+    }
+
+    fn validate(&self, msg: &HamiltonianProverMessage) -> Result<(), String> {
+        let n = self.graph.n as usize;
+        match (self.r + 1, msg, self.b) {
+            (1, HamiltonianProverMessage::Commitment(commitment), _) => {
+                check_count(commitment.n as usize, n, "vertices in commitment")?;
+                if !commitment.directed {
+                    return Err("expected a commitment to a directed graph".to_string());
+                }
+                commitment.check()
+            },
+            (1, _, _) => Err("expected a commitment".to_string()),
+            (_, HamiltonianProverMessage::Permutation(permutation, _), false) => check_count(permutation.n() as usize, n, "vertices in permutation"),
+            (_, HamiltonianProverMessage::Cycle(cycle, openings), true) => {
+                check_count(cycle.vertices.len(), n, "vertices in cycle")?;
+                check_count(openings.len(), n, "openings")
+            },
+            (_, _, false) => Err("expected a permutation".to_string()),
+            (_, _, true) => Err("expected a cycle".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &HamiltonianProverMessage) -> (HamiltonianVerifierMessage, bool) {
+        self.r += 1;
+        let n = self.graph.n;
+        match (self.r, msg, &self.commitment) {
+            (1, HamiltonianProverMessage::Commitment(commitment), _) => {
+                self.commitment = Some(commitment.clone());
+                self.b = checked_thread_rng().gen();
+                (HamiltonianVerifierMessage { b: self.b }, false)
+            },
+            // The committed graph must be the graph relabeled by the permutation
+            (_, HamiltonianProverMessage::Permutation(permutation, openings), Some(commitment)) => {
+                let accept = commitment.verify_all(openings).is_some_and(|committed| committed == self.graph.permute(permutation));
+                (HamiltonianVerifierMessage { b: self.b }, accept)
+            },
+            // Every edge of the cycle must be committed to as present in the graph
+            (_, HamiltonianProverMessage::Cycle(cycle, openings), Some(commitment)) => {
+                let vertices = &cycle.vertices;
+                let accept = n > 0 && vertices.iter().all(|&v| v < n) && vertices.iter().collect::<HashSet<_>>().len() == n as usize
+                    && (0..vertices.len()).all(|i| {
+                        let opening = &openings[i];
+                        (opening.a, opening.b) == (vertices[i], vertices[(i + 1) % vertices.len()]) && commitment.verify_entry(opening) == Some(true)
+                    });
+                (HamiltonianVerifierMessage { b: self.b }, accept)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_verify_cycle() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)]);
+    assert!(HamiltonianCycle { vertices: vec![0, 1, 2, 3] }.verify(&graph));
+    assert!(HamiltonianCycle { vertices: vec![2, 3, 0, 1] }.verify(&graph));
+    // Uses an edge in the wrong direction
+    assert!(!HamiltonianCycle { vertices: vec![0, 3, 2, 1] }.verify(&graph));
+    // Repeats a vertex
+    assert!(!HamiltonianCycle { vertices: vec![0, 1, 2, 0] }.verify(&graph));
+}
+
+#[test]
+fn test_planted_cycle() {
+    let (graph, cycle) = random_hamiltonian_graph(30, 0.1);
+    assert!(cycle.verify(&graph));
+}
+
+#[test]
+fn test_find_planted_cycle() {
+    let (graph, _) = random_hamiltonian_graph(20, 0.1);
+    let cycle = find_hamiltonian_cycle(&graph).unwrap();
+    assert!(cycle.verify(&graph));
+}
+
+#[test]
+fn test_no_hamiltonian_cycle() {
+    // A directed path has no way to return to its start
+    let path = Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]);
+    assert_eq!(find_hamiltonian_cycle(&path), None);
+    // Two disjoint cycles cover every vertex but cannot be joined
+    let two_cycles = Graph::new(4, vec![(0, 1), (1, 0), (2, 3), (3, 2)]);
+    assert_eq!(find_hamiltonian_cycle(&two_cycles), None);
+}
+
+#[test]
+fn test_hamiltonian_proof() {
+    let (graph, cycle) = random_hamiltonian_graph(10, 0.2);
+    let path = Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]);
+    let not_a_cycle = HamiltonianCycle { vertices: vec![0, 1, 2, 3] };
+    for scheme in [GraphCommitmentScheme::PerEntry, GraphCommitmentScheme::Merkle].iter().cloned() {
+        let mut prover = HamiltonianProver { r: 0, scheme, permutation: Permutation::identity(0), committed: None, graph: &graph, cycle: &cycle };
+        let mut verifier = HamiltonianVerifier { r: 0, b: false, commitment: None, graph: &graph };
+        assert!(run_interactive_proof(&mut prover, &mut verifier));
+
+        // Without a cycle, the prover can still open a copy of the graph, but not a cycle through it
+        let rejected = (0..100).filter(|_| {
+            let mut prover = HamiltonianProver { r: 0, scheme, permutation: Permutation::identity(0), committed: None, graph: &path, cycle: &not_a_cycle };
+            let mut verifier = HamiltonianVerifier { r: 0, b: false, commitment: None, graph: &path };
+            !run_interactive_proof(&mut prover, &mut verifier)
+        }).count();
+        assert!(rejected > 25 && rejected < 75);
+    }
+}
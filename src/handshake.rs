@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::Deserialize;
+use crate::audit::to_hex;
+use crate::hash::{Hasher, Sha256Hasher};
+use crate::keys::KeyMaterial;
+use crate::randomness::checked_thread_rng;
+use crate::schnorr::{SchnorrPublicKey, SchnorrSignature};
+#[cfg(test)]
+use crate::params::{Parameters, SchnorrParameters};
+#[cfg(test)]
+use crate::schnorr::SchnorrKeyPair;
+
+// ************ Handshake implementation ************
+
+// A server open to anyone verifies whatever it is sent. Deployments that only serve known provers give
+// each one an identity, and a client authenticates before starting a session or verification, with an
+// Authorization header holding either:
+//
+// - "Bearer <token>", an API token whose SHA-256 digest the server knows
+// - "Signature <identity> <nonce> <r> <s>", a Schnorr signature in hex on a nonce the server issued from
+//   POST /sessions/challenge, under a public key saved by `KeyMaterial`. Each nonce is accepted once and
+//   only for a minute, so a signature can't be replayed.
+//
+// Each identity has a policy limiting the protocols it may use and the size of its instances, which the
+// server enforces on top of its Limits.
+//
+// Authentication doesn't stop an open server from being flooded with sessions, each of which it keeps in
+// memory until it expires. A server can instead make each session cost its client some work, as a
+// hashcash-style puzzle: it issues a random nonce, and the client looks for a number whose SHA-256 digest
+// with the nonce starts with the server's number of zero bits, which takes about 2^bits hashes to find
+// and one to check. The client sends the solution in an X-Zklib-Work header of "<nonce> <solution>", and
+// the server checks it before reading anything else of the request. Like challenges, each puzzle is
+// solved once and only for a minute.
+
+// How long a challenge nonce stays valid
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(60);
+
+// Most challenges, or puzzles, outstanding at once, beyond which no more are issued until some expire
+const MAX_CHALLENGES: usize = 4096;
+
+// Most challenges, or puzzles, outstanding for one client, beyond which its own oldest is dropped
+const MAX_CHALLENGES_PER_CLIENT: usize = 16;
+
+// Header carrying the solution to a puzzle
+pub const WORK_HEADER: &str = "x-zklib-work";
+
+// Most zero bits a puzzle may ask for, beyond which honest clients would take hours
+pub const MAX_WORK_BITS: u32 = 40;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credential {
+    // Hex SHA-256 digest of an API token
+    Token(String),
+    Key(SchnorrPublicKey),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct Policy {
+    // Protocols the identity may use, e.g. "gi" for sessions, or every protocol if None
+    pub protocols: Option<Vec<String>>,
+    // Largest instance the identity may submit, in bytes, if limited. It is measured like the server's own
+    // limits measure it: GI instances by their memory footprint, and verification requests by their size.
+    pub max_instance_bytes: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub name: String,
+    pub credential: Credential,
+    pub policy: Policy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthError {
+    // No credentials were presented
+    Missing,
+    // The credentials are malformed, unknown, or don't verify
+    Rejected(String),
+    // The identity is known, but its policy doesn't allow the request
+    Forbidden(String),
+    // Too many challenges are outstanding to issue another
+    Busy,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "Authentication required"),
+            AuthError::Rejected(reason) => write!(f, "Authentication failed: {}", reason),
+            AuthError::Forbidden(reason) => write!(f, "Not allowed: {}", reason),
+            AuthError::Busy => write!(f, "Too many challenges outstanding, try again later"),
+        }
+    }
+}
+
+impl Identity {
+    // Checks the identity's policy for a request using the protocol on an instance of the given size
+    pub fn authorize(&self, protocol: &str, instance_bytes: usize) -> Result<(), AuthError> {
+        if self.policy.protocols.as_ref().is_some_and(|protocols| !protocols.iter().any(|x| x == protocol)) {
+            return Err(AuthError::Forbidden(format!("{} may not use {}", self.name, protocol)));
+        }
+        match self.policy.max_instance_bytes {
+            Some(limit) if instance_bytes > limit => Err(AuthError::Forbidden(format!("{} may submit instances of at most {} bytes, not {}", self.name, limit, instance_bytes))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Message a client signs to answer a challenge
+pub fn challenge_message(nonce: &str) -> Vec<u8> {
+    [b"zklib handshake ".as_slice(), nonce.as_bytes()].concat()
+}
+
+// The Authorization header answering a challenge with a signature
+pub fn signature_header(identity: &str, nonce: &str, signature: &SchnorrSignature) -> String {
+    format!("Signature {} {} {} {}", identity, nonce, signature.r.to_str_radix(16), signature.s.to_str_radix(16))
+}
+
+// An identity as saved in the server's identities file, with a token digest or a PEM public key
+#[derive(Deserialize)]
+struct IdentityEntry {
+    name: String,
+    token_sha256: Option<String>,
+    public_key: Option<String>,
+    #[serde(default)]
+    policy: Policy,
+}
+
+// Outstanding nonces of challenges or puzzles, with what each was issued for, the client it was issued to
+// and when. A client asking for more than its share loses its own oldest nonce rather than anyone else's,
+// so a flood from one address can't expire the nonces of honest clients. A flood from many addresses can
+// still use up MAX_CHALLENGES, after which new nonces are refused rather than taken from other clients.
+struct Nonces<T> {
+    issued: HashMap<String, (T, IpAddr, Instant)>,
+}
+
+impl<T> Nonces<T> {
+    fn new() -> Nonces<T> {
+        Nonces { issued: HashMap::new() }
+    }
+
+    // Issues a nonce to the client, or None if too many are outstanding
+    fn issue(&mut self, client: IpAddr, value: T) -> Option<String> {
+        let now = Instant::now();
+        self.issued.retain(|_, (_, _, issued)| now.duration_since(*issued) < CHALLENGE_TTL);
+        let own = self.issued.iter().filter(|(_, (_, to, _))| *to == client);
+        if own.clone().count() >= MAX_CHALLENGES_PER_CLIENT {
+            let oldest = own.min_by_key(|(_, (_, _, issued))| *issued).map(|(nonce, _)| nonce.clone()).unwrap();
+            self.issued.remove(&oldest);
+        } else if self.issued.len() >= MAX_CHALLENGES {
+            return None;
+        }
+        let nonce = to_hex(&checked_thread_rng().gen::<[u8; 32]>());
+        self.issued.insert(nonce.clone(), (value, client, now));
+        Some(nonce)
+    }
+
+    // Uses up the nonce, returning what it was issued for if it hadn't expired
+    fn redeem(&mut self, nonce: &str) -> Option<T> {
+        match self.issued.remove(nonce) {
+            Some((value, _, issued)) if issued.elapsed() < CHALLENGE_TTL => Some(value),
+            _ => None,
+        }
+    }
+}
+
+pub struct Handshake {
+    identities: HashMap<String, Identity>,
+    // Outstanding challenges, with the identity each was issued to
+    challenges: Nonces<String>,
+}
+
+impl Handshake {
+    pub fn new(identities: Vec<Identity>) -> Handshake {
+        Handshake { identities: identities.into_iter().map(|identity| (identity.name.clone(), identity)).collect(), challenges: Nonces::new() }
+    }
+
+    // Loads identities from a JSON array of {"name", "token_sha256" or "public_key", "policy"}
+    pub fn load(path: &Path) -> Result<Handshake, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let entries: Vec<IdentityEntry> = serde_json::from_str(&contents).map_err(|e| format!("malformed identities {}: {}", path.display(), e))?;
+        let identities = entries.into_iter().map(|entry| {
+            let credential = match (&entry.token_sha256, &entry.public_key) {
+                (Some(digest), None) => Credential::Token(digest.to_lowercase()),
+                (None, Some(pem)) => Credential::Key(SchnorrPublicKey::from_pem(pem).map_err(|e| format!("identity {}: {}", entry.name, e))?),
+                _ => return Err(format!("identity {} needs exactly one of token_sha256 and public_key", entry.name)),
+            };
+            Ok(Identity { name: entry.name, credential, policy: entry.policy })
+        }).collect::<Result<Vec<Identity>, String>>()?;
+        Ok(Handshake::new(identities))
+    }
+
+    pub fn len(&self) -> usize {
+        self.identities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    // Issues a nonce for the identity to sign, to the client at the address
+    pub fn challenge(&mut self, identity: &str, client: IpAddr) -> Result<String, AuthError> {
+        match self.identities.get(identity) {
+            Some(Identity { credential: Credential::Key(_), .. }) => (),
+            _ => return Err(AuthError::Rejected(format!("{} has no public key", identity))),
+        }
+        self.challenges.issue(client, identity.to_string()).ok_or(AuthError::Busy)
+    }
+
+    // Returns the identity the Authorization header proves, using up its nonce if it answers a challenge
+    pub fn authenticate(&mut self, authorization: Option<&str>) -> Result<&Identity, AuthError> {
+        let authorization = authorization.ok_or(AuthError::Missing)?;
+        let name = match authorization.split_once(' ') {
+            Some(("Bearer", token)) => {
+                let digest = to_hex(&Sha256Hasher::hash(&[token.as_bytes()]));
+                let identity = self.identities.values().find(|identity| identity.credential == Credential::Token(digest.clone()));
+                identity.ok_or_else(|| AuthError::Rejected("unknown token".to_string()))?.name.clone()
+            },
+            Some(("Signature", rest)) => self.check_signature(rest)?,
+            _ => return Err(AuthError::Rejected("expected a Bearer token or a Signature".to_string())),
+        };
+        Ok(&self.identities[&name])
+    }
+
+    fn check_signature(&mut self, rest: &str) -> Result<String, AuthError> {
+        let parts: Vec<&str> = rest.split(' ').collect();
+        let (name, nonce, r, s) = match parts[..] {
+            [name, nonce, r, s] => (name, nonce, r, s),
+            _ => return Err(AuthError::Rejected("expected a Signature of an identity, a nonce, r and s".to_string())),
+        };
+        let number = |x: &str| BigUint::parse_bytes(x.as_bytes(), 16).ok_or_else(|| AuthError::Rejected(format!("{} is not hex", x)));
+        let signature = SchnorrSignature { r: number(r)?, s: number(s)? };
+        // The nonce is used up even if the signature fails, so each one gets a single guess
+        match self.challenges.redeem(nonce) {
+            Some(issued_to) if issued_to == name => (),
+            _ => return Err(AuthError::Rejected("unknown or expired challenge".to_string())),
+        }
+        let key = match &self.identities[name].credential {
+            Credential::Key(key) => key,
+            Credential::Token(_) => unreachable!("Challenges are only issued to identities with keys"),
+        };
+        if !signature.verify(&key.params, &key.y, &challenge_message(nonce)) {
+            return Err(AuthError::Rejected("signature does not verify".to_string()));
+        }
+        Ok(name.to_string())
+    }
+}
+
+// Digest a puzzle's solution is judged by
+pub fn work_digest(nonce: &str, solution: u64) -> [u8; 32] {
+    Sha256Hasher::hash(&[b"zklib work ", nonce.as_bytes(), &solution.to_be_bytes()])
+}
+
+fn leading_zero_bits(digest: &[u8; 32]) -> u32 {
+    let zero_bytes = digest.iter().take_while(|&&x| x == 0).count();
+    (zero_bytes as u32) * 8 + digest.get(zero_bytes).map_or(0, |x| x.leading_zeros())
+}
+
+// Finds the smallest solution to the puzzle, which takes about 2^bits hashes
+pub fn solve_puzzle(nonce: &str, bits: u32) -> u64 {
+    (0..).find(|&solution| leading_zero_bits(&work_digest(nonce, solution)) >= bits).unwrap()
+}
+
+// The X-Zklib-Work header presenting a solution
+pub fn work_header(nonce: &str, solution: u64) -> String {
+    format!("{} {}", nonce, solution)
+}
+
+// Puzzles a server has issued and not yet seen solved
+pub struct Puzzles {
+    pub bits: u32,
+    issued: Nonces<()>,
+}
+
+impl Puzzles {
+    pub fn new(bits: u32) -> Puzzles {
+        Puzzles { bits, issued: Nonces::new() }
+    }
+
+    // Issues a nonce to solve to the client at the address
+    pub fn issue(&mut self, client: IpAddr) -> Result<String, String> {
+        self.issued.issue(client, ()).ok_or_else(|| "Too many puzzles outstanding, try again later".to_string())
+    }
+
+    // Checks the X-Zklib-Work header, using up its nonce
+    pub fn redeem(&mut self, work: Option<&str>) -> Result<(), String> {
+        let work = work.ok_or_else(|| format!("Solve a puzzle of {} bits first", self.bits))?;
+        let (nonce, solution) = match work.split_once(' ').map(|(nonce, solution)| (nonce, solution.parse::<u64>())) {
+            Some((nonce, Ok(solution))) => (nonce, solution),
+            _ => return Err("Expected a nonce and a solution".to_string()),
+        };
+        // The nonce is used up even if the solution is wrong, so each one gets a single guess
+        if self.issued.redeem(nonce).is_none() {
+            return Err("Unknown or expired puzzle".to_string());
+        }
+        if leading_zero_bits(&work_digest(nonce, solution)) < self.bits {
+            return Err(format!("Solution does not have {} leading zero bits", self.bits));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_handshake() {
+    let params = SchnorrParameters::generate(64);
+    let key = SchnorrKeyPair::generate(&params);
+    let policy = Policy { protocols: Some(vec!["gi".to_string()]), max_instance_bytes: Some(1000) };
+    let mut handshake = Handshake::new(vec![
+        Identity { name: "alice".to_string(), credential: Credential::Token(to_hex(&Sha256Hasher::hash(&[b"secret token"]))), policy: Policy::default() },
+        Identity { name: "bob".to_string(), credential: Credential::Key(key.public_key()), policy },
+    ]);
+    let client = IpAddr::from([127, 0, 0, 1]);
+    assert_eq!(handshake.authenticate(Some("Bearer secret token")).unwrap().name, "alice");
+    assert!(matches!(handshake.authenticate(Some("Bearer wrong token")), Err(AuthError::Rejected(_))));
+    assert_eq!(handshake.authenticate(None), Err(AuthError::Missing));
+
+    // A signed challenge works once, and only for the identity it was issued to
+    assert!(handshake.challenge("alice", client).is_err());
+    let nonce = handshake.challenge("bob", client).unwrap();
+    let header = signature_header("bob", &nonce, &key.sign(&challenge_message(&nonce)));
+    let bob = handshake.authenticate(Some(&header)).unwrap();
+    assert_eq!(bob.name, "bob");
+    assert_eq!(bob.authorize("gi", 1000), Ok(()));
+    assert!(matches!(bob.authorize("schnorr-signature", 10), Err(AuthError::Forbidden(_))));
+    assert!(matches!(bob.authorize("gi", 1001), Err(AuthError::Forbidden(_))));
+    assert!(matches!(handshake.authenticate(Some(&header)), Err(AuthError::Rejected(_))));
+    let nonce = handshake.challenge("bob", client).unwrap();
+    let forged = signature_header("bob", &nonce, &SchnorrKeyPair::generate(&params).sign(&challenge_message(&nonce)));
+    assert!(matches!(handshake.authenticate(Some(&forged)), Err(AuthError::Rejected(_))));
+}
+
+#[test]
+fn test_puzzles() {
+    assert_eq!(leading_zero_bits(&[0; 32]), 256);
+    let mut digest = [0; 32];
+    digest[1] = 0x10;
+    assert_eq!(leading_zero_bits(&digest), 11);
+
+    let client = IpAddr::from([127, 0, 0, 1]);
+    let mut puzzles = Puzzles::new(12);
+    let nonce = puzzles.issue(client).unwrap();
+    let solution = solve_puzzle(&nonce, 12);
+    assert!(leading_zero_bits(&work_digest(&nonce, solution)) >= 12);
+    assert!(puzzles.redeem(None).is_err());
+    assert!(puzzles.redeem(Some(&nonce)).is_err());
+    assert_eq!(puzzles.redeem(Some(&work_header(&nonce, solution))), Ok(()));
+    // A solution is only good once, and a wrong one uses up its puzzle
+    assert!(puzzles.redeem(Some(&work_header(&nonce, solution))).is_err());
+    let nonce = puzzles.issue(client).unwrap();
+    let wrong = (0..).find(|&x| leading_zero_bits(&work_digest(&nonce, x)) < 12).unwrap();
+    assert!(puzzles.redeem(Some(&work_header(&nonce, wrong))).is_err());
+    assert!(puzzles.redeem(Some(&work_header(&nonce, solve_puzzle(&nonce, 12)))).is_err());
+}
+
+#[test]
+fn test_nonce_limits() {
+    let honest = IpAddr::from([127, 0, 0, 1]);
+    let mut nonces = Nonces::new();
+    let kept = nonces.issue(honest, 0).unwrap();
+    // A client asking for more than its share only drops its own oldest nonces
+    let flood: Vec<String> = (0..2 * MAX_CHALLENGES_PER_CLIENT).map(|i| nonces.issue(IpAddr::from([10, 0, 0, 1]), i).unwrap()).collect();
+    assert_eq!(nonces.issued.len(), MAX_CHALLENGES_PER_CLIENT + 1);
+    assert_eq!(nonces.redeem(&flood[0]), None);
+    assert_eq!(nonces.redeem(&flood[flood.len() - 1]), Some(flood.len() - 1));
+    assert_eq!(nonces.redeem(&kept), Some(0));
+    assert_eq!(nonces.redeem(&kept), None);
+
+    // Clients at many addresses can use up the rest, after which nobody gets a new one but none are dropped
+    let kept = nonces.issue(honest, 0).unwrap();
+    let mut i = 0u32;
+    while nonces.issued.len() < MAX_CHALLENGES {
+        nonces.issue(IpAddr::from(std::net::Ipv4Addr::from(0x0b00_0000 + i / 4)), 0).unwrap();
+        i += 1;
+    }
+    assert_eq!(nonces.issue(IpAddr::from([12, 0, 0, 1]), 0), None);
+    assert_eq!(nonces.redeem(&kept), Some(0));
+    assert!(nonces.issue(honest, 0).is_some());
+}
@@ -0,0 +1,142 @@
+use std::fmt::Debug;
+use std::sync::OnceLock;
+use sha2::{Digest, Sha256};
+use crate::field::Fp;
+
+// ************ Hash function implementation ************
+
+// Commitments, Merkle trees, and challenge transcripts only need a hash from byte strings to 32-byte
+// digests, so each can use any Hasher, with SHA-256 by default. Protocols whose verifiers are themselves
+// arithmetized, such as recursive STARKs, want a hash that is cheap to express over the field instead,
+// which Poseidon is.
+
+pub trait Hasher: Clone + Copy + Debug + Default + PartialEq + Eq {
+    // Digest of the concatenation of the parts
+    fn hash(parts: &[&[u8]]) -> [u8; 32];
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sha256Hasher;
+
+impl Hasher for Sha256Hasher {
+    fn hash(parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().into()
+    }
+}
+
+// ************ Poseidon implementation ************
+
+// Poseidon over the field of field.rs, as a sponge on a state of 12 elements that absorbs 8 elements at a
+// time and keeps 4 as capacity. Each round adds round constants, raises elements to the 7th power, which
+// is a permutation since gcd(7, p - 1) = 1, and multiplies the state by an MDS matrix. The first and last
+// 4 rounds apply the power to every element and the 22 rounds between only to the first, following the
+// parameters Plonky2 uses for this field for 128-bit security.
+//
+// The round constants are derived from SHA-256 of a fixed label and the MDS matrix is the Cauchy matrix
+// 1 / (i + j + 12), so digests don't match other Poseidon implementations' test vectors.
+
+const WIDTH: usize = 12;
+const RATE: usize = 8;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 22;
+
+struct PoseidonConstants {
+    round_constants: Vec<[Fp; WIDTH]>,
+    mds: [[Fp; WIDTH]; WIDTH],
+}
+
+fn constants() -> &'static PoseidonConstants {
+    static CONSTANTS: OnceLock<PoseidonConstants> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let round_constants = (0..FULL_ROUNDS + PARTIAL_ROUNDS).map(|round| {
+            let mut constants = [Fp::ZERO; WIDTH];
+            for (i, c) in constants.iter_mut().enumerate() {
+                let seed = Sha256Hasher::hash(&[b"zklib poseidon", &(round as u64).to_be_bytes(), &(i as u64).to_be_bytes()]);
+                *c = Fp::from_challenge(&seed);
+            }
+            constants
+        }).collect();
+        let mut mds = [[Fp::ZERO; WIDTH]; WIDTH];
+        for (i, row) in mds.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = Fp::new((i + j + WIDTH) as u64).inverse().unwrap();
+            }
+        }
+        PoseidonConstants { round_constants, mds }
+    })
+}
+
+pub fn poseidon_permutation(state: &mut [Fp; WIDTH]) {
+    let constants = constants();
+    for (round, round_constants) in constants.round_constants.iter().enumerate() {
+        state.iter_mut().zip(round_constants).for_each(|(x, &c)| *x += c);
+        let partial = (FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS).contains(&round);
+        let sboxes = if partial { 1 } else { WIDTH };
+        state[..sboxes].iter_mut().for_each(|x| *x = x.pow(7));
+        let previous = *state;
+        for (x, row) in state.iter_mut().zip(&constants.mds) {
+            *x = row.iter().zip(&previous).fold(Fp::ZERO, |acc, (&m, &y)| acc + m * y);
+        }
+    }
+}
+
+// Hashes field elements to 4 field elements, padding the input with a 1 and then zeros to a multiple of
+// the rate so that inputs of different lengths never collide
+pub fn poseidon_hash(elements: &[Fp]) -> [Fp; 4] {
+    let mut padded = elements.to_vec();
+    padded.push(Fp::ONE);
+    padded.resize(padded.len().div_ceil(RATE) * RATE, Fp::ZERO);
+    let mut state = [Fp::ZERO; WIDTH];
+    for block in padded.chunks(RATE) {
+        state.iter_mut().zip(block).for_each(|(x, &y)| *x += y);
+        poseidon_permutation(&mut state);
+    }
+    [state[0], state[1], state[2], state[3]]
+}
+
+// Poseidon on bytes, packing 7 bytes into each field element so that every packing is below the modulus.
+// The digest is the 4 output elements in Fp::to_bytes form.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoseidonHasher;
+
+impl Hasher for PoseidonHasher {
+    fn hash(parts: &[&[u8]]) -> [u8; 32] {
+        let mut bytes = parts.concat();
+        // A 1 byte and zeros make the packing injective
+        bytes.push(1);
+        bytes.resize(bytes.len().div_ceil(7) * 7, 0);
+        let elements: Vec<Fp> = bytes.chunks(7).map(|chunk| {
+            let mut limb = [0u8; 8];
+            limb[..7].copy_from_slice(chunk);
+            Fp::new(u64::from_le_bytes(limb))
+        }).collect();
+        let mut digest = [0u8; 32];
+        for (chunk, x) in digest.chunks_mut(8).zip(poseidon_hash(&elements)) {
+            chunk.copy_from_slice(&x.to_bytes());
+        }
+        digest
+    }
+}
+
+#[test]
+fn test_poseidon() {
+    let input: Vec<Fp> = (0..10).map(Fp::new).collect();
+    let digest = poseidon_hash(&input);
+    assert_eq!(poseidon_hash(&input), digest);
+    // Padding separates inputs that differ only in trailing zeros
+    assert_ne!(poseidon_hash(&input[..9]), poseidon_hash(&[&input[..9], &[Fp::ZERO]].concat()));
+    assert_ne!(poseidon_hash(&[]), poseidon_hash(&[Fp::ZERO]));
+    let mut state = [Fp::ZERO; WIDTH];
+    poseidon_permutation(&mut state);
+    assert_ne!(state, [Fp::ZERO; WIDTH]);
+}
+
+#[test]
+fn test_hashers() {
+    assert_eq!(Sha256Hasher::hash(&[b"zk", b"lib"]), <[u8; 32]>::from(Sha256::digest(b"zklib")));
+    assert_eq!(PoseidonHasher::hash(&[b"zk", b"lib"]), PoseidonHasher::hash(&[b"zklib"]));
+    assert_ne!(PoseidonHasher::hash(&[b"zklib"]), PoseidonHasher::hash(&[b"zklib\0"]));
+    assert_ne!(PoseidonHasher::hash(&[]), Sha256Hasher::hash(&[]));
+}
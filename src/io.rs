@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::Path;
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Serialize};
+use crate::graph::{Graph, GraphPair, GraphStorage};
+
+// ************ Graph file implementation ************
+
+// Graphs and GI/GNI instances are saved to and loaded from files in one of several formats, chosen by the
+// file's extension, so that the command line and the experiment harness can run on instances generated
+// elsewhere. The serde formats store a graph as its vertex count and edge list, and are checked like
+// deserialized messages, including the MAX_DESERIALIZED_VERTICES and MAX_DESERIALIZED_EDGES limits. Edge
+// lists are plain text for graphs from other tools and only have the much larger limits of Graph::try_new.
+//
+// An edge list holds one or more graphs. Each starts with a line holding its vertex count, followed by a
+// line "a b" for every edge from a to b. Blank lines and lines starting with # are ignored. An instance
+// is a file of two graphs, g0 followed by g1.
+//
+// DIMACS files, the format of the bliss and nauty benchmark collections, hold a single undirected graph:
+// a line "p edge <vertices> <edges>" followed by a line "e u v" for every edge, numbering vertices from 1.
+// Lines starting with c are comments. Vertex colors, on lines starting with n, are ignored, so colored
+// benchmarks are loaded as their uncolored graphs. Undirected graphs are stored with every edge in both
+// directions, and DIMACS graphs in compressed sparse rows since benchmark graphs can be large.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    // .json
+    Json,
+    // .bin or .bincode
+    Bincode,
+    // .msgpack or .mp
+    MessagePack,
+    // .txt, .edges, or .el
+    EdgeList,
+    // .dimacs, .col, or .bliss
+    Dimacs,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Format, String> {
+        let extension = path.extension().and_then(|x| x.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => Ok(Format::Json),
+            Some("bin") | Some("bincode") => Ok(Format::Bincode),
+            Some("msgpack") | Some("mp") => Ok(Format::MessagePack),
+            Some("txt") | Some("edges") | Some("el") => Ok(Format::EdgeList),
+            Some("dimacs") | Some("col") | Some("bliss") => Ok(Format::Dimacs),
+            _ => Err(format!("can't tell the format of {} from its extension, expected .json, .bin, .msgpack, .txt, or .dimacs", path.display())),
+        }
+    }
+
+    // Encodes a value in one of the serde formats
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::to_vec(value).map_err(|e| e.to_string()),
+            Format::EdgeList | Format::Dimacs => Err(format!("only graphs can be written as {:?}", self)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Bincode => bincode::deserialize(bytes).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::EdgeList | Format::Dimacs => Err(format!("only graphs can be read from {:?}", self)),
+        }
+    }
+}
+
+pub fn load_graph(path: &Path) -> Result<Graph, String> {
+    let format = Format::from_path(path)?;
+    let bytes = read(path)?;
+    let graph = match format {
+        Format::EdgeList => {
+            let mut graphs = read_edge_list(&text(&bytes)?)?;
+            check_graph_count(&graphs, 1)?;
+            graphs.remove(0)
+        }
+        Format::Dimacs => read_dimacs(&text(&bytes)?)?,
+        _ => format.decode(&bytes)?,
+    };
+    Ok(graph)
+}
+
+pub fn save_graph(path: &Path, graph: &Graph) -> Result<(), String> {
+    let bytes = match Format::from_path(path)? {
+        Format::EdgeList => write_edge_list(&[graph]).into_bytes(),
+        Format::Dimacs => write_dimacs(graph)?.into_bytes(),
+        format => format.encode(graph)?,
+    };
+    write(path, &bytes)
+}
+
+pub fn load_instance(path: &Path) -> Result<GraphPair, String> {
+    let format = Format::from_path(path)?;
+    let bytes = read(path)?;
+    let instance = match format {
+        Format::EdgeList => {
+            let graphs = read_edge_list(&text(&bytes)?)?;
+            check_graph_count(&graphs, 2)?;
+            let (g0, g1) = graphs.into_iter().collect_tuple().unwrap();
+            GraphPair { g0, g1 }
+        }
+        Format::Dimacs => return Err(format!("{} holds a single graph, not an instance", path.display())),
+        _ => format.decode(&bytes)?,
+    };
+    Ok(instance)
+}
+
+pub fn save_instance(path: &Path, instance: &GraphPair) -> Result<(), String> {
+    let bytes = match Format::from_path(path)? {
+        Format::EdgeList => write_edge_list(&[&instance.g0, &instance.g1]).into_bytes(),
+        Format::Dimacs => return Err(format!("{} can only hold a single graph, not an instance", path.display())),
+        format => format.encode(instance)?,
+    };
+    write(path, &bytes)
+}
+
+// Parses the graphs of an edge list, reporting the line of the first problem
+pub fn read_edge_list(text: &str) -> Result<Vec<Graph>, String> {
+    let mut graphs = Vec::new();
+    let mut current: Option<(u32, Vec<(u32, u32)>)> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let numbers: Vec<u32> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>()
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        match (numbers.as_slice(), &mut current) {
+            (&[n], _) => {
+                if let Some((n, edges)) = current.take() {
+                    graphs.push(Graph::try_new(n, edges)?);
+                }
+                current = Some((n, Vec::new()));
+            }
+            (&[a, b], Some((_, edges))) => edges.push((a, b)),
+            (&[_, _], None) => return Err(format!("line {}: edge before the vertex count of its graph", i + 1)),
+            _ => return Err(format!("line {}: expected a vertex count or an edge, found {} numbers", i + 1, numbers.len())),
+        }
+    }
+    if let Some((n, edges)) = current {
+        graphs.push(Graph::try_new(n, edges)?);
+    }
+    Ok(graphs)
+}
+
+pub fn write_edge_list(graphs: &[&Graph]) -> String {
+    let mut text = String::new();
+    for graph in graphs {
+        text.push_str(&format!("{}\n", graph.n));
+        graph.edges().sorted().for_each(|(a, b)| text.push_str(&format!("{} {}\n", a, b)));
+    }
+    text
+}
+
+// Parses a DIMACS graph, reporting the line of the first problem
+pub fn read_dimacs(text: &str) -> Result<Graph, String> {
+    let mut n = None;
+    let mut edges = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |e: String| format!("line {}: {}", i + 1, e);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] | ["c", ..] | ["n", ..] => {}
+            ["p", _, vertices, _] => {
+                if n.is_some() {
+                    return Err(error("second problem line".to_string()));
+                }
+                n = Some(vertices.parse::<u32>().map_err(|e| error(e.to_string()))?);
+            }
+            ["e", u, v] => {
+                let n = n.ok_or_else(|| error("edge before the problem line".to_string()))?;
+                let (u, v) = (parse_dimacs_vertex(u, n).map_err(error)?, parse_dimacs_vertex(v, n).map_err(error)?);
+                edges.extend([(u, v), (v, u)]);
+            }
+            _ => return Err(error(format!("unrecognized line {:?}", line))),
+        }
+    }
+    let n = n.ok_or("missing the problem line \"p edge <vertices> <edges>\"")?;
+    Graph::try_with_storage(n, edges, GraphStorage::Csr)
+}
+
+// Vertices are numbered from 1 in DIMACS files and from 0 in graphs
+fn parse_dimacs_vertex(field: &str, n: u32) -> Result<u32, String> {
+    match field.parse::<u32>().map_err(|e| e.to_string())? {
+        v if (1..=n).contains(&v) => Ok(v - 1),
+        v => Err(format!("vertex {} is outside 1 to {}", v, n)),
+    }
+}
+
+// Writes an undirected graph, one whose edges all go both ways, as DIMACS
+pub fn write_dimacs(graph: &Graph) -> Result<String, String> {
+    if let Some((a, b)) = graph.edges().find(|&(a, b)| !graph.has_edge(b, a)) {
+        return Err(format!("DIMACS graphs are undirected, but the edge from {} to {} has no reverse", a, b));
+    }
+    let edges: Vec<(u32, u32)> = graph.edges().filter(|&(a, b)| a <= b).sorted().collect();
+    let mut text = format!("p edge {} {}\n", graph.n, edges.len());
+    edges.iter().for_each(|&(a, b)| text.push_str(&format!("e {} {}\n", a + 1, b + 1)));
+    Ok(text)
+}
+
+// Loads every DIMACS file in a directory, by extension, with each graph's file name, in order of name
+pub fn load_dataset(dir: &Path) -> Result<Vec<(String, Graph)>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| format!("failed to read {}: {}", dir.display(), e))?.path();
+        if path.is_file() && Format::from_path(&path) == Ok(Format::Dimacs) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.iter().map(|path| {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let graph = read_dimacs(&text(&read(path)?)?).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok((name, graph))
+    }).collect()
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+fn text(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+}
+
+fn check_graph_count(graphs: &[Graph], expected: usize) -> Result<(), String> {
+    if graphs.len() == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {} graphs, found {}", expected, graphs.len()))
+    }
+}
+
+#[test]
+fn test_graph_formats() {
+    let dir = std::env::temp_dir().join(format!("zklib-io-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let instance = GraphPair::sample_hard_instance(6);
+    for extension in ["json", "bin", "msgpack", "txt"] {
+        let path = dir.join(format!("instance.{}", extension));
+        save_instance(&path, &instance).unwrap();
+        assert_eq!(load_instance(&path).unwrap(), instance);
+        let path = dir.join(format!("graph.{}", extension));
+        save_graph(&path, &instance.g1).unwrap();
+        assert_eq!(load_graph(&path).unwrap(), instance.g1);
+    }
+    // A graph is not an instance
+    assert!(load_instance(&dir.join("graph.txt")).is_err());
+    assert!(load_instance(&dir.join("graph.json")).is_err());
+    assert!(Format::from_path(&dir.join("graph.csv")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_edge_list() {
+    let graphs = read_edge_list("# a triangle\n3\n0 1\n1 2\n2 0\n\n2\n").unwrap();
+    assert_eq!(graphs, vec![Graph::new(3, vec![(0, 1), (1, 2), (2, 0)]), Graph::new(2, Vec::new())]);
+    assert!(read_edge_list("0 1\n").unwrap_err().contains("line 1"));
+    assert!(read_edge_list("2\n0 x\n").unwrap_err().contains("line 2"));
+    assert!(read_edge_list("2\n0 1 2\n").is_err());
+    // Edges are checked like any other graph's
+    assert!(read_edge_list("2\n0 2\n").is_err());
+}
+
+#[test]
+fn test_read_dimacs() {
+    let graph = read_dimacs("c a path\np edge 3 2\nn 1 5\ne 1 2\ne 2 3\n").unwrap();
+    assert_eq!(graph, Graph::new(3, vec![(0, 1), (1, 0), (1, 2), (2, 1)]));
+    assert_eq!(read_dimacs(&write_dimacs(&graph).unwrap()).unwrap(), graph);
+    assert!(read_dimacs("e 1 2\n").unwrap_err().contains("line 1"));
+    assert!(read_dimacs("p edge 3 1\ne 0 1\n").unwrap_err().contains("line 2"));
+    assert!(read_dimacs("p edge 3 1\ne 1 4\n").is_err());
+    assert!(read_dimacs("c no problem line\n").is_err());
+    assert!(write_dimacs(&Graph::new(2, vec![(0, 1)])).is_err());
+}
+
+#[test]
+fn test_load_dataset() {
+    let dir = std::env::temp_dir().join(format!("zklib-dataset-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let instance = GraphPair::sample_hard_instance(6);
+    save_graph(&dir.join("b.dimacs"), &instance.g1).unwrap();
+    save_graph(&dir.join("a.bliss"), &instance.g0).unwrap();
+    // Files in other formats are skipped
+    save_graph(&dir.join("c.json"), &instance.g0).unwrap();
+    let dataset = load_dataset(&dir).unwrap();
+    assert_eq!(dataset, vec![("a.bliss".to_string(), instance.g0), ("b.dimacs".to_string(), instance.g1)]);
+    fs::remove_dir_all(&dir).unwrap();
+}
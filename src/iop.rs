@@ -0,0 +1,552 @@
+use std::fmt;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::merkle::{MerklePath, MerkleTree};
+use crate::randomness::checked_thread_rng;
+use crate::transcript::{ChallengeTranscript, ProofContext};
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Interactive oracle proof implementation ************
+
+// In an interactive oracle proof (IOP), the prover's messages are oracles: long strings the verifier
+// doesn't read in full, but queries at a few positions once the interaction is over. Each round, the
+// verifier sends a fresh public challenge and the prover answers with an oracle. After the last round,
+// the verifier picks positions with fresh randomness, reads the symbols there, and decides. FRI, STARKs,
+// and sumcheck-based protocols are all IOPs, and they only describe these rounds and queries.
+//
+// An IOP becomes a proof by committing to each oracle with a Merkle tree and opening the queried symbols
+// with their paths. iop_to_interactive runs it as an interactive proof between a Prover and a Verifier
+// like any other protocol, and iop_to_nizk derives every challenge and the query randomness from a
+// transcript bound to a proof context instead (Fiat-Shamir), giving a non-interactive proof.
+//
+// Verifiers read in batches. A non-adaptive verifier picks every position up front, while an adaptive one
+// picks each batch after reading the symbols of the batches before it, which can save reads, e.g. by
+// stopping at the first bad symbol. Some analyses only hold for non-adaptive verifiers, so both compilers
+// take the mode, record the schedule of batches, and default to non-adaptive queries.
+
+// One oracle, as a list of symbols
+pub type Oracle = Vec<Vec<u8>>;
+
+// A position the verifier reads: the index of a symbol in one of the prover's oracles
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Query {
+    pub oracle: usize,
+    pub index: usize,
+}
+
+// The verifier of a public-coin IOP. Its challenges are uniformly random, so it only chooses what to read
+// and whether to accept.
+pub trait Iop {
+    // Number of oracles the prover sends, each after a fresh challenge
+    fn rounds(&self) -> usize;
+
+    // Positions to read, given the challenges, the lengths of the oracles, and fresh randomness
+    fn queries(&self, challenges: &[[u8; 32]], lengths: &[usize], randomness: &[u8; 32]) -> Vec<Query>;
+
+    // Decides from the symbols at the queried positions, in the order of the queries
+    fn decide(&self, challenges: &[[u8; 32]], lengths: &[usize], queries: &[Query], answers: &[Vec<u8>]) -> bool;
+
+    // For adaptive queries, positions to read in the next batch given fresh randomness and the symbols
+    // read so far, or none once the verifier has read enough. By default, the first batch is every query.
+    fn next_queries(&self, challenges: &[[u8; 32]], lengths: &[usize], randomness: &[u8; 32], queried: &[Query], _answers: &[Vec<u8>]) -> Vec<Query> {
+        if queried.is_empty() { self.queries(challenges, lengths, randomness) } else { Vec::new() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueryMode {
+    // Every position is picked with Iop::queries before any symbol is read
+    NonAdaptive,
+    // Positions are picked in batches with Iop::next_queries, each after reading the batches before it
+    Adaptive,
+}
+
+impl fmt::Display for QueryMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryMode::NonAdaptive => write!(f, "non-adaptive"),
+            QueryMode::Adaptive => write!(f, "adaptive"),
+        }
+    }
+}
+
+pub trait IopProver {
+    // Returns the oracle for the given round, starting from 0, after that round's challenge
+    fn oracle(&mut self, round: usize, challenge: &[u8; 32]) -> Oracle;
+}
+
+// Derives count indices below length from query randomness, for Iop::queries
+pub fn query_indices(randomness: &[u8; 32], count: usize, length: usize) -> Vec<usize> {
+    if length == 0 {
+        return Vec::new();
+    }
+    (0..count as u64).map(|i| {
+        let mut hasher = Sha256::new();
+        hasher.update(randomness);
+        hasher.update(i.to_be_bytes());
+        let digest = hasher.finalize();
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        // Reducing 128 bits keeps the bias below 2^-64
+        (u128::from_be_bytes(bytes) % length as u128) as usize
+    }).collect()
+}
+
+// Root and number of symbols of a committed oracle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OracleCommitment {
+    pub root: [u8; 32],
+    pub length: usize,
+}
+
+// A queried symbol with its path in the oracle's Merkle tree
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Answer {
+    pub symbol: Vec<u8>,
+    pub path: MerklePath,
+}
+
+fn commit_oracle(oracle: &Oracle) -> (MerkleTree, OracleCommitment) {
+    let tree = MerkleTree::new(oracle);
+    let commitment = OracleCommitment { root: tree.root(), length: oracle.len() };
+    (tree, commitment)
+}
+
+fn open(oracles: &[(Oracle, MerkleTree)], queries: &[Query]) -> Vec<Answer> {
+    queries.iter().map(|query| {
+        let (oracle, tree) = &oracles[query.oracle];
+        Answer { symbol: oracle[query.index].clone(), path: tree.open(query.index) }
+    }).collect()
+}
+
+// Positions for the next batch in the given mode, or none once the verifier has read enough
+fn next_batch<I: Iop>(iop: &I, mode: QueryMode, challenges: &[[u8; 32]], lengths: &[usize], randomness: &[u8; 32], queried: &[Query], answers: &[Vec<u8>]) -> Vec<Query> {
+    match mode {
+        QueryMode::NonAdaptive if queried.is_empty() => iop.queries(challenges, lengths, randomness),
+        QueryMode::NonAdaptive => Vec::new(),
+        QueryMode::Adaptive => iop.next_queries(challenges, lengths, randomness, queried, answers),
+    }
+}
+
+// Checks the answers to a batch of queries against the commitments, returning their symbols
+fn check_batch(commitments: &[OracleCommitment], queries: &[Query], answers: &[Answer]) -> Result<Vec<Vec<u8>>, String> {
+    check_queries(queries, commitments)?;
+    if answers.len() != queries.len() {
+        return Err(format!("Expected {} answers. Found {} answers.", queries.len(), answers.len()));
+    }
+    for (i, (query, answer)) in queries.iter().zip(answers).enumerate() {
+        let commitment = &commitments[query.oracle];
+        if !answer.path.verify(&commitment.root, commitment.length, query.index, &answer.symbol) {
+            return Err(format!("Answer {} does not open oracle {} at {}", i, query.oracle, query.index));
+        }
+    }
+    Ok(answers.iter().map(|x| x.symbol.clone()).collect())
+}
+
+// Asks the IOP to decide from every symbol read, describing why it rejected otherwise
+fn decide<I: Iop>(iop: &I, challenges: &[[u8; 32]], commitments: &[OracleCommitment], queries: &[Query], symbols: &[Vec<u8>]) -> Result<(), String> {
+    let lengths: Vec<usize> = commitments.iter().map(|x| x.length).collect();
+    if !iop.decide(challenges, &lengths, queries, symbols) {
+        return Err("IOP verifier rejected the queried symbols".to_string());
+    }
+    Ok(())
+}
+
+fn check_queries(queries: &[Query], commitments: &[OracleCommitment]) -> Result<(), String> {
+    match queries.iter().find(|query| commitments.get(query.oracle).is_none_or(|x| query.index >= x.length)) {
+        Some(query) => Err(format!("query of symbol {} in oracle {} is out of range", query.index, query.oracle)),
+        None => Ok(()),
+    }
+}
+
+// ************ Interactive IOP implementation ************
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum IopProverMessage {
+    // Commitment to the oracle for the round
+    Oracle(OracleCommitment),
+    // Symbols at the queried positions
+    Answers(Vec<Answer>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum IopVerifierMessage {
+    // Public challenge for the next round
+    Challenge([u8; 32]),
+    // Positions to open once every oracle is committed, or none once the verifier has decided
+    Queries(Vec<Query>),
+}
+
+pub struct IopInteractiveProver<'a, P: IopProver> {
+    // Keep track of round number
+    pub r: u32,
+    // Oracles sent so far along with their Merkle trees
+    pub oracles: Vec<(Oracle, MerkleTree)>,
+    pub rounds: usize,
+    pub prover: &'a mut P,
+}
+
+impl<P: IopProver> Prover for IopInteractiveProver<'_, P> {
+    type ProverMessage = IopProverMessage;
+    type VerifierMessage = IopVerifierMessage;
+
+    fn validate(&self, msg: &IopVerifierMessage) -> Result<(), String> {
+        match msg {
+            IopVerifierMessage::Queries(queries) => {
+                let commitments: Vec<OracleCommitment> = self.oracles.iter().map(|(oracle, tree)| OracleCommitment { root: tree.root(), length: oracle.len() }).collect();
+                check_queries(queries, &commitments)
+            },
+            IopVerifierMessage::Challenge(_) => Ok(()),
+        }
+    }
+
+    fn handle(&mut self, msg: &IopVerifierMessage) -> (IopProverMessage, bool) {
+        self.r += 1;
+        match msg {
+            // While rounds remain, the prover commits to the oracle for each challenge
+            IopVerifierMessage::Challenge(challenge) if self.oracles.len() < self.rounds => {
+                let oracle = self.prover.oracle(self.oracles.len(), challenge);
+                let (tree, commitment) = commit_oracle(&oracle);
+                self.oracles.push((oracle, tree));
+                (IopProverMessage::Oracle(commitment), false)
+            },
+            // Once every oracle is committed, the prover opens the queried symbols of each batch
+            IopVerifierMessage::Queries(queries) if self.oracles.len() == self.rounds && !queries.is_empty() => (IopProverMessage::Answers(open(&self.oracles, queries)), false),
+            // After the verifier decides, the prover sends a message to terminate the interaction
+            _ => (IopProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct IopInteractiveVerifier<'a, I: Iop> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub challenges: Vec<[u8; 32]>,
+    pub commitments: Vec<OracleCommitment>,
+    // Batches of queries sent so far, and the symbols read from them
+    pub schedule: Vec<Vec<Query>>,
+    pub answers: Vec<Vec<u8>>,
+    pub mode: QueryMode,
+    pub iop: &'a I,
+}
+
+impl<I: Iop> IopInteractiveVerifier<'_, I> {
+    fn challenge(&mut self) -> IopVerifierMessage {
+        let challenge: [u8; 32] = checked_thread_rng().gen();
+        self.challenges.push(challenge);
+        IopVerifierMessage::Challenge(challenge)
+    }
+
+    // Every query sent so far, in order
+    pub fn queries(&self) -> Vec<Query> {
+        self.schedule.concat()
+    }
+
+    // Sends the next batch of queries, or decides once there are none
+    fn query(&mut self) -> (IopVerifierMessage, bool) {
+        let lengths: Vec<usize> = self.commitments.iter().map(|x| x.length).collect();
+        let batch = next_batch(self.iop, self.mode, &self.challenges, &lengths, &checked_thread_rng().gen(), &self.queries(), &self.answers);
+        if !batch.is_empty() {
+            self.schedule.push(batch.clone());
+            return (IopVerifierMessage::Queries(batch), false);
+        }
+        let accept = decide(self.iop, &self.challenges, &self.commitments, &self.queries(), &self.answers);
+        if let Err(e) = &accept {
+            narrate!("Rejecting proof: {}.", e);
+        }
+        (IopVerifierMessage::Queries(Vec::new()), accept.is_ok())
+    }
+}
+
+impl<I: Iop> Verifier for IopInteractiveVerifier<'_, I> {
+    type ProverMessage = IopProverMessage;
+    type VerifierMessage = IopVerifierMessage;
+
+    fn init(&mut self) -> IopVerifierMessage {
+        narrate!("Initializing IOP with {} rounds.", self.iop.rounds());
+        self.challenge()
+    }
+
+    fn validate(&self, msg: &IopProverMessage) -> Result<(), String> {
+        match (self.commitments.len() < self.iop.rounds(), msg) {
+            (true, IopProverMessage::Oracle(_)) => Ok(()),
+            (true, _) => Err("expected an oracle commitment".to_string()),
+            (false, IopProverMessage::Answers(answers)) => {
+                let expected = self.schedule.last().map_or(0, |batch| batch.len());
+                if answers.len() == expected { Ok(()) } else { Err(format!("expected {} answers, found {}", expected, answers.len())) }
+            },
+            (false, _) => Err("expected answers".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &IopProverMessage) -> (IopVerifierMessage, bool) {
+        self.r += 1;
+        match msg {
+            IopProverMessage::Oracle(commitment) => {
+                self.commitments.push(*commitment);
+                if self.commitments.len() < self.iop.rounds() {
+                    (self.challenge(), false)
+                } else {
+                    // After the last oracle, the verifier picks the positions to read
+                    self.query()
+                }
+            },
+            IopProverMessage::Answers(answers) => {
+                let batch = self.schedule.last().cloned().unwrap_or_default();
+                match check_batch(&self.commitments, &batch, answers) {
+                    Ok(symbols) => {
+                        self.answers.extend(symbols);
+                        self.query()
+                    },
+                    Err(e) => {
+                        narrate!("Rejecting proof: {}.", e);
+                        (IopVerifierMessage::Queries(Vec::new()), false)
+                    },
+                }
+            },
+            IopProverMessage::Done => panic!("Prover ended the interaction on round {}!", self.r),
+        }
+    }
+}
+
+// Compiles an IOP into an interactive proof, in which the prover commits to its oracles with Merkle trees
+// and opens the queried symbols
+pub fn iop_to_interactive<'a, I: Iop, P: IopProver>(iop: &'a I, prover: &'a mut P) -> (IopInteractiveProver<'a, P>, IopInteractiveVerifier<'a, I>) {
+    iop_to_interactive_with_mode(iop, prover, QueryMode::NonAdaptive)
+}
+
+pub fn iop_to_interactive_with_mode<'a, I: Iop, P: IopProver>(iop: &'a I, prover: &'a mut P, mode: QueryMode) -> (IopInteractiveProver<'a, P>, IopInteractiveVerifier<'a, I>) {
+    let rounds = iop.rounds();
+    let prover = IopInteractiveProver { r: 0, oracles: Vec::new(), rounds, prover };
+    let verifier = IopInteractiveVerifier { r: 0, challenges: Vec::new(), commitments: Vec::new(), schedule: Vec::new(), answers: Vec::new(), mode, iop };
+    (prover, verifier)
+}
+
+// ************ Non-interactive IOP implementation ************
+
+// Commitments to every oracle and the answers to the queries derived from them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IopProof {
+    // Context the proof was produced in
+    pub context: ProofContext,
+    // Mode the queries were derived in
+    pub mode: QueryMode,
+    pub commitments: Vec<OracleCommitment>,
+    // Answers to every batch of queries, in order
+    pub answers: Vec<Answer>,
+}
+
+// Derives the challenges and query randomness from a transcript that absorbs every commitment in turn,
+// followed by the query mode and every batch of queries along with the symbols read
+struct IopTranscript(ChallengeTranscript);
+
+impl IopTranscript {
+    fn absorb(&mut self, commitment: &OracleCommitment) {
+        self.0.append_message(b"oracle", &commitment.root);
+        self.0.append_message(b"length", &(commitment.length as u64).to_be_bytes());
+    }
+
+    fn absorb_batch(&mut self, queries: &[Query], symbols: &[Vec<u8>]) {
+        self.0.append_message(b"batch", &(queries.len() as u64).to_be_bytes());
+        for (query, symbol) in queries.iter().zip(symbols) {
+            self.0.append_message(b"query", &[(query.oracle as u64).to_be_bytes(), (query.index as u64).to_be_bytes()].concat());
+            self.0.append_message(b"answer", symbol);
+        }
+    }
+
+    fn challenge(&mut self, label: &[u8]) -> [u8; 32] {
+        let mut challenge = [0u8; 32];
+        self.0.challenge_bytes(label, &mut challenge);
+        challenge
+    }
+}
+
+// Compiles an IOP into a non-interactive proof bound to the context (Fiat-Shamir)
+pub fn iop_to_nizk<I: Iop, P: IopProver>(iop: &I, prover: &mut P, context: &ProofContext) -> IopProof {
+    iop_to_nizk_with_mode(iop, prover, context, QueryMode::NonAdaptive)
+}
+
+pub fn iop_to_nizk_with_mode<I: Iop, P: IopProver>(iop: &I, prover: &mut P, context: &ProofContext, mode: QueryMode) -> IopProof {
+    let mut transcript = IopTranscript(ChallengeTranscript::with_context(context));
+    let mut challenges = Vec::new();
+    let mut oracles = Vec::new();
+    let mut commitments = Vec::new();
+    for round in 0..iop.rounds() {
+        challenges.push(transcript.challenge(b"challenge"));
+        let oracle = prover.oracle(round, &challenges[round]);
+        let (tree, commitment) = commit_oracle(&oracle);
+        transcript.absorb(&commitment);
+        oracles.push((oracle, tree));
+        commitments.push(commitment);
+    }
+    let lengths: Vec<usize> = commitments.iter().map(|x| x.length).collect();
+    transcript.0.append_message(b"query mode", mode.to_string().as_bytes());
+    let (mut queried, mut symbols, mut answers) = (Vec::new(), Vec::new(), Vec::new());
+    loop {
+        let batch = next_batch(iop, mode, &challenges, &lengths, &transcript.challenge(b"queries"), &queried, &symbols);
+        if batch.is_empty() {
+            break;
+        }
+        let opened = open(&oracles, &batch);
+        let batch_symbols: Vec<Vec<u8>> = opened.iter().map(|x| x.symbol.clone()).collect();
+        transcript.absorb_batch(&batch, &batch_symbols);
+        queried.extend(batch);
+        symbols.extend(batch_symbols);
+        answers.extend(opened);
+    }
+    IopProof { context: context.clone(), mode, commitments, answers }
+}
+
+// Verifies a non-interactive proof with non-adaptive queries, rederiving its challenges and queries,
+// describing why it was rejected otherwise
+pub fn verify_iop_nizk<I: Iop>(iop: &I, proof: &IopProof, context: &ProofContext) -> Result<(), String> {
+    verify_iop_nizk_with_mode(iop, proof, context, QueryMode::NonAdaptive)
+}
+
+// Verifies a non-interactive proof like verify_iop_nizk, rejecting proofs whose queries weren't derived in the given mode
+pub fn verify_iop_nizk_with_mode<I: Iop>(iop: &I, proof: &IopProof, context: &ProofContext, mode: QueryMode) -> Result<(), String> {
+    proof.context.check(context)?;
+    if proof.mode != mode {
+        return Err(format!("Proof has {} queries, expected {} queries", proof.mode, mode));
+    }
+    if proof.commitments.len() != iop.rounds() {
+        return Err(format!("Expected {} oracles. Found {} oracles.", iop.rounds(), proof.commitments.len()));
+    }
+    let mut transcript = IopTranscript(ChallengeTranscript::with_context(context));
+    let mut challenges = Vec::new();
+    for commitment in &proof.commitments {
+        challenges.push(transcript.challenge(b"challenge"));
+        transcript.absorb(commitment);
+    }
+    let lengths: Vec<usize> = proof.commitments.iter().map(|x| x.length).collect();
+    transcript.0.append_message(b"query mode", mode.to_string().as_bytes());
+    let (mut queried, mut symbols, mut remaining) = (Vec::new(), Vec::new(), proof.answers.as_slice());
+    loop {
+        let batch = next_batch(iop, mode, &challenges, &lengths, &transcript.challenge(b"queries"), &queried, &symbols);
+        if batch.is_empty() {
+            break;
+        }
+        if remaining.len() < batch.len() {
+            return Err(format!("Proof answers {} queries, but the verifier asked for more", proof.answers.len()));
+        }
+        let (answers, rest) = remaining.split_at(batch.len());
+        let batch_symbols = check_batch(&proof.commitments, &batch, answers)?;
+        transcript.absorb_batch(&batch, &batch_symbols);
+        queried.extend(batch);
+        symbols.extend(batch_symbols);
+        remaining = rest;
+    }
+    if !remaining.is_empty() {
+        return Err(format!("Proof has {} answers the verifier never asked for", remaining.len()));
+    }
+    decide(iop, &challenges, &proof.commitments, &queried, &symbols)
+}
+
+// The prover claims every byte of its first oracle is below a bound, and its second oracle is the first
+// shifted by the second challenge. The verifier checks both at a few random positions, and when querying
+// adaptively, reads one position per batch and stops at the first that fails.
+#[cfg(test)]
+struct ShiftIop {
+    bound: u8,
+    repetitions: usize,
+}
+
+#[cfg(test)]
+impl Iop for ShiftIop {
+    fn rounds(&self) -> usize {
+        2
+    }
+
+    fn queries(&self, _challenges: &[[u8; 32]], lengths: &[usize], randomness: &[u8; 32]) -> Vec<Query> {
+        query_indices(randomness, self.repetitions, lengths[0]).into_iter().flat_map(|index| vec![Query { oracle: 0, index }, Query { oracle: 1, index }]).collect()
+    }
+
+    fn decide(&self, challenges: &[[u8; 32]], lengths: &[usize], _queries: &[Query], answers: &[Vec<u8>]) -> bool {
+        lengths[0] == lengths[1] && answers.chunks(2).all(|pair| match (pair[0].as_slice(), pair[1].as_slice()) {
+            (&[x], &[y]) => x < self.bound && y == x.wrapping_add(challenges[1][0]),
+            _ => false,
+        })
+    }
+
+    fn next_queries(&self, challenges: &[[u8; 32]], lengths: &[usize], randomness: &[u8; 32], queried: &[Query], answers: &[Vec<u8>]) -> Vec<Query> {
+        if queried.len() == 2 * self.repetitions || !self.decide(challenges, lengths, queried, answers) {
+            return Vec::new();
+        }
+        query_indices(randomness, 1, lengths[0]).into_iter().flat_map(|index| vec![Query { oracle: 0, index }, Query { oracle: 1, index }]).collect()
+    }
+}
+
+#[cfg(test)]
+struct ShiftProver {
+    values: Vec<u8>,
+}
+
+#[cfg(test)]
+impl IopProver for ShiftProver {
+    fn oracle(&mut self, round: usize, challenge: &[u8; 32]) -> Oracle {
+        let shift = if round == 0 { 0 } else { challenge[0] };
+        self.values.iter().map(|x| vec![x.wrapping_add(shift)]).collect()
+    }
+}
+
+#[test]
+fn test_iop_to_interactive() {
+    let iop = ShiftIop { bound: 10, repetitions: 8 };
+    let mut honest = ShiftProver { values: (0..10).collect() };
+    let (mut prover, mut verifier) = iop_to_interactive(&iop, &mut honest);
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+    assert_eq!(verifier.commitments.len(), 2);
+    let mut cheating = ShiftProver { values: vec![10; 16] };
+    let (mut prover, mut verifier) = iop_to_interactive(&iop, &mut cheating);
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+}
+
+#[test]
+fn test_iop_to_nizk() {
+    let iop = ShiftIop { bound: 10, repetitions: 8 };
+    let context = ProofContext::new("shift", b"session", b"statement");
+    let proof = iop_to_nizk(&iop, &mut ShiftProver { values: (0..10).collect() }, &context);
+    assert_eq!(verify_iop_nizk(&iop, &proof, &context), Ok(()));
+    // Proofs are deterministic given the prover's oracles
+    assert_eq!(iop_to_nizk(&iop, &mut ShiftProver { values: (0..10).collect() }, &context), proof);
+    let cheating = iop_to_nizk(&iop, &mut ShiftProver { values: vec![10; 16] }, &context);
+    assert!(verify_iop_nizk(&iop, &cheating, &context).is_err());
+    let mut tampered = proof.clone();
+    tampered.answers[0].symbol = vec![200];
+    assert!(verify_iop_nizk(&iop, &tampered, &context).unwrap_err().contains("does not open"));
+    let other = ProofContext::new("shift", b"other session", b"statement");
+    assert!(verify_iop_nizk(&iop, &proof, &other).is_err());
+}
+
+#[test]
+fn test_adaptive_queries() {
+    let iop = ShiftIop { bound: 10, repetitions: 8 };
+    let context = ProofContext::new("shift", b"session", b"statement");
+    let proof = iop_to_nizk_with_mode(&iop, &mut ShiftProver { values: (0..10).collect() }, &context, QueryMode::Adaptive);
+    assert_eq!(verify_iop_nizk_with_mode(&iop, &proof, &context, QueryMode::Adaptive), Ok(()));
+    assert_eq!(proof.answers.len(), 16);
+    // The mode is part of the proof, so a verifier requiring non-adaptive queries rejects it
+    assert!(verify_iop_nizk(&iop, &proof, &context).unwrap_err().contains("expected non-adaptive"));
+    let mut relabeled = proof.clone();
+    relabeled.mode = QueryMode::NonAdaptive;
+    assert!(verify_iop_nizk(&iop, &relabeled, &context).is_err());
+    // An adaptive verifier stops reading at the first bad symbol, while a non-adaptive one reads them all
+    let cheating = iop_to_nizk_with_mode(&iop, &mut ShiftProver { values: vec![10; 16] }, &context, QueryMode::Adaptive);
+    assert_eq!(cheating.answers.len(), 2);
+    assert!(verify_iop_nizk_with_mode(&iop, &cheating, &context, QueryMode::Adaptive).is_err());
+    assert_eq!(iop_to_nizk(&iop, &mut ShiftProver { values: vec![10; 16] }, &context).answers.len(), 16);
+    let mut honest = ShiftProver { values: (0..10).collect() };
+    let (mut prover, mut verifier) = iop_to_interactive_with_mode(&iop, &mut honest, QueryMode::Adaptive);
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+    assert_eq!(verifier.schedule.len(), 8);
+    let mut cheating = ShiftProver { values: vec![10; 16] };
+    let (mut prover, mut verifier) = iop_to_interactive_with_mode(&iop, &mut cheating, QueryMode::Adaptive);
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+    assert_eq!(verifier.schedule.len(), 1);
+}
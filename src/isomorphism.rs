@@ -0,0 +1,378 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+use itertools::Itertools;
+use crate::graph::{refine_colors_from, vertex_label, Graph, SearchBudget, SearchResult};
+use crate::permutation::Permutation;
+#[cfg(test)]
+use rand::{thread_rng, Rng};
+#[cfg(test)]
+use crate::graph::GraphPair;
+
+// ************ Isomorphism decision implementation ************
+
+// Deciding whether two graphs are isomorphic, for users who want the graph machinery without a protocol
+// around it. Every mode first compares sizes and runs color refinement, which settles most non-isomorphic
+// pairs in polynomial time. Only pairs that refinement can't tell apart reach the backtracking search,
+// whose running time is exponential in the worst case. Exact and budgeted decisions settle trees before
+// any of this, in near-linear time.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecisionMode {
+    // Search until the question is settled, however long that takes
+    Exact,
+    // Only run color refinement, which can prove graphs non-isomorphic but never isomorphic
+    WLOnly,
+    // Search within the given budget, giving up once it is spent
+    Budgeted(SearchBudget),
+}
+
+impl Default for DecisionMode {
+    fn default() -> DecisionMode {
+        DecisionMode::Budgeted(SearchBudget::default())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    // An isomorphism that takes the first graph to the second
+    Isomorphic(Permutation),
+    NonIsomorphic,
+    // The mode's checks couldn't settle the question
+    Unknown,
+}
+
+impl Decision {
+    pub fn is_isomorphic(&self) -> Option<bool> {
+        match self {
+            Decision::Isomorphic(_) => Some(true),
+            Decision::NonIsomorphic => Some(false),
+            Decision::Unknown => None,
+        }
+    }
+}
+
+// Decides whether a and b are isomorphic. Exact never returns Unknown, WLOnly never returns Isomorphic,
+// and Budgeted returns Unknown when the search exhausts its budget.
+pub fn decide(a: &Graph, b: &Graph, mode: DecisionMode) -> Decision {
+    if a.n != b.n || a.edge_count() != b.edge_count() {
+        return Decision::NonIsomorphic;
+    }
+    if mode != DecisionMode::WLOnly {
+        if let Some(decision) = decide_trees(a, b) {
+            return decision;
+        }
+    }
+    if !a.refinement_equivalent(b) {
+        return Decision::NonIsomorphic;
+    }
+    let budget = match mode {
+        DecisionMode::Exact => SearchBudget::unlimited(),
+        DecisionMode::WLOnly => return Decision::Unknown,
+        DecisionMode::Budgeted(budget) => budget,
+    };
+    match a.find_isomorphism_to_with_budget(b, budget) {
+        SearchResult::Found(isomorphism) => Decision::Isomorphic(isomorphism),
+        SearchResult::NotFound => Decision::NonIsomorphic,
+        SearchResult::Exhausted => Decision::Unknown,
+    }
+}
+
+// ************ Tree isomorphism implementation ************
+
+// The AHU algorithm (Aho, Hopcroft, and Ullman) names every subtree of a rooted tree by the sorted names
+// of its children's subtrees, from the leaves up, so two rooted trees are isomorphic exactly when their
+// roots get the same name. Names come from one table shared by both trees, which makes them comparable.
+// An isomorphism pairs up the children of matched vertices in order of their names.
+//
+// Two kinds of graph count as trees: undirected trees, stored with every edge in both directions, and
+// out-trees, with every edge directed away from a root. Each kind is preserved by isomorphism, so a tree
+// is never isomorphic to a graph that isn't a tree of the same kind. An undirected tree is rooted at its
+// center, the one or two vertices left after repeatedly removing every leaf, which isomorphisms preserve.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TreeShape {
+    Undirected,
+    OutTree(u32),
+}
+
+// Decides isomorphism if either graph is a tree, and returns None otherwise
+fn decide_trees(a: &Graph, b: &Graph) -> Option<Decision> {
+    let decision = match (tree_shape(a), tree_shape(b)) {
+        (None, None) => return None,
+        (Some(TreeShape::Undirected), Some(TreeShape::Undirected)) => {
+            let mut names = HashMap::new();
+            let from = RootedTree::new(a, centers(a)[0], &mut names);
+            // A tree with two centers is rooted at either of them
+            centers(b).into_iter().map(|root| RootedTree::new(b, root, &mut names)).find(|to| from.name() == to.name())
+                .map_or(Decision::NonIsomorphic, |to| Decision::Isomorphic(from.isomorphism_to(&to)))
+        }
+        (Some(TreeShape::OutTree(x)), Some(TreeShape::OutTree(y))) => {
+            let mut names = HashMap::new();
+            let (from, to) = (RootedTree::new(a, x, &mut names), RootedTree::new(b, y, &mut names));
+            if from.name() == to.name() { Decision::Isomorphic(from.isomorphism_to(&to)) } else { Decision::NonIsomorphic }
+        }
+        _ => Decision::NonIsomorphic,
+    };
+    Some(decision)
+}
+
+fn tree_shape(g: &Graph) -> Option<TreeShape> {
+    let n = g.n as usize;
+    if n == 0 {
+        return None;
+    }
+    if g.edge_count() == 2 * (n - 1) && g.edges().all(|(a, b)| a != b && g.has_edge(b, a)) {
+        return Some(TreeShape::Undirected).filter(|_| breadth_first(g, 0).0.len() == n);
+    }
+    if g.edge_count() == n - 1 {
+        // n - 1 edges reaching every vertex from a root are an out-tree, and the root can't have an in-edge
+        let mut in_degrees = vec![0usize; n];
+        g.edges().for_each(|(_, b)| in_degrees[b as usize] += 1);
+        let root = vertex_label(in_degrees.iter().position(|&d| d == 0)?);
+        return Some(TreeShape::OutTree(root)).filter(|_| breadth_first(g, root).0.len() == n);
+    }
+    None
+}
+
+// Vertices reachable from the root in breadth-first order, and the parent of each, with the root its own
+// parent and unreached vertices u32::MAX
+fn breadth_first(g: &Graph, root: u32) -> (Vec<u32>, Vec<u32>) {
+    let mut parents = vec![u32::MAX; g.n as usize];
+    parents[root as usize] = root;
+    let mut order = vec![root];
+    let mut queue = VecDeque::from(vec![root]);
+    while let Some(v) = queue.pop_front() {
+        for w in g.successors(v) {
+            if parents[w as usize] == u32::MAX {
+                parents[w as usize] = v;
+                order.push(w);
+                queue.push_back(w);
+            }
+        }
+    }
+    (order, parents)
+}
+
+// The one or two centers of an undirected tree
+fn centers(g: &Graph) -> Vec<u32> {
+    let mut degrees: Vec<usize> = (0..g.n).map(|v| g.out_degree(v)).collect();
+    let mut leaves: Vec<u32> = (0..g.n).filter(|&v| degrees[v as usize] <= 1).collect();
+    let mut remaining = g.n as usize;
+    while remaining > 2 {
+        remaining -= leaves.len();
+        leaves.iter().for_each(|&v| degrees[v as usize] = 0);
+        let mut next = Vec::new();
+        for &v in &leaves {
+            for w in g.successors(v) {
+                if degrees[w as usize] > 0 {
+                    degrees[w as usize] -= 1;
+                    if degrees[w as usize] == 1 {
+                        next.push(w);
+                    }
+                }
+            }
+        }
+        leaves = next;
+    }
+    leaves
+}
+
+// A tree rooted at a vertex, with the AHU name of every subtree
+struct RootedTree {
+    root: u32,
+    children: Vec<Vec<u32>>,
+    names: Vec<u32>,
+}
+
+impl RootedTree {
+    fn new(g: &Graph, root: u32, table: &mut HashMap<Vec<u32>, u32>) -> RootedTree {
+        let (order, parents) = breadth_first(g, root);
+        let mut children = vec![Vec::new(); g.n as usize];
+        order.iter().skip(1).for_each(|&v| children[parents[v as usize] as usize].push(v));
+        let mut names = vec![0; g.n as usize];
+        // Children come after their parent in breadth-first order, so they are named first
+        for &v in order.iter().rev() {
+            let key: Vec<u32> = children[v as usize].iter().map(|&w| names[w as usize]).sorted().collect();
+            let next = vertex_label(table.len());
+            names[v as usize] = *table.entry(key).or_insert(next);
+        }
+        RootedTree { root, children, names }
+    }
+
+    fn name(&self) -> u32 {
+        self.names[self.root as usize]
+    }
+
+    // Isomorphism to a tree whose root has the same name, matching children with equal names
+    fn isomorphism_to(&self, other: &RootedTree) -> Permutation {
+        let mut map = vec![0; self.names.len()];
+        let mut stack = vec![(self.root, other.root)];
+        while let Some((v, w)) = stack.pop() {
+            map[v as usize] = w;
+            let from = self.children[v as usize].iter().copied().sorted_by_key(|&x| self.names[x as usize]);
+            let to = other.children[w as usize].iter().copied().sorted_by_key(|&x| other.names[x as usize]);
+            stack.extend(from.zip(to));
+        }
+        Permutation::new(map).unwrap()
+    }
+}
+
+// ************ Canonical form implementation ************
+
+// A canonical labeling relabels a graph so that isomorphic graphs, and only they, come out equal, which
+// turns deciding isomorphism into comparing the relabeled graphs. The search individualizes and refines:
+// it refines the coloring, and while some color class has several vertices, it branches on giving each
+// vertex of the smallest such class a color of its own and refining again. Every branch ends in a coloring
+// with one vertex per color, which labels each vertex by its color, and the canonical form is the
+// relabeling with the smallest encoding.
+//
+// Classes are chosen by color alone and every branch is explored, so the search tree of a relabeled graph
+// is the same tree with its vertices renamed: it has the same number of nodes, and canonicalizing two
+// isomorphic graphs takes the same work. The price is that nothing is pruned, not even the branches that
+// automorphisms make redundant, so highly symmetric graphs such as empty graphs have an exponential tree,
+// which the budget cuts short.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalLabeling {
+    // Relabeling taking the graph to its canonical form
+    pub labeling: Permutation,
+    // Nodes of the search tree, each a round of refinement
+    pub nodes: u64,
+}
+
+// Searches for the canonical labeling of the graph, returning None if the budget runs out
+pub fn canonical_labeling(graph: &Graph, budget: SearchBudget) -> Option<CanonicalLabeling> {
+    let mut search = CanonicalSearch { graph, best: None, nodes: 0, budget, start: Instant::now() };
+    search.explore(vec![0; graph.n as usize]).ok()?;
+    let (_, labeling) = search.best?;
+    Some(CanonicalLabeling { labeling: Permutation::new(labeling).unwrap(), nodes: search.nodes })
+}
+
+pub fn canonical_form(graph: &Graph, budget: SearchBudget) -> Option<Graph> {
+    canonical_labeling(graph, budget).map(|canonical| graph.permute(&canonical.labeling))
+}
+
+struct CanonicalSearch<'a> {
+    graph: &'a Graph,
+    // Smallest encoding found so far, along with the labeling giving it
+    best: Option<(Vec<u8>, Vec<u32>)>,
+    nodes: u64,
+    budget: SearchBudget,
+    start: Instant,
+}
+
+impl CanonicalSearch<'_> {
+    // Refines the coloring and explores the subtree below it, returning Err if the budget runs out
+    fn explore(&mut self, colors: Vec<u32>) -> Result<(), ()> {
+        self.nodes += 1;
+        if self.budget.max_nodes.is_some_and(|x| self.nodes > x) || self.budget.max_time.is_some_and(|x| self.start.elapsed() > x) {
+            return Err(());
+        }
+        let colors = refine_colors_from(&[self.graph], vec![colors]).remove(0);
+        let mut sizes = vec![0; colors.len()];
+        colors.iter().for_each(|&c| sizes[c as usize] += 1);
+        // The smallest class with more than one vertex, breaking ties by color
+        match (0..sizes.len()).filter(|&c| sizes[c] > 1).min_by_key(|&c| sizes[c]) {
+            None => {
+                // Colors are numbered from 0, so a coloring with a vertex per color is a labeling
+                let encoding = self.graph.permute(&Permutation::new(colors.clone()).unwrap()).encode();
+                if self.best.as_ref().is_none_or(|(best, _)| encoding < *best) {
+                    self.best = Some((encoding, colors));
+                }
+            }
+            Some(class) => {
+                for v in (0..colors.len()).filter(|&v| colors[v] as usize == class) {
+                    // Splits v off below the rest of its class, keeping every other class in its place
+                    let individualized = colors.iter().enumerate().map(|(u, &c)| 2 * c + (u != v) as u32).collect();
+                    self.explore(individualized)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_decide() {
+    let graph = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let permuted = graph.permute(&Permutation::random(graph.n, &mut thread_rng()));
+    match decide(&graph, &permuted, DecisionMode::Exact) {
+        Decision::Isomorphic(isomorphism) => assert_eq!(graph.permute(&isomorphism), permuted),
+        decision => panic!("Expected an isomorphism, got {:?}", decision),
+    }
+    assert_eq!(decide(&graph, &permuted, DecisionMode::WLOnly), Decision::Unknown);
+    // Vertex 1 has out-degree 2 in the graph and 1 in the other, which refinement notices
+    let other = Graph::new(4, vec![(0, 1), (1, 2), (2, 3), (0, 3), (3, 0)]);
+    for mode in [DecisionMode::Exact, DecisionMode::WLOnly, DecisionMode::default()] {
+        assert_eq!(decide(&graph, &other, mode), Decision::NonIsomorphic);
+    }
+}
+
+#[test]
+fn test_decide_hard_instance() {
+    // Refinement can't tell the CFI graphs apart, and a tiny budget can't settle them either
+    let instance = GraphPair::sample_hard_instance(6);
+    assert_eq!(decide(&instance.g0, &instance.g1, DecisionMode::WLOnly), Decision::Unknown);
+    assert_eq!(decide(&instance.g0, &instance.g1, DecisionMode::Budgeted(SearchBudget::nodes(10))).is_isomorphic(), None);
+}
+
+// Random undirected tree on n vertices, with every vertex after the first attached to an earlier one
+#[cfg(test)]
+fn random_tree(n: u32) -> Vec<(u32, u32)> {
+    (1..n).map(|v| (thread_rng().gen_range(0..v), v)).collect()
+}
+
+#[cfg(test)]
+fn undirected(edges: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    edges.iter().flat_map(|&(a, b)| vec![(a, b), (b, a)]).collect()
+}
+
+#[test]
+fn test_decide_trees() {
+    // Budgets too small for any search show that trees never reach one
+    let mode = DecisionMode::Budgeted(SearchBudget::nodes(0));
+    let edges = random_tree(2000);
+    let tree = Graph::new(2000, undirected(&edges));
+    let permuted = tree.permute(&Permutation::random(tree.n, &mut thread_rng()));
+    match decide(&tree, &permuted, mode) {
+        Decision::Isomorphic(isomorphism) => assert_eq!(tree.permute(&isomorphism), permuted),
+        decision => panic!("Expected an isomorphism, got {:?}", decision),
+    }
+    // The same edges directed away from vertex 0 form an out-tree
+    let out_tree = Graph::new(2000, edges);
+    let permuted = out_tree.permute(&Permutation::random(out_tree.n, &mut thread_rng()));
+    assert_eq!(decide(&out_tree, &permuted, mode).is_isomorphic(), Some(true));
+    // A path and a spider with the same number of vertices, both with two centers
+    let path = Graph::new(6, undirected(&[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5)]));
+    let spider = Graph::new(6, undirected(&[(0, 1), (1, 2), (2, 3), (3, 4), (2, 5)]));
+    assert_eq!(decide(&path, &spider, mode), Decision::NonIsomorphic);
+    // A triangle and a disjoint edge have as many edges as a tree on 5 vertices but aren't one
+    let forest = Graph::new(5, undirected(&[(0, 1), (1, 2), (2, 0), (3, 4)]));
+    let star = Graph::new(5, undirected(&[(0, 1), (0, 2), (0, 3), (0, 4)]));
+    assert_eq!(decide(&star, &forest, mode), Decision::NonIsomorphic);
+    // Reversing a path makes its other end the root, which is still isomorphic
+    let directed = Graph::new(4, vec![(0, 1), (1, 2), (2, 3)]);
+    let reversed = Graph::new(4, vec![(3, 2), (2, 1), (1, 0)]);
+    assert_eq!(decide(&directed, &reversed, mode).is_isomorphic(), Some(true));
+    assert_eq!(decide(&directed, &Graph::new(4, vec![(0, 1), (0, 2), (2, 3)]), mode), Decision::NonIsomorphic);
+}
+
+#[test]
+fn test_canonical_form() {
+    let graph = Graph::new(6, vec![(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3), (0, 3)]);
+    let canonical = canonical_labeling(&graph, SearchBudget::default()).unwrap();
+    for _ in 0..10 {
+        let permuted = graph.permute(&Permutation::random(graph.n, &mut thread_rng()));
+        let relabeled = canonical_labeling(&permuted, SearchBudget::default()).unwrap();
+        // The search tree of a relabeled graph has the same shape
+        assert_eq!(relabeled.nodes, canonical.nodes);
+        assert_eq!(permuted.permute(&relabeled.labeling), graph.permute(&canonical.labeling));
+    }
+    // A directed 6-cycle with a chord has the same degrees as two directed triangles joined by an edge
+    let cycle = Graph::new(6, vec![(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0), (0, 3)]);
+    assert_eq!(decide(&graph, &cycle, DecisionMode::Exact), Decision::NonIsomorphic);
+    assert_ne!(canonical_form(&cycle, SearchBudget::default()), canonical_form(&graph, SearchBudget::default()));
+    // The empty graph's tree has a leaf for every ordering of its vertices
+    assert_eq!(canonical_form(&Graph::new(10, Vec::new()), SearchBudget::nodes(1000)), None);
+    assert_eq!(canonical_form(&Graph::new(0, Vec::new()), SearchBudget::default()), Some(Graph::new(0, Vec::new())));
+}
@@ -0,0 +1,422 @@
+use std::convert::TryInto;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use num_bigint::BigUint;
+use num_traits::Zero;
+use zeroize::Zeroizing;
+use crate::modarith::mod_exp;
+use crate::ot::{Ciphertext, decrypt, encrypt};
+use crate::params::{Parameters, SchnorrParameters};
+use crate::pedersen::PedersenParameters;
+use crate::schnorr::{SchnorrKeyPair, SchnorrPublicKey};
+use crate::secret::Secret;
+#[cfg(test)]
+use rand::{thread_rng, Rng};
+
+// ************ Key management implementation ************
+
+// Parties that outlive a single run keep their keys in files: Schnorr signing keys, ElGamal decryption
+// keys, and Pedersen commitment keys, along with the public halves of the first two. A key is encoded as
+// its group (p, q, g) followed by its own numbers, each as a length-prefixed big-endian integer like
+// encode_biguints, and stored as base64 between PEM lines whose label names the kind of key, so that one
+// kind can't be loaded as another. Keys are validated when they are loaded.
+//
+// Secret keys hold their secret exponent in a Secret, which overwrites it when dropped, the buffers that
+// hold their encodings are overwritten too, and their files are only readable by their owner.
+
+pub trait KeyMaterial: Sized {
+    // Label of the PEM block holding the key
+    const LABEL: &'static str;
+    // Whether the key is secret, in which case its files are created readable only by their owner
+    const SECRET: bool;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>>;
+
+    // Parses a key, returning an error if it is malformed or fails validation
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String>;
+
+    fn to_pem(&self) -> Zeroizing<String> {
+        pem_encode(Self::LABEL, &self.to_bytes())
+    }
+
+    fn from_pem(pem: &str) -> Result<Self, String> {
+        Self::from_bytes(&pem_decode(Self::LABEL, pem)?)
+    }
+
+    // Writes the key to a new file as PEM, refusing to overwrite an existing one
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if Self::SECRET {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)
+            .and_then(|mut file| file.write_all(self.to_pem().as_bytes()).and_then(|_| file.sync_all()))
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        let pem = Zeroizing::new(fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?);
+        Self::from_pem(&pem).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Encodes the numbers like encode_biguints, into a buffer sized up front so it is never reallocated
+// and left behind
+fn encode_key(values: &[&BigUint]) -> Zeroizing<Vec<u8>> {
+    let length = values.iter().map(|x| 8 + x.bits().div_ceil(8).max(1) as usize).sum();
+    let mut bytes = Zeroizing::new(Vec::with_capacity(length));
+    for x in values {
+        let value = Zeroizing::new(x.to_bytes_be());
+        bytes.extend_from_slice(&(value.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&value);
+    }
+    bytes
+}
+
+// Inverse of encode_key, expecting exactly the given number of values
+fn decode_key(bytes: &[u8], count: usize) -> Result<Vec<BigUint>, String> {
+    let mut values = Vec::with_capacity(count);
+    let mut rest = bytes;
+    for _ in 0..count {
+        if rest.len() < 8 {
+            return Err("Key is truncated".to_string());
+        }
+        let (length, tail) = rest.split_at(8);
+        let length = u64::from_be_bytes(length.try_into().unwrap());
+        if length > tail.len() as u64 {
+            return Err("Key is truncated".to_string());
+        }
+        let (value, tail) = tail.split_at(length as usize);
+        values.push(BigUint::from_bytes_be(value));
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(format!("Key has {} trailing bytes", rest.len()));
+    }
+    Ok(values)
+}
+
+fn decode_group(p: BigUint, q: BigUint, g: BigUint) -> Result<SchnorrParameters, String> {
+    let params = SchnorrParameters { p, q, g };
+    if !params.validate() {
+        return Err("Key has an invalid group".to_string());
+    }
+    Ok(params)
+}
+
+// Decodes a group followed by a secret exponent x in [1, q), returning it with y = g^x. x is held in a
+// Secret from the start, so that it is erased however decoding ends.
+fn decode_secret_key(bytes: &[u8]) -> Result<(SchnorrParameters, Secret<BigUint>, BigUint), String> {
+    let mut values = decode_key(bytes, 4)?;
+    let x = Secret::new(values.pop().unwrap());
+    let g = values.pop().unwrap();
+    let q = values.pop().unwrap();
+    let p = values.pop().unwrap();
+    let params = decode_group(p, q, g)?;
+    params.check_exponent(&x).map_err(|_| "Secret key is not reduced mod q".to_string())?;
+    if x.is_zero() {
+        return Err("Secret key is zero".to_string());
+    }
+    let y = mod_exp(&params.g, &x, &params.p);
+    Ok((params, x, y))
+}
+
+fn decode_public_key(bytes: &[u8]) -> Result<(SchnorrParameters, BigUint), String> {
+    let mut values = decode_key(bytes, 4)?;
+    let y = values.pop().unwrap();
+    let g = values.pop().unwrap();
+    let q = values.pop().unwrap();
+    let p = values.pop().unwrap();
+    let params = decode_group(p, q, g)?;
+    params.check_element(&y)?;
+    Ok((params, y))
+}
+
+impl KeyMaterial for SchnorrKeyPair {
+    const LABEL: &'static str = "ZKLIB SCHNORR SECRET KEY";
+    const SECRET: bool = true;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let params = &self.params;
+        encode_key(&[&params.p, &params.q, &params.g, &self.x])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<SchnorrKeyPair, String> {
+        let (params, x, y) = decode_secret_key(bytes)?;
+        Ok(SchnorrKeyPair { params, x, y })
+    }
+}
+
+impl KeyMaterial for SchnorrPublicKey {
+    const LABEL: &'static str = "ZKLIB SCHNORR PUBLIC KEY";
+    const SECRET: bool = false;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let params = &self.params;
+        encode_key(&[&params.p, &params.q, &params.g, &self.y])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<SchnorrPublicKey, String> {
+        let (params, y) = decode_public_key(bytes)?;
+        Ok(SchnorrPublicKey { params, y })
+    }
+}
+
+// The commitment key (g, h) of Pedersen commitments, which is public but must be generated so that
+// nobody knows log_g h
+impl KeyMaterial for PedersenParameters {
+    const LABEL: &'static str = "ZKLIB PEDERSEN COMMITMENT KEY";
+    const SECRET: bool = false;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let group = &self.group;
+        encode_key(&[&group.p, &group.q, &group.g, &self.h])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<PedersenParameters, String> {
+        let mut values = decode_key(bytes, 4)?;
+        let h = values.pop().unwrap();
+        let g = values.pop().unwrap();
+        let q = values.pop().unwrap();
+        let p = values.pop().unwrap();
+        let params = PedersenParameters { group: SchnorrParameters { p, q, g }, h };
+        if !params.validate() {
+            return Err("Commitment key is invalid".to_string());
+        }
+        Ok(params)
+    }
+}
+
+// ************ ElGamal keys ************
+
+// Decryption key x with public key y = g^x for hashed ElGamal encryption, as used by oblivious transfer
+pub struct ElGamalKeyPair {
+    pub params: SchnorrParameters,
+    pub x: Secret<BigUint>,
+    pub y: BigUint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElGamalPublicKey {
+    pub params: SchnorrParameters,
+    pub y: BigUint,
+}
+
+impl ElGamalKeyPair {
+    pub fn generate(params: &SchnorrParameters) -> ElGamalKeyPair {
+        let x = loop {
+            let x = params.random_exponent();
+            if !x.is_zero() {
+                break x;
+            }
+        };
+        let y = mod_exp(&params.g, &x, &params.p);
+        ElGamalKeyPair { params: params.clone(), x: Secret::new(x), y }
+    }
+
+    pub fn public_key(&self) -> ElGamalPublicKey {
+        ElGamalPublicKey { params: self.params.clone(), y: self.y.clone() }
+    }
+
+    pub fn decrypt(&self, ciphertext: &Ciphertext) -> Vec<u8> {
+        decrypt(&self.params, &self.x, ciphertext)
+    }
+}
+
+impl ElGamalPublicKey {
+    pub fn encrypt(&self, message: &[u8]) -> Ciphertext {
+        encrypt(&self.params, &self.y, message)
+    }
+}
+
+impl KeyMaterial for ElGamalKeyPair {
+    const LABEL: &'static str = "ZKLIB ELGAMAL SECRET KEY";
+    const SECRET: bool = true;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let params = &self.params;
+        encode_key(&[&params.p, &params.q, &params.g, &self.x])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ElGamalKeyPair, String> {
+        let (params, x, y) = decode_secret_key(bytes)?;
+        Ok(ElGamalKeyPair { params, x, y })
+    }
+}
+
+impl KeyMaterial for ElGamalPublicKey {
+    const LABEL: &'static str = "ZKLIB ELGAMAL PUBLIC KEY";
+    const SECRET: bool = false;
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let params = &self.params;
+        encode_key(&[&params.p, &params.q, &params.g, &self.y])
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<ElGamalPublicKey, String> {
+        let (params, y) = decode_public_key(bytes)?;
+        Ok(ElGamalPublicKey { params, y })
+    }
+}
+
+// ************ PEM encoding ************
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Base64 with padding, split into lines of 64 characters between BEGIN and END lines with the label
+pub fn pem_encode(label: &str, bytes: &[u8]) -> Zeroizing<String> {
+    let characters = bytes.len().div_ceil(3) * 4;
+    let mut pem = Zeroizing::new(String::with_capacity(characters + characters.div_ceil(64) + 2 * label.len() + 32));
+    pem.push_str("-----BEGIN ");
+    pem.push_str(label);
+    pem.push_str("-----\n");
+    for (i, chunk) in bytes.chunks(3).enumerate() {
+        if i > 0 && i % 16 == 0 {
+            pem.push('\n');
+        }
+        let n = chunk.iter().enumerate().fold(0u32, |n, (j, &b)| n | (b as u32) << (16 - 8 * j));
+        for j in 0..4 {
+            pem.push(if j <= chunk.len() { BASE64_ALPHABET[(n >> (18 - 6 * j)) as usize & 63] as char } else { '=' });
+        }
+    }
+    if !bytes.is_empty() {
+        pem.push('\n');
+    }
+    pem.push_str("-----END ");
+    pem.push_str(label);
+    pem.push_str("-----\n");
+    pem
+}
+
+// Decodes the first PEM block, which must have the given label
+pub fn pem_decode(label: &str, pem: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut lines = pem.lines().map(str::trim).filter(|line| !line.is_empty());
+    let found = lines.next().and_then(|line| line.strip_prefix("-----BEGIN ")).and_then(|line| line.strip_suffix("-----")).ok_or("Missing PEM header")?;
+    if found != label {
+        return Err(format!("Expected a {} but found a {}", label, found));
+    }
+    let end = format!("-----END {}-----", label);
+    let mut body = Zeroizing::new(String::with_capacity(pem.len()));
+    for line in lines {
+        if line == end {
+            return base64_decode(&body);
+        }
+        body.push_str(line);
+    }
+    Err("Missing PEM footer".to_string())
+}
+
+fn base64_decode(text: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let data = text.trim_end_matches('=');
+    if !text.len().is_multiple_of(4) || text.len() - data.len() > 2 {
+        return Err("Malformed base64 padding".to_string());
+    }
+    let mut bytes = Zeroizing::new(Vec::with_capacity(data.len() * 3 / 4));
+    let (mut n, mut bits) = (0u32, 0);
+    for c in data.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&x| x == c).ok_or_else(|| format!("Invalid base64 character {:?}", c as char))?;
+        n = (n << 6 | value as u32) & 0xfff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits) as u8);
+        }
+    }
+    if bits >= 6 {
+        return Err("Base64 is truncated".to_string());
+    }
+    // The bits left over after the last byte must be zero, so that every byte string has one encoding
+    if n & ((1 << bits) - 1) != 0 {
+        return Err("Base64 has nonzero padding bits".to_string());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+fn test_group() -> SchnorrParameters {
+    SchnorrParameters::generate(64)
+}
+
+#[test]
+fn test_pem_encoding() {
+    for length in 0..100 {
+        let bytes: Vec<u8> = (0..length).map(|_| thread_rng().gen()).collect();
+        let pem = pem_encode("TEST", &bytes);
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        assert_eq!(*pem_decode("TEST", &pem).unwrap(), bytes);
+    }
+    assert_eq!(*pem_encode("TEST", b"zklib"), "-----BEGIN TEST-----\nemtsaWI=\n-----END TEST-----\n");
+
+    assert!(pem_decode("OTHER", &pem_encode("TEST", b"zklib")).unwrap_err().contains("found a TEST"));
+    assert!(pem_decode("TEST", "-----BEGIN TEST-----\nemtsaWI=\n").is_err());
+    assert!(pem_decode("TEST", "-----BEGIN TEST-----\nemts*WI=\n-----END TEST-----").is_err());
+    assert!(pem_decode("TEST", "-----BEGIN TEST-----\nemtsaWI\n-----END TEST-----").is_err());
+    // Only the canonical encoding is accepted, without leftover bits set in its last character
+    assert!(pem_decode("TEST", "-----BEGIN TEST-----\nemtsaWJ=\n-----END TEST-----").unwrap_err().contains("padding bits"));
+    assert!(pem_decode("TEST", "-----BEGIN TEST-----\nemtsaR==\n-----END TEST-----").is_err());
+    assert_eq!(*pem_decode("TEST", "-----BEGIN TEST-----\nemtsaQ==\n-----END TEST-----").unwrap(), b"zkli");
+}
+
+#[test]
+fn test_schnorr_keys() {
+    let key = SchnorrKeyPair::generate(&test_group());
+    let loaded = SchnorrKeyPair::from_pem(&key.to_pem()).unwrap();
+    assert_eq!((&loaded.x, &loaded.y), (&key.x, &key.y));
+    let public_key = SchnorrPublicKey::from_pem(&key.public_key().to_pem()).unwrap();
+    assert_eq!(public_key, key.public_key());
+    assert!(loaded.sign(b"message").verify(&public_key.params, &public_key.y, b"message"));
+
+    // A secret key can't be loaded as a public key or as an ElGamal key
+    assert!(SchnorrPublicKey::from_pem(&key.to_pem()).is_err());
+    assert!(ElGamalKeyPair::from_pem(&key.to_pem()).is_err());
+    // Secret keys must be reduced mod q, and public keys must be in the group
+    let unreduced = SchnorrKeyPair { params: key.params.clone(), x: Secret::new(&*key.x + &key.params.q), y: key.y.clone() };
+    assert!(SchnorrKeyPair::from_bytes(&unreduced.to_bytes()).is_err());
+    let outside = SchnorrPublicKey { params: key.params.clone(), y: &key.params.p - 1u32 };
+    assert!(SchnorrPublicKey::from_bytes(&outside.to_bytes()).is_err());
+    // Truncated and extended encodings are rejected
+    let bytes = key.to_bytes();
+    assert!(SchnorrKeyPair::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(SchnorrKeyPair::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+}
+
+#[test]
+fn test_elgamal_keys() {
+    let key = ElGamalKeyPair::generate(&test_group());
+    let public_key = ElGamalPublicKey::from_pem(&key.public_key().to_pem()).unwrap();
+    let ciphertext = public_key.encrypt(b"attack at dawn");
+    let loaded = ElGamalKeyPair::from_pem(&key.to_pem()).unwrap();
+    assert_eq!(loaded.decrypt(&ciphertext), b"attack at dawn");
+    assert_ne!(ElGamalKeyPair::generate(&key.params).decrypt(&ciphertext), b"attack at dawn");
+}
+
+#[test]
+fn test_pedersen_key() {
+    let params = PedersenParameters::generate(64);
+    assert_eq!(PedersenParameters::from_pem(&params.to_pem()).unwrap(), params);
+    let degenerate = PedersenParameters { group: params.group.clone(), h: BigUint::from(1u32) };
+    assert!(PedersenParameters::from_bytes(&degenerate.to_bytes()).is_err());
+}
+
+#[test]
+fn test_key_files() {
+    let dir = std::env::temp_dir().join(format!("zklib-keys-{}", thread_rng().gen::<u64>()));
+    fs::create_dir_all(&dir).unwrap();
+    let key = SchnorrKeyPair::generate(&test_group());
+    let path = dir.join("schnorr.pem");
+    key.save(&path).unwrap();
+    assert_eq!(SchnorrKeyPair::load(&path).unwrap().y, key.y);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    // Saving never overwrites a key
+    assert!(SchnorrKeyPair::generate(&key.params).save(&path).is_err());
+    assert_eq!(SchnorrKeyPair::load(&path).unwrap().y, key.y);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,4 +1,84 @@
+// Prints a party's description of what it is doing, unless narration is turned off with
+// config::set_narration. Defined before the modules so that all of them can use it.
+macro_rules! narrate {
+    ($($arg:tt)*) => {
+        if $crate::config::narration() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod accumulator;
+pub mod audit;
+pub mod batchgi;
+pub mod beacon;
+pub mod challenge;
+pub mod chaumpedersen;
+pub mod circuit;
+pub mod coinflip;
+pub mod coloring;
+pub mod commitandprove;
+pub mod commitment;
+pub mod committed;
+pub mod committedgi;
+pub mod config;
+pub mod connectivity;
+pub mod degree;
+pub mod designated;
+#[cfg(feature = "beacon")]
+pub mod drand;
+pub mod escrow;
+pub mod experiments;
+pub mod field;
+pub mod fri;
+pub mod fuzzing;
+pub mod garbled;
 pub mod graph;
+#[cfg(feature = "groth16")]
+pub mod groth16;
+pub mod hamiltonian;
+#[cfg(feature = "server")]
+pub mod handshake;
+pub mod hash;
+pub mod io;
+pub mod iop;
+pub mod isomorphism;
+pub mod keys;
+pub mod lookup;
+pub mod merkle;
+pub mod modarith;
+pub mod nullifier;
+pub mod okamoto;
+pub mod oracle;
+pub mod ot;
+pub mod params;
+pub mod pedersen;
+pub mod permutation;
+pub mod permutationcommitment;
+pub mod pipeline;
+pub mod plonkish;
+pub mod randomness;
+pub mod registry;
+pub mod repetition;
+pub mod rewind;
+pub mod schnorr;
+pub mod secret;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod session;
+pub mod statement;
+pub mod strategy;
+pub mod sudoku;
+pub mod trace;
+pub mod transcript;
+pub mod transport;
+pub mod voting;
+pub mod vss;
+
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
 pub trait Prover {
     type ProverMessage;
@@ -8,6 +88,43 @@ pub trait Prover {
     // to send to the verifier as well as a 'done' flag denoting whether the interaction
     // is complete.
     fn handle(&mut self, msg: &Self::VerifierMessage) -> (Self::ProverMessage, bool);
+
+    // The runner calls validate before handing the prover a message, so that structurally
+    // invalid messages (e.g. a graph with the wrong number of vertices) are rejected with a
+    // description of the problem instead of reaching handle.
+    fn validate(&self, _msg: &Self::VerifierMessage) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Lends a prover to a runner that takes provers by value, e.g. run_metered, so that it keeps its state
+// for the next interaction
+impl<P: Prover + ?Sized> Prover for &mut P {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        (**self).handle(msg)
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        (**self).validate(msg)
+    }
+}
+
+// A prover whose state between messages can be saved and restored, so that a long proof can be paused by
+// the prover's process, e.g. while a laptop sleeps, and resumed later against a verifier that kept its
+// own state, such as a server session. The state holds whatever the prover needs to answer the next
+// message, which may be secret, so it should be stored like the witness. A snapshot records the digest of
+// the instance it was taken on, so it can't be restored into a prover for another instance.
+pub trait ResumableProver: Prover {
+    type ProverState: Serialize + DeserializeOwned;
+
+    fn snapshot(&self) -> Self::ProverState;
+
+    // Puts the prover back in the state of the snapshot, or explains why the snapshot doesn't fit the
+    // prover's instance, leaving the prover as it was
+    fn restore(&mut self, state: Self::ProverState) -> Result<(), String>;
 }
 
 pub trait Verifier {
@@ -22,24 +139,144 @@ pub trait Verifier {
     // to send to the prover as well as an 'accept' flag denoting whether it accepts or not.
     // If the flag is ever set to true, the verifier accepts the proof.
     fn handle(&mut self, msg: &Self::ProverMessage) -> (Self::VerifierMessage, bool);
+
+    // The runner calls validate before handing the verifier a message, and rejects the proof
+    // if the message is structurally invalid.
+    fn validate(&self, _msg: &Self::ProverMessage) -> Result<(), String> {
+        Ok(())
+    }
+
+    // After the verifier rejects, diagnose_interactive_proof calls diagnose with the last message the
+    // verifier handled, to describe the check that message failed.
+    fn diagnose(&self, _msg: &Self::ProverMessage) -> Option<Diagnosis> {
+        None
+    }
+}
+
+// Structurally invalid message caught by the runner, along with the round it was sent on
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    ProverMessage(u32, String),
+    VerifierMessage(u32, String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::ProverMessage(round, reason) => write!(f, "Prover sent an invalid message on round {}: {}", round, reason),
+            ValidationError::VerifierMessage(round, reason) => write!(f, "Verifier sent an invalid message on round {}: {}", round, reason),
+        }
+    }
+}
+
+// The check a rejected message failed, e.g. "permuted graph != g1", along with details such as a diff of
+// the mismatched graphs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnosis {
+    pub check: String,
+    pub details: Option<String>,
+}
+
+impl Diagnosis {
+    pub fn new(check: &str, details: Option<String>) -> Diagnosis {
+        Diagnosis { check: check.to_string(), details }
+    }
+}
+
+// Round of the last message the verifier handled before rejecting, along with its diagnosis if the
+// verifier gives one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub round: u32,
+    pub diagnosis: Option<Diagnosis>,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Verifier rejected the message sent on round {}", self.round)?;
+        if let Some(diagnosis) = &self.diagnosis {
+            write!(f, ": {}", diagnosis.check)?;
+            if let Some(details) = &diagnosis.details {
+                write!(f, " ({})", details)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Why an interaction didn't end in acceptance
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProofFailure {
+    Invalid(ValidationError),
+    Rejected(Rejection),
+}
+
+impl fmt::Display for ProofFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofFailure::Invalid(e) => write!(f, "{}", e),
+            ProofFailure::Rejected(rejection) => write!(f, "{}", rejection),
+        }
+    }
 }
 
 pub fn run_interactive_proof<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>) -> bool{
 
+    let accept = match diagnose_interactive_proof(prover, verifier) {
+        Ok(()) => true,
+        Err(e) => {
+            narrate!("{}.", e);
+            false
+        }
+    };
+    if accept {
+        narrate!("Proof accepted.");
+    } else {
+        narrate!("Proof rejected.")
+    }
+
+    accept
+}
+
+// Runs the interaction like run_interactive_proof, validating every message before it is handled and
+// stopping at the first invalid one
+pub fn try_run_interactive_proof<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>) -> Result<bool, ValidationError> {
+    run(prover, verifier).map(|(accept, _)| accept)
+}
+
+// Runs the interaction like try_run_interactive_proof, and if the verifier rejects, asks it to diagnose
+// the last message it handled
+pub fn diagnose_interactive_proof<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>) -> Result<(), ProofFailure> {
+    match run(prover, verifier).map_err(ProofFailure::Invalid)? {
+        (true, _) => Ok(()),
+        (false, last) => {
+            let (round, diagnosis) = last.map_or((0, None), |(round, msg)| (round, verifier.diagnose(&msg)));
+            Err(ProofFailure::Rejected(Rejection { round, diagnosis }))
+        },
+    }
+}
+
+// Returns whether the verifier accepted, along with the last message it handled and the round it was sent on
+fn run<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>) -> Result<(bool, Option<(u32, T)>), ValidationError> {
     let mut verifier_msg = verifier.init();
     let mut accept = false;
+    let mut round = 1;
+    let mut last = None;
 
     // Run interaction until prover sets 'done' flag to true, prover must send last message
-    while let (prover_msg, false) = prover.handle(&verifier_msg) {
+    loop {
+        prover.validate(&verifier_msg).map_err(|reason| ValidationError::VerifierMessage(round, reason))?;
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        if done {
+            break;
+        }
+        verifier.validate(&prover_msg).map_err(|reason| ValidationError::ProverMessage(round, reason))?;
         let x = verifier.handle(&prover_msg);
         verifier_msg = x.0;
         accept = x.1;
-    }
-    if accept {
-        println!("Proof accepted.");
-    } else {
-        println!("Proof rejected.")
+        last = Some((round, prover_msg));
+        round += 1;
     }
 
-    accept
-}
\ No newline at end of file
+    Ok((accept, last))
+}
@@ -0,0 +1,434 @@
+use std::collections::HashSet;
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use serde::{Serialize, Deserialize};
+use crate::{Diagnosis, Prover, Verifier};
+use crate::modarith::{mod_exp, mod_inverse};
+use crate::params::{serde_biguint, serde_biguints};
+use crate::pedersen::{PedersenCommitment, PedersenOpening, PedersenParameters};
+use crate::secret::Secret;
+use crate::statement::{Statement, Transparent, Witness};
+use crate::transcript::{ChallengeTranscript, encode_biguints};
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Lookup argument implementation ************
+
+// The prover holds Pedersen commitments C_i to values f_1, ..., f_n and proves that every value appears
+// in a public table t_1, ..., t_N, without revealing which entries they are. Like plookup, the argument
+// reduces the lookup to an identity between multisets checked at a random point, but it uses the
+// logarithmic derivative form of the identity, which needs only commitments to single values rather than
+// commitments to polynomials:
+//
+//   sum_i 1 / (X - f_i) = sum_j m_j / (X - t_j)
+//
+// where m_j counts the values equal to t_j. Over Z_q with n < q, the identity holds as rational functions
+// exactly when every f_i is in the table, and otherwise fails at a random X except with probability
+// (n + N) / q.
+//
+// The prover first commits to the multiplicities m_j, after which the verifier picks a random alpha
+// outside the table. The prover then commits to every inverse u_i = 1 / (alpha - f_i) and proves in a
+// single sigma protocol that each inverse is correct and that the inverses sum to the weighted sum of the
+// multiplicities. With D_i = g^alpha / C_i = g^(alpha - f_i) h^(-r_i), the inverse u_i is correct exactly
+// when D_i^u_i = g h^(-r_i u_i), so the prover shows knowledge of (u_i, s_i, r_i u_i) with
+// U_i = g^u_i h^s_i and g = D_i^u_i h^(r_i u_i). Both sums are homomorphic combinations of commitments,
+// so they are equal when their quotient is a power of h, which the prover shows by knowing its discrete log.
+
+// Commitments to values the prover claims are all in the table
+#[derive(Clone, Debug, PartialEq)]
+pub struct LookupInstance {
+    pub params: PedersenParameters,
+    pub table: Vec<BigUint>,
+    pub commitments: Vec<PedersenCommitment>,
+}
+
+// Openings of the instance's commitments, in the same order
+pub struct LookupWitness {
+    pub openings: Vec<PedersenOpening>,
+}
+
+impl LookupInstance {
+    // Commits to the values, returning the instance along with its witness
+    pub fn commit(params: &PedersenParameters, table: &[BigUint], values: &[BigUint]) -> (LookupInstance, LookupWitness) {
+        let (commitments, openings) = values.iter().map(|x| params.commit(x)).unzip();
+        (LookupInstance { params: params.clone(), table: table.to_vec(), commitments }, LookupWitness { openings })
+    }
+
+    // Weights 1 / (alpha - t_j) of the multiplicities, or None if alpha is in the table
+    fn weights(&self, alpha: &BigUint) -> Option<Vec<BigUint>> {
+        let q = &self.params.group.q;
+        self.table.iter().map(|t| mod_inverse(&((alpha + q - t % q) % q), q)).collect()
+    }
+
+    // Computes D_i = g^alpha / C_i, using that C_i^(q - 1) is the inverse of C_i in the subgroup of order q
+    fn shifted(&self, alpha: &BigUint, commitment: &PedersenCommitment) -> BigUint {
+        let group = &self.params.group;
+        mod_exp(&group.g, alpha, &group.p) * mod_exp(&commitment.c, &(&group.q - 1u32), &group.p) % &group.p
+    }
+}
+
+// Number of values equal to each table entry, counting every value at the first entry equal to it
+fn multiplicities(table: &[BigUint], values: &[BigUint]) -> Vec<BigUint> {
+    let mut counts = vec![0u64; table.len()];
+    for value in values {
+        if let Some(j) = table.iter().position(|t| t == value) {
+            counts[j] += 1;
+        }
+    }
+    counts.into_iter().map(BigUint::from).collect()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum LookupProverMessage {
+    // Commitments M_j to the multiplicity of each table entry
+    Multiplicities(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Commitments U_i to the inverses, and the announcements (a_i, b_i) for each value followed by the
+    // announcement e for the sums
+    Inverses {
+        #[serde(with = "serde_biguints")]
+        inverses: Vec<BigUint>,
+        #[serde(with = "serde_biguints")]
+        announcements: Vec<BigUint>,
+    },
+    // Responses (z_i1, z_i2, z_i3) for each value followed by the response z for the sums
+    Response(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LookupVerifierMessage {
+    // Evaluation point alpha on the first round, and the sigma protocol's challenge on the second
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct LookupProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomness of the multiplicity commitments, followed by the openings and nonces of the sigma protocol
+    pub state: Secret<Vec<BigUint>>,
+    pub instance: &'a LookupInstance,
+    pub witness: &'a LookupWitness,
+}
+
+impl Prover for LookupProver<'_> {
+    type ProverMessage = LookupProverMessage;
+    type VerifierMessage = LookupVerifierMessage;
+
+    fn handle(&mut self, msg: &LookupVerifierMessage) -> (LookupProverMessage, bool) {
+        self.r += 1;
+        let params = &self.instance.params;
+        let (p, q) = (&params.group.p, &params.group.q);
+        let openings = &self.witness.openings;
+        match self.r {
+            // During the first round, the prover commits to the multiplicities
+            1 => {
+                let values: Vec<BigUint> = openings.iter().map(|x| x.value.clone()).collect();
+                let (commitments, randomness): (Vec<_>, Vec<_>) = multiplicities(&self.instance.table, &values).iter().map(|m| {
+                    let (commitment, opening) = params.commit(m);
+                    (commitment.c, (*opening.randomness).clone())
+                }).unzip();
+                self.state = Secret::new(randomness);
+                (LookupProverMessage::Multiplicities(commitments), false)
+            },
+            // During the second round, the prover commits to the inverses at alpha and announces the sigma protocol
+            2 => {
+                let alpha = &msg.c;
+                let weights = self.instance.weights(alpha).expect("Verifier chose alpha in the table");
+                // Randomness of the quotient of the sums, whose discrete log base h the prover shows it knows
+                let mut difference = weights.iter().zip(self.state.iter()).fold(BigUint::zero(), |acc, (w, s)| (acc + q - w * s % q) % q);
+                let mut inverses = Vec::new();
+                let mut announcements = Vec::new();
+                for (opening, commitment) in openings.iter().zip(&self.instance.commitments) {
+                    let u = mod_inverse(&((alpha + q - &opening.value % q) % q), q).expect("Verifier chose alpha equal to a committed value");
+                    let s = params.group.random_exponent();
+                    let v = &*opening.randomness * &u % q;
+                    let (k1, k2, k3) = (params.group.random_exponent(), params.group.random_exponent(), params.group.random_exponent());
+                    inverses.push(params.commit_with_randomness(&u, &s).c);
+                    announcements.push(params.commit_with_randomness(&k1, &k2).c);
+                    announcements.push(mod_exp(&self.instance.shifted(alpha, commitment), &k1, p) * mod_exp(&params.h, &k3, p) % p);
+                    difference = (difference + &s) % q;
+                    self.state.extend(vec![u, s, v, k1, k2, k3]);
+                }
+                let k = params.group.random_exponent();
+                announcements.push(mod_exp(&params.h, &k, p));
+                self.state.extend(vec![difference, k]);
+                (LookupProverMessage::Inverses { inverses, announcements }, false)
+            },
+            // During the third round, the prover masks its openings with the nonces according to the challenge
+            3 => {
+                let c = &msg.c;
+                let state = &self.state[self.instance.table.len()..];
+                let (values, sums) = state.split_at(state.len() - 2);
+                let mut response: Vec<BigUint> = values.chunks(6).flat_map(|x| {
+                    let (u, s, v, k1, k2, k3) = (&x[0], &x[1], &x[2], &x[3], &x[4], &x[5]);
+                    vec![(k1 + c * u) % q, (k2 + c * s) % q, (k3 + c * v) % q]
+                }).collect();
+                response.push((&sums[1] + c * &sums[0]) % q);
+                (LookupProverMessage::Response(response), false)
+            },
+            // After sending its responses, the prover sends a message to terminate the interaction
+            _ => (LookupProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &LookupVerifierMessage) -> Result<(), String> {
+        let group = &self.instance.params.group;
+        match self.r + 1 {
+            // Alpha has to leave every weight and inverse defined, so it can't be a table entry or a committed value
+            2 => {
+                group.check_exponent(&msg.c)?;
+                if self.instance.weights(&msg.c).is_none() {
+                    return Err("alpha is in the table".to_string());
+                }
+                if self.witness.openings.iter().any(|opening| &opening.value % &group.q == msg.c) {
+                    return Err("alpha is one of the committed values".to_string());
+                }
+                Ok(())
+            },
+            3 => group.check_exponent(&msg.c),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct LookupVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub multiplicities: Vec<BigUint>,
+    pub alpha: BigUint,
+    pub inverses: Vec<BigUint>,
+    pub announcements: Vec<BigUint>,
+    pub challenge: BigUint,
+    // Running transcript from which challenges are derived
+    pub transcript: ChallengeTranscript,
+    pub instance: &'a LookupInstance,
+}
+
+impl LookupVerifier<'_> {
+    pub fn new(instance: &LookupInstance) -> LookupVerifier<'_> {
+        LookupVerifier {
+            r: 0,
+            multiplicities: Vec::new(),
+            alpha: BigUint::zero(),
+            inverses: Vec::new(),
+            announcements: Vec::new(),
+            challenge: BigUint::zero(),
+            transcript: ChallengeTranscript::new(b"lookup"),
+            instance,
+        }
+    }
+
+    // Checks the responses, describing the first equation that fails
+    fn check(&self, response: &[BigUint]) -> Result<(), String> {
+        let params = &self.instance.params;
+        let (p, q) = (&params.group.p, &params.group.q);
+        let c = &self.challenge;
+        if let Some(i) = self.instance.commitments.iter().position(|x| params.group.check_element(&x.c).is_err()) {
+            return Err(format!("commitment {} is not in the group", i));
+        }
+        let weights = self.instance.weights(&self.alpha).ok_or("alpha is in the table")?;
+        for (i, (z, a)) in response.chunks_exact(3).zip(self.announcements.chunks_exact(2)).enumerate() {
+            let inverse = &self.inverses[i];
+            if params.commit_with_randomness(&z[0], &z[1]).c != &a[0] * mod_exp(inverse, c, p) % p {
+                return Err(format!("g^z1 h^z2 != a U^c for value {}", i));
+            }
+            let shifted = self.instance.shifted(&self.alpha, &self.instance.commitments[i]);
+            if mod_exp(&shifted, &z[0], p) * mod_exp(&params.h, &z[2], p) % p != &a[1] * mod_exp(&params.group.g, c, p) % p {
+                return Err(format!("D^z1 h^z3 != b g^c for value {}", i));
+            }
+        }
+        // Quotient of the commitment to the sum of the inverses by the commitment to the weighted multiplicities
+        let inverses = self.inverses.iter().fold(BigUint::one(), |acc, x| acc * x % p);
+        let weighted = self.multiplicities.iter().zip(&weights).fold(BigUint::one(), |acc, (m, w)| acc * mod_exp(m, w, p) % p);
+        let quotient = inverses * mod_exp(&weighted, &(q - 1u32), p) % p;
+        let (z, e) = (response.last().unwrap(), self.announcements.last().unwrap());
+        if mod_exp(&params.h, z, p) != e * mod_exp(&quotient, c, p) % p {
+            return Err("h^z != e (sum U / sum M^w)^c".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Verifier for LookupVerifier<'_> {
+    type ProverMessage = LookupProverMessage;
+    type VerifierMessage = LookupVerifierMessage;
+
+    fn init(&mut self) -> LookupVerifierMessage {
+        narrate!("Initializing lookup of {} values in a table of {}.", self.instance.commitments.len(), self.instance.table.len());
+        self.transcript.append_message(b"instance", &self.instance.encode());
+        LookupVerifierMessage { c: BigUint::zero() }
+    }
+
+    fn validate(&self, msg: &LookupProverMessage) -> Result<(), String> {
+        let group = &self.instance.params.group;
+        let (n, size) = (self.instance.commitments.len(), self.instance.table.len());
+        let check_lengths = |name: &str, xs: &[BigUint], expected: usize| match xs.len() {
+            len if len == expected => Ok(()),
+            len => Err(format!("expected {} {}, found {}", expected, name, len)),
+        };
+        match (self.r + 1, msg) {
+            (1, LookupProverMessage::Multiplicities(ms)) => {
+                check_lengths("multiplicities", ms, size)?;
+                ms.iter().try_for_each(|x| group.check_element(x))
+            },
+            (1, _) => Err("expected multiplicities".to_string()),
+            (2, LookupProverMessage::Inverses { inverses, announcements }) => {
+                check_lengths("inverses", inverses, n)?;
+                check_lengths("announcements", announcements, 2 * n + 1)?;
+                inverses.iter().chain(announcements).try_for_each(|x| group.check_element(x))
+            },
+            (2, _) => Err("expected inverses".to_string()),
+            (_, LookupProverMessage::Response(zs)) => {
+                check_lengths("responses", zs, 3 * n + 1)?;
+                zs.iter().try_for_each(|x| group.check_exponent(x))
+            },
+            _ => Err("expected responses".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &LookupProverMessage) -> Option<Diagnosis> {
+        match msg {
+            LookupProverMessage::Response(response) => self.check(response).err().map(|check| Diagnosis::new(&check, None)),
+            _ => None,
+        }
+    }
+
+    fn handle(&mut self, msg: &LookupProverMessage) -> (LookupVerifierMessage, bool) {
+        self.r += 1;
+        let q = &self.instance.params.group.q;
+        match (self.r, msg) {
+            (1, LookupProverMessage::Multiplicities(ms)) => {
+                self.multiplicities = ms.clone();
+                ms.iter().for_each(|x| self.transcript.append_biguint(b"multiplicity", x));
+                // Alpha must be outside the table, which a random alpha is except with probability N / q
+                let table: HashSet<BigUint> = self.instance.table.iter().map(|t| t % q).collect();
+                self.alpha = self.transcript.verifier_challenge_scalar(b"alpha", q);
+                while table.contains(&self.alpha) {
+                    self.alpha = self.transcript.verifier_challenge_scalar(b"alpha", q);
+                }
+                narrate!("Verifier chose alpha {}.", &self.alpha);
+                (LookupVerifierMessage { c: self.alpha.clone() }, false)
+            },
+            (2, LookupProverMessage::Inverses { inverses, announcements }) => {
+                self.inverses = inverses.clone();
+                self.announcements = announcements.clone();
+                inverses.iter().for_each(|x| self.transcript.append_biguint(b"inverse", x));
+                announcements.iter().for_each(|x| self.transcript.append_biguint(b"announcement", x));
+                self.challenge = self.transcript.verifier_challenge_scalar(b"challenge", q);
+                narrate!("Verifier chose challenge {}.", &self.challenge);
+                (LookupVerifierMessage { c: self.challenge.clone() }, false)
+            },
+            (_, LookupProverMessage::Response(response)) => {
+                response.iter().for_each(|x| self.transcript.append_biguint(b"response", x));
+                (LookupVerifierMessage { c: self.challenge.clone() }, self.check(response).is_ok())
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+impl Witness for LookupWitness {}
+
+impl Statement for LookupInstance {
+    type Witness = LookupWitness;
+
+    fn protocol(&self) -> &'static str {
+        "lookup"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let group = &self.params.group;
+        let size = BigUint::from(self.table.len());
+        let mut values = vec![&group.p, &group.q, &group.g, &self.params.h, &size];
+        values.extend(&self.table);
+        values.extend(self.commitments.iter().map(|x| &x.c));
+        encode_biguints(&values)
+    }
+
+    fn validate(&self, witness: &LookupWitness) -> Result<(), String> {
+        if witness.openings.len() != self.commitments.len() {
+            return Err(format!("Expected {} openings. Found {} openings.", self.commitments.len(), witness.openings.len()));
+        }
+        self.table.iter().try_for_each(|t| self.params.group.check_exponent(t))?;
+        for (i, (opening, commitment)) in witness.openings.iter().zip(&self.commitments).enumerate() {
+            if !commitment.verify(&self.params, opening) {
+                return Err(format!("Opening {} does not open its commitment", i));
+            }
+            if !self.table.contains(&opening.value) {
+                return Err(format!("Value {} is not in the table", i));
+            }
+        }
+        Ok(())
+    }
+
+    fn run_proof(&self, witness: &LookupWitness) -> bool {
+        let mut prover = LookupProver { r: 0, state: Secret::default(), instance: self, witness };
+        crate::run_interactive_proof(&mut prover, &mut LookupVerifier::new(self))
+    }
+}
+
+impl Transparent for LookupInstance {}
+
+#[cfg(test)]
+fn sample_table() -> Vec<BigUint> {
+    [1u32, 2, 3, 5, 8, 13, 21].iter().map(|&x| BigUint::from(x)).collect()
+}
+
+#[test]
+fn test_lookup() {
+    let params = PedersenParameters::generate(64);
+    let values: Vec<BigUint> = [3u32, 3, 21, 1, 8].iter().map(|&x| BigUint::from(x)).collect();
+    let (instance, witness) = LookupInstance::commit(&params, &sample_table(), &values);
+    assert_eq!(instance.validate(&witness), Ok(()));
+    assert_eq!(crate::statement::prove(&instance, &witness), Ok(true));
+    // An empty lookup is trivially in the table
+    let (empty, witness) = LookupInstance::commit(&params, &sample_table(), &[]);
+    assert_eq!(crate::statement::prove(&empty, &witness), Ok(true));
+}
+
+#[test]
+fn test_lookup_missing_value() {
+    let params = PedersenParameters::generate(64);
+    let values: Vec<BigUint> = [3u32, 4, 21].iter().map(|&x| BigUint::from(x)).collect();
+    let (instance, witness) = LookupInstance::commit(&params, &sample_table(), &values);
+    assert_eq!(instance.validate(&witness), Err("Value 1 is not in the table".to_string()));
+    // A prover that runs anyway can't make the sums agree
+    let mut prover = LookupProver { r: 0, state: Secret::default(), instance: &instance, witness: &witness };
+    let mut verifier = LookupVerifier::new(&instance);
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+    let mut prover = LookupProver { r: 0, state: Secret::default(), instance: &instance, witness: &witness };
+    match crate::diagnose_interactive_proof(&mut prover, &mut LookupVerifier::new(&instance)) {
+        Err(crate::ProofFailure::Rejected(rejection)) => assert_eq!(rejection.diagnosis.unwrap().check, "h^z != e (sum U / sum M^w)^c"),
+        _ => panic!("Verifier should reject the sums"),
+    }
+}
+
+#[test]
+fn test_lookup_wrong_opening() {
+    let params = PedersenParameters::generate(64);
+    let values = vec![BigUint::from(5u32)];
+    let (instance, mut witness) = LookupInstance::commit(&params, &sample_table(), &values);
+    // Opening the commitment to another table entry fails the inverse check
+    witness.openings[0].value = BigUint::from(8u32);
+    assert!(instance.validate(&witness).is_err());
+    let mut prover = LookupProver { r: 0, state: Secret::default(), instance: &instance, witness: &witness };
+    assert!(!run_interactive_proof(&mut prover, &mut LookupVerifier::new(&instance)));
+}
+
+#[test]
+fn test_lookup_invalid_alpha() {
+    let params = PedersenParameters::generate(64);
+    let values: Vec<BigUint> = [3u32, 4].iter().map(|&x| BigUint::from(x)).collect();
+    let (instance, witness) = LookupInstance::commit(&params, &sample_table(), &values);
+    let mut prover = LookupProver { r: 0, state: Secret::default(), instance: &instance, witness: &witness };
+    prover.handle(&LookupVerifierMessage { c: BigUint::zero() });
+    // An alpha the prover can't invert at is refused rather than panicking in handle
+    let alpha = |x: u32| LookupVerifierMessage { c: BigUint::from(x) };
+    assert_eq!(prover.validate(&alpha(5)), Err("alpha is in the table".to_string()));
+    assert_eq!(prover.validate(&alpha(4)), Err("alpha is one of the committed values".to_string()));
+    assert!(prover.validate(&LookupVerifierMessage { c: params.group.q.clone() }).is_err());
+    assert_eq!(prover.validate(&alpha(6)), Ok(()));
+}
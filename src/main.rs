@@ -1,3 +1,781 @@
+use std::{fs, io, path::PathBuf, process};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use serde_json::json;
+use zklib::audit::verify_log;
+use zklib::config::{ConfigFile, LogLevel};
+#[cfg(feature = "server")]
+use zklib::config::{HttpTransport, ServerConfig};
+use zklib::experiments::{analyze, bench_isomorphism, check_isomorphic, check_vertex_counts, gni_timing_instance, measure_gni_timing, soundness_vs_bias, soundness_vs_bias_on, soundness_vs_rounds, soundness_vs_rounds_on, Growth, IsomorphismRun, Report, TimingReport, ANALYZED_PROTOCOLS, BENCHED_PROTOCOLS, LEAKAGE_THRESHOLD};
+use zklib::graph::{GIProver, GIProverMalicious, GIVerifier, Graph, GraphPair};
+use zklib::graph::{SearchBudget, MAX_VERTICES};
+use zklib::io::{load_dataset, load_instance, save_instance};
+use zklib::isomorphism::DecisionMode;
+use zklib::keys::{ElGamalKeyPair, KeyMaterial};
+use zklib::params::{Parameters, SchnorrParameters};
+use zklib::pedersen::PedersenParameters;
+use zklib::registry::StatementRegistry;
+use zklib::schnorr::SchnorrKeyPair;
+#[cfg(feature = "server")]
+use zklib::audit::AuditLog;
+#[cfg(feature = "server")]
+use zklib::server::{Listener, Shutdown};
+#[cfg(feature = "server")]
+use zklib::session::SessionStore;
+use zklib::trace::trace_gi;
+
+// Groups are generated with safe primes of 2048 bits unless asked otherwise, and between 64 and 8192 bits
+const DEFAULT_BITS: u64 = 2048;
+const MIN_BITS: u64 = 64;
+const MAX_BITS: u64 = 8192;
+
+#[derive(Parser)]
+#[command(name = "zklib", about = "Zero knowledge proofs and interactive protocols")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[arg(long, global = true, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true",
+        help = "Apply provers' secret permutations with data-independent memory accesses, or not with --constant-time=false")]
+    constant_time: Option<bool>,
+    #[arg(long, global = true, help = "Most threads to run parallel work such as experiment trials on [default: one per core]")]
+    threads: Option<usize>,
+    #[arg(long, global = true, value_parser = ["error", "warn", "info", "debug"], help = "How much to log to stderr [default: info]")]
+    log_level: Option<String>,
+    #[arg(long, global = true, help = "Configuration file to load [default: $ZKLIB_CONFIG, or zklib.toml if there is one]")]
+    config: Option<PathBuf>,
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text, help = "Print results for people to read, or as JSON for scripts")]
+    format: Format,
+}
+
+// How a command prints its result. JSON output is a single document on stdout, with protocols' narration
+// turned off and errors printed as {"error": ...}.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Measure how acceptance of honest and cheating provers changes with the number of repetitions")]
+    Bench(BenchArgs),
+    #[command(about = "Time isomorphism decisions on a directory of DIMACS benchmark graphs")]
+    BenchIsomorphism(BenchIsomorphismArgs),
+    #[command(about = "Report how proof size, rounds, and prover and verifier time grow with the instance size")]
+    Analyze(AnalyzeArgs),
+    #[command(about = "Test whether the GNI parties' response times leak the verifier's secret bit, optionally padding every response")]
+    Timing(TimingArgs),
+    #[command(about = "Run a GI proof and write its trace as JSON lines, one event per line")]
+    Trace(TraceArgs),
+    #[cfg(feature = "server")]
+    #[command(about = "Serve an HTTP endpoint that verifies non-interactive proofs")]
+    Serve(ServeArgs),
+    #[command(subcommand, about = "Inspect the server's audit log")]
+    Audit(AuditCommand),
+    #[command(subcommand, about = "Register, list, and fetch named statements in a registry directory")]
+    Registry(RegistryCommand),
+    #[command(about = "Generate a group for number-theoretic protocols and write it as JSON")]
+    Params(ParamsArgs),
+    #[command(about = "Generate a key and write it as PEM, with the public half of a key pair next to it")]
+    Keygen(KeygenArgs),
+    #[cfg(feature = "server")]
+    #[command(about = "Sign a message with a Schnorr key, writing a request body for /verify/schnorr-signature")]
+    Sign(SignArgs),
+    #[command(about = "Write a completion script for the shell to stdout, e.g. zklib completions bash > /etc/bash_completion.d/zklib")]
+    Completions {
+        #[arg(help = "Shell to complete in")]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    #[command(about = "Check that every entry of an audit log is intact and chained to the one before it")]
+    Verify {
+        #[arg(help = "Audit log to check")]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegistryCommand {
+    #[command(about = "Register a GI instance under a name, so sessions can refer to it instead of uploading it")]
+    Register {
+        #[arg(long, help = "Registry directory, e.g. the one a server was started with --registry")]
+        dir: PathBuf,
+        #[arg(help = "Name to register the instance under")]
+        name: String,
+        #[arg(help = "Instance to register, as .json, .bin, .msgpack, or an edge list .txt")]
+        instance: PathBuf,
+    },
+    #[command(about = "List the registered statements")]
+    List {
+        #[arg(long, help = "Registry directory")]
+        dir: PathBuf,
+    },
+    #[command(about = "Write a registered statement's instance to a file")]
+    Fetch {
+        #[arg(long, help = "Registry directory")]
+        dir: PathBuf,
+        #[arg(help = "Name or digest of the statement")]
+        id: String,
+        #[arg(help = "File to write the instance to, as .json, .bin, .msgpack, or an edge list .txt")]
+        output: PathBuf,
+    },
+}
+
+#[derive(clap::Args)]
+struct BenchArgs {
+    #[arg(long, default_value = "gi", value_parser = clap::builder::PossibleValuesParser::new(BENCHED_PROTOCOLS))]
+    protocol: String,
+    #[arg(long, default_value_t = 8, help = "Largest number of sequential repetitions to measure")]
+    max_rounds: u32,
+    #[arg(long, default_value_t = 1000, help = "Trials per configuration")]
+    trials: usize,
+    #[arg(long, help = "Write the report as CSV")]
+    csv: Option<PathBuf>,
+    #[arg(long, help = "Write the report as JSON")]
+    json: Option<PathBuf>,
+    #[arg(long, help = "Render soundness-vs-rounds curves as SVG (requires the plot feature)")]
+    plot: Option<PathBuf>,
+    #[arg(long, requires = "nonisomorphic", help = "Isomorphic instance to run on, as .json, .bin, .msgpack, or an edge list .txt")]
+    isomorphic: Option<PathBuf>,
+    #[arg(long, requires = "isomorphic", help = "Non-isomorphic instance to run on, in the same formats")]
+    nonisomorphic: Option<PathBuf>,
+    #[arg(long, value_delimiter = ',', help = "Measure cheating GI provers against verifiers challenging with g1 with these probabilities instead, e.g. 0.5,0.75,0.9")]
+    bias: Vec<f64>,
+}
+
+#[derive(clap::Args)]
+struct BenchIsomorphismArgs {
+    #[arg(long, help = "Directory of .dimacs, .col, or .bliss graphs, e.g. from the bliss or nauty benchmark collections")]
+    dataset: PathBuf,
+    #[arg(long, default_value = "budgeted", value_parser = ["exact", "wl", "budgeted"], help = "How hard to try deciding each graph")]
+    mode: String,
+    #[arg(long, help = "Most search nodes per graph in budgeted mode [default: 10000000]")]
+    max_nodes: Option<u64>,
+    #[arg(long, help = "Most seconds per graph in budgeted mode")]
+    seconds: Option<f64>,
+    #[arg(long, help = "Write the results as JSON")]
+    json: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct AnalyzeArgs {
+    #[arg(long, default_value = "gi", value_parser = clap::builder::PossibleValuesParser::new(ANALYZED_PROTOCOLS))]
+    protocol: String,
+    #[arg(long, value_delimiter = ',', default_values_t = [10, 100, 1000], help = "Instance sizes to run, e.g. vertices for GI or the degree bound for FRI")]
+    sizes: Vec<usize>,
+    #[arg(long, default_value_t = 10, help = "Trials per size")]
+    trials: usize,
+    #[arg(long, help = "Write the growth curves as CSV")]
+    csv: Option<PathBuf>,
+    #[arg(long, help = "Write the growth curves as JSON")]
+    json: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct TimingArgs {
+    #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(u32).range(2..=MAX_VERTICES as i64), help = "Vertices of the random non-isomorphic instance to run on")]
+    vertices: u32,
+    #[arg(long, conflicts_with = "vertices", help = "Non-isomorphic instance to run on instead, as .json, .bin, .msgpack, or an edge list .txt")]
+    instance: Option<PathBuf>,
+    #[arg(long, default_value_t = 1000, help = "Trials, each timing one response from each party")]
+    trials: usize,
+    #[arg(long, help = "Pad every response to this many milliseconds")]
+    pad_ms: Option<f64>,
+    #[arg(long, help = "Time a prover that balances its work by canonicalizing both graphs")]
+    canonical: bool,
+    #[arg(long, help = "Write the report as JSON")]
+    json: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct TraceArgs {
+    #[arg(help = "File to write the trace to")]
+    output: PathBuf,
+    #[arg(long, help = "Trace a prover that guesses the challenge instead of an honest prover")]
+    cheating: bool,
+    #[arg(long, help = "GI instance to run on, as .json, .bin, .msgpack, or an edge list .txt")]
+    instance: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct ParamsArgs {
+    #[arg(long, default_value_t = DEFAULT_BITS, value_parser = clap::value_parser!(u64).range(MIN_BITS..=MAX_BITS), help = "Bits of the group's modulus")]
+    bits: u64,
+    #[arg(help = "File to write the parameters to")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct KeygenArgs {
+    #[arg(long, value_parser = ["schnorr", "pedersen", "elgamal"], help = "Kind of key to generate")]
+    scheme: String,
+    #[arg(long, conflicts_with = "bits", help = "Parameters written by zklib params to generate the key in")]
+    params: Option<PathBuf>,
+    #[arg(long, value_parser = clap::value_parser!(u64).range(MIN_BITS..=MAX_BITS), help = "Bits of a fresh group to generate the key in, without --params [default: 2048]")]
+    bits: Option<u64>,
+    #[arg(help = "File to write the key to, with the public key of a key pair written to the same path with .pub appended")]
+    output: PathBuf,
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args)]
+struct SignArgs {
+    #[arg(long, help = "Schnorr secret key written by zklib keygen")]
+    key: PathBuf,
+    #[arg(long, help = "Message to sign")]
+    message: String,
+    #[arg(help = "File to write the request body to")]
+    output: PathBuf,
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    #[arg(long, help = "Address to listen on [default: 127.0.0.1:8080]")]
+    listen: Option<String>,
+    #[arg(long, help = "Largest request body in bytes [default: 1 MiB]")]
+    max_body_bytes: Option<usize>,
+    #[arg(long, help = "Requests from one IP address verified at the same time [default: 4]")]
+    max_in_flight: Option<usize>,
+    #[arg(long, help = "Sustained requests per second from one IP address [default: 10]")]
+    requests_per_second: Option<f64>,
+    #[arg(long, help = "Largest modulus in bits [default: 4096]")]
+    max_modulus_bits: Option<u64>,
+    #[arg(long, help = "Largest session instance in bytes of memory [default: 16 MiB]")]
+    max_instance_bytes: Option<usize>,
+    #[arg(long, help = "Make clients solve a puzzle with this many leading zero bits before each session [default: 0, no puzzle]")]
+    work_bits: Option<u32>,
+    #[arg(long, help = "Directory to save interactive sessions in, so they survive restarts")]
+    state_dir: Option<PathBuf>,
+    #[arg(long, help = "Seconds a session lasts after its last message [default: 3600]")]
+    session_ttl: Option<u64>,
+    #[arg(long, help = "Append every verdict to this hash-chained audit log")]
+    audit_log: Option<PathBuf>,
+    #[arg(long, help = "Directory of registered statements that sessions can refer to by name")]
+    registry: Option<PathBuf>,
+    #[arg(long, help = "Only serve clients authenticating as one of the identities in this JSON file")]
+    identities: Option<PathBuf>,
+    #[arg(long, help = "Answer liveness probes at /healthz and readiness probes at /readyz on this port")]
+    health_port: Option<u16>,
+    #[arg(long, help = "Seconds to let sessions under way finish after SIGTERM before shutting down [default: 30]")]
+    drain_timeout: Option<u64>,
+}
+
 fn main() {
+    let cli = Cli::parse();
+    let format = cli.format;
+    // Completion scripts don't depend on the configuration, so a broken one can't keep them from being written
+    if let Command::Completions { shell } = cli.command {
+        clap_complete::generate(shell, &mut Cli::command(), "zklib", &mut io::stdout());
+        return;
+    }
+    let config = ConfigFile::discover(cli.config.as_deref(), |name| std::env::var(name).ok()).unwrap_or_else(|e| fail(format, &e));
+    // Flags take precedence over the configuration
+    config.apply();
+    if let Some(enabled) = cli.constant_time {
+        zklib::config::set_constant_time(enabled);
+    }
+    if let Some(threads) = cli.threads {
+        zklib::config::set_parallelism(threads);
+    }
+    if let Some(level) = &cli.log_level {
+        zklib::config::set_log_level(level.parse().unwrap());
+    }
+    zklib::config::set_narration(format == Format::Text);
+    let result = match cli.command {
+        Command::Bench(args) => bench(&args, format),
+        Command::BenchIsomorphism(args) => bench_isomorphism_dataset(&args, format),
+        Command::Analyze(args) => analyze_growth(&args, format),
+        Command::Timing(args) => timing(&args, format),
+        Command::Trace(args) => trace(&args, format),
+        #[cfg(feature = "server")]
+        Command::Serve(args) => serve(&args, &config.server, format),
+        Command::Audit(AuditCommand::Verify { path }) => audit_verify(&path, format),
+        Command::Registry(command) => registry(&command, format),
+        Command::Params(args) => params(&args, format),
+        Command::Keygen(args) => keygen(&args, format),
+        #[cfg(feature = "server")]
+        Command::Sign(args) => sign(&args, format),
+        Command::Completions { .. } => Ok(()),
+    };
+    if let Err(e) = result {
+        fail(format, &e);
+    }
+}
+
+// Prints the command's result in the chosen format, building the JSON only if it is asked for
+fn emit(format: Format, json: impl FnOnce() -> String, text: impl FnOnce()) {
+    match format {
+        Format::Text => text(),
+        Format::Json => println!("{}", json()),
+    }
+}
+
+fn fail(format: Format, e: &str) -> ! {
+    match format {
+        Format::Text => eprintln!("error: {}", e),
+        Format::Json => println!("{}", json!({ "error": e })),
+    }
+    process::exit(1);
+}
+
+// ************ Bench implementation ************
+
+fn bench(args: &BenchArgs, format: Format) -> Result<(), String> {
+    // Check for the plot feature before spending time on trials
+    if args.plot.is_some() && !cfg!(feature = "plot") {
+        return Err("--plot requires zklib to be built with the plot feature".to_string());
+    }
+    if !args.bias.is_empty() && args.protocol != "gi" {
+        return Err("--bias only applies to gi".to_string());
+    }
+    let report = match (&args.isomorphic, &args.nonisomorphic) {
+        (_, Some(nonisomorphic)) if !args.bias.is_empty() => soundness_vs_bias_on(&load_instance(nonisomorphic)?, &args.bias, args.max_rounds, args.trials)?,
+        _ if !args.bias.is_empty() => soundness_vs_bias(&args.bias, args.max_rounds, args.trials)?,
+        (Some(isomorphic), Some(nonisomorphic)) => {
+            soundness_vs_rounds_on(&args.protocol, &load_instance(isomorphic)?, &load_instance(nonisomorphic)?, args.max_rounds, args.trials)?
+        }
+        _ => soundness_vs_rounds(&args.protocol, args.max_rounds, args.trials)?,
+    };
+    let write = |path: &PathBuf, contents: String| fs::write(path, contents).map_err(|e| format!("failed to write {}: {}", path.display(), e));
+    if let Some(path) = &args.csv {
+        write(path, report.to_csv())?;
+    }
+    if let Some(path) = &args.json {
+        write(path, report.to_json())?;
+    }
+    #[cfg(feature = "plot")]
+    if let Some(path) = &args.plot {
+        report.plot(&format!("{} soundness vs. repetitions", args.protocol.to_uppercase()), path)?;
+    }
+    emit(format, || report.to_json(), || print_summary(&report));
+    Ok(())
+}
+
+fn print_summary(report: &Report) {
+    // Prover and verifier columns are mean CPU milliseconds per trial
+    println!("\n{:<16} {:>6} {:>10} {:>19} {:>10} {:>8} {:>10} {:>11}", "configuration", "rounds", "accepted", "99% interval", "bytes", "seconds", "prover ms", "verifier ms");
+    for m in &report.measurements {
+        let (lower, upper) = m.trials.confidence_interval();
+        println!("{:<16} {:>6} {:>10.4} {:>8.4} - {:>8.4} {:>10.1} {:>8.3} {:>10.3} {:>11.3}", m.name, m.rounds, m.trials.acceptance_rate(), lower, upper, m.message_bytes, m.trials.seconds,
+            m.prover_time.cpu * 1e3, m.verifier_time.cpu * 1e3);
+    }
 }
 
+fn bench_isomorphism_dataset(args: &BenchIsomorphismArgs, format: Format) -> Result<(), String> {
+    let max_time = args.seconds.map(|seconds| match std::time::Duration::try_from_secs_f64(seconds) {
+        Ok(limit) if seconds > 0.0 => Ok(limit),
+        _ => Err(format!("limit {} is not a positive number of seconds", seconds)),
+    }).transpose()?;
+    let mode = match args.mode.as_str() {
+        "exact" => DecisionMode::Exact,
+        "wl" => DecisionMode::WLOnly,
+        _ if args.max_nodes.is_none() && max_time.is_none() => DecisionMode::default(),
+        _ => DecisionMode::Budgeted(SearchBudget { max_nodes: args.max_nodes, max_time }),
+    };
+    let dataset = load_dataset(&args.dataset)?;
+    if dataset.is_empty() {
+        return Err(format!("no DIMACS graphs in {}", args.dataset.display()));
+    }
+    let runs = bench_isomorphism(&dataset, mode);
+    if let Some(path) = &args.json {
+        let json = serde_json::to_string_pretty(&runs).unwrap();
+        fs::write(path, json).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    }
+    emit(format, || serde_json::to_string_pretty(&runs).unwrap(), || print_isomorphism_runs(&runs));
+    Ok(())
+}
+
+fn print_isomorphism_runs(runs: &[IsomorphismRun]) {
+    println!("{:<32} {:>9} {:>10} {:>15} {:>10}", "graph", "vertices", "edges", "decision", "seconds");
+    for run in runs {
+        println!("{:<32} {:>9} {:>10} {:>15} {:>10.4}", run.name, run.vertices, run.edges, run.decision, run.seconds);
+    }
+    let settled = runs.iter().filter(|run| run.decision == "isomorphic").count();
+    println!("\nSettled {} of {} graphs in {:.3} seconds.", settled, runs.len(), runs.iter().map(|run| run.seconds).sum::<f64>());
+}
+
+// ************ Analyze implementation ************
+
+fn analyze_growth(args: &AnalyzeArgs, format: Format) -> Result<(), String> {
+    let growth = analyze(&args.protocol, &args.sizes, args.trials)?;
+    let write = |path: &PathBuf, contents: String| fs::write(path, contents).map_err(|e| format!("failed to write {}: {}", path.display(), e));
+    if let Some(path) = &args.csv {
+        write(path, growth.to_csv())?;
+    }
+    if let Some(path) = &args.json {
+        write(path, growth.to_json())?;
+    }
+    emit(format, || growth.to_json(), || print_growth(&growth));
+    Ok(())
+}
+
+fn print_growth(growth: &Growth) {
+    // Time columns are mean CPU milliseconds per trial
+    println!("\n{:<10} {:>8} {:>9} {:>7} {:>12} {:>10} {:>11}", "protocol", "size", "accepted", "rounds", "proof bytes", "prover ms", "verifier ms");
+    for p in &growth.points {
+        println!("{:<10} {:>8} {:>4}/{:<4} {:>7.1} {:>12.1} {:>10.3} {:>11.3}", growth.protocol, p.size, p.accepted, p.trials, p.rounds, p.proof_bytes,
+            p.prover_time.cpu * 1e3, p.verifier_time.cpu * 1e3);
+    }
+    let exponent = |x: Option<f64>| x.map_or("-".to_string(), |x| format!("{:.2}", x));
+    println!("\nGrowth exponents: rounds {}, proof bytes {}, prover time {}, verifier time {}.", exponent(growth.exponent(|p| p.rounds)),
+        exponent(growth.exponent(|p| p.proof_bytes)), exponent(growth.exponent(|p| p.prover_time.cpu)), exponent(growth.exponent(|p| p.verifier_time.cpu)));
+}
+
+// ************ Timing implementation ************
+
+fn timing(args: &TimingArgs, format: Format) -> Result<(), String> {
+    let instance = match &args.instance {
+        Some(path) => load_instance(path)?,
+        None => gni_timing_instance(args.vertices),
+    };
+    let pad = match args.pad_ms {
+        Some(ms) if !(ms > 0.0 && ms.is_finite()) => return Err(format!("pad {} is not a positive number of milliseconds", ms)),
+        pad => pad.map(|ms| std::time::Duration::from_secs_f64(ms / 1e3)),
+    };
+    let report = measure_gni_timing(&instance, args.trials, pad, args.canonical)?;
+    if let Some(path) = &args.json {
+        fs::write(path, report.to_json()).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    }
+    emit(format, || report.to_json(), || print_timing(&report));
+    Ok(())
+}
+
+fn print_timing(report: &TimingReport) {
+    // Time columns are mean wall-clock microseconds per response
+    println!("\n{:<10} {:>12} {:>12} {:>10} {:>9} {:>8}", "party", "b = 0 us", "b = 1 us", "t", "overruns", "leaks");
+    for leakage in [&report.verifier, &report.prover].iter() {
+        let [zero, one] = &leakage.classes;
+        println!("{:<10} {:>12.1} {:>12.1} {:>10.2} {:>9} {:>8}", leakage.party, zero.mean * 1e6, one.mean * 1e6, leakage.t, leakage.overruns, if leakage.leaks() { "yes" } else { "no" });
+    }
+    match report.pad {
+        Some(pad) => println!("\nPadded every response to {} ms over {} trials; |t| above {} means a leak.", pad * 1e3, report.trials, LEAKAGE_THRESHOLD),
+        None => println!("\nRan {} trials without padding; |t| above {} means a leak.", report.trials, LEAKAGE_THRESHOLD),
+    }
+}
+
+// ************ Trace implementation ************
+
+fn trace(args: &TraceArgs, format: Format) -> Result<(), String> {
+    let instance = match &args.instance {
+        Some(path) => load_instance(path)?,
+        None => GraphPair {
+            g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+            g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+        },
+    };
+    // An honest prover needs an isomorphism to prove, while a cheating one only needs graphs the verifier can compare
+    if !args.cheating {
+        check_isomorphic(&instance)?;
+    } else {
+        check_vertex_counts(&instance)?;
+    }
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let trace = if args.cheating {
+        trace_gi(&mut GIProverMalicious::new(&instance), &mut verifier)
+    } else {
+        trace_gi(&mut GIProver::new(&instance), &mut verifier)
+    };
+    fs::write(&args.output, trace.to_json_lines()).map_err(|e| format!("failed to write {}: {}", args.output.display(), e))?;
+    emit(format, || serde_json::to_string_pretty(&json!({ "accepted": trace.accepted(), "output": args.output, "events": trace.events })).unwrap(),
+        || println!("Wrote {} events to {}.", trace.events.len(), args.output.display()));
+    Ok(())
+}
+
+// ************ Serve implementation ************
+
+#[cfg(feature = "server")]
+fn serve(args: &ServeArgs, config: &ServerConfig, format: Format) -> Result<(), String> {
+    let (file, defaults) = (&config.limits, zklib::server::Limits::default());
+    let limits = zklib::server::Limits {
+        max_body_bytes: args.max_body_bytes.or(file.max_body_bytes).unwrap_or(defaults.max_body_bytes),
+        max_in_flight_per_ip: args.max_in_flight.or(file.max_in_flight_per_ip).unwrap_or(defaults.max_in_flight_per_ip),
+        requests_per_second: args.requests_per_second.or(file.requests_per_second).unwrap_or(defaults.requests_per_second),
+        burst: file.burst.unwrap_or(defaults.burst),
+        max_modulus_bits: args.max_modulus_bits.or(file.max_modulus_bits).unwrap_or(defaults.max_modulus_bits),
+        max_statements: file.max_statements.unwrap_or(defaults.max_statements),
+        max_range_bits: file.max_range_bits.unwrap_or(defaults.max_range_bits),
+        max_vertices: file.max_vertices.unwrap_or(defaults.max_vertices),
+        max_instance_bytes: args.max_instance_bytes.or(file.max_instance_bytes).unwrap_or(defaults.max_instance_bytes),
+        max_repetitions: file.max_repetitions.unwrap_or(defaults.max_repetitions),
+        max_sessions: file.max_sessions.unwrap_or(defaults.max_sessions),
+        max_registered: file.max_registered.unwrap_or(defaults.max_registered),
+        work_bits: args.work_bits.or(file.work_bits).unwrap_or(defaults.work_bits),
+        protocols: config.protocols.clone(),
+    };
+    // A server is a production deployment, so nothing it runs may use a toy RNG
+    zklib::config::set_require_crypto_rng(true);
+    if limits.work_bits > zklib::handshake::MAX_WORK_BITS {
+        return Err(format!("work bits must be at most {}", zklib::handshake::MAX_WORK_BITS));
+    }
+    let served: Vec<&str> = zklib::server::VERIFIABLE_PROTOCOLS.iter().copied().chain(["gi"].iter().copied()).collect();
+    if let Some(protocol) = limits.protocols.iter().flatten().find(|protocol| !served.contains(&protocol.as_str())) {
+        return Err(format!("unknown protocol {} in protocols, expected some of {}", protocol, served.join(", ")));
+    }
+    #[cfg(feature = "tls")]
+    let tls = config.tls()?;
+    // Without the tls feature there is no HTTPS listener to build, so the transport is refused up front
+    #[cfg(not(feature = "tls"))]
+    let tls: Option<std::convert::Infallible> = match config.tls()? {
+        Some(_) => return Err("transport https requires zklib to be built with the tls feature".to_string()),
+        None => None,
+    };
+    let listen = args.listen.as_deref().or(config.listen.as_deref()).unwrap_or("127.0.0.1:8080");
+    let ttl = std::time::Duration::from_secs(args.session_ttl.or(config.session_ttl).unwrap_or(3600));
+    let drain_timeout = std::time::Duration::from_secs(args.drain_timeout.or(config.drain_timeout).unwrap_or(30));
+    // Probes are answered on the same interface as the service
+    let health = args.health_port.or(config.health_port).map(|port| match listen.rsplit_once(':') {
+        Some((host, _)) => format!("{}:{}", host, port),
+        None => format!("{}:{}", listen, port),
+    });
+    let log = |message: String| zklib::config::log(LogLevel::Info, &message);
+    let sessions = match args.state_dir.as_ref().or(config.state_dir.as_ref()) {
+        Some(dir) => {
+            let sessions = SessionStore::open(dir, ttl)?;
+            log(format!("Restored {} sessions from {}", sessions.len(), dir.display()));
+            sessions
+        },
+        None => SessionStore::in_memory(ttl),
+    };
+    let audit = args.audit_log.as_deref().or(config.audit_log.as_deref()).map(AuditLog::open).transpose()?;
+    let identities = args.identities.as_ref().or(config.identities.as_ref());
+    let handshake = identities.map(|path| zklib::handshake::Handshake::load(path)).transpose()?;
+    if let (Some(handshake), Some(path)) = (&handshake, identities) {
+        log(format!("Authenticating clients as the {} identities in {}", handshake.len(), path.display()));
+    }
+    let registry = match args.registry.as_ref().or(config.registry.as_ref()) {
+        Some(dir) => {
+            let registry = StatementRegistry::open(dir)?;
+            log(format!("Loaded {} registered statements from {}", registry.len(), dir.display()));
+            registry
+        },
+        None => StatementRegistry::in_memory(),
+    };
+    let scheme = match config.transport.unwrap_or_default() {
+        HttpTransport::Http => "http",
+        HttpTransport::Https => "https",
+    };
+    let enabled = |protocol: &str| limits.protocols.as_ref().is_none_or(|protocols| protocols.iter().any(|x| x == protocol));
+    let verified: Vec<&str> = zklib::server::VERIFIABLE_PROTOCOLS.iter().copied().filter(|protocol| enabled(protocol)).collect();
+    let sessions_enabled = enabled("gi");
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("failed to start runtime: {}", e))?;
+    runtime.block_on(async {
+        let listener = std::net::TcpListener::bind(listen).map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+        // The bound address, which differs from the requested one for port 0
+        let listen = listener.local_addr().map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+        if !verified.is_empty() {
+            log(format!("Verifying {} proofs at {}://{}/verify/{{protocol}}", verified.join(", "), scheme, listen));
+        }
+        if sessions_enabled {
+            log(format!("Running interactive GI sessions at {}://{}/sessions/gi", scheme, listen));
+        }
+        let listener = match tls {
+            #[cfg(feature = "tls")]
+            Some(tls) => Listener::Https(listener, tls),
+            None => {
+                listener.set_nonblocking(true).map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+                Listener::Http(tokio::net::TcpListener::from_std(listener).map_err(|e| format!("failed to listen on {}: {}", listen, e))?)
+            },
+        };
+        let shutdown = Shutdown::new(drain_timeout);
+        if let Some(health) = &health {
+            let health_listener = tokio::net::TcpListener::bind(&health).await.map_err(|e| format!("failed to listen on {}: {}", health, e))?;
+            log(format!("Answering health probes at http://{}/healthz and /readyz", health));
+            tokio::spawn(zklib::server::serve_health(health_listener, shutdown.clone()));
+        }
+        // Scripts learn where the service is from a single document once it is listening, where people read the log
+        emit(format, || json!({ "listen": listen, "scheme": scheme, "verify": verified, "sessions": sessions_enabled, "health": health }).to_string(), || ());
+        tokio::spawn(stop_on_signal(shutdown.clone()));
+        zklib::server::serve_until(listener, &shutdown, limits, sessions, audit, handshake, registry).await.map_err(|e| format!("server failed: {}", e))
+    })
+}
+
+// Begins draining on the first SIGTERM or Ctrl-C, and exits at once on the second
+#[cfg(feature = "server")]
+async fn stop_on_signal(shutdown: Shutdown) {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            while terminate.recv().await.is_some() {
+                stop(&shutdown);
+            }
+        });
+    }
+    while tokio::signal::ctrl_c().await.is_ok() {
+        stop(&shutdown);
+    }
+}
+
+#[cfg(feature = "server")]
+fn stop(shutdown: &Shutdown) {
+    if shutdown.is_requested() {
+        zklib::config::log(LogLevel::Warn, "Asked to stop again, exiting without draining");
+        process::exit(1);
+    }
+    zklib::config::log(LogLevel::Info, &format!("Draining sessions for up to {} seconds before shutting down", shutdown.drain_timeout.as_secs()));
+    shutdown.begin();
+}
+
+// ************ Audit implementation ************
+
+fn audit_verify(path: &std::path::Path, format: Format) -> Result<(), String> {
+    let summary = verify_log(path)?;
+    emit(format, || serde_json::to_string_pretty(&summary).unwrap(), || {
+        println!("Verified {} entries ({} accepted) in {}.", summary.entries, summary.accepted, path.display());
+        println!("Head hash: {}", summary.head);
+    });
+    Ok(())
+}
+
+// ************ Registry implementation ************
+
+fn registry(command: &RegistryCommand, format: Format) -> Result<(), String> {
+    // Statements are listed without their instances, which zklib registry fetch writes out
+    let summary = |name: &str, digest: &str| json!({ "name": name, "digest": digest });
+    match command {
+        RegistryCommand::Register { dir, name, instance } => {
+            let mut registry = StatementRegistry::open(dir)?;
+            let statement = registry.register(name, load_instance(instance)?)?;
+            emit(format, || summary(&statement.name, &statement.digest).to_string(), || println!("Registered {} as {}.", statement.name, statement.digest));
+        },
+        RegistryCommand::List { dir } => {
+            let registry = StatementRegistry::open(dir)?;
+            let statements = registry.list();
+            emit(format, || serde_json::to_string_pretty(&statements.iter().map(|statement| summary(&statement.name, &statement.digest)).collect::<Vec<_>>()).unwrap(), || {
+                for statement in &statements {
+                    println!("{}  {}", statement.digest, statement.name);
+                }
+            });
+        },
+        RegistryCommand::Fetch { dir, id, output } => {
+            let registry = StatementRegistry::open(dir)?;
+            let statement = registry.get(id).ok_or_else(|| format!("no statement {} in {}", id, dir.display()))?;
+            save_instance(output, &statement.instance)?;
+            emit(format, || json!({ "name": statement.name, "digest": statement.digest, "output": output }).to_string(), || println!("Wrote {} to {}.", statement.name, output.display()));
+        },
+    }
+    Ok(())
+}
+
+// ************ Key generation implementation ************
+
+fn generate_params(bits: u64) -> SchnorrParameters {
+    zklib::config::log(LogLevel::Info, &format!("Generating a group with a {}-bit safe prime, which can take a while.", bits));
+    SchnorrParameters::generate(bits)
+}
+
+fn load_params(path: &std::path::Path) -> Result<SchnorrParameters, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    SchnorrParameters::from_json(&json).ok_or_else(|| format!("{}: parameters are malformed or invalid", path.display()))
+}
+
+// Fails if the file exists, so that generated keys and parameters never replace ones already in use
+fn check_new(path: &std::path::Path) -> Result<(), String> {
+    match path.exists() {
+        true => Err(format!("{} already exists", path.display())),
+        false => Ok(()),
+    }
+}
+
+fn params(args: &ParamsArgs, format: Format) -> Result<(), String> {
+    // Check before spending time on the safe prime
+    check_new(&args.output)?;
+    let params = generate_params(args.bits);
+    fs::OpenOptions::new().write(true).create_new(true).open(&args.output)
+        .and_then(|mut file| io::Write::write_all(&mut file, params.to_json().as_bytes()))
+        .map_err(|e| format!("failed to write {}: {}", args.output.display(), e))?;
+    emit(format, || json!({ "bits": params.p.bits(), "output": args.output }).to_string(), || println!("Wrote the parameters to {}.", args.output.display()));
+    Ok(())
+}
+
+fn keygen(args: &KeygenArgs, format: Format) -> Result<(), String> {
+    let mut public = args.output.clone().into_os_string();
+    public.push(".pub");
+    let public = PathBuf::from(public);
+    // Check every file the key goes to before loading or generating a group, so that a key pair is never
+    // left half written and no time is spent on a safe prime that can't be saved
+    check_new(&args.output)?;
+    if args.scheme != "pedersen" {
+        check_new(&public)?;
+    }
+    let params = match &args.params {
+        Some(path) => load_params(path)?,
+        None => generate_params(args.bits.unwrap_or(DEFAULT_BITS)),
+    };
+    match args.scheme.as_str() {
+        "schnorr" => {
+            let key = SchnorrKeyPair::generate(&params);
+            key.save(&args.output)?;
+            key.public_key().save(&public)?;
+        },
+        "elgamal" => {
+            let key = ElGamalKeyPair::generate(&params);
+            key.save(&args.output)?;
+            key.public_key().save(&public)?;
+        },
+        _ => {
+            let key = PedersenParameters { h: params.random_generator(), group: params };
+            key.save(&args.output)?;
+            emit(format, || json!({ "scheme": args.scheme, "output": args.output }).to_string(), || println!("Wrote the commitment key to {}.", args.output.display()));
+            return Ok(());
+        },
+    }
+    emit(format, || json!({ "scheme": args.scheme, "output": args.output, "public": public }).to_string(),
+        || println!("Wrote the secret key to {} and the public key to {}.", args.output.display(), public.display()));
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+fn sign(args: &SignArgs, format: Format) -> Result<(), String> {
+    let key = SchnorrKeyPair::load(&args.key)?;
+    let signature = key.sign(args.message.as_bytes());
+    let instance = zklib::server::SignatureInstance { params: key.params.clone(), public_key: key.y.clone(), message: args.message.clone() };
+    let body = serde_json::to_string_pretty(&serde_json::json!({ "instance": instance, "proof": signature })).unwrap();
+    fs::write(&args.output, body).map_err(|e| format!("failed to write {}: {}", args.output.display(), e))?;
+    emit(format, || json!({ "output": args.output }).to_string(), || println!("Wrote the signed request to {}.", args.output.display()));
+    Ok(())
+}
+
+#[test]
+fn test_constant_time_flag() {
+    let parse = |args: &[&str]| Cli::try_parse_from(["zklib"].iter().chain(args).chain(["completions", "bash"].iter())).map(|cli| cli.constant_time);
+    assert_eq!(parse(&[]).ok(), Some(None));
+    assert_eq!(parse(&["--constant-time"]).ok(), Some(Some(true)));
+    // An explicit value overrides the configuration either way
+    assert_eq!(parse(&["--constant-time=false"]).ok(), Some(Some(false)));
+    assert_eq!(parse(&["--constant-time=true"]).ok(), Some(Some(true)));
+    assert!(parse(&["--constant-time=maybe"]).is_err());
+}
+
+#[test]
+fn test_existing_outputs() {
+    let dir = std::env::temp_dir().join(format!("zklib-keygen-{}", rand::random::<u64>()));
+    fs::create_dir_all(&dir).unwrap();
+    let existing = dir.join("key");
+    fs::write(&existing, "in use").unwrap();
+    fs::write(dir.join("other.pub"), "in use").unwrap();
+    // Existing files are refused before a group is generated, and left as they were
+    let error = params(&ParamsArgs { bits: MAX_BITS, output: existing.clone() }, Format::Text);
+    assert_eq!(error, Err(format!("{} already exists", existing.display())));
+    let keygen_to = |output: PathBuf| keygen(&KeygenArgs { scheme: "schnorr".to_string(), params: None, bits: Some(MAX_BITS), output }, Format::Text);
+    assert_eq!(keygen_to(existing.clone()), Err(format!("{} already exists", existing.display())));
+    assert_eq!(keygen_to(dir.join("other")), Err(format!("{} already exists", dir.join("other.pub").display())));
+    assert!(!dir.join("other").exists());
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "in use");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_isomorphism_time_limit() {
+    let bench = |seconds: f64| bench_isomorphism_dataset(&BenchIsomorphismArgs { dataset: PathBuf::from("missing"), mode: "budgeted".to_string(), max_nodes: None, seconds: Some(seconds), json: None }, Format::Text);
+    // Limits a Duration can't hold are refused before the dataset is read
+    for seconds in [-1.0, 0.0, f64::NAN, f64::INFINITY, 1e30].iter().copied() {
+        assert_eq!(bench(seconds), Err(format!("limit {} is not a positive number of seconds", seconds)));
+    }
+    assert!(!bench(0.5).unwrap_err().contains("seconds"));
+}
@@ -0,0 +1,131 @@
+use std::marker::PhantomData;
+use serde::{Serialize, Deserialize};
+use crate::hash::{Hasher, Sha256Hasher};
+
+// ************ Merkle tree implementation ************
+
+// A Merkle tree commits to a list of leaves with a single digest, and opens any one leaf with the digests
+// of its siblings on the way to the root. Leaves and inner nodes are hashed with different prefixes, so a
+// leaf can't be passed off as an inner node, and the list is padded with empty digests to a power of two.
+// The root also hashes in the number of leaves, so that a tree committed for one length can't be opened
+// as a tree of another. Trees hash with SHA-256 unless given another Hasher.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleTree<H: Hasher = Sha256Hasher> {
+    // Digests of each layer from the padded leaves up to the top node
+    layers: Vec<Vec<[u8; 32]>>,
+    length: usize,
+    hasher: PhantomData<H>,
+}
+
+// Digests of the siblings of a leaf, from the bottom of the tree up
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MerklePath {
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: &[Vec<u8>]) -> MerkleTree {
+        MerkleTree::with_hasher(leaves)
+    }
+}
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn with_hasher(leaves: &[Vec<u8>]) -> MerkleTree<H> {
+        let mut layer: Vec<[u8; 32]> = leaves.iter().map(|leaf| hash_leaf::<H>(leaf)).collect();
+        layer.resize(leaves.len().next_power_of_two(), [0u8; 32]);
+        let mut layers = vec![layer];
+        while layers.last().unwrap().len() > 1 {
+            let next = layers.last().unwrap().chunks(2).map(|pair| hash_node::<H>(&pair[0], &pair[1])).collect();
+            layers.push(next);
+        }
+        MerkleTree { layers, length: leaves.len(), hasher: PhantomData }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        hash_root::<H>(self.length, &self.layers.last().unwrap()[0])
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn open(&self, index: usize) -> MerklePath {
+        if index >= self.length {
+            panic!("Leaf {} is outside a tree of {} leaves", index, self.length);
+        }
+        let siblings = self.layers[..self.layers.len() - 1].iter().enumerate().map(|(depth, layer)| layer[(index >> depth) ^ 1]).collect();
+        MerklePath { siblings }
+    }
+}
+
+impl MerklePath {
+    // Checks that the leaf is at the index of a tree with the given root and number of leaves
+    pub fn verify(&self, root: &[u8; 32], length: usize, index: usize, leaf: &[u8]) -> bool {
+        self.verify_with_hasher::<Sha256Hasher>(root, length, index, leaf)
+    }
+
+    // Checks the path like verify, for a tree hashed with H
+    pub fn verify_with_hasher<H: Hasher>(&self, root: &[u8; 32], length: usize, index: usize, leaf: &[u8]) -> bool {
+        // The length comes from the other party, so one too large to pad to a power of two is refused
+        // rather than overflowing
+        match length.checked_next_power_of_two() {
+            Some(padded) if index < length && self.siblings.len() as u32 == padded.trailing_zeros() => {},
+            _ => return false,
+        }
+        let top = self.siblings.iter().enumerate().fold(hash_leaf::<H>(leaf), |node, (depth, sibling)| {
+            if (index >> depth) & 1 == 0 { hash_node::<H>(&node, sibling) } else { hash_node::<H>(sibling, &node) }
+        });
+        hash_root::<H>(length, &top) == *root
+    }
+}
+
+fn hash_leaf<H: Hasher>(leaf: &[u8]) -> [u8; 32] {
+    H::hash(&[&[0u8], leaf])
+}
+
+fn hash_node<H: Hasher>(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    H::hash(&[&[1u8], left, right])
+}
+
+fn hash_root<H: Hasher>(length: usize, top: &[u8; 32]) -> [u8; 32] {
+    H::hash(&[&[2u8], &(length as u64).to_be_bytes(), top])
+}
+
+#[test]
+fn test_merkle_tree() {
+    for length in [1, 2, 5, 8] {
+        let leaves: Vec<Vec<u8>> = (0..length).map(|i| vec![i as u8; i + 1]).collect();
+        let tree = MerkleTree::new(&leaves);
+        let root = tree.root();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let path = tree.open(i);
+            assert!(path.verify(&root, length, i, leaf));
+            assert!(!path.verify(&root, length, i, b"other leaf"));
+            assert!(!path.verify(&root, length, i ^ 1, leaf));
+            // The root binds the number of leaves, even for lengths with the same padded size
+            assert!(!path.verify(&root, length + 1, i, leaf));
+        }
+    }
+    assert!(MerkleTree::new(&[]).is_empty());
+    // A claimed length with no power of two above it is rejected rather than overflowing
+    let path = MerkleTree::new(&[b"leaf".to_vec()]).open(0);
+    assert!(!path.verify(&[0u8; 32], usize::MAX, 0, b"leaf"));
+    assert!(!MerklePath { siblings: vec![[0u8; 32]; 64] }.verify(&[0u8; 32], usize::MAX, 0, b"leaf"));
+}
+
+#[test]
+fn test_poseidon_merkle_tree() {
+    use crate::hash::PoseidonHasher;
+    let leaves: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i]).collect();
+    let tree = MerkleTree::<PoseidonHasher>::with_hasher(&leaves);
+    let path = tree.open(3);
+    assert!(path.verify_with_hasher::<PoseidonHasher>(&tree.root(), 6, 3, &leaves[3]));
+    // A path in a Poseidon tree doesn't verify with SHA-256
+    assert!(!path.verify(&tree.root(), 6, 3, &leaves[3]));
+    assert_ne!(tree.root(), MerkleTree::new(&leaves).root());
+}
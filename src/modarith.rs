@@ -0,0 +1,251 @@
+use num_bigint::{BigInt, BigUint, RandBigInt, Sign};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use crate::randomness::checked_thread_rng;
+
+// ************ Modular arithmetic and number-theoretic utilities ************
+
+// Odd primes used to cheaply rule out most composite candidates before running Miller-Rabin
+const SMALL_PRIMES: [u32; 24] = [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97];
+
+// Number of Miller-Rabin rounds, giving a false positive probability of at most 4^-40
+const MILLER_RABIN_ROUNDS: usize = 40;
+
+// Computes base^exp mod modulus
+pub fn mod_exp(base: &BigUint, exp: &BigUint, modulus: &BigUint) -> BigUint {
+    base.modpow(exp, modulus)
+}
+
+// Computes the inverse of a modulo m, if it exists
+pub fn mod_inverse(a: &BigUint, m: &BigUint) -> Option<BigUint> {
+    if m.is_zero() {
+        return None;
+    }
+    // Extended Euclidean algorithm, tracking only the coefficient of a
+    let m_signed = BigInt::from_biguint(Sign::Plus, m.clone());
+    let (mut r0, mut r1) = (m_signed.clone(), BigInt::from_biguint(Sign::Plus, a % m));
+    let (mut t0, mut t1) = (BigInt::zero(), BigInt::one());
+    while !r1.is_zero() {
+        let q = &r0 / &r1;
+        let r2 = &r0 - &q * &r1;
+        let t2 = &t0 - &q * &t1;
+        r0 = r1;
+        r1 = r2;
+        t0 = t1;
+        t1 = t2;
+    }
+    if !r0.is_one() {
+        return None;
+    }
+    t0.mod_floor(&m_signed).to_biguint()
+}
+
+// Computes the Jacobi symbol (a/n) for odd positive n, returning -1, 0, or 1
+pub fn jacobi(a: &BigUint, n: &BigUint) -> i8 {
+    if n.is_even() || n.is_zero() {
+        panic!("Jacobi symbol is only defined for odd positive n. Found n = {}.", n);
+    }
+    let (mut a, mut n) = (a % n, n.clone());
+    let mut result = 1;
+    let three = BigUint::from(3u32);
+    let five = BigUint::from(5u32);
+    let eight = BigUint::from(8u32);
+    let four = BigUint::from(4u32);
+    while !a.is_zero() {
+        // Pull out factors of two using (2/n) = -1 exactly when n = 3, 5 mod 8
+        while a.is_even() {
+            a >>= 1;
+            let r = &n % &eight;
+            if r == three || r == five {
+                result = -result;
+            }
+        }
+        // Quadratic reciprocity flips the sign when both are 3 mod 4
+        std::mem::swap(&mut a, &mut n);
+        if &a % &four == three && &n % &four == three {
+            result = -result;
+        }
+        a %= &n;
+    }
+    if n.is_one() { result } else { 0 }
+}
+
+// Probabilistic primality test using trial division followed by Miller-Rabin
+pub fn is_probable_prime(n: &BigUint) -> bool {
+    let two = BigUint::from(2u32);
+    if *n < two {
+        return false;
+    }
+    for &p in SMALL_PRIMES.iter() {
+        let p = BigUint::from(p);
+        if *n == p {
+            return true;
+        }
+        if (n % &p).is_zero() {
+            return false;
+        }
+    }
+    if *n == two {
+        return true;
+    }
+    if n.is_even() {
+        return false;
+    }
+    // Write n - 1 = 2^s * d with d odd
+    let n_minus_one = n - 1u32;
+    let s = n_minus_one.trailing_zeros().unwrap();
+    let d = &n_minus_one >> s;
+    let mut rng = checked_thread_rng();
+    'witness: for _ in 0..MILLER_RABIN_ROUNDS {
+        let a = rng.gen_biguint_range(&two, &n_minus_one);
+        let mut x = mod_exp(&a, &d, n);
+        if x.is_one() || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = mod_exp(&x, &two, n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+// Samples a random odd integer with exactly the given number of bits
+fn random_odd(bits: u64) -> BigUint {
+    let mut n = checked_thread_rng().gen_biguint(bits);
+    n.set_bit(bits - 1, true);
+    n.set_bit(0, true);
+    n
+}
+
+// Generates a random prime with exactly the given number of bits
+pub fn random_prime(bits: u64) -> BigUint {
+    if bits < 2 {
+        panic!("Primes must have at least 2 bits. Found {} bits.", bits);
+    }
+    loop {
+        let candidate = random_odd(bits);
+        if is_probable_prime(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+// Generates a random safe prime p = 2q + 1 with exactly the given number of bits, where q is also prime.
+// Returns the pair (p, q).
+pub fn random_safe_prime(bits: u64) -> (BigUint, BigUint) {
+    if bits < 3 {
+        panic!("Safe primes must have at least 3 bits. Found {} bits.", bits);
+    }
+    loop {
+        let q = random_odd(bits - 1);
+        let p: BigUint = (&q << 1) + 1u32;
+        // Checking p first is cheap and rules out most candidates, since it usually fails trial division
+        if is_probable_prime(&p) && is_probable_prime(&q) {
+            return (p, q);
+        }
+    }
+}
+
+// Generates a random Blum integer N = pq with the given number of bits, where p and q are distinct
+// primes congruent to 3 mod 4. Returns the triple (N, p, q). Below 9 bits there are no two such primes
+// of the right sizes, e.g. 11 is the only one with 4 bits, so the search would never end.
+pub fn random_blum_integer(bits: u64) -> (BigUint, BigUint, BigUint) {
+    if bits < 9 {
+        panic!("Blum integers must have at least 9 bits. Found {} bits.", bits);
+    }
+    let blum_prime = |bits: u64| loop {
+        let p = random_prime(bits);
+        if p.bit(1) {
+            return p;
+        }
+    };
+    loop {
+        let p = blum_prime(bits / 2);
+        let q = blum_prime(bits - bits / 2);
+        let n = &p * &q;
+        if p != q && n.bits() == bits {
+            return (n, p, q);
+        }
+    }
+}
+
+#[test]
+fn test_mod_exp() {
+    let result = mod_exp(&BigUint::from(4u32), &BigUint::from(13u32), &BigUint::from(497u32));
+    assert_eq!(result, BigUint::from(445u32));
+}
+
+#[test]
+fn test_mod_inverse() {
+    let m = BigUint::from(3233u32);
+    let inverse = mod_inverse(&BigUint::from(17u32), &BigUint::from(3120u32)).unwrap();
+    assert_eq!(inverse, BigUint::from(2753u32));
+    // Inverses only exist for elements coprime to the modulus
+    assert_eq!(mod_inverse(&BigUint::from(61u32), &m), None);
+    assert_eq!(mod_inverse(&BigUint::zero(), &m), None);
+}
+
+#[test]
+fn test_jacobi() {
+    // Values from the table of Jacobi symbols (a/n)
+    let cases = [(1u32, 1u32, 1i8), (2, 3, -1), (5, 9, 1), (2, 15, 1), (7, 15, -1), (6, 15, 0), (19, 45, 1), (8, 21, -1), (30, 59, -1)];
+    for &(a, n, expected) in cases.iter() {
+        assert_eq!(jacobi(&BigUint::from(a), &BigUint::from(n)), expected, "({}/{})", a, n);
+    }
+}
+
+#[test]
+fn test_jacobi_matches_euler_criterion() {
+    // For a prime p, the Jacobi symbol is the Legendre symbol a^((p-1)/2) mod p
+    let p = BigUint::from(1019u32);
+    let exp = (&p - 1u32) >> 1;
+    for a in 1..100u32 {
+        let a = BigUint::from(a);
+        let euler = mod_exp(&a, &exp, &p);
+        let expected = if euler.is_one() { 1 } else { -1 };
+        assert_eq!(jacobi(&a, &p), expected);
+    }
+}
+
+#[test]
+fn test_is_probable_prime() {
+    let primes = [2u32, 3, 5, 97, 101, 7919, 104729];
+    let composites = [0u32, 1, 4, 91, 561, 1105, 7917, 104730];
+    assert!(primes.iter().all(|&x| is_probable_prime(&BigUint::from(x))));
+    assert!(!composites.iter().any(|&x| is_probable_prime(&BigUint::from(x))));
+    // Mersenne prime 2^127 - 1
+    assert!(is_probable_prime(&((BigUint::one() << 127) - 1u32)));
+}
+
+#[test]
+fn test_random_safe_prime() {
+    let (p, q) = random_safe_prime(64);
+    assert_eq!(p.bits(), 64);
+    assert_eq!(p, (&q << 1) + 1u32);
+    assert!(is_probable_prime(&p) && is_probable_prime(&q));
+}
+
+#[test]
+fn test_random_blum_integer() {
+    let (n, p, q) = random_blum_integer(128);
+    let four = BigUint::from(4u32);
+    assert_eq!(n.bits(), 128);
+    assert_eq!(n, &p * &q);
+    assert_eq!(&p % &four, BigUint::from(3u32));
+    assert_eq!(&q % &four, BigUint::from(3u32));
+    // The smallest sizes terminate too
+    for bits in 9..16 {
+        let (n, p, q) = random_blum_integer(bits);
+        assert!(n.bits() == bits && p != q);
+    }
+}
+
+#[test]
+#[should_panic(expected = "at least 9 bits")]
+fn test_random_blum_integer_too_small() {
+    random_blum_integer(8);
+}
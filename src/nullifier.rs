@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use num_bigint::BigUint;
+use num_traits::One;
+use serde::{Serialize, Deserialize};
+use crate::audit::to_hex;
+use crate::chaumpedersen::{EqualityProof, EqualityStatement};
+use crate::hash::{Hasher, Sha256Hasher};
+use crate::modarith::mod_exp;
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::schnorr::SchnorrKeyPair;
+use crate::transcript::ChallengeTranscript;
+#[cfg(test)]
+use crate::params::Parameters;
+
+// ************ Nullifier implementation ************
+
+// A nullifier is a tag that a prover derives deterministically from its secret and a scope, e.g. one
+// election or one use of a credential, and publishes along with its proof. The same secret always gives
+// the same tag in the same scope, so a verifier that remembers the tags it has seen in a NullifierSet
+// rejects a second proof from the same secret.
+//
+// Nullifier::derive is a PRF of any witness's encoding, a hash keyed by the witness. The verifier can't
+// check that a tag was derived from the witness behind a proof, so it only prevents reuse when the proof
+// itself shows how the tag was derived, or when the prover is trusted to derive it honestly.
+//
+// A NullifierProof is checkable on its own: for a Schnorr key x with public key y = g^x, the tag is
+// T = U^x for a group element U hashed from the scope, with a Chaum-Pedersen EqualityProof that
+// log_U T = log_g y, bound to a message such as a ballot. T is fixed by x and the scope, so a key can't
+// produce a second valid tag in the same scope. The proof is verified against y, so it identifies the key
+// to anyone who checks it, and anyone who knows y can link its tags across scopes by checking them. Only
+// to someone who doesn't know y do tags from different scopes look unrelated, since T is a PRF of the
+// scope under the DDH assumption, so hiding which key was used takes a proof that y is one of a set of
+// keys, which this module doesn't provide. Every element of the statement, y and T included, is checked
+// to be in the subgroup, so a tag can't be varied by a factor of small order.
+
+// Digest identifying a use of a secret in a scope
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Nullifier(pub [u8; 32]);
+
+impl Nullifier {
+    // Keyed hash of the scope under the witness's encoding
+    pub fn derive(witness: &[u8], scope: &[u8]) -> Nullifier {
+        let lengths = [(witness.len() as u64).to_be_bytes(), (scope.len() as u64).to_be_bytes()];
+        Nullifier(Sha256Hasher::hash(&[b"nullifier", &lengths[0], witness, &lengths[1], scope]))
+    }
+}
+
+// Tag T = U^x of a Schnorr key in a scope, with a Chaum-Pedersen proof that it matches the public key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NullifierProof {
+    #[serde(with = "serde_biguint")]
+    pub tag: BigUint,
+    pub proof: EqualityProof,
+}
+
+// Element U of the subgroup of order q derived from the scope, whose discrete log nobody knows
+pub fn scope_generator(params: &SchnorrParameters, scope: &[u8]) -> BigUint {
+    let cofactor = (&params.p - 1u32) / &params.q;
+    let mut transcript = ChallengeTranscript::new(b"nullifier scope");
+    transcript.append_message(b"scope", scope);
+    loop {
+        // Raising a random residue to the cofactor lands in the subgroup, where anything but 1 generates it
+        let u = mod_exp(&transcript.challenge_scalar(b"residue", &params.p), &cofactor, &params.p);
+        if u > BigUint::one() {
+            return u;
+        }
+    }
+}
+
+// Statement log_g y = log_U T that the tag matches the public key
+fn tag_statement(params: &SchnorrParameters, y: &BigUint, u: BigUint, tag: &BigUint) -> EqualityStatement {
+    [params.g.clone(), y.clone(), u, tag.clone()]
+}
+
+// Derives the key's tag in the scope, proving that it matches the public key and binding the proof to the message
+pub fn nullify(key: &SchnorrKeyPair, scope: &[u8], message: &[u8]) -> NullifierProof {
+    let params = &key.params;
+    let u = scope_generator(params, scope);
+    let tag = mod_exp(&u, &key.x, &params.p);
+    let proof = EqualityProof::prove(params, &tag_statement(params, &key.y, u, &tag), &key.x, b"nullifier", message);
+    NullifierProof { tag, proof }
+}
+
+impl NullifierProof {
+    // Checks that the tag belongs to the public key y in the scope, and that the proof is bound to the message
+    pub fn verify(&self, params: &SchnorrParameters, y: &BigUint, scope: &[u8], message: &[u8]) -> bool {
+        let u = scope_generator(params, scope);
+        self.proof.verify(params, &tag_statement(params, y, u, &self.tag), b"nullifier", message)
+    }
+
+    pub fn nullifier(&self) -> Nullifier {
+        Nullifier(Sha256Hasher::hash(&[b"nullifier tag", &self.tag.to_bytes_be()]))
+    }
+}
+
+// Nullifiers a verifier has already accepted
+#[derive(Clone, Debug, Default)]
+pub struct NullifierSet {
+    seen: HashSet<Nullifier>,
+}
+
+impl NullifierSet {
+    pub fn new() -> NullifierSet {
+        NullifierSet::default()
+    }
+
+    pub fn contains(&self, nullifier: &Nullifier) -> bool {
+        self.seen.contains(nullifier)
+    }
+
+    // Records the nullifier, failing if it was already used
+    pub fn insert(&mut self, nullifier: Nullifier) -> Result<(), String> {
+        if !self.seen.insert(nullifier) {
+            return Err(format!("Nullifier {} was already used", to_hex(&nullifier.0)));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+#[test]
+fn test_derived_nullifier() {
+    let tag = Nullifier::derive(b"witness", b"election 1");
+    assert_eq!(tag, Nullifier::derive(b"witness", b"election 1"));
+    assert_ne!(tag, Nullifier::derive(b"witness", b"election 2"));
+    // Lengths are hashed in, so moving bytes between the witness and the scope changes the tag
+    assert_ne!(Nullifier::derive(b"ab", b"c"), Nullifier::derive(b"a", b"bc"));
+    let mut seen = NullifierSet::new();
+    assert_eq!(seen.insert(tag), Ok(()));
+    assert!(seen.insert(tag).is_err());
+    assert_eq!(seen.len(), 1);
+}
+
+#[test]
+fn test_nullifier_proof() {
+    let params = SchnorrParameters::generate(64);
+    let key = SchnorrKeyPair::generate(&params);
+    let proof = nullify(&key, b"election 1", b"ballot");
+    assert!(proof.verify(&params, &key.y, b"election 1", b"ballot"));
+    assert!(!proof.verify(&params, &key.y, b"election 1", b"another ballot"));
+    assert!(!proof.verify(&params, &key.y, b"election 2", b"ballot"));
+    assert!(!proof.verify(&params, &SchnorrKeyPair::generate(&params).y, b"election 1", b"ballot"));
+
+    // A second proof in the same scope carries the same tag, even for another message, so it is rejected
+    let mut seen = NullifierSet::new();
+    assert_eq!(seen.insert(proof.nullifier()), Ok(()));
+    let again = nullify(&key, b"election 1", b"another ballot");
+    assert!(again.verify(&params, &key.y, b"election 1", b"another ballot"));
+    assert!(seen.insert(again.nullifier()).is_err());
+    assert_eq!(seen.insert(nullify(&key, b"election 2", b"ballot").nullifier()), Ok(()));
+
+    // Public keys and tags outside the subgroup are refused, so a tag can't be moved by an element of order 2
+    let minus_one = &params.p - 1u32;
+    assert!(!proof.verify(&params, &minus_one, b"election 1", b"ballot"));
+    assert!(!proof.verify(&params, &(&key.y * &minus_one % &params.p), b"election 1", b"ballot"));
+    let negated = NullifierProof { tag: &proof.tag * &minus_one % &params.p, ..proof.clone() };
+    assert!(!negated.verify(&params, &key.y, b"election 1", b"ballot"));
+    assert_ne!(negated.nullifier(), proof.nullifier());
+}
@@ -0,0 +1,293 @@
+use num_bigint::BigUint;
+use crate::{Diagnosis, Prover, Verifier};
+use crate::modarith::mod_exp;
+use serde::{Serialize, Deserialize};
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::secret::Secret;
+use crate::statement::{Statement, Transparent, Witness};
+use crate::transcript::{ChallengeTranscript, encode_biguints};
+#[cfg(test)]
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use num_traits::Zero;
+#[cfg(test)]
+use rayon::prelude::*;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Okamoto identification protocol implementation ************
+
+// Public key y = g^x1 * h^x2 in a Schnorr group with two generators g and h. Nobody should know the
+// discrete log of h base g, otherwise they could convert one witness for y into another.
+pub struct OkamotoInstance {
+    pub params: SchnorrParameters,
+    pub h: BigUint,
+    pub y: BigUint,
+}
+
+// Secret exponents (x1, x2) representing the public key
+pub struct OkamotoWitness {
+    pub x1: Secret<BigUint>,
+    pub x2: Secret<BigUint>,
+}
+
+impl OkamotoInstance {
+    // Samples a second generator and a random key pair for the given group
+    pub fn generate(params: &SchnorrParameters) -> (OkamotoInstance, OkamotoWitness) {
+        let h = params.random_generator();
+        let witness = OkamotoWitness { x1: Secret::new(params.random_exponent()), x2: Secret::new(params.random_exponent()) };
+        let y = OkamotoInstance::represent(params, &h, &witness.x1, &witness.x2);
+        (OkamotoInstance { params: params.clone(), h, y }, witness)
+    }
+
+    // Computes g^a * h^b
+    fn represent(params: &SchnorrParameters, h: &BigUint, a: &BigUint, b: &BigUint) -> BigUint {
+        mod_exp(&params.g, a, &params.p) * mod_exp(h, b, &params.p) % &params.p
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum OkamotoProverMessage {
+    // Commitment a = g^r1 * h^r2 to random nonces
+    Commitment(#[serde(with = "serde_biguint")] BigUint),
+    // Responses z1 = r1 + c * x1 and z2 = r2 + c * x2 mod q
+    Response(#[serde(with = "serde_biguint")] BigUint, #[serde(with = "serde_biguint")] BigUint),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OkamotoVerifierMessage {
+    // Random challenge in Z_q
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct OkamotoProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random nonces (r1, r2) used for the commitment
+    pub nonces: Secret<(BigUint, BigUint)>,
+    pub instance: &'a OkamotoInstance,
+    pub witness: &'a OkamotoWitness,
+}
+
+impl Prover for OkamotoProver<'_> {
+    type ProverMessage = OkamotoProverMessage;
+    type VerifierMessage = OkamotoVerifierMessage;
+
+    fn handle(&mut self, msg: &OkamotoVerifierMessage) -> (OkamotoProverMessage, bool) {
+        self.r += 1;
+        let params = &self.instance.params;
+        match self.r {
+            // During the first round, the prover commits to random nonces
+            1 => {
+                self.nonces = Secret::new((params.random_exponent(), params.random_exponent()));
+                (OkamotoProverMessage::Commitment(OkamotoInstance::represent(params, &self.instance.h, &self.nonces.0, &self.nonces.1)), false)
+            },
+            // During the second round, the prover masks its witness with the nonces according to the challenge
+            2 => {
+                let z1 = (&self.nonces.0 + &msg.c * &*self.witness.x1) % &params.q;
+                let z2 = (&self.nonces.1 + &msg.c * &*self.witness.x2) % &params.q;
+                (OkamotoProverMessage::Response(z1, z2), false)
+            },
+            // After sending its responses, the prover sends a message to terminate the interaction
+            _ => (OkamotoProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct OkamotoVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub commitment: BigUint,
+    pub challenge: BigUint,
+    pub response: (BigUint, BigUint),
+    // Running transcript from which challenges are derived
+    pub transcript: ChallengeTranscript,
+    pub instance: &'a OkamotoInstance,
+}
+
+impl Verifier for OkamotoVerifier<'_> {
+    type ProverMessage = OkamotoProverMessage;
+    type VerifierMessage = OkamotoVerifierMessage;
+
+    fn init(&mut self) -> OkamotoVerifierMessage {
+        narrate!("Initializing Okamoto instance with public key {}.", &self.instance.y);
+        self.transcript.append_biguint(b"public key", &self.instance.y);
+        OkamotoVerifierMessage { c: BigUint::from(0u32) }
+    }
+
+    fn validate(&self, msg: &OkamotoProverMessage) -> Result<(), String> {
+        let params = &self.instance.params;
+        match (self.r + 1, msg) {
+            (1, OkamotoProverMessage::Commitment(a)) => params.check_element(a),
+            (1, _) => Err("expected a commitment".to_string()),
+            (_, OkamotoProverMessage::Response(z1, z2)) => params.check_exponent(z1).and(params.check_exponent(z2)),
+            _ => Err("expected responses".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &OkamotoProverMessage) -> Option<Diagnosis> {
+        if let OkamotoProverMessage::Response(z1, z2) = msg {
+            let params = &self.instance.params;
+            let lhs = OkamotoInstance::represent(params, &self.instance.h, z1, z2);
+            let rhs = &self.commitment * mod_exp(&self.instance.y, &self.challenge, &params.p) % &params.p;
+            Some(Diagnosis::new("g^z1 * h^z2 != a * y^c", Some(format!("g^z1 * h^z2 = {}, a * y^c = {}", lhs, rhs))))
+        } else {
+            None
+        }
+    }
+
+    fn handle(&mut self, msg: &OkamotoProverMessage) -> (OkamotoVerifierMessage, bool) {
+        self.r += 1;
+        let params = &self.instance.params;
+        match self.r {
+            1 => {
+                if let OkamotoProverMessage::Commitment(a) = msg {
+                    narrate!("Verifier received commitment {}.", a);
+                    self.commitment = a.clone();
+                    self.transcript.append_biguint(b"commitment", a);
+                    self.challenge = self.transcript.verifier_challenge_scalar(b"challenge", &params.q);
+                    narrate!("Verifier chose challenge {}.", &self.challenge);
+                    (OkamotoVerifierMessage { c: self.challenge.clone() }, false)
+                } else {
+                    panic!("Prover did not send a valid commitment on round 1!")
+                }
+            },
+            _ => {
+                if let OkamotoProverMessage::Response(z1, z2) = msg {
+                    narrate!("Verifier received responses {} and {}.", z1, z2);
+                    self.response = (z1.clone(), z2.clone());
+                    self.transcript.append_biguint(b"response", z1);
+                    self.transcript.append_biguint(b"response", z2);
+                    // The verifier accepts if g^z1 * h^z2 = a * y^c
+                    let lhs = OkamotoInstance::represent(params, &self.instance.h, z1, z2);
+                    let rhs = &self.commitment * mod_exp(&self.instance.y, &self.challenge, &params.p) % &params.p;
+                    (OkamotoVerifierMessage { c: self.challenge.clone() }, lhs == rhs)
+                } else {
+                    panic!("Prover did not send a valid response on round 2!")
+                }
+            }
+        }
+    }
+}
+
+impl Witness for OkamotoWitness {}
+
+impl Statement for OkamotoInstance {
+    type Witness = OkamotoWitness;
+
+    fn protocol(&self) -> &'static str {
+        "okamoto identification"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let params = &self.params;
+        encode_biguints(&[&params.p, &params.q, &params.g, &self.h, &self.y])
+    }
+
+    fn validate(&self, witness: &OkamotoWitness) -> Result<(), String> {
+        self.params.check_exponent(&witness.x1)?;
+        self.params.check_exponent(&witness.x2)?;
+        if OkamotoInstance::represent(&self.params, &self.h, &witness.x1, &witness.x2) != self.y {
+            return Err("Witness does not represent the public key".to_string());
+        }
+        Ok(())
+    }
+
+    fn run_proof(&self, witness: &OkamotoWitness) -> bool {
+        let zero = || BigUint::from(0u32);
+        let mut prover = OkamotoProver { r: 0, nonces: Secret::default(), instance: self, witness };
+        let mut verifier = OkamotoVerifier {
+            r: 0,
+            commitment: zero(),
+            challenge: zero(),
+            response: (zero(), zero()),
+            transcript: ChallengeTranscript::new(b"okamoto"),
+            instance: self,
+        };
+        crate::run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+impl Transparent for OkamotoInstance {}
+
+#[cfg(test)]
+fn run_okamoto(instance: &OkamotoInstance, witness: &OkamotoWitness) -> (bool, (BigUint, BigUint, BigUint, BigUint)) {
+    let mut prover = OkamotoProver {
+        r: 0,
+        nonces: Secret::default(),
+        instance,
+        witness,
+    };
+    let mut verifier = OkamotoVerifier {
+        r: 0,
+        commitment: BigUint::zero(),
+        challenge: BigUint::zero(),
+        response: (BigUint::zero(), BigUint::zero()),
+        transcript: ChallengeTranscript::new(b"okamoto"),
+        instance,
+    };
+    let accept = run_interactive_proof(&mut prover, &mut verifier);
+    (accept, (verifier.commitment, verifier.challenge, verifier.response.0, verifier.response.1))
+}
+
+#[test]
+fn test_okamoto_interactive_proof() {
+    let params = SchnorrParameters::generate(64);
+    let (instance, witness) = OkamotoInstance::generate(&params);
+    // Since the proof has perfect completeness, an honest prover should always be accepted.
+    assert!(run_okamoto(&instance, &witness).0);
+}
+
+#[test]
+fn test_okamoto_wrong_witness() {
+    let params = SchnorrParameters::generate(64);
+    let (instance, _) = OkamotoInstance::generate(&params);
+    let (_, wrong_witness) = OkamotoInstance::generate(&params);
+    // A prover with the wrong witness only succeeds if the challenge is 0, which happens with probability 1/q.
+    assert!(!run_okamoto(&instance, &wrong_witness).0);
+}
+
+#[test]
+fn test_okamoto_witness_indistinguishable() {
+    // In a tiny group we can construct two different witnesses for the same public key by choosing
+    // h = g^k, since (x1 + k * t, x2 - t) represents the same key for any t.
+    let n = 20000;
+    let params = SchnorrParameters { p: BigUint::from(11u32), q: BigUint::from(5u32), g: BigUint::from(4u32) };
+    assert!(params.validate());
+    let k = BigUint::from(3u32);
+    let h = mod_exp(&params.g, &k, &params.p);
+    let witness0 = OkamotoWitness { x1: Secret::new(BigUint::from(1u32)), x2: Secret::new(BigUint::from(2u32)) };
+    let witness1 = OkamotoWitness { x1: Secret::new((&*witness0.x1 + &k) % &params.q), x2: Secret::new((&*witness0.x2 + &params.q - 1u32) % &params.q) };
+    let y = OkamotoInstance::represent(&params, &h, &witness0.x1, &witness0.x2);
+    assert_eq!(y, OkamotoInstance::represent(&params, &h, &witness1.x1, &witness1.x2));
+    let instance = OkamotoInstance { params, h, y };
+
+    let transcript_distribution = |witness: &OkamotoWitness| {
+        let transcripts: Vec<_> = (0..n).collect::<Vec<i32>>().par_iter().map(|_| {
+            let (accept, transcript) = run_okamoto(&instance, witness);
+            assert!(accept);
+            transcript
+        }).collect();
+        let mut counts = HashMap::new();
+        transcripts.into_iter().for_each(|x| *counts.entry(x).or_insert(0) += 1);
+        counts
+    };
+    let (counts0, counts1) = (transcript_distribution(&witness0), transcript_distribution(&witness1));
+
+    // Transcripts are uniform over the 125 accepting (a, c, z1, z2) regardless of the witness, so the
+    // empirical distributions should only differ by sampling noise.
+    let keys: Vec<_> = counts0.keys().chain(counts1.keys()).collect();
+    let distance: f64 = keys.iter().collect::<HashSet<_>>().iter().map(|x| {
+        let (a, b) = (*counts0.get(x).unwrap_or(&0) as f64, *counts1.get(x).unwrap_or(&0) as f64);
+        (a - b).abs() / n as f64
+    }).sum::<f64>() / 2.0;
+    narrate!("Total variation distance between transcript distributions: {}.", distance);
+
+    assert_eq!(counts0.len(), 125);
+    assert_eq!(counts1.len(), 125);
+    assert!(distance < 0.1);
+}
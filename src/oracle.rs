@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use rand::Rng;
+use crate::hash::Hasher;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use num_bigint::BigUint;
+#[cfg(test)]
+use crate::transcript::ChallengeTranscript;
+
+// ************ Random oracle implementation ************
+
+// Security proofs of Fiat-Shamir protocols model the hash as a random oracle: a table that answers every
+// new query with fresh randomness and every repeated query consistently. Reductions then control the
+// table. A simulator programs the answers to queries it has yet to see, and the forking lemma reruns a
+// prover with the same table up to some query and fresh answers from there on, so that it answers two
+// challenges for the same commitment.
+//
+// RoTable is such a table, sampled lazily and logging every query. RandomOracle is the Hasher that reads
+// from the table installed by RoTable::run, so any transcript, commitment, or Merkle tree built with it,
+// such as ChallengeTranscript::<RandomOracle>::with_hasher, queries the table instead of hashing. Tables
+// are per thread, and only meant for tests.
+
+// A query of the oracle and its answer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoQuery {
+    pub input: Vec<u8>,
+    pub output: [u8; 32],
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoTable {
+    // Answers to every input queried or programmed so far
+    pub entries: HashMap<Vec<u8>, [u8; 32]>,
+    // Every query in the order it was made, including repeated ones
+    pub log: Vec<RoQuery>,
+}
+
+thread_local! {
+    static INSTALLED: RefCell<Option<RoTable>> = const { RefCell::new(None) };
+}
+
+impl RoTable {
+    pub fn new() -> RoTable {
+        RoTable::default()
+    }
+
+    // Answers the query from the table, sampling a fresh answer for a new input, and logs it
+    pub fn query(&mut self, input: &[u8]) -> [u8; 32] {
+        let output = *self.entries.entry(input.to_vec()).or_insert_with(|| checked_thread_rng().gen());
+        self.log.push(RoQuery { input: input.to_vec(), output });
+        output
+    }
+
+    // Sets the answer to an input, returning the answer it replaces if it was already set
+    pub fn program(&mut self, input: &[u8], output: [u8; 32]) -> Option<[u8; 32]> {
+        self.entries.insert(input.to_vec(), output)
+    }
+
+    // The table as it was before query number at, so that rerunning a deterministic prover against it
+    // repeats the first at queries and gets fresh answers from there on
+    pub fn fork(&self, at: usize) -> RoTable {
+        let mut forked = RoTable::new();
+        for query in &self.log[..at] {
+            forked.entries.insert(query.input.clone(), query.output);
+            forked.log.push(query.clone());
+        }
+        forked
+    }
+
+    // Runs f with this table answering every RandomOracle query on the current thread
+    pub fn run<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        let previous = INSTALLED.with(|installed| installed.replace(Some(std::mem::take(self))));
+        let result = f();
+        *self = INSTALLED.with(|installed| installed.replace(previous)).expect("Random oracle table was removed while running");
+        result
+    }
+}
+
+// Hasher answering from the table installed by RoTable::run, treating the concatenated parts as the input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RandomOracle;
+
+impl Hasher for RandomOracle {
+    fn hash(parts: &[&[u8]]) -> [u8; 32] {
+        INSTALLED.with(|installed| match installed.borrow_mut().as_mut() {
+            Some(table) => table.query(&parts.concat()),
+            None => panic!("Random oracle queried outside of RoTable::run"),
+        })
+    }
+}
+
+#[test]
+fn test_random_oracle() {
+    let mut table = RoTable::new();
+    let (a, b, c) = table.run(|| (RandomOracle::hash(&[b"zk", b"lib"]), RandomOracle::hash(&[b"zklib"]), RandomOracle::hash(&[b"other"])));
+    // Answers are consistent and every query is logged
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(table.log.len(), 3);
+    assert_eq!(table.entries.len(), 2);
+    // A programmed answer is returned from then on
+    assert_eq!(table.program(b"other", [7u8; 32]), Some(c));
+    assert_eq!(table.run(|| RandomOracle::hash(&[b"other"])), [7u8; 32]);
+    // A fork keeps the answers to the queries before it only
+    let mut forked = table.fork(1);
+    assert_eq!(forked.log.len(), 1);
+    assert_eq!(forked.run(|| RandomOracle::hash(&[b"zklib"])), a);
+    assert_ne!(forked.run(|| RandomOracle::hash(&[b"other"])), [7u8; 32]);
+}
+
+#[test]
+fn test_random_oracle_transcript() {
+    // Transcripts run against different tables derive independent challenges
+    let q = BigUint::from(2305843009213693951u64);
+    let challenge = || {
+        let mut transcript = ChallengeTranscript::<RandomOracle>::with_hasher(b"test");
+        transcript.append_message(b"message", b"hello");
+        transcript.challenge_scalar(b"c", &q)
+    };
+    let mut table = RoTable::new();
+    let first = table.run(challenge);
+    assert_eq!(table.run(challenge), first);
+    assert_ne!(RoTable::new().run(challenge), first);
+}
@@ -0,0 +1,260 @@
+use num_bigint::BigUint;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::{Prover, Verifier};
+use crate::modarith::{mod_exp, mod_inverse};
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::secret::Secret;
+#[cfg(test)]
+use num_traits::Zero;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Bellare-Micali oblivious transfer implementation ************
+
+// In 1-out-of-2 oblivious transfer, a sender holding messages m0 and m1 lets a receiver learn m_b
+// for a choice bit b of its choosing, without the sender learning b or the receiver learning m_(1-b).
+//
+// The sender publishes a random group element C whose discrete log the receiver doesn't know. The
+// receiver picks a secret key k, sets PK_b = g^k and PK_(1-b) = C / PK_b, and sends PK_0. Since
+// PK_0 * PK_1 = C, the receiver can only know the secret key for one of them. The sender then
+// ElGamal-encrypts m0 under PK_0 and m1 under PK_1, and the receiver decrypts the one it can.
+//
+// The sender plays the role of the prover and the receiver plays the role of the verifier, accepting
+// once it has decrypted its chosen message.
+
+// Hashed ElGamal ciphertext (g^r, H(PK^r) XOR m)
+pub type Ciphertext = (BigUint, Vec<u8>);
+
+// Longest message a transfer carries. The receiver hashes a pad as long as the ciphertext it decrypts,
+// so it refuses longer ones rather than doing work the sender chooses.
+pub const MAX_OT_MESSAGE_BYTES: usize = 256;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum OTSenderMessage {
+    // Random group element C
+    Setup(#[serde(with = "serde_biguint")] BigUint),
+    // Encryptions (g^r_i, H(PK_i^r_i) XOR m_i) of both messages
+    Ciphertexts(#[serde(with = "serde_ciphertext")] Ciphertext, #[serde(with = "serde_ciphertext")] Ciphertext),
+    // Interaction complete
+    Done
+}
+
+// Serializes ciphertexts as pairs of the hexadecimal ephemeral key and the masked bytes
+pub(crate) mod serde_ciphertext {
+    use num_bigint::BigUint;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Ciphertext;
+
+    #[derive(Serialize, Deserialize)]
+    struct Hex(#[serde(with = "crate::params::serde_biguint")] BigUint, Vec<u8>);
+
+    pub fn serialize<S: Serializer>(x: &Ciphertext, serializer: S) -> Result<S::Ok, S::Error> {
+        Hex(x.0.clone(), x.1.clone()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Ciphertext, D::Error> {
+        let Hex(ephemeral, masked) = Hex::deserialize(deserializer)?;
+        Ok((ephemeral, masked))
+    }
+}
+
+#[derive(Clone)]
+pub enum OTReceiverMessage {
+    // Request for the sender to start a transfer
+    Request,
+    // Public key PK_0, from which the sender derives PK_1 = C / PK_0
+    PublicKey(BigUint),
+}
+
+pub struct OTSender<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random group element sent to the receiver
+    pub c: BigUint,
+    pub params: &'a SchnorrParameters,
+    pub messages: (&'a [u8], &'a [u8]),
+}
+
+impl Prover for OTSender<'_> {
+    type ProverMessage = OTSenderMessage;
+    type VerifierMessage = OTReceiverMessage;
+
+    fn handle(&mut self, msg: &OTReceiverMessage) -> (OTSenderMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match (self.r, msg) {
+            // During the first round, the sender publishes a random group element
+            (1, OTReceiverMessage::Request) => {
+                self.c = params.random_generator();
+                (OTSenderMessage::Setup(self.c.clone()), false)
+            },
+            // During the second round, the sender encrypts each message under the corresponding public key
+            (2, OTReceiverMessage::PublicKey(pk0)) => {
+                let pk1 = other_public_key(params, &self.c, pk0);
+                (OTSenderMessage::Ciphertexts(encrypt(params, pk0, self.messages.0), encrypt(params, &pk1, self.messages.1)), false)
+            },
+            (1, _) | (2, _) => panic!("Receiver did not send a valid message on round {}!", self.r),
+            // After sending the ciphertexts, the sender sends a message to terminate the interaction
+            _ => (OTSenderMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &OTReceiverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, OTReceiverMessage::Request) => Ok(()),
+            (1, _) => Err("expected a request".to_string()),
+            // PK_0 has to be invertible for the sender to derive PK_1
+            (2, OTReceiverMessage::PublicKey(pk0)) => self.params.check_element(pk0),
+            (2, _) => Err("expected a public key".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct OTReceiver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Choice bit b
+    pub choice: bool,
+    // Secret key for PK_b
+    pub k: Secret<BigUint>,
+    // Chosen message once the transfer is complete
+    pub received: Option<Vec<u8>>,
+    pub params: &'a SchnorrParameters,
+}
+
+impl Verifier for OTReceiver<'_> {
+    type ProverMessage = OTSenderMessage;
+    type VerifierMessage = OTReceiverMessage;
+
+    fn init(&mut self) -> OTReceiverMessage {
+        OTReceiverMessage::Request
+    }
+
+    fn validate(&self, msg: &OTSenderMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, OTSenderMessage::Setup(c)) => self.params.check_element(c),
+            (1, _) => Err("expected a setup message".to_string()),
+            (_, OTSenderMessage::Ciphertexts((e0, m0), (e1, m1))) => {
+                if m0.len().max(m1.len()) > MAX_OT_MESSAGE_BYTES {
+                    return Err(format!("ciphertexts of {} and {} bytes exceed the limit of {}", m0.len(), m1.len(), MAX_OT_MESSAGE_BYTES));
+                }
+                self.params.check_element(e0)?;
+                self.params.check_element(e1)
+            },
+            _ => Err("expected ciphertexts".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &OTSenderMessage) -> (OTReceiverMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match self.r {
+            1 => {
+                if let OTSenderMessage::Setup(c) = msg {
+                    let (k, pk0) = receiver_keys(params, c, self.choice);
+                    self.k = Secret::new(k);
+                    (OTReceiverMessage::PublicKey(pk0), false)
+                } else {
+                    panic!("Sender did not send a valid setup message on round 1!")
+                }
+            },
+            _ => {
+                if let OTSenderMessage::Ciphertexts(c0, c1) = msg {
+                    self.received = Some(decrypt(params, &self.k, if self.choice { c1 } else { c0 }));
+                    narrate!("Receiver obtained message {}.", if self.choice {1} else {0});
+                    (OTReceiverMessage::Request, true)
+                } else {
+                    panic!("Sender did not send valid ciphertexts on round 2!")
+                }
+            }
+        }
+    }
+}
+
+// Samples the receiver's secret key k and computes PK_0, where PK_b = g^k and PK_(1-b) = C / PK_b
+pub(crate) fn receiver_keys(params: &SchnorrParameters, c: &BigUint, choice: bool) -> (BigUint, BigUint) {
+    let k = params.random_exponent();
+    let pk_b = mod_exp(&params.g, &k, &params.p);
+    let pk0 = if choice { other_public_key(params, c, &pk_b) } else { pk_b };
+    (k, pk0)
+}
+
+// Derives the public key PK_(1-i) = C / PK_i
+pub(crate) fn other_public_key(params: &SchnorrParameters, c: &BigUint, pk: &BigUint) -> BigUint {
+    c * mod_inverse(pk, &params.p).expect("Public key must be invertible") % &params.p
+}
+
+// Hashed ElGamal encryption of a message under public key pk
+pub(crate) fn encrypt(params: &SchnorrParameters, pk: &BigUint, message: &[u8]) -> Ciphertext {
+    encrypt_with_randomness(params, pk, message, &params.random_exponent())
+}
+
+// Deterministic encryption with the given randomness, so that a sender can later prove what it sent by revealing r
+pub(crate) fn encrypt_with_randomness(params: &SchnorrParameters, pk: &BigUint, message: &[u8], r: &BigUint) -> Ciphertext {
+    (mod_exp(&params.g, r, &params.p), xor_with_pad(&mod_exp(pk, r, &params.p), message))
+}
+
+pub(crate) fn decrypt(params: &SchnorrParameters, k: &BigUint, (ephemeral, masked): &Ciphertext) -> Vec<u8> {
+    xor_with_pad(&mod_exp(ephemeral, k, &params.p), masked)
+}
+
+// XORs the message with a pad derived from the shared group element by hashing it with a counter
+fn xor_with_pad(shared: &BigUint, message: &[u8]) -> Vec<u8> {
+    let key = shared.to_bytes_be();
+    message.chunks(32).enumerate().flat_map(|(i, chunk)| {
+        let mut hasher = Sha256::new();
+        hasher.update(&key);
+        hasher.update((i as u64).to_be_bytes());
+        let pad = hasher.finalize();
+        chunk.iter().zip(pad).map(|(x, y)| x ^ y).collect::<Vec<u8>>()
+    }).collect()
+}
+
+#[test]
+fn test_oblivious_transfer() {
+    let params = SchnorrParameters::generate(64);
+    let m0 = b"the first message, which is longer than a single hash block".to_vec();
+    let m1 = b"the second message".to_vec();
+    for &choice in [false, true].iter() {
+        let mut sender = OTSender { r: 0, c: BigUint::zero(), params: &params, messages: (&m0, &m1) };
+        let mut receiver = OTReceiver { r: 0, choice, k: Secret::default(), received: None, params: &params };
+        assert!(run_interactive_proof(&mut sender, &mut receiver));
+        assert_eq!(receiver.received.unwrap(), if choice { m1.clone() } else { m0.clone() });
+    }
+}
+
+#[test]
+fn test_oblivious_transfer_hides_other_message() {
+    // Decrypting the other ciphertext with the receiver's key yields garbage
+    let params = SchnorrParameters::generate(64);
+    let (m0, m1) = (vec![0u8; 32], vec![1u8; 32]);
+    let mut sender = OTSender { r: 0, c: BigUint::zero(), params: &params, messages: (&m0, &m1) };
+    let mut receiver = OTReceiver { r: 0, choice: false, k: Secret::default(), received: None, params: &params };
+    let request = receiver.init();
+    let (setup, _) = sender.handle(&request);
+    let (pk0, _) = receiver.handle(&setup);
+    let (ciphertexts, _) = sender.handle(&pk0);
+    if let OTSenderMessage::Ciphertexts(_, c1) = ciphertexts {
+        assert_ne!(decrypt(&params, &receiver.k, &c1), m1);
+    } else {
+        panic!("Sender did not send ciphertexts!")
+    }
+}
+
+#[test]
+fn test_oblivious_transfer_validation() {
+    // A setup element or public key outside the group is rejected before it reaches handle
+    let params = SchnorrParameters::generate(64);
+    let (m0, m1) = (vec![0u8; 32], vec![1u8; 32]);
+    let mut sender = OTSender { r: 0, c: BigUint::zero(), params: &params, messages: (&m0, &m1) };
+    let receiver = OTReceiver { r: 0, choice: false, k: Secret::default(), received: None, params: &params };
+    assert!(receiver.validate(&OTSenderMessage::Setup(BigUint::zero())).is_err());
+    assert!(receiver.validate(&OTSenderMessage::Done).is_err());
+    let long = (params.g.clone(), vec![0; MAX_OT_MESSAGE_BYTES + 1]);
+    assert!(receiver.validate(&OTSenderMessage::Ciphertexts(long.clone(), long)).is_err());
+    sender.handle(&OTReceiverMessage::Request);
+    assert!(sender.validate(&OTReceiverMessage::PublicKey(BigUint::zero())).is_err());
+    assert!(sender.validate(&OTReceiverMessage::Request).is_err());
+}
@@ -0,0 +1,259 @@
+use num_bigint::{BigUint, RandBigInt};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use serde::{Serialize, de::DeserializeOwned, Deserialize};
+use crate::modarith::{is_probable_prime, mod_exp, mod_inverse, random_prime, random_safe_prime};
+use crate::randomness::checked_thread_rng;
+use crate::secret::Secret;
+
+// ************ Public parameters for number-theoretic protocols ************
+
+// Public parameters shared by the parties of a protocol, generated with a given security level in bits
+pub trait Parameters: Sized + Serialize + DeserializeOwned {
+    // Generates fresh parameters whose modulus has the given number of bits
+    fn generate(bits: u64) -> Self;
+
+    // Checks that the parameters are well formed, e.g. after loading them from an untrusted source
+    fn validate(&self) -> bool;
+
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // Parses parameters from JSON, returning None if they are malformed or fail validation
+    fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok().filter(Self::validate)
+    }
+}
+
+// Schnorr group: the subgroup of prime order q of the multiplicative group mod p, generated by g.
+// Generated parameters use a safe prime p = 2q + 1, so the subgroup is the quadratic residues mod p.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchnorrParameters {
+    #[serde(with = "serde_biguint")]
+    pub p: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub q: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub g: BigUint,
+}
+
+impl Parameters for SchnorrParameters {
+    fn generate(bits: u64) -> SchnorrParameters {
+        let (p, q) = random_safe_prime(bits);
+        // Squaring a random element lands in the subgroup of order q, and any element other than 1 generates it
+        let two = BigUint::from(2u32);
+        let g = loop {
+            let h = checked_thread_rng().gen_biguint_range(&two, &(&p - 1u32));
+            let g = mod_exp(&h, &two, &p);
+            if !g.is_one() {
+                break g;
+            }
+        };
+        SchnorrParameters { p, q, g }
+    }
+
+    fn validate(&self) -> bool {
+        let one = BigUint::one();
+        is_probable_prime(&self.p) && is_probable_prime(&self.q)
+            && (&self.p - 1u32).is_multiple_of(&self.q)
+            && self.g > one && self.g < self.p
+            && mod_exp(&self.g, &self.q, &self.p).is_one()
+    }
+}
+
+impl SchnorrParameters {
+    // Samples a uniformly random exponent in Z_q
+    pub fn random_exponent(&self) -> BigUint {
+        checked_thread_rng().gen_biguint_below(&self.q)
+    }
+
+    // Checks that x is an element of the subgroup of order q, describing the problem otherwise
+    pub fn check_element(&self, x: &BigUint) -> Result<(), String> {
+        if x.is_zero() || *x >= self.p {
+            Err(format!("{} is not a nonzero residue mod p", x))
+        } else if !mod_exp(x, &self.q, &self.p).is_one() {
+            Err(format!("{} is not in the subgroup of order q", x))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_exponent(&self, x: &BigUint) -> Result<(), String> {
+        if *x >= self.q {
+            Err(format!("{} is not reduced mod q", x))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Samples a random generator of the subgroup of order q, whose discrete log base g is unknown
+    pub fn random_generator(&self) -> BigUint {
+        let cofactor = (&self.p - 1u32) / &self.q;
+        loop {
+            let h = mod_exp(&checked_thread_rng().gen_biguint_range(&BigUint::from(2u32), &self.p), &cofactor, &self.p);
+            if !h.is_one() {
+                return h;
+            }
+        }
+    }
+}
+
+// RSA modulus n = pq with public exponent e, whose factorization is discarded after generation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RsaParameters {
+    #[serde(with = "serde_biguint")]
+    pub n: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub e: BigUint,
+}
+
+// Factorization of an RSA modulus, which should only be held by a trusted party issuing secrets
+pub struct RsaTrapdoor {
+    pub p: Secret<BigUint>,
+    pub q: Secret<BigUint>,
+    // Inverse of e modulo (p-1)(q-1)
+    pub d: Secret<BigUint>,
+}
+
+impl RsaParameters {
+    // Generates an RSA modulus along with its factorization
+    pub fn generate_with_trapdoor(bits: u64) -> (RsaParameters, RsaTrapdoor) {
+        // Smaller moduli can't exceed the exponent, so they would never be valid, and the smallest sizes
+        // have no two distinct primes to multiply, so the search would never end
+        if bits < 17 {
+            panic!("RSA moduli must have at least 17 bits. Found {} bits.", bits);
+        }
+        let e = BigUint::from(65537u32);
+        loop {
+            let p = random_prime(bits / 2);
+            let q = random_prime(bits - bits / 2);
+            let n = &p * &q;
+            if p == q || n.bits() != bits {
+                continue;
+            }
+            // e must be invertible modulo phi(n) for e-th roots to be unique
+            let phi = (&p - 1u32) * (&q - 1u32);
+            if let Some(d) = mod_inverse(&e, &phi) {
+                return (RsaParameters { n, e }, RsaTrapdoor { p: Secret::new(p), q: Secret::new(q), d: Secret::new(d) });
+            }
+        }
+    }
+}
+
+impl Parameters for RsaParameters {
+    fn generate(bits: u64) -> RsaParameters {
+        RsaParameters::generate_with_trapdoor(bits).0
+    }
+
+    fn validate(&self) -> bool {
+        // Without the factorization we can only rule out obviously bad moduli and exponents
+        self.n.is_odd() && !is_probable_prime(&self.n)
+            && self.e.is_odd() && self.e > BigUint::one() && self.e < self.n
+    }
+}
+
+// Serializes big integers as hexadecimal strings, for use with #[serde(with = "serde_biguint")]
+pub(crate) mod serde_biguint {
+    use num_bigint::BigUint;
+    use num_traits::Num;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(x: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&x.to_str_radix(16))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        BigUint::from_str_radix(&s, 16).map_err(D::Error::custom)
+    }
+}
+
+// Serializes lists of big integers as lists of hexadecimal strings
+pub(crate) mod serde_biguints {
+    use num_bigint::BigUint;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Hex(#[serde(with = "super::serde_biguint")] BigUint);
+
+    pub fn serialize<S: Serializer>(xs: &[BigUint], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(xs.iter().map(|x| Hex(x.clone())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BigUint>, D::Error> {
+        Ok(Vec::<Hex>::deserialize(deserializer)?.into_iter().map(|Hex(x)| x).collect())
+    }
+}
+
+// Serializes pairs of big integers as pairs of hexadecimal strings
+pub(crate) mod serde_biguint_pair {
+    use std::convert::TryFrom;
+    use num_bigint::BigUint;
+    use serde::{Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(x: &(BigUint, BigUint), serializer: S) -> Result<S::Ok, S::Error> {
+        super::serde_biguints::serialize(&[x.0.clone(), x.1.clone()], serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(BigUint, BigUint), D::Error> {
+        match <[BigUint; 2]>::try_from(super::serde_biguints::deserialize(deserializer)?) {
+            Ok([a, b]) => Ok((a, b)),
+            Err(xs) => Err(D::Error::custom(format!("expected 2 integers, got {}", xs.len()))),
+        }
+    }
+}
+
+#[test]
+fn test_generate_schnorr_parameters() {
+    let params = SchnorrParameters::generate(64);
+    assert_eq!(params.p.bits(), 64);
+    assert!(params.validate());
+}
+
+#[test]
+fn test_invalid_schnorr_parameters() {
+    let mut params = SchnorrParameters::generate(64);
+    // The identity does not generate the subgroup
+    params.g = BigUint::one();
+    assert!(!params.validate());
+}
+
+#[test]
+fn test_random_generator() {
+    let params = SchnorrParameters::generate(64);
+    let h = params.random_generator();
+    assert!(mod_exp(&h, &params.q, &params.p).is_one());
+    assert!(params.random_exponent() < params.q);
+}
+
+#[test]
+fn test_generate_rsa_parameters() {
+    let (params, trapdoor) = RsaParameters::generate_with_trapdoor(128);
+    assert_eq!(params.n.bits(), 128);
+    assert_eq!(params.n, &*trapdoor.p * &*trapdoor.q);
+    assert!(params.validate());
+    // Encryption followed by decryption should be the identity
+    let m = BigUint::from(1234567u32);
+    assert_eq!(mod_exp(&mod_exp(&m, &params.e, &params.n), &trapdoor.d, &params.n), m);
+}
+
+#[test]
+#[should_panic(expected = "at least 17 bits")]
+fn test_tiny_rsa_parameters() {
+    RsaParameters::generate(4);
+}
+
+#[test]
+fn test_parameters_json_roundtrip() {
+    let params = SchnorrParameters::generate(64);
+    assert_eq!(SchnorrParameters::from_json(&params.to_json()), Some(params));
+    let params = RsaParameters::generate(128);
+    assert_eq!(RsaParameters::from_json(&params.to_json()), Some(params));
+}
+
+#[test]
+fn test_parameters_from_invalid_json() {
+    assert_eq!(SchnorrParameters::from_json("{\"p\": \"17\", \"q\": \"8\", \"g\": \"3\"}"), None);
+    assert_eq!(SchnorrParameters::from_json("not json"), None);
+}
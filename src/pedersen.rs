@@ -0,0 +1,91 @@
+use num_bigint::BigUint;
+use num_traits::One;
+use serde::{Serialize, Deserialize};
+use crate::modarith::mod_exp;
+use crate::params::{Parameters, SchnorrParameters, serde_biguint};
+use crate::secret::Secret;
+
+// ************ Pedersen commitment implementation ************
+
+// A Pedersen commitment to a value x in Z_q is C = g^x h^r for fresh randomness r, where g and h generate
+// a Schnorr group and nobody knows the discrete log of h base g. The commitment is perfectly hiding,
+// since g^x h^r is a uniformly random group element for any x, and computationally binding, since opening
+// C to two different values reveals log_g h. Unlike hash-based commitments, Pedersen commitments are
+// homomorphic and have algebraic structure that sigma protocols can prove statements about.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PedersenParameters {
+    pub group: SchnorrParameters,
+    // Second generator whose discrete log base g is unknown
+    #[serde(with = "serde_biguint")]
+    pub h: BigUint,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PedersenCommitment {
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PedersenOpening {
+    pub value: BigUint,
+    pub randomness: Secret<BigUint>,
+}
+
+impl Parameters for PedersenParameters {
+    fn generate(bits: u64) -> PedersenParameters {
+        let group = SchnorrParameters::generate(bits);
+        let h = group.random_generator();
+        PedersenParameters { group, h }
+    }
+
+    fn validate(&self) -> bool {
+        let group = &self.group;
+        group.validate() && !self.h.is_one() && self.h < group.p
+            && mod_exp(&self.h, &group.q, &group.p).is_one()
+    }
+}
+
+impl PedersenParameters {
+    // Commits to a value in Z_q with fresh randomness
+    pub fn commit(&self, value: &BigUint) -> (PedersenCommitment, PedersenOpening) {
+        let opening = PedersenOpening { value: value % &self.group.q, randomness: Secret::new(self.group.random_exponent()) };
+        (self.commit_with_randomness(&opening.value, &opening.randomness), opening)
+    }
+
+    pub fn commit_with_randomness(&self, value: &BigUint, randomness: &BigUint) -> PedersenCommitment {
+        let group = &self.group;
+        PedersenCommitment { c: mod_exp(&group.g, value, &group.p) * mod_exp(&self.h, randomness, &group.p) % &group.p }
+    }
+}
+
+impl PedersenCommitment {
+    pub fn verify(&self, params: &PedersenParameters, opening: &PedersenOpening) -> bool {
+        *self == params.commit_with_randomness(&opening.value, &opening.randomness)
+    }
+
+    // Commitment to the sum of the committed values, opened by the sums of the values and randomness
+    pub fn add(&self, params: &PedersenParameters, other: &PedersenCommitment) -> PedersenCommitment {
+        PedersenCommitment { c: &self.c * &other.c % &params.group.p }
+    }
+}
+
+#[test]
+fn test_pedersen_commitment() {
+    let params = PedersenParameters::generate(64);
+    assert!(params.validate());
+    let (commitment, opening) = params.commit(&BigUint::from(42u32));
+    assert!(commitment.verify(&params, &opening));
+    let wrong = PedersenOpening { value: BigUint::from(43u32), randomness: opening.randomness.clone() };
+    assert!(!commitment.verify(&params, &wrong));
+}
+
+#[test]
+fn test_pedersen_homomorphism() {
+    let params = PedersenParameters::generate(64);
+    let (c1, o1) = params.commit(&BigUint::from(20u32));
+    let (c2, o2) = params.commit(&BigUint::from(22u32));
+    let sum = PedersenOpening { value: BigUint::from(42u32), randomness: Secret::new((&*o1.randomness + &*o2.randomness) % &params.group.q) };
+    assert!(c1.add(&params, &c2).verify(&params, &sum));
+}
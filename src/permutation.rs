@@ -0,0 +1,135 @@
+use std::convert::TryFrom;
+use rand::{Rng, seq::SliceRandom};
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroize;
+use crate::graph::{MAX_DESERIALIZED_VERTICES, MAX_VERTICES, vertex_label};
+use crate::secret::Erase;
+
+// ************ Permutation implementation ************
+
+// A bijection on the vertices 0..n, mapping vertex v to map[v]. The map is checked when a permutation
+// is constructed, including when it is deserialized, so every Permutation is a valid isomorphism
+// candidate and code handling one never has to check for repeated or out-of-range vertices.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "Vec<u32>", into = "Vec<u32>")]
+pub struct Permutation {
+    map: Vec<u32>,
+}
+
+impl Permutation {
+    // Checks that the map is a bijection on 0..n, where n is its length and at most MAX_VERTICES,
+    // describing the problem otherwise
+    pub fn new(map: Vec<u32>) -> Result<Permutation, String> {
+        let n = map.len();
+        if n > MAX_VERTICES as usize {
+            return Err(format!("Permutation has {} vertices, more than the limit of {}", n, MAX_VERTICES));
+        }
+        let mut seen = vec![false; n];
+        for &x in map.iter() {
+            if x as usize >= n {
+                return Err(format!("Permutation maps to vertex {}, which is out of range", x));
+            }
+            if std::mem::replace(&mut seen[x as usize], true) {
+                return Err(format!("Permutation maps two vertices to vertex {}", x));
+            }
+        }
+        Ok(Permutation { map })
+    }
+
+    pub fn identity(n: u32) -> Permutation {
+        assert!(n <= MAX_VERTICES, "Permutation has {} vertices, more than the limit of {}", n, MAX_VERTICES);
+        Permutation { map: (0..n).collect() }
+    }
+
+    // Samples a uniformly random permutation of 0..n
+    pub fn random<R: Rng + ?Sized>(n: u32, rng: &mut R) -> Permutation {
+        assert!(n <= MAX_VERTICES, "Permutation has {} vertices, more than the limit of {}", n, MAX_VERTICES);
+        let mut map: Vec<u32> = (0..n).collect();
+        map.shuffle(rng);
+        Permutation { map }
+    }
+
+    // Number of vertices the permutation acts on
+    pub fn n(&self) -> u32 {
+        vertex_label(self.map.len())
+    }
+
+    pub fn apply(&self, v: u32) -> u32 {
+        self.map[v as usize]
+    }
+
+    pub fn invert(&self) -> Permutation {
+        let mut inverted = vec![0; self.map.len()];
+        self.map.iter().enumerate().for_each(|(i, &x)| inverted[x as usize] = vertex_label(i));
+        Permutation { map: inverted }
+    }
+
+    // Applies self, then other. Panics if the two act on different numbers of vertices.
+    pub fn compose(&self, other: &Permutation) -> Permutation {
+        assert_eq!(self.n(), other.n(), "Cannot compose permutations of different sizes");
+        Permutation { map: self.map.iter().map(|&x| other.apply(x)).collect() }
+    }
+
+    pub fn as_slice(&self) -> &[u32] {
+        &self.map
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.map.iter().flat_map(|x| x.to_be_bytes()).collect()
+    }
+}
+
+impl TryFrom<Vec<u32>> for Permutation {
+    type Error = String;
+
+    // Used when deserializing, so messages are held to the smaller limit on graphs in messages
+    fn try_from(map: Vec<u32>) -> Result<Permutation, String> {
+        if map.len() > MAX_DESERIALIZED_VERTICES as usize {
+            return Err(format!("Permutation has {} vertices, more than the limit of {}", map.len(), MAX_DESERIALIZED_VERTICES));
+        }
+        Permutation::new(map)
+    }
+}
+
+impl From<Permutation> for Vec<u32> {
+    fn from(permutation: Permutation) -> Vec<u32> {
+        permutation.map
+    }
+}
+
+// Leaves the permutation on no vertices, which is still a valid permutation, after overwriting its map
+impl Erase for Permutation {
+    fn erase(&mut self) {
+        self.map.zeroize();
+    }
+}
+
+#[test]
+fn test_permutation_invariants() {
+    assert!(Permutation::new(vec![2, 0, 1]).is_ok());
+    assert_eq!(Permutation::new(vec![0, 0, 1]), Err("Permutation maps two vertices to vertex 0".to_string()));
+    assert_eq!(Permutation::new(vec![0, 3, 1]), Err("Permutation maps to vertex 3, which is out of range".to_string()));
+    // Messages are held to a smaller size than permutations built locally
+    let large: Vec<u32> = (0..MAX_DESERIALIZED_VERTICES + 1).collect();
+    assert!(Permutation::new(large.clone()).is_ok());
+    assert!(serde_json::from_str::<Permutation>(&serde_json::to_string(&large).unwrap()).is_err());
+}
+
+#[test]
+fn test_permutation_compose_invert() {
+    let p = Permutation::random(10, &mut rand::thread_rng());
+    let q = Permutation::random(10, &mut rand::thread_rng());
+    assert_eq!(p.compose(&p.invert()), Permutation::identity(10));
+    assert_eq!(p.invert().compose(&p), Permutation::identity(10));
+    assert_eq!((0..10).map(|v| q.apply(p.apply(v))).collect::<Vec<u32>>(), p.compose(&q).as_slice());
+}
+
+#[test]
+fn test_permutation_serde() {
+    let p = Permutation::new(vec![1, 2, 0]).unwrap();
+    let json = serde_json::to_string(&p).unwrap();
+    assert_eq!(json, "[1,2,0]");
+    assert_eq!(serde_json::from_str::<Permutation>(&json).unwrap(), p);
+    // Deserialization enforces the same invariants as construction
+    assert!(serde_json::from_str::<Permutation>("[1,1,0]").is_err());
+}
@@ -0,0 +1,288 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::commitandprove::{CommittedProof, RangeProof};
+use crate::graph::check_count;
+use crate::modarith::mod_exp;
+use crate::params::{serde_biguint, serde_biguints};
+use crate::pedersen::{PedersenCommitment, PedersenOpening, PedersenParameters};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::secret::Secret;
+#[cfg(test)]
+use rand::thread_rng;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Permutation commitment implementation ************
+
+// A permutation pi on n vertices is committed to as its permutation matrix, with a Pedersen commitment
+// C_ij to the bit that is 1 exactly when pi(i) = j. Unlike a hash commitment to its encoding, the matrix
+// can be used homomorphically, e.g. multiplying row i's commitments weighted by the committed values of
+// vertices j commits to the value of pi(i), which is what shuffle proofs and proofs about committed
+// isomorphisms build on.
+//
+// A committed matrix is only useful once it is known to be a permutation matrix. The prover shows this
+// with a sigma protocol for three facts about the matrix, which together make it a permutation matrix:
+//
+// - every entry is a bit, by an OR proof that C_ij = h^r or C_ij / g = h^r, as in RangeProof with one bit
+// - every row sums to 1, by revealing the total randomness R of the row, so that the product of its
+//   commitments is g h^R
+// - every column sums to 1, in the same way
+//
+// The OR proofs share a single challenge. The row and column totals are sums of independent uniformly
+// random values, and every entry keeps randomness of its own that isn't fixed by them, so the commitments
+// stay perfectly hiding.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PermutationCommitment {
+    pub n: u32,
+    // Commitments to the entries of the permutation matrix, row by row
+    pub entries: Vec<PedersenCommitment>,
+}
+
+pub struct PermutationOpening {
+    pub permutation: Permutation,
+    pub openings: Vec<PedersenOpening>,
+}
+
+// Entries (i, j) of a matrix on n vertices, row by row
+fn matrix(n: u32) -> impl Iterator<Item = (u32, u32)> {
+    (0..n).flat_map(move |i| (0..n).map(move |j| (i, j)))
+}
+
+impl PermutationCommitment {
+    pub fn commit(params: &PedersenParameters, permutation: &Permutation) -> (PermutationCommitment, PermutationOpening) {
+        let n = permutation.n();
+        let (entries, openings) = matrix(n)
+            .map(|(i, j)| params.commit(&BigUint::from((permutation.apply(i) == j) as u32))).unzip();
+        (PermutationCommitment { n, entries }, PermutationOpening { permutation: permutation.clone(), openings })
+    }
+
+    // Checks that the opening matches the commitment
+    pub fn verify(&self, params: &PedersenParameters, opening: &PermutationOpening) -> bool {
+        let n = self.n;
+        opening.permutation.n() == n && self.entries.len() == opening.openings.len()
+            && matrix(n).zip(self.entries.iter().zip(&opening.openings)).all(|((i, j), (c, o))| {
+                o.value == BigUint::from((opening.permutation.apply(i) == j) as u32) && c.verify(params, o)
+            })
+    }
+
+    pub fn entry(&self, i: u32, j: u32) -> &PedersenCommitment {
+        &self.entries[(i * self.n + j) as usize]
+    }
+
+    // Checks that there is a commitment in the group for every entry of a matrix on n vertices
+    pub fn check_commitments(&self, params: &PedersenParameters, n: u32) -> Result<(), String> {
+        check_count(self.n as usize, n as usize, "vertices in committed permutation")?;
+        check_count(self.entries.len(), n as usize * n as usize, "commitments to entries")?;
+        self.entries.iter().try_for_each(|x| params.group.check_element(&x.c))
+    }
+
+    // Checks that the commitments open to the permutation's matrix with the randomness, row by row
+    pub fn check_opening(&self, params: &PedersenParameters, permutation: &Permutation, randomness: &[BigUint]) -> bool {
+        let n = self.n;
+        permutation.n() == n && randomness.len() == self.entries.len()
+            && matrix(n).zip(self.entries.iter().zip(randomness)).all(|((i, j), (c, r))| {
+                *c == params.commit_with_randomness(&BigUint::from((permutation.apply(i) == j) as u32), r)
+            })
+    }
+
+    // Checks that entry (i, j) of other commits to the same bit as entry source(i, j) of this commitment,
+    // given the differences between their randomness row by row, without revealing either bit
+    pub fn check_rearranged(&self, params: &PedersenParameters, other: &PermutationCommitment, source: impl Fn(u32, u32) -> (u32, u32), differences: &[BigUint]) -> bool {
+        let (n, p) = (self.n, &params.group.p);
+        other.n == n && other.entries.len() == self.entries.len() && differences.len() == self.entries.len()
+            && matrix(n).zip(other.entries.iter().zip(differences)).all(|((i, j), (c, d))| {
+                let (a, b) = source(i, j);
+                c.c == &self.entry(a, b).c * mod_exp(&params.h, d, p) % p
+            })
+    }
+
+    // Products of the commitments in each row, followed by those in each column
+    fn lines(&self, params: &PedersenParameters) -> Vec<BigUint> {
+        let (n, p) = (self.n, &params.group.p);
+        let product = |entries: Vec<&PedersenCommitment>| entries.iter().fold(BigUint::one(), |acc, x| acc * &x.c % p);
+        let rows = (0..n).map(|i| product((0..n).map(|j| self.entry(i, j)).collect()));
+        let columns = (0..n).map(|j| product((0..n).map(|i| self.entry(i, j)).collect()));
+        rows.chain(columns).collect()
+    }
+}
+
+impl PermutationOpening {
+    // Randomness of every entry, row by row, which opens the commitment along with the permutation
+    pub fn randomness(&self) -> Vec<BigUint> {
+        self.openings.iter().map(|x| (*x.randomness).clone()).collect()
+    }
+
+    // Differences between the randomness of entry (i, j) of other and of entry source(i, j) of this
+    // opening, row by row, as checked by PermutationCommitment::check_rearranged
+    pub fn differences(&self, q: &BigUint, other: &PermutationOpening, source: impl Fn(u32, u32) -> (u32, u32)) -> Vec<BigUint> {
+        let n = self.permutation.n();
+        matrix(n).zip(&other.openings).map(|((i, j), opening)| {
+            let (a, b) = source(i, j);
+            (&*opening.randomness + q - &*self.openings[(a * n + b) as usize].randomness % q) % q
+        }).collect()
+    }
+
+    // Total randomness of each row, followed by that of each column
+    fn line_randomness(&self, q: &BigUint) -> Vec<BigUint> {
+        let n = self.permutation.n() as usize;
+        let total = |indices: Vec<usize>| indices.iter().fold(BigUint::zero(), |acc, &k| (acc + &*self.openings[k].randomness) % q);
+        let rows = (0..n).map(|i| total((0..n).map(|j| i * n + j).collect()));
+        let columns = (0..n).map(|j| total((0..n).map(|i| i * n + j).collect()));
+        rows.chain(columns).collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PermutationMatrixProverMessage {
+    // Announcements of the OR proof for each entry, and the total randomness of each row and column
+    Announcement {
+        #[serde(with = "serde_biguints")]
+        bits: Vec<BigUint>,
+        #[serde(with = "serde_biguints")]
+        lines: Vec<BigUint>,
+    },
+    // Responses of the OR proof for each entry
+    Response(#[serde(with = "serde_biguints")] Vec<BigUint>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PermutationMatrixVerifierMessage {
+    // Challenge shared by the OR proofs
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+// Proves that a commitment is to a permutation matrix
+pub struct PermutationMatrixProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Secret state of the OR proof for each entry
+    pub state: Secret<Vec<Vec<BigUint>>>,
+    pub params: &'a PedersenParameters,
+    pub opening: &'a PermutationOpening,
+}
+
+impl Prover for PermutationMatrixProver<'_> {
+    type ProverMessage = PermutationMatrixProverMessage;
+    type VerifierMessage = PermutationMatrixVerifierMessage;
+
+    fn handle(&mut self, msg: &PermutationMatrixVerifierMessage) -> (PermutationMatrixProverMessage, bool) {
+        self.r += 1;
+        let proof = RangeProof { bits: 1 };
+        match self.r {
+            // During the first round, the prover announces the OR proofs and reveals the row and column totals
+            1 => {
+                let (bits, state): (Vec<_>, Vec<_>) = self.opening.openings.iter().map(|x| proof.announce(self.params, x, &())).unzip();
+                self.state = Secret::new(state);
+                let lines = self.opening.line_randomness(&self.params.group.q);
+                (PermutationMatrixProverMessage::Announcement { bits: bits.concat(), lines }, false)
+            },
+            // During the second round, the prover responds to the challenge in every OR proof
+            2 => {
+                let response = self.opening.openings.iter().zip(self.state.iter()).flat_map(|(x, state)| proof.respond(self.params, x, &(), state, &msg.c)).collect();
+                (PermutationMatrixProverMessage::Response(response), false)
+            },
+            // After responding, the prover sends a message to terminate the interaction
+            _ => (PermutationMatrixProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct PermutationMatrixVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    pub bits: Vec<BigUint>,
+    pub lines: Vec<BigUint>,
+    pub challenge: BigUint,
+    pub params: &'a PedersenParameters,
+    pub commitment: &'a PermutationCommitment,
+}
+
+impl PermutationMatrixVerifier<'_> {
+    pub fn new<'a>(params: &'a PedersenParameters, commitment: &'a PermutationCommitment) -> PermutationMatrixVerifier<'a> {
+        PermutationMatrixVerifier { r: 0, bits: Vec::new(), lines: Vec::new(), challenge: BigUint::zero(), params, commitment }
+    }
+}
+
+impl Verifier for PermutationMatrixVerifier<'_> {
+    type ProverMessage = PermutationMatrixProverMessage;
+    type VerifierMessage = PermutationMatrixVerifierMessage;
+
+    fn init(&mut self) -> PermutationMatrixVerifierMessage {
+        PermutationMatrixVerifierMessage { c: BigUint::zero() }
+    }
+
+    fn validate(&self, msg: &PermutationMatrixProverMessage) -> Result<(), String> {
+        let n = self.commitment.n as usize;
+        match (self.r + 1, msg) {
+            (1, PermutationMatrixProverMessage::Announcement { bits, lines }) => {
+                self.commitment.check_commitments(self.params, self.commitment.n)?;
+                check_count(bits.len(), 3 * n * n, "OR proof announcements")?;
+                check_count(lines.len(), 2 * n, "row and column totals")
+            },
+            (1, _) => Err("expected an announcement".to_string()),
+            (_, PermutationMatrixProverMessage::Response(response)) => check_count(response.len(), 3 * n * n, "OR proof responses"),
+            _ => Err("expected a response".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &PermutationMatrixProverMessage) -> (PermutationMatrixVerifierMessage, bool) {
+        self.r += 1;
+        let (p, q) = (&self.params.group.p, &self.params.group.q);
+        match (self.r, msg) {
+            (1, PermutationMatrixProverMessage::Announcement { bits, lines }) => {
+                self.bits = bits.clone();
+                self.lines = lines.clone();
+                self.challenge = checked_thread_rng().gen_range(BigUint::zero()..q.clone());
+                (PermutationMatrixVerifierMessage { c: self.challenge.clone() }, false)
+            },
+            (_, PermutationMatrixProverMessage::Response(response)) => {
+                let proof = RangeProof { bits: 1 };
+                let g = &self.params.group.g;
+                let lines_sum_to_one = self.commitment.lines(self.params).iter().zip(&self.lines).all(|(product, randomness)| {
+                    *product == g * mod_exp(&self.params.h, randomness, p) % p
+                });
+                let entries_are_bits = self.commitment.entries.iter().zip(self.bits.chunks(3).zip(response.chunks(3))).all(|(c, (a, z))| {
+                    proof.check(self.params, c, a, &self.challenge, z)
+                });
+                (PermutationMatrixVerifierMessage { c: self.challenge.clone() }, lines_sum_to_one && entries_are_bits)
+            },
+            _ => panic!("Prover did not send a valid message on round {}!", self.r),
+        }
+    }
+}
+
+#[test]
+fn test_permutation_commitment() {
+    let params = PedersenParameters::generate(64);
+    let permutation = Permutation::random(5, &mut thread_rng());
+    let (commitment, opening) = PermutationCommitment::commit(&params, &permutation);
+    assert!(commitment.verify(&params, &opening));
+    let mut prover = PermutationMatrixProver { r: 0, state: Secret::new(Vec::new()), params: &params, opening: &opening };
+    let mut verifier = PermutationMatrixVerifier::new(&params, &commitment);
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+
+    // Runs the proof on a matrix of committed values
+    let prove = |values: &[BigUint]| {
+        let (entries, openings) = values.iter().map(|x| params.commit(x)).unzip();
+        let commitment = PermutationCommitment { n: 2, entries };
+        let opening = PermutationOpening { permutation: Permutation::identity(2), openings };
+        let mut prover = PermutationMatrixProver { r: 0, state: Secret::new(Vec::new()), params: &params, opening: &opening };
+        let mut verifier = PermutationMatrixVerifier::new(&params, &commitment);
+        run_interactive_proof(&mut prover, &mut verifier)
+    };
+    let (zero, one) = (BigUint::zero(), BigUint::one());
+    assert!(prove(&[zero.clone(), one.clone(), one.clone(), zero.clone()]));
+    // A row of two ones and a row of none fails the row totals
+    assert!(!prove(&[one.clone(), one.clone(), zero.clone(), zero]));
+    // Entries other than bits fail their OR proofs, even where the rows and columns sum to 1 mod q
+    let minus_one = &params.group.q - 1u32;
+    assert!(!prove(&[BigUint::from(2u32), minus_one.clone(), minus_one, one]));
+}
@@ -0,0 +1,162 @@
+use crate::{Prover, ValidationError, Verifier};
+use crate::transcript::{ChallengeTranscript, ProofContext, TranscriptMessage};
+#[cfg(test)]
+use num_bigint::BigUint;
+#[cfg(test)]
+use crate::graph::{GIProver, GIVerifier, Graph, GraphPair};
+#[cfg(test)]
+use crate::params::{Parameters, SchnorrParameters};
+#[cfg(test)]
+use crate::schnorr::{SchnorrKeyPair, SchnorrProver, SchnorrVerifier};
+#[cfg(test)]
+use crate::secret::Secret;
+
+// ************ Protocol pipeline implementation ************
+
+// A pipeline runs several protocols one after another within a single session, e.g. a GI proof followed
+// by a Schnorr proof. Every stage's messages pass through the pipeline, which records them in one
+// transcript bound to the session's context, and each stage's verdict is collected into one report. The
+// session is only accepted if every stage is.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageReport {
+    pub name: String,
+    pub accepted: bool,
+    // Number of prover messages the verifier handled
+    pub rounds: u32,
+    // Total size of the messages exchanged
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipelineReport {
+    pub context: ProofContext,
+    pub stages: Vec<StageReport>,
+    // Digest of the transcript of every message in the session
+    pub digest: [u8; 32],
+}
+
+impl PipelineReport {
+    pub fn accepted(&self) -> bool {
+        !self.stages.is_empty() && self.stages.iter().all(|x| x.accepted)
+    }
+}
+
+type StageRunner<'a> = Box<dyn FnMut(&mut ChallengeTranscript) -> (bool, u32) + 'a>;
+
+pub struct ProtocolPipeline<'a> {
+    pub context: ProofContext,
+    stages: Vec<(String, StageRunner<'a>)>,
+}
+
+impl<'a> ProtocolPipeline<'a> {
+    pub fn new(context: ProofContext) -> ProtocolPipeline<'a> {
+        ProtocolPipeline { context, stages: Vec::new() }
+    }
+
+    // Adds a protocol to run after the stages added so far
+    pub fn add_stage<T: TranscriptMessage, U: TranscriptMessage>(&mut self, name: &str, prover: &'a mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &'a mut dyn Verifier<ProverMessage = T, VerifierMessage = U>) {
+        self.stages.push((name.to_string(), Box::new(move |transcript| run_transcribed_proof(prover, verifier, transcript))));
+    }
+
+    // Runs every stage in order, even after a rejection, so the report has a verdict for each one
+    pub fn run(&mut self) -> PipelineReport {
+        let mut transcript = ChallengeTranscript::with_context(&self.context);
+        let mut stages = Vec::new();
+        for (name, runner) in self.stages.iter_mut() {
+            narrate!("Running stage {}.", name);
+            transcript.append_message(b"stage", name.as_bytes());
+            let start = transcript.message_bytes();
+            let (accepted, rounds) = runner(&mut transcript);
+            let bytes = transcript.message_bytes() - start;
+            transcript.append_message(b"verdict", &[accepted as u8]);
+            stages.push(StageReport { name: name.clone(), accepted, rounds, bytes });
+        }
+        let report = PipelineReport { context: self.context.clone(), stages, digest: transcript.digest() };
+        if report.accepted() {
+            narrate!("Pipeline accepted.");
+        } else {
+            narrate!("Pipeline rejected.");
+        }
+        report
+    }
+}
+
+// Runs an interactive proof like run_interactive_proof, absorbing every message into the transcript and
+// returning the verdict along with the number of rounds. Invalid messages are rejected the same way. The
+// number of bytes exchanged is the growth of the transcript's message_bytes.
+pub fn run_transcribed_proof<T: TranscriptMessage, U: TranscriptMessage>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>, transcript: &mut ChallengeTranscript) -> (bool, u32) {
+    let mut verifier_msg = verifier.init();
+    let mut accept = false;
+    let mut rounds = 0;
+    loop {
+        verifier_msg.append_to(transcript);
+        if let Err(reason) = prover.validate(&verifier_msg) {
+            narrate!("{}.", ValidationError::VerifierMessage(rounds + 1, reason));
+            return (false, rounds);
+        }
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        prover_msg.append_to(transcript);
+        if done {
+            break;
+        }
+        if let Err(reason) = verifier.validate(&prover_msg) {
+            narrate!("{}.", ValidationError::ProverMessage(rounds + 1, reason));
+            return (false, rounds);
+        }
+        let x = verifier.handle(&prover_msg);
+        verifier_msg = x.0;
+        accept = x.1;
+        rounds += 1;
+    }
+    (accept, rounds)
+}
+
+#[cfg(test)]
+// Runs a GI proof followed by a Schnorr proof for the given public key, returning the report
+fn run_gi_then_schnorr(key: &SchnorrKeyPair, y: &BigUint, session_id: &[u8]) -> PipelineReport {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let mut gi_prover = GIProver::new(&instance);
+    let mut gi_verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let mut schnorr_prover = SchnorrProver { r: 0, nonce: Secret::default(), key };
+    let mut schnorr_verifier = SchnorrVerifier {
+        r: 0,
+        commitment: BigUint::from(0u32),
+        challenge: BigUint::from(0u32),
+        transcript: ChallengeTranscript::new(b"schnorr identification"),
+        params: &key.params,
+        y,
+    };
+    let mut pipeline = ProtocolPipeline::new(ProofContext::new("test pipeline", session_id, b"statement"));
+    pipeline.add_stage("graph isomorphism", &mut gi_prover, &mut gi_verifier);
+    pipeline.add_stage("schnorr identification", &mut schnorr_prover, &mut schnorr_verifier);
+    pipeline.run()
+}
+
+#[test]
+fn test_pipeline() {
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let report = run_gi_then_schnorr(&key, &key.y, b"session");
+    assert!(report.accepted());
+    assert_eq!(report.stages.iter().map(|x| (x.name.as_str(), x.accepted, x.rounds)).collect::<Vec<_>>(),
+        vec![("graph isomorphism", true, 2), ("schnorr identification", true, 2)]);
+}
+
+#[test]
+fn test_pipeline_stage_rejected() {
+    // A prover without the secret key passes the GI stage but fails the Schnorr stage, failing the session
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let other = SchnorrKeyPair::generate(&key.params);
+    let report = run_gi_then_schnorr(&key, &other.y, b"session");
+    assert!(!report.accepted());
+    assert_eq!(report.stages.iter().map(|x| x.accepted).collect::<Vec<bool>>(), vec![true, false]);
+}
+
+#[test]
+fn test_empty_pipeline() {
+    let report = ProtocolPipeline::new(ProofContext::new("test pipeline", b"session", b"statement")).run();
+    assert!(!report.accepted());
+}
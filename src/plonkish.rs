@@ -0,0 +1,294 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use crate::circuit::{Circuit, Gate};
+#[cfg(test)]
+use crate::circuit::EXAMPLE_CIRCUIT;
+
+// ************ Plonkish arithmetization implementation ************
+
+// A Plonkish circuit is a table over the integers modulo a prime, with one row per gate and three wire
+// columns a, b, and c. Each row has selectors that fix its gate, and is satisfied when
+//
+//   q_l a + q_r b + q_o c + q_m a b + q_c + public input = 0
+//
+// so that addition, multiplication, constants, and public inputs are all one kind of row. Rows don't name
+// the wires they read: copy constraints instead require cells in different rows to hold equal values.
+// The copy constraints are summarized by a permutation of the cells that cycles through each class of
+// cells that must be equal, which is what a PLONK-style permutation argument commits to, so an assignment
+// satisfies them exactly when every cell holds the same value as its image.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    A,
+    B,
+    C,
+}
+
+// A cell of the table, numbered column by column by index so that the a cells come first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub column: Column,
+    pub row: usize,
+}
+
+impl Cell {
+    fn index(self, rows: usize) -> usize {
+        let column = match self.column {
+            Column::A => 0,
+            Column::B => 1,
+            Column::C => 2,
+        };
+        column * rows + self.row
+    }
+}
+
+// Selectors of one row, reduced modulo the circuit's modulus when checked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlonkGate {
+    pub q_l: BigUint,
+    pub q_r: BigUint,
+    pub q_o: BigUint,
+    pub q_m: BigUint,
+    pub q_c: BigUint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlonkishCircuit {
+    pub modulus: BigUint,
+    pub gates: Vec<PlonkGate>,
+    pub copies: Vec<(Cell, Cell)>,
+    // Rows whose constraint includes a public input, in the order the public inputs are given
+    pub public_rows: Vec<usize>,
+}
+
+// Values of the cells in the a, b, and c columns, one per row
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlonkishAssignment {
+    pub a: Vec<BigUint>,
+    pub b: Vec<BigUint>,
+    pub c: Vec<BigUint>,
+}
+
+impl PlonkishCircuit {
+    pub fn rows(&self) -> usize {
+        self.gates.len()
+    }
+
+    // Checks that the modulus is at least 2 and that copy constraints and public input rows refer to rows
+    // of the table
+    pub fn is_valid(&self) -> bool {
+        let rows = self.rows();
+        self.modulus >= BigUint::from(2u32)
+            && self.copies.iter().all(|(x, y)| x.row < rows && y.row < rows) && self.public_rows.iter().all(|&row| row < rows)
+    }
+
+    // Permutation of the 3 * rows cells, numbered as by Cell, whose cycles are the classes of cells that
+    // copy constraints make equal
+    pub fn copy_permutation(&self) -> Vec<usize> {
+        let cells = 3 * self.rows();
+        let mut classes: Vec<usize> = (0..cells).collect();
+        fn find(classes: &mut [usize], mut x: usize) -> usize {
+            while classes[x] != x {
+                classes[x] = classes[classes[x]];
+                x = classes[x];
+            }
+            x
+        }
+        for (x, y) in &self.copies {
+            let (x, y) = (find(&mut classes, x.index(self.rows())), find(&mut classes, y.index(self.rows())));
+            classes[x] = y;
+        }
+        // Each cell maps to the next cell of its class, and the last to the first
+        let mut first = vec![usize::MAX; cells];
+        let mut last = vec![usize::MAX; cells];
+        let mut permutation: Vec<usize> = (0..cells).collect();
+        for cell in 0..cells {
+            let class = find(&mut classes, cell);
+            if first[class] == usize::MAX {
+                first[class] = cell;
+            } else {
+                permutation[last[class]] = cell;
+            }
+            last[class] = cell;
+        }
+        for class in 0..cells {
+            if last[class] != usize::MAX {
+                permutation[last[class]] = first[class];
+            }
+        }
+        permutation
+    }
+
+    // Checks that the assignment satisfies every gate with the given public inputs and every copy
+    // constraint, describing the first violation otherwise
+    pub fn check(&self, assignment: &PlonkishAssignment, public_inputs: &[BigUint]) -> Result<(), String> {
+        check_modulus(&self.modulus)?;
+        if !self.is_valid() {
+            return Err("Copy constraints and public inputs must refer to rows of the table".to_string());
+        }
+        let rows = self.rows();
+        if [&assignment.a, &assignment.b, &assignment.c].iter().any(|column| column.len() != rows) {
+            return Err(format!("Assignment must have {} values in each column", rows));
+        }
+        if public_inputs.len() != self.public_rows.len() {
+            return Err(format!("Expected {} public inputs. Found {} public inputs.", self.public_rows.len(), public_inputs.len()));
+        }
+        let p = &self.modulus;
+        let mut public = vec![BigUint::zero(); rows];
+        for (&row, input) in self.public_rows.iter().zip(public_inputs) {
+            public[row] = (&public[row] + input) % p;
+        }
+        for (row, gate) in self.gates.iter().enumerate() {
+            let (a, b, c) = (&assignment.a[row], &assignment.b[row], &assignment.c[row]);
+            let value = &gate.q_l * a + &gate.q_r * b + &gate.q_o * c + &gate.q_m * a * b + &gate.q_c + &public[row];
+            if !(value % p).is_zero() {
+                return Err(format!("Gate {} is not satisfied", row));
+            }
+        }
+        let values: Vec<BigUint> = [&assignment.a, &assignment.b, &assignment.c].iter().flat_map(|column| column.iter().map(|x| x % p)).collect();
+        match self.copy_permutation().iter().enumerate().find(|&(cell, &image)| values[cell] != values[image]) {
+            Some((cell, image)) => Err(format!("Copy constraint between cells {} and {} is violated", cell, image)),
+            None => Ok(()),
+        }
+    }
+
+    // Arithmetizes a boolean circuit, with one row per input constraining it to 0 or 1, one row per gate,
+    // and a last row constraining the output to 1. Gate rows read their inputs in a and b and write their
+    // output in c, and copy constraints connect every read to the cell that computed the wire. Selectors
+    // of -1 and -2 are written as p - 1 and p - 2, so the modulus p must be at least 2.
+    pub fn from_boolean(circuit: &Circuit, modulus: &BigUint) -> Result<PlonkishCircuit, String> {
+        check_modulus(modulus)?;
+        let one = BigUint::one();
+        let minus = |x: u32| modulus - BigUint::from(x);
+        let n = circuit.num_inputs;
+        // Cell holding the value of each wire
+        let wire = |w: usize| if w < n { Cell { column: Column::A, row: w } } else { Cell { column: Column::C, row: w } };
+        let mut gates = Vec::new();
+        let mut copies = Vec::new();
+        // a b - a = 0, with b a copy of a
+        gates.extend((0..n).map(|_| PlonkGate { q_l: minus(1), q_m: one.clone(), ..PlonkGate::default() }));
+        copies.extend((0..n).map(|row| (Cell { column: Column::B, row }, Cell { column: Column::A, row })));
+        for (k, gate) in circuit.gates.iter().enumerate() {
+            let row = n + k;
+            let (selectors, reads) = match *gate {
+                // a b - c = 0
+                Gate::And(x, y) => (PlonkGate { q_o: minus(1), q_m: one.clone(), ..PlonkGate::default() }, vec![x, y]),
+                // a + b - 2 a b - c = 0
+                Gate::Xor(x, y) => (PlonkGate { q_l: one.clone(), q_r: one.clone(), q_o: minus(1), q_m: minus(2), ..PlonkGate::default() }, vec![x, y]),
+                // 1 - a - c = 0
+                Gate::Not(x) => (PlonkGate { q_l: minus(1), q_o: minus(1), q_c: one.clone(), ..PlonkGate::default() }, vec![x]),
+            };
+            gates.push(selectors);
+            copies.extend(reads.iter().zip([Column::A, Column::B]).map(|(&x, column)| (Cell { column, row }, wire(x))));
+        }
+        // a - 1 = 0
+        gates.push(PlonkGate { q_l: one, q_c: minus(1), ..PlonkGate::default() });
+        copies.push((Cell { column: Column::A, row: n + circuit.gates.len() }, wire(circuit.output)));
+        Ok(PlonkishCircuit { modulus: modulus.clone(), gates, copies, public_rows: Vec::new() })
+    }
+}
+
+// Checks that the modulus is at least 2, which every value reduces modulo and -1 and -2 are written with
+fn check_modulus(modulus: &BigUint) -> Result<(), String> {
+    if *modulus < BigUint::from(2u32) {
+        return Err(format!("Modulus {} is less than 2", modulus));
+    }
+    Ok(())
+}
+
+impl PlonkishAssignment {
+    // Assignment of a circuit from PlonkishCircuit::from_boolean on the given inputs, which satisfies it
+    // exactly when the circuit outputs 1
+    pub fn from_boolean(circuit: &Circuit, inputs: &[bool]) -> PlonkishAssignment {
+        let wires: Vec<BigUint> = circuit.evaluate_wires(inputs).into_iter().map(|x| BigUint::from(x as u8)).collect();
+        let mut assignment = PlonkishAssignment { a: wires[..circuit.num_inputs].to_vec(), ..PlonkishAssignment::default() };
+        assignment.b = assignment.a.clone();
+        assignment.c = vec![BigUint::zero(); circuit.num_inputs];
+        for (k, gate) in circuit.gates.iter().enumerate() {
+            let (x, y) = match *gate {
+                Gate::And(x, y) | Gate::Xor(x, y) => (wires[x].clone(), wires[y].clone()),
+                Gate::Not(x) => (wires[x].clone(), BigUint::zero()),
+            };
+            assignment.a.push(x);
+            assignment.b.push(y);
+            assignment.c.push(wires[circuit.num_inputs + k].clone());
+        }
+        assignment.a.push(wires[circuit.output].clone());
+        assignment.b.push(BigUint::zero());
+        assignment.c.push(BigUint::zero());
+        assignment
+    }
+}
+
+#[cfg(test)]
+fn test_modulus() -> BigUint {
+    BigUint::from(2_147_483_647u32)
+}
+
+#[test]
+fn test_plonkish_boolean_circuit() {
+    let circuit = Circuit::parse(EXAMPLE_CIRCUIT).unwrap();
+    let plonkish = PlonkishCircuit::from_boolean(&circuit, &test_modulus()).unwrap();
+    assert_eq!(plonkish.rows(), 3 + 3 + 1);
+    for x in 0..8 {
+        let inputs: Vec<bool> = (0..3).map(|i| (x >> i) & 1 == 1).collect();
+        let assignment = PlonkishAssignment::from_boolean(&circuit, &inputs);
+        assert_eq!(plonkish.check(&assignment, &[]).is_ok(), circuit.evaluate(&inputs));
+    }
+}
+
+#[test]
+fn test_plonkish_violations() {
+    let circuit = Circuit::parse(EXAMPLE_CIRCUIT).unwrap();
+    let plonkish = PlonkishCircuit::from_boolean(&circuit, &test_modulus()).unwrap();
+    let assignment = PlonkishAssignment::from_boolean(&circuit, &[true, false, true]);
+    // An input that isn't a bit breaks its booleanity row
+    let mut tampered = assignment.clone();
+    tampered.a[1] = BigUint::from(2u32);
+    tampered.b[1] = BigUint::from(2u32);
+    assert_eq!(plonkish.check(&tampered, &[]), Err("Gate 1 is not satisfied".to_string()));
+    // Reading a different value than the wire holds breaks a copy constraint, but no gate
+    let mut tampered = assignment.clone();
+    tampered.a[4] = BigUint::zero();
+    tampered.c[4] = BigUint::zero();
+    assert!(plonkish.check(&tampered, &[]).unwrap_err().contains("Copy constraint"));
+    assert!(plonkish.check(&PlonkishAssignment::default(), &[]).is_err());
+    assert!(plonkish.check(&assignment, &[BigUint::one()]).is_err());
+    // Moduli below 2 are refused rather than underflowing or dividing by zero
+    for modulus in 0..2u32 {
+        let error = format!("Modulus {} is less than 2", modulus);
+        assert_eq!(PlonkishCircuit::from_boolean(&circuit, &BigUint::from(modulus)), Err(error.clone()));
+        assert_eq!(PlonkishCircuit { modulus: BigUint::from(modulus), ..plonkish.clone() }.check(&assignment, &[]), Err(error));
+    }
+}
+
+#[test]
+fn test_plonkish_public_inputs() {
+    // x^2 + x = out, with out public: the first row computes x * x, the second x^2 + x, and the third
+    // checks it against the public input
+    let p = test_modulus();
+    let minus_one = &p - 1u32;
+    let gates = vec![
+        PlonkGate { q_m: BigUint::one(), q_o: minus_one.clone(), ..PlonkGate::default() },
+        PlonkGate { q_l: BigUint::one(), q_r: BigUint::one(), q_o: minus_one.clone(), ..PlonkGate::default() },
+        PlonkGate { q_l: minus_one, ..PlonkGate::default() },
+    ];
+    let copies = vec![
+        (Cell { column: Column::A, row: 0 }, Cell { column: Column::B, row: 0 }),
+        (Cell { column: Column::C, row: 0 }, Cell { column: Column::A, row: 1 }),
+        (Cell { column: Column::A, row: 0 }, Cell { column: Column::B, row: 1 }),
+        (Cell { column: Column::C, row: 1 }, Cell { column: Column::A, row: 2 }),
+    ];
+    let circuit = PlonkishCircuit { modulus: p, gates, copies, public_rows: vec![2] };
+    let values = |a: [u32; 3], b: [u32; 3], c: [u32; 3]| PlonkishAssignment {
+        a: a.iter().map(|&x| BigUint::from(x)).collect(),
+        b: b.iter().map(|&x| BigUint::from(x)).collect(),
+        c: c.iter().map(|&x| BigUint::from(x)).collect(),
+    };
+    let assignment = values([3, 9, 12], [3, 3, 0], [9, 12, 0]);
+    assert_eq!(circuit.check(&assignment, &[BigUint::from(12u32)]), Ok(()));
+    assert!(circuit.check(&assignment, &[BigUint::from(13u32)]).is_err());
+    // The cells of x, x^2, and x^2 + x each form one cycle of the copy permutation
+    let permutation = circuit.copy_permutation();
+    assert_eq!((permutation[0], permutation[3], permutation[4]), (3, 4, 0));
+}
@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::fmt;
+use rand::{CryptoRng, RngCore};
+use rand::rngs::ThreadRng;
+use crate::config;
+#[cfg(test)]
+use rand::rngs::mock::StepRng;
+
+// ************ Randomness health implementation ************
+
+// Zero knowledge and soundness both assume the parties' randomness is unpredictable, and nothing in a
+// transcript shows when it isn't. A seeded toy RNG left in from a classroom demo, or a hardware source
+// that has failed stuck, produces proofs that verify while leaking the witness or letting a prover guess
+// challenges. HealthCheckedRng wraps an RNG with the continuous tests hardware generators run on their own
+// output, adapted from NIST SP 800-90B and FIPS 140-2:
+//
+// - repetition: no 64-bit word of output may equal the one before it, which a working generator does
+//   with probability 2^-64
+// - monobit: every window of 20000 output bits must have between 9500 and 10500 ones, over seven standard
+//   deviations from the mean, so a working generator fails about once in 10^12 windows
+//
+// The tests run on the inner RNG's output in blocks as it is drawn, whatever the sizes of the requests. A
+// failure is sticky: every later request fails too, with try_fill_bytes returning the error and the
+// infallible methods panicking, since carrying on with bad randomness is never safe.
+//
+// The type system only tells cryptographic RNGs apart at compile time, so HealthCheckedRng::new requires
+// CryptoRng, while HealthCheckedRng::insecure wraps anything, and refuses to after
+// config::set_require_crypto_rng(true), which production deployments such as `zklib serve` call.
+//
+// Provers, verifiers and everything else in the library draw their randomness from checked_thread_rng,
+// a handle like rand::thread_rng. Once cryptographic RNGs are required, every draw goes through one
+// HealthCheckedRng per thread, so the tests see the thread's whole output and a failure stops every later
+// proof on the thread. Otherwise draws go straight to the thread's RNG, without the cost of the tests.
+
+// Bytes drawn from the inner RNG at a time
+const BLOCK_BYTES: usize = 64;
+
+// Bits per monobit window, and the most the count of ones in a window may stray from half of them
+const MONOBIT_WINDOW_BITS: u32 = 20_000;
+const MONOBIT_MAX_DEVIATION: u32 = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthError {
+    // The same 64-bit word was output twice in a row
+    Repetition(u64),
+    // A window of output had this many ones
+    Monobit { ones: u32, bits: u32 },
+    // A non-cryptographic RNG was wrapped while one is required
+    NotCryptographic,
+}
+
+impl fmt::Display for HealthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthError::Repetition(word) => write!(f, "RNG repeated the word {:#018x}", word),
+            HealthError::Monobit { ones, bits } => write!(f, "RNG output {} ones in {} bits", ones, bits),
+            HealthError::NotCryptographic => write!(f, "A cryptographic RNG is required"),
+        }
+    }
+}
+
+impl std::error::Error for HealthError {}
+
+pub struct HealthCheckedRng<R> {
+    pub inner: R,
+    // Output drawn from the inner RNG and checked, but not yet handed out
+    block: [u8; BLOCK_BYTES],
+    used: usize,
+    last_word: Option<u64>,
+    // Ones and bits seen so far in the current monobit window
+    ones: u32,
+    bits: u32,
+    failure: Option<HealthError>,
+}
+
+impl<R: RngCore + CryptoRng> HealthCheckedRng<R> {
+    pub fn new(inner: R) -> HealthCheckedRng<R> {
+        HealthCheckedRng::wrap(inner)
+    }
+}
+
+impl<R: RngCore> HealthCheckedRng<R> {
+    // Wraps an RNG that may not be cryptographic, e.g. a seeded one for reproducible experiments, unless
+    // the library is configured to require cryptographic RNGs
+    pub fn insecure(inner: R) -> Result<HealthCheckedRng<R>, HealthError> {
+        if config::require_crypto_rng() {
+            return Err(HealthError::NotCryptographic);
+        }
+        Ok(HealthCheckedRng::wrap(inner))
+    }
+
+    fn wrap(inner: R) -> HealthCheckedRng<R> {
+        HealthCheckedRng { inner, block: [0; BLOCK_BYTES], used: BLOCK_BYTES, last_word: None, ones: 0, bits: 0, failure: None }
+    }
+
+    // The test the RNG failed, if any
+    pub fn failure(&self) -> Option<&HealthError> {
+        self.failure.as_ref()
+    }
+
+    // Draws and checks the next block of output
+    fn refill(&mut self) -> Result<(), HealthError> {
+        self.inner.fill_bytes(&mut self.block);
+        self.used = 0;
+        for chunk in self.block.chunks(8) {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            if self.last_word == Some(word) {
+                return Err(HealthError::Repetition(word));
+            }
+            self.last_word = Some(word);
+            self.ones += word.count_ones();
+            self.bits += 64;
+            if self.bits >= MONOBIT_WINDOW_BITS {
+                let (ones, bits) = (self.ones, self.bits);
+                (self.ones, self.bits) = (0, 0);
+                if ones.abs_diff(bits / 2) > MONOBIT_MAX_DEVIATION * bits / MONOBIT_WINDOW_BITS {
+                    return Err(HealthError::Monobit { ones, bits });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: RngCore> RngCore for HealthCheckedRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = self.try_fill_bytes(dest) {
+            panic!("Randomness failed its health checks: {}", e);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if let Some(failure) = &self.failure {
+                return Err(rand::Error::new(failure.clone()));
+            }
+            if self.used == BLOCK_BYTES {
+                if let Err(e) = self.refill() {
+                    self.failure = Some(e);
+                    continue;
+                }
+            }
+            let count = (dest.len() - filled).min(BLOCK_BYTES - self.used);
+            dest[filled..filled + count].copy_from_slice(&self.block[self.used..self.used + count]);
+            self.used += count;
+            filled += count;
+        }
+        Ok(())
+    }
+}
+
+impl<R: RngCore + CryptoRng> CryptoRng for HealthCheckedRng<R> {}
+
+thread_local! {
+    static CHECKED_THREAD_RNG: RefCell<HealthCheckedRng<ThreadRng>> = RefCell::new(HealthCheckedRng::new(rand::thread_rng()));
+}
+
+// Handle to the thread's cryptographic RNG, health checked while cryptographic RNGs are required
+#[derive(Clone, Copy, Debug)]
+pub struct CheckedThreadRng;
+
+pub fn checked_thread_rng() -> CheckedThreadRng {
+    CheckedThreadRng
+}
+
+impl CheckedThreadRng {
+    // The test the thread's checked RNG failed, if any
+    pub fn failure(&self) -> Option<HealthError> {
+        CHECKED_THREAD_RNG.with(|rng| rng.borrow().failure().cloned())
+    }
+}
+
+impl RngCore for CheckedThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = self.try_fill_bytes(dest) {
+            panic!("Randomness failed its health checks: {}", e);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        if config::require_crypto_rng() {
+            CHECKED_THREAD_RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+        } else {
+            rand::thread_rng().try_fill_bytes(dest)
+        }
+    }
+}
+
+impl CryptoRng for CheckedThreadRng {}
+
+#[test]
+fn test_health_checks() {
+    // A working RNG passes, whatever the sizes of the requests
+    let mut rng = HealthCheckedRng::new(rand::thread_rng());
+    (0..2000).for_each(|i| { rng.fill_bytes(&mut vec![0u8; i % 37]); });
+    assert_eq!(rng.failure(), None);
+
+    // A stuck RNG fails the repetition test, and stays failed
+    let mut stuck = HealthCheckedRng::wrap(StepRng::new(7, 0));
+    assert!(stuck.try_fill_bytes(&mut [0u8; 16]).is_err());
+    assert_eq!(stuck.failure(), Some(&HealthError::Repetition(7)));
+    assert!(stuck.try_fill_bytes(&mut [0u8; 1]).is_err());
+    // A counter changes every word but has nearly all of its high bits set or clear
+    let mut counter = HealthCheckedRng::wrap(StepRng::new(u64::MAX / 2, 1));
+    assert!(counter.try_fill_bytes(&mut [0u8; 10_000]).is_err());
+    assert!(matches!(counter.failure(), Some(HealthError::Monobit { .. })));
+}
+
+#[test]
+fn test_require_crypto_rng() {
+    // Only this test changes the setting, and it restores the default before checking anything else
+    config::set_require_crypto_rng(true);
+    let refused = HealthCheckedRng::insecure(StepRng::new(0, 1)).err();
+    // Protocols' draws on this thread now go through the thread's checked RNG
+    let mut rng = checked_thread_rng();
+    (0..200).for_each(|i| { rng.fill_bytes(&mut vec![0u8; i % 37]); });
+    let checked = CHECKED_THREAD_RNG.with(|rng| rng.borrow().last_word.is_some());
+    config::set_require_crypto_rng(false);
+    assert_eq!(refused, Some(HealthError::NotCryptographic));
+    assert!(checked && rng.failure().is_none());
+    assert!(HealthCheckedRng::insecure(StepRng::new(0, 1)).is_ok());
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::audit::to_hex;
+use crate::config::{self, LogLevel};
+use crate::graph::GraphPair;
+#[cfg(test)]
+use crate::graph::Graph;
+
+// ************ Statement registry implementation ************
+
+// Provers that keep proving the same statement, e.g. a well-known GI instance, would otherwise upload its
+// graphs for every session. A registry holds instances under a name and the hex digest of the instance,
+// so a session can refer to either instead. A name always refers to the same instance once registered,
+// so clients that look a statement up by name know what they are proving.
+//
+// If the registry has a directory, each statement is saved to <dir>/<digest>.json, written to a temporary
+// path and renamed like sessions. Statements registered in the directory by another process, e.g.
+// `zklib registry register`, are found by `refresh`. A server refreshes when a client asks for a statement
+// it doesn't know, so refresh_stale reads the directory at most once per refresh_interval, however often
+// clients ask for names that don't exist. A file that is malformed, doesn't match its digest or reuses a
+// name is skipped with a warning rather than failing the refresh, so one bad file doesn't hide the rest.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredStatement {
+    pub name: String,
+    // Hex digest of the instance, see GraphPair::digest
+    pub digest: String,
+    pub instance: GraphPair,
+    // Unix time the statement was registered at
+    pub registered_at: u64,
+}
+
+// How long refresh_stale waits between reads of the directory by default
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct StatementRegistry {
+    pub dir: Option<PathBuf>,
+    pub refresh_interval: Duration,
+    // Statements by digest
+    statements: BTreeMap<String, RegisteredStatement>,
+    // Digests by name
+    names: BTreeMap<String, String>,
+    // When the directory was last read
+    refreshed: Option<Instant>,
+    // Files that were skipped, so that each is only warned about once
+    skipped: BTreeSet<PathBuf>,
+}
+
+// Names are what clients type, so they are short and can't be mistaken for digests
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err(format!("Statement name {:?} must have between 1 and 64 characters", name));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        return Err(format!("Statement name {:?} may only contain letters, digits, '-', '_' and '.'", name));
+    }
+    if name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Statement name {:?} looks like a digest", name));
+    }
+    Ok(())
+}
+
+impl StatementRegistry {
+    pub fn in_memory() -> StatementRegistry {
+        StatementRegistry::new(None)
+    }
+
+    fn new(dir: Option<PathBuf>) -> StatementRegistry {
+        StatementRegistry { dir, refresh_interval: REFRESH_INTERVAL, statements: BTreeMap::new(), names: BTreeMap::new(), refreshed: None, skipped: BTreeSet::new() }
+    }
+
+    // Loads the statements saved in the directory, creating it if needed
+    pub fn open(dir: &Path) -> Result<StatementRegistry, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let mut registry = StatementRegistry::new(Some(dir.to_path_buf()));
+        registry.refresh(usize::MAX)?;
+        Ok(registry)
+    }
+
+    // Loads statements saved in the directory since it was last read, until the registry holds limit
+    // statements, returning how many were loaded
+    pub fn refresh(&mut self, limit: usize) -> Result<usize, String> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => return Ok(0),
+        };
+        self.refreshed = Some(Instant::now());
+        let mut added = 0;
+        let entries = fs::read_dir(&dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let known = path.file_stem().and_then(|x| x.to_str()).is_some_and(|digest| self.statements.contains_key(digest));
+            if path.extension().is_none_or(|x| x != "json") || known || self.skipped.contains(&path) {
+                continue;
+            }
+            if self.len() >= limit {
+                config::log(LogLevel::Warn, &format!("Registry holds {} statements, the limit, so {} and any others are not loaded", limit, path.display()));
+                break;
+            }
+            match self.load(&path) {
+                Ok(()) => added += 1,
+                Err(e) => {
+                    config::log(LogLevel::Warn, &format!("Skipping {}: {}", path.display(), e));
+                    self.skipped.insert(path);
+                },
+            }
+        }
+        Ok(added)
+    }
+
+    // Refreshes unless the directory was read less than refresh_interval ago
+    pub fn refresh_stale(&mut self, limit: usize) -> Result<usize, String> {
+        match self.refreshed {
+            Some(refreshed) if refreshed.elapsed() < self.refresh_interval => Ok(0),
+            _ => self.refresh(limit),
+        }
+    }
+
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("failed to read it: {}", e))?;
+        let statement: RegisteredStatement = serde_json::from_str(&contents).map_err(|e| format!("malformed statement: {}", e))?;
+        if to_hex(&statement.instance.digest()) != statement.digest {
+            return Err("statement does not match its digest".to_string());
+        }
+        self.insert(statement)
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    // Looks a statement up by name or by digest
+    pub fn get(&self, id: &str) -> Option<&RegisteredStatement> {
+        let digest = self.names.get(id).map_or(id, String::as_str);
+        self.statements.get(digest)
+    }
+
+    // Every statement, ordered by name
+    pub fn list(&self) -> Vec<&RegisteredStatement> {
+        self.names.values().map(|digest| &self.statements[digest]).collect()
+    }
+
+    // Registers the instance under the name, or returns the existing statement if the instance is already
+    // registered under it. A name taken by another instance, or an instance registered under another
+    // name, is refused.
+    pub fn register(&mut self, name: &str, instance: GraphPair) -> Result<&RegisteredStatement, String> {
+        check_name(name)?;
+        let digest = to_hex(&instance.digest());
+        let registered_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        let statement = RegisteredStatement { name: name.to_string(), digest: digest.clone(), instance, registered_at };
+        match self.statements.get(&digest) {
+            Some(existing) if existing.name == name => return Ok(&self.statements[&digest]),
+            Some(existing) => return Err(format!("Instance is already registered as {}", existing.name)),
+            None => (),
+        }
+        if self.names.contains_key(name) {
+            return Err(format!("Statement name {} is taken by another instance", name));
+        }
+        self.save(&statement)?;
+        self.insert(statement)?;
+        Ok(&self.statements[&digest])
+    }
+
+    fn insert(&mut self, statement: RegisteredStatement) -> Result<(), String> {
+        check_name(&statement.name)?;
+        if self.names.contains_key(&statement.name) {
+            return Err(format!("Statement name {} is registered twice", statement.name));
+        }
+        self.names.insert(statement.name.clone(), statement.digest.clone());
+        self.statements.insert(statement.digest.clone(), statement);
+        Ok(())
+    }
+
+    fn save(&self, statement: &RegisteredStatement) -> Result<(), String> {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.json", statement.digest));
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, serde_json::to_string(statement).unwrap())
+                .and_then(|_| fs::rename(&temporary, &path))
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_registry() {
+    let dir = std::env::temp_dir().join(format!("zklib-registry-{}", rand::random::<u64>()));
+    let instance = GraphPair { g0: Graph::new(3, vec![(0, 1), (1, 2)]), g1: Graph::new(3, vec![(2, 0), (0, 1)]) };
+    let other = GraphPair { g0: Graph::new(3, vec![(0, 1)]), g1: Graph::new(3, vec![(1, 2)]) };
+    let mut registry = StatementRegistry::open(&dir).unwrap();
+    let digest = registry.register("path-3", instance.clone()).unwrap().digest.clone();
+    assert_eq!(digest, to_hex(&instance.digest()));
+    assert_eq!(registry.get("path-3").unwrap().instance, instance);
+    assert_eq!(registry.get(&digest).unwrap().name, "path-3");
+    assert!(registry.get("unknown").is_none());
+    // Registering again is harmless, but a name keeps its instance and an instance keeps its name
+    assert!(registry.register("path-3", instance.clone()).is_ok());
+    assert!(registry.register("path-3", other.clone()).is_err());
+    assert!(registry.register("another-name", instance.clone()).is_err());
+    assert!(registry.register("has spaces", other.clone()).is_err());
+    assert!(registry.register(&digest, other.clone()).is_err());
+
+    // A second registry over the directory sees the first's statements, and the first finds the second's
+    let mut reopened = StatementRegistry::open(&dir).unwrap();
+    assert_eq!(reopened.get("path-3").unwrap().digest, digest);
+    reopened.register("edge", other).unwrap();
+    assert_eq!(registry.refresh(usize::MAX), Ok(1));
+    assert_eq!(registry.list().iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["edge", "path-3"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_registry_refresh() {
+    let dir = std::env::temp_dir().join(format!("zklib-registry-{}", rand::random::<u64>()));
+    let mut registry = StatementRegistry::open(&dir).unwrap();
+    registry.refresh_interval = Duration::from_secs(3600);
+    let mut other = StatementRegistry::open(&dir).unwrap();
+    for n in 2..5 {
+        other.register(&format!("edge-{}", n), GraphPair { g0: Graph::new(n, vec![(0, 1)]), g1: Graph::new(n, vec![(1, 0)]) }).unwrap();
+    }
+    // Bad files are skipped, and the directory is read at most once per interval
+    fs::write(dir.join("malformed.json"), "{").unwrap();
+    let mut forged: RegisteredStatement = serde_json::from_str(&fs::read_to_string(dir.join(format!("{}.json", other.get("edge-2").unwrap().digest))).unwrap()).unwrap();
+    forged.name = "forged".to_string();
+    forged.instance.g0 = Graph::new(2, Vec::new());
+    fs::write(dir.join("forged.json"), serde_json::to_string(&forged).unwrap()).unwrap();
+    assert_eq!(registry.refresh(usize::MAX), Ok(3));
+    assert_eq!(registry.len(), 3);
+    other.register("edge-5", GraphPair { g0: Graph::new(5, vec![(0, 1)]), g1: Graph::new(5, vec![(1, 0)]) }).unwrap();
+    assert_eq!(registry.refresh_stale(usize::MAX), Ok(0));
+    // The limit caps the statements loaded in all
+    registry.refresh_interval = Duration::ZERO;
+    assert_eq!(registry.refresh_stale(3), Ok(0));
+    assert_eq!(registry.refresh_stale(usize::MAX), Ok(1));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use crate::{Prover, ValidationError};
+#[cfg(test)]
+use crate::Verifier;
+use crate::beacon::PublicCoinVerifier;
+use crate::challenge::Challenge;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMalicious, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair, ParallelGIProver, ParallelGIVerifier};
+
+// ************ Adaptive repetition implementation ************
+
+// Sequential repetition drives a protocol's soundness error down geometrically, but a verifier running a
+// fixed number of repetitions has to pick that number for the protocol in advance, and keeps going after a
+// repetition it already rejected. A RepetitionPolicy instead sets the soundness error the verifier wants,
+// and run_repeated tracks the error accumulated so far, stopping to accept as soon as it reaches the
+// target and rejecting at the first repetition the verifier rejects, since no later repetition can make
+// up for it. A cheating prover is therefore usually turned away after a repetition or two, and an honest
+// one runs exactly as many repetitions as the target needs.
+//
+// The error of a repetition is counted from the challenges the verifier actually sent, so the same policy
+// suits a GI verifier sending one bit, a parallel GI verifier sending k, or a Schnorr verifier sending an
+// element of Z_q. A prover that can't answer every challenge has to guess at least one of them in advance,
+// so a repetition's error is at most the sum of its challenges' guessing probabilities, and the errors of
+// accepted repetitions multiply. The count assumes uniform challenges, so a verifier with a biased source
+// of randomness gets less soundness than it counts. A repetition whose verifier sends no challenges counts
+// with an error of 1, so such a protocol never reaches the target, and is rejected once the policy's
+// maximum number of repetitions runs out.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RepetitionPolicy {
+    // Soundness error at which the verifier stops and accepts
+    pub target_error: f64,
+    // Most repetitions to run before rejecting for not reaching the target
+    pub max_repetitions: u32,
+}
+
+impl RepetitionPolicy {
+    // Aims for a soundness error of 2^-bits
+    pub fn bits(bits: u32, max_repetitions: u32) -> RepetitionPolicy {
+        RepetitionPolicy { target_error: 0.5f64.powi(bits as i32), max_repetitions }
+    }
+}
+
+// Whether the verifier accepted, after how many repetitions, including a rejected one, and the soundness
+// error accumulated by the accepted ones
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RepeatedRun {
+    pub accept: bool,
+    pub repetitions: u32,
+    pub soundness_error: f64,
+}
+
+impl fmt::Display for RepeatedRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {} repetitions with soundness error {:e}", if self.accept { "Accepted" } else { "Rejected" }, self.repetitions, self.soundness_error)
+    }
+}
+
+// Plays repetitions between fresh provers and verifiers from next until the policy accepts or a
+// repetition is rejected, validating every message like try_run_interactive_proof
+pub fn run_repeated<P, V, F>(policy: &RepetitionPolicy, mut next: F) -> Result<RepeatedRun, ValidationError>
+where P: Prover, V: PublicCoinVerifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>, F: FnMut() -> (P, V) {
+    let mut run = RepeatedRun { accept: false, repetitions: 0, soundness_error: 1.0 };
+    while run.soundness_error > policy.target_error {
+        if run.repetitions == policy.max_repetitions {
+            narrate!("Soundness error {:e} is still above the target after {} repetitions.", run.soundness_error, run.repetitions);
+            return Ok(run);
+        }
+        let (mut prover, mut verifier) = next();
+        run.repetitions += 1;
+        let (accept, error) = run_counted(&mut prover, &mut verifier)?;
+        if !accept {
+            narrate!("Repetition {} rejected.", run.repetitions);
+            return Ok(run);
+        }
+        run.soundness_error *= error;
+    }
+    narrate!("Accepted after {} repetitions with soundness error {:e}.", run.repetitions, run.soundness_error);
+    run.accept = true;
+    Ok(run)
+}
+
+// Runs one repetition, returning whether the verifier accepted and the soundness error of its challenges,
+// which is 1 if it sent none
+fn run_counted<P, V>(prover: &mut P, verifier: &mut V) -> Result<(bool, f64), ValidationError>
+where P: Prover, V: PublicCoinVerifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage> {
+    let mut verifier_msg = verifier.init();
+    let mut accept = false;
+    let mut error: Option<f64> = None;
+    let mut round = 1;
+
+    loop {
+        prover.validate(&verifier_msg).map_err(|reason| ValidationError::VerifierMessage(round, reason))?;
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        if done {
+            break;
+        }
+        verifier.validate(&prover_msg).map_err(|reason| ValidationError::ProverMessage(round, reason))?;
+        if let Some(space) = verifier.challenge_space() {
+            *error.get_or_insert(0.0) += V::Challenge::guessing_probability(&space);
+        }
+        let x = verifier.handle(&prover_msg);
+        verifier_msg = x.0;
+        accept = x.1;
+        round += 1;
+    }
+
+    Ok((accept, error.map_or(1.0, |error| error.min(1.0))))
+}
+
+// Accepts every prover without challenging it
+#[cfg(test)]
+struct CredulousVerifier;
+
+#[cfg(test)]
+impl Verifier for CredulousVerifier {
+    type ProverMessage = GIProverMessage;
+    type VerifierMessage = GIVerifierMessage;
+
+    fn init(&mut self) -> GIVerifierMessage {
+        GIVerifierMessage { b: false }
+    }
+
+    fn handle(&mut self, _msg: &GIProverMessage) -> (GIVerifierMessage, bool) {
+        (GIVerifierMessage { b: false }, true)
+    }
+}
+
+#[cfg(test)]
+impl PublicCoinVerifier for CredulousVerifier {
+    type Challenge = bool;
+
+    fn challenge_space(&self) -> Option<()> {
+        None
+    }
+
+    fn handle_with(&mut self, msg: &GIProverMessage, _challenge: Option<bool>) -> (GIVerifierMessage, bool) {
+        self.handle(msg)
+    }
+}
+
+#[test]
+fn test_adaptive_repetition() {
+    let isomorphic = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let nonisomorphic = GraphPair { g0: isomorphic.g0.clone(), g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]) };
+    let policy = RepetitionPolicy::bits(20, 100);
+    let verifier = |instance| GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+
+    // An honest prover runs exactly the 20 single-bit repetitions the target needs
+    let run = run_repeated(&policy, || (GIProver::new(&isomorphic), verifier(&isomorphic))).unwrap();
+    assert_eq!(run, RepeatedRun { accept: true, repetitions: 20, soundness_error: policy.target_error });
+    // With 8 bits per repetition, 3 repetitions overshoot the target
+    let run = run_repeated(&policy, || {
+        let prover = ParallelGIProver { r: 0, k: 8, random_perms: Vec::new(), openings: Vec::new(), instance: &isomorphic };
+        (prover, ParallelGIVerifier { r: 0, k: 8, challenge: Vec::new(), commitments: Vec::new(), instance: &isomorphic })
+    }).unwrap();
+    assert_eq!((run.accept, run.repetitions, run.soundness_error), (true, 3, 0.5f64.powi(24)));
+
+    // A cheating prover is rejected at its first wrong guess, after 2 repetitions on average
+    let cheating = || GIProverMalicious::new(&nonisomorphic);
+    let runs: Vec<RepeatedRun> = (0..200).map(|_| run_repeated(&policy, || (cheating(), verifier(&nonisomorphic))).unwrap()).collect();
+    assert!(runs.iter().all(|run| !run.accept && run.soundness_error == 0.5f64.powi(run.repetitions as i32 - 1)));
+    let mean = runs.iter().map(|run| run.repetitions as f64).sum::<f64>() / runs.len() as f64;
+    assert!(mean > 1.5 && mean < 2.5);
+    // A target out of reach within the maximum is a rejection
+    let run = run_repeated(&RepetitionPolicy::bits(20, 5), || (GIProver::new(&isomorphic), verifier(&isomorphic))).unwrap();
+    assert_eq!(run, RepeatedRun { accept: false, repetitions: 5, soundness_error: 0.5f64.powi(5) });
+    // A verifier that never challenges gains no soundness from any number of repetitions
+    let run = run_repeated(&policy, || (GIProver::new(&isomorphic), CredulousVerifier)).unwrap();
+    assert_eq!(run, RepeatedRun { accept: false, repetitions: 100, soundness_error: 1.0 });
+}
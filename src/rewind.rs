@@ -0,0 +1,318 @@
+use rand::Rng;
+use rayon::prelude::*;
+use crate::{Prover, Verifier};
+use crate::config::{self, ParallelismHint};
+use crate::graph::{GIProverMessage, GIVerifierMessage, Graph, GraphPair};
+use crate::permutation::Permutation;
+use crate::graph::GIVerifier;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use std::rc::Rc;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMalicious};
+#[cfg(test)]
+use crate::strategy::{Round, StrategicProver};
+
+// ************ Rewinding runner implementation ************
+
+// Simulators and extractors are defined by rewinding: a simulator runs a verifier until it asks a
+// question the simulator can answer, restarting it from a saved state otherwise, and an extractor asks a
+// prover two different questions from the same state. The runner below plays an interaction like
+// run_interactive_proof, but first saves a checkpoint of both parties before every prover move, so that
+// the interaction can be resumed from any round with the parties exactly as they were.
+
+// Both parties just before the prover handles the verifier's message on the given round
+#[derive(Clone)]
+pub struct Checkpoint<P: Prover, V> {
+    pub round: u32,
+    pub prover: P,
+    pub verifier: V,
+    pub verifier_msg: P::VerifierMessage,
+    // Message the prover sent on the previous round
+    pub prover_msg: Option<P::ProverMessage>,
+    // Whether the verifier accepted along with its last message
+    pub accept: bool,
+}
+
+impl<P, V> Checkpoint<P, V>
+where
+    P: Prover + Clone,
+    V: Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage> + Clone,
+    P::VerifierMessage: Clone,
+    P::ProverMessage: Clone,
+{
+    // Runs the rest of the interaction from this checkpoint on copies of the parties
+    pub fn resume(&self) -> bool {
+        let (mut prover, mut verifier) = (self.prover.clone(), self.verifier.clone());
+        let start = (self.verifier_msg.clone(), self.prover_msg.clone(), self.accept);
+        play(&mut prover, &mut verifier, start, self.round, &mut Vec::new())
+    }
+
+    // Asks a copy of the prover to answer a message of our choosing instead of the verifier's
+    pub fn respond(&self, msg: &P::VerifierMessage) -> Result<P::ProverMessage, String> {
+        let mut prover = self.prover.clone();
+        prover.validate(msg)?;
+        Ok(prover.handle(msg).0)
+    }
+}
+
+// Runs the interaction, returning the verdict along with a checkpoint taken before every prover move
+pub fn run_with_checkpoints<P, V>(mut prover: P, mut verifier: V) -> (bool, Vec<Checkpoint<P, V>>)
+where
+    P: Prover + Clone,
+    V: Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage> + Clone,
+    P::VerifierMessage: Clone,
+    P::ProverMessage: Clone,
+{
+    let mut checkpoints = Vec::new();
+    let verifier_msg = verifier.init();
+    let accept = play(&mut prover, &mut verifier, (verifier_msg, None, false), 1, &mut checkpoints);
+    (accept, checkpoints)
+}
+
+type Messages<P> = (<P as Prover>::VerifierMessage, Option<<P as Prover>::ProverMessage>, bool);
+
+// Plays the interaction from the verifier's message, the prover's previous message, and the verdict so far
+fn play<P, V>(prover: &mut P, verifier: &mut V, start: Messages<P>, mut round: u32, checkpoints: &mut Vec<Checkpoint<P, V>>) -> bool
+where
+    P: Prover + Clone,
+    V: Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage> + Clone,
+    P::VerifierMessage: Clone,
+    P::ProverMessage: Clone,
+{
+    let (mut verifier_msg, mut prover_msg, mut accept) = start;
+    loop {
+        checkpoints.push(Checkpoint { round, prover: prover.clone(), verifier: verifier.clone(), verifier_msg: verifier_msg.clone(), prover_msg, accept });
+        if prover.validate(&verifier_msg).is_err() {
+            return false;
+        }
+        let (msg, done) = prover.handle(&verifier_msg);
+        if done {
+            return accept;
+        }
+        if verifier.validate(&msg).is_err() {
+            return false;
+        }
+        let x = verifier.handle(&msg);
+        prover_msg = Some(msg);
+        verifier_msg = x.0;
+        accept = x.1;
+        round += 1;
+    }
+}
+
+// ************ GI simulator and extractor implementation ************
+
+// The GI proof is zero knowledge because a simulator that doesn't know the isomorphism can produce
+// transcripts distributed exactly like real ones, for any verifier: it guesses the verifier's challenge
+// b', sends a random permutation H of G_b', and answers if the verifier's challenge b equals b', rewinding
+// the verifier otherwise. Each attempt succeeds with probability 1/2 since H hides b'.
+//
+// The proof is a proof of knowledge because an extractor that can rewind a prover convincing the verifier
+// with probability above 1/2 can recover an isomorphism: it asks the same committed H for isomorphisms
+// to both G0 and G1, and composes them.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GITranscript {
+    pub graph: Graph,
+    pub b: bool,
+    pub isomorphism: Permutation,
+    pub accepted: bool,
+}
+
+// Produces a transcript of the verifier interacting with an honest prover without knowing the
+// isomorphism, giving up after the given number of rewinds
+pub fn simulate_gi<V>(instance: &GraphPair, verifier: &V, max_attempts: usize) -> Option<GITranscript>
+where
+    V: Verifier<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+{
+    for _ in 0..max_attempts {
+        // Rewinding restores the verifier to its initial state
+        let mut verifier = verifier.clone();
+        verifier.init();
+        let guess = checked_thread_rng().gen_bool(0.5);
+        let sigma = Permutation::random(instance.g0.n, &mut checked_thread_rng());
+        let graph = if guess { &instance.g1 } else { &instance.g0 }.permute(&sigma);
+        let msg = GIProverMessage::Graph(graph.clone());
+        if verifier.validate(&msg).is_err() {
+            return None;
+        }
+        let b = verifier.handle(&msg).0.b;
+        if b != guess {
+            continue;
+        }
+        let isomorphism = sigma.invert();
+        let msg = GIProverMessage::Isomorphism(isomorphism.clone());
+        let accepted = verifier.validate(&msg).is_ok() && verifier.handle(&msg).1;
+        return Some(GITranscript { graph, b, isomorphism, accepted });
+    }
+    None
+}
+
+// Recovers an isomorphism from g0 to g1 by rewinding the prover after its first message and asking for
+// isomorphisms to both graphs
+pub fn extract_gi<P, V>(instance: &GraphPair, prover: P, verifier: V) -> Option<Permutation>
+where
+    P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+    V: Verifier<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+{
+    let (_, checkpoints) = run_with_checkpoints(prover, verifier);
+    extract_gi_from(instance, &checkpoints)
+}
+
+// Recovers an isomorphism from the checkpoints of an interaction that has already been run
+pub fn extract_gi_from<P, V>(instance: &GraphPair, checkpoints: &[Checkpoint<P, V>]) -> Option<Permutation>
+where
+    P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+    V: Verifier<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+{
+    // The prover has committed to H by the second round
+    let checkpoint = checkpoints.get(1)?;
+    let graph = match &checkpoint.prover_msg {
+        Some(GIProverMessage::Graph(graph)) => graph,
+        _ => return None,
+    };
+    let mut answers = [false, true].iter().map(|&b| match checkpoint.respond(&GIVerifierMessage { b }) {
+        Ok(GIProverMessage::Isomorphism(x)) if x.n() == graph.n && graph.permute(&x) == *if b { &instance.g1 } else { &instance.g0 } => Some(x),
+        _ => None,
+    });
+    let (to_g0, to_g1) = (answers.next()??, answers.next()??);
+    // The isomorphism from g0 to g1 goes back to H, then on to g1
+    Some(to_g0.invert().compose(&to_g1))
+}
+
+// ************ Knowledge error experiment implementation ************
+
+// A proof of knowledge has knowledge error k if whenever a prover convinces the verifier with
+// probability e > k, the extractor recovers a witness from it with probability about e - k. For GI,
+// k = 1/2: a prover that prepared for only one challenge convinces the verifier half the time, but
+// there's nothing to extract from it. The experiment below runs a prover many times against the honest
+// verifier, attempting extraction from every run, and reports the gap between how often the prover
+// convinced the verifier and how often a witness was extracted. On an instance that isn't isomorphic,
+// nothing can ever be extracted, and the acceptance rate is the empirical soundness error.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KnowledgeReport {
+    pub name: String,
+    pub trials: usize,
+    // Runs the verifier accepted
+    pub accepted: usize,
+    // Runs from which the extractor recovered an isomorphism
+    pub extracted: usize,
+}
+
+impl KnowledgeReport {
+    // Empirical soundness error when the instance isn't isomorphic
+    pub fn acceptance_rate(&self) -> f64 {
+        self.accepted as f64 / self.trials as f64
+    }
+
+    pub fn extraction_rate(&self) -> f64 {
+        self.extracted as f64 / self.trials as f64
+    }
+
+    pub fn knowledge_error(&self) -> f64 {
+        (self.acceptance_rate() - self.extraction_rate()).max(0.0)
+    }
+}
+
+// Measures how often provers made by make_prover convince the honest GI verifier, and how often the
+// extractor succeeds against them
+pub fn measure_gi_knowledge<P, F>(name: &str, instance: &GraphPair, trials: usize, make_prover: F) -> KnowledgeReport
+where
+    P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> + Clone,
+    F: Fn() -> P + Sync,
+{
+    let (accepted, extracted) = config::install(ParallelismHint::Default, || (0..trials).into_par_iter().map(|_| {
+        let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+        let (accept, checkpoints) = run_with_checkpoints(make_prover(), verifier);
+        let extracted = extract_gi_from(instance, &checkpoints).is_some_and(|x| instance.g0.permute(&x) == instance.g1);
+        (accept as usize, extracted as usize)
+    }).reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1)));
+    let report = KnowledgeReport { name: name.to_string(), trials, accepted, extracted };
+    narrate!("Prover {} was accepted {} out of {} times, and a witness was extracted {} times (knowledge error {:.3}).",
+        name, accepted, trials, extracted, report.knowledge_error());
+    report
+}
+
+#[cfg(test)]
+fn test_instance() -> GraphPair {
+    GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    }
+}
+
+#[test]
+fn test_checkpoints() {
+    let instance = test_instance();
+    let prover = GIProver::new(&instance);
+    let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let (accept, checkpoints) = run_with_checkpoints(prover, verifier);
+    assert!(accept);
+    assert_eq!(checkpoints.iter().map(|x| x.round).collect::<Vec<u32>>(), vec![1, 2, 3]);
+    // Resuming an honest interaction from any round is still accepted
+    assert!(checkpoints.iter().all(|x| x.resume()));
+}
+
+#[test]
+fn test_gi_simulator() {
+    let instance = test_instance();
+    let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let transcripts: Vec<GITranscript> = (0..200).filter_map(|_| simulate_gi(&instance, &verifier, 64)).collect();
+    assert_eq!(transcripts.len(), 200);
+    assert!(transcripts.iter().all(|x| x.accepted));
+    // Like the honest verifier's challenges, simulated challenges are uniformly random
+    let ones = transcripts.iter().filter(|x| x.b).count();
+    assert!(ones > 60 && ones < 140);
+}
+
+#[test]
+fn test_gi_extractor() {
+    let instance = test_instance();
+    let prover = GIProver::new(&instance);
+    let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let witness = extract_gi(&instance, prover, verifier).unwrap();
+    assert_eq!(instance.g0.permute(&witness), instance.g1);
+}
+
+#[test]
+fn test_gi_extractor_malicious_prover() {
+    // A prover that can only answer one challenge has no isomorphism to extract
+    let instance = test_instance();
+    let prover = GIProverMalicious::new(&instance);
+    let verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(extract_gi(&instance, prover, verifier), None);
+}
+
+#[test]
+fn test_knowledge_error() {
+    let instance = test_instance();
+    let honest = measure_gi_knowledge("honest", &instance, 200, || GIProver::new(&instance));
+    assert_eq!((honest.accepted, honest.extracted), (200, 200));
+    // A prover that only prepared for one challenge is accepted about half the time, with nothing to extract
+    let guessing = measure_gi_knowledge("guessing", &instance, 1000, || GIProverMalicious::new(&instance));
+    assert_eq!(guessing.extracted, 0);
+    assert!(guessing.knowledge_error() > 0.4 && guessing.knowledge_error() < 0.6);
+    // Provers built from strategies can be measured too, since strategies can be cloned along with them
+    let stubborn = measure_gi_knowledge("identity", &instance, 200, || {
+        let honest = GIProver::new(&instance);
+        StrategicProver::new(honest, Rc::new(|round: Round<GIVerifierMessage, GIProverMessage>| match round.honest {
+            GIProverMessage::Isomorphism(_) => GIProverMessage::Isomorphism(Permutation::identity(4)),
+            x => x,
+        }))
+    });
+    assert!(stubborn.extraction_rate() < 0.5);
+}
+
+#[test]
+fn test_soundness_error() {
+    // On nonisomorphic graphs, nothing is ever extracted and the acceptance rate is the soundness error
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)]),
+    };
+    let report = measure_gi_knowledge("guessing", &instance, 1000, || GIProverMalicious::new(&instance));
+    assert_eq!(report.extracted, 0);
+    assert!(report.acceptance_rate() > 0.4 && report.acceptance_rate() < 0.6);
+}
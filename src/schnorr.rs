@@ -0,0 +1,543 @@
+use num_bigint::BigUint;
+use crate::{Diagnosis, Prover, Verifier};
+use crate::beacon::PublicCoinVerifier;
+use crate::challenge::Challenge;
+use crate::hash::{Hasher, Sha256Hasher};
+use crate::modarith::mod_exp;
+use serde::{Serialize, Deserialize};
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::secret::Secret;
+use crate::statement::{Statement, Transparent, Witness};
+use crate::transcript::{ChallengeTranscript, TranscriptMessage, encode_biguints};
+#[cfg(test)]
+use num_traits::Zero;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Schnorr signatures ************
+
+// Signing key x with public key y = g^x
+pub struct SchnorrKeyPair {
+    pub params: SchnorrParameters,
+    pub x: Secret<BigUint>,
+    pub y: BigUint,
+}
+
+// Signature (R, s) on a message m, valid if g^s = R * y^H(R, m)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchnorrSignature {
+    #[serde(with = "serde_biguint")]
+    pub r: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub s: BigUint,
+}
+
+impl SchnorrKeyPair {
+    pub fn generate(params: &SchnorrParameters) -> SchnorrKeyPair {
+        let x = params.random_exponent();
+        let y = mod_exp(&params.g, &x, &params.p);
+        SchnorrKeyPair { params: params.clone(), x: Secret::new(x), y }
+    }
+
+    pub fn public_key(&self) -> SchnorrPublicKey {
+        SchnorrPublicKey { params: self.params.clone(), y: self.y.clone() }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> SchnorrSignature {
+        self.sign_with_hasher::<Sha256Hasher>(message)
+    }
+
+    pub fn sign_with_hasher<H: Hasher>(&self, message: &[u8]) -> SchnorrSignature {
+        self.sign_with_nonce::<H>(&self.params.random_exponent(), message)
+    }
+
+    fn sign_with_nonce<H: Hasher>(&self, k: &BigUint, message: &[u8]) -> SchnorrSignature {
+        let params = &self.params;
+        let r = mod_exp(&params.g, k, &params.p);
+        let e = signature_challenge::<H>(params, &r, message);
+        SchnorrSignature { s: (k + e * &*self.x) % &params.q, r }
+    }
+}
+
+impl SchnorrSignature {
+    // Checks the signature on the message against the public key y
+    pub fn verify(&self, params: &SchnorrParameters, y: &BigUint, message: &[u8]) -> bool {
+        self.verify_with_hasher::<Sha256Hasher>(params, y, message)
+    }
+
+    pub fn verify_with_hasher<H: Hasher>(&self, params: &SchnorrParameters, y: &BigUint, message: &[u8]) -> bool {
+        let e = signature_challenge::<H>(params, &self.r, message);
+        mod_exp(&params.g, &self.s, &params.p) == &self.r * mod_exp(y, &e, &params.p) % &params.p
+    }
+}
+
+// Derives the exponent e = H(R, m) mod q from a transcript of the nonce commitment and message
+fn signature_challenge<H: Hasher>(params: &SchnorrParameters, r: &BigUint, message: &[u8]) -> BigUint {
+    let mut transcript = ChallengeTranscript::<H>::with_hasher(b"schnorr signature");
+    transcript.append_biguint(b"nonce", r);
+    transcript.append_message(b"message", message);
+    transcript.challenge_scalar(b"challenge", &params.q)
+}
+
+// ************ Schnorr identification implementation ************
+
+// The prover convinces the verifier that it knows the secret key x for its public key y = g^x. The
+// prover commits to a random nonce with a = g^k, the verifier responds with a random challenge c, and the
+// prover replies with z = k + c x mod q. The verifier accepts if g^z = a y^c.
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SchnorrProverMessage {
+    // Commitment a = g^k to a random nonce
+    Commitment(#[serde(with = "serde_biguint")] BigUint),
+    // Response z = k + c * x mod q
+    Response(#[serde(with = "serde_biguint")] BigUint),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SchnorrVerifierMessage {
+    // Random challenge in Z_q
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct SchnorrProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random nonce used for the commitment
+    pub nonce: Secret<BigUint>,
+    pub key: &'a SchnorrKeyPair,
+}
+
+impl Prover for SchnorrProver<'_> {
+    type ProverMessage = SchnorrProverMessage;
+    type VerifierMessage = SchnorrVerifierMessage;
+
+    fn handle(&mut self, msg: &SchnorrVerifierMessage) -> (SchnorrProverMessage, bool) {
+        self.r += 1;
+        let params = &self.key.params;
+        match self.r {
+            // During the first round, the prover commits to a random nonce
+            1 => {
+                self.nonce = Secret::new(params.random_exponent());
+                (SchnorrProverMessage::Commitment(mod_exp(&params.g, &self.nonce, &params.p)), false)
+            },
+            // During the second round, the prover masks its secret key with the nonce according to the challenge
+            2 => (SchnorrProverMessage::Response((&*self.nonce + &msg.c * &*self.key.x) % &params.q), false),
+            // After sending its response, the prover sends a message to terminate the interaction
+            _ => (SchnorrProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &SchnorrVerifierMessage) -> Result<(), String> {
+        if self.r + 1 == 2 { msg.c.check(&self.key.params.q) } else { Ok(()) }
+    }
+}
+
+pub struct SchnorrVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub commitment: BigUint,
+    pub challenge: BigUint,
+    // Running transcript from which challenges are derived
+    pub transcript: ChallengeTranscript,
+    pub params: &'a SchnorrParameters,
+    // Prover's public key
+    pub y: &'a BigUint,
+}
+
+impl Verifier for SchnorrVerifier<'_> {
+    type ProverMessage = SchnorrProverMessage;
+    type VerifierMessage = SchnorrVerifierMessage;
+
+    fn init(&mut self) -> SchnorrVerifierMessage {
+        narrate!("Initializing Schnorr identification for public key {}.", self.y);
+        self.transcript.append_biguint(b"public key", self.y);
+        SchnorrVerifierMessage { c: BigUint::from(0u32) }
+    }
+
+    fn validate(&self, msg: &SchnorrProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, SchnorrProverMessage::Commitment(a)) => self.params.check_element(a),
+            (1, _) => Err("expected a commitment".to_string()),
+            (_, SchnorrProverMessage::Response(z)) => self.params.check_exponent(z),
+            _ => Err("expected a response".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &SchnorrProverMessage) -> Option<Diagnosis> {
+        if let SchnorrProverMessage::Response(z) = msg {
+            let params = self.params;
+            let lhs = mod_exp(&params.g, z, &params.p);
+            let rhs = &self.commitment * mod_exp(self.y, &self.challenge, &params.p) % &params.p;
+            Some(Diagnosis::new("g^z != a * y^c", Some(format!("g^z = {}, a * y^c = {}", lhs, rhs))))
+        } else {
+            None
+        }
+    }
+
+    fn handle(&mut self, msg: &SchnorrProverMessage) -> (SchnorrVerifierMessage, bool) {
+        self.handle_with(msg, None)
+    }
+}
+
+impl PublicCoinVerifier for SchnorrVerifier<'_> {
+    type Challenge = BigUint;
+
+    fn challenge_space(&self) -> Option<BigUint> {
+        if self.r == 0 { Some(self.params.q.clone()) } else { None }
+    }
+
+    fn handle_with(&mut self, msg: &SchnorrProverMessage, challenge: Option<BigUint>) -> (SchnorrVerifierMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match self.r {
+            1 => {
+                if let SchnorrProverMessage::Commitment(a) = msg {
+                    narrate!("Verifier received commitment {}.", a);
+                    self.commitment = a.clone();
+                    self.transcript.append_biguint(b"commitment", a);
+                    self.challenge = match challenge {
+                        Some(c) => {
+                            self.transcript.append_challenge(b"challenge", &c);
+                            c
+                        },
+                        None => self.transcript.verifier_challenge_scalar(b"challenge", &params.q),
+                    };
+                    (SchnorrVerifierMessage { c: self.challenge.clone() }, false)
+                } else {
+                    panic!("Prover did not send a valid commitment on round 1!")
+                }
+            },
+            _ => {
+                if let SchnorrProverMessage::Response(z) = msg {
+                    narrate!("Verifier received response {}.", z);
+                    self.transcript.append_biguint(b"response", z);
+                    // The verifier accepts if g^z = a * y^c
+                    let accept = mod_exp(&params.g, z, &params.p) == &self.commitment * mod_exp(self.y, &self.challenge, &params.p) % &params.p;
+                    (SchnorrVerifierMessage { c: self.challenge.clone() }, accept)
+                } else {
+                    panic!("Prover did not send a valid response on round 2!")
+                }
+            }
+        }
+    }
+}
+
+impl TranscriptMessage for SchnorrProverMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        match self {
+            SchnorrProverMessage::Commitment(a) => transcript.append_biguint(b"commitment", a),
+            SchnorrProverMessage::Response(z) => transcript.append_biguint(b"response", z),
+            SchnorrProverMessage::Done => transcript.append_message(b"done", &[]),
+        }
+    }
+}
+
+impl TranscriptMessage for SchnorrVerifierMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_challenge(b"challenge", &self.c);
+    }
+}
+
+// The statement that the prover knows the discrete log of y, with the secret key x as the witness
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchnorrPublicKey {
+    pub params: SchnorrParameters,
+    pub y: BigUint,
+}
+
+impl Witness for BigUint {}
+
+impl Statement for SchnorrPublicKey {
+    type Witness = BigUint;
+
+    fn protocol(&self) -> &'static str {
+        "schnorr identification"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let params = &self.params;
+        encode_biguints(&[&params.p, &params.q, &params.g, &self.y])
+    }
+
+    fn validate(&self, witness: &BigUint) -> Result<(), String> {
+        let params = &self.params;
+        params.check_exponent(witness)?;
+        if mod_exp(&params.g, witness, &params.p) != self.y {
+            return Err("Secret key does not match the public key".to_string());
+        }
+        Ok(())
+    }
+
+    fn run_proof(&self, witness: &BigUint) -> bool {
+        let key = SchnorrKeyPair { params: self.params.clone(), x: Secret::new(witness.clone()), y: self.y.clone() };
+        let mut prover = SchnorrProver { r: 0, nonce: Secret::default(), key: &key };
+        let mut verifier = SchnorrVerifier {
+            r: 0,
+            commitment: BigUint::from(0u32),
+            challenge: BigUint::from(0u32),
+            transcript: ChallengeTranscript::new(b"schnorr identification"),
+            params: &self.params,
+            y: &self.y,
+        };
+        crate::run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+impl Transparent for SchnorrPublicKey {}
+
+// ************ Blind Schnorr signature issuance implementation ************
+
+// The signer plays the role of the prover, answering the user's requests with its signing key, while
+// the user plays the role of the verifier and accepts once it has unblinded a valid signature.
+
+#[derive(Clone)]
+pub enum BlindSignerMessage {
+    // Commitment R' = g^k to a random nonce
+    Nonce(BigUint),
+    // Response s' = k + e' * x to the blinded challenge
+    Response(BigUint),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone)]
+pub enum BlindUserMessage {
+    // Request for the signer to start a signing session
+    Request,
+    // Blinded challenge e' = H(R, m) + beta
+    Challenge(BigUint),
+}
+
+pub struct BlindSigner<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random nonce k used for the commitment
+    pub nonce: Secret<BigUint>,
+    pub key: &'a SchnorrKeyPair,
+}
+
+impl Prover for BlindSigner<'_> {
+    type ProverMessage = BlindSignerMessage;
+    type VerifierMessage = BlindUserMessage;
+
+    fn handle(&mut self, msg: &BlindUserMessage) -> (BlindSignerMessage, bool) {
+        self.r += 1;
+        let params = &self.key.params;
+        match (self.r, msg) {
+            // During the first round, the signer commits to a random nonce
+            (1, BlindUserMessage::Request) => {
+                self.nonce = Secret::new(params.random_exponent());
+                (BlindSignerMessage::Nonce(mod_exp(&params.g, &self.nonce, &params.p)), false)
+            },
+            // During the second round, the signer answers the blinded challenge without learning the message
+            (2, BlindUserMessage::Challenge(e)) => {
+                narrate!("Signer received blinded challenge {}.", e);
+                (BlindSignerMessage::Response((&*self.nonce + e * &*self.key.x) % &params.q), false)
+            },
+            (1, _) | (2, _) => panic!("User did not send a valid message on round {}!", self.r),
+            // After responding, the signer sends a message to terminate the interaction
+            _ => (BlindSignerMessage::Done, true),
+        }
+    }
+}
+
+pub struct BlindSigningUser<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random blinding factors
+    pub alpha: BigUint,
+    pub beta: BigUint,
+    // Blinded nonce commitment R = R' * g^alpha * y^beta
+    pub blinded_nonce: BigUint,
+    // Resulting signature once the session is complete
+    pub signature: Option<SchnorrSignature>,
+    pub params: &'a SchnorrParameters,
+    // Signer's public key
+    pub y: &'a BigUint,
+    pub message: &'a [u8],
+}
+
+impl Verifier for BlindSigningUser<'_> {
+    type ProverMessage = BlindSignerMessage;
+    type VerifierMessage = BlindUserMessage;
+
+    fn init(&mut self) -> BlindUserMessage {
+        narrate!("Requesting blind signature from signer with public key {}.", self.y);
+        BlindUserMessage::Request
+    }
+
+    fn handle(&mut self, msg: &BlindSignerMessage) -> (BlindUserMessage, bool) {
+        self.r += 1;
+        let params = self.params;
+        match self.r {
+            1 => {
+                if let BlindSignerMessage::Nonce(signer_nonce) = msg {
+                    narrate!("User received nonce commitment {}.", signer_nonce);
+                    // The user blinds the signer's commitment and shifts the challenge so the signer cannot recognize either later
+                    self.alpha = params.random_exponent();
+                    self.beta = params.random_exponent();
+                    self.blinded_nonce = signer_nonce * mod_exp(&params.g, &self.alpha, &params.p) % &params.p
+                        * mod_exp(self.y, &self.beta, &params.p) % &params.p;
+                    let e = signature_challenge::<Sha256Hasher>(params, &self.blinded_nonce, self.message);
+                    (BlindUserMessage::Challenge((e + &self.beta) % &params.q), false)
+                } else {
+                    panic!("Signer did not send a valid nonce commitment on round 1!")
+                }
+            },
+            _ => {
+                if let BlindSignerMessage::Response(s) = msg {
+                    narrate!("User received response {}.", s);
+                    // Unblinding the response yields a signature on the user's message under the blinded nonce
+                    let signature = SchnorrSignature { r: self.blinded_nonce.clone(), s: (s + &self.alpha) % &params.q };
+                    let valid = signature.verify(params, self.y, self.message);
+                    self.signature = Some(signature);
+                    (BlindUserMessage::Request, valid)
+                } else {
+                    panic!("Signer did not send a valid response on round 2!")
+                }
+            }
+        }
+    }
+}
+
+// Runs a signing session, returning the user's verdict and signature along with the signer's view (R', e', s')
+#[cfg(test)]
+fn run_blind_signing(key: &SchnorrKeyPair, message: &[u8]) -> (bool, SchnorrSignature, (BigUint, BigUint, BigUint)) {
+    let params = &key.params;
+    let mut signer = BlindSigner { r: 0, nonce: Secret::default(), key };
+    let mut user = BlindSigningUser {
+        r: 0,
+        alpha: BigUint::zero(),
+        beta: BigUint::zero(),
+        blinded_nonce: BigUint::zero(),
+        signature: None,
+        params,
+        y: &key.y,
+        message,
+    };
+    let accept = run_interactive_proof(&mut signer, &mut user);
+    let blinded_challenge = (signature_challenge::<Sha256Hasher>(params, &user.blinded_nonce, message) + &user.beta) % &params.q;
+    let response = (&*signer.nonce + &blinded_challenge * &*key.x) % &params.q;
+    let view = (mod_exp(&params.g, &signer.nonce, &params.p), blinded_challenge, response);
+    (accept, user.signature.unwrap(), view)
+}
+
+#[test]
+fn test_schnorr_signature() {
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let signature = key.sign(b"message");
+    assert!(signature.verify(&key.params, &key.y, b"message"));
+    assert!(!signature.verify(&key.params, &key.y, b"other message"));
+}
+
+#[test]
+fn test_schnorr_signature_forking() {
+    // Rerunning a signer with the same nonce against a random oracle forked at the challenge query gives
+    // two signatures (R, s) and (R, s') for different challenges, from which the signing key is extracted
+    use crate::modarith::mod_inverse;
+    use crate::oracle::{RandomOracle, RoTable};
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let (params, k) = (&key.params, key.params.random_exponent());
+    let mut table = RoTable::new();
+    let signature = table.run(|| key.sign_with_nonce::<RandomOracle>(&k, b"message"));
+    // The challenge fits one block, derived by the signer's second to last query before it is absorbed
+    let mut forked = table.fork(table.log.len() - 2);
+    assert!(table.run(|| signature.verify_with_hasher::<RandomOracle>(params, &key.y, b"message")));
+    let e = |table: &mut RoTable, signature: &SchnorrSignature| table.run(|| signature_challenge::<RandomOracle>(params, &signature.r, b"message"));
+    let second = forked.run(|| key.sign_with_nonce::<RandomOracle>(&k, b"message"));
+    assert_eq!(second.r, signature.r);
+    let (e1, e2) = (e(&mut table, &signature), e(&mut forked, &second));
+    assert_ne!(e1, e2);
+    let x = (&signature.s + &params.q - &second.s) * mod_inverse(&((&e1 + &params.q - &e2) % &params.q), &params.q).unwrap() % &params.q;
+    assert_eq!(x, *key.x);
+    // Against the SHA-256 transcript, the signature is no longer valid
+    assert!(!signature.verify(params, &key.y, b"message"));
+}
+
+#[test]
+fn test_schnorr_identification() {
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let mut prover = SchnorrProver { r: 0, nonce: Secret::default(), key: &key };
+    let mut verifier = SchnorrVerifier {
+        r: 0,
+        commitment: BigUint::zero(),
+        challenge: BigUint::zero(),
+        transcript: ChallengeTranscript::new(b"schnorr identification"),
+        params: &key.params,
+        y: &key.y,
+    };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+}
+
+#[test]
+fn test_schnorr_identification_diagnosis() {
+    // A prover using another key is rejected on its response, with the failed verification equation
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let other = SchnorrKeyPair::generate(&key.params);
+    let mut prover = SchnorrProver { r: 0, nonce: Secret::default(), key: &other };
+    let mut verifier = SchnorrVerifier {
+        r: 0,
+        commitment: BigUint::zero(),
+        challenge: BigUint::zero(),
+        transcript: ChallengeTranscript::new(b"schnorr identification"),
+        params: &key.params,
+        y: &key.y,
+    };
+    match crate::diagnose_interactive_proof(&mut prover, &mut verifier) {
+        Err(crate::ProofFailure::Rejected(rejection)) => {
+            assert_eq!(rejection.round, 2);
+            assert_eq!(rejection.diagnosis.unwrap().check, "g^z != a * y^c");
+        },
+        _ => panic!("Proof with the wrong key was not rejected!"),
+    }
+}
+
+#[test]
+fn test_blind_signature_issuance() {
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let (accept, signature, _) = run_blind_signing(&key, b"blinded message");
+    assert!(accept);
+    assert!(signature.verify(&key.params, &key.y, b"blinded message"));
+}
+
+#[test]
+fn test_blind_signature_unlinkable() {
+    // Any signing session and any valid signature are linked by the blinding factors alpha = s - s'
+    // and beta = e' - H(R, m), so the signer's view gives no information about which session
+    // produced which signature.
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let params = &key.params;
+    let (_, signature0, view0) = run_blind_signing(&key, b"first");
+    let (_, signature1, view1) = run_blind_signing(&key, b"second");
+    let links = |(signer_nonce, blinded_challenge, response): &(BigUint, BigUint, BigUint), signature: &SchnorrSignature, message: &[u8]| {
+        let alpha = (&signature.s + &params.q - response) % &params.q;
+        let beta = (blinded_challenge + &params.q - signature_challenge::<Sha256Hasher>(params, &signature.r, message)) % &params.q;
+        signer_nonce * mod_exp(&params.g, &alpha, &params.p) % &params.p * mod_exp(&key.y, &beta, &params.p) % &params.p == signature.r
+    };
+    assert!(links(&view0, &signature0, b"first"));
+    assert!(links(&view0, &signature1, b"second"));
+    assert!(links(&view1, &signature0, b"first"));
+    assert!(links(&view1, &signature1, b"second"));
+}
+
+#[test]
+fn test_schnorr_identification_invalid_commitment() {
+    // A commitment outside the subgroup is rejected before the verifier derives a challenge for it
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let verifier = SchnorrVerifier {
+        r: 0,
+        commitment: BigUint::zero(),
+        challenge: BigUint::zero(),
+        transcript: ChallengeTranscript::new(b"schnorr identification"),
+        params: &key.params,
+        y: &key.y,
+    };
+    assert!(verifier.validate(&SchnorrProverMessage::Commitment(BigUint::zero())).is_err());
+    assert!(verifier.validate(&SchnorrProverMessage::Commitment(&key.params.p - 1u32)).is_err());
+    assert!(verifier.validate(&SchnorrProverMessage::Response(BigUint::zero())).is_err());
+    assert_eq!(verifier.validate(&SchnorrProverMessage::Commitment(key.y.clone())), Ok(()));
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{compiler_fence, Ordering};
+use num_bigint::BigUint;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+#[cfg(test)]
+use num_traits::Zero;
+
+// ************ Secret value implementation ************
+
+// Witnesses, secret keys, nonces, and commitment randomness outlive the computations that use them in
+// protocol state, and anything left in freed memory can turn up in a core dump, swap, or a later
+// allocation. Wrapping such a value in Secret overwrites it when it is dropped, keeps it out of Debug
+// output, and marks where secrets live. Secret derefs to the value, so code using it reads as before.
+//
+// This is best effort. Big integer arithmetic allocates temporaries that are freed without being
+// overwritten, and its running time depends on the values, so only comparisons of secret bytes are
+// made constant time, with ct_eq.
+
+// A value that can overwrite itself in place. Zeroize can't be implemented for BigUint outside of
+// num-bigint, so secrets are bounded by this trait instead.
+pub trait Erase {
+    fn erase(&mut self);
+}
+
+// Assigning a BigUint as many zero digits as it has writes them over its buffer before it is truncated,
+// since clearing a Vec keeps its allocation
+impl Erase for BigUint {
+    fn erase(&mut self) {
+        let words = self.bits().div_ceil(32) as usize;
+        self.assign_from_slice(&vec![0u32; words]);
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl Erase for Vec<u8> {
+    fn erase(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<const N: usize> Erase for [u8; N] {
+    fn erase(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<A: Erase, B: Erase> Erase for (A, B) {
+    fn erase(&mut self) {
+        self.0.erase();
+        self.1.erase();
+    }
+}
+
+impl<T: Erase> Erase for Vec<T> {
+    fn erase(&mut self) {
+        self.iter_mut().for_each(Erase::erase);
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Secret<T: Erase>(T);
+
+impl<T: Erase> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+}
+
+impl<T: Erase> From<T> for Secret<T> {
+    fn from(value: T) -> Secret<T> {
+        Secret(value)
+    }
+}
+
+impl<T: Erase> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Erase> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Erase> Zeroize for Secret<T> {
+    fn zeroize(&mut self) {
+        self.0.erase();
+    }
+}
+
+impl<T: Erase> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.erase();
+    }
+}
+
+impl<T: Erase> ZeroizeOnDrop for Secret<T> {}
+
+impl<T: Erase> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+impl<T: Erase + PartialEq> PartialEq for Secret<T> {
+    fn eq(&self, other: &Secret<T>) -> bool {
+        self.0 == other.0
+    }
+}
+
+// Secrets serialize as their value, for the protocols that eventually reveal them, such as openings
+impl<T: Erase + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Erase + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Secret<T>, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+// Compares byte strings in time that depends only on their lengths
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
+#[test]
+fn test_erase() {
+    let mut x = BigUint::from_bytes_be(&[0xff; 100]);
+    x.erase();
+    assert!(x.is_zero());
+    let mut secret = Secret::new((BigUint::from(7u32), vec![1u8, 2, 3]));
+    secret.zeroize();
+    assert!(secret.0.0.is_zero() && secret.0.1.is_empty());
+}
+
+#[test]
+fn test_secret_is_redacted() {
+    let secret = Secret::new(BigUint::from(1234567u32));
+    assert_eq!(format!("{:?}", secret), "Secret(..)");
+    assert_eq!(*secret, BigUint::from(1234567u32));
+    assert_eq!(serde_json::to_string(&Secret::new([1u8, 2])).unwrap(), "[1,2]");
+}
+
+#[test]
+fn test_ct_eq() {
+    assert!(ct_eq(b"zklib", b"zklib"));
+    assert!(!ct_eq(b"zklib", b"zklic"));
+    assert!(!ct_eq(b"zklib", b"zkli"));
+}
@@ -0,0 +1,1475 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::{Router, body::Bytes, extract::{ConnectInfo, DefaultBodyLimit, Path, State}, http::{header, HeaderMap, HeaderName, StatusCode}, routing::{get, post}};
+use cpu_time::ThreadTime;
+use num_bigint::BigUint;
+use prometheus::{core::Collector, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder, TEXT_FORMAT};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+#[cfg(feature = "tls")]
+use axum_server::tls_rustls::RustlsConfig;
+use crate::audit::AuditLog;
+use crate::commitandprove::{CommitAndProve, EqualityProof, NonInteractiveProof, OpeningProof, RangeProof};
+use crate::config::{self, LogLevel};
+#[cfg(feature = "tls")]
+use crate::config::TlsPaths;
+use crate::designated::{verify_designated, DesignatedProof};
+use crate::escrow::Escrow;
+use crate::graph::{GIProverMessage, GIVerifierMessage, GraphPair};
+use crate::handshake::{AuthError, Handshake, Identity, Puzzles, CHALLENGE_TTL, MAX_WORK_BITS, WORK_HEADER};
+#[cfg(feature = "groth16")]
+use crate::groth16::{verify_groth16, Groth16Instance, Groth16Proof};
+use crate::params::{Parameters, SchnorrParameters, serde_biguint};
+use crate::pedersen::{PedersenCommitment, PedersenParameters};
+use crate::registry::StatementRegistry;
+use crate::schnorr::SchnorrSignature;
+use crate::session::{lock, GISession, SessionError, SessionStore};
+use crate::transcript::ProofContext;
+#[cfg(test)]
+use crate::audit::{to_hex, AuditEntry};
+#[cfg(test)]
+use crate::designated::prove_designated;
+#[cfg(test)]
+use crate::graph::{GIProver, Graph};
+#[cfg(test)]
+use crate::handshake::{challenge_message, signature_header, solve_puzzle, work_header, Credential, Policy};
+#[cfg(test)]
+use crate::hash::{Hasher, Sha256Hasher};
+#[cfg(test)]
+use crate::Prover;
+#[cfg(test)]
+use crate::schnorr::SchnorrKeyPair;
+
+// ************ Verification service implementation ************
+
+// Clients that only need to check non-interactive proofs can send them to an HTTP service instead of
+// linking the library. A client posts the instance and the proof as JSON to /verify/{protocol}, and the
+// service replies with a verdict. The instance is part of the request, so the client decides what is
+// being proven, and the service checks the parameters in it before verifying anything.
+//
+// - schnorr-signature: a Schnorr signature on a message, which proves knowledge of the signing key
+// - designated: a designated-verifier proof of knowledge of a discrete log
+// - commit-and-prove: a sequence of proofs about one Pedersen commitment, verified in order
+// - groth16-bn254: a Groth16 proof produced outside zklib, with the groth16 feature
+//
+// Interactive GI proofs run as sessions instead. A client posts the instance and a number of repetitions
+// to /sessions/gi, then posts each prover message to /sessions/gi/{id} and gets the verifier's reply,
+// until the session has a verdict. A prover can attach its annotations encrypted for an auditor, see
+// escrow.rs, by posting them to /sessions/gi/{id}/escrow. Instances registered with the server, see
+// registry.rs, are listed at /statements, and a session can name one in place of its instance.
+//
+// A server given identities only serves clients that authenticate, as described in handshake.rs, and
+// refuses requests their policies don't allow. The session id returned to an authenticated client is
+// random, so later messages of the session need no credentials, but only the client that started a
+// session can attach an escrow to it, since the escrow is what an auditor later takes its word from. A
+// server can also make clients solve a puzzle from /sessions/puzzle before it starts each session, and
+// refuses sessions without a solution with a 428.
+
+#[cfg(not(feature = "groth16"))]
+pub const VERIFIABLE_PROTOCOLS: &[&str] = &["schnorr-signature", "designated", "commit-and-prove"];
+#[cfg(feature = "groth16")]
+pub const VERIFIABLE_PROTOCOLS: &[&str] = &["schnorr-signature", "designated", "commit-and-prove", "groth16-bn254"];
+
+#[derive(Deserialize)]
+struct VerifyRequest<I, P> {
+    instance: I,
+    proof: P,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignatureInstance {
+    pub params: SchnorrParameters,
+    #[serde(with = "serde_biguint")]
+    pub public_key: BigUint,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DesignatedInstance {
+    pub params: SchnorrParameters,
+    #[serde(with = "serde_biguint")]
+    pub prover_key: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub verifier_key: BigUint,
+    pub session_id: String,
+}
+
+// A statement about a committed value, matching one of the committed proofs
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommittedStatement {
+    Opening,
+    Equality { other: PedersenCommitment },
+    Range { bits: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommittedInstance {
+    pub params: PedersenParameters,
+    pub commitment: PedersenCommitment,
+    pub session_id: String,
+    // Statements proven by the proofs, in the order they were proven
+    pub statements: Vec<CommittedStatement>,
+}
+
+// Starts an interactive GI proof of the instance, repeated the given number of times
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionRequest {
+    pub instance: GraphPair,
+    pub repetitions: u32,
+}
+
+// A session request as sent, with either the instance or the name or digest of a registered statement
+#[derive(Deserialize)]
+struct SessionBody {
+    instance: Option<GraphPair>,
+    statement: Option<String>,
+    repetitions: u32,
+}
+
+// Registers the instance under the name
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+    pub instance: GraphPair,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub protocol: String,
+    pub accept: bool,
+    // Why the proof was rejected, if it was
+    pub reason: Option<String>,
+}
+
+impl Verdict {
+    fn new(protocol: &str, result: Result<(), String>) -> Verdict {
+        Verdict { protocol: protocol.to_string(), accept: result.is_ok(), reason: result.err() }
+    }
+}
+
+// Requests the service can't produce a verdict for
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyError {
+    UnknownProtocol(String),
+    Malformed(String),
+    // The request is well formed, but verifying it would cost more than the limits allow
+    OverBudget(String),
+}
+
+impl VerifyError {
+    fn status(&self) -> StatusCode {
+        match self {
+            VerifyError::UnknownProtocol(_) => StatusCode::NOT_FOUND,
+            VerifyError::Malformed(_) => StatusCode::BAD_REQUEST,
+            VerifyError::OverBudget(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            VerifyError::UnknownProtocol(protocol) => format!("Unknown protocol {}, expected one of {}", protocol, VERIFIABLE_PROTOCOLS.join(", ")),
+            VerifyError::Malformed(reason) => format!("Malformed request: {}", reason),
+            VerifyError::OverBudget(reason) => format!("Request exceeds limits: {}", reason),
+        }
+    }
+}
+
+fn parse<I: DeserializeOwned, P: DeserializeOwned>(body: &[u8]) -> Result<VerifyRequest<I, P>, VerifyError> {
+    serde_json::from_slice(body).map_err(|e| VerifyError::Malformed(e.to_string()))
+}
+
+fn check(accept: bool, reason: &str) -> Result<(), String> {
+    if accept { Ok(()) } else { Err(reason.to_string()) }
+}
+
+// Verifies the JSON request body for the given protocol, refusing requests that exceed the limits
+// before doing any work that depends on their size
+pub fn verify_request(protocol: &str, body: &[u8], limits: &Limits) -> Result<Verdict, VerifyError> {
+    if body.len() > limits.max_body_bytes {
+        return Err(VerifyError::OverBudget(format!("Body has {} bytes, more than the limit of {}", body.len(), limits.max_body_bytes)));
+    }
+    let result = match protocol {
+        "schnorr-signature" => {
+            let request: VerifyRequest<SignatureInstance, SchnorrSignature> = parse(body)?;
+            let (instance, signature) = (&request.instance, &request.proof);
+            limits.check_numbers(&instance.params, [&instance.public_key, &signature.r, &signature.s])?;
+            verify_signature(instance, signature)
+        },
+        "designated" => {
+            let request: VerifyRequest<DesignatedInstance, DesignatedProof> = parse(body)?;
+            let (instance, proof) = (&request.instance, &request.proof);
+            let numbers = [&instance.prover_key, &instance.verifier_key, &proof.announcements.0, &proof.announcements.1, &proof.challenge, &proof.responses.0, &proof.responses.1];
+            limits.check_numbers(&instance.params, numbers)?;
+            check(instance.params.validate(), "Parameters are invalid")
+                .and_then(|_| instance.params.check_element(&instance.prover_key))
+                .and_then(|_| instance.params.check_element(&instance.verifier_key))
+                .and_then(|_| check(verify_designated(&instance.params, &instance.prover_key, &instance.verifier_key, instance.session_id.as_bytes(), proof), "Proof does not verify"))
+        },
+        "commit-and-prove" => {
+            let request: VerifyRequest<CommittedInstance, Vec<NonInteractiveProof>> = parse(body)?;
+            limits.check_committed(&request.instance, &request.proof)?;
+            verify_committed(&request.instance, &request.proof)
+        },
+        #[cfg(feature = "groth16")]
+        "groth16-bn254" => {
+            // Decoding and verification cost grows with the number of public inputs, which the body limit bounds
+            let request: VerifyRequest<Groth16Instance, Groth16Proof> = parse(body)?;
+            verify_groth16(&request.instance, &request.proof)
+        },
+        _ => return Err(VerifyError::UnknownProtocol(protocol.to_string())),
+    };
+    Ok(Verdict::new(protocol, result))
+}
+
+fn verify_signature(instance: &SignatureInstance, signature: &SchnorrSignature) -> Result<(), String> {
+    check(instance.params.validate(), "Parameters are invalid")?;
+    instance.params.check_element(&instance.public_key)?;
+    check(signature.verify(&instance.params, &instance.public_key, instance.message.as_bytes()), "Signature does not verify")
+}
+
+fn verify_committed(instance: &CommittedInstance, proofs: &[NonInteractiveProof]) -> Result<(), String> {
+    check(instance.params.validate(), "Parameters are invalid")?;
+    if proofs.len() != instance.statements.len() {
+        return Err(format!("Got {} proofs for {} statements", proofs.len(), instance.statements.len()));
+    }
+    let mut session = CommitAndProve::new(&instance.params, &instance.commitment, instance.session_id.as_bytes());
+    instance.statements.iter().zip(proofs).enumerate().try_for_each(|(i, (statement, proof))| {
+        let accept = match statement {
+            CommittedStatement::Opening => session.verify(&OpeningProof, proof),
+            CommittedStatement::Equality { other } => session.verify(&EqualityProof { other: other.clone() }, proof),
+            CommittedStatement::Range { bits } => session.verify(&RangeProof { bits: *bits }, proof),
+        };
+        check(accept, &format!("Proof {} ({:?}) does not verify", i, statement))
+    })
+}
+
+// ************ Limits implementation ************
+
+// Anyone who can reach the service picks the instance it verifies, so an attacker doesn't need to find a
+// hard instance, just a large one: checking that a huge modulus is prime, exponentiating by a huge
+// response, or verifying thousands of range proofs ties up a verification thread for as long as the
+// attacker likes. The service therefore bounds the size of everything before it starts verifying, and
+// bounds how much of its capacity any one client can use.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    // Largest request body accepted, checked against Content-Length before the body is read
+    pub max_body_bytes: usize,
+    // Requests from one IP address that may be verified at the same time
+    pub max_in_flight_per_ip: usize,
+    // Sustained rate of requests accepted from one IP address, and how many may arrive at once
+    pub requests_per_second: f64,
+    pub burst: u32,
+    // Largest modulus p, and so the largest number in a request, which bounds the cost of primality
+    // tests and of every exponentiation
+    pub max_modulus_bits: u64,
+    // Largest number of commit-and-prove statements, each of which is verified separately
+    pub max_statements: usize,
+    // Largest range proof, which is one OR proof per bit
+    pub max_range_bits: u32,
+    // Largest graph in a session's instance, which bounds the size of every message in the session
+    pub max_vertices: u32,
+    // Largest session instance by Graph::memory_footprint, which is kept in memory until the session
+    // expires. A graph with few vertices can still be dense.
+    pub max_instance_bytes: usize,
+    // Most repetitions a session may ask for, each of which takes two prover messages
+    pub max_repetitions: u32,
+    // Most sessions open at once, each of which is kept in memory and on disk until it expires
+    pub max_sessions: usize,
+    // Most statements registered with the server, each of which is kept in memory and on disk for good
+    pub max_registered: usize,
+    // Leading zero bits of the puzzle a client solves before each session, or 0 to start sessions freely
+    pub work_bits: u32,
+    // Protocols served, from VERIFIABLE_PROTOCOLS and "gi" for sessions, or None to serve all of them
+    pub protocols: Option<Vec<String>>,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_body_bytes: 1 << 20,
+            max_in_flight_per_ip: 4,
+            requests_per_second: 10.0,
+            burst: 20,
+            max_modulus_bits: 4096,
+            max_statements: 16,
+            max_range_bits: 64,
+            max_vertices: 1024,
+            max_instance_bytes: 16 << 20,
+            max_repetitions: 128,
+            max_sessions: 1024,
+            max_registered: 1024,
+            work_bits: 0,
+            protocols: None,
+        }
+    }
+}
+
+impl Limits {
+    // Checks that the service was configured to serve the protocol
+    fn check_protocol(&self, protocol: &str) -> Result<(), (StatusCode, String)> {
+        match &self.protocols {
+            Some(protocols) if !protocols.iter().any(|x| x == protocol) => Err((StatusCode::NOT_FOUND, format!("Protocol {} is not enabled on this server", protocol))),
+            _ => Ok(()),
+        }
+    }
+
+    // Checks the size of the group before the parameters are validated, and that no other number is
+    // larger than the modulus
+    fn check_numbers<'a>(&self, params: &'a SchnorrParameters, numbers: impl IntoIterator<Item = &'a BigUint>) -> Result<(), VerifyError> {
+        let bits = params.p.bits();
+        if bits > self.max_modulus_bits {
+            return Err(VerifyError::OverBudget(format!("Modulus has {} bits, more than the limit of {}", bits, self.max_modulus_bits)));
+        }
+        if [&params.q, &params.g].iter().copied().chain(numbers).any(|x| x.bits() > bits) {
+            return Err(VerifyError::OverBudget("Request contains a number larger than the modulus".to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_committed(&self, instance: &CommittedInstance, proofs: &[NonInteractiveProof]) -> Result<(), VerifyError> {
+        let statements = &instance.statements;
+        if statements.len() > self.max_statements || proofs.len() > self.max_statements {
+            return Err(VerifyError::OverBudget(format!("Request has {} statements and {} proofs, more than the limit of {}", statements.len(), proofs.len(), self.max_statements)));
+        }
+        let range_bits = statements.iter().filter_map(|s| match s { CommittedStatement::Range { bits } => Some(*bits), _ => None });
+        if let Some(bits) = range_bits.max().filter(|&bits| bits > self.max_range_bits) {
+            return Err(VerifyError::OverBudget(format!("Range proof has {} bits, more than the limit of {}", bits, self.max_range_bits)));
+        }
+        let others = statements.iter().filter_map(|s| match s { CommittedStatement::Equality { other } => Some(&other.c), _ => None });
+        let values = proofs.iter().flat_map(|proof| proof.announcement.iter().chain(&proof.response));
+        self.check_numbers(&instance.params.group, [&instance.params.h, &instance.commitment.c].iter().copied().chain(others).chain(values))
+    }
+
+    // Checks a session request, returning the size of its instance as check_instance measures it
+    fn check_session(&self, request: &SessionRequest, open: usize) -> Result<usize, VerifyError> {
+        if request.repetitions == 0 {
+            return Err(VerifyError::Malformed("Session needs at least one repetition".to_string()));
+        }
+        if request.repetitions > self.max_repetitions {
+            return Err(VerifyError::OverBudget(format!("Session has {} repetitions, more than the limit of {}", request.repetitions, self.max_repetitions)));
+        }
+        let bytes = self.check_instance(&request.instance)?;
+        if open >= self.max_sessions {
+            return Err(VerifyError::OverBudget(format!("Server has {} open sessions, the limit", open)));
+        }
+        Ok(bytes)
+    }
+
+    // Checks the size of a GI instance, returning its memory footprint, which is also the size an identity's
+    // policy is checked against
+    fn check_instance(&self, instance: &GraphPair) -> Result<usize, VerifyError> {
+        let n = instance.g0.n.max(instance.g1.n);
+        if n > self.max_vertices {
+            return Err(VerifyError::OverBudget(format!("Graph has {} vertices, more than the limit of {}", n, self.max_vertices)));
+        }
+        let bytes = instance.memory_footprint();
+        if bytes > self.max_instance_bytes {
+            return Err(VerifyError::OverBudget(format!("Instance takes {} bytes, more than the limit of {}", bytes, self.max_instance_bytes)));
+        }
+        Ok(bytes)
+    }
+}
+
+// Requests a client has in flight, and the tokens left in its bucket, which refills at
+// requests_per_second up to burst
+struct Client {
+    in_flight: usize,
+    tokens: f64,
+    refilled: Instant,
+}
+
+// Once this many clients are tracked, those that are no longer limited are forgotten. If that isn't
+// enough, idle clients are forgotten longest idle first until only half as many are tracked, and a new
+// client is refused while every tracked one has a request in flight.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+struct ServerState {
+    limits: Limits,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+    sessions: SessionStore,
+    metrics: Metrics,
+    audit: Option<Mutex<AuditLog>>,
+    handshake: Option<Mutex<Handshake>>,
+    puzzles: Option<Mutex<Puzzles>>,
+    registry: Mutex<StatementRegistry>,
+    shutdown: Shutdown,
+}
+
+// Holds one of a client's in-flight slots, releasing it when dropped
+struct Permit<'a> {
+    state: &'a ServerState,
+    ip: IpAddr,
+}
+
+impl ServerState {
+    fn new(limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry, shutdown: Shutdown) -> ServerState {
+        let puzzles = (limits.work_bits > 0).then(|| Mutex::new(Puzzles::new(limits.work_bits.min(MAX_WORK_BITS))));
+        ServerState {
+            puzzles, limits, clients: Mutex::new(HashMap::new()), sessions, metrics: Metrics::new(), audit: audit.map(Mutex::new),
+            handshake: handshake.map(Mutex::new), registry: Mutex::new(registry), shutdown,
+        }
+    }
+
+    // The instance the session request holds or names. Statements registered in the registry's directory
+    // since the server started are picked up the first time they are asked for.
+    fn resolve(&self, body: SessionBody) -> Result<SessionRequest, (StatusCode, String)> {
+        let instance = match (body.instance, body.statement) {
+            (Some(instance), None) => instance,
+            (None, Some(id)) => {
+                let mut registry = lock(&self.registry);
+                if registry.get(&id).is_none() {
+                    registry.refresh_stale(self.limits.max_registered).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                }
+                registry.get(&id).ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown statement {}", id)))?.instance.clone()
+            },
+            _ => return Err((StatusCode::BAD_REQUEST, "Malformed request: expected either an instance or a statement".to_string())),
+        };
+        Ok(SessionRequest { instance, repetitions: body.repetitions })
+    }
+
+    // Checks that the client is allowed to use the protocol on an instance of the given size, if the server
+    // has identities
+    fn authorize(&self, authorization: Option<&str>, protocol: &str, instance_bytes: usize) -> Result<(), (StatusCode, String)> {
+        match &self.handshake {
+            Some(handshake) => lock(handshake).authenticate(authorization).and_then(|identity| identity.authorize(protocol, instance_bytes)).map_err(auth_status),
+            None => Ok(()),
+        }
+    }
+
+    // The client's identity, if the server has identities
+    fn authenticate(&self, authorization: Option<&str>) -> Result<Option<Identity>, (StatusCode, String)> {
+        match &self.handshake {
+            Some(handshake) => Ok(Some(lock(handshake).authenticate(authorization).map_err(auth_status)?.clone())),
+            None => Ok(None),
+        }
+    }
+
+    // Checks the client's solution to a puzzle, if the server asks for work
+    fn check_work(&self, work: Option<&str>) -> Result<(), (StatusCode, String)> {
+        match &self.puzzles {
+            Some(puzzles) => lock(puzzles).redeem(work).map_err(|e| (StatusCode::PRECONDITION_REQUIRED, e)),
+            None => Ok(()),
+        }
+    }
+
+    // Appends the verdict to the audit log if the server keeps one, given the digests of the statement and
+    // of the proof. A verdict that can't be logged isn't reported.
+    fn audit<F: FnOnce() -> ([u8; 32], [u8; 32])>(&self, protocol: &str, accept: bool, digests: F) -> Result<(), (StatusCode, String)> {
+        if let Some(log) = &self.audit {
+            let (statement, transcript) = digests();
+            lock(log).append(protocol, &statement, accept, &transcript).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        }
+        Ok(())
+    }
+
+    fn admit(&self, ip: IpAddr) -> Result<Permit<'_>, String> {
+        let now = Instant::now();
+        let limits = &self.limits;
+        let refill = |client: &mut Client| {
+            let tokens = client.tokens + now.duration_since(client.refilled).as_secs_f64() * limits.requests_per_second;
+            client.tokens = tokens.min(limits.burst as f64);
+            client.refilled = now;
+        };
+        let mut clients = lock(&self.clients);
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&ip) {
+            // refilled is when a client last made a request, since every request refills its bucket
+            let mut idle: Vec<(Instant, IpAddr)> = Vec::new();
+            clients.retain(|&ip, client| {
+                let last_seen = client.refilled;
+                refill(client);
+                if client.in_flight == 0 {
+                    idle.push((last_seen, ip));
+                }
+                client.in_flight > 0 || client.tokens < limits.burst as f64
+            });
+            idle.sort_unstable();
+            let excess = clients.len().saturating_sub(MAX_TRACKED_CLIENTS / 2);
+            let evicted: Vec<IpAddr> = idle.into_iter().map(|(_, ip)| ip).filter(|ip| clients.contains_key(ip)).take(excess).collect();
+            evicted.iter().for_each(|ip| {
+                clients.remove(ip);
+            });
+            if clients.len() >= MAX_TRACKED_CLIENTS {
+                return Err(format!("Too many clients, the limit is {}", MAX_TRACKED_CLIENTS));
+            }
+        }
+        let client = clients.entry(ip).or_insert(Client { in_flight: 0, tokens: limits.burst as f64, refilled: now });
+        refill(client);
+        if client.in_flight >= limits.max_in_flight_per_ip {
+            return Err(format!("Too many requests in flight, the limit is {}", limits.max_in_flight_per_ip));
+        }
+        if client.tokens < 1.0 {
+            return Err(format!("Too many requests, the limit is {} per second", limits.requests_per_second));
+        }
+        client.tokens -= 1.0;
+        client.in_flight += 1;
+        Ok(Permit { state: self, ip })
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = lock(&self.state.clients).get_mut(&self.ip) {
+            client.in_flight -= 1;
+        }
+    }
+}
+
+// ************ Metrics implementation ************
+
+// Operators running the service scrape these from /metrics in the Prometheus text format. Protocol labels
+// only take the names of protocols the service knows, since the path is chosen by the client.
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    sessions_started: IntCounter,
+    verdicts: IntCounterVec,
+    round_latency: HistogramVec,
+    verification_cpu: HistogramVec,
+    bytes_received: IntCounterVec,
+    bytes_sent: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let counter = |name: &str, help: &str, labels: &[&str]| IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+        let histogram = |name: &str, help: &str| HistogramVec::new(HistogramOpts::new(name, help), &["protocol"]).unwrap();
+        let metrics = Metrics {
+            registry: Registry::new(),
+            requests: counter("zklib_requests_total", "Requests handled, by endpoint and response status", &["endpoint", "status"]),
+            sessions_started: IntCounter::new("zklib_sessions_started_total", "Interactive sessions started").unwrap(),
+            verdicts: counter("zklib_verdicts_total", "Proofs accepted and rejected, by protocol", &["protocol", "verdict"]),
+            round_latency: histogram("zklib_round_latency_seconds", "Time to verify a proof or handle one prover message"),
+            verification_cpu: histogram("zklib_verification_cpu_seconds", "CPU time spent verifying a proof or handling one prover message"),
+            bytes_received: counter("zklib_bytes_received_total", "Request body bytes received, by endpoint", &["endpoint"]),
+            bytes_sent: counter("zklib_bytes_sent_total", "Response body bytes sent, by endpoint", &["endpoint"]),
+        };
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.sessions_started.clone()),
+            Box::new(metrics.verdicts.clone()),
+            Box::new(metrics.round_latency.clone()),
+            Box::new(metrics.verification_cpu.clone()),
+            Box::new(metrics.bytes_received.clone()),
+            Box::new(metrics.bytes_sent.clone()),
+        ];
+        collectors.into_iter().for_each(|collector| metrics.registry.register(collector).unwrap());
+        metrics
+    }
+
+    fn record_verdict(&self, protocol: &str, accept: bool) {
+        self.verdicts.with_label_values(&[protocol, if accept { "accept" } else { "reject" }]).inc();
+    }
+
+    fn encode(&self) -> String {
+        TextEncoder::new().encode_to_string(&self.registry.gather()).unwrap()
+    }
+}
+
+// ************ HTTP implementation ************
+
+type Response = (StatusCode, [(HeaderName, &'static str); 1], String);
+
+// What a request is for, which labels its metrics
+#[derive(Clone, Copy)]
+struct Endpoint {
+    name: &'static str,
+    protocol: &'static str,
+}
+
+// Admits the client and runs the request off the threads serving requests, since verifying is CPU bound
+// and saving sessions blocks on the disk
+async fn run_limited<F>(state: Arc<ServerState>, ip: IpAddr, endpoint: Endpoint, received: usize, handle: F) -> Response
+where F: FnOnce(&ServerState) -> Result<Value, (StatusCode, String)> + Send + 'static {
+    let task_state = state.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let state = task_state;
+        let _permit = state.admit(ip).map_err(|reason| (StatusCode::TOO_MANY_REQUESTS, reason))?;
+        let (start, cpu_start) = (Instant::now(), ThreadTime::now());
+        let result = handle(&state);
+        state.metrics.round_latency.with_label_values(&[endpoint.protocol]).observe(start.elapsed().as_secs_f64());
+        state.metrics.verification_cpu.with_label_values(&[endpoint.protocol]).observe(cpu_start.elapsed().as_secs_f64());
+        result
+    }).await;
+    let (status, body) = match result {
+        Ok(Ok(body)) => (StatusCode::OK, body),
+        Ok(Err((status, error))) => (status, json!({ "error": error })),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e.to_string() })),
+    };
+    let body = body.to_string();
+    // Failures are logged with their error, and successes only when debugging
+    let request = format!("{} {} from {}: {}", endpoint.name, endpoint.protocol, ip, status.as_u16());
+    match status {
+        _ if status.is_server_error() => config::log(LogLevel::Error, &format!("{} {}", request, body)),
+        _ if status.is_client_error() => config::log(LogLevel::Warn, &format!("{} {}", request, body)),
+        _ => config::log(LogLevel::Debug, &request),
+    }
+    let metrics = &state.metrics;
+    metrics.requests.with_label_values(&[endpoint.name, status.as_str()]).inc();
+    metrics.bytes_received.with_label_values(&[endpoint.name]).inc_by(received as u64);
+    metrics.bytes_sent.with_label_values(&[endpoint.name]).inc_by(body.len() as u64);
+    (status, [(header::CONTENT_TYPE, "application/json")], body)
+}
+
+fn auth_status(e: AuthError) -> (StatusCode, String) {
+    let status = match e {
+        AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+        AuthError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+        AuthError::Missing | AuthError::Rejected(_) => StatusCode::UNAUTHORIZED,
+    };
+    (status, e.to_string())
+}
+
+fn session_error_status(e: &SessionError) -> StatusCode {
+    match e {
+        SessionError::UnknownSession(_) => StatusCode::NOT_FOUND,
+        SessionError::Finished(_) => StatusCode::CONFLICT,
+        SessionError::Invalid(_) => StatusCode::BAD_REQUEST,
+        SessionError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        SessionError::Escrow(_) => StatusCode::CONFLICT,
+    }
+}
+
+// Progress of the session, along with the verifier's reply to the last message if there is one
+fn session_reply(session: &GISession, message: Option<GIVerifierMessage>) -> Value {
+    json!({
+        "session": session.id,
+        "repetitions": session.repetitions,
+        "accepted": session.accepted,
+        "verdict": session.verdict,
+        "expires_at": session.expires_at,
+        "escrowed": session.escrow.is_some(),
+        "message": message,
+    })
+}
+
+// The Authorization header, if the client sent a readable one
+fn authorization(headers: &HeaderMap) -> Option<String> {
+    headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).map(String::from)
+}
+
+// The solution to a puzzle, if the client sent a readable one
+fn work(headers: &HeaderMap) -> Option<String> {
+    headers.get(WORK_HEADER).and_then(|value| value.to_str().ok()).map(String::from)
+}
+
+async fn verify(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, Path(protocol): Path<String>, headers: HeaderMap, body: Bytes) -> Response {
+    let label = VERIFIABLE_PROTOCOLS.iter().find(|&&x| x == protocol).copied().unwrap_or("unknown");
+    let authorization = authorization(&headers);
+    run_limited(state, addr.ip(), Endpoint { name: "verify", protocol: label }, body.len(), move |state| {
+        state.limits.check_protocol(&protocol)?;
+        // The request holds the instance, so its size bounds the instance's
+        state.authorize(authorization.as_deref(), &protocol, body.len())?;
+        let verdict = verify_request(&protocol, &body, &state.limits).map_err(|e| (e.status(), e.message()))?;
+        state.metrics.record_verdict(label, verdict.accept);
+        state.audit(label, verdict.accept, || {
+            // The request is known to be valid JSON, and re-encoding it gives digests that don't depend on
+            // the client's formatting
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let digest = |x: &Value| ProofContext::hash_statement(x.to_string().as_bytes());
+            (digest(&request["instance"]), digest(&request["proof"]))
+        })?;
+        Ok(serde_json::to_value(verdict).unwrap())
+    }).await
+}
+
+async fn create_session(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap, body: Bytes) -> Response {
+    let authorization = authorization(&headers);
+    let work = work(&headers);
+    run_limited(state, addr.ip(), Endpoint { name: "create_session", protocol: "gi" }, body.len(), move |state| {
+        if state.shutdown.is_requested() {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down".to_string()));
+        }
+        state.limits.check_protocol("gi")?;
+        // The work is checked first, so a client that hasn't done it costs the server one hash, and the client
+        // is authenticated before the server parses anything it sent
+        state.check_work(work.as_deref())?;
+        let client = state.authenticate(authorization.as_deref())?;
+        let body: SessionBody = serde_json::from_slice(&body).map_err(|e| VerifyError::Malformed(e.to_string())).map_err(|e| (e.status(), e.message()))?;
+        let request = state.resolve(body)?;
+        // Sessions started at the same time may all pass the check, so the limit can be overshot by as many
+        // requests as the server handles at once
+        let bytes = state.limits.check_session(&request, state.sessions.len()).map_err(|e| (e.status(), e.message()))?;
+        if let Some(client) = &client {
+            client.authorize("gi", bytes).map_err(auth_status)?;
+        }
+        let owner = client.map(|client| client.name);
+        let session = state.sessions.create(request.instance, request.repetitions, owner).map_err(|e| (session_error_status(&e), e.to_string()))?;
+        state.metrics.sessions_started.inc();
+        Ok(session_reply(&session, Some(session.init())))
+    }).await
+}
+
+async fn session_message(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, Path(id): Path<String>, body: Bytes) -> Response {
+    run_limited(state, addr.ip(), Endpoint { name: "session_message", protocol: "gi" }, body.len(), move |state| {
+        let msg: GIProverMessage = serde_json::from_slice(&body).map_err(|e| VerifyError::Malformed(e.to_string())).map_err(|e| (e.status(), e.message()))?;
+        // A finished session refuses further messages, so this is the only message that sees its verdict. It
+        // is logged before the session is saved with it, so a verdict that can't be logged is never reached.
+        let (reply, session) = state.sessions.handle_and(&id, &msg, |session| match session.verdict {
+            Some(accept) => state.audit("gi", accept, || (session.instance.digest(), ProofContext::hash_statement(&serde_json::to_vec(&session.transcript).unwrap())))
+                .map_err(|(_, e)| e),
+            None => Ok(()),
+        }).map_err(|e| (session_error_status(&e), e.to_string()))?;
+        if let Some(accept) = session.verdict {
+            state.metrics.record_verdict("gi", accept);
+        }
+        Ok(session_reply(&session, Some(reply)))
+    }).await
+}
+
+async fn attach_escrow(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, Path(id): Path<String>, headers: HeaderMap, body: Bytes) -> Response {
+    let authorization = authorization(&headers);
+    run_limited(state, addr.ip(), Endpoint { name: "attach_escrow", protocol: "gi" }, body.len(), move |state| {
+        let client = state.authenticate(authorization.as_deref())?.map(|identity| identity.name);
+        let escrow: Escrow = serde_json::from_slice(&body).map_err(|e| VerifyError::Malformed(e.to_string())).map_err(|e| (e.status(), e.message()))?;
+        let owner = state.sessions.get(&id).ok_or_else(|| (StatusCode::NOT_FOUND, SessionError::UnknownSession(id.clone()).to_string()))?.owner;
+        if owner != client {
+            return Err((StatusCode::FORBIDDEN, "Only the client that started the session may attach an escrow".to_string()));
+        }
+        let session = state.sessions.attach_escrow(&id, escrow).map_err(|e| (session_error_status(&e), e.to_string()))?;
+        Ok(session_reply(&session, None))
+    }).await
+}
+
+async fn session_status(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, Path(id): Path<String>) -> Response {
+    run_limited(state, addr.ip(), Endpoint { name: "session_status", protocol: "gi" }, 0, move |state| {
+        let session = state.sessions.get(&id).ok_or_else(|| (StatusCode::NOT_FOUND, SessionError::UnknownSession(id.clone()).to_string()))?;
+        Ok(session_reply(&session, None))
+    }).await
+}
+
+async fn list_statements(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>) -> Response {
+    run_limited(state, addr.ip(), Endpoint { name: "list_statements", protocol: "gi" }, 0, move |state| {
+        let mut registry = lock(&state.registry);
+        registry.refresh_stale(state.limits.max_registered).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let statements: Vec<Value> = registry.list().iter().map(|x| json!({ "name": x.name, "digest": x.digest, "registered_at": x.registered_at })).collect();
+        Ok(json!({ "statements": statements }))
+    }).await
+}
+
+async fn get_statement(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, Path(id): Path<String>) -> Response {
+    run_limited(state, addr.ip(), Endpoint { name: "get_statement", protocol: "gi" }, 0, move |state| {
+        let mut registry = lock(&state.registry);
+        if registry.get(&id).is_none() {
+            registry.refresh_stale(state.limits.max_registered).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        }
+        let statement = registry.get(&id).ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown statement {}", id)))?;
+        Ok(serde_json::to_value(statement).unwrap())
+    }).await
+}
+
+async fn register_statement(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap, body: Bytes) -> Response {
+    let authorization = authorization(&headers);
+    run_limited(state, addr.ip(), Endpoint { name: "register_statement", protocol: "gi" }, body.len(), move |state| {
+        // Statements are saved for good, so only clients the server can identify may register them
+        let client = state.authenticate(authorization.as_deref())?
+            .ok_or_else(|| (StatusCode::FORBIDDEN, "Server does not authenticate clients, so it does not register statements".to_string()))?;
+        let request: RegisterRequest = serde_json::from_slice(&body).map_err(|e| VerifyError::Malformed(e.to_string())).map_err(|e| (e.status(), e.message()))?;
+        let bytes = state.limits.check_instance(&request.instance).map_err(|e| (e.status(), e.message()))?;
+        client.authorize("gi", bytes).map_err(auth_status)?;
+        let mut registry = lock(&state.registry);
+        if registry.len() >= state.limits.max_registered && registry.get(&request.name).is_none() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Server has {} registered statements, the limit", registry.len())));
+        }
+        let statement = registry.register(&request.name, request.instance).map_err(|e| (StatusCode::CONFLICT, e))?;
+        Ok(json!({ "name": statement.name, "digest": statement.digest, "registered_at": statement.registered_at }))
+    }).await
+}
+
+#[derive(Deserialize)]
+struct ChallengeRequest {
+    identity: String,
+}
+
+async fn create_challenge(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>, body: Bytes) -> Response {
+    let client = addr.ip();
+    run_limited(state, client, Endpoint { name: "create_challenge", protocol: "handshake" }, body.len(), move |state| {
+        let handshake = state.handshake.as_ref().ok_or_else(|| (StatusCode::NOT_FOUND, "Server does not authenticate clients".to_string()))?;
+        let request: ChallengeRequest = serde_json::from_slice(&body).map_err(|e| VerifyError::Malformed(e.to_string())).map_err(|e| (e.status(), e.message()))?;
+        let nonce = lock(handshake).challenge(&request.identity, client).map_err(auth_status)?;
+        let mut reply = json!({ "nonce": nonce, "expires_in": CHALLENGE_TTL.as_secs() });
+        // A client signing in to start a session gets its puzzle in the same round trip
+        if let Some(puzzles) = &state.puzzles {
+            reply["puzzle"] = puzzle_reply(&mut lock(puzzles), client)?;
+        }
+        Ok(reply)
+    }).await
+}
+
+fn puzzle_reply(puzzles: &mut Puzzles, client: IpAddr) -> Result<Value, (StatusCode, String)> {
+    let nonce = puzzles.issue(client).map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
+    Ok(json!({ "nonce": nonce, "bits": puzzles.bits, "expires_in": CHALLENGE_TTL.as_secs() }))
+}
+
+async fn create_puzzle(State(state): State<Arc<ServerState>>, ConnectInfo(addr): ConnectInfo<SocketAddr>) -> Response {
+    let client = addr.ip();
+    run_limited(state, client, Endpoint { name: "create_puzzle", protocol: "handshake" }, 0, move |state| {
+        let puzzles = state.puzzles.as_ref().ok_or_else(|| (StatusCode::NOT_FOUND, "Server does not ask for work".to_string()))?;
+        puzzle_reply(&mut lock(puzzles), client)
+    }).await
+}
+
+async fn metrics(State(state): State<Arc<ServerState>>) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, TEXT_FORMAT)], state.metrics.encode())
+}
+
+fn app(state: Arc<ServerState>) -> Router {
+    let body_limit = DefaultBodyLimit::max(state.limits.max_body_bytes);
+    Router::new()
+        .route("/verify/{protocol}", post(verify))
+        .route("/sessions/gi", post(create_session))
+        .route("/sessions/challenge", post(create_challenge))
+        .route("/sessions/puzzle", post(create_puzzle))
+        .route("/statements", get(list_statements).post(register_statement))
+        .route("/statements/{id}", get(get_statement))
+        .route("/sessions/gi/{id}", post(session_message).get(session_status))
+        .route("/sessions/gi/{id}/escrow", post(attach_escrow))
+        .route("/metrics", get(metrics))
+        .layer(body_limit)
+        .with_state(state)
+}
+
+pub fn router(limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry) -> Router {
+    app(Arc::new(ServerState::new(limits, sessions, audit, handshake, registry, Shutdown::default())))
+}
+
+// Starts deleting expired sessions in the background, as long as the server runs
+fn start(limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry, shutdown: Shutdown) -> Arc<ServerState> {
+    let period = sessions.ttl.clamp(Duration::from_secs(1), Duration::from_secs(60));
+    let state = Arc::new(ServerState::new(limits, sessions, audit, handshake, registry, shutdown));
+    let cleanup = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let state = cleanup.clone();
+            let _ = tokio::task::spawn_blocking(move || state.sessions.remove_expired()).await;
+        }
+    });
+    state
+}
+
+// Serves requests on the listener until the server fails, deleting expired sessions as it goes and
+// logging every verdict to the audit log if there is one, and only serving authenticated clients if it
+// has identities
+pub async fn serve(listener: TcpListener, limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry) -> io::Result<()> {
+    serve_until(Listener::Http(listener), &Shutdown::default(), limits, sessions, audit, handshake, registry).await
+}
+
+// Serves requests as serve does, over TLS with the certificate chain and private key in the PEM files
+#[cfg(feature = "tls")]
+pub async fn serve_tls(listener: std::net::TcpListener, tls: &TlsPaths, limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry) -> io::Result<()> {
+    serve_until(Listener::Https(listener, tls.clone()), &Shutdown::default(), limits, sessions, audit, handshake, registry).await
+}
+
+// ************ Health and shutdown implementation ************
+
+// An orchestrator such as Kubernetes restarts a server that stops answering its liveness probe, only
+// routes clients to one that passes its readiness probe, and asks a server to stop with SIGTERM before
+// killing it. serve_health answers both probes on a port of their own, so that probes don't count against
+// any client's rate limit and the service's port can stay private to clients.
+//
+// Once a Shutdown is begun, the server reports itself not ready and refuses new sessions with a 503,
+// while sessions under way carry on until they reach a verdict or drain_timeout passes. The server then
+// stops accepting connections and returns once the requests it is handling are answered. Sessions left
+// unfinished are still saved if the server has a state directory, so a client can finish them against
+// the next server.
+
+// Where the service takes connections
+pub enum Listener {
+    Http(TcpListener),
+    // HTTPS, with the certificate chain and private key in the PEM files
+    #[cfg(feature = "tls")]
+    Https(std::net::TcpListener, TlsPaths),
+}
+
+// How often a draining server checks whether its sessions have finished
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+// Handle for asking a running server to stop, shared between the server, its health endpoints, and
+// whatever receives the signal
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+    // Longest the server waits for sessions under way once shutdown is asked for
+    pub drain_timeout: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Shutdown {
+        Shutdown::new(Duration::ZERO)
+    }
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration) -> Shutdown {
+        Shutdown { requested: Arc::new(watch::channel(false).0), drain_timeout }
+    }
+
+    pub fn begin(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    async fn requested(&self) {
+        let _ = self.requested.subscribe().wait_for(|&requested| requested).await;
+    }
+}
+
+// Resolves once shutdown was asked for and the sessions under way have finished or run out of time
+async fn drain(state: Arc<ServerState>) {
+    state.shutdown.requested().await;
+    let deadline = Instant::now() + state.shutdown.drain_timeout;
+    loop {
+        let sessions = state.clone();
+        let in_progress = tokio::task::spawn_blocking(move || sessions.sessions.in_progress()).await.unwrap_or(0);
+        if in_progress == 0 {
+            config::log(LogLevel::Info, "All sessions finished, shutting down");
+            return;
+        }
+        if Instant::now() >= deadline {
+            config::log(LogLevel::Warn, &format!("Shutting down with {} sessions still in progress", in_progress));
+            return;
+        }
+        tokio::time::sleep(DRAIN_POLL).await;
+    }
+}
+
+// Serves requests as serve does until shutdown is asked for, then drains the sessions under way
+pub async fn serve_until(listener: Listener, shutdown: &Shutdown, limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry) -> io::Result<()> {
+    let state = start(limits, sessions, audit, handshake, registry, shutdown.clone());
+    let drained = drain(state.clone());
+    let service = app(state).into_make_service_with_connect_info::<SocketAddr>();
+    match listener {
+        Listener::Http(listener) => axum::serve(listener, service).with_graceful_shutdown(drained).await,
+        #[cfg(feature = "tls")]
+        Listener::Https(listener, tls) => {
+            let config = RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?;
+            listener.set_nonblocking(true)?;
+            let handle = axum_server::Handle::new();
+            let stop = handle.clone();
+            tokio::spawn(async move {
+                drained.await;
+                stop.graceful_shutdown(None);
+            });
+            axum_server::from_tcp_rustls(listener, config).handle(handle).serve(service).await
+        },
+    }
+}
+
+fn status_reply(status: StatusCode, description: &str) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/json")], json!({ "status": description }).to_string())
+}
+
+async fn liveness() -> Response {
+    status_reply(StatusCode::OK, "alive")
+}
+
+async fn readiness(State(shutdown): State<Shutdown>) -> Response {
+    if shutdown.is_requested() {
+        status_reply(StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        status_reply(StatusCode::OK, "ready")
+    }
+}
+
+// Answers liveness probes at /healthz and readiness probes at /readyz until the process exits
+pub async fn serve_health(listener: TcpListener, shutdown: Shutdown) -> io::Result<()> {
+    let app = Router::new().route("/healthz", get(liveness)).route("/readyz", get(readiness)).with_state(shutdown);
+    axum::serve(listener, app).await
+}
+
+#[cfg(test)]
+fn send_request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    send_authorized(addr, method, path, None, body)
+}
+
+#[cfg(test)]
+fn send_authorized(addr: SocketAddr, method: &str, path: &str, authorization: Option<&str>, body: &str) -> (u16, String) {
+    let headers: Vec<(&str, &str)> = authorization.iter().map(|&x| ("Authorization", x)).collect();
+    send_with_headers(addr, method, path, &headers, body)
+}
+
+#[cfg(test)]
+fn send_with_headers(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> (u16, String) {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    let headers: String = headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", method, path, headers, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, body.to_string())
+}
+
+#[cfg(test)]
+fn post_json(addr: SocketAddr, path: &str, body: &str) -> (u16, Value) {
+    let (status, body) = send_request(addr, "POST", path, body);
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+fn get_json(addr: SocketAddr, path: &str) -> (u16, Value) {
+    let (status, body) = send_request(addr, "GET", path, "");
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+fn spawn_server(runtime: &tokio::runtime::Runtime, limits: Limits) -> SocketAddr {
+    spawn_server_with(runtime, limits, SessionStore::in_memory(Duration::from_secs(60)), None, None)
+}
+
+#[cfg(test)]
+fn spawn_server_with(runtime: &tokio::runtime::Runtime, limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>) -> SocketAddr {
+    spawn_server_with_registry(runtime, limits, sessions, audit, handshake, StatementRegistry::in_memory())
+}
+
+#[cfg(test)]
+fn spawn_server_with_registry(runtime: &tokio::runtime::Runtime, limits: Limits, sessions: SessionStore, audit: Option<AuditLog>, handshake: Option<Handshake>, registry: StatementRegistry) -> SocketAddr {
+    let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    runtime.spawn(serve(listener, limits, sessions, audit, handshake, registry));
+    addr
+}
+
+#[test]
+fn test_verify_signature_request() {
+    let params = SchnorrParameters::generate(64);
+    let key = SchnorrKeyPair::generate(&params);
+    let instance = SignatureInstance { params, public_key: key.y.clone(), message: "hello".to_string() };
+    let body = json!({ "instance": instance, "proof": key.sign(b"hello") }).to_string();
+    let limits = Limits::default();
+    assert!(verify_request("schnorr-signature", body.as_bytes(), &limits).unwrap().accept);
+
+    let forged = json!({ "instance": instance, "proof": key.sign(b"goodbye") }).to_string();
+    let verdict = verify_request("schnorr-signature", forged.as_bytes(), &limits).unwrap();
+    assert_eq!(verdict.reason, Some("Signature does not verify".to_string()));
+    assert!(matches!(verify_request("schnorr-signature", b"{}", &limits), Err(VerifyError::Malformed(_))));
+    assert!(matches!(verify_request("gi", body.as_bytes(), &limits), Err(VerifyError::UnknownProtocol(_))));
+}
+
+#[cfg(feature = "groth16")]
+#[test]
+fn test_verify_groth16_request() {
+    let (instance, proof) = crate::groth16::square_root_proof();
+    let body = json!({ "instance": instance, "proof": proof }).to_string();
+    assert!(verify_request("groth16-bn254", body.as_bytes(), &Limits::default()).unwrap().accept);
+    let wrong = Groth16Instance { public_inputs: vec!["4".to_string()], ..instance };
+    let body = json!({ "instance": wrong, "proof": proof }).to_string();
+    let verdict = verify_request("groth16-bn254", body.as_bytes(), &Limits::default()).unwrap();
+    assert_eq!(verdict.reason, Some("Proof does not verify".to_string()));
+}
+
+#[test]
+fn test_verify_committed_request() {
+    let params = PedersenParameters::generate(64);
+    let (commitment, opening) = params.commit(&BigUint::from(5u32));
+    let mut session = CommitAndProve::new(&params, &commitment, b"session");
+    let proofs = vec![session.prove(&OpeningProof, &opening, &()), session.prove(&RangeProof { bits: 3 }, &opening, &())];
+    let statements = vec![CommittedStatement::Opening, CommittedStatement::Range { bits: 3 }];
+    let instance = CommittedInstance { params, commitment, session_id: "session".to_string(), statements };
+    let body = json!({ "instance": instance, "proof": proofs }).to_string();
+    assert!(verify_request("commit-and-prove", body.as_bytes(), &Limits::default()).unwrap().accept);
+
+    // Proofs only verify in the order they were proven
+    let reordered = CommittedInstance { statements: vec![CommittedStatement::Range { bits: 3 }, CommittedStatement::Opening], ..instance };
+    let proofs: Vec<NonInteractiveProof> = proofs.into_iter().rev().collect();
+    let body = json!({ "instance": reordered, "proof": proofs }).to_string();
+    assert!(!verify_request("commit-and-prove", body.as_bytes(), &Limits::default()).unwrap().accept);
+}
+
+#[test]
+fn test_request_budgets() {
+    let params = SchnorrParameters::generate(64);
+    let key = SchnorrKeyPair::generate(&params);
+    let instance = SignatureInstance { params, public_key: key.y.clone(), message: "hello".to_string() };
+    let mut signature = key.sign(b"hello");
+    let over_budget = |signature: &SchnorrSignature, limits: &Limits| {
+        let body = json!({ "instance": instance, "proof": signature }).to_string();
+        matches!(verify_request("schnorr-signature", body.as_bytes(), limits), Err(VerifyError::OverBudget(_)))
+    };
+    assert!(!over_budget(&signature, &Limits::default()));
+    assert!(over_budget(&signature, &Limits { max_modulus_bits: 32, ..Limits::default() }));
+    assert!(over_budget(&signature, &Limits { max_body_bytes: 100, ..Limits::default() }));
+    // A response far larger than the group would make verification slow rather than fail
+    signature.s <<= 10000;
+    assert!(over_budget(&signature, &Limits::default()));
+
+    let params = PedersenParameters::generate(64);
+    let (commitment, _) = params.commit(&BigUint::from(5u32));
+    let instance = CommittedInstance { params, commitment, session_id: "session".to_string(), statements: vec![CommittedStatement::Range { bits: 40 }] };
+    let body = json!({ "instance": instance, "proof": [] }).to_string();
+    let limits = Limits { max_range_bits: 32, ..Limits::default() };
+    assert!(matches!(verify_request("commit-and-prove", body.as_bytes(), &limits), Err(VerifyError::OverBudget(_))));
+
+    // A dense graph can take far more memory than its vertex count suggests
+    let complete = Graph::new(100, (0..100).flat_map(|a| (0..100).map(move |b| (a, b))).collect());
+    let request = SessionRequest { instance: GraphPair { g0: complete.clone(), g1: complete }, repetitions: 1 };
+    assert!(Limits::default().check_session(&request, 0).is_ok());
+    let limits = Limits { max_instance_bytes: 100_000, ..Limits::default() };
+    assert!(matches!(limits.check_session(&request, 0), Err(VerifyError::OverBudget(_))));
+}
+
+#[test]
+fn test_verification_server() {
+    let params = SchnorrParameters::generate(64);
+    let (prover, verifier) = (SchnorrKeyPair::generate(&params), SchnorrKeyPair::generate(&params));
+    let proof = prove_designated(&prover, &verifier.y, b"session");
+    let instance = DesignatedInstance { params, prover_key: prover.y.clone(), verifier_key: verifier.y.clone(), session_id: "session".to_string() };
+    let body = json!({ "instance": instance, "proof": proof }).to_string();
+
+    let log = std::env::temp_dir().join(format!("zklib-audit-{}.jsonl", rand::random::<u64>()));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server_with(&runtime, Limits::default(), SessionStore::in_memory(Duration::from_secs(60)), Some(AuditLog::open(&log).unwrap()), None);
+    let (status, verdict) = post_json(addr, "/verify/designated", &body);
+    assert_eq!(status, 200);
+    assert_eq!(verdict["accept"], true);
+    let (status, error) = post_json(addr, "/verify/designated", "not json");
+    assert_eq!(status, 400);
+    assert!(error["error"].as_str().unwrap().starts_with("Malformed request"));
+    assert_eq!(post_json(addr, "/verify/unknown", &body).0, 404);
+
+    let (status, metrics) = send_request(addr, "GET", "/metrics", "");
+    assert_eq!(status, 200);
+    assert!(metrics.contains("zklib_verdicts_total{protocol=\"designated\",verdict=\"accept\"} 1"));
+    assert!(metrics.contains("zklib_requests_total{endpoint=\"verify\",status=\"400\"} 1"));
+    assert!(metrics.contains("zklib_round_latency_seconds_count{protocol=\"unknown\"} 1"));
+    assert!(metrics.contains("zklib_verification_cpu_seconds_count{protocol=\"designated\"} 2"));
+    assert!(metrics.contains(&format!("zklib_bytes_received_total{{endpoint=\"verify\"}} {}", 2 * body.len() + "not json".len())));
+
+    // Only the verdict is logged, identified by digests of the instance and proof
+    let entries: Vec<AuditEntry> = std::fs::read_to_string(&log).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].statement, to_hex(&ProofContext::hash_statement(json!(instance).to_string().as_bytes())));
+    std::fs::remove_file(&log).unwrap();
+}
+
+#[test]
+fn test_server_limits() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server(&runtime, Limits { max_body_bytes: 64, requests_per_second: 0.001, burst: 2, ..Limits::default() });
+    // Oversized bodies are refused before they are read
+    assert_eq!(post_json(addr, "/verify/designated", &"x".repeat(65)).0, 413);
+    assert_eq!(post_json(addr, "/verify/designated", "{}").0, 400);
+    assert_eq!(post_json(addr, "/verify/designated", "{}").0, 400);
+    let (status, error) = post_json(addr, "/verify/designated", "{}");
+    assert_eq!(status, 429);
+    assert!(error["error"].as_str().unwrap().starts_with("Too many requests"));
+}
+
+#[test]
+fn test_tracked_clients() {
+    // Every client spends its only token, so none is forgotten for having a full bucket
+    let limits = Limits { requests_per_second: 0.001, burst: 1, ..Limits::default() };
+    let state = ServerState::new(limits.clone(), SessionStore::in_memory(Duration::from_secs(60)), None, None, StatementRegistry::in_memory(), Shutdown::default());
+    let client = |i: usize| IpAddr::from([10, 0, (i >> 8) as u8, i as u8]);
+    (0..MAX_TRACKED_CLIENTS).for_each(|i| drop(state.admit(client(i)).unwrap()));
+    // A new client makes room by forgetting the clients idle longest, down to half as many
+    drop(state.admit(client(MAX_TRACKED_CLIENTS)).unwrap());
+    assert_eq!(lock(&state.clients).len(), MAX_TRACKED_CLIENTS / 2 + 1);
+    assert!(state.admit(client(0)).is_ok());
+    assert!(state.admit(client(MAX_TRACKED_CLIENTS - 1)).err().unwrap().starts_with("Too many requests"));
+
+    // While every tracked client has a request in flight, new clients are refused instead of tracked
+    let state = ServerState::new(Limits { burst: 2, ..limits }, SessionStore::in_memory(Duration::from_secs(60)), None, None, StatementRegistry::in_memory(), Shutdown::default());
+    let permits: Vec<Permit> = (0..MAX_TRACKED_CLIENTS).map(|i| state.admit(client(i)).unwrap()).collect();
+    assert!(state.admit(client(MAX_TRACKED_CLIENTS)).err().unwrap().starts_with("Too many clients"));
+    drop(permits);
+    assert!(state.admit(client(MAX_TRACKED_CLIENTS)).is_ok());
+}
+
+#[test]
+fn test_server_protocols() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server(&runtime, Limits { protocols: Some(vec!["designated".to_string()]), ..Limits::default() });
+    assert_eq!(post_json(addr, "/verify/designated", "{}").0, 400);
+    let (status, error) = post_json(addr, "/verify/schnorr-signature", "{}");
+    assert_eq!(status, 404);
+    assert_eq!(error["error"], "Protocol schnorr-signature is not enabled on this server");
+    assert_eq!(post_json(addr, "/sessions/gi", &json!({ "repetitions": 1 }).to_string()).0, 404);
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn test_server_over_tls() {
+    use std::convert::TryFrom;
+    use std::io::{Read, Write};
+    use rustls::pki_types::{CertificateDer, ServerName, pem::PemObject};
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/tls");
+    let tls = TlsPaths { cert: dir.join("cert.pem"), key: dir.join("key.pem") };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    runtime.spawn(async move { serve_tls(listener, &tls, Limits::default(), SessionStore::in_memory(Duration::from_secs(60)), None, None, StatementRegistry::in_memory()).await });
+
+    // A client trusting the test CA gets its request answered
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(dir.join("ca.pem")).unwrap()).unwrap();
+    let config = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let connection = rustls::ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap()).unwrap();
+    let mut stream = rustls::StreamOwned::new(connection, std::net::TcpStream::connect(addr).unwrap());
+    write!(stream, "POST /verify/designated HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}").unwrap();
+    let mut response = String::new();
+    // The server may close the connection without a TLS close_notify, which rustls reports as an error
+    let _ = stream.read_to_string(&mut response);
+    assert!(response.starts_with("HTTP/1.1 400"));
+
+    // Plain HTTP to the same port gets no HTTP response
+    let mut plain = std::net::TcpStream::connect(addr).unwrap();
+    write!(plain, "GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let _ = plain.read_to_end(&mut response);
+    assert!(!response.starts_with(b"HTTP/1.1"));
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let request = json!({ "instance": instance, "repetitions": 1 }).to_string();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let start = |drain_timeout: Duration| {
+        let (listener, health) = runtime.block_on(async { (TcpListener::bind("127.0.0.1:0").await.unwrap(), TcpListener::bind("127.0.0.1:0").await.unwrap()) });
+        let (addr, health_addr) = (listener.local_addr().unwrap(), health.local_addr().unwrap());
+        let shutdown = Shutdown::new(drain_timeout);
+        runtime.spawn(serve_health(health, shutdown.clone()));
+        let stopping = shutdown.clone();
+        let server = runtime.spawn(async move {
+            serve_until(Listener::Http(listener), &stopping, Limits::default(), SessionStore::in_memory(Duration::from_secs(60)), None, None, StatementRegistry::in_memory()).await
+        });
+        (addr, health_addr, shutdown, server)
+    };
+    let (addr, health, shutdown, server) = start(Duration::from_secs(30));
+    assert_eq!(get_json(health, "/healthz"), (200, json!({ "status": "alive" })));
+    assert_eq!(get_json(health, "/readyz"), (200, json!({ "status": "ready" })));
+    let (status, created) = post_json(addr, "/sessions/gi", &request);
+    assert_eq!(status, 200);
+
+    // Once shutdown begins the server stops taking sessions, but finishes the one under way
+    shutdown.begin();
+    assert_eq!(get_json(health, "/readyz"), (503, json!({ "status": "draining" })));
+    assert_eq!(get_json(health, "/healthz").0, 200);
+    assert_eq!(post_json(addr, "/sessions/gi", &request).0, 503);
+    let id = created["session"].as_str().unwrap();
+    let mut prover = GIProver::new(&instance);
+    let mut reply = created.clone();
+    while reply["verdict"].is_null() {
+        let (msg, _) = prover.handle(&serde_json::from_value(reply["message"].clone()).unwrap());
+        reply = post_json(addr, &format!("/sessions/gi/{}", id), &serde_json::to_string(&msg).unwrap()).1;
+    }
+    assert_eq!(reply["verdict"], true);
+    let stopped = runtime.block_on(async { tokio::time::timeout(Duration::from_secs(5), server).await });
+    assert!(matches!(stopped, Ok(Ok(Ok(())))));
+    assert!(std::net::TcpStream::connect(addr).is_err());
+
+    // A session that never finishes only holds the server up for the drain timeout
+    let (addr, _, shutdown, server) = start(Duration::from_millis(300));
+    assert_eq!(post_json(addr, "/sessions/gi", &request).0, 200);
+    shutdown.begin();
+    let stopped = runtime.block_on(async { tokio::time::timeout(Duration::from_secs(5), server).await });
+    assert!(matches!(stopped, Ok(Ok(Ok(())))));
+}
+
+#[test]
+fn test_sessions_survive_restart() {
+    let dir = std::env::temp_dir().join(format!("zklib-server-{}", rand::random::<u64>()));
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let mut prover = GIProver::new(&instance);
+    let mut send = |addr: SocketAddr, id: &str, reply: &Value| {
+        let reply: GIVerifierMessage = serde_json::from_value(reply["message"].clone()).unwrap();
+        let (msg, _) = prover.handle(&reply);
+        if prover.r == 2 {
+            prover.r = 0;
+        }
+        post_json(addr, &format!("/sessions/gi/{}", id), &serde_json::to_string(&msg).unwrap())
+    };
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server_with(&runtime, Limits::default(), SessionStore::open(&dir, Duration::from_secs(60)).unwrap(), Some(AuditLog::open(&dir.join("audit.jsonl")).unwrap()), None);
+    let (status, created) = post_json(addr, "/sessions/gi", &json!({ "instance": instance, "repetitions": 2 }).to_string());
+    assert_eq!(status, 200);
+    let id = created["session"].as_str().unwrap().to_string();
+    let (_, mut reply) = send(addr, &id, &created);
+    reply = send(addr, &id, &reply).1;
+    assert_eq!(reply["accepted"], 1);
+    drop(runtime);
+
+    // A new server over the same directory finishes the proof
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server_with(&runtime, Limits::default(), SessionStore::open(&dir, Duration::from_secs(60)).unwrap(), Some(AuditLog::open(&dir.join("audit.jsonl")).unwrap()), None);
+    reply = send(addr, &id, &reply).1;
+    let (status, reply) = send(addr, &id, &reply);
+    assert_eq!(status, 200);
+    assert_eq!(reply["verdict"], true);
+    assert_eq!(get_json(addr, &format!("/sessions/gi/{}", id)).1["verdict"], true);
+    assert_eq!(post_json(addr, &format!("/sessions/gi/{}", id), "\"Done\"").0, 409);
+    // An escrow is attached once, and only if it was sealed for the session
+    let auditor = crate::keys::ElGamalKeyPair::generate(&SchnorrParameters::generate(64));
+    let context = crate::escrow::escrow_context(&instance.digest(), &id);
+    let escrow = crate::escrow::Escrow::seal(&auditor.public_key(), &context, &[]);
+    let (status, reply) = post_json(addr, &format!("/sessions/gi/{}/escrow", id), &serde_json::to_string(&escrow).unwrap());
+    assert_eq!((status, reply["escrowed"].as_bool()), (200, Some(true)));
+    assert_eq!(post_json(addr, &format!("/sessions/gi/{}/escrow", id), &serde_json::to_string(&escrow).unwrap()).0, 409);
+    assert_eq!(get_json(addr, "/sessions/gi/unknown").0, 404);
+    let too_many = json!({ "instance": instance, "repetitions": 1000 }).to_string();
+    assert_eq!(post_json(addr, "/sessions/gi", &too_many).0, 422);
+
+    // The verdict was logged once, by the server that reached it
+    let summary = crate::audit::verify_log(&dir.join("audit.jsonl")).unwrap();
+    assert_eq!((summary.entries, summary.accepted), (1, 1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_server_handshake() {
+    let params = SchnorrParameters::generate(64);
+    let key = SchnorrKeyPair::generate(&params);
+    let token = Credential::Token(to_hex(&Sha256Hasher::hash(&[b"secret token"])));
+    let handshake = Handshake::new(vec![
+        Identity { name: "auditor".to_string(), credential: token, policy: Policy { protocols: Some(vec!["schnorr-signature".to_string()]), max_instance_bytes: None } },
+        Identity { name: "prover".to_string(), credential: Credential::Key(key.public_key()), policy: Policy { protocols: None, max_instance_bytes: Some(100_000) } },
+    ]);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = spawn_server_with(&runtime, Limits::default(), SessionStore::in_memory(Duration::from_secs(60)), None, Some(handshake));
+    let signer = SchnorrKeyPair::generate(&params);
+    let instance = SignatureInstance { params: params.clone(), public_key: signer.y.clone(), message: "hello".to_string() };
+    let body = json!({ "instance": instance, "proof": signer.sign(b"hello") }).to_string();
+
+    assert_eq!(post_json(addr, "/verify/schnorr-signature", &body).0, 401);
+    assert_eq!(send_authorized(addr, "POST", "/verify/schnorr-signature", Some("Bearer wrong token"), &body).0, 401);
+    let (status, verdict) = send_authorized(addr, "POST", "/verify/schnorr-signature", Some("Bearer secret token"), &body);
+    assert_eq!(status, 200);
+    assert!(verdict.contains("\"accept\":true"));
+    assert_eq!(send_authorized(addr, "POST", "/verify/designated", Some("Bearer secret token"), "{}").0, 403);
+
+    // The prover signs a challenge for each request
+    let sign_in = || {
+        let (status, challenge) = post_json(addr, "/sessions/challenge", &json!({ "identity": "prover" }).to_string());
+        assert_eq!(status, 200);
+        let nonce = challenge["nonce"].as_str().unwrap().to_string();
+        signature_header("prover", &nonce, &key.sign(&challenge_message(&nonce)))
+    };
+    let small = GraphPair { g0: Graph::new(4, vec![(0, 1)]), g1: Graph::new(4, vec![(2, 3)]) };
+    let header = sign_in();
+    let request = json!({ "instance": small, "repetitions": 1 }).to_string();
+    let (status, created) = send_authorized(addr, "POST", "/sessions/gi", Some(&header), &request);
+    assert_eq!(status, 200);
+    // A signature is only good for one request
+    assert_eq!(send_authorized(addr, "POST", "/sessions/gi", Some(&header), &request).0, 401);
+    // Clients are authenticated before the server reads their requests
+    assert_eq!(send_authorized(addr, "POST", "/sessions/gi", None, "not json").0, 401);
+    assert_eq!(send_authorized(addr, "POST", "/sessions/gi", Some(&sign_in()), "not json").0, 400);
+
+    // Only the prover that started the session can attach an escrow to it
+    let id = serde_json::from_str::<Value>(&created).unwrap()["session"].as_str().unwrap().to_string();
+    let auditor = crate::keys::ElGamalKeyPair::generate(&params);
+    let escrow = serde_json::to_string(&crate::escrow::Escrow::seal(&auditor.public_key(), &crate::escrow::escrow_context(&small.digest(), &id), &[])).unwrap();
+    let path = format!("/sessions/gi/{}/escrow", id);
+    assert_eq!(post_json(addr, &path, &escrow).0, 401);
+    assert_eq!(send_authorized(addr, "POST", &path, Some("Bearer secret token"), &escrow).0, 403);
+    assert_eq!(send_authorized(addr, "POST", &path, Some(&sign_in()), &escrow).0, 200);
+    let complete = Graph::new(100, (0..100).flat_map(|a| (0..100).map(move |b| (a, b))).collect());
+    let large = json!({ "instance": GraphPair { g0: complete.clone(), g1: complete }, "repetitions": 1 }).to_string();
+    assert_eq!(send_authorized(addr, "POST", "/sessions/gi", Some(&sign_in()), &large).0, 403);
+    assert_eq!(post_json(addr, "/sessions/challenge", &json!({ "identity": "auditor" }).to_string()).0, 401);
+}
+
+#[test]
+fn test_server_puzzles() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let limits = Limits { work_bits: 8, ..Limits::default() };
+    let addr = spawn_server_with(&runtime, limits, SessionStore::in_memory(Duration::from_secs(60)), None, None);
+    let instance = GraphPair { g0: Graph::new(4, vec![(0, 1)]), g1: Graph::new(4, vec![(2, 3)]) };
+    let request = json!({ "instance": instance, "repetitions": 1 }).to_string();
+    assert_eq!(post_json(addr, "/sessions/gi", &request).0, 428);
+
+    let (status, puzzle) = post_json(addr, "/sessions/puzzle", "");
+    assert_eq!(status, 200);
+    assert_eq!(puzzle["bits"], 8);
+    let nonce = puzzle["nonce"].as_str().unwrap();
+    let work = work_header(nonce, solve_puzzle(nonce, 8));
+    assert_eq!(send_with_headers(addr, "POST", "/sessions/gi", &[("X-Zklib-Work", &work)], &request).0, 200);
+    // Each solution starts one session
+    assert_eq!(send_with_headers(addr, "POST", "/sessions/gi", &[("X-Zklib-Work", &work)], &request).0, 428);
+
+    // Without a puzzle to solve, sessions start freely and no puzzles are issued
+    let addr = spawn_server(&runtime, Limits::default());
+    assert_eq!(post_json(addr, "/sessions/puzzle", "").0, 404);
+    assert_eq!(post_json(addr, "/sessions/gi", &request).0, 200);
+}
+
+#[test]
+fn test_statement_registry() {
+    let dir = std::env::temp_dir().join(format!("zklib-server-registry-{}", rand::random::<u64>()));
+    let instance = GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let limits = Limits { max_registered: 2, ..Limits::default() };
+    let token = Credential::Token(to_hex(&Sha256Hasher::hash(&[b"secret token"])));
+    let handshake = Handshake::new(vec![Identity { name: "registrar".to_string(), credential: token, policy: Policy::default() }]);
+    let mut registry = StatementRegistry::open(&dir).unwrap();
+    registry.refresh_interval = Duration::ZERO;
+    let addr = spawn_server_with_registry(&runtime, limits.clone(), SessionStore::in_memory(Duration::from_secs(60)), None, Some(handshake), registry);
+    let register = |name: &str, instance: &GraphPair| {
+        let (status, body) = send_authorized(addr, "POST", "/statements", Some("Bearer secret token"), &json!({ "name": name, "instance": instance }).to_string());
+        (status, serde_json::from_str::<Value>(&body).unwrap_or(Value::Null))
+    };
+    // Registering takes credentials, which every other request here can do without
+    assert_eq!(post_json(addr, "/statements", &json!({ "name": "example", "instance": instance }).to_string()).0, 401);
+    let (status, registered) = register("example", &instance);
+    assert_eq!(status, 200);
+    assert_eq!(registered["digest"], to_hex(&instance.digest()));
+    let filler = GraphPair { g0: Graph::new(3, vec![(0, 1)]), g1: Graph::new(3, vec![(1, 0)]) };
+    assert_eq!(register("filler", &filler).0, 200);
+    let other = GraphPair { g0: Graph::new(2, vec![(0, 1)]), g1: Graph::new(2, vec![(1, 0)]) };
+    assert_eq!(register("other", &other).0, 422);
+    let (status, listed) = get_json(addr, "/statements");
+    assert_eq!(status, 200);
+    assert_eq!(listed["statements"][0]["name"], "example");
+    let (status, fetched) = get_json(addr, &format!("/statements/{}", registered["digest"].as_str().unwrap()));
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_value::<GraphPair>(fetched["instance"].clone()).unwrap(), instance);
+    assert_eq!(get_json(addr, "/statements/unknown").0, 404);
+
+    // A session names the statement instead of uploading it, and a statement registered in the directory
+    // by another process is found when asked for
+    let session = |body: Value| {
+        let (status, body) = send_authorized(addr, "POST", "/sessions/gi", Some("Bearer secret token"), &body.to_string());
+        (status, serde_json::from_str::<Value>(&body).unwrap_or(Value::Null))
+    };
+    let (status, created) = session(json!({ "statement": "example", "repetitions": 1 }));
+    assert_eq!(status, 200);
+    assert_eq!(created["repetitions"], 1);
+    // The server stops loading statements from the directory at its limit
+    let mut outside = StatementRegistry::open(&dir).unwrap();
+    outside.register("other", other).unwrap();
+    assert_eq!(session(json!({ "statement": "other", "repetitions": 1 })).0, 404);
+    std::fs::remove_file(dir.join(format!("{}.json", to_hex(&filler.digest())))).unwrap();
+    let addr = spawn_server_with_registry(&runtime, limits, SessionStore::in_memory(Duration::from_secs(60)), None, None, StatementRegistry::open(&dir).unwrap());
+    assert_eq!(get_json(addr, "/statements").1["statements"].as_array().unwrap().len(), 2);
+    assert_eq!(post_json(addr, "/sessions/gi", &json!({ "statement": "other", "repetitions": 1 }).to_string()).0, 200);
+    assert_eq!(post_json(addr, "/sessions/gi", &json!({ "statement": "missing", "repetitions": 1 }).to_string()).0, 404);
+    assert_eq!(post_json(addr, "/sessions/gi", &json!({ "repetitions": 1 }).to_string()).0, 400);
+    // Without identities, the server doesn't register statements at all
+    assert_eq!(post_json(addr, "/statements", &json!({ "name": "example", "instance": instance }).to_string()).0, 403);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,504 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::{ValidationError, Verifier};
+use crate::audit::to_hex;
+use crate::beacon::PublicCoinVerifier;
+use crate::escrow::{escrow_context, Annotation, Escrow};
+use crate::graph::{GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair, RefinedGIVerifier, RefinementCache};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+#[cfg(test)]
+use rand::thread_rng;
+#[cfg(test)]
+use crate::graph::GIProver;
+#[cfg(test)]
+use crate::{Prover, ResumableProver};
+#[cfg(test)]
+use crate::escrow::EscrowingProver;
+#[cfg(test)]
+use crate::keys::ElGamalKeyPair;
+#[cfg(test)]
+use crate::params::{Parameters, SchnorrParameters};
+
+// ************ Interactive session implementation ************
+
+// A session is one interactive proof between a remote prover and a verifier that lives on the server,
+// e.g. a GI proof repeated k times, which takes 2k prover messages. Rather than holding a verifier in
+// memory between messages, a session records every message exchanged. Since the GI verifier is public
+// coin, replaying the prover's messages of the repetition in progress with the challenges it was sent
+// restores the verifier exactly, so the transcript is all that needs to be kept to survive a restart.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionMessage {
+    // Repetition the message belongs to, counting from 0
+    pub repetition: u32,
+    pub prover: GIProverMessage,
+    pub verifier: GIVerifierMessage,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GISession {
+    pub id: String,
+    pub instance: GraphPair,
+    pub repetitions: u32,
+    // Repetitions the verifier has accepted so far
+    pub accepted: u32,
+    pub transcript: Vec<SessionMessage>,
+    // Set once the verifier accepts every repetition or rejects one
+    pub verdict: Option<bool>,
+    // Unix time after which the session is discarded, pushed back by every message
+    pub expires_at: u64,
+    // The prover's annotations, encrypted for an auditor, if it attached them
+    #[serde(default)]
+    pub escrow: Option<Escrow>,
+    // Identity of the client that started the session, if the server authenticates clients
+    #[serde(default)]
+    pub owner: Option<String>,
+    // Color refinement of the instance, computed on the first message and reused by every repetition.
+    // It is rebuilt rather than stored, since it follows from the instance.
+    #[serde(skip)]
+    refinement: OnceLock<RefinementCache>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionError {
+    UnknownSession(String),
+    Finished(String),
+    Invalid(ValidationError),
+    // The session's state could not be written to disk, so the message was not handled
+    Storage(String),
+    // An escrow was already attached, or was sealed for another session
+    Escrow(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownSession(id) => write!(f, "No session {}, it may have expired", id),
+            SessionError::Finished(id) => write!(f, "Session {} is already finished", id),
+            SessionError::Invalid(e) => write!(f, "{}", e),
+            SessionError::Storage(reason) => write!(f, "Failed to store session: {}", reason),
+            SessionError::Escrow(reason) => write!(f, "Cannot attach escrow: {}", reason),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs())
+}
+
+impl GISession {
+    pub fn new(instance: GraphPair, repetitions: u32, ttl: Duration) -> GISession {
+        let id = checked_thread_rng().gen::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+        GISession { id, instance, repetitions, accepted: 0, transcript: Vec::new(), verdict: None, expires_at: unix_time() + ttl.as_secs(), escrow: None,
+            owner: None, refinement: OnceLock::new() }
+    }
+
+    // Rebuilds the verifier of the repetition in progress by replaying its messages
+    fn verifier(&self) -> RefinedGIVerifier<'_> {
+        let inner = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &self.instance };
+        let mut verifier = RefinedGIVerifier { inner, refinement: self.refinement.get_or_init(|| RefinementCache::new(&self.instance)) };
+        for message in self.transcript.iter().filter(|m| m.repetition == self.accepted) {
+            let challenge = verifier.challenge_space().map(|_| message.verifier.b);
+            verifier.handle_with(&message.prover, challenge);
+        }
+        verifier
+    }
+
+    // The verifier's first message, which the prover answers with its first graph
+    pub fn init(&self) -> GIVerifierMessage {
+        self.verifier().init()
+    }
+
+    // Handles the prover's next message, returning the verifier's reply
+    pub fn handle(&mut self, msg: &GIProverMessage) -> Result<GIVerifierMessage, SessionError> {
+        if self.verdict.is_some() {
+            return Err(SessionError::Finished(self.id.clone()));
+        }
+        let (reply, accept, finished_repetition) = {
+            let mut verifier = self.verifier();
+            verifier.validate(msg).map_err(|reason| SessionError::Invalid(ValidationError::ProverMessage(verifier.inner.r + 1, reason)))?;
+            let (reply, accept) = verifier.handle(msg);
+            (reply, accept, verifier.inner.r == 2)
+        };
+        self.transcript.push(SessionMessage { repetition: self.accepted, prover: msg.clone(), verifier: reply.clone() });
+        if finished_repetition {
+            if accept {
+                self.accepted += 1;
+                if self.accepted == self.repetitions {
+                    self.verdict = Some(true);
+                }
+            } else {
+                self.verdict = Some(false);
+            }
+        }
+        Ok(reply)
+    }
+
+    // Context an escrow for this session must be sealed with
+    pub fn escrow_context(&self) -> [u8; 32] {
+        escrow_context(&self.instance.digest(), &self.id)
+    }
+
+    // Checks an auditor's decrypted annotations against the transcript: every graph the prover sent must
+    // come with an isomorphism mapping it onto g0, at the round the graph was sent on
+    pub fn check_annotations(&self, annotations: &[Annotation]) -> Result<(), String> {
+        for (i, message) in self.transcript.iter().enumerate() {
+            let round = i as u32 + 1;
+            if let GIProverMessage::Graph(graph) = &message.prover {
+                let annotation = annotations.iter().find(|x| x.round == round && x.label == "isomorphism to g0")
+                    .ok_or_else(|| format!("Round {} has no isomorphism to g0", round))?;
+                let isomorphism: Permutation = bincode::deserialize(&annotation.value).map_err(|e| format!("Round {}: malformed isomorphism: {}", round, e))?;
+                if isomorphism.n() != graph.n || graph.permute(&isomorphism) != self.instance.g0 {
+                    return Err(format!("Round {}: the prover's graph is not a copy of g0", round));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ************ Session store implementation ************
+
+// Sessions are kept in memory, and if the store has a directory, each one is also written to
+// <dir>/<id>.json after every message, so that a restarted server picks up where it left off. A file is
+// written to a temporary path and renamed over the old one, so a crash never leaves half a session.
+//
+// The store is shared by every request a server handles, so it locks each session on its own, and only
+// holds the lock on the map of sessions to look one up or add or remove one. A slow disk then delays the
+// messages of the session being written, rather than every request. A session is locked while its file
+// is written or deleted, so a message and the removal of its expired session can't race, and a removed
+// session is left empty for any request that looked it up before it was removed.
+pub struct SessionStore {
+    pub dir: Option<PathBuf>,
+    // How long a session lasts after its last message
+    pub ttl: Duration,
+    sessions: Mutex<Sessions>,
+}
+
+#[derive(Default)]
+struct Sessions {
+    by_id: HashMap<String, Arc<Mutex<Option<GISession>>>>,
+    // Sessions created or continued since the store was opened, rather than only loaded from the directory
+    touched: HashSet<String>,
+}
+
+// Locks the mutex even if a thread panicked while holding it. The session store and the server's other
+// state only change once an operation has succeeded, so a panic partway through leaves them consistent.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl SessionStore {
+    pub fn in_memory(ttl: Duration) -> SessionStore {
+        SessionStore { dir: None, ttl, sessions: Mutex::default() }
+    }
+
+    // Loads the sessions saved in the directory, creating it if needed and deleting expired sessions
+    pub fn open(dir: &Path, ttl: Duration) -> Result<SessionStore, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let mut sessions = Sessions::default();
+        let entries = fs::read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_some_and(|x| x == "json") {
+                let contents = fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                let session: GISession = serde_json::from_str(&contents).map_err(|e| format!("malformed session {}: {}", path.display(), e))?;
+                sessions.by_id.insert(session.id.clone(), Arc::new(Mutex::new(Some(session))));
+            }
+        }
+        let store = SessionStore { dir: Some(dir.to_path_buf()), ttl, sessions: Mutex::new(sessions) };
+        store.remove_expired();
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.sessions).by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Sessions touched since the store was opened that haven't reached a verdict or expired, which a server
+    // shutting down waits for. A session only loaded from the directory has no prover connected to this
+    // process, and is picked up again by the next one.
+    pub fn in_progress(&self) -> usize {
+        let now = unix_time();
+        let touched: Vec<_> = {
+            let sessions = lock(&self.sessions);
+            sessions.touched.iter().filter_map(|id| sessions.by_id.get(id).cloned()).collect()
+        };
+        touched.iter().filter(|slot| lock(slot).as_ref().is_some_and(|s| s.verdict.is_none() && s.expires_at > now)).count()
+    }
+
+    // A copy of the session as it is now
+    pub fn get(&self, id: &str) -> Option<GISession> {
+        let slot = lock(&self.sessions).by_id.get(id).cloned()?;
+        let session = lock(&slot).clone();
+        session
+    }
+
+    pub fn create(&self, instance: GraphPair, repetitions: u32, owner: Option<String>) -> Result<GISession, SessionError> {
+        let session = GISession { owner, ..GISession::new(instance, repetitions, self.ttl) };
+        self.save(&session)?;
+        let mut sessions = lock(&self.sessions);
+        sessions.touched.insert(session.id.clone());
+        sessions.by_id.insert(session.id.clone(), Arc::new(Mutex::new(Some(session.clone()))));
+        Ok(session)
+    }
+
+    // Hands the prover's message to the session and saves the result, leaving the session as it was if
+    // the message is invalid or can't be saved
+    pub fn handle(&self, id: &str, msg: &GIProverMessage) -> Result<(GIVerifierMessage, GISession), SessionError> {
+        self.handle_and(id, msg, |_| Ok(()))
+    }
+
+    // Like handle, but first passes the session with the message handled to commit, e.g. to log the
+    // verdict it reached, and leaves the session as it was if commit fails
+    pub fn handle_and<F>(&self, id: &str, msg: &GIProverMessage, commit: F) -> Result<(GIVerifierMessage, GISession), SessionError>
+    where F: FnOnce(&GISession) -> Result<(), String> {
+        self.update(id, true, |session| {
+            let reply = session.handle(msg)?;
+            session.expires_at = unix_time() + self.ttl.as_secs();
+            commit(session).map_err(SessionError::Storage)?;
+            Ok(reply)
+        })
+    }
+
+    // Attaches the prover's escrow, which can only be done once and must be sealed for the session
+    pub fn attach_escrow(&self, id: &str, escrow: Escrow) -> Result<GISession, SessionError> {
+        self.update(id, false, |session| {
+            if session.escrow.is_some() {
+                return Err(SessionError::Escrow("the session already has one".to_string()));
+            }
+            if escrow.context != to_hex(&session.escrow_context()) {
+                return Err(SessionError::Escrow("it was sealed for another session".to_string()));
+            }
+            session.escrow = Some(escrow);
+            Ok(())
+        }).map(|(_, session)| session)
+    }
+
+    // Changes a copy of the session and saves it, keeping the change only if both succeed
+    fn update<T, F>(&self, id: &str, touch: bool, change: F) -> Result<(T, GISession), SessionError>
+    where F: FnOnce(&mut GISession) -> Result<T, SessionError> {
+        let slot = {
+            let mut sessions = lock(&self.sessions);
+            let slot = sessions.by_id.get(id).cloned().ok_or_else(|| SessionError::UnknownSession(id.to_string()))?;
+            if touch {
+                sessions.touched.insert(id.to_string());
+            }
+            slot
+        };
+        let mut current = lock(&slot);
+        let mut session = current.clone().ok_or_else(|| SessionError::UnknownSession(id.to_string()))?;
+        let result = change(&mut session)?;
+        self.save(&session)?;
+        *current = Some(session.clone());
+        Ok((result, session))
+    }
+
+    // Deletes every expired session, returning how many there were
+    pub fn remove_expired(&self) -> usize {
+        let now = unix_time();
+        let slots: Vec<_> = lock(&self.sessions).by_id.iter().map(|(id, slot)| (id.clone(), slot.clone())).collect();
+        let mut expired = Vec::new();
+        for (id, slot) in slots {
+            // A message may have extended the session since the map was read
+            let mut session = lock(&slot);
+            if session.as_ref().is_some_and(|s| s.expires_at <= now) {
+                *session = None;
+                if let Some(path) = self.path(&id) {
+                    let _ = fs::remove_file(path);
+                }
+                expired.push(id);
+            }
+        }
+        let mut sessions = lock(&self.sessions);
+        for id in &expired {
+            sessions.by_id.remove(id);
+            sessions.touched.remove(id);
+        }
+        expired.len()
+    }
+
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.json", id)))
+    }
+
+    fn save(&self, session: &GISession) -> Result<(), SessionError> {
+        if let Some(path) = self.path(&session.id) {
+            let temporary = path.with_extension("json.tmp");
+            fs::write(&temporary, serde_json::to_string(session).unwrap())
+                .and_then(|_| fs::rename(&temporary, &path))
+                .map_err(|e| SessionError::Storage(format!("{}: {}", path.display(), e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_instance() -> GraphPair {
+    GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    }
+}
+
+#[test]
+fn test_gi_session() {
+    let instance = test_instance();
+    let mut session = GISession::new(instance.clone(), 3, Duration::from_secs(60));
+    let mut prover = GIProver::new(&instance);
+    for repetition in 0..3 {
+        assert_eq!(session.accepted, repetition);
+        let mut reply = GIVerifierMessage { b: false };
+        for _ in 0..2 {
+            reply = session.handle(&prover.handle(&reply).0).unwrap();
+        }
+        prover.r = 0;
+    }
+    assert_eq!(session.verdict, Some(true));
+    assert_eq!(session.transcript.len(), 6);
+    assert!(matches!(session.handle(&GIProverMessage::Done), Err(SessionError::Finished(_))));
+
+    // Messages that don't fit the round are refused without changing the session
+    let mut session = GISession::new(instance.clone(), 1, Duration::from_secs(60));
+    assert!(matches!(session.handle(&GIProverMessage::Done), Err(SessionError::Invalid(_))));
+    // So are graphs that color refinement tells apart from g0, before the verifier sends a challenge
+    assert!(matches!(session.handle(&GIProverMessage::Graph(Graph::new(4, vec![(0, 1)]))), Err(SessionError::Invalid(_))));
+    assert!(session.transcript.is_empty());
+}
+
+#[test]
+fn test_prover_resume() {
+    let dir = std::env::temp_dir().join(format!("zklib-sessions-{}", thread_rng().gen::<u64>()));
+    let instance = test_instance();
+    let store = SessionStore::open(&dir, Duration::from_secs(60)).unwrap();
+    let id = store.create(instance.clone(), 2, None).unwrap().id.clone();
+    let mut prover = GIProver::new(&instance);
+    let (reply, _) = store.handle(&id, &prover.handle(&GIVerifierMessage { b: false }).0).unwrap();
+
+    // Both processes stop partway through the first repetition, and the prover's state is saved on its side
+    let saved = serde_json::to_string(&prover.snapshot()).unwrap();
+    drop((prover, store));
+    let store = SessionStore::open(&dir, Duration::from_secs(60)).unwrap();
+    // The reloaded session only counts as in progress for the new process once the prover comes back to it
+    assert_eq!(store.in_progress(), 0);
+    let mut prover = GIProver::new(&instance);
+    prover.restore(serde_json::from_str(&saved).unwrap()).unwrap();
+    let (mut reply, _) = store.handle(&id, &prover.handle(&reply).0).unwrap();
+    assert_eq!(store.in_progress(), 1);
+    // The second repetition is played by a fresh prover
+    let mut prover = GIProver::new(&instance);
+    for _ in 0..2 {
+        reply = store.handle(&id, &prover.handle(&reply).0).unwrap().0;
+    }
+    assert_eq!(store.get(&id).unwrap().verdict, Some(true));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_prover_restore_checks_instance() {
+    let instance = test_instance();
+    let mut prover = GIProver::new(&instance);
+    prover.handle(&GIVerifierMessage { b: false });
+    let state = prover.snapshot();
+
+    // A snapshot only restores into a prover for the instance it was taken on
+    let other = GraphPair { g0: instance.g1.clone(), g1: instance.g0.clone() };
+    let mut stranger = GIProver::new(&other);
+    assert_eq!(stranger.restore(state.clone()), Err("Snapshot was taken on another instance".to_string()));
+    assert_eq!(stranger.r, 0);
+    // Nor can its graph be swapped for one the prover can't answer for
+    let mut tampered = state.clone();
+    tampered.random_perm = instance.g1.clone();
+    assert!(GIProver::new(&instance).restore(tampered).is_err());
+    let mut restored = GIProver::new(&instance);
+    assert_eq!(restored.restore(state), Ok(()));
+    assert_eq!(restored.random_perm, prover.random_perm);
+}
+
+#[test]
+fn test_session_store_persistence() {
+    let dir = std::env::temp_dir().join(format!("zklib-sessions-{}", thread_rng().gen::<u64>()));
+    let instance = test_instance();
+    let mut prover = GIProver::new(&instance);
+    let store = SessionStore::open(&dir, Duration::from_secs(60)).unwrap();
+    let id = store.create(instance.clone(), 1, None).unwrap().id.clone();
+    let (reply, _) = store.handle(&id, &prover.handle(&GIVerifierMessage { b: false }).0).unwrap();
+
+    // A new store over the same directory continues the proof from the saved transcript
+    drop(store);
+    let store = SessionStore::open(&dir, Duration::from_secs(60)).unwrap();
+    let last = prover.handle(&reply).0;
+    // A verdict that can't be committed, e.g. to an audit log, leaves the session where it was
+    assert!(matches!(store.handle_and(&id, &last, |_| Err("log is full".to_string())), Err(SessionError::Storage(_))));
+    assert_eq!(SessionStore::open(&dir, Duration::from_secs(60)).unwrap().get(&id).unwrap().verdict, None);
+    let (_, session) = store.handle(&id, &last).unwrap();
+    assert_eq!(session.verdict, Some(true));
+
+    // Expired sessions are deleted from memory and from disk
+    store.update(&id, false, |session| { session.expires_at = 0; Ok(()) }).unwrap();
+    assert_eq!(store.remove_expired(), 1);
+    assert!(SessionStore::open(&dir, Duration::from_secs(60)).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_session_store_survives_panics() {
+    // A request that panics partway through a message poisons the session's lock, but leaves the session
+    // as it was for the next request
+    let instance = test_instance();
+    let store = SessionStore::in_memory(Duration::from_secs(60));
+    let id = store.create(instance.clone(), 1, None).unwrap().id;
+    let msg = GIProver::new(&instance).handle(&GIVerifierMessage { b: false }).0;
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| store.handle_and(&id, &msg, |_| panic!("Commit failed"))));
+    assert!(panicked.is_err());
+    assert!(store.get(&id).unwrap().transcript.is_empty());
+    assert_eq!(store.handle(&id, &msg).unwrap().1.transcript.len(), 1);
+}
+
+#[test]
+fn test_session_escrow() {
+    let params = SchnorrParameters::generate(64);
+    let auditor = ElGamalKeyPair::generate(&params);
+    let instance = test_instance();
+    let store = SessionStore::in_memory(Duration::from_secs(60));
+    let id = store.create(instance.clone(), 2, None).unwrap().id.clone();
+    let mut prover = GIProver::new(&instance);
+    let mut escrowing = EscrowingProver::new(&mut prover);
+    let mut reply = store.get(&id).unwrap().init();
+    for _ in 0..2 {
+        for _ in 0..2 {
+            reply = store.handle(&id, &escrowing.handle(&reply).0).unwrap().0;
+        }
+        escrowing.inner.r = 0;
+    }
+    let session = store.get(&id).unwrap();
+    assert_eq!(session.verdict, Some(true));
+    let escrow = escrowing.seal(&auditor.public_key(), &session.escrow_context());
+
+    // Only an escrow for this session is attached, and only once
+    let mut other = escrow.clone();
+    other.context = to_hex(&[0; 32]);
+    assert!(matches!(store.attach_escrow(&id, other), Err(SessionError::Escrow(_))));
+    store.attach_escrow(&id, escrow.clone()).unwrap();
+    assert!(matches!(store.attach_escrow(&id, escrow), Err(SessionError::Escrow(_))));
+
+    // The auditor checks that every graph the prover sent was a copy of g0
+    let session = store.get(&id).unwrap();
+    let annotations = session.escrow.as_ref().unwrap().open(&auditor).unwrap();
+    assert_eq!(annotations.iter().map(|x| x.round).collect::<Vec<_>>(), [1, 3]);
+    assert_eq!(session.check_annotations(&annotations), Ok(()));
+    assert!(session.check_annotations(&annotations[..1]).is_err());
+    let malformed = [annotations[0].clone(), Annotation { value: vec![1, 2, 3], ..annotations[1].clone() }];
+    assert!(session.check_annotations(&malformed).is_err());
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::config;
+use crate::io::Format;
+use crate::transcript::ProofContext;
+#[cfg(test)]
+use crate::graph::{Graph, GraphPair};
+#[cfg(test)]
+use crate::okamoto::OkamotoInstance;
+#[cfg(test)]
+use crate::params::{Parameters, SchnorrParameters};
+#[cfg(test)]
+use crate::permutation::Permutation;
+#[cfg(test)]
+use crate::schnorr::SchnorrKeyPair;
+#[cfg(test)]
+use crate::secret::Secret;
+
+// ************ Statement and witness implementation ************
+
+// A statement is the public claim a protocol proves, such as "G0 and G1 are isomorphic" or "I know the
+// secret key for y", and a witness is the secret that makes it true, such as the isomorphism or the
+// secret key. Each statement type knows how to check a witness and how to run its protocol's interactive
+// proof, so that code like prove below can handle every protocol the same way.
+
+pub trait Witness {}
+
+pub trait Statement {
+    type Witness: Witness;
+
+    // Name of the protocol proving statements of this kind
+    fn protocol(&self) -> &'static str;
+
+    // Canonical encoding of the statement, used to bind proofs to it
+    fn encode(&self) -> Vec<u8>;
+
+    // Estimate of the bytes the statement takes up in memory, checked against config::memory_limit before
+    // a proof is run. Statements whose size is dominated by their encoding can keep the default.
+    fn memory_footprint(&self) -> usize {
+        self.encode().len()
+    }
+
+    // Checks that the witness makes the statement true, describing the problem otherwise
+    fn validate(&self, witness: &Self::Witness) -> Result<(), String>;
+
+    // Runs the protocol's interactive proof between an honest prover holding the witness and an honest
+    // verifier, returning whether the verifier accepted
+    fn run_proof(&self, witness: &Self::Witness) -> bool;
+}
+
+// Proves the statement with the given witness, refusing to run the protocol if the witness is invalid or
+// the statement is larger than the configured memory limit
+pub fn prove<S: Statement>(statement: &S, witness: &S::Witness) -> Result<bool, String> {
+    check_memory_limit(statement)?;
+    statement.validate(witness)?;
+    narrate!("Proving {} statement.", statement.protocol());
+    Ok(statement.run_proof(witness))
+}
+
+fn check_memory_limit<S: Statement>(statement: &S) -> Result<(), String> {
+    if let Some(limit) = config::memory_limit() {
+        let footprint = statement.memory_footprint();
+        if footprint > limit {
+            return Err(format!("Statement takes {} bytes, more than the limit of {}", footprint, limit));
+        }
+    }
+    Ok(())
+}
+
+// Proof context for a proof of the statement in the given session
+pub fn statement_context<S: Statement>(statement: &S, session_id: &[u8]) -> ProofContext {
+    ProofContext::new(statement.protocol(), session_id, &statement.encode())
+}
+
+// ************ Preprocessing implementation ************
+
+// Some protocols, such as SNARKs over R1CS or GKR circuits, preprocess a statement once into a prover key
+// and a verifier key, and every later proof of it uses the keys instead. Compile gives such protocols the
+// same shape as transparent ones, which have nothing to preprocess: a transparent statement, such as a GI
+// instance, compiles to empty keys and proves as before. Keys record a digest of the statement they were
+// compiled from, so keys cached for one statement are never used with another.
+
+pub trait Compile: Statement {
+    type ProverKey: Serialize + DeserializeOwned;
+    type VerifierKey: Serialize + DeserializeOwned;
+
+    // Fails if the statement can't be preprocessed, e.g. because its keys don't decode
+    fn compile(&self) -> Result<(Self::ProverKey, Self::VerifierKey), String>;
+
+    // Runs the protocol's interactive proof like run_proof, with the prover and verifier using the keys
+    fn run_proof_with_keys(&self, prover_key: &Self::ProverKey, verifier_key: &Self::VerifierKey, witness: &Self::Witness) -> bool;
+}
+
+// Statements whose protocols need no preprocessing, which compile to empty keys
+pub trait Transparent: Statement {}
+
+impl<S: Transparent> Compile for S {
+    type ProverKey = ();
+    type VerifierKey = ();
+
+    fn compile(&self) -> Result<((), ()), String> {
+        Ok(((), ()))
+    }
+
+    fn run_proof_with_keys(&self, _prover_key: &(), _verifier_key: &(), witness: &S::Witness) -> bool {
+        self.run_proof(witness)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Keys<S: Compile> {
+    // Digest of the statement's protocol and encoding
+    pub statement: [u8; 32],
+    pub prover: S::ProverKey,
+    pub verifier: S::VerifierKey,
+}
+
+impl<S: Compile> Keys<S> {
+    pub fn check(&self, statement: &S) -> Result<(), String> {
+        if self.statement != statement_digest(statement) {
+            return Err(format!("Keys were compiled for a different {} statement", statement.protocol()));
+        }
+        Ok(())
+    }
+}
+
+pub fn compile<S: Compile>(statement: &S) -> Result<Keys<S>, String> {
+    let (prover, verifier) = statement.compile()?;
+    Ok(Keys { statement: statement_digest(statement), prover, verifier })
+}
+
+// Loads the statement's keys from a file in one of the io::Format serde formats, compiling them and
+// saving them there if the file doesn't exist yet
+pub fn load_or_compile<S: Compile>(statement: &S, path: &Path) -> Result<Keys<S>, String> {
+    let format = Format::from_path(path)?;
+    if path.exists() {
+        let bytes = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let keys: Keys<S> = format.decode(&bytes)?;
+        keys.check(statement)?;
+        return Ok(keys);
+    }
+    let keys = compile(statement)?;
+    std::fs::write(path, format.encode(&keys)?).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    Ok(keys)
+}
+
+// Proves the statement like prove, using keys compiled from it
+pub fn prove_with_keys<S: Compile>(statement: &S, keys: &Keys<S>, witness: &S::Witness) -> Result<bool, String> {
+    keys.check(statement)?;
+    check_memory_limit(statement)?;
+    statement.validate(witness)?;
+    narrate!("Proving {} statement with compiled keys.", statement.protocol());
+    Ok(statement.run_proof_with_keys(&keys.prover, &keys.verifier, witness))
+}
+
+fn statement_digest<S: Statement>(statement: &S) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zklib statement");
+    hasher.update(statement.protocol().as_bytes());
+    hasher.update([0]);
+    hasher.update(statement.encode());
+    hasher.finalize().into()
+}
+
+#[test]
+fn test_prove_graph_isomorphism() {
+    let g0 = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let isomorphism = Secret::new(Permutation::new(vec![2, 1, 0, 3]).unwrap());
+    let instance = GraphPair { g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]), g0 };
+    assert_eq!(prove(&instance, &isomorphism), Ok(true));
+    assert!(prove(&instance, &Permutation::identity(4)).is_err());
+    assert!(prove(&instance, &Permutation::identity(3)).is_err());
+    // The prover answers with the witness, so one that isn't an isomorphism is caught whenever g1 is challenged
+    assert!((0..64).all(|_| instance.run_proof(&isomorphism)));
+    assert!((0..64).any(|_| !instance.run_proof(&Permutation::identity(4))));
+}
+
+#[test]
+fn test_prove_discrete_log() {
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    assert_eq!(prove(&key.public_key(), &key.x), Ok(true));
+    assert!(prove(&key.public_key(), &(&*key.x + 1u32)).is_err());
+    let (instance, witness) = OkamotoInstance::generate(&key.params);
+    assert_eq!(prove(&instance, &witness), Ok(true));
+}
+
+#[test]
+fn test_statement_context() {
+    // Statements of different protocols, or different statements of one protocol, get different contexts
+    let key = SchnorrKeyPair::generate(&SchnorrParameters::generate(64));
+    let other = SchnorrKeyPair::generate(&key.params);
+    let context = statement_context(&key.public_key(), b"session");
+    assert!(context.check(&statement_context(&other.public_key(), b"session")).is_err());
+    let (instance, _) = OkamotoInstance::generate(&key.params);
+    assert!(context.check(&statement_context(&instance, b"session")).is_err());
+    assert_eq!(context.check(&statement_context(&key.public_key(), b"session")), Ok(()));
+    // A GI instance's own context is the statement's
+    let pair = GraphPair { g0: Graph::new(3, vec![(0, 1)]), g1: Graph::new(3, vec![(1, 2)]) };
+    assert_eq!(pair.context(pair.protocol(), b"session"), statement_context(&pair, b"session"));
+}
+
+#[test]
+fn test_memory_limit() {
+    let small = GraphPair { g0: Graph::new(4, vec![(0, 1)]), g1: Graph::new(4, vec![(0, 1)]) };
+    let large = GraphPair { g0: Graph::new(10_000, Vec::new()), g1: Graph::new(10_000, Vec::new()) };
+    assert!(small.memory_footprint() < 100_000 && large.memory_footprint() > 100_000);
+    config::with_memory_limit(Some(100_000), || {
+        assert!(prove(&large, &Permutation::identity(10_000)).unwrap_err().contains("more than the limit"));
+        assert_eq!(prove(&small, &Permutation::identity(4)), Ok(true));
+    });
+}
+
+#[test]
+fn test_compile() {
+    let g0 = Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]);
+    let instance = GraphPair { g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]), g0: g0.clone() };
+    let isomorphism = Permutation::new(vec![2, 1, 0, 3]).unwrap();
+    let keys = compile(&instance).unwrap();
+    assert_eq!(prove_with_keys(&instance, &keys, &isomorphism), Ok(true));
+    // Keys are bound to the statement they were compiled from
+    let other = GraphPair { g0: g0.clone(), g1: g0 };
+    assert!(prove_with_keys(&other, &keys, &Permutation::identity(4)).unwrap_err().contains("different"));
+    let path = std::env::temp_dir().join(format!("zklib-keys-{}.json", std::process::id()));
+    let saved = load_or_compile(&instance, &path).unwrap();
+    assert_eq!(load_or_compile(&instance, &path).unwrap().statement, saved.statement);
+    assert!(load_or_compile(&other, &path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
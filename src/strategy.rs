@@ -0,0 +1,208 @@
+use std::rc::Rc;
+use crate::{Diagnosis, Prover, Verifier};
+use crate::experiments::run_trials;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair};
+#[cfg(test)]
+use crate::permutation::Permutation;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Strategy implementation ************
+
+// Writing a malicious party usually means reimplementing a protocol's state machine to change one
+// message. A strategy instead wraps an honest party and rewrites the messages it sends: on every round
+// the strategy sees the message the party received, the reply the honest party would send, and the
+// messages sent so far, and returns the message to actually send. Returning the honest reply plays
+// honestly, and anything else is an attack, e.g. flipping a challenge bit or replaying an earlier
+// message.
+
+// What a strategy sees on each round
+pub struct Round<'a, In, Out> {
+    // Number of messages the party has sent before this one
+    pub round: u32,
+    // Message received from the other party, if any
+    pub received: Option<&'a In>,
+    // Reply the honest party would send
+    pub honest: Out,
+    // Messages actually sent on earlier rounds
+    pub sent: &'a [Out],
+}
+
+// Strategies are shared rather than boxed so that parties using them can be cloned, e.g. for rewinding
+pub type Strategy<'a, In, Out> = Rc<dyn Fn(Round<'_, In, Out>) -> Out + 'a>;
+
+// Plays honestly
+pub fn honest<'a, In, Out>() -> Strategy<'a, In, Out> {
+    Rc::new(|round| round.honest)
+}
+
+// Replays the previous message on every round after the first
+pub fn replay_previous<'a, In, Out: Clone>() -> Strategy<'a, In, Out> {
+    Rc::new(|round| round.sent.last().cloned().unwrap_or(round.honest))
+}
+
+// A prover whose messages are chosen by a strategy on top of an honest prover
+#[derive(Clone)]
+pub struct StrategicProver<'a, P: Prover> {
+    pub honest: P,
+    pub strategy: Strategy<'a, P::VerifierMessage, P::ProverMessage>,
+    pub sent: Vec<P::ProverMessage>,
+}
+
+impl<'a, P: Prover> StrategicProver<'a, P> where P::ProverMessage: Clone {
+    pub fn new(honest: P, strategy: Strategy<'a, P::VerifierMessage, P::ProverMessage>) -> StrategicProver<'a, P> {
+        StrategicProver { honest, strategy, sent: Vec::new() }
+    }
+}
+
+impl<P: Prover> Prover for StrategicProver<'_, P> where P::ProverMessage: Clone {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        let (honest, done) = self.honest.handle(msg);
+        let round = Round { round: self.sent.len() as u32, received: Some(msg), honest, sent: &self.sent };
+        let msg = (self.strategy)(round);
+        self.sent.push(msg.clone());
+        (msg, done)
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        self.honest.validate(msg)
+    }
+}
+
+// A verifier whose messages are chosen by a strategy on top of an honest verifier. The honest verifier
+// still decides whether to accept, based on the messages it would have sent.
+#[derive(Clone)]
+pub struct StrategicVerifier<'a, V: Verifier> {
+    pub honest: V,
+    pub strategy: Strategy<'a, V::ProverMessage, V::VerifierMessage>,
+    pub sent: Vec<V::VerifierMessage>,
+}
+
+impl<'a, V: Verifier> StrategicVerifier<'a, V> where V::VerifierMessage: Clone {
+    pub fn new(honest: V, strategy: Strategy<'a, V::ProverMessage, V::VerifierMessage>) -> StrategicVerifier<'a, V> {
+        StrategicVerifier { honest, strategy, sent: Vec::new() }
+    }
+
+    fn play(&mut self, received: Option<&V::ProverMessage>, honest: V::VerifierMessage) -> V::VerifierMessage {
+        let round = Round { round: self.sent.len() as u32, received, honest, sent: &self.sent };
+        let msg = (self.strategy)(round);
+        self.sent.push(msg.clone());
+        msg
+    }
+}
+
+impl<V: Verifier> Verifier for StrategicVerifier<'_, V> where V::VerifierMessage: Clone {
+    type ProverMessage = V::ProverMessage;
+    type VerifierMessage = V::VerifierMessage;
+
+    fn init(&mut self) -> V::VerifierMessage {
+        let honest = self.honest.init();
+        self.play(None, honest)
+    }
+
+    fn handle(&mut self, msg: &V::ProverMessage) -> (V::VerifierMessage, bool) {
+        let (honest, accept) = self.honest.handle(msg);
+        (self.play(Some(msg), honest), accept)
+    }
+
+    fn validate(&self, msg: &V::ProverMessage) -> Result<(), String> {
+        self.honest.validate(msg)
+    }
+
+    fn diagnose(&self, msg: &V::ProverMessage) -> Option<Diagnosis> {
+        self.honest.diagnose(msg)
+    }
+}
+
+// ************ Strategy experiment implementation ************
+
+// Runs each named experiment the given number of times in parallel and reports how often the verifier
+// accepted. An experiment sets up fresh parties, plays one interaction, and returns the verdict.
+
+pub type Experiment<'a> = Box<dyn Fn() -> bool + Sync + 'a>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrategyReport {
+    pub name: String,
+    pub trials: usize,
+    pub accepted: usize,
+}
+
+impl StrategyReport {
+    pub fn acceptance_rate(&self) -> f64 {
+        self.accepted as f64 / self.trials as f64
+    }
+}
+
+pub fn measure_strategies(trials: usize, experiments: &[(&str, Experiment)]) -> Vec<StrategyReport> {
+    experiments.iter().map(|(name, experiment)| {
+        let accepted = run_trials(trials, experiment).accepted;
+        let report = StrategyReport { name: name.to_string(), trials, accepted };
+        narrate!("Strategy {} was accepted {} out of {} times ({:.3}).", name, accepted, trials, report.acceptance_rate());
+        report
+    }).collect()
+}
+
+#[cfg(test)]
+fn test_instance(isomorphic: bool) -> GraphPair {
+    let g1 = if isomorphic {
+        Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)])
+    } else {
+        Graph::new(4, vec![(0, 2), (2, 3), (1, 3), (2, 1), (3, 0)])
+    };
+    GraphPair { g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]), g1 }
+}
+
+#[cfg(test)]
+fn run_gi_with(instance: &GraphPair, strategy: Strategy<GIVerifierMessage, GIProverMessage>) -> bool {
+    let honest = GIProver::new(instance);
+    let mut prover = StrategicProver::new(honest, strategy);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+    run_interactive_proof(&mut prover, &mut verifier)
+}
+
+#[test]
+fn test_honest_strategy() {
+    assert!(run_gi_with(&test_instance(true), honest()));
+}
+
+#[test]
+fn test_replay_strategy() {
+    // Replaying the graph where an isomorphism is expected fails validation
+    assert!(!run_gi_with(&test_instance(true), replay_previous()));
+}
+
+#[test]
+fn test_measure_strategies() {
+    let instance = test_instance(true);
+    let experiments: Vec<(&str, Experiment)> = vec![
+        ("honest", Box::new(|| run_gi_with(&instance, honest()))),
+        // Sending the identity instead of the isomorphism only works if the verifier asked for g0 and the
+        // random permutation happens to be g0 itself
+        ("identity", Box::new(|| run_gi_with(&instance, Rc::new(|round: Round<GIVerifierMessage, GIProverMessage>| {
+            match round.honest {
+                GIProverMessage::Isomorphism(_) => GIProverMessage::Isomorphism(Permutation::identity(4)),
+                x => x,
+            }
+        })))),
+    ];
+    let reports = measure_strategies(200, &experiments);
+    assert_eq!(reports[0].acceptance_rate(), 1.0);
+    assert!(reports[1].acceptance_rate() < 0.5);
+}
+
+#[test]
+fn test_strategic_verifier() {
+    // A strategy that always challenges with g1 replaces every message the honest verifier sends. The
+    // honest verifier still checks the answer against its own random bit, so the verdict isn't asserted.
+    let instance = test_instance(true);
+    let honest = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let mut verifier = StrategicVerifier::new(honest, Rc::new(|_| GIVerifierMessage { b: true }));
+    let mut prover = GIProver::new(&instance);
+    run_interactive_proof(&mut prover, &mut verifier);
+    assert!(verifier.sent.iter().all(|x| x.b));
+}
@@ -0,0 +1,386 @@
+use std::{collections::HashSet, fmt};
+use rand::{Rng, seq::SliceRandom};
+use serde::{Serialize, Deserialize};
+use crate::{Prover, Verifier};
+use crate::commitment::{commit, Commitment, Opening};
+use crate::graph::check_count;
+use crate::randomness::checked_thread_rng;
+use crate::statement::{Statement, Transparent, Witness};
+#[cfg(test)]
+use crate::experiments::run_trials;
+#[cfg(test)]
+use crate::run_interactive_proof;
+
+// ************ Zero-knowledge Sudoku solution proof implementation ************
+
+// A 9x9 grid of digits, where 0 marks an empty cell
+pub type Grid = [[u8; 9]; 9];
+
+pub struct SudokuPuzzle {
+    // Clues given by the puzzle
+    pub grid: Grid,
+}
+
+impl SudokuPuzzle {
+    // Checks that the given grid is a completed Sudoku that agrees with every clue of the puzzle
+    pub fn is_solved_by(&self, solution: &Grid) -> bool {
+        let agrees_with_clues = (0..9).all(|i| (0..9).all(|j| {
+            self.grid[i][j] == 0 || self.grid[i][j] == solution[i][j]
+        }));
+        agrees_with_clues && SudokuChallenge::units().iter().all(|unit| {
+            is_permutation_of_digits(&unit.cells(self).iter().map(|&(i, j)| solution[i][j]).collect::<Vec<u8>>())
+        })
+    }
+
+    // Positions of all cells filled in by the puzzle
+    fn clues(&self) -> Vec<(usize, usize)> {
+        (0..9).flat_map(|i| (0..9).map(move |j| (i, j))).filter(|&(i, j)| self.grid[i][j] != 0).collect()
+    }
+}
+
+impl fmt::Debug for SudokuPuzzle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut output = String::new();
+        output.push_str("Sudoku {\n");
+        self.grid.iter().for_each(|row| {
+            let row: String = row.iter().map(|&x| if x == 0 { '.' } else { (b'0' + x) as char }).collect();
+            output.push_str(&format!("  {}\n", row));
+        });
+        output.push('}');
+        write!(f, "{}", output)
+    }
+}
+
+// The verifier either asks to see one of the 27 units (rows, columns, and 3x3 squares) of the
+// relabeled solution, or asks to see the relabeled clues along with the relabeling itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SudokuChallenge {
+    Row(usize),
+    Column(usize),
+    Square(usize),
+    Clues,
+}
+
+impl SudokuChallenge {
+    fn units() -> Vec<SudokuChallenge> {
+        (0..9).flat_map(|k| vec![SudokuChallenge::Row(k), SudokuChallenge::Column(k), SudokuChallenge::Square(k)]).collect()
+    }
+
+    // Uniformly random challenge among the 28 possibilities
+    fn random() -> SudokuChallenge {
+        match checked_thread_rng().gen_range(0..28) {
+            27 => SudokuChallenge::Clues,
+            k => SudokuChallenge::units()[k],
+        }
+    }
+
+    // Cells the prover must open in response to the challenge
+    fn cells(&self, puzzle: &SudokuPuzzle) -> Vec<(usize, usize)> {
+        match *self {
+            SudokuChallenge::Row(i) => (0..9).map(|j| (i, j)).collect(),
+            SudokuChallenge::Column(j) => (0..9).map(|i| (i, j)).collect(),
+            SudokuChallenge::Square(k) => (0..9).map(|x| (3 * (k / 3) + x / 3, 3 * (k % 3) + x % 3)).collect(),
+            SudokuChallenge::Clues => puzzle.clues(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SudokuProverMessage {
+    // Commitments to each cell of the relabeled solution in row-major order, and to the new label of each digit 1-9
+    Commitments(Vec<Commitment>, Vec<Commitment>),
+    // Openings of the cells requested by the verifier, and of the relabeling if the clues were requested
+    Openings(Vec<Opening>, Vec<Opening>),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone)]
+pub struct SudokuVerifierMessage {
+    // Part of the committed solution that the prover must reveal
+    pub challenge: SudokuChallenge,
+}
+
+pub struct SudokuProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Openings for the commitments sent to the verifier
+    pub cell_openings: Vec<Opening>,
+    pub relabeling_openings: Vec<Opening>,
+    pub puzzle: &'a SudokuPuzzle,
+    pub solution: &'a Grid,
+}
+
+impl Prover for SudokuProver<'_> {
+    type ProverMessage = SudokuProverMessage;
+    type VerifierMessage = SudokuVerifierMessage;
+
+    fn handle(&mut self, msg: &SudokuVerifierMessage) -> (SudokuProverMessage, bool) {
+        self.r += 1;
+        match self.r {
+            // During the first round, the prover randomly relabels the digits of its solution and commits to every cell and to the relabeling
+            1 => {
+                let mut relabeling: Vec<u8> = (1..=9).collect();
+                relabeling.shuffle(&mut checked_thread_rng());
+                let (cell_commitments, cell_openings): (Vec<Commitment>, Vec<Opening>) = self.solution.iter()
+                    .flat_map(|row| row.iter())
+                    .map(|&x| commit(&[relabeling[x as usize - 1]]))
+                    .unzip();
+                let (relabeling_commitments, relabeling_openings): (Vec<Commitment>, Vec<Opening>) = relabeling.iter()
+                    .map(|&x| commit(&[x]))
+                    .unzip();
+                self.cell_openings = cell_openings;
+                self.relabeling_openings = relabeling_openings;
+                (SudokuProverMessage::Commitments(cell_commitments, relabeling_commitments), false)
+            },
+            // During the second round, the prover opens the cells requested by the verifier
+            2 => {
+                narrate!("Prover received challenge {:?}.", msg.challenge);
+                let cells = msg.challenge.cells(self.puzzle).iter().map(|&(i, j)| self.cell_openings[9 * i + j].clone()).collect();
+                let relabeling = if msg.challenge == SudokuChallenge::Clues { self.relabeling_openings.clone() } else { Vec::new() };
+                (SudokuProverMessage::Openings(cells, relabeling), false)
+            },
+            // After opening the commitments, the prover sends a message to terminate the interaction
+            _ => (SudokuProverMessage::Done, true),
+        }
+    }
+
+    fn validate(&self, msg: &SudokuVerifierMessage) -> Result<(), String> {
+        match msg.challenge {
+            SudokuChallenge::Row(k) | SudokuChallenge::Column(k) | SudokuChallenge::Square(k) if k >= 9 => {
+                Err(format!("Challenged unit {} is out of range", k))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct SudokuVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Randomly chosen challenge
+    pub challenge: SudokuChallenge,
+    // Commitments received from the prover
+    pub cell_commitments: Vec<Commitment>,
+    pub relabeling_commitments: Vec<Commitment>,
+    pub puzzle: &'a SudokuPuzzle,
+}
+
+impl SudokuVerifier<'_> {
+    // Checks the prover's openings against the commitments from the first round
+    fn check_openings(&self, cells: &[Opening], relabeling: &[Opening]) -> bool {
+        let positions = self.challenge.cells(self.puzzle);
+        if cells.len() != positions.len() || !cells.iter().all(|x| x.value.len() == 1) {
+            return false;
+        }
+        let cells_valid = positions.iter().zip(cells.iter()).all(|(&(i, j), opening)| self.cell_commitments[9 * i + j].verify(opening));
+        let values: Vec<u8> = cells.iter().map(|x| x.value[0]).collect();
+        match self.challenge {
+            // Every row, column, and square of the relabeled solution must contain each digit exactly once
+            SudokuChallenge::Row(_) | SudokuChallenge::Column(_) | SudokuChallenge::Square(_) => cells_valid && is_permutation_of_digits(&values),
+            // The relabeled clues must match the clues of the puzzle under the committed relabeling
+            SudokuChallenge::Clues => {
+                if relabeling.len() != 9 || !relabeling.iter().all(|x| x.value.len() == 1) {
+                    return false;
+                }
+                let relabeling_valid = self.relabeling_commitments.iter().zip(relabeling.iter()).all(|(c, opening)| c.verify(opening));
+                let labels: Vec<u8> = relabeling.iter().map(|x| x.value[0]).collect();
+                cells_valid && relabeling_valid && is_permutation_of_digits(&labels) && positions.iter().zip(values.iter()).all(|(&(i, j), &x)| {
+                    labels[self.puzzle.grid[i][j] as usize - 1] == x
+                })
+            },
+        }
+    }
+}
+
+impl Verifier for SudokuVerifier<'_> {
+    type ProverMessage = SudokuProverMessage;
+    type VerifierMessage = SudokuVerifierMessage;
+
+    fn init(&mut self) -> SudokuVerifierMessage {
+        narrate!("Initializing Sudoku instance with puzzle {:?}.", self.puzzle);
+        SudokuVerifierMessage { challenge: SudokuChallenge::Clues }
+    }
+
+    fn validate(&self, msg: &SudokuProverMessage) -> Result<(), String> {
+        match (self.r + 1, msg) {
+            (1, SudokuProverMessage::Commitments(cells, relabeling)) => {
+                check_count(cells.len(), 81, "cell commitments")?;
+                check_count(relabeling.len(), 9, "relabeling commitments")
+            },
+            (1, _) => Err("expected commitments".to_string()),
+            (_, SudokuProverMessage::Openings(..)) => Ok(()),
+            _ => Err("expected openings".to_string()),
+        }
+    }
+
+    fn handle(&mut self, msg: &SudokuProverMessage) -> (SudokuVerifierMessage, bool) {
+        self.r += 1;
+        match self.r {
+            1 => {
+                if let SudokuProverMessage::Commitments(cells, relabeling) = msg {
+                    if cells.len() != 81 || relabeling.len() != 9 {
+                        panic!("Prover did not send the correct number of commitments on round 1!")
+                    }
+                    self.cell_commitments = cells.clone();
+                    self.relabeling_commitments = relabeling.clone();
+                    self.challenge = SudokuChallenge::random();
+                    narrate!("Verifier chose challenge {:?}.", self.challenge);
+                    (SudokuVerifierMessage { challenge: self.challenge }, false)
+                } else {
+                    panic!("Prover did not send valid commitments on round 1!")
+                }
+            },
+            _ => {
+                if let SudokuProverMessage::Openings(cells, relabeling) = msg {
+                    narrate!("Verifier received {} cell openings.", cells.len());
+                    (SudokuVerifierMessage { challenge: self.challenge }, self.check_openings(cells, relabeling))
+                } else {
+                    panic!("Prover did not send valid openings on round 2!")
+                }
+            }
+        }
+    }
+}
+
+fn is_permutation_of_digits(values: &[u8]) -> bool {
+    values.len() == 9 && values.iter().all(|x| (1..=9).contains(x)) && values.iter().collect::<HashSet<_>>().len() == 9
+}
+
+impl Witness for Grid {}
+
+// The statement that the puzzle has a solution, with a solution as the witness
+impl Statement for SudokuPuzzle {
+    type Witness = Grid;
+
+    fn protocol(&self) -> &'static str {
+        "sudoku"
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.grid.iter().flatten().copied().collect()
+    }
+
+    fn validate(&self, witness: &Grid) -> Result<(), String> {
+        if !self.is_solved_by(witness) {
+            return Err("Grid does not solve the puzzle".to_string());
+        }
+        Ok(())
+    }
+
+    fn run_proof(&self, witness: &Grid) -> bool {
+        let mut prover = SudokuProver { r: 0, cell_openings: Vec::new(), relabeling_openings: Vec::new(), puzzle: self, solution: witness };
+        let mut verifier = SudokuVerifier {
+            r: 0,
+            challenge: SudokuChallenge::Clues,
+            cell_commitments: Vec::new(),
+            relabeling_commitments: Vec::new(),
+            puzzle: self,
+        };
+        crate::run_interactive_proof(&mut prover, &mut verifier)
+    }
+}
+
+impl Transparent for SudokuPuzzle {}
+
+#[test]
+fn test_sudoku_statement() {
+    let (puzzle, solution) = example_puzzle();
+    assert_eq!(crate::statement::prove(&puzzle, &solution), Ok(true));
+    assert!(crate::statement::prove(&puzzle, &[[0; 9]; 9]).is_err());
+}
+
+// Puzzle with its solution, for tests and the fuzzer
+pub(crate) fn example_puzzle() -> (SudokuPuzzle, Grid) {
+    let puzzle = SudokuPuzzle {
+        grid: [
+            [5, 3, 0, 0, 7, 0, 0, 0, 0],
+            [6, 0, 0, 1, 9, 5, 0, 0, 0],
+            [0, 9, 8, 0, 0, 0, 0, 6, 0],
+            [8, 0, 0, 0, 6, 0, 0, 0, 3],
+            [4, 0, 0, 8, 0, 3, 0, 0, 1],
+            [7, 0, 0, 0, 2, 0, 0, 0, 6],
+            [0, 6, 0, 0, 0, 0, 2, 8, 0],
+            [0, 0, 0, 4, 1, 9, 0, 0, 5],
+            [0, 0, 0, 0, 8, 0, 0, 7, 9],
+        ],
+    };
+    let solution = [
+        [5, 3, 4, 6, 7, 8, 9, 1, 2],
+        [6, 7, 2, 1, 9, 5, 3, 4, 8],
+        [1, 9, 8, 3, 4, 2, 5, 6, 7],
+        [8, 5, 9, 7, 6, 1, 4, 2, 3],
+        [4, 2, 6, 8, 5, 3, 7, 9, 1],
+        [7, 1, 3, 9, 2, 4, 8, 5, 6],
+        [9, 6, 1, 5, 3, 7, 2, 8, 4],
+        [2, 8, 7, 4, 1, 9, 6, 3, 5],
+        [3, 4, 5, 2, 8, 6, 1, 7, 9],
+    ];
+    (puzzle, solution)
+}
+
+#[test]
+fn test_is_solved_by() {
+    let (puzzle, mut solution) = example_puzzle();
+    assert!(puzzle.is_solved_by(&solution));
+    solution[0][2] = 1;
+    assert!(!puzzle.is_solved_by(&solution));
+}
+
+#[test]
+fn test_sudoku_interactive_proof() {
+    let (puzzle, solution) = example_puzzle();
+    // Since the proof has perfect completeness, an honest prover should succeed regardless of the verifier's challenge.
+    for _ in 0..100 {
+        let mut prover = SudokuProver {
+            r: 0,
+            cell_openings: Vec::new(),
+            relabeling_openings: Vec::new(),
+            puzzle: &puzzle,
+            solution: &solution,
+        };
+        let mut verifier = SudokuVerifier {
+            r: 0,
+            challenge: SudokuChallenge::Clues,
+            cell_commitments: Vec::new(),
+            relabeling_commitments: Vec::new(),
+            puzzle: &puzzle,
+        };
+        assert!(run_interactive_proof(&mut prover, &mut verifier));
+    }
+}
+
+#[test]
+fn test_sudoku_malicious_interactive_proof() {
+    // A prover holding a completed Sudoku that ignores the clues passes every challenge except
+    // the clue check, so it should succeed with probability 27/28 per run.
+    let n = 1000;
+    let (puzzle, solution) = example_puzzle();
+    // Swapping two digits everywhere keeps the grid a valid Sudoku but breaks the clues
+    let swap = |x: u8| match x { 1 => 2, 2 => 1, x => x };
+    let mut wrong_solution = solution;
+    wrong_solution.iter_mut().for_each(|row| row.iter_mut().for_each(|x| *x = swap(*x)));
+    assert!(!puzzle.is_solved_by(&wrong_solution));
+
+    let successes = run_trials(n, || {
+        let mut prover = SudokuProver {
+            r: 0,
+            cell_openings: Vec::new(),
+            relabeling_openings: Vec::new(),
+            puzzle: &puzzle,
+            solution: &wrong_solution,
+        };
+        let mut verifier = SudokuVerifier {
+            r: 0,
+            challenge: SudokuChallenge::Clues,
+            cell_commitments: Vec::new(),
+            relabeling_commitments: Vec::new(),
+            puzzle: &puzzle,
+        };
+        run_interactive_proof(&mut prover, &mut verifier)
+    }).accepted;
+
+    narrate!("Malicious Sudoku prover succeeded {} out of {} times.", successes, n);
+
+    assert!(successes != n);
+}
@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::{diagnose_interactive_proof, Prover, Verifier};
+use crate::graph::{GIProverMessage, GIVerifier, GIVerifierMessage, Graph};
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMalicious, GraphPair};
+
+// ************ Interaction trace implementation ************
+
+// A trace is a machine-readable record of an interaction as a sequence of typed events, meant for
+// front-ends that replay or animate a protocol. Every message is recorded as JSON along with its round
+// and sender, and protocols can add snapshots of the graphs involved on each round, e.g. the GI
+// prover's random permutation and the graph it maps onto under the revealed isomorphism.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Party {
+    Prover,
+    Verifier,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    Start { protocol: String },
+    Message { round: u32, sender: Party, message: Value },
+    // A graph worth drawing on the given round, with round 0 holding the instance
+    Snapshot { round: u32, label: String, graph: Graph },
+    // Whether the verifier accepted, and if not, why
+    Verdict { accept: bool, reason: Option<String> },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn accepted(&self) -> bool {
+        self.events.iter().any(|event| matches!(event, TraceEvent::Verdict { accept: true, .. }))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.events).unwrap()
+    }
+
+    // One event per line, so a front-end can consume events as they are written
+    pub fn to_json_lines(&self) -> String {
+        self.events.iter().map(|event| format!("{}\n", serde_json::to_string(event).unwrap())).collect()
+    }
+}
+
+// Called with the round and every message the prover sends, returning snapshots to record after it
+pub type Snapshots<'a, T> = Box<dyn FnMut(u32, &T) -> Vec<(String, Graph)> + 'a>;
+
+// Records every message the prover receives and sends, since the prover sees the whole interaction
+struct TracingProver<'a, 'b, P: Prover> {
+    inner: &'b mut dyn Prover<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>,
+    round: u32,
+    events: &'b RefCell<Vec<TraceEvent>>,
+    snapshots: Snapshots<'a, P::ProverMessage>,
+}
+
+fn to_value<T: Serialize>(msg: &T) -> Value {
+    serde_json::to_value(msg).unwrap_or(Value::Null)
+}
+
+impl<P: Prover> Prover for TracingProver<'_, '_, P> where P::ProverMessage: Serialize, P::VerifierMessage: Serialize {
+    type ProverMessage = P::ProverMessage;
+    type VerifierMessage = P::VerifierMessage;
+
+    fn handle(&mut self, msg: &P::VerifierMessage) -> (P::ProverMessage, bool) {
+        self.round += 1;
+        let (reply, done) = self.inner.handle(msg);
+        let mut events = self.events.borrow_mut();
+        events.push(TraceEvent::Message { round: self.round, sender: Party::Verifier, message: to_value(msg) });
+        // The message sent along with the done flag never reaches the verifier
+        if !done {
+            events.push(TraceEvent::Message { round: self.round, sender: Party::Prover, message: to_value(&reply) });
+            for (label, graph) in (self.snapshots)(self.round, &reply) {
+                events.push(TraceEvent::Snapshot { round: self.round, label, graph });
+            }
+        }
+        (reply, done)
+    }
+
+    fn validate(&self, msg: &P::VerifierMessage) -> Result<(), String> {
+        self.inner.validate(msg)
+    }
+}
+
+// Runs the interaction like diagnose_interactive_proof, recording it as a trace
+pub fn run_traced<P: Prover>(
+    protocol: &str,
+    prover: &mut P,
+    verifier: &mut dyn Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>,
+) -> Trace where P::ProverMessage: Serialize, P::VerifierMessage: Serialize {
+    run_traced_with(protocol, prover, verifier, Vec::new(), Box::new(|_, _| Vec::new()))
+}
+
+// Runs the interaction like run_traced, starting with snapshots of the instance and recording the
+// snapshots returned for every prover message
+pub fn run_traced_with<'a, P: Prover>(
+    protocol: &str,
+    prover: &mut P,
+    verifier: &mut dyn Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>,
+    instance: Vec<(String, Graph)>,
+    snapshots: Snapshots<'a, P::ProverMessage>,
+) -> Trace where P::ProverMessage: Serialize, P::VerifierMessage: Serialize {
+    let events = RefCell::new(vec![TraceEvent::Start { protocol: protocol.to_string() }]);
+    events.borrow_mut().extend(instance.into_iter().map(|(label, graph)| TraceEvent::Snapshot { round: 0, label, graph }));
+    let result = {
+        let mut tracing = TracingProver::<P> { inner: prover, round: 0, events: &events, snapshots };
+        diagnose_interactive_proof(&mut tracing, verifier)
+    };
+    let mut events = events.into_inner();
+    events.push(TraceEvent::Verdict { accept: result.is_ok(), reason: result.err().map(|e| e.to_string()) });
+    Trace { events }
+}
+
+// Traces a GI proof with snapshots of g0 and g1, the prover's graph H, and H mapped under the revealed
+// isomorphism, which matches the challenged graph if the prover answered correctly
+pub fn trace_gi<P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage>>(prover: &mut P, verifier: &mut GIVerifier) -> Trace {
+    let instance = vec![("g0".to_string(), verifier.instance.g0.clone()), ("g1".to_string(), verifier.instance.g1.clone())];
+    let mut h: Option<Graph> = None;
+    let snapshots: Snapshots<GIProverMessage> = Box::new(move |_, msg| match msg {
+        GIProverMessage::Graph(graph) => {
+            h = Some(graph.clone());
+            vec![("h".to_string(), graph.clone())]
+        },
+        GIProverMessage::Isomorphism(isomorphism) => match &h {
+            Some(graph) if graph.n == isomorphism.n() => vec![("h permuted".to_string(), graph.permute(isomorphism))],
+            _ => Vec::new(),
+        },
+        GIProverMessage::Done => Vec::new(),
+    });
+    run_traced_with("gi", prover, verifier, instance, snapshots)
+}
+
+#[cfg(test)]
+fn test_instance() -> GraphPair {
+    GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    }
+}
+
+#[test]
+fn test_trace_gi() {
+    let instance = test_instance();
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let trace = trace_gi(&mut prover, &mut verifier);
+    assert!(trace.accepted());
+    let labels: Vec<&str> = trace.events.iter().filter_map(|event| match event {
+        TraceEvent::Snapshot { label, .. } => Some(label.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(labels, vec!["g0", "g1", "h", "h permuted"]);
+    // The permuted graph is the challenged graph
+    let challenged = if verifier.b { &instance.g1 } else { &instance.g0 };
+    assert!(trace.events.iter().any(|event| matches!(event, TraceEvent::Snapshot { label, graph, .. } if label == "h permuted" && graph == challenged)));
+
+    // Traces survive a round trip through JSON, one event per line
+    let events: Vec<TraceEvent> = trace.to_json_lines().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(events, trace.events);
+}
+
+#[test]
+fn test_trace_rejection() {
+    // A prover that can't answer the challenge is caught at least once in 20 proofs
+    let instance = test_instance();
+    let rejected = (0..20).map(|_| {
+        let mut prover = GIProverMalicious::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        run_traced("gi", &mut prover, &mut verifier)
+    }).find(|trace| !trace.accepted()).unwrap();
+    match rejected.events.last().unwrap() {
+        TraceEvent::Verdict { accept: false, reason: Some(reason) } => assert!(reason.contains("permuted graph")),
+        event => panic!("expected a rejection, got {:?}", event),
+    }
+    assert_eq!(rejected.events.iter().filter(|event| matches!(event, TraceEvent::Message { .. })).count(), 5);
+}
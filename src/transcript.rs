@@ -0,0 +1,271 @@
+use num_bigint::BigUint;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use std::marker::PhantomData;
+use sha2::{Digest, Sha256};
+use crate::challenge::Challenge;
+use crate::hash::{Hasher, Sha256Hasher};
+use crate::randomness::checked_thread_rng;
+
+// ************ Challenge transcript implementation ************
+
+// A running hash of every message exchanged in a session, in the style of Merlin transcripts. Each
+// message is absorbed along with a label describing it, and both are length-prefixed so that different
+// sequences of messages can never hash to the same state. Every challenge in a protocol is derived from
+// the transcript and then absorbed into it:
+//
+// - Non-interactive provers and verifiers derive challenges from the transcript alone, which makes the
+//   Fiat-Shamir transform sound since each challenge depends on everything absorbed before it.
+// - Public-coin verifiers derive challenges from the transcript along with fresh randomness, which keeps
+//   the challenge uniformly random while tying it to the messages it answers.
+//
+// Either way, the prover absorbs the challenge it receives, so two parties that exchanged the same
+// messages end up with the same digest, which can be logged to audit the interaction.
+//
+// Transcripts hash with SHA-256 unless given another Hasher, such as Poseidon for protocols whose
+// verifiers are arithmetized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengeTranscript<H: Hasher = Sha256Hasher> {
+    state: [u8; 32],
+    // Total length of the messages absorbed so far, excluding labels
+    message_bytes: u64,
+    hasher: PhantomData<H>,
+}
+
+impl ChallengeTranscript {
+    // Starts a transcript for the protocol with the given name
+    pub fn new(protocol: &[u8]) -> ChallengeTranscript {
+        ChallengeTranscript::with_hasher(protocol)
+    }
+}
+
+impl<H: Hasher> ChallengeTranscript<H> {
+    // Starts a transcript like new, hashing with H
+    pub fn with_hasher(protocol: &[u8]) -> ChallengeTranscript<H> {
+        let mut transcript = ChallengeTranscript { state: [0u8; 32], message_bytes: 0, hasher: PhantomData };
+        transcript.append_message(b"protocol", protocol);
+        transcript
+    }
+
+    pub fn append_message(&mut self, label: &[u8], message: &[u8]) {
+        let (label_len, message_len) = ((label.len() as u64).to_be_bytes(), (message.len() as u64).to_be_bytes());
+        self.state = H::hash(&[&self.state, &label_len, label, &message_len, message]);
+        self.message_bytes += message.len() as u64;
+    }
+
+    pub fn append_biguint(&mut self, label: &[u8], x: &BigUint) {
+        self.append_message(label, &x.to_bytes_be());
+    }
+
+    // Fills dest with challenge bytes derived from the transcript so far, and absorbs them
+    pub fn challenge_bytes(&mut self, label: &[u8], dest: &mut [u8]) {
+        self.expand(label, dest);
+        self.append_message(label, dest);
+    }
+
+    // Derives a challenge in Z_q from the transcript so far, and absorbs it
+    pub fn challenge_scalar(&mut self, label: &[u8], q: &BigUint) -> BigUint {
+        self.challenge(label, q)
+    }
+
+    // Derives a challenge in Z_q from the transcript so far and fresh randomness, for public-coin
+    // verifiers. Only the resulting challenge is absorbed, so the prover's transcript stays in sync.
+    pub fn verifier_challenge_scalar(&mut self, label: &[u8], q: &BigUint) -> BigUint {
+        self.verifier_challenge(label, q)
+    }
+
+    // Derives a challenge from any challenge space from the transcript so far, and absorbs its encoding
+    pub fn challenge<C: Challenge>(&mut self, label: &[u8], space: &C::Space) -> C {
+        let mut bytes = vec![0u8; C::byte_len(space)];
+        self.expand(label, &mut bytes);
+        let challenge = C::from_bytes(space, &bytes);
+        self.append_challenge(label, &challenge);
+        challenge
+    }
+
+    // Derives a challenge like challenge, but mixing in fresh randomness as verifier_challenge_scalar does
+    pub fn verifier_challenge<C: Challenge>(&mut self, label: &[u8], space: &C::Space) -> C {
+        let mut forked = self.clone();
+        forked.append_message(b"verifier randomness", &checked_thread_rng().gen::<[u8; 32]>());
+        let challenge = forked.challenge(label, space);
+        self.append_challenge(label, &challenge);
+        challenge
+    }
+
+    // Absorbs a challenge received from the other party
+    pub fn append_challenge<C: Challenge>(&mut self, label: &[u8], challenge: &C) {
+        self.append_message(label, &challenge.encode());
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        self.state
+    }
+
+    // For a transcript recorded by a runner, the growth of this count over an interaction is the number
+    // of bytes the parties sent each other
+    pub fn message_bytes(&self) -> u64 {
+        self.message_bytes
+    }
+
+    fn expand(&self, label: &[u8], dest: &mut [u8]) {
+        for (i, chunk) in dest.chunks_mut(32).enumerate() {
+            let block = H::hash(&[&self.state, &(label.len() as u64).to_be_bytes(), label, &(i as u64).to_be_bytes()]);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    }
+}
+
+// Protocol messages that can be absorbed into a transcript, so that a runner can record every message of
+// an interaction without knowing its protocol
+pub trait TranscriptMessage {
+    fn append_to(&self, transcript: &mut ChallengeTranscript);
+}
+
+// ************ Proof context implementation ************
+
+// Everything a proof is about besides its messages: a domain separation label naming the protocol, an id
+// for the session, and a hash of the public statement. A context is absorbed into the transcript before
+// any message, so every Fiat-Shamir challenge depends on it, and verifiers check that a proof's context
+// is the one they expect. A proof produced for one protocol, session, or statement is then rejected
+// anywhere else instead of being replayed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofContext {
+    pub domain: String,
+    pub session_id: Vec<u8>,
+    pub statement_hash: [u8; 32],
+}
+
+impl ProofContext {
+    pub fn new(domain: &str, session_id: &[u8], statement: &[u8]) -> ProofContext {
+        ProofContext { domain: domain.to_string(), session_id: session_id.to_vec(), statement_hash: ProofContext::hash_statement(statement) }
+    }
+
+    pub fn hash_statement(statement: &[u8]) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Sha256::digest(statement));
+        hash
+    }
+
+    // Checks that a proof's context matches the one the verifier expects, describing any mismatch
+    pub fn check(&self, expected: &ProofContext) -> Result<(), String> {
+        if self.domain != expected.domain {
+            return Err(format!("Proof is for protocol '{}', expected '{}'", self.domain, expected.domain));
+        }
+        if self.session_id != expected.session_id {
+            return Err("Proof is for a different session".to_string());
+        }
+        if self.statement_hash != expected.statement_hash {
+            return Err("Proof is for a different statement".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Encodes a statement made up of big integers, length-prefixing each one
+pub fn encode_biguints(values: &[&BigUint]) -> Vec<u8> {
+    values.iter().flat_map(|x| {
+        let bytes = x.to_bytes_be();
+        (bytes.len() as u64).to_be_bytes().iter().copied().chain(bytes).collect::<Vec<u8>>()
+    }).collect()
+}
+
+impl ChallengeTranscript {
+    // Starts a transcript bound to the context, with the context's domain as the protocol name
+    pub fn with_context(context: &ProofContext) -> ChallengeTranscript {
+        let mut transcript = ChallengeTranscript::new(context.domain.as_bytes());
+        transcript.append_message(b"session", &context.session_id);
+        transcript.append_message(b"statement", &context.statement_hash);
+        transcript
+    }
+}
+
+#[test]
+fn test_transcript_is_deterministic() {
+    let q = BigUint::from(2305843009213693951u64);
+    let mut a = ChallengeTranscript::new(b"test");
+    let mut b = ChallengeTranscript::new(b"test");
+    a.append_message(b"message", b"hello");
+    b.append_message(b"message", b"hello");
+    let challenge = a.challenge_scalar(b"c", &q);
+    assert_eq!(challenge, b.challenge_scalar(b"c", &q));
+    assert_eq!(a.digest(), b.digest());
+    // Each challenge is absorbed, so asking again yields a fresh challenge
+    assert_ne!(a.challenge_scalar(b"c", &q), challenge);
+}
+
+#[test]
+fn test_challenge_bytes() {
+    let mut a = ChallengeTranscript::new(b"test");
+    let mut b = ChallengeTranscript::new(b"test");
+    let (mut x, mut y) = ([0u8; 80], [0u8; 80]);
+    a.challenge_bytes(b"c", &mut x);
+    b.challenge_bytes(b"c", &mut y);
+    assert_eq!(x, y);
+    // Different labels give unrelated challenges
+    b.challenge_bytes(b"d", &mut y);
+    assert_ne!(x, y);
+}
+
+#[test]
+fn test_verifier_challenge() {
+    // Verifier challenges are random, but a prover absorbing the challenge it received stays in sync
+    let q = BigUint::from(2305843009213693951u64);
+    let mut prover = ChallengeTranscript::new(b"test");
+    let mut verifier = ChallengeTranscript::new(b"test");
+    let challenge = verifier.verifier_challenge_scalar(b"c", &q);
+    assert_ne!(challenge, ChallengeTranscript::new(b"test").verifier_challenge_scalar(b"c", &q));
+    prover.append_biguint(b"c", &challenge);
+    assert_eq!(prover.digest(), verifier.digest());
+}
+
+#[test]
+fn test_generic_challenges() {
+    // Bit-vector challenges stay in sync the same way, and match scalar challenges' derivation
+    let q = BigUint::from(2305843009213693951u64);
+    let mut prover = ChallengeTranscript::new(b"test");
+    let mut verifier = ChallengeTranscript::new(b"test");
+    let bits: Vec<bool> = verifier.verifier_challenge(b"c", &20);
+    assert_eq!(bits.len(), 20);
+    prover.append_challenge(b"c", &bits);
+    assert_eq!(prover.digest(), verifier.digest());
+    assert_eq!(prover.challenge::<BigUint>(b"z", &q), verifier.challenge_scalar(b"z", &q));
+}
+
+#[test]
+fn test_transcript_framing() {
+    // Moving bytes between the label and the message changes the transcript
+    let mut a = ChallengeTranscript::new(b"test");
+    let mut b = ChallengeTranscript::new(b"test");
+    a.append_message(b"ab", b"c");
+    b.append_message(b"a", b"bc");
+    assert_ne!(a.digest(), b.digest());
+    assert_ne!(ChallengeTranscript::new(b"test").digest(), ChallengeTranscript::new(b"other").digest());
+}
+
+#[test]
+fn test_proof_context() {
+    let context = ProofContext::new("test", b"session 1", b"statement");
+    assert_eq!(context.check(&context.clone()), Ok(()));
+    assert!(context.check(&ProofContext::new("other", b"session 1", b"statement")).is_err());
+    assert!(context.check(&ProofContext::new("test", b"session 2", b"statement")).is_err());
+    assert!(context.check(&ProofContext::new("test", b"session 1", b"other statement")).is_err());
+    // Changing any part of the context changes the transcript
+    let digest = ChallengeTranscript::with_context(&context).digest();
+    assert_ne!(ChallengeTranscript::with_context(&ProofContext::new("other", b"session 1", b"statement")).digest(), digest);
+    assert_ne!(ChallengeTranscript::with_context(&ProofContext::new("test", b"session 2", b"statement")).digest(), digest);
+    assert_ne!(ChallengeTranscript::with_context(&ProofContext::new("test", b"session 1", b"other statement")).digest(), digest);
+}
+
+#[test]
+fn test_poseidon_transcript() {
+    use crate::hash::PoseidonHasher;
+    let q = BigUint::from(2305843009213693951u64);
+    let mut a = ChallengeTranscript::<PoseidonHasher>::with_hasher(b"test");
+    let mut b = ChallengeTranscript::<PoseidonHasher>::with_hasher(b"test");
+    a.append_message(b"message", b"hello");
+    b.append_message(b"message", b"hello");
+    assert_eq!(a.challenge_scalar(b"c", &q), b.challenge_scalar(b"c", &q));
+    let mut sha = ChallengeTranscript::new(b"test");
+    sha.append_message(b"message", b"hello");
+    assert_ne!(sha.digest(), a.digest());
+}
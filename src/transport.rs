@@ -0,0 +1,718 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use crate::{Prover, ValidationError, Verifier};
+#[cfg(test)]
+use crate::audit::to_hex;
+#[cfg(test)]
+use crate::beacon::PublicCoinVerifier;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair};
+#[cfg(test)]
+use crate::permutation::Permutation;
+
+pub mod broadcast;
+pub mod mock;
+pub mod quorum;
+pub mod streaming;
+
+// ************ Transport implementation ************
+
+// run_interactive_proof hands messages between parties in memory, where nothing can go wrong on the way.
+// Over a network, messages travel as frames that can arrive late, out of order, damaged, or not at all. A
+// Transport is one end of a link that sends and receives frames, and run_over_transport plays an
+// interaction between two ends of a link, encoding every message with bincode. Each frame carries the
+// round its message belongs to and a checksum, so the receiving end can tell a damaged or stray frame
+// from the one it expects.
+//
+// How the runner reacts to a frame that is late, damaged, or stray is set by a RetryPolicy. Strict runs
+// abort on the first such frame. In retrying runs, an end that doesn't receive the message of a round
+// intact in time sends a nack for the round, and the other end resends its message when the nack
+// arrives. The sending end only learns of a failure through the link, as it would if the ends were in
+// different processes, and a lost nack is sent again after the next wait. Resending is safe because
+// frames are numbered: the receiving end discards copies of rounds it already received, so a party never
+// handles the same message twice, whichever copy arrives first.
+//
+// A party that is slow to reply, such as a prover searching for an isomorphism between large graphs, looks
+// the same as a dead link from the other end. With a heartbeat, the waiting end pings the other after
+// every interval without a frame, and only gives up once the timeout passes without any frame at all. A
+// peer answering every ping but never sending its message would keep the wait going forever, so no wait
+// for a message lasts longer than the options' patience, whatever else arrives. A party doing a long
+// computation in its own process wraps it in keepalive, which answers pings with pongs in the background.
+//
+// On the wire, a frame is its kind as one byte (0 for a message, 1 for a ping, 2 for a pong, 3 for a
+// nack), its round as a big-endian u32, the length of its payload as a big-endian u32, the payload, and
+// the checksum. Message payloads are bincode's default encoding of the message. tests/vectors/wire.json
+// holds frames of every GI message and a full GI session in this format, for checking other
+// implementations against.
+//
+// A prover streaming large messages, such as committed matrices, can also outpace a constrained verifier.
+// Links built from LinkOptions with a buffer hold at most that many unread frames at each end, and a
+// sender blocks until there is room rather than queueing without bound. The buffer only counts messages,
+// and the runners never have more of them in flight to one end than it holds. Pings and pongs are let
+// through, but a waiting end only pings again once the other has answered, so at most one of each is
+// ever waiting at an end. A rate limit paces the runner's messages, so
+// that each end sends at most that many payload bytes per second.
+//
+// Some protocols are only sound if the prover answers quickly, e.g. when a slow answer could come from an
+// offline computation the challenge was meant to rule out. With a deadline, the verifier's end times the
+// prover's reply to each of its messages from when it sent the message, and rejects once a reply is
+// late rather than waiting for it. run_timed_over_transport reports every response time alongside the
+// verdict.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    // A protocol message
+    Message,
+    // A request for a pong, sent while waiting for a message
+    Ping,
+    Pong,
+    // A request to resend the message of the round, which didn't arrive intact in time
+    Nack,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub round: u32,
+    pub payload: Vec<u8>,
+    // SHA-256 of the kind, the round, and the payload
+    pub checksum: [u8; 32],
+}
+
+impl Frame {
+    pub fn new(round: u32, payload: Vec<u8>) -> Frame {
+        Frame::with_kind(FrameKind::Message, round, payload)
+    }
+
+    pub fn ping(round: u32) -> Frame {
+        Frame::with_kind(FrameKind::Ping, round, Vec::new())
+    }
+
+    pub fn pong(round: u32) -> Frame {
+        Frame::with_kind(FrameKind::Pong, round, Vec::new())
+    }
+
+    pub fn nack(round: u32) -> Frame {
+        Frame::with_kind(FrameKind::Nack, round, Vec::new())
+    }
+
+    fn with_kind(kind: FrameKind, round: u32, payload: Vec<u8>) -> Frame {
+        let checksum = Frame::digest(kind, round, &payload);
+        Frame { kind, round, payload, checksum }
+    }
+
+    // Whether the frame arrived as it was sent
+    pub fn is_intact(&self) -> bool {
+        self.checksum == Frame::digest(self.kind, self.round, &self.payload)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.kind as u8];
+        bytes.extend_from_slice(&self.round.to_be_bytes());
+        bytes.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes.extend_from_slice(&self.checksum);
+        bytes
+    }
+
+    // Parses a frame in to_bytes form, or None if the bytes aren't one. The checksum isn't checked.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Frame> {
+        let kind = match *bytes.first()? {
+            0 => FrameKind::Message,
+            1 => FrameKind::Ping,
+            2 => FrameKind::Pong,
+            3 => FrameKind::Nack,
+            _ => return None,
+        };
+        let round = u32::from_be_bytes(bytes.get(1..5)?.try_into().ok()?);
+        let length = u32::from_be_bytes(bytes.get(5..9)?.try_into().ok()?) as usize;
+        if bytes.len() != 9 + length + 32 {
+            return None;
+        }
+        let payload = bytes[9..9 + length].to_vec();
+        let checksum = bytes[9 + length..].try_into().ok()?;
+        Some(Frame { kind, round, payload, checksum })
+    }
+
+    fn digest(kind: FrameKind, round: u32, payload: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update([kind as u8]);
+        hasher.update(round.to_be_bytes());
+        hasher.update(payload);
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportError {
+    // No frame arrived within the timeout
+    Timeout,
+    // The other end of the link is gone
+    Closed,
+    // A frame arrived that doesn't match its checksum
+    Corrupt,
+    // A frame arrived for a round other than the one expected
+    OutOfOrder { expected: u32, found: u32 },
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Timeout => write!(f, "timed out waiting for a frame"),
+            TransportError::Closed => write!(f, "the other end closed the link"),
+            TransportError::Corrupt => write!(f, "received a corrupted frame"),
+            TransportError::OutOfOrder { expected, found } => write!(f, "expected a frame for round {}, received one for round {}", expected, found),
+        }
+    }
+}
+
+pub trait Transport {
+    fn send(&mut self, frame: Frame) -> Result<(), TransportError>;
+
+    // Returns the next frame to arrive within the timeout
+    fn recv(&mut self, timeout: Duration) -> Result<Frame, TransportError>;
+
+    // Lets the duration pass before this end does anything else
+    fn pause(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    // Time on this end's clock, which only has to be monotonic
+    fn now(&self) -> Duration {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        EPOCH.get_or_init(Instant::now).elapsed()
+    }
+}
+
+// ************ Loopback transport implementation ************
+
+// Two ends of a link in memory, which deliver every frame in order and in real time. The ends can be
+// moved to different threads.
+pub struct LoopbackTransport {
+    outgoing: Arc<Queue>,
+    incoming: Arc<Queue>,
+    // Most unread messages the other end holds, and the longest a sender waits for room, if bounded
+    capacity: Option<(NonZeroUsize, Duration)>,
+}
+
+// Frames sent to one end and not yet read by it
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<Frame>,
+    // How many of the frames are messages
+    messages: usize,
+    // Whether either end was dropped
+    closed: bool,
+}
+
+impl Queue {
+    // Blocks until the state is ready or the timeout passes, and returns it locked either way
+    fn wait(&self, timeout: Duration, ready: impl Fn(&QueueState) -> bool) -> MutexGuard<'_, QueueState> {
+        let state = self.state.lock().unwrap();
+        self.changed.wait_timeout_while(state, timeout, |state| !ready(state)).unwrap().0
+    }
+}
+
+pub fn loopback() -> (LoopbackTransport, LoopbackTransport) {
+    link(None)
+}
+
+// A link whose ends each buffer the number of messages in the options, if any, with senders waiting up
+// to the options' timeout for room
+pub fn loopback_with(options: &LinkOptions) -> (LoopbackTransport, LoopbackTransport) {
+    link(options.buffer.map(|frames| (frames, options.timeout)))
+}
+
+fn link(capacity: Option<(NonZeroUsize, Duration)>) -> (LoopbackTransport, LoopbackTransport) {
+    let (a, b) = (Arc::new(Queue::default()), Arc::new(Queue::default()));
+    (LoopbackTransport { outgoing: a.clone(), incoming: b.clone(), capacity }, LoopbackTransport { outgoing: b, incoming: a, capacity })
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, frame: Frame) -> Result<(), TransportError> {
+        let is_message = frame.kind == FrameKind::Message;
+        let (messages, timeout) = match self.capacity {
+            Some((messages, timeout)) if is_message => (messages.get(), timeout),
+            _ => (usize::MAX, Duration::ZERO),
+        };
+        let mut state = self.outgoing.wait(timeout, |state| state.closed || state.messages < messages);
+        if state.closed {
+            return Err(TransportError::Closed);
+        }
+        if state.messages >= messages {
+            return Err(TransportError::Timeout);
+        }
+        state.messages += is_message as usize;
+        state.frames.push_back(frame);
+        self.outgoing.changed.notify_all();
+        Ok(())
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Frame, TransportError> {
+        let mut state = self.incoming.wait(timeout, |state| state.closed || !state.frames.is_empty());
+        match state.frames.pop_front() {
+            Some(frame) => {
+                state.messages -= (frame.kind == FrameKind::Message) as usize;
+                // A sender may be waiting for the room this frame took up
+                self.incoming.changed.notify_all();
+                Ok(frame)
+            },
+            None if state.closed => Err(TransportError::Closed),
+            None => Err(TransportError::Timeout),
+        }
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        for queue in [&self.outgoing, &self.incoming] {
+            queue.state.lock().unwrap().closed = true;
+            queue.changed.notify_all();
+        }
+    }
+}
+
+// ************ Networked runner implementation ************
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkError {
+    // The transport failed while delivering the message of the given round
+    Transport { round: u32, error: TransportError },
+    // A message arrived intact but couldn't be decoded
+    Malformed { round: u32, reason: String },
+    Invalid(ValidationError),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::Transport { round, error } => write!(f, "Transport failed on round {}: {}", round, error),
+            LinkError::Malformed { round, reason } => write!(f, "Malformed message on round {}: {}", round, reason),
+            LinkError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryPolicy {
+    // Aborts on the first frame that times out, is damaged, or is for another round
+    Strict,
+    // Nacks a frame up to the given number of times when it times out or arrives damaged, and discards
+    // frames for rounds already received
+    Retry { attempts: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkOptions {
+    // Longest wait for each frame
+    pub timeout: Duration,
+    pub policy: RetryPolicy,
+    // Interval without a frame after which the waiting end pings the other, if any
+    pub heartbeat: Option<Duration>,
+    // Longest wait for a message however many other frames arrive meanwhile, or the timeout if that's
+    // longer
+    pub patience: Duration,
+    // Most unread messages each end of a link from loopback_with or mock::pair_with holds, if bounded
+    pub buffer: Option<NonZeroUsize>,
+    // Most payload bytes per second each end sends messages at, if limited
+    pub rate_limit: Option<u64>,
+    // Longest the verifier waits for the prover's reply to each of its messages, counted from sending it,
+    // before rejecting, if enforced
+    pub deadline: Option<Duration>,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions { timeout: Duration::from_secs(5), policy: RetryPolicy::Strict, heartbeat: None, patience: Duration::from_secs(60), buffer: None, rate_limit: None, deadline: None }
+    }
+}
+
+// Verdict of a run, with the time the prover took to reply to each verifier message, as measured at the
+// verifier's end, and the round whose reply missed the deadline, if one did
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedRun {
+    pub accept: bool,
+    pub response_times: Vec<Duration>,
+    pub late: Option<u32>,
+}
+
+impl fmt::Display for TimedRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.accept, self.late) {
+            (_, Some(round)) => write!(f, "Rejected: the prover took {:?} to reply on round {}, past the deadline", self.response_times.last().unwrap(), round),
+            (accept, None) => write!(f, "{} with the slowest reply after {:?}", if accept { "Accepted" } else { "Rejected" }, self.response_times.iter().max().copied().unwrap_or_default()),
+        }
+    }
+}
+
+// Plays the interaction like try_run_interactive_proof, with every verifier message sent from the
+// verifier's end of the link and received at the prover's, and every prover message the other way
+pub fn run_over_transport<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>,
+    prover_link: &mut dyn Transport, verifier_link: &mut dyn Transport, options: &LinkOptions) -> Result<bool, LinkError>
+where T: Serialize + DeserializeOwned, U: Serialize + DeserializeOwned {
+    run_timed_over_transport(prover, verifier, prover_link, verifier_link, options).map(|run| run.accept)
+}
+
+// Plays the interaction like run_over_transport, timing the prover's replies and rejecting at the first
+// one past the deadline if there is one
+pub fn run_timed_over_transport<T, U>(prover: &mut dyn Prover<ProverMessage = T, VerifierMessage = U>, verifier: &mut dyn Verifier<ProverMessage = T, VerifierMessage = U>,
+    prover_link: &mut dyn Transport, verifier_link: &mut dyn Transport, options: &LinkOptions) -> Result<TimedRun, LinkError>
+where T: Serialize + DeserializeOwned, U: Serialize + DeserializeOwned {
+    let mut verifier_msg = verifier.init();
+    let mut run = TimedRun { accept: false, response_times: Vec::new(), late: None };
+    let mut round = 1;
+
+    // Run interaction until prover sets 'done' flag to true, prover must send last message
+    loop {
+        let sent_at = verifier_link.now();
+        let received: U = exchange(verifier_link, prover_link, round, &verifier_msg, options)?;
+        prover.validate(&received).map_err(|reason| LinkError::Invalid(ValidationError::VerifierMessage(round, reason)))?;
+        let (prover_msg, done) = prover.handle(&received);
+        if done {
+            break;
+        }
+        // The verifier waits no longer for the reply than the deadline leaves
+        let reply_options = match options.deadline {
+            Some(deadline) => LinkOptions { timeout: options.timeout.min(deadline.saturating_sub(verifier_link.now() - sent_at)), ..*options },
+            None => *options,
+        };
+        let received = exchange(prover_link, verifier_link, round, &prover_msg, &reply_options);
+        let elapsed = verifier_link.now() - sent_at;
+        run.response_times.push(elapsed);
+        // A reply is late if it arrived after the deadline, or if the wait for it ran up to the deadline.
+        // A timeout shorter than what the deadline left is an ordinary link failure.
+        let late = match (&received, options.deadline) {
+            (Err(LinkError::Transport { error: TransportError::Timeout, .. }), Some(deadline)) => elapsed >= deadline,
+            (_, Some(deadline)) => elapsed > deadline,
+            (_, None) => false,
+        };
+        if late {
+            run.late = Some(round);
+            run.accept = false;
+            return Ok(run);
+        }
+        let received: T = received?;
+        verifier.validate(&received).map_err(|reason| LinkError::Invalid(ValidationError::ProverMessage(round, reason)))?;
+        let x = verifier.handle(&received);
+        verifier_msg = x.0;
+        run.accept = x.1;
+        round += 1;
+    }
+
+    Ok(run)
+}
+
+// Sends the message of the round from one end of the link and decodes it at the other, which nacks it as
+// the policy allows
+fn exchange<M: Serialize + DeserializeOwned>(from: &mut dyn Transport, to: &mut dyn Transport, round: u32, msg: &M, options: &LinkOptions) -> Result<M, LinkError> {
+    let payload = bincode::serialize(msg).map_err(|e| LinkError::Malformed { round, reason: e.to_string() })?;
+    let frame = Frame::new(round, payload);
+    let pacing = pacing(&frame, options);
+    let send = |link: &mut dyn Transport| {
+        if let Some(pacing) = pacing {
+            link.pause(pacing);
+        }
+        link.send(frame.clone())
+    };
+    let attempts = match options.policy {
+        RetryPolicy::Strict => 0,
+        RetryPolicy::Retry { attempts } => attempts,
+    };
+    let fail = |error| LinkError::Transport { round, error };
+    send(from).map_err(fail)?;
+    let mut nacked = 0;
+    let received = loop {
+        let error = match receive(to, round, options) {
+            Ok(received) => break received,
+            Err(error) => error,
+        };
+        if nacked == attempts || error == TransportError::Closed {
+            return Err(fail(error));
+        }
+        nacked += 1;
+        to.send(Frame::nack(round)).map_err(fail)?;
+        // A nack that doesn't arrive leaves the receiving end waiting, and it nacks again
+        match await_nack(from, round, options) {
+            Ok(()) => send(from).map_err(fail)?,
+            Err(TransportError::Closed) => return Err(fail(TransportError::Closed)),
+            Err(_) => (),
+        }
+    };
+    bincode::deserialize(&received.payload).map_err(|e| LinkError::Malformed { round, reason: e.to_string() })
+}
+
+// Waits at the sending end for a nack of the round, answering pings meanwhile and ignoring anything else
+fn await_nack(link: &mut dyn Transport, round: u32, options: &LinkOptions) -> Result<(), TransportError> {
+    loop {
+        let frame = link.recv(options.timeout)?;
+        match frame.kind {
+            _ if !frame.is_intact() => (),
+            FrameKind::Nack if frame.round == round => return Ok(()),
+            FrameKind::Ping => link.send(Frame::pong(frame.round))?,
+            _ => (),
+        }
+    }
+}
+
+// Sending the frame takes as long as its payload does at the rate limit, every time it's sent
+fn pacing(frame: &Frame, options: &LinkOptions) -> Option<Duration> {
+    options.rate_limit.map(|rate| Duration::from_nanos(frame.payload.len() as u64 * 1_000_000_000 / rate.max(1)))
+}
+
+fn receive(link: &mut dyn Transport, round: u32, options: &LinkOptions) -> Result<Frame, TransportError> {
+    // Time since the last frame arrived, and whether this end has pinged the other since
+    let mut silence = Duration::ZERO;
+    let mut pinged = false;
+    let started = link.now();
+    let patience = options.patience.max(options.timeout);
+    loop {
+        let waited = link.now() - started;
+        if waited >= patience {
+            return Err(TransportError::Timeout);
+        }
+        let remaining = options.timeout.saturating_sub(silence).min(patience - waited);
+        let wait = options.heartbeat.map_or(remaining, |interval| interval.min(remaining));
+        let frame = match link.recv(wait) {
+            Ok(frame) => frame,
+            Err(TransportError::Timeout) if wait < remaining => {
+                silence += wait;
+                if !pinged {
+                    link.send(Frame::ping(round))?;
+                    pinged = true;
+                }
+                continue;
+            },
+            Err(error) => return Err(error),
+        };
+        if !frame.is_intact() {
+            return Err(TransportError::Corrupt);
+        }
+        silence = Duration::ZERO;
+        pinged = false;
+        match frame.kind {
+            FrameKind::Ping => {
+                link.send(Frame::pong(frame.round))?;
+                continue;
+            },
+            // A nack for a message this end already resent, or a late one it no longer has to answer
+            FrameKind::Pong | FrameKind::Nack => continue,
+            FrameKind::Message => (),
+        }
+        // A retrying run may still receive copies of frames it already has, and ignores them. Each one
+        // restarts the wait.
+        if frame.round < round && options.policy != RetryPolicy::Strict {
+            continue;
+        }
+        if frame.round != round {
+            return Err(TransportError::OutOfOrder { expected: round, found: frame.round });
+        }
+        return Ok(frame);
+    }
+}
+
+// Runs f, answering pings on the link with pongs from another thread until it returns, so that the other
+// end knows this one is alive. Every other frame arriving meanwhile is dropped, so f must be the work
+// the other end is waiting on: run_over_transport's ends send nothing but pings and resent copies of
+// messages already received while they wait, but run_streamed's verifier sends the messages of later
+// repetitions, which keepalive would lose.
+pub fn keepalive<L: Transport + Send, R, F: FnOnce() -> R>(link: &mut L, poll: Duration, f: F) -> R {
+    let finished = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                let pong = match link.recv(poll) {
+                    Ok(frame) if frame.kind == FrameKind::Ping && frame.is_intact() => Frame::pong(frame.round),
+                    Err(TransportError::Closed) => return,
+                    _ => continue,
+                };
+                if link.send(pong).is_err() {
+                    return;
+                }
+            }
+        });
+        let result = f();
+        finished.store(true, Ordering::SeqCst);
+        result
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn transport_test_instance() -> GraphPair {
+    GraphPair {
+        g0: Graph::new(4, vec![(0, 1), (1, 2), (1, 3), (0, 3), (3, 0)]),
+        g1: Graph::new(4, vec![(2, 1), (1, 0), (1, 3), (2, 3), (3, 2)]),
+    }
+}
+
+#[test]
+fn test_frame_checksum() {
+    let frame = Frame::new(3, b"message".to_vec());
+    assert!(frame.is_intact());
+    let mut damaged = frame.clone();
+    damaged.payload[0] ^= 1;
+    assert!(!damaged.is_intact());
+    let mut moved = frame;
+    moved.round = 4;
+    assert!(!moved.is_intact());
+}
+
+#[test]
+fn test_loopback() {
+    let instance = transport_test_instance();
+    let (mut prover_link, mut verifier_link) = loopback();
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &LinkOptions::default()), Ok(true));
+    // Nothing was left in flight, so the next wait times out
+    assert_eq!(prover_link.recv(Duration::from_millis(1)), Err(TransportError::Timeout));
+    drop(verifier_link);
+    assert_eq!(prover_link.send(Frame::new(1, Vec::new())), Err(TransportError::Closed));
+}
+
+#[test]
+fn test_keepalive() {
+    let (mut prover_link, mut verifier_link) = loopback();
+    // The prover's end answers a ping while it works, and drops anything else
+    let pong = keepalive(&mut prover_link, Duration::from_millis(5), || {
+        verifier_link.send(Frame::ping(1)).unwrap();
+        verifier_link.recv(Duration::from_secs(5))
+    });
+    assert_eq!(pong, Ok(Frame::pong(1)));
+    assert_eq!(prover_link.recv(Duration::ZERO), Err(TransportError::Timeout));
+}
+
+#[test]
+fn test_backpressure() {
+    let options = LinkOptions { timeout: Duration::from_millis(20), buffer: NonZeroUsize::new(2), ..LinkOptions::default() };
+    let (mut a, mut b) = loopback_with(&options);
+    // A full buffer makes the sender wait, and give up once the timeout passes
+    for round in 1..=2 {
+        assert_eq!(a.send(Frame::new(round, Vec::new())), Ok(()));
+    }
+    assert_eq!(a.send(Frame::new(3, Vec::new())), Err(TransportError::Timeout));
+    // A reader draining the buffer lets every frame through in order
+    let reader = thread::spawn(move || (0..6).map(|_| b.recv(Duration::from_secs(1)).unwrap().round).collect::<Vec<u32>>());
+    for round in 3..=6 {
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(a.send(Frame::new(round, Vec::new())), Ok(()));
+    }
+    assert_eq!(reader.join().unwrap(), [1, 2, 3, 4, 5, 6]);
+
+    // A runner never has more than one message in flight to an end, so the smallest buffer is enough
+    let instance = transport_test_instance();
+    let (mut prover_link, mut verifier_link) = loopback_with(&LinkOptions { buffer: NonZeroUsize::new(1), ..options });
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &options), Ok(true));
+}
+
+// ************ Wire vector implementation ************
+
+#[cfg(test)]
+const WIRE_VECTORS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors/wire.json");
+
+#[cfg(test)]
+fn message_frame<M: Serialize>(round: u32, msg: &M) -> Frame {
+    Frame::new(round, bincode::serialize(msg).unwrap())
+}
+
+// Every vector in tests/vectors/wire.json. The session is a GI run on transport_test_instance where the
+// prover sends a fixed permutation of g1 and the verifier challenges it with g1, framed as
+// run_over_transport sends it.
+#[cfg(test)]
+fn wire_vectors() -> serde_json::Value {
+    let instance = transport_test_instance();
+    let permutation = Permutation::new(vec![2, 0, 3, 1]).unwrap();
+    let permuted = instance.g1.permute(&permutation);
+    let isomorphism = permutation.invert();
+    let challenge = GIVerifierMessage { b: true };
+    let named = |name: &str, frame: Frame| serde_json::json!({ "name": name, "frame": to_hex(&frame.to_bytes()) });
+    let frames = vec![
+        named("gi verifier message b = false", message_frame(1, &GIVerifierMessage { b: false })),
+        named("gi verifier message b = true", message_frame(2, &challenge)),
+        named("gi prover graph", message_frame(1, &GIProverMessage::Graph(permuted.clone()))),
+        named("gi prover isomorphism", message_frame(2, &GIProverMessage::Isomorphism(isomorphism.clone()))),
+        named("gi prover done", message_frame(3, &GIProverMessage::Done)),
+        named("ping", Frame::ping(7)),
+        named("pong", Frame::pong(7)),
+        named("nack", Frame::nack(7)),
+    ];
+    let mut damaged = message_frame(1, &GIVerifierMessage { b: false });
+    damaged.payload[0] ^= 1;
+    let mut truncated = message_frame(1, &GIVerifierMessage { b: false }).to_bytes();
+    truncated.pop();
+    let rejected = vec![
+        named("payload doesn't match checksum", damaged),
+        serde_json::json!({ "name": "truncated checksum", "frame": to_hex(&truncated) }),
+    ];
+    // The prover ends the run after the verifier's third message, so Done is never sent
+    let session = [
+        ("verifier", message_frame(1, &GIVerifierMessage { b: false })),
+        ("prover", message_frame(1, &GIProverMessage::Graph(permuted))),
+        ("verifier", message_frame(2, &challenge)),
+        ("prover", message_frame(2, &GIProverMessage::Isomorphism(isomorphism))),
+        ("verifier", message_frame(3, &challenge)),
+    ];
+    serde_json::json!({
+        "frames": frames,
+        "rejected": rejected,
+        "session": {
+            "instance": instance,
+            "accept": true,
+            "frames": session.iter().map(|(from, frame)| serde_json::json!({ "from": from, "frame": to_hex(&frame.to_bytes()) })).collect::<Vec<_>>(),
+        },
+    })
+}
+
+#[cfg(test)]
+fn from_hex(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+// Checks the committed vectors against the current wire format. ZKLIB_UPDATE_VECTORS=1 rewrites them
+// instead, after a deliberate change to the format.
+#[test]
+fn test_wire_vectors() {
+    let vectors = wire_vectors();
+    if std::env::var_os("ZKLIB_UPDATE_VECTORS").is_some() {
+        std::fs::write(WIRE_VECTORS, serde_json::to_string_pretty(&vectors).unwrap() + "\n").unwrap();
+    }
+    let committed: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(WIRE_VECTORS).unwrap()).unwrap();
+    assert_eq!(committed, vectors, "Wire format differs from tests/vectors/wire.json");
+    let frame = |vector: &serde_json::Value| Frame::from_bytes(&from_hex(vector["frame"].as_str().unwrap()));
+    for vector in committed["frames"].as_array().unwrap() {
+        let parsed = frame(vector).unwrap();
+        assert!(parsed.is_intact());
+        assert_eq!(to_hex(&parsed.to_bytes()), vector["frame"]);
+    }
+    assert!(committed["rejected"].as_array().unwrap().iter().all(|vector| !frame(vector).is_some_and(|frame| frame.is_intact())));
+    // Replaying the session's prover messages with its challenges convinces a verifier
+    let instance: GraphPair = serde_json::from_value(committed["session"]["instance"].clone()).unwrap();
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let session: Vec<Frame> = committed["session"]["frames"].as_array().unwrap().iter().map(|vector| frame(vector).unwrap()).collect();
+    let mut accept = false;
+    for (i, pair) in session.chunks(2).enumerate() {
+        assert_eq!(pair[0].round, i as u32 + 1);
+        if let [_, prover_frame] = pair {
+            let msg: GIProverMessage = bincode::deserialize(&prover_frame.payload).unwrap();
+            let challenge: GIVerifierMessage = bincode::deserialize(&session[2 * i + 2].payload).unwrap();
+            accept = verifier.handle_with(&msg, Some(challenge.b)).1;
+        }
+    }
+    assert_eq!(Some(accept), committed["session"]["accept"].as_bool());
+}
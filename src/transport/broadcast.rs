@@ -0,0 +1,200 @@
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::{Prover, ValidationError};
+use crate::beacon::{challenge_from_beacon, BeaconValue, PublicCoinVerifier, RandomnessBeacon};
+use super::{exchange, LinkError, LinkOptions, Transport};
+#[cfg(test)]
+use std::time::Duration;
+#[cfg(test)]
+use crate::Verifier;
+#[cfg(test)]
+use crate::beacon::LocalBeacon;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair};
+#[cfg(test)]
+use super::mock::{pair, MockTransport};
+#[cfg(test)]
+use super::transport_test_instance;
+
+// ************ Broadcast runner implementation ************
+
+// A prover can convince several verifiers at once, e.g. auditors who each want to check a proof
+// themselves, by running one interaction over a separate link to each. Every verifier draws its
+// challenges from the same beacon value, so they all send the prover the same messages and the prover
+// answers them once, with its message sent to every verifier. The runner checks that the verifiers'
+// messages agree before the prover sees them, since a prover answering different challenges on different
+// links could leak its witness. Each verifier reaches its own verdict.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BroadcastError {
+    // The runner was given no verifiers
+    NoVerifiers,
+    // The verifiers weren't given one link of each kind apiece
+    Mismatched { verifiers: usize, prover_links: usize, verifier_links: usize },
+    // The link to the given verifier failed, or a message on it was invalid
+    Link { verifier: usize, error: LinkError },
+    Beacon(String),
+    // The given verifier sent a different message from the first verifier on the given round
+    Inconsistent { round: u32, verifier: usize },
+}
+
+impl fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BroadcastError::NoVerifiers => write!(f, "At least one verifier is needed"),
+            BroadcastError::Mismatched { verifiers, prover_links, verifier_links } =>
+                write!(f, "Every verifier needs one link of each kind, got {} verifiers, {} prover links and {} verifier links", verifiers, prover_links, verifier_links),
+            BroadcastError::Link { verifier, error } => write!(f, "Link to verifier {} failed: {}", verifier, error),
+            BroadcastError::Beacon(reason) => write!(f, "Randomness beacon failed: {}", reason),
+            BroadcastError::Inconsistent { round, verifier } => write!(f, "Verifier {} sent a different message from verifier 0 on round {}", verifier, round),
+        }
+    }
+}
+
+// Each verifier's verdict, in the order of the verifiers, along with the beacon values the challenges
+// were derived from
+#[derive(Clone, Debug, PartialEq)]
+pub struct BroadcastRun {
+    pub accepts: Vec<bool>,
+    pub beacon_values: Vec<BeaconValue>,
+}
+
+impl BroadcastRun {
+    pub fn all_accept(&self) -> bool {
+        self.accepts.iter().all(|&accept| accept)
+    }
+}
+
+// Plays one interaction between the prover and every verifier, where verifier i talks to the prover over
+// verifier_links[i], whose other end is prover_links[i]
+pub fn run_broadcast<P, V>(prover: &mut P, verifiers: &mut [V], prover_links: &mut [&mut dyn Transport], verifier_links: &mut [&mut dyn Transport],
+    beacon: &mut dyn RandomnessBeacon, options: &LinkOptions) -> Result<BroadcastRun, BroadcastError>
+where P: Prover, V: PublicCoinVerifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>,
+    P::ProverMessage: Serialize + DeserializeOwned, P::VerifierMessage: Serialize + DeserializeOwned {
+    if verifiers.is_empty() {
+        return Err(BroadcastError::NoVerifiers);
+    }
+    if prover_links.len() != verifiers.len() || verifier_links.len() != verifiers.len() {
+        return Err(BroadcastError::Mismatched { verifiers: verifiers.len(), prover_links: prover_links.len(), verifier_links: verifier_links.len() });
+    }
+    let mut verifier_msgs: Vec<P::VerifierMessage> = verifiers.iter_mut().map(|verifier| verifier.init()).collect();
+    let mut accepts = vec![false; verifiers.len()];
+    let mut beacon_values = Vec::new();
+    let mut round = 1;
+
+    loop {
+        // The prover handles the first verifier's message once every other one matches it
+        let mut received: Option<(P::VerifierMessage, Vec<u8>)> = None;
+        for (i, msg) in verifier_msgs.iter().enumerate() {
+            let msg = exchange(verifier_links[i], prover_links[i], round, msg, options).map_err(|error| BroadcastError::Link { verifier: i, error })?;
+            let encoded = bincode::serialize(&msg).unwrap();
+            match &received {
+                Some((_, first)) if *first != encoded => return Err(BroadcastError::Inconsistent { round, verifier: i }),
+                Some(_) => (),
+                None => received = Some((msg, encoded)),
+            }
+        }
+        let (verifier_msg, _) = received.unwrap();
+        prover.validate(&verifier_msg).map_err(|reason| BroadcastError::Link { verifier: 0, error: LinkError::Invalid(ValidationError::VerifierMessage(round, reason)) })?;
+        let (prover_msg, done) = prover.handle(&verifier_msg);
+        if done {
+            break;
+        }
+        // One beacon value per round, shared by every verifier that sends a challenge
+        let value = if verifiers.iter().any(|verifier| verifier.challenge_space().is_some()) {
+            Some(beacon.next_value().map_err(BroadcastError::Beacon)?)
+        } else {
+            None
+        };
+        for (i, verifier) in verifiers.iter_mut().enumerate() {
+            let link_error = |error| BroadcastError::Link { verifier: i, error };
+            let msg = exchange(prover_links[i], verifier_links[i], round, &prover_msg, options).map_err(link_error)?;
+            verifier.validate(&msg).map_err(|reason| link_error(LinkError::Invalid(ValidationError::ProverMessage(round, reason))))?;
+            let challenge = verifier.challenge_space().zip(value.as_ref()).map(|(space, value)| challenge_from_beacon(value, &space));
+            let (reply, accept) = verifier.handle_with(&msg, challenge);
+            verifier_msgs[i] = reply;
+            accepts[i] = accept;
+        }
+        beacon_values.extend(value);
+        round += 1;
+    }
+
+    Ok(BroadcastRun { accepts, beacon_values })
+}
+
+// A GI verifier that can answer with the opposite of the challenge it is given
+#[cfg(test)]
+struct FlippingVerifier<'a> {
+    inner: GIVerifier<'a>,
+    flip: bool,
+}
+
+#[cfg(test)]
+impl Verifier for FlippingVerifier<'_> {
+    type ProverMessage = GIProverMessage;
+    type VerifierMessage = GIVerifierMessage;
+
+    fn init(&mut self) -> GIVerifierMessage {
+        self.inner.init()
+    }
+
+    fn handle(&mut self, msg: &GIProverMessage) -> (GIVerifierMessage, bool) {
+        self.inner.handle(msg)
+    }
+}
+
+#[cfg(test)]
+impl PublicCoinVerifier for FlippingVerifier<'_> {
+    type Challenge = bool;
+
+    fn challenge_space(&self) -> Option<()> {
+        self.inner.challenge_space()
+    }
+
+    fn handle_with(&mut self, msg: &GIProverMessage, challenge: Option<bool>) -> (GIVerifierMessage, bool) {
+        self.inner.handle_with(msg, challenge.map(|b| b != self.flip))
+    }
+}
+
+#[cfg(test)]
+fn broadcast_gi<V>(instance: &GraphPair, verifiers: &mut [V]) -> (Result<BroadcastRun, BroadcastError>, Vec<MockTransport>)
+where V: PublicCoinVerifier<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> {
+    let options = LinkOptions { timeout: Duration::from_millis(100), ..LinkOptions::default() };
+    let (mut prover_links, mut verifier_links): (Vec<MockTransport>, Vec<MockTransport>) = verifiers.iter().map(|_| pair(Duration::from_millis(30))).unzip();
+    let mut prover = GIProver::new(instance);
+    let result = run_broadcast(&mut prover, verifiers, &mut prover_links.iter_mut().map(|x| x as &mut dyn Transport).collect::<Vec<_>>(),
+        &mut verifier_links.iter_mut().map(|x| x as &mut dyn Transport).collect::<Vec<_>>(), &mut LocalBeacon::default(), &options);
+    (result, prover_links)
+}
+
+#[test]
+fn test_broadcast() {
+    let instance = transport_test_instance();
+    let verifier = || GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let mut verifiers = [verifier(), verifier(), verifier()];
+    let (run, prover_links) = broadcast_gi(&instance, &mut verifiers);
+    let run = run.unwrap();
+    assert_eq!(run.accepts, [true; 3]);
+    assert!(run.all_accept());
+    // Every verifier sent the challenge derived from the single beacon value, and got the same messages
+    assert_eq!(run.beacon_values.len(), 1);
+    let b = challenge_from_beacon::<bool>(&run.beacon_values[0], &());
+    assert!(verifiers.iter().all(|verifier| verifier.b == b));
+    assert!(prover_links.iter().all(|link| link.sent == prover_links[0].sent));
+
+    // A verifier whose challenge differs from the others' is caught before the prover answers it
+    let mut verifiers = [FlippingVerifier { inner: verifier(), flip: false }, FlippingVerifier { inner: verifier(), flip: true }];
+    let (run, prover_links) = broadcast_gi(&instance, &mut verifiers);
+    assert_eq!(run, Err(BroadcastError::Inconsistent { round: 2, verifier: 1 }));
+    assert_eq!(prover_links[0].sent.len(), 1);
+
+    // Verifiers the runner can't pair with links are refused before anything is sent
+    let (run, prover_links) = broadcast_gi(&instance, &mut Vec::<GIVerifier>::new());
+    assert_eq!((run, prover_links.len()), (Err(BroadcastError::NoVerifiers), 0));
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    let run = run_broadcast(&mut GIProver::new(&instance), &mut [verifier(), verifier()], &mut [&mut prover_link], &mut [&mut verifier_link],
+        &mut LocalBeacon::default(), &LinkOptions::default());
+    assert_eq!(run, Err(BroadcastError::Mismatched { verifiers: 2, prover_links: 1, verifier_links: 1 }));
+    assert!(prover_link.sent.is_empty() && verifier_link.sent.is_empty());
+}
@@ -0,0 +1,354 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::time::Duration;
+use super::{Frame, FrameKind, LinkOptions, Transport, TransportError};
+#[cfg(test)]
+use super::{receive, run_over_transport, run_timed_over_transport, transport_test_instance, LinkError, RetryPolicy};
+#[cfg(test)]
+use crate::graph::{GIProver, GIVerifier, Graph};
+
+// ************ Mock transport implementation ************
+
+// Two ends of a simulated link for testing how runners cope with a network. Time on the link is virtual:
+// a frame is delivered once the link's latency has passed, waiting for a frame advances the clock to its
+// delivery or to the end of the timeout, and nothing depends on the wall clock or on threads, so every
+// run of a test sees the same events. Each end can be programmed with a list of faults, applied in order
+// to the frames it sends. A link from pair_with buffers messages like loopback_with, and since nothing can
+// read from the other end while this one waits for room, a send to a full buffer times out at once.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // Delivered after the link's latency
+    Deliver,
+    // Never delivered
+    Drop,
+    // Delivered after the link's latency plus the given delay
+    Delay(Duration),
+    // Held back until the next frame sent the same way, and delivered right after it
+    Reorder,
+    // Delivered with a byte flipped
+    Corrupt,
+}
+
+struct InFlight {
+    deliver_at: Duration,
+    // Order in which frames were put in flight, which breaks ties between equal delivery times
+    seq: u64,
+    frame: Frame,
+}
+
+#[derive(Default)]
+struct Network {
+    now: Duration,
+    seq: u64,
+    // Frames in flight to each end, and the frame held back for each by a Reorder fault
+    in_flight: [Vec<InFlight>; 2],
+    held: [Option<Frame>; 2],
+}
+
+impl Network {
+    fn deliver(&mut self, to: usize, frame: Frame, delay: Duration) {
+        self.seq += 1;
+        let (deliver_at, seq) = (self.now + delay, self.seq);
+        self.in_flight[to].push(InFlight { deliver_at, seq, frame });
+    }
+}
+
+pub struct MockTransport {
+    // Index of this end, 0 or 1
+    end: usize,
+    network: Rc<RefCell<Network>>,
+    pub latency: Duration,
+    // Faults for the next frames this end sends, with every frame delivered once they run out
+    pub faults: VecDeque<Fault>,
+    // Every frame this end sent, whatever happened to it on the way
+    pub sent: Vec<Frame>,
+    // Most unread messages the other end holds, and the longest a sender waits for room, if bounded
+    capacity: Option<(NonZeroUsize, Duration)>,
+}
+
+// Two ends of a link with the same latency each way
+pub fn pair(latency: Duration) -> (MockTransport, MockTransport) {
+    link(latency, None)
+}
+
+// Two ends of a link with the same latency each way, which each buffer the number of messages in the
+// options, if any
+pub fn pair_with(latency: Duration, options: &LinkOptions) -> (MockTransport, MockTransport) {
+    link(latency, options.buffer.map(|frames| (frames, options.timeout)))
+}
+
+fn link(latency: Duration, capacity: Option<(NonZeroUsize, Duration)>) -> (MockTransport, MockTransport) {
+    let network = Rc::new(RefCell::new(Network::default()));
+    let end = |end| MockTransport { end, network: network.clone(), latency, faults: VecDeque::new(), sent: Vec::new(), capacity };
+    (end(0), end(1))
+}
+
+impl MockTransport {
+    // Queues faults for the next frames this end sends
+    pub fn program<I: IntoIterator<Item = Fault>>(&mut self, faults: I) {
+        self.faults.extend(faults);
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&mut self, frame: Frame) -> Result<(), TransportError> {
+        let mut network = self.network.borrow_mut();
+        let to = 1 - self.end;
+        if let (Some((messages, timeout)), FrameKind::Message) = (self.capacity, frame.kind) {
+            let waiting = network.in_flight[to].iter().map(|x| &x.frame).chain(&network.held[to]).filter(|frame| frame.kind == FrameKind::Message).count();
+            if waiting >= messages.get() {
+                network.now += timeout;
+                return Err(TransportError::Timeout);
+            }
+        }
+        self.sent.push(frame.clone());
+        match self.faults.pop_front().unwrap_or(Fault::Deliver) {
+            Fault::Deliver => network.deliver(to, frame, self.latency),
+            Fault::Drop => (),
+            Fault::Delay(delay) => network.deliver(to, frame, self.latency + delay),
+            Fault::Reorder => {
+                // Only one frame is held back at a time, so an earlier one goes out now
+                if let Some(held) = network.held[to].replace(frame) {
+                    network.deliver(to, held, self.latency);
+                }
+                return Ok(());
+            },
+            Fault::Corrupt => {
+                let mut frame = frame;
+                match frame.payload.last_mut() {
+                    Some(byte) => *byte ^= 1,
+                    None => frame.checksum[0] ^= 1,
+                }
+                network.deliver(to, frame, self.latency);
+            },
+        }
+        if let Some(held) = network.held[to].take() {
+            network.deliver(to, held, self.latency);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Result<Frame, TransportError> {
+        let mut network = self.network.borrow_mut();
+        let deadline = network.now + timeout;
+        let next = network.in_flight[self.end].iter().enumerate().min_by_key(|(_, x)| (x.deliver_at, x.seq)).map(|(i, x)| (i, x.deliver_at));
+        match next {
+            Some((i, deliver_at)) if deliver_at <= deadline => {
+                network.now = network.now.max(deliver_at);
+                Ok(network.in_flight[self.end].remove(i).frame)
+            },
+            _ => {
+                network.now = deadline;
+                Err(TransportError::Timeout)
+            },
+        }
+    }
+
+    fn pause(&mut self, duration: Duration) {
+        self.network.borrow_mut().now += duration;
+    }
+
+    // Virtual time since the link was created
+    fn now(&self) -> Duration {
+        self.network.borrow().now
+    }
+}
+
+#[test]
+fn test_mock_latency() {
+    let (mut a, mut b) = pair(Duration::from_millis(50));
+    a.send(Frame::new(1, vec![1])).unwrap();
+    // The frame hasn't arrived after 20ms, and arrives 30ms later
+    assert_eq!(b.recv(Duration::from_millis(20)), Err(TransportError::Timeout));
+    assert_eq!(b.now(), Duration::from_millis(20));
+    assert_eq!(b.recv(Duration::from_millis(100)), Ok(Frame::new(1, vec![1])));
+    assert_eq!(a.now(), Duration::from_millis(50));
+}
+
+#[test]
+fn test_mock_faults() {
+    let (mut a, mut b) = pair(Duration::from_millis(10));
+    let wait = Duration::from_secs(1);
+    a.program([Fault::Drop, Fault::Reorder, Fault::Deliver, Fault::Corrupt, Fault::Delay(Duration::from_secs(2))]);
+    for round in 1..=6 {
+        a.send(Frame::new(round, vec![round as u8])).unwrap();
+    }
+    // Frame 1 is dropped, 2 arrives after 3, 4 is damaged, and 5 arrives after 6
+    let rounds: Vec<u32> = (0..4).map(|_| b.recv(wait).unwrap().round).collect();
+    assert_eq!(rounds, [3, 2, 4, 6]);
+    assert_eq!(b.recv(wait), Err(TransportError::Timeout));
+    assert_eq!(b.recv(wait).map(|frame| frame.round), Ok(5));
+    assert_eq!(a.sent.len(), 6);
+    let (mut a, mut b) = pair(Duration::from_millis(10));
+    a.program([Fault::Corrupt]);
+    a.send(Frame::new(1, vec![7])).unwrap();
+    assert!(!b.recv(wait).unwrap().is_intact());
+}
+
+#[test]
+fn test_mock_heartbeat() {
+    let options = LinkOptions { timeout: Duration::from_millis(100), heartbeat: Some(Duration::from_millis(20)), patience: Duration::from_secs(1), ..LinkOptions::default() };
+    // A prover thinking for three times the timeout, with or without answering pings every 20ms
+    let slow_prover = |alive: bool, think: u64| {
+        let (mut prover_link, mut verifier_link) = pair(Duration::ZERO);
+        for at in (20..think).step_by(20).filter(|_| alive) {
+            prover_link.program([Fault::Delay(Duration::from_millis(at))]);
+            prover_link.send(Frame::pong(1)).unwrap();
+        }
+        prover_link.program([Fault::Delay(Duration::from_millis(think))]);
+        prover_link.send(Frame::new(1, b"reply".to_vec())).unwrap();
+        let result = receive(&mut verifier_link, 1, &options).map(|frame| frame.payload);
+        (result, verifier_link.now())
+    };
+    assert_eq!(slow_prover(true, 300), (Ok(b"reply".to_vec()), Duration::from_millis(300)));
+    assert_eq!(slow_prover(false, 300), (Err(TransportError::Timeout), Duration::from_millis(100)));
+    // Pongs alone keep the verifier waiting no longer than its patience
+    assert_eq!(slow_prover(true, 5000), (Err(TransportError::Timeout), Duration::from_secs(1)));
+}
+
+#[test]
+fn test_mock_buffer() {
+    let options = LinkOptions { timeout: Duration::from_millis(100), buffer: NonZeroUsize::new(1), ..LinkOptions::default() };
+    let (mut a, mut b) = pair_with(Duration::from_millis(10), &options);
+    a.send(Frame::new(1, Vec::new())).unwrap();
+    // Nothing drains the full buffer while the sender waits, so it gives up after the timeout
+    assert_eq!(a.send(Frame::new(2, Vec::new())), Err(TransportError::Timeout));
+    assert_eq!(a.now(), Duration::from_millis(100));
+    assert_eq!(b.recv(Duration::ZERO).map(|frame| frame.round), Ok(1));
+    assert_eq!(a.send(Frame::new(2, Vec::new())), Ok(()));
+    assert_eq!(a.sent.len(), 2);
+
+    // A runner fits in the smallest buffer, which pings and pongs don't take up
+    let instance = transport_test_instance();
+    let heartbeat = LinkOptions { heartbeat: Some(Duration::from_millis(5)), ..options };
+    for options in [options, heartbeat] {
+        let (mut prover_link, mut verifier_link) = pair_with(Duration::from_millis(30), &options);
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &options), Ok(true));
+        assert_eq!(prover_link.now(), Duration::from_millis(150));
+    }
+}
+
+#[test]
+fn test_mock_runner() {
+    let instance = transport_test_instance();
+    let options = LinkOptions { timeout: Duration::from_millis(100), ..LinkOptions::default() };
+    let run = |prover_link: &mut MockTransport, verifier_link: &mut MockTransport| {
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        run_over_transport(&mut prover, &mut verifier, prover_link, verifier_link, &options)
+    };
+    // Each of the 5 messages, up to the verifier's reply to the last one, takes 30ms on a link within the timeout
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    assert_eq!(run(&mut prover_link, &mut verifier_link), Ok(true));
+    assert_eq!(prover_link.now(), Duration::from_millis(150));
+    // With a heartbeat shorter than the latency, each end pings while it waits, and the pings are answered
+    // and ignored without affecting the run
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let heartbeat = LinkOptions { heartbeat: Some(Duration::from_millis(20)), ..options };
+    assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &heartbeat), Ok(true));
+    assert!(prover_link.sent.iter().chain(&verifier_link.sent).any(|frame| frame.kind == FrameKind::Ping));
+    // A rate limit adds the time each payload takes to send at 1000 bytes per second
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let limited = LinkOptions { rate_limit: Some(1000), ..options };
+    assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &limited), Ok(true));
+    let bytes: usize = prover_link.sent.iter().chain(&verifier_link.sent).map(|frame| frame.payload.len()).sum();
+    assert_eq!(prover_link.now().as_micros(), 150_000 + 1000 * bytes as u128);
+    // A link slower than the timeout fails on the first message
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(200));
+    assert_eq!(run(&mut prover_link, &mut verifier_link), Err(LinkError::Transport { round: 1, error: TransportError::Timeout }));
+    // A prover message lost or damaged on the way fails the round it belongs to
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Deliver, Fault::Drop]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link), Err(LinkError::Transport { round: 2, error: TransportError::Timeout }));
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Corrupt]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link), Err(LinkError::Transport { round: 1, error: TransportError::Corrupt }));
+}
+
+#[test]
+fn test_mock_retry() {
+    let instance = transport_test_instance();
+    let run = |prover_link: &mut MockTransport, verifier_link: &mut MockTransport, policy: RetryPolicy| {
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        let result = run_over_transport(&mut prover, &mut verifier, prover_link, verifier_link, &LinkOptions { timeout: Duration::from_millis(100), policy, ..LinkOptions::default() });
+        // The prover handles each message once, however many copies arrive
+        (result, prover.r)
+    };
+    let retry = RetryPolicy::Retry { attempts: 2 };
+    // A lost prover message is resent after the verifier's wait times out
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Deliver, Fault::Drop]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, retry), (Ok(true), 3));
+    assert_eq!(prover_link.sent.len(), 3);
+    // The prover only learns of the loss from the verifier's nack, and a lost nack is sent again
+    assert_eq!(verifier_link.sent.iter().filter(|frame| frame.kind == FrameKind::Nack).count(), 1);
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Deliver, Fault::Drop]);
+    verifier_link.program([Fault::Deliver, Fault::Deliver, Fault::Drop]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, retry), (Ok(true), 3));
+    assert_eq!(verifier_link.sent.iter().filter(|frame| frame.kind == FrameKind::Nack).collect::<Vec<_>>(), [&Frame::nack(2); 2]);
+    assert_eq!(prover_link.sent.len(), 3);
+    // A late verifier message is resent, and the late copy is discarded when it arrives on the next round
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    verifier_link.program([Fault::Delay(Duration::from_millis(120))]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, retry), (Ok(true), 3));
+    assert_eq!(verifier_link.sent[0], verifier_link.sent[1]);
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    verifier_link.program([Fault::Delay(Duration::from_millis(120))]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, RetryPolicy::Strict).0, Err(LinkError::Transport { round: 1, error: TransportError::Timeout }));
+    // Damaged frames are resent too, up to the number of attempts
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Corrupt, Fault::Corrupt]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, retry), (Ok(true), 3));
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Corrupt; 3]);
+    assert_eq!(run(&mut prover_link, &mut verifier_link, retry).0, Err(LinkError::Transport { round: 1, error: TransportError::Corrupt }));
+}
+
+#[test]
+fn test_mock_deadline() {
+    let instance = transport_test_instance();
+    let options = LinkOptions { timeout: Duration::from_millis(100), deadline: Some(Duration::from_millis(50)), ..LinkOptions::default() };
+    let run = |prover_link: &mut MockTransport, verifier_link: &mut MockTransport| {
+        let mut prover = GIProver::new(&instance);
+        let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+        run_timed_over_transport(&mut prover, &mut verifier, prover_link, verifier_link, &options).unwrap()
+    };
+    // Each reply takes a round trip
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(20));
+    let timed = run(&mut prover_link, &mut verifier_link);
+    assert!(timed.accept);
+    assert_eq!(timed.response_times, [Duration::from_millis(40); 2]);
+    assert_eq!(timed.late, None);
+    // The verifier stops waiting for a slow reply at the deadline, and rejects
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(20));
+    prover_link.program([Fault::Deliver, Fault::Delay(Duration::from_millis(30))]);
+    let timed = run(&mut prover_link, &mut verifier_link);
+    assert!(!timed.accept);
+    assert_eq!(timed.late, Some(2));
+    assert_eq!(timed.response_times, [Duration::from_millis(40), Duration::from_millis(50)]);
+    // Without a deadline the slow reply is accepted
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(20));
+    prover_link.program([Fault::Deliver, Fault::Delay(Duration::from_millis(30))]);
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    assert_eq!(run_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &LinkOptions { deadline: None, ..options }), Ok(true));
+    // A reply lost well before the deadline is a link failure when the timeout runs out first, not a late reply
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(20));
+    prover_link.program([Fault::Drop]);
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let patient = LinkOptions { deadline: Some(Duration::from_secs(1)), ..options };
+    let timed = run_timed_over_transport(&mut prover, &mut verifier, &mut prover_link, &mut verifier_link, &patient);
+    assert_eq!(timed, Err(LinkError::Transport { round: 1, error: TransportError::Timeout }));
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use super::{receive, Frame, LinkError, LinkOptions, Transport};
+#[cfg(test)]
+use std::thread;
+#[cfg(test)]
+use std::time::Duration;
+#[cfg(test)]
+use crate::{Prover, Verifier};
+#[cfg(test)]
+use crate::beacon::PublicCoinVerifier;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GraphPair};
+#[cfg(test)]
+use super::{loopback, transport_test_instance, LoopbackTransport, TransportError};
+
+// ************ Verifier quorum implementation ************
+
+// A deployment that doesn't trust any single verifier machine can have several check each transcript.
+// The coordinator sends the transcript of an interaction, e.g. a finished session's messages, to n
+// verifier nodes over a link to each, and accepts if at least m of them accept. A node that fails to
+// answer in time, or whose link fails, counts as not accepting, so a quorum of m tolerates n - m nodes
+// that are down or that reject wrongly, and a wrong accept needs m nodes to collude.
+//
+// The transcript travels in a single frame, and each node answers with a single frame holding its vote.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuorumError {
+    // The quorum needs no accepts, or more accepts than there are nodes
+    Threshold { threshold: usize, nodes: usize },
+    // The transcript couldn't be encoded to send
+    Encoding(String),
+}
+
+impl fmt::Display for QuorumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuorumError::Threshold { threshold, nodes } => write!(f, "A quorum of {} out of {} nodes is out of range, expected 1 to {}", threshold, nodes, nodes),
+            QuorumError::Encoding(reason) => write!(f, "Transcript could not be encoded: {}", reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuorumVerdict {
+    // Accepts needed
+    pub threshold: usize,
+    // Each node's vote, in the order of the links, or how its link failed
+    pub votes: Vec<Result<bool, LinkError>>,
+    pub accept: bool,
+}
+
+impl QuorumVerdict {
+    pub fn accepted(&self) -> usize {
+        self.votes.iter().filter(|vote| vote == &&Ok(true)).count()
+    }
+}
+
+impl fmt::Display for QuorumVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.votes.iter().filter(|vote| vote.is_err()).count();
+        write!(f, "{} with {} of {} nodes accepting, {} needed", if self.accept { "Accepted" } else { "Rejected" }, self.accepted(), self.votes.len(), self.threshold)?;
+        match failed {
+            0 => Ok(()),
+            _ => write!(f, " ({} failed to vote)", failed),
+        }
+    }
+}
+
+// Sends the transcript to every node, and then collects their votes, so that the nodes check it in
+// parallel
+pub fn run_quorum<T: Serialize>(transcript: &T, links: &mut [&mut dyn Transport], threshold: usize, options: &LinkOptions) -> Result<QuorumVerdict, QuorumError> {
+    if threshold == 0 || threshold > links.len() {
+        return Err(QuorumError::Threshold { threshold, nodes: links.len() });
+    }
+    let frame = Frame::new(1, bincode::serialize(transcript).map_err(|e| QuorumError::Encoding(e.to_string()))?);
+    let sent: Vec<Result<(), LinkError>> = links.iter_mut().map(|link| link.send(frame.clone()).map_err(|error| LinkError::Transport { round: 1, error })).collect();
+    let votes: Vec<Result<bool, LinkError>> = links.iter_mut().zip(sent).map(|(link, sent)| {
+        sent?;
+        let reply = receive(*link, 1, options).map_err(|error| LinkError::Transport { round: 1, error })?;
+        bincode::deserialize(&reply.payload).map_err(|e| LinkError::Malformed { round: 1, reason: e.to_string() })
+    }).collect();
+    let accept = votes.iter().filter(|vote| vote == &&Ok(true)).count() >= threshold;
+    Ok(QuorumVerdict { threshold, votes, accept })
+}
+
+// Serves one vote on a node: waits for a transcript, checks it, and sends back whether it accepts. A
+// transcript that can't be decoded is rejected.
+pub fn serve_quorum_node<T: DeserializeOwned, F: FnOnce(&T) -> bool>(link: &mut dyn Transport, options: &LinkOptions, check: F) -> Result<bool, LinkError> {
+    let frame = receive(link, 1, options).map_err(|error| LinkError::Transport { round: 1, error })?;
+    let vote = match bincode::deserialize(&frame.payload) {
+        Ok(transcript) => check(&transcript),
+        Err(_) => false,
+    };
+    link.send(Frame::new(1, bincode::serialize(&vote).unwrap())).map_err(|error| LinkError::Transport { round: 1, error })?;
+    Ok(vote)
+}
+
+#[cfg(test)]
+type GITranscript = (GraphPair, Vec<(GIProverMessage, GIVerifierMessage)>);
+
+// Replays the prover's messages of a GI transcript with the challenges it records
+#[cfg(test)]
+fn check_gi_transcript((instance, messages): &GITranscript) -> bool {
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance };
+    let mut accept = false;
+    for (prover_msg, reply) in messages {
+        if verifier.validate(prover_msg).is_err() {
+            return false;
+        }
+        accept = verifier.handle_with(prover_msg, Some(reply.b)).1;
+    }
+    accept
+}
+
+// Runs a quorum over three honest nodes, one that rejects everything, and one that never answers,
+// returning the verdict and the votes the nodes that answered sent
+#[cfg(test)]
+fn run_test_quorum(transcript: &GITranscript, threshold: usize) -> (QuorumVerdict, Vec<Result<bool, LinkError>>) {
+    let options = LinkOptions { timeout: Duration::from_millis(200), ..LinkOptions::default() };
+    let (mut coordinator_links, mut node_links): (Vec<LoopbackTransport>, Vec<LoopbackTransport>) = (0..5).map(|_| loopback()).unzip();
+    let nodes: Vec<_> = node_links.drain(..4).enumerate().map(|(i, mut link)| {
+        thread::spawn(move || serve_quorum_node(&mut link, &options, |transcript: &GITranscript| i < 3 && check_gi_transcript(transcript)))
+    }).collect();
+    let mut links: Vec<&mut dyn Transport> = coordinator_links.iter_mut().map(|x| x as &mut dyn Transport).collect();
+    let verdict = run_quorum(transcript, &mut links, threshold, &options).unwrap();
+    (verdict, nodes.into_iter().map(|node| node.join().unwrap()).collect())
+}
+
+#[test]
+fn test_quorum() {
+    let instance = transport_test_instance();
+    let mut prover = GIProver::new(&instance);
+    let mut verifier = GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance };
+    let mut messages = Vec::new();
+    let mut reply = verifier.init();
+    for _ in 0..2 {
+        let prover_msg = prover.handle(&reply).0;
+        reply = verifier.handle(&prover_msg).0;
+        messages.push((prover_msg, reply.clone()));
+    }
+    let transcript = (instance.clone(), messages);
+
+    let (verdict, votes) = run_test_quorum(&transcript, 3);
+    assert_eq!(votes, [Ok(true), Ok(true), Ok(true), Ok(false)]);
+    assert_eq!(verdict.votes[..4], votes[..]);
+    assert!(verdict.accept);
+    assert_eq!(verdict.accepted(), 3);
+    assert_eq!(verdict.votes[4], Err(LinkError::Transport { round: 1, error: TransportError::Timeout }));
+    assert_eq!(verdict.to_string(), "Accepted with 3 of 5 nodes accepting, 3 needed (1 failed to vote)");
+    // The same votes fall short of a quorum of 4
+    let (verdict, _) = run_test_quorum(&transcript, 4);
+    assert!(!verdict.accept);
+    assert_eq!(verdict.to_string(), "Rejected with 3 of 5 nodes accepting, 4 needed (1 failed to vote)");
+    // A transcript with a different challenge than the prover answered convinces no honest node
+    let mut tampered = transcript;
+    tampered.1[0].1.b = !tampered.1[0].1.b;
+    let (verdict, _) = run_test_quorum(&tampered, 1);
+    assert_eq!((verdict.accept, verdict.accepted()), (false, 0));
+
+    // A quorum that needs no accepts, or more accepts than there are nodes, is refused before the transcript is sent
+    let (mut coordinator_link, _node_link) = loopback();
+    let mut links: Vec<&mut dyn Transport> = vec![&mut coordinator_link];
+    assert_eq!(run_quorum(&tampered, &mut links, 0, &LinkOptions::default()), Err(QuorumError::Threshold { threshold: 0, nodes: 1 }));
+    assert_eq!(run_quorum(&tampered, &mut links, 2, &LinkOptions::default()), Err(QuorumError::Threshold { threshold: 2, nodes: 1 }));
+    assert_eq!(run_quorum(&tampered, &mut [], 1, &LinkOptions::default()), Err(QuorumError::Threshold { threshold: 1, nodes: 0 }));
+}
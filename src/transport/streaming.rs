@@ -0,0 +1,208 @@
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::{Prover, ValidationError, Verifier};
+use super::{pacing, receive, Frame, LinkError, LinkOptions, Transport};
+#[cfg(test)]
+use std::num::NonZeroUsize;
+#[cfg(test)]
+use std::time::Duration;
+#[cfg(test)]
+use crate::graph::{GIProver, GIProverMalicious, GIProverMessage, GIVerifier, GIVerifierMessage, Graph, GuessStrategy};
+#[cfg(test)]
+use super::{transport_test_instance, TransportError};
+#[cfg(test)]
+use super::mock::{pair, pair_with, Fault, MockTransport};
+
+// ************ Streaming runner implementation ************
+
+// A statement proven by k sequential repetitions, like GI with soundness error 2^-k, spends most of its
+// time over a slow link waiting on round trips, since each repetition's commitment only goes out once the
+// last repetition has been checked. run_streamed keeps up to depth repetitions in flight over one link:
+// the next repetition starts as soon as one finishes, and each end answers every message waiting for it
+// before it waits for the replies. With depth d, k repetitions of a protocol with r messages take about
+// ceil(k / d) * r latencies rather than k * r.
+//
+// Messages of different repetitions share the link, so each frame carries its sequence number in its
+// direction rather than its round, and each end handles frames in the order they were sent, failing with
+// OutOfOrder on any frame but the next one. Within a repetition, the verifier still only sends its
+// challenge after receiving the commitment it challenges, so every repetition is as sound as before.
+// Frames aren't resent, since a resent frame would arrive behind the ones sent after it, so only the
+// timeout, heartbeat and rate limit of the LinkOptions apply.
+//
+// Overlapping repetitions composes them partly in parallel: the verifier sees the next repetitions'
+// commitments before it picks the challenge for the current one. Parallel repetition of GI isn't known
+// to be zero knowledge, so a prover facing a verifier it doesn't trust keeps depth at 1, which is plain
+// sequential repetition.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamError {
+    // The repetitions were given a different number of provers and verifiers
+    Mismatched { provers: usize, verifiers: usize },
+    // No repetitions were allowed in flight
+    NoDepth,
+    // The link failed, or a message on it was invalid, during the given repetition
+    Failed { repetition: usize, error: LinkError },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Mismatched { provers, verifiers } => write!(f, "Every repetition needs a prover and a verifier, got {} provers and {} verifiers", provers, verifiers),
+            StreamError::NoDepth => write!(f, "At least one repetition must be in flight"),
+            StreamError::Failed { repetition, error } => write!(f, "Repetition {} failed: {}", repetition, error),
+        }
+    }
+}
+
+// Each repetition's verdict, in the order of the repetitions
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamedRun {
+    pub accepts: Vec<bool>,
+}
+
+impl StreamedRun {
+    pub fn all_accept(&self) -> bool {
+        self.accepts.iter().all(|&accept| accept)
+    }
+}
+
+// Plays repetition i between provers[i] and verifiers[i], with up to depth repetitions in flight at once
+pub fn run_streamed<P, V>(provers: &mut [P], verifiers: &mut [V], prover_link: &mut dyn Transport, verifier_link: &mut dyn Transport,
+    depth: usize, options: &LinkOptions) -> Result<StreamedRun, StreamError>
+where P: Prover, V: Verifier<ProverMessage = P::ProverMessage, VerifierMessage = P::VerifierMessage>,
+    P::ProverMessage: Serialize + DeserializeOwned, P::VerifierMessage: Serialize + DeserializeOwned {
+    if provers.len() != verifiers.len() {
+        return Err(StreamError::Mismatched { provers: provers.len(), verifiers: verifiers.len() });
+    }
+    if depth == 0 {
+        return Err(StreamError::NoDepth);
+    }
+    // Every repetition in flight has one message on its way to one end, which has to fit in that end's buffer
+    let depth = options.buffer.map_or(depth, |frames| depth.min(frames.get()));
+    let mut accepts = vec![false; verifiers.len()];
+    // Sequence numbers of the next frames from the verifier's end and from the prover's end, as sent and as received
+    let mut sent = [1; 2];
+    let mut received = [1; 2];
+    // Repetition and round of every message in flight to each end, in the order they were sent
+    let mut to_prover = Vec::new();
+    let mut to_verifier = Vec::new();
+    let mut started = 0;
+    while started < depth.min(verifiers.len()) {
+        send(verifier_link, &mut sent[0], 1, &verifiers[started].init(), options).map_err(|error| StreamError::Failed { repetition: started, error })?;
+        to_prover.push((started, 1));
+        started += 1;
+    }
+
+    while !to_prover.is_empty() {
+        // The prover's end answers every message waiting for it
+        for (repetition, round) in std::mem::take(&mut to_prover) {
+            let fail = |error| StreamError::Failed { repetition, error };
+            let msg: P::VerifierMessage = next(prover_link, &mut received[0], round, options).map_err(fail)?;
+            let prover = &mut provers[repetition];
+            prover.validate(&msg).map_err(|reason| fail(LinkError::Invalid(ValidationError::VerifierMessage(round, reason))))?;
+            let (reply, done) = prover.handle(&msg);
+            if !done {
+                send(prover_link, &mut sent[1], round, &reply, options).map_err(fail)?;
+                to_verifier.push((repetition, round));
+                continue;
+            }
+            // The finished repetition makes room for the next one
+            if started < verifiers.len() {
+                send(verifier_link, &mut sent[0], 1, &verifiers[started].init(), options).map_err(|error| StreamError::Failed { repetition: started, error })?;
+                to_prover.push((started, 1));
+                started += 1;
+            }
+        }
+        // Then the verifier's end checks every reply waiting for it
+        for (repetition, round) in std::mem::take(&mut to_verifier) {
+            let fail = |error| StreamError::Failed { repetition, error };
+            let msg: P::ProverMessage = next(verifier_link, &mut received[1], round, options).map_err(fail)?;
+            let verifier = &mut verifiers[repetition];
+            verifier.validate(&msg).map_err(|reason| fail(LinkError::Invalid(ValidationError::ProverMessage(round, reason))))?;
+            let (reply, accept) = verifier.handle(&msg);
+            accepts[repetition] = accept;
+            send(verifier_link, &mut sent[0], round + 1, &reply, options).map_err(fail)?;
+            to_prover.push((repetition, round + 1));
+        }
+    }
+
+    Ok(StreamedRun { accepts })
+}
+
+// Sends the message of the round as the next frame in sequence from the end
+fn send<M: Serialize>(link: &mut dyn Transport, sequence: &mut u32, round: u32, msg: &M, options: &LinkOptions) -> Result<(), LinkError> {
+    let payload = bincode::serialize(msg).map_err(|e| LinkError::Malformed { round, reason: e.to_string() })?;
+    let frame = Frame::new(*sequence, payload);
+    if let Some(pacing) = pacing(&frame, options) {
+        link.pause(pacing);
+    }
+    link.send(frame).map_err(|error| LinkError::Transport { round, error })?;
+    *sequence += 1;
+    Ok(())
+}
+
+// Receives the next frame in sequence at the end, and decodes it as the message of the round
+fn next<M: DeserializeOwned>(link: &mut dyn Transport, sequence: &mut u32, round: u32, options: &LinkOptions) -> Result<M, LinkError> {
+    let frame = receive(link, *sequence, options).map_err(|error| LinkError::Transport { round, error })?;
+    *sequence += 1;
+    bincode::deserialize(&frame.payload).map_err(|e| LinkError::Malformed { round, reason: e.to_string() })
+}
+
+#[cfg(test)]
+fn stream_gi<P>(provers: &mut [P], depth: usize, prover_link: &mut MockTransport, verifier_link: &mut MockTransport) -> Result<StreamedRun, StreamError>
+where P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> {
+    stream_gi_with(provers, depth, prover_link, verifier_link, &LinkOptions { timeout: Duration::from_millis(100), ..LinkOptions::default() })
+}
+
+#[cfg(test)]
+fn stream_gi_with<P>(provers: &mut [P], depth: usize, prover_link: &mut MockTransport, verifier_link: &mut MockTransport, options: &LinkOptions) -> Result<StreamedRun, StreamError>
+where P: Prover<ProverMessage = GIProverMessage, VerifierMessage = GIVerifierMessage> {
+    let instance = transport_test_instance();
+    let mut verifiers: Vec<GIVerifier> = provers.iter().map(|_| GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }).collect();
+    run_streamed(provers, &mut verifiers, prover_link, verifier_link, depth, options)
+}
+
+#[test]
+fn test_streamed() {
+    let instance = transport_test_instance();
+    let honest = || (0..8).map(|_| GIProver::new(&instance)).collect::<Vec<_>>();
+    // One repetition in flight takes 5 latencies of 30ms per repetition, like running each on its own, and
+    // more in flight overlap their round trips
+    for (depth, elapsed) in [(1, 1200), (3, 450), (4, 300), (8, 150), (20, 150)] {
+        let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+        let run = stream_gi(&mut honest(), depth, &mut prover_link, &mut verifier_link).unwrap();
+        assert_eq!(run.accepts, [true; 8]);
+        assert_eq!(prover_link.now(), Duration::from_millis(elapsed));
+        // Frames are numbered in sequence each way, 2 from the prover and 3 from the verifier per repetition
+        assert!(prover_link.sent.iter().map(|frame| frame.round).eq(1..=16));
+        assert!(verifier_link.sent.iter().map(|frame| frame.round).eq(1..=24));
+    }
+
+    // A bounded link holds back as many repetitions as don't fit in its buffers
+    let options = LinkOptions { timeout: Duration::from_millis(100), buffer: NonZeroUsize::new(2), ..LinkOptions::default() };
+    let (mut prover_link, mut verifier_link) = pair_with(Duration::from_millis(30), &options);
+    assert_eq!(stream_gi_with(&mut honest(), 8, &mut prover_link, &mut verifier_link, &options).map(|run| run.accepts), Ok(vec![true; 8]));
+    assert_eq!(prover_link.now(), Duration::from_millis(600));
+
+    // Every repetition is checked on its own, so a prover guessing the challenge is caught in some of them
+    let mut cheating: Vec<GIProverMalicious> = (0..16).map(|_| GIProverMalicious::with_strategy(&instance, 0.5, GuessStrategy::AlwaysZero).unwrap()).collect();
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    let run = stream_gi(&mut cheating, 8, &mut prover_link, &mut verifier_link).unwrap();
+    assert_eq!(run.accepts.len(), 16);
+    assert!(!run.all_accept());
+
+    // A frame overtaken by a later one fails the repetition it belongs to, rather than being handled out of order
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    prover_link.program([Fault::Reorder]);
+    let error = LinkError::Transport { round: 1, error: TransportError::OutOfOrder { expected: 1, found: 2 } };
+    assert_eq!(stream_gi(&mut honest(), 2, &mut prover_link, &mut verifier_link), Err(StreamError::Failed { repetition: 0, error }));
+
+    // Repetitions the runner can't play are refused before anything is sent
+    let (mut prover_link, mut verifier_link) = pair(Duration::from_millis(30));
+    assert_eq!(stream_gi(&mut honest(), 0, &mut prover_link, &mut verifier_link), Err(StreamError::NoDepth));
+    let mut verifiers = vec![GIVerifier { r: 0, b: false, random_perm: Graph::new(0, Vec::new()), instance: &instance }];
+    let run = run_streamed(&mut honest(), &mut verifiers, &mut prover_link, &mut verifier_link, 2, &LinkOptions::default());
+    assert_eq!(run, Err(StreamError::Mismatched { provers: 8, verifiers: 1 }));
+    assert!(prover_link.sent.is_empty() && verifier_link.sent.is_empty());
+}
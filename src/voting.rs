@@ -0,0 +1,247 @@
+use num_bigint::BigUint;
+use serde::{Serialize, Deserialize};
+use crate::chaumpedersen::{EqualityProof, EqualityStatement, OrProof};
+use crate::keys::{ElGamalKeyPair, ElGamalPublicKey};
+use crate::modarith::mod_exp;
+use crate::params::{SchnorrParameters, serde_biguint, serde_biguints};
+use crate::permutation::Permutation;
+use crate::randomness::checked_thread_rng;
+use crate::transcript::ChallengeTranscript;
+#[cfg(test)]
+use crate::params::Parameters;
+
+// ************ Verifiable election implementation ************
+
+// The pieces of a toy verifiable election, which examples/voting.rs puts together. Voters encrypt a vote
+// of 0 or 1 under the trustee's ElGamal key, in the exponent as (g^r, g^v y^r) so that ciphertexts can be
+// re-encrypted by multiplying in an encryption of 0, and attach an OrProof that the plaintext is 0 or 1.
+// A mixer re-encrypts and shuffles the accepted ballots and proves that its output is a shuffle of its
+// input, so that decrypted votes can't be traced back to voters. The trustee decrypts every shuffled
+// ballot with a Chaum-Pedersen proof that it used its key, and anyone can recount the tally from them.
+//
+// The shuffle proof is a cut-and-choose proof: the mixer shuffles its input SHUFFLE_ROUNDS more times,
+// and opens each of these shuffles either from the input or to the output, which reveals nothing about
+// the shuffle itself and catches a mixer that changed a ballot with probability 1/2 per round. Every
+// proof is made non-interactive with a ChallengeTranscript, so a cheating mixer can redraw its shuffles
+// offline until the transcript happens to ask for the openings it can give, which takes about 2^k tries
+// for k rounds. The rounds are therefore set for 128 bits against that grinding, rather than the 40 an
+// interactive proof would need.
+
+pub const SHUFFLE_ROUNDS: usize = 128;
+
+// Exponential ElGamal ciphertext (g^r, g^v y^r) of a vote v
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElGamalCiphertext {
+    #[serde(with = "serde_biguint")]
+    pub a: BigUint,
+    #[serde(with = "serde_biguint")]
+    pub b: BigUint,
+}
+
+// Encrypted vote with a proof that it encrypts 0 or 1
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ballot {
+    pub ciphertext: ElGamalCiphertext,
+    pub proof: OrProof,
+}
+
+// Intermediate shuffles of the mixer's input, with the permutation and randomness opening each one from
+// the input or to the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShuffleProof {
+    pub shuffles: Vec<Vec<ElGamalCiphertext>>,
+    pub openings: Vec<Opening>,
+}
+
+// Permutation and re-encryption randomness of a shuffle
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Opening {
+    pub permutation: Permutation,
+    #[serde(with = "serde_biguints")]
+    pub randomness: Vec<BigUint>,
+}
+
+// Decrypted vote, with a proof that the ciphertext decrypts to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Decryption {
+    pub vote: u32,
+    pub proof: EqualityProof,
+}
+
+// Computes x^-e as x^(q - e), for elements of the subgroup of order q
+fn pow_neg(params: &SchnorrParameters, x: &BigUint, e: &BigUint) -> BigUint {
+    mod_exp(x, &(&params.q - e % &params.q), &params.p)
+}
+
+impl ElGamalCiphertext {
+    // Encrypts the vote with randomness r
+    pub fn encrypt(key: &ElGamalPublicKey, vote: u32, r: &BigUint) -> ElGamalCiphertext {
+        let params = &key.params;
+        let p = &params.p;
+        ElGamalCiphertext { a: mod_exp(&params.g, r, p), b: mod_exp(&params.g, &BigUint::from(vote), p) * mod_exp(&key.y, r, p) % p }
+    }
+
+    // Multiplies in an encryption of 0 with randomness r, which changes the ciphertext but not the vote
+    pub fn reencrypt(&self, key: &ElGamalPublicKey, r: &BigUint) -> ElGamalCiphertext {
+        let zero = ElGamalCiphertext::encrypt(key, 0, r);
+        ElGamalCiphertext { a: &self.a * zero.a % &key.params.p, b: &self.b * zero.b % &key.params.p }
+    }
+
+    // Statement log_g a = log_y (b / g^vote) that the ciphertext encrypts the vote
+    fn encrypts(&self, key: &ElGamalPublicKey, vote: u32) -> EqualityStatement {
+        let params = &key.params;
+        let b = &self.b * pow_neg(params, &params.g, &BigUint::from(vote)) % &params.p;
+        [params.g.clone(), self.a.clone(), key.y.clone(), b]
+    }
+
+    fn absorb(&self, transcript: &mut ChallengeTranscript) {
+        transcript.append_biguint(b"a", &self.a);
+        transcript.append_biguint(b"b", &self.b);
+    }
+}
+
+impl Ballot {
+    // Encrypts the vote and proves that it is 0 or 1
+    pub fn cast(key: &ElGamalPublicKey, vote: bool) -> Ballot {
+        let r = key.params.random_exponent();
+        let ciphertext = ElGamalCiphertext::encrypt(key, vote as u32, &r);
+        let statements = [ciphertext.encrypts(key, 0), ciphertext.encrypts(key, 1)];
+        let proof = OrProof::prove(&key.params, &statements, vote as usize, &r, b"ballot", &[]);
+        Ballot { ciphertext, proof }
+    }
+
+    // Checks that the ballot encrypts 0 or 1 under the key
+    pub fn verify(&self, key: &ElGamalPublicKey) -> bool {
+        let statements = [self.ciphertext.encrypts(key, 0), self.ciphertext.encrypts(key, 1)];
+        self.proof.verify(&key.params, &statements, b"ballot", &[])
+    }
+}
+
+// Re-encrypts input i into position permutation(i) of the output
+pub fn shuffle(key: &ElGamalPublicKey, input: &[ElGamalCiphertext], opening: &Opening) -> Vec<ElGamalCiphertext> {
+    let mut output = input.to_vec();
+    for (i, (ciphertext, r)) in input.iter().zip(&opening.randomness).enumerate() {
+        output[opening.permutation.apply(i as u32) as usize] = ciphertext.reencrypt(key, r);
+    }
+    output
+}
+
+impl Opening {
+    // Random shuffle of n ciphertexts
+    pub fn random(params: &SchnorrParameters, n: usize) -> Opening {
+        Opening { permutation: Permutation::random(n as u32, &mut checked_thread_rng()), randomness: (0..n).map(|_| params.random_exponent()).collect() }
+    }
+}
+
+// Which intermediate shuffles to open to the output rather than from the input
+fn shuffle_challenge(key: &ElGamalPublicKey, input: &[ElGamalCiphertext], output: &[ElGamalCiphertext], shuffles: &[Vec<ElGamalCiphertext>]) -> Vec<bool> {
+    let mut transcript = ChallengeTranscript::new(b"shuffle");
+    let params = &key.params;
+    [&params.p, &params.q, &params.g, &key.y].iter().for_each(|x| transcript.append_biguint(b"key", x));
+    input.iter().chain(output).chain(shuffles.iter().flatten()).for_each(|ciphertext| ciphertext.absorb(&mut transcript));
+    let mut bytes = vec![0u8; shuffles.len().div_ceil(8)];
+    transcript.challenge_bytes(b"challenge", &mut bytes);
+    (0..shuffles.len()).map(|i| bytes[i / 8] >> (i % 8) & 1 == 1).collect()
+}
+
+impl ShuffleProof {
+    // Proves that output is the shuffle of input with the opening
+    pub fn prove(key: &ElGamalPublicKey, input: &[ElGamalCiphertext], output: &[ElGamalCiphertext], opening: &Opening) -> ShuffleProof {
+        let q = &key.params.q;
+        let secrets: Vec<Opening> = (0..SHUFFLE_ROUNDS).map(|_| Opening::random(&key.params, input.len())).collect();
+        let shuffles: Vec<_> = secrets.iter().map(|secret| shuffle(key, input, secret)).collect();
+        let openings = shuffle_challenge(key, input, output, &shuffles).into_iter().zip(secrets).map(|(to_output, secret)| {
+            if !to_output {
+                return secret;
+            }
+            // Input i sits at sigma(i) in the intermediate shuffle and at permutation(i) in the output
+            let sigma = &secret.permutation;
+            let mut randomness = vec![BigUint::default(); input.len()];
+            for i in 0..input.len() {
+                randomness[sigma.apply(i as u32) as usize] = (&opening.randomness[i] + q - &secret.randomness[i]) % q;
+            }
+            Opening { permutation: sigma.invert().compose(&opening.permutation), randomness }
+        }).collect();
+        ShuffleProof { shuffles, openings }
+    }
+
+    // Checks that output is a shuffle of input
+    pub fn verify(&self, key: &ElGamalPublicKey, input: &[ElGamalCiphertext], output: &[ElGamalCiphertext]) -> bool {
+        let n = input.len();
+        let well_formed = self.shuffles.len() == SHUFFLE_ROUNDS && self.openings.len() == SHUFFLE_ROUNDS && output.len() == n
+            && self.shuffles.iter().zip(&self.openings).all(|(intermediate, opening)| {
+                intermediate.len() == n && opening.permutation.n() as usize == n && opening.randomness.len() == n
+            });
+        well_formed && shuffle_challenge(key, input, output, &self.shuffles).into_iter().zip(self.shuffles.iter().zip(&self.openings)).all(|(to_output, (intermediate, opening))| {
+            let (from, to) = if to_output { (intermediate.as_slice(), output) } else { (input, intermediate.as_slice()) };
+            shuffle(key, from, opening) == to
+        })
+    }
+}
+
+impl Decryption {
+    // Decrypts the ciphertext to a vote of 0 or 1, proving log_g y = log_a (b / g^vote) with the key
+    pub fn decrypt(key: &ElGamalKeyPair, ciphertext: &ElGamalCiphertext) -> Decryption {
+        let params = &key.params;
+        let m = &ciphertext.b * pow_neg(params, &ciphertext.a, &key.x) % &params.p;
+        let vote = if m == params.g { 1 } else { 0 };
+        let proof = EqualityProof::prove(params, &decrypts(&key.public_key(), ciphertext, vote), &key.x, b"decryption", &[]);
+        Decryption { vote, proof }
+    }
+
+    // Checks that the ciphertext decrypts to the vote under the key
+    pub fn verify(&self, key: &ElGamalPublicKey, ciphertext: &ElGamalCiphertext) -> bool {
+        self.vote <= 1 && self.proof.verify(&key.params, &decrypts(key, ciphertext, self.vote), b"decryption", &[])
+    }
+}
+
+// Statement log_g y = log_a (b / g^vote) that the key decrypts the ciphertext to the vote
+fn decrypts(key: &ElGamalPublicKey, ciphertext: &ElGamalCiphertext, vote: u32) -> EqualityStatement {
+    let [g, a, y, b] = ciphertext.encrypts(key, vote);
+    [g, y, a, b]
+}
+
+#[test]
+fn test_ballot() {
+    let trustee = ElGamalKeyPair::generate(&SchnorrParameters::generate(64));
+    let key = trustee.public_key();
+    for vote in [false, true] {
+        let ballot = Ballot::cast(&key, vote);
+        assert!(ballot.verify(&key));
+        assert!(!ballot.verify(&ElGamalKeyPair::generate(&key.params).public_key()));
+        let decryption = Decryption::decrypt(&trustee, &ballot.ciphertext);
+        assert_eq!(decryption.vote, vote as u32);
+        assert!(decryption.verify(&key, &ballot.ciphertext));
+        assert!(!Decryption { vote: 1 - vote as u32, ..decryption }.verify(&key, &ballot.ciphertext));
+    }
+    // A ballot encrypting 2 can't reuse the proof of an honest one
+    let honest = Ballot::cast(&key, true);
+    let stuffed = Ballot { ciphertext: ElGamalCiphertext::encrypt(&key, 2, &key.params.random_exponent()), proof: honest.proof };
+    assert!(!stuffed.verify(&key));
+}
+
+#[test]
+fn test_shuffle() {
+    let trustee = ElGamalKeyPair::generate(&SchnorrParameters::generate(64));
+    let key = trustee.public_key();
+    let votes = [true, false, true, true, false];
+    let input: Vec<ElGamalCiphertext> = votes.iter().map(|&vote| Ballot::cast(&key, vote).ciphertext).collect();
+    let opening = Opening::random(&key.params, input.len());
+    let output = shuffle(&key, &input, &opening);
+    let proof = ShuffleProof::prove(&key, &input, &output, &opening);
+    assert!(proof.verify(&key, &input, &output));
+    // The shuffle keeps the votes, and only their order changes
+    let mut shuffled: Vec<u32> = output.iter().map(|ciphertext| Decryption::decrypt(&trustee, ciphertext).vote).collect();
+    shuffled.sort();
+    assert_eq!(shuffled, [0, 0, 1, 1, 1]);
+
+    // A mixer that replaces a ballot can't open the shuffles the challenge sends to the output
+    let mut tampered = output.clone();
+    tampered[0] = ElGamalCiphertext::encrypt(&key, 1, &key.params.random_exponent());
+    assert!(!ShuffleProof::prove(&key, &input, &tampered, &opening).verify(&key, &input, &tampered));
+    assert!(!proof.verify(&key, &input, &tampered));
+    // Proofs with too few rounds, or outputs of another length, are refused
+    let short = ShuffleProof { shuffles: proof.shuffles[1..].to_vec(), openings: proof.openings[1..].to_vec() };
+    assert!(!short.verify(&key, &input, &output));
+    assert!(!proof.verify(&key, &input, &output[1..]));
+}
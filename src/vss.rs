@@ -0,0 +1,260 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use crate::{Diagnosis, Prover, Verifier};
+use crate::modarith::{mod_exp, mod_inverse};
+use serde::{Serialize, Deserialize};
+use crate::params::{SchnorrParameters, serde_biguint};
+use crate::secret::Secret;
+use crate::transcript::ChallengeTranscript;
+#[cfg(test)]
+use crate::{run_interactive_proof, params::Parameters};
+
+// ************ Feldman verifiable secret sharing ************
+
+// A dealer splits a secret into n Shamir shares so that any t of them reconstruct it, and publishes
+// commitments g^a_j to the coefficients of the sharing polynomial f(z) = a_0 + a_1 z + ... + a_(t-1) z^(t-1).
+// Each party can then check its share against the commitments without learning anything about the secret
+// beyond g^secret.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Share {
+    // Evaluation point, starting from 1 since f(0) is the secret
+    pub index: u32,
+    pub value: Secret<BigUint>,
+}
+
+pub struct FeldmanCommitments {
+    pub params: SchnorrParameters,
+    // Commitments g^a_j to each coefficient of the sharing polynomial
+    pub coefficients: Vec<BigUint>,
+}
+
+// Shares the secret among n parties with reconstruction threshold t
+pub fn deal(params: &SchnorrParameters, secret: &BigUint, t: u32, n: u32) -> (Vec<Share>, FeldmanCommitments) {
+    if t == 0 || t > n {
+        panic!("Threshold must be between 1 and the number of parties. Found threshold {} for {} parties.", t, n);
+    }
+    // The coefficients, the secret among them, are erased once the shares are computed
+    let mut polynomial = Secret::new(vec![secret % &params.q]);
+    polynomial.extend((1..t).map(|_| params.random_exponent()));
+    let shares = (1..=n).map(|i| Share { index: i, value: Secret::new(evaluate(&polynomial, &BigUint::from(i), &params.q)) }).collect();
+    let coefficients = polynomial.iter().map(|a| mod_exp(&params.g, a, &params.p)).collect();
+    (shares, FeldmanCommitments { params: params.clone(), coefficients })
+}
+
+impl FeldmanCommitments {
+    // Computes g^f(i) from the commitments alone, as the product of (g^a_j)^(i^j)
+    pub fn public_share(&self, index: u32) -> BigUint {
+        let params = &self.params;
+        let i = BigUint::from(index);
+        let mut power = BigUint::one();
+        let mut result = BigUint::one();
+        for c in self.coefficients.iter() {
+            result = result * mod_exp(c, &power, &params.p) % &params.p;
+            power = power * &i % &params.q;
+        }
+        result
+    }
+
+    // Checks that a share lies on the committed polynomial
+    pub fn verify_share(&self, share: &Share) -> bool {
+        share.index != 0 && mod_exp(&self.params.g, &share.value, &self.params.p) == self.public_share(share.index)
+    }
+}
+
+// Recovers f(0) from at least t shares with distinct indices by Lagrange interpolation over Z_q
+pub fn reconstruct(params: &SchnorrParameters, shares: &[Share]) -> BigUint {
+    let q = &params.q;
+    shares.iter().fold(BigUint::zero(), |acc, share| {
+        let i = BigUint::from(share.index);
+        let (numerator, denominator) = shares.iter().filter(|x| x.index != share.index).fold((BigUint::one(), BigUint::one()), |(num, den), other| {
+            let j = BigUint::from(other.index);
+            // The Lagrange basis polynomial for i evaluated at 0 is the product of j / (j - i)
+            (num * &j % q, den * ((&j + q - &i % q) % q) % q)
+        });
+        let coefficient = numerator * mod_inverse(&denominator, q).expect("Shares must have distinct indices") % q;
+        (acc + &*share.value * coefficient) % q
+    })
+}
+
+fn evaluate(polynomial: &[BigUint], x: &BigUint, q: &BigUint) -> BigUint {
+    polynomial.iter().rev().fold(BigUint::zero(), |acc, a| (acc * x + a) % q)
+}
+
+// ************ Zero-knowledge proof of share consistency ************
+
+// A shareholder proves that it holds the share committed to by the dealer, i.e. that it knows the
+// discrete log of the public share g^f(i), without revealing the share itself. This is a Schnorr
+// proof of knowledge against the public share.
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ShareProverMessage {
+    // Commitment g^k to a random nonce
+    Commitment(#[serde(with = "serde_biguint")] BigUint),
+    // Response k + c * f(i) mod q
+    Response(#[serde(with = "serde_biguint")] BigUint),
+    // Interaction complete
+    Done
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ShareVerifierMessage {
+    // Random challenge in Z_q
+    #[serde(with = "serde_biguint")]
+    pub c: BigUint,
+}
+
+pub struct ShareProver<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Random nonce used for the commitment
+    pub nonce: Secret<BigUint>,
+    pub commitments: &'a FeldmanCommitments,
+    pub share: &'a Share,
+}
+
+impl Prover for ShareProver<'_> {
+    type ProverMessage = ShareProverMessage;
+    type VerifierMessage = ShareVerifierMessage;
+
+    fn handle(&mut self, msg: &ShareVerifierMessage) -> (ShareProverMessage, bool) {
+        self.r += 1;
+        let params = &self.commitments.params;
+        match self.r {
+            // During the first round, the prover commits to a random nonce
+            1 => {
+                self.nonce = Secret::new(params.random_exponent());
+                (ShareProverMessage::Commitment(mod_exp(&params.g, &self.nonce, &params.p)), false)
+            },
+            // During the second round, the prover masks its share with the nonce according to the challenge
+            2 => (ShareProverMessage::Response((&*self.nonce + &msg.c * &*self.share.value) % &params.q), false),
+            // After sending its response, the prover sends a message to terminate the interaction
+            _ => (ShareProverMessage::Done, true),
+        }
+    }
+}
+
+pub struct ShareVerifier<'a> {
+    // Keep track of round number
+    pub r: u32,
+    // Transcript of the interaction
+    pub commitment: BigUint,
+    pub challenge: BigUint,
+    // Running transcript from which challenges are derived
+    pub transcript: ChallengeTranscript,
+    pub commitments: &'a FeldmanCommitments,
+    // Index of the share the prover claims to hold
+    pub index: u32,
+}
+
+impl Verifier for ShareVerifier<'_> {
+    type ProverMessage = ShareProverMessage;
+    type VerifierMessage = ShareVerifierMessage;
+
+    fn init(&mut self) -> ShareVerifierMessage {
+        narrate!("Initializing share consistency proof for share {}.", self.index);
+        self.transcript.append_message(b"index", &self.index.to_be_bytes());
+        self.commitments.coefficients.iter().for_each(|x| self.transcript.append_biguint(b"coefficient", x));
+        ShareVerifierMessage { c: BigUint::zero() }
+    }
+
+    fn validate(&self, msg: &ShareProverMessage) -> Result<(), String> {
+        let params = &self.commitments.params;
+        match (self.r + 1, msg) {
+            (1, ShareProverMessage::Commitment(a)) => params.check_element(a),
+            (1, _) => Err("expected a commitment".to_string()),
+            (_, ShareProverMessage::Response(z)) => params.check_exponent(z),
+            _ => Err("expected a response".to_string()),
+        }
+    }
+
+    fn diagnose(&self, msg: &ShareProverMessage) -> Option<Diagnosis> {
+        if let ShareProverMessage::Response(z) = msg {
+            let params = &self.commitments.params;
+            let lhs = mod_exp(&params.g, z, &params.p);
+            let rhs = &self.commitment * mod_exp(&self.commitments.public_share(self.index), &self.challenge, &params.p) % &params.p;
+            Some(Diagnosis::new("g^z != a * (g^f(i))^c", Some(format!("g^z = {}, a * (g^f(i))^c = {}", lhs, rhs))))
+        } else {
+            None
+        }
+    }
+
+    fn handle(&mut self, msg: &ShareProverMessage) -> (ShareVerifierMessage, bool) {
+        self.r += 1;
+        let params = &self.commitments.params;
+        match self.r {
+            1 => {
+                if let ShareProverMessage::Commitment(a) = msg {
+                    narrate!("Verifier received commitment {}.", a);
+                    self.commitment = a.clone();
+                    self.transcript.append_biguint(b"commitment", a);
+                    self.challenge = self.transcript.verifier_challenge_scalar(b"challenge", &params.q);
+                    (ShareVerifierMessage { c: self.challenge.clone() }, false)
+                } else {
+                    panic!("Prover did not send a valid commitment on round 1!")
+                }
+            },
+            _ => {
+                if let ShareProverMessage::Response(z) = msg {
+                    narrate!("Verifier received response {}.", z);
+                    self.transcript.append_biguint(b"response", z);
+                    // The verifier accepts if g^z = a * (g^f(i))^c, where g^f(i) is computed from the dealer's commitments
+                    let public_share = self.commitments.public_share(self.index);
+                    let accept = mod_exp(&params.g, z, &params.p) == &self.commitment * mod_exp(&public_share, &self.challenge, &params.p) % &params.p;
+                    (ShareVerifierMessage { c: self.challenge.clone() }, accept)
+                } else {
+                    panic!("Prover did not send a valid response on round 2!")
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_deal_and_verify_shares() {
+    let params = SchnorrParameters::generate(64);
+    let (shares, commitments) = deal(&params, &BigUint::from(42u32), 3, 5);
+    assert_eq!(shares.len(), 5);
+    assert_eq!(commitments.coefficients.len(), 3);
+    assert!(shares.iter().all(|x| commitments.verify_share(x)));
+}
+
+#[test]
+fn test_detect_corrupted_share() {
+    let params = SchnorrParameters::generate(64);
+    let (mut shares, commitments) = deal(&params, &BigUint::from(42u32), 3, 5);
+    shares[2].value = Secret::new((&*shares[2].value + 1u32) % &params.q);
+    assert!(!commitments.verify_share(&shares[2]));
+}
+
+#[test]
+fn test_reconstruct() {
+    let params = SchnorrParameters::generate(64);
+    let secret = params.random_exponent();
+    let (shares, _) = deal(&params, &secret, 3, 5);
+    // Any 3 shares reconstruct the secret
+    assert_eq!(reconstruct(&params, &shares[0..3]), secret);
+    assert_eq!(reconstruct(&params, &[shares[4].clone(), shares[1].clone(), shares[3].clone()]), secret);
+    assert_eq!(reconstruct(&params, &shares), secret);
+    // Fewer shares determine a different polynomial, which almost certainly has a different value at 0
+    assert_ne!(reconstruct(&params, &shares[0..2]), secret);
+}
+
+#[test]
+fn test_share_consistency_proof() {
+    let params = SchnorrParameters::generate(64);
+    let (shares, commitments) = deal(&params, &BigUint::from(42u32), 2, 3);
+    let mut prover = ShareProver { r: 0, nonce: Secret::default(), commitments: &commitments, share: &shares[1] };
+    let mut verifier = ShareVerifier { r: 0, commitment: BigUint::zero(), challenge: BigUint::zero(), transcript: ChallengeTranscript::new(b"feldman share"), commitments: &commitments, index: 2 };
+    assert!(run_interactive_proof(&mut prover, &mut verifier));
+}
+
+#[test]
+fn test_share_consistency_proof_wrong_share() {
+    // A party claiming another party's index cannot complete the proof without that party's share
+    let params = SchnorrParameters::generate(64);
+    let (shares, commitments) = deal(&params, &BigUint::from(42u32), 2, 3);
+    let mut prover = ShareProver { r: 0, nonce: Secret::default(), commitments: &commitments, share: &shares[0] };
+    let mut verifier = ShareVerifier { r: 0, commitment: BigUint::zero(), challenge: BigUint::zero(), transcript: ChallengeTranscript::new(b"feldman share"), commitments: &commitments, index: 2 };
+    assert!(!run_interactive_proof(&mut prover, &mut verifier));
+}
@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use serde_json::Value;
+#[cfg(feature = "server")]
+use std::io::{BufRead, BufReader};
+#[cfg(feature = "server")]
+use std::process::Stdio;
+
+// Runs zklib in a fresh directory, so that no zklib.toml is picked up, with --format json
+fn zklib(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_zklib"));
+    command.current_dir(dir).env_remove("ZKLIB_CONFIG").arg("--format").arg("json").args(args);
+    command
+}
+
+// Parses the single JSON document the command printed on stdout
+fn document(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| panic!("stdout is not JSON ({}): {}", e, String::from_utf8_lossy(&output.stdout)))
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zklib-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_json_results() {
+    let dir = temp_dir("results");
+    let output = zklib(&dir, &["params", "--bits", "64", "group.json"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(document(&output), serde_json::json!({ "bits": 64, "output": "group.json" }));
+
+    let output = zklib(&dir, &["keygen", "--scheme", "schnorr", "--params", "group.json", "key.pem"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(document(&output), serde_json::json!({ "scheme": "schnorr", "output": "key.pem", "public": "key.pem.pub" }));
+
+    let output = zklib(&dir, &["trace", "trace.jsonl"]).output().unwrap();
+    assert!(output.status.success());
+    let trace = document(&output);
+    assert_eq!(trace["accepted"], Value::Bool(true));
+    assert!(!trace["events"].as_array().unwrap().is_empty());
+
+    // Errors are a document too, with a nonzero exit status
+    let output = zklib(&dir, &["params", "--bits", "64", "group.json"]).output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(document(&output), serde_json::json!({ "error": "group.json already exists" }));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_bench() {
+    let dir = temp_dir("bench");
+    let output = zklib(&dir, &["bench", "--max-rounds", "2", "--trials", "10"]).output().unwrap();
+    assert!(output.status.success());
+    let report = document(&output);
+    let names: Vec<&str> = report.as_array().unwrap().iter().map(|x| x["name"].as_str().unwrap()).collect();
+    assert!(names.contains(&"gi/honest") && names.contains(&"gi/cheating"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_json_serve() {
+    let dir = temp_dir("serve");
+    let mut server = zklib(&dir, &["serve", "--listen", "127.0.0.1:0"]).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().unwrap();
+    // The server prints where it listens once it is ready, then keeps running
+    let mut line = String::new();
+    BufReader::new(server.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+    let started: Value = serde_json::from_str(&line).unwrap();
+    let listen = started["listen"].as_str().unwrap();
+    assert!(listen.starts_with("127.0.0.1:") && !listen.ends_with(":0"));
+    assert_eq!((&started["scheme"], &started["sessions"]), (&Value::from("http"), &Value::Bool(true)));
+    assert!(!started["verify"].as_array().unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}